@@ -13,12 +13,14 @@ defmodule OLWSX.Actors.DDoSShield do
 
   def check(remote), do: GenServer.call(__MODULE__, {:check, remote})
 
+  # Same verdict as check/1 without taking a token (header phase of an
+  # Expect: 100-continue request; the full request is charged when it comes).
+  def peek(remote), do: GenServer.call(__MODULE__, {:peek, remote})
+
   @impl true
   def handle_call({:check, remote}, _from, state) do
     now = System.system_time(:second)
-    b = Map.get(state, remote, %{tokens: @bucket_capacity, last: now})
-    elapsed = now - b.last
-    tokens = min(@bucket_capacity, b.tokens + elapsed * @refill_per_sec)
+    tokens = available(state, remote, now)
     if tokens > 0 do
       b = %{tokens: tokens - 1, last: now}
       {:reply, :ok, Map.put(state, remote, b)}
@@ -26,4 +28,17 @@ defmodule OLWSX.Actors.DDoSShield do
       {:reply, :limited, state}
     end
   end
+
+  def handle_call({:peek, remote}, _from, state) do
+    if available(state, remote, System.system_time(:second)) > 0 do
+      {:reply, :ok, state}
+    else
+      {:reply, :limited, state}
+    end
+  end
+
+  defp available(state, remote, now) do
+    b = Map.get(state, remote, %{tokens: @bucket_capacity, last: now})
+    min(@bucket_capacity, b.tokens + (now - b.last) * @refill_per_sec)
+  end
 end
\ No newline at end of file
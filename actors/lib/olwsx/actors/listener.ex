@@ -5,6 +5,7 @@ defmodule OLWSX.Actors.Listener do
 
   use GenServer
   alias OLWSX.Actors.{Codec, Manager, Telemetry, DDoSShield, Isolation}
+  import Bitwise
 
   def start_link(path) when is_binary(path), do: GenServer.start_link(__MODULE__, path, name: __MODULE__)
 
@@ -38,14 +39,15 @@ defmodule OLWSX.Actors.Listener do
         case Codec.decode_request(bin) do
           {:ok, env} ->
             remote = extract_remote(csock)
-            case DDoSShield.check(remote) do
+            header_phase = header_phase?(env)
+            case shield(remote, header_phase) do
               :ok ->
                 env = Map.put(env, :remote, remote)
                 case Manager.submit(env) do
                   {:ok, resp} ->
                     frame = Codec.encode_response(resp)
                     _ = :socket.send(csock, frame)
-                    Telemetry.inc(:listener_ok)
+                    Telemetry.inc(if header_phase, do: :listener_header_phase, else: :listener_ok)
                   {:error, reason} ->
                     frame = Codec.encode_response(%{
                       status: 502,
@@ -82,6 +84,13 @@ defmodule OLWSX.Actors.Listener do
     _ = :socket.close(csock)
   end
 
+  # Expect: 100-continue header phase (edge hint 0x8): the request is sent
+  # again with its body, so the shield only looks and does not charge.
+  defp header_phase?(env), do: (env.edge_hints &&& 0x8) != 0
+
+  defp shield(remote, true), do: DDoSShield.peek(remote)
+  defp shield(remote, false), do: DDoSShield.check(remote)
+
   defp recv_all(sock, max_bytes, timeout_ms) do
     :socket.setopt(sock, :tcp, :recvtimeout, timeout_ms)
     case :socket.recv(sock, max_bytes, timeout_ms) do
@@ -0,0 +1,173 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: admin/api/flags.go
+// Role: Feature flag endpoints (list, set, delete)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Edit the node's flag file (plugins/flags.rs format: "name=value" lines,
+//   '#' comments); the runtime's file watch applies edits within one poll.
+// - Same validation as the runtime, so a file written here always loads.
+// - Atomic writes (temp file + rename); readers never see a partial file.
+// - Read-modify-write under the runtime's advisory lock ("<file>.lock"), so
+//   an edit here and a FlagStore::set on the node never overwrite each other.
+// - NewServer picks the file up from OLWSX_FLAGS_FILE, as the runtime does.
+// =============================================================================
+
+package admin
+
+import (
+	"bufio"
+	"encoding/json"
+	"errors"
+	"fmt"
+	"net/http"
+	"os"
+	"sort"
+	"strings"
+)
+
+const (
+	maxFlagName  = 64
+	maxFlagValue = 1024
+)
+
+// FlagsFileEnv names the flag file; shared with plugins/flags.rs.
+const FlagsFileEnv = "OLWSX_FLAGS_FILE"
+
+// WithFlags enables /api/v1/flags backed by the flag file at path.
+func (s *Server) WithFlags(path string) *Server {
+	s.flagsPath = path
+	return s
+}
+
+// GET  /api/v1/flags
+// POST /api/v1/flags  body: {"name":"maintenance_mode","value":"on"}
+//                     or    {"name":"maintenance_mode","delete":true}
+func (s *Server) Flags(w http.ResponseWriter, r *http.Request) {
+	if s.flagsPath == "" {
+		http.Error(w, "flags not configured", http.StatusNotFound); return
+	}
+	switch r.Method {
+	case http.MethodGet:
+		s.mu.Lock()
+		flags, err := readFlags(s.flagsPath)
+		s.mu.Unlock()
+		if err != nil {
+			http.Error(w, err.Error(), http.StatusInternalServerError); return
+		}
+		writeJSON(w, map[string]interface{}{"flags": flags}, http.StatusOK)
+	case http.MethodPost:
+		var req struct {
+			Name, Value string
+			Delete      bool
+		}
+		if err := json.Unmarshal(readBody(r), &req); err != nil {
+			http.Error(w, "bad request", http.StatusBadRequest); return
+		}
+		if err := validateFlag(req.Name, req.Value); err != nil {
+			http.Error(w, err.Error(), http.StatusBadRequest); return
+		}
+		s.mu.Lock()
+		defer s.mu.Unlock()
+		unlock, err := lockFlags(s.flagsPath)
+		if err != nil {
+			http.Error(w, err.Error(), http.StatusInternalServerError); return
+		}
+		defer unlock()
+		flags, err := readFlags(s.flagsPath)
+		if err != nil {
+			http.Error(w, err.Error(), http.StatusInternalServerError); return
+		}
+		if req.Delete {
+			if _, ok := flags[req.Name]; !ok {
+				http.Error(w, "unknown flag", http.StatusNotFound); return
+			}
+			delete(flags, req.Name)
+		} else {
+			flags[req.Name] = req.Value
+		}
+		if err := writeFlags(s.flagsPath, flags); err != nil {
+			http.Error(w, err.Error(), http.StatusInternalServerError); return
+		}
+		if req.Delete {
+			writeJSON(w, map[string]string{"ok": "deleted", "name": req.Name}, http.StatusOK)
+		} else {
+			writeJSON(w, map[string]string{"ok": "set", "name": req.Name, "value": req.Value}, http.StatusOK)
+		}
+	default:
+		http.Error(w, "method not allowed", http.StatusMethodNotAllowed)
+	}
+}
+
+func validateFlag(name, value string) error {
+	if name == "" || len(name) > maxFlagName {
+		return fmt.Errorf("invalid flag name '%s' (1..%d of [a-z0-9_.-])", name, maxFlagName)
+	}
+	for i := 0; i < len(name); i++ {
+		c := name[i]
+		if !(c >= 'a' && c <= 'z' || c >= '0' && c <= '9' || c == '_' || c == '.' || c == '-') {
+			return fmt.Errorf("invalid flag name '%s' (1..%d of [a-z0-9_.-])", name, maxFlagName)
+		}
+	}
+	if len(value) > maxFlagValue || strings.ContainsAny(value, "\r\n") {
+		return fmt.Errorf("invalid value for flag '%s' (one line, at most %d bytes)", name, maxFlagValue)
+	}
+	return nil
+}
+
+// A missing file is an empty flag set.
+func readFlags(path string) (map[string]string, error) {
+	flags := make(map[string]string)
+	f, err := os.Open(path)
+	if errors.Is(err, os.ErrNotExist) {
+		return flags, nil
+	}
+	if err != nil {
+		return nil, err
+	}
+	defer f.Close()
+	sc := bufio.NewScanner(f)
+	for n := 1; sc.Scan(); n++ {
+		line := strings.TrimSpace(sc.Text())
+		if line == "" || strings.HasPrefix(line, "#") {
+			continue
+		}
+		k, v, ok := strings.Cut(line, "=")
+		if !ok {
+			return nil, fmt.Errorf("flags: %s:%d: expected name=value", path, n)
+		}
+		flags[strings.TrimSpace(k)] = strings.TrimSpace(v)
+	}
+	return flags, sc.Err()
+}
+
+func writeFlags(path string, flags map[string]string) error {
+	names := make([]string, 0, len(flags))
+	for k := range flags {
+		names = append(names, k)
+	}
+	sort.Strings(names)
+	var b strings.Builder
+	b.WriteString("# OLWSX feature flags (name=value); edited via the admin API\n")
+	for _, k := range names {
+		b.WriteString(k + "=" + flags[k] + "\n")
+	}
+	tmp := path + ".tmp"
+	f, err := os.Create(tmp)
+	if err != nil {
+		return err
+	}
+	if _, err := f.WriteString(b.String()); err != nil {
+		f.Close()
+		return err
+	}
+	if err := f.Sync(); err != nil {
+		f.Close()
+		return err
+	}
+	if err := f.Close(); err != nil {
+		return err
+	}
+	return os.Rename(tmp, path)
+}
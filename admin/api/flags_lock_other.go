@@ -0,0 +1,8 @@
+//go:build !unix
+
+package admin
+
+// lockFlags is a no-op here; edits rely on the in-process mutex alone.
+func lockFlags(path string) (func(), error) {
+	return func() {}, nil
+}
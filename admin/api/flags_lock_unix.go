@@ -0,0 +1,25 @@
+//go:build unix
+
+package admin
+
+import (
+	"os"
+	"syscall"
+)
+
+// lockFlags takes the exclusive lock plugins/flags.rs holds while it writes
+// the flag file; the returned func releases it.
+func lockFlags(path string) (func(), error) {
+	f, err := os.OpenFile(path+".lock", os.O_RDWR|os.O_CREATE, 0o644)
+	if err != nil {
+		return nil, err
+	}
+	if err := syscall.Flock(int(f.Fd()), syscall.LOCK_EX); err != nil {
+		f.Close()
+		return nil, err
+	}
+	return func() {
+		_ = syscall.Flock(int(f.Fd()), syscall.LOCK_UN)
+		f.Close()
+	}, nil
+}
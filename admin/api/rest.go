@@ -19,6 +19,7 @@ import (
 	"encoding/json"
 	"errors"
 	"net/http"
+	"os"
 	"strings"
 	"sync"
 	"time"
@@ -29,6 +30,7 @@ type Server struct {
 	hmacKey  []byte
 	configStaging map[string]string // id -> content
 	applied   []string              // applied staging ids
+	flagsPath string                // feature flag file; "" disables /api/v1/flags
 }
 
 func NewServer(hmacKey string) *Server {
@@ -36,6 +38,7 @@ func NewServer(hmacKey string) *Server {
 		hmacKey: []byte(hmacKey),
 		configStaging: make(map[string]string),
 		applied: make([]string, 0, 16),
+		flagsPath: os.Getenv(FlagsFileEnv),
 	}
 }
 
@@ -163,6 +166,7 @@ func (s *Server) Routes(mux *http.ServeMux) {
 	mux.HandleFunc("/api/v1/config/apply", s.withAuth(s.Apply))
 	mux.HandleFunc("/api/v1/config/rollback", s.withAuth(s.Rollback))
 	mux.HandleFunc("/api/v1/rate-limit", s.withAuth(s.SetRateLimit))
+	mux.HandleFunc("/api/v1/flags", s.withAuth(s.Flags))
 }
 
 func writeJSON(w http.ResponseWriter, v interface{}, code int) {
@@ -173,7 +177,7 @@ func writeJSON(w http.ResponseWriter, v interface{}, code int) {
 
 func nowMs() int64 { return time.Now().UnixNano() / int64(time.Millisecond) }
 
-// Example main
+// Example main (flags: OLWSX_FLAGS_FILE, or srv.WithFlags(path))
 // func main() {
 //   srv := NewServer("supersecretkey")
 //   mux := http.NewServeMux()
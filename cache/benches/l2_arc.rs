@@ -0,0 +1,94 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/benches/l2_arc.rs
+// Role: L2 ARC bookkeeping benchmark (cost per operation vs. resident items)
+// ----------------------------------------------------------------------------
+// Run with `cargo bench --bench l2_arc`. Each workload is timed at several
+// fill levels up to the frozen 65,536-item cap; with O(1) list operations the
+// ns/op column stays flat as the tier fills instead of growing with it.
+//   hit:    lookups of resident keys, touches applied by periodic re-inserts
+//   churn:  inserts of new keys (at the cap: eviction to the ghost lists)
+//   ghost:  lookups of recently evicted keys (ghost-list membership checks)
+// ============================================================================
+
+#![allow(clippy::needless_return)]
+
+use cache::l2::L2;
+use cache::{Cache, Entry};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const CAP: usize = 65_536;
+const OPS: usize = 20_000;
+
+fn key(i: usize) -> Vec<u8> {
+    return format!("key-{:08}", i).into_bytes();
+}
+
+fn entry() -> Entry {
+    return Entry::new(b"v".to_vec(), 0, Duration::from_secs(600));
+}
+
+// xorshift: deterministic key choice without pulling in a rand crate
+fn next(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    return *state;
+}
+
+fn filled(n: usize) -> L2 {
+    let l2 = L2::new();
+    for i in 0..n {
+        l2.insert(&key(i), entry()).unwrap();
+    }
+    return l2;
+}
+
+fn hit(n: usize) -> Duration {
+    let l2 = filled(n);
+    let mut rng = 0x9e37_79b9_7f4a_7c15;
+    let start = Instant::now();
+    for op in 0..OPS {
+        let k = key(next(&mut rng) as usize % n);
+        if op % 8 == 0 {
+            l2.insert(&k, entry()).unwrap();
+        } else {
+            black_box(l2.lookup(&k).is_ok());
+        }
+    }
+    return start.elapsed();
+}
+
+fn churn(n: usize) -> Duration {
+    let l2 = filled(n);
+    let start = Instant::now();
+    for i in 0..OPS {
+        l2.insert(&key(n + i), entry()).unwrap();
+    }
+    return start.elapsed();
+}
+
+fn ghost(n: usize) -> Duration {
+    // the first n keys end up on the ghost lists
+    let l2 = L2::new();
+    for i in 0..CAP + n {
+        l2.insert(&key(i), entry()).unwrap();
+    }
+    let mut rng = 0x2545_f491_4f6c_dd1d;
+    let start = Instant::now();
+    for _ in 0..OPS {
+        black_box(l2.lookup(&key(next(&mut rng) as usize % n)).is_err());
+    }
+    return start.elapsed();
+}
+
+fn main() {
+    println!("{:<8}{:>10}{:>12}", "bench", "items", "ns/op");
+    for (name, run) in [("hit", hit as fn(usize) -> Duration), ("churn", churn), ("ghost", ghost)] {
+        for n in [1_024, 16_384, CAP] {
+            let ns = run(n).as_nanos() / OPS as u128;
+            println!("{:<8}{:>10}{:>12}", name, n, ns);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/checksum.rs
+// Role: Final integrity checksums (CRC-32/ISO-HDLC, table-driven, no deps)
+// ----------------------------------------------------------------------------
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    return table;
+}
+
+static TABLE: [u32; 256] = make_table();
+
+/// CRC-32 as used by gzip/zlib/PNG.
+pub fn crc32(data: &[u8]) -> u32 {
+    return crc32_update(0, data);
+}
+
+/// Continue a running CRC-32 over another slice.
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut c = !crc;
+    for b in data {
+        c = TABLE[((c ^ *b as u32) & 0xFF) as usize] ^ (c >> 8);
+    }
+    return !c;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32_update(crc32(b"1234"), b"56789"), 0xCBF4_3926);
+    }
+}
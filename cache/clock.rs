@@ -0,0 +1,57 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/clock.rs
+// Role: Final time source abstraction (system + mock for deterministic tests)
+// ----------------------------------------------------------------------------
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Monotonic time source consulted by the tiers for TTL decisions.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// Real monotonic clock (default for every tier).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        return Instant::now();
+    }
+}
+
+/// Manually advanced clock; time only moves on `advance`.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    base: Instant,
+    offset: Arc<Mutex<Duration>>,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        return MockClock { base: Instant::now(), offset: Arc::new(Mutex::new(Duration::ZERO)) };
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let mut off = self.offset.lock().unwrap();
+        *off += by;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        return self.base + *self.offset.lock().unwrap();
+    }
+}
+
+pub(crate) fn system() -> Arc<dyn Clock> {
+    return Arc::new(SystemClock);
+}
@@ -0,0 +1,436 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/cluster.rs
+// Role: Final sharded L3 backend (consistent hashing with virtual nodes)
+// ----------------------------------------------------------------------------
+// `L3Cluster` spreads keys over named shards, each any `L3Backend` (usually a
+// `resp::RespBackend` per node). Every shard owns `vnodes` points on a 64-bit
+// ring; a key belongs to the first point at or after its hash. Adding or
+// removing a shard therefore only moves the keys of the arcs it gains or
+// loses, about 1/N of them.
+//
+// The hash is FNV-1a with a 64-bit finalizer: stable across processes and
+// builds, so every node maps a key to the same shard.
+//
+// A shard that fails with an I/O error is marked down for `retry`; its keys
+// fall through to the next live shard on the ring and the operation is
+// retried there. Writes that skip a down shard are remembered and replayed as
+// removals when it comes back, so it cannot serve a value that changed
+// while it was away. The other shards then mark those keys stale and remove
+// them before they next serve them, so a copy written to a successor during
+// the outage does not resurface when the owner fails again. Catch-up removes
+// run without the ring or shard locks held. With every shard down the cluster returns the I/O error
+// and L3 drops to local mode. `clear` flushes every shard and fails if any
+// of them does.
+// ============================================================================
+
+use crate::clock::{self, Clock};
+use crate::l3::L3Backend;
+use crate::{CacheError, Entry};
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_VNODES: usize = 160;
+pub const DEFAULT_RETRY: Duration = Duration::from_secs(5);
+
+/// Per-shard view for health endpoints and routing decisions in the core.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShardHealth {
+    pub name: String,
+    pub up: bool,
+    pub failures: u64, // I/O failures since the shard was added
+    pub missed: usize, // keys to invalidate when it comes back
+    pub stale: usize,  // keys it may hold from another shard's outage
+}
+
+struct Shard {
+    name: String,
+    backend: Arc<dyn L3Backend>,
+    state: Mutex<ShardState>,
+}
+
+#[derive(Default)]
+struct ShardState {
+    down_until: Option<Instant>,
+    failures: u64,
+    missed: HashSet<Vec<u8>>, // written elsewhere while this shard was skipped
+    stale: HashSet<Vec<u8>>,  // may hold copies written while their owner was skipped
+    catching_up: bool,        // a caller is replaying removes; skip meanwhile
+}
+
+#[derive(Default)]
+struct Ring {
+    shards: Vec<Arc<Shard>>,
+    points: Vec<(u64, usize)>, // sorted by hash; index into `shards`
+}
+
+pub struct L3Cluster {
+    ring: RwLock<Ring>,
+    vnodes: usize,
+    retry: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl L3Cluster {
+    pub fn new() -> Self {
+        return Self::with_vnodes(DEFAULT_VNODES);
+    }
+
+    /// More virtual nodes even out the key spread at the cost of ring size.
+    pub fn with_vnodes(vnodes: usize) -> Self {
+        return L3Cluster { ring: RwLock::new(Ring::default()), vnodes: vnodes.max(1), retry: DEFAULT_RETRY, clock: clock::system() };
+    }
+
+    /// How long a failed shard is routed around before it is tried again.
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = retry;
+        return self;
+    }
+
+    /// Replace the time source used for down windows.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        return self;
+    }
+
+    /// False if a shard with this name already exists.
+    pub fn add_shard(&self, name: &str, backend: Arc<dyn L3Backend>) -> bool {
+        let mut ring = self.ring.write().unwrap();
+        if ring.shards.iter().any(|s| s.name == name) {
+            return false;
+        }
+        ring.shards.push(Arc::new(Shard { name: name.to_string(), backend, state: Mutex::new(ShardState::default()) }));
+        self.rebuild(&mut ring);
+        return true;
+    }
+
+    /// False if no shard has this name. Its keys move to their successors.
+    pub fn remove_shard(&self, name: &str) -> bool {
+        let mut ring = self.ring.write().unwrap();
+        let before = ring.shards.len();
+        ring.shards.retain(|s| s.name != name);
+        if ring.shards.len() == before {
+            return false;
+        }
+        self.rebuild(&mut ring);
+        return true;
+    }
+
+    pub fn shards(&self) -> usize {
+        return self.ring.read().unwrap().shards.len();
+    }
+
+    /// The shard that owns `key` when every shard is up.
+    pub fn owner(&self, key: &[u8]) -> Option<String> {
+        let ring = self.ring.read().unwrap();
+        return ring.walk(hash(key)).next().map(|s| s.name.clone());
+    }
+
+    /// The shard that serves `key` right now (owner or, while it is down,
+    /// the next live shard on the ring).
+    pub fn route(&self, key: &[u8]) -> Option<String> {
+        let ring = self.ring.read().unwrap();
+        let now = self.clock.now();
+        return ring.walk(hash(key)).find(|s| s.is_up(now)).map(|s| s.name.clone());
+    }
+
+    pub fn health(&self) -> Vec<ShardHealth> {
+        let ring = self.ring.read().unwrap();
+        let now = self.clock.now();
+        return ring
+            .shards
+            .iter()
+            .map(|s| {
+                let st = s.state.lock().unwrap();
+                ShardHealth {
+                    name: s.name.clone(),
+                    up: st.down_until.is_none_or(|t| now >= t),
+                    failures: st.failures,
+                    missed: st.missed.len(),
+                    stale: st.stale.len(),
+                }
+            })
+            .collect();
+    }
+
+    fn rebuild(&self, ring: &mut Ring) {
+        let mut points = Vec::with_capacity(ring.shards.len() * self.vnodes);
+        for (idx, s) in ring.shards.iter().enumerate() {
+            for v in 0..self.vnodes {
+                points.push((hash(format!("{}#{}", s.name, v).as_bytes()), idx));
+            }
+        }
+        // ties broken by name so every node builds the same ring
+        points.sort_unstable_by(|a, b| a.0.cmp(&b.0).then_with(|| ring.shards[a.1].name.cmp(&ring.shards[b.1].name)));
+        ring.points = points;
+    }
+
+    // Runs `op` on the first usable shard for `key`, moving on when a shard
+    // fails with an I/O error. `write` records the key on skipped shards.
+    fn dispatch<T>(&self, key: &[u8], write: bool, op: impl Fn(&dyn L3Backend) -> Result<T, CacheError>) -> Result<T, CacheError> {
+        let shards: Vec<Arc<Shard>> = self.ring.read().unwrap().walk(hash(key)).cloned().collect();
+        let now = self.clock.now();
+        let mut last = CacheError::Io(ErrorKind::NotConnected);
+        for shard in shards.iter() {
+            if !self.catch_up(shard, now) {
+                if write {
+                    shard.state.lock().unwrap().missed.insert(key.to_vec());
+                }
+                continue;
+            }
+            match op(&*shard.backend) {
+                Err(CacheError::Io(kind)) => {
+                    let mut st = shard.state.lock().unwrap();
+                    st.failures += 1;
+                    st.down_until = Some(now + self.retry);
+                    if write {
+                        st.missed.insert(key.to_vec());
+                    }
+                    last = CacheError::Io(kind);
+                }
+                r => return r,
+            }
+        }
+        return Err(last);
+    }
+
+    // Up, or past its down window and caught up: missed and stale keys are
+    // removed from it, then its missed keys turn stale on the other shards.
+    // The removes run with no lock held; meanwhile the shard is skipped.
+    fn catch_up(&self, shard: &Shard, now: Instant) -> bool {
+        let mut st = shard.state.lock().unwrap();
+        if st.catching_up || st.down_until.is_some_and(|t| now < t) {
+            return false;
+        }
+        st.catching_up = true;
+        loop {
+            let (missed, stale) = (std::mem::take(&mut st.missed), std::mem::take(&mut st.stale));
+            if missed.is_empty() && stale.is_empty() {
+                break;
+            }
+            drop(st);
+            // removes are idempotent: after a failure everything is retried
+            let ok = missed.iter().chain(stale.iter()).all(|k| shard.backend.remove(k).is_ok());
+            if ok {
+                self.mark_stale(shard, &missed);
+            }
+            st = shard.state.lock().unwrap();
+            if !ok {
+                st.missed.extend(missed);
+                st.stale.extend(stale);
+                st.failures += 1;
+                st.down_until = Some(now + self.retry);
+                st.catching_up = false;
+                return false;
+            }
+        }
+        st.down_until = None;
+        st.catching_up = false;
+        return true;
+    }
+
+    // Any shard but `owner` may have taken these keys while it was skipped.
+    fn mark_stale(&self, owner: &Shard, keys: &HashSet<Vec<u8>>) {
+        if keys.is_empty() {
+            return;
+        }
+        let others: Vec<Arc<Shard>> = self.ring.read().unwrap().shards.iter().filter(|s| s.name != owner.name).cloned().collect();
+        for s in others.iter() {
+            s.state.lock().unwrap().stale.extend(keys.iter().cloned());
+        }
+    }
+}
+
+impl Default for L3Cluster {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Shard {
+    fn is_up(&self, now: Instant) -> bool {
+        return self.state.lock().unwrap().down_until.is_none_or(|t| now >= t);
+    }
+}
+
+impl Ring {
+    // Distinct shards in ring order starting at `h`.
+    fn walk(&self, h: u64) -> impl Iterator<Item = &Arc<Shard>> {
+        let start = self.points.partition_point(|(p, _)| *p < h);
+        let n = self.points.len();
+        let mut seen = vec![false; self.shards.len()];
+        return (0..n).filter_map(move |i| {
+            let idx = self.points[(start + i) % n].1;
+            if seen[idx] {
+                return None;
+            }
+            seen[idx] = true;
+            return Some(&self.shards[idx]);
+        });
+    }
+}
+
+impl L3Backend for L3Cluster {
+    fn get(&self, key: &[u8]) -> Result<Option<Entry>, CacheError> {
+        return self.dispatch(key, false, |b| b.get(key));
+    }
+
+    fn put(&self, key: &[u8], entry: &Entry) -> Result<(), CacheError> {
+        return self.dispatch(key, true, |b| b.put(key, entry));
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<bool, CacheError> {
+        return self.dispatch(key, true, |b| b.remove(key));
+    }
+
+    fn clear(&self) -> Result<(), CacheError> {
+        let shards: Vec<Arc<Shard>> = self.ring.read().unwrap().shards.clone();
+        for s in shards.iter() {
+            s.backend.clear()?;
+            let mut st = s.state.lock().unwrap();
+            st.missed.clear();
+            st.stale.clear();
+        }
+        return Ok(());
+    }
+
+    fn make_room(&self, key: &[u8], max_entries: usize, now: Instant) {
+        let shard = self.ring.read().unwrap().walk(hash(key)).find(|s| s.is_up(self.clock.now())).cloned();
+        if let Some(s) = shard {
+            s.backend.make_room(key, max_entries, now);
+        }
+    }
+}
+
+fn hash(b: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for &c in b {
+        h ^= c as u64;
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    // FNV-1a alone clusters on short, similar keys; finalize (splitmix64)
+    h ^= h >> 30;
+    h = h.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h ^= h >> 27;
+    h = h.wrapping_mul(0x94d0_49bb_1331_11eb);
+    return h ^ (h >> 31);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[derive(Default)]
+    struct MapShard {
+        map: Mutex<HashMap<Vec<u8>, Entry>>,
+        down: AtomicBool,
+    }
+
+    impl L3Backend for MapShard {
+        fn get(&self, key: &[u8]) -> Result<Option<Entry>, CacheError> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(CacheError::Io(ErrorKind::ConnectionRefused));
+            }
+            return Ok(self.map.lock().unwrap().get(key).cloned());
+        }
+        fn put(&self, key: &[u8], entry: &Entry) -> Result<(), CacheError> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(CacheError::Io(ErrorKind::ConnectionRefused));
+            }
+            self.map.lock().unwrap().insert(key.to_vec(), entry.clone());
+            return Ok(());
+        }
+        fn remove(&self, key: &[u8]) -> Result<bool, CacheError> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(CacheError::Io(ErrorKind::ConnectionRefused));
+            }
+            return Ok(self.map.lock().unwrap().remove(key).is_some());
+        }
+        fn clear(&self) -> Result<(), CacheError> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(CacheError::Io(ErrorKind::ConnectionRefused));
+            }
+            self.map.lock().unwrap().clear();
+            return Ok(());
+        }
+    }
+
+    fn keys() -> Vec<Vec<u8>> {
+        return (0..10_000).map(|i| format!("/assets/{}.js", i).into_bytes()).collect();
+    }
+
+    #[test]
+    fn adding_a_shard_moves_about_one_nth() {
+        let c = L3Cluster::new();
+        for i in 0..4 {
+            c.add_shard(&format!("node-{}", i), Arc::new(MapShard::default()));
+        }
+        assert!(!c.add_shard("node-0", Arc::new(MapShard::default())));
+        let before: Vec<String> = keys().iter().map(|k| c.owner(k).unwrap()).collect();
+        let mut per_shard: HashMap<&str, usize> = HashMap::new();
+        for o in before.iter() {
+            *per_shard.entry(o).or_default() += 1;
+        }
+        assert!(per_shard.values().all(|n| (1_500..3_500).contains(n)), "spread {:?}", per_shard);
+
+        c.add_shard("node-4", Arc::new(MapShard::default()));
+        let mut moved = 0;
+        for (k, old) in keys().iter().zip(before.iter()) {
+            let new = c.owner(k).unwrap();
+            if &new != old {
+                assert_eq!(new, "node-4", "keys only move to the new shard");
+                moved += 1;
+            }
+        }
+        assert!((1_000..3_000).contains(&moved), "moved {}", moved);
+
+        assert!(c.remove_shard("node-4"));
+        assert!(keys().iter().zip(before.iter()).all(|(k, old)| &c.owner(k).unwrap() == old));
+    }
+
+    #[test]
+    fn dead_shard_is_routed_around_and_caught_up() {
+        let clock = Arc::new(MockClock::new());
+        let c = L3Cluster::new().with_clock(clock.clone());
+        let shards: Vec<Arc<MapShard>> = (0..3).map(|_| Arc::new(MapShard::default())).collect();
+        for (i, s) in shards.iter().enumerate() {
+            c.add_shard(&format!("node-{}", i), s.clone());
+        }
+        let key = b"/index.html";
+        let owner = c.owner(key).unwrap();
+        let oi: usize = owner["node-".len()..].parse().unwrap();
+        let e = |v: &[u8]| Entry::new(v.to_vec(), 0, Duration::from_secs(60));
+        c.put(key, &e(b"v1")).unwrap();
+
+        shards[oi].down.store(true, Ordering::SeqCst);
+        c.put(key, &e(b"v2")).unwrap(); // fails over within the call
+        let h = c.health();
+        let dead = h.iter().find(|s| s.name == owner).unwrap();
+        assert_eq!((dead.up, dead.failures, dead.missed), (false, 1, 1));
+        assert_ne!(c.route(key).unwrap(), owner);
+        assert_eq!(c.get(key).unwrap().unwrap().value, b"v2");
+
+        // back up: the stale v1 on the owner is removed before it serves again
+        shards[oi].down.store(false, Ordering::SeqCst);
+        clock.advance(DEFAULT_RETRY);
+        assert_eq!(c.route(key).unwrap(), owner);
+        assert!(c.get(key).unwrap().is_none());
+        assert!(c.health().iter().all(|s| s.up && s.missed == 0));
+
+        // the copy the successor took during the outage does not come back
+        shards[oi].down.store(true, Ordering::SeqCst);
+        assert!(c.get(key).unwrap().is_none());
+        shards[oi].down.store(false, Ordering::SeqCst);
+        clock.advance(DEFAULT_RETRY);
+
+        for s in shards.iter() {
+            s.down.store(true, Ordering::SeqCst);
+        }
+        assert!(matches!(c.get(key), Err(CacheError::Io(_))));
+    }
+}
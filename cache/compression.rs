@@ -1,15 +1,17 @@
 // ============================================================================
 // OLWSX - OverLab Web ServerX
 // File: cache/compression.rs
-// Role: Final compression facade (markers and transparent pass-through)
+// Role: Final compression facade (native gzip encode/decode, no dependencies)
 // ----------------------------------------------------------------------------
-// To keep the cache layer self-contained and deterministic without external
-// dependencies, we implement a marker-based facade: functions return the
-// same input (pass-through) while annotating meta flags chosen by caller.
-// Actual compression can be done by higher layers, but the API here is stable.
+// Gzip is implemented here (LZ77 + fixed Huffman on encode, full RFC 1951 on
+// decode) so the cache stays dependency-free. This build has no zstd or
+// brotli backend: requests for them are served with gzip and the returned
+// meta flags say so, so flags always describe the stored bytes. Output that
+// would not shrink is stored as-is under COMP_NONE.
 // ============================================================================
 
-use crate::meta;
+use crate::checksum::crc32;
+use crate::{meta, CacheError};
 
 #[derive(Clone, Debug)]
 pub enum Algo {
@@ -23,15 +25,42 @@ pub enum Algo {
 pub struct CompResult {
     pub data: Vec<u8>,
     pub meta_flags: u32,
+    pub original_size: usize,
+}
+
+impl CompResult {
+    /// Stored size over original size (1.0 for empty input).
+    pub fn ratio(&self) -> f64 {
+        if self.original_size == 0 {
+            return 1.0;
+        }
+        return self.data.len() as f64 / self.original_size as f64;
+    }
 }
 
 pub fn compress(input: &[u8], algo: Algo) -> CompResult {
+    let raw = || CompResult { data: input.to_vec(), meta_flags: meta::COMP_NONE, original_size: input.len() };
     match algo {
-        Algo::None => CompResult { data: input.to_vec(), meta_flags: meta::COMP_NONE },
-        Algo::Gzip => CompResult { data: input.to_vec(), meta_flags: meta::COMP_GZIP },
-        Algo::Zstd => CompResult { data: input.to_vec(), meta_flags: meta::COMP_ZSTD },
-        Algo::Brotli => CompResult { data: input.to_vec(), meta_flags: meta::COMP_BROTLI },
+        Algo::None => return raw(),
+        Algo::Gzip | Algo::Zstd | Algo::Brotli => {
+            let data = gzip(input);
+            if data.len() >= input.len() {
+                return raw();
+            }
+            return CompResult { data, meta_flags: meta::COMP_GZIP, original_size: input.len() };
+        }
+    }
+}
+
+/// Inverse of `compress`, driven by the entry's meta flags.
+pub fn decompress(data: &[u8], meta_flags: u32) -> Result<Vec<u8>, CacheError> {
+    if meta_flags & (meta::COMP_ZSTD | meta::COMP_BROTLI) != 0 {
+        return Err(CacheError::Io(std::io::ErrorKind::Unsupported));
+    }
+    if meta_flags & meta::COMP_GZIP != 0 {
+        return gunzip(data);
     }
+    return Ok(data.to_vec());
 }
 
 pub fn best_for_mime(mime: &str) -> Algo {
@@ -40,4 +69,406 @@ pub fn best_for_mime(mime: &str) -> Algo {
         return Algo::Gzip;
     }
     return Algo::None;
-}
\ No newline at end of file
+}
+
+// ------------------------------- gzip container -----------------------------
+
+pub fn gzip(input: &[u8]) -> Vec<u8> {
+    // magic, CM=deflate, no flags, mtime 0, XFL 0, OS unknown
+    let mut out = vec![0x1f, 0x8b, 0x08, 0x00, 0, 0, 0, 0, 0x00, 0xff];
+    out.extend_from_slice(&deflate(input));
+    out.extend_from_slice(&crc32(input).to_le_bytes());
+    out.extend_from_slice(&(input.len() as u32).to_le_bytes());
+    return out;
+}
+
+pub fn gunzip(input: &[u8]) -> Result<Vec<u8>, CacheError> {
+    const FHCRC: u8 = 0x02;
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+    const FCOMMENT: u8 = 0x10;
+    if input.len() < 18 || input[0] != 0x1f || input[1] != 0x8b || input[2] != 8 {
+        return Err(CacheError::Corrupt);
+    }
+    let flags = input[3];
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let x = input.get(pos..pos + 2).ok_or(CacheError::Corrupt)?;
+        pos += 2 + u16::from_le_bytes([x[0], x[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = input.get(pos..).and_then(|r| r.iter().position(|b| *b == 0)).ok_or(CacheError::Corrupt)?;
+            pos += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+    let tail = input.get(input.len() - 8..).ok_or(CacheError::Corrupt)?;
+    let crc = u32::from_le_bytes([tail[0], tail[1], tail[2], tail[3]]);
+    let isize = u32::from_le_bytes([tail[4], tail[5], tail[6], tail[7]]);
+    let body = input.get(pos..input.len() - 8).ok_or(CacheError::Corrupt)?;
+    // ISIZE is the length mod 2^32; it bounds the output for anything we wrote.
+    // It is untrusted: deflate cannot expand more than MAX_RATIO:1, so a larger
+    // claim is rejected before anything is allocated for it.
+    if isize as usize > body.len().saturating_mul(MAX_RATIO) {
+        return Err(CacheError::Corrupt);
+    }
+    let out = inflate(body, isize as usize)?;
+    if crc != crc32(&out) || isize != out.len() as u32 {
+        return Err(CacheError::Corrupt);
+    }
+    return Ok(out);
+}
+
+// ------------------------------- RFC 1951 tables ----------------------------
+
+const LEN_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LEN_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+const CLEN_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+// ------------------------------- deflate (encode) ---------------------------
+
+const WINDOW: usize = 32 * 1024;
+const HASH_BITS: u32 = 15;
+const MAX_CHAIN: usize = 64;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const NIL: usize = usize::MAX;
+const MAX_RATIO: usize = 1032; // 258-byte match per 2-bit code, plus block overhead
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    buf: u64,
+    cnt: u32,
+}
+
+impl BitWriter {
+    // LSB-first, as deflate packs all non-Huffman fields.
+    fn put(&mut self, value: u32, n: u32) {
+        self.buf |= (value as u64) << self.cnt;
+        self.cnt += n;
+        while self.cnt >= 8 {
+            self.out.push(self.buf as u8);
+            self.buf >>= 8;
+            self.cnt -= 8;
+        }
+    }
+
+    // Huffman codes are defined MSB-first.
+    fn put_code(&mut self, code: u32, n: u32) {
+        self.put(code.reverse_bits() >> (32 - n), n);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.cnt > 0 {
+            self.out.push(self.buf as u8);
+        }
+        return self.out;
+    }
+}
+
+fn put_literal(w: &mut BitWriter, sym: usize) {
+    let (code, n) = match sym {
+        0..=143 => (0x30 + sym, 8),
+        144..=255 => (0x190 + sym - 144, 9),
+        256..=279 => (sym - 256, 7),
+        _ => (0xc0 + sym - 280, 8),
+    };
+    w.put_code(code as u32, n);
+}
+
+fn put_match(w: &mut BitWriter, len: usize, dist: usize) {
+    let s = (0..LEN_BASE.len()).rev().find(|s| LEN_BASE[*s] as usize <= len).unwrap_or(0);
+    put_literal(w, 257 + s);
+    w.put((len - LEN_BASE[s] as usize) as u32, LEN_EXTRA[s] as u32);
+    let d = (0..DIST_BASE.len()).rev().find(|d| DIST_BASE[*d] as usize <= dist).unwrap_or(0);
+    w.put_code(d as u32, 5);
+    w.put((dist - DIST_BASE[d] as usize) as u32, DIST_EXTRA[d] as u32);
+}
+
+fn hash3(b: &[u8]) -> usize {
+    let v = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+    return (v.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize;
+}
+
+/// Single final block with the fixed Huffman code; greedy LZ77 over a 32K window.
+fn deflate(input: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter::default();
+    w.put(1, 1); // BFINAL
+    w.put(1, 2); // BTYPE = fixed Huffman
+    let mut head = vec![NIL; 1 << HASH_BITS];
+    let mut prev = vec![NIL; WINDOW];
+    let insert = |i: usize, head: &mut [usize], prev: &mut [usize]| {
+        if i + MIN_MATCH <= input.len() {
+            let h = hash3(&input[i..]);
+            prev[i % WINDOW] = head[h];
+            head[h] = i;
+        }
+    };
+    let mut i = 0;
+    while i < input.len() {
+        let (mut best_len, mut best_dist) = (0, 0);
+        if i + MIN_MATCH <= input.len() {
+            let limit = MAX_MATCH.min(input.len() - i);
+            let mut cand = head[hash3(&input[i..])];
+            let mut chain = 0;
+            while cand != NIL && cand < i && i - cand < WINDOW && chain < MAX_CHAIN {
+                let l = input[cand..].iter().zip(input[i..i + limit].iter()).take_while(|(a, b)| a == b).count();
+                if l > best_len {
+                    best_len = l;
+                    best_dist = i - cand;
+                    if l == limit {
+                        break;
+                    }
+                }
+                let next = prev[cand % WINDOW];
+                if next == NIL || next >= cand {
+                    break; // slot reused by a newer position
+                }
+                cand = next;
+                chain += 1;
+            }
+        }
+        if best_len >= MIN_MATCH {
+            put_match(&mut w, best_len, best_dist);
+            for k in i..i + best_len {
+                insert(k, &mut head, &mut prev);
+            }
+            i += best_len;
+        } else {
+            put_literal(&mut w, input[i] as usize);
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+    put_literal(&mut w, 256);
+    return w.finish();
+}
+
+// ------------------------------- inflate (decode) ---------------------------
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    cnt: u32,
+}
+
+impl Bits<'_> {
+    fn need(&mut self, n: u32) -> Result<u32, CacheError> {
+        while self.cnt < n {
+            let b = *self.data.get(self.pos).ok_or(CacheError::Corrupt)?;
+            self.pos += 1;
+            self.buf |= (b as u32) << self.cnt;
+            self.cnt += 8;
+        }
+        let v = self.buf & ((1u32 << n) - 1);
+        self.buf >>= n;
+        self.cnt -= n;
+        return Ok(v);
+    }
+}
+
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, CacheError> {
+        let mut counts = [0u16; 16];
+        for l in lengths {
+            counts[*l as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut left: i32 = 1;
+        for c in counts.iter().skip(1) {
+            left = (left << 1) - *c as i32;
+            if left < 0 {
+                return Err(CacheError::Corrupt);
+            }
+        }
+        let mut offs = [0u16; 16];
+        for i in 1..15 {
+            offs[i + 1] = offs[i] + counts[i];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (sym, l) in lengths.iter().enumerate() {
+            if *l != 0 {
+                symbols[offs[*l as usize] as usize] = sym as u16;
+                offs[*l as usize] += 1;
+            }
+        }
+        return Ok(Huffman { counts, symbols });
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, CacheError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.need(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        return Err(CacheError::Corrupt);
+    }
+}
+
+fn inflate(input: &[u8], cap: usize) -> Result<Vec<u8>, CacheError> {
+    // grow with the data instead of reserving the claimed size up front
+    let mut out: Vec<u8> = Vec::with_capacity(cap.min(input.len().saturating_mul(4)));
+    let mut bits = Bits { data: input, pos: 0, buf: 0, cnt: 0 };
+    loop {
+        let last = bits.need(1)?;
+        match bits.need(2)? {
+            0 => {
+                bits.buf = 0;
+                bits.cnt = 0;
+                let hdr = input.get(bits.pos..bits.pos + 4).ok_or(CacheError::Corrupt)?;
+                let len = u16::from_le_bytes([hdr[0], hdr[1]]) as usize;
+                if len != !u16::from_le_bytes([hdr[2], hdr[3]]) as usize || out.len() + len > cap {
+                    return Err(CacheError::Corrupt);
+                }
+                bits.pos += 4;
+                out.extend_from_slice(input.get(bits.pos..bits.pos + len).ok_or(CacheError::Corrupt)?);
+                bits.pos += len;
+            }
+            1 => {
+                let mut l = [8u8; 288];
+                l[144..256].fill(9);
+                l[256..280].fill(7);
+                codes(&mut bits, &mut out, &Huffman::new(&l)?, &Huffman::new(&[5u8; 30])?, cap)?;
+            }
+            2 => {
+                let (lit, dist) = dynamic_tables(&mut bits)?;
+                codes(&mut bits, &mut out, &lit, &dist, cap)?;
+            }
+            _ => return Err(CacheError::Corrupt),
+        }
+        if last == 1 {
+            return Ok(out);
+        }
+    }
+}
+
+fn dynamic_tables(bits: &mut Bits) -> Result<(Huffman, Huffman), CacheError> {
+    let hlit = bits.need(5)? as usize + 257;
+    let hdist = bits.need(5)? as usize + 1;
+    let hclen = bits.need(4)? as usize + 4;
+    if hlit > 286 || hdist > 30 {
+        return Err(CacheError::Corrupt);
+    }
+    let mut cl = [0u8; 19];
+    for i in CLEN_ORDER.iter().take(hclen) {
+        cl[*i] = bits.need(3)? as u8;
+    }
+    let clh = Huffman::new(&cl)?;
+    let mut lengths = vec![0u8; hlit + hdist];
+    let mut i = 0;
+    while i < hlit + hdist {
+        let sym = clh.decode(bits)?;
+        let (val, rep) = match sym {
+            0..=15 => (sym as u8, 1),
+            16 => (*lengths.get(i.wrapping_sub(1)).ok_or(CacheError::Corrupt)?, 3 + bits.need(2)? as usize),
+            17 => (0, 3 + bits.need(3)? as usize),
+            _ => (0, 11 + bits.need(7)? as usize),
+        };
+        if i + rep > lengths.len() {
+            return Err(CacheError::Corrupt);
+        }
+        lengths[i..i + rep].fill(val);
+        i += rep;
+    }
+    if lengths[256] == 0 {
+        return Err(CacheError::Corrupt);
+    }
+    return Ok((Huffman::new(&lengths[..hlit])?, Huffman::new(&lengths[hlit..])?));
+}
+
+fn codes(bits: &mut Bits, out: &mut Vec<u8>, lit: &Huffman, dist: &Huffman, cap: usize) -> Result<(), CacheError> {
+    loop {
+        let sym = lit.decode(bits)? as usize;
+        if sym < 256 {
+            if out.len() >= cap {
+                return Err(CacheError::Corrupt);
+            }
+            out.push(sym as u8);
+        } else if sym == 256 {
+            return Ok(());
+        } else {
+            let s = sym - 257;
+            if s >= 29 {
+                return Err(CacheError::Corrupt);
+            }
+            let len = LEN_BASE[s] as usize + bits.need(LEN_EXTRA[s] as u32)? as usize;
+            let ds = dist.decode(bits)? as usize;
+            if ds >= 30 {
+                return Err(CacheError::Corrupt);
+            }
+            let d = DIST_BASE[ds] as usize + bits.need(DIST_EXTRA[ds] as u32)? as usize;
+            if d > out.len() || out.len() + len > cap {
+                return Err(CacheError::Corrupt);
+            }
+            let start = out.len() - d;
+            for k in 0..len {
+                let b = out[start + k];
+                out.push(b);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gzip_round_trip_and_flags() {
+        let text: Vec<u8> = (0..2000).flat_map(|i| format!("{{\"id\":{},\"name\":\"item-{}\"}},", i, i % 17).into_bytes()).collect();
+        let c = compress(&text, Algo::Gzip);
+        assert_eq!(c.meta_flags, meta::COMP_GZIP);
+        assert_eq!(c.original_size, text.len());
+        assert!(c.ratio() < 0.25, "ratio {}", c.ratio());
+        assert_eq!(decompress(&c.data, c.meta_flags).unwrap(), text);
+
+        // no zstd/brotli backend: served as gzip, flagged as such
+        assert_eq!(compress(&text, Algo::Brotli).meta_flags, meta::COMP_GZIP);
+        // incompressible input is stored raw
+        let tiny = compress(b"ab", Algo::Gzip);
+        assert_eq!((tiny.meta_flags, tiny.data.as_slice()), (meta::COMP_NONE, &b"ab"[..]));
+
+        let mut bad = c.data.clone();
+        bad[20] ^= 0xff;
+        assert!(decompress(&bad, meta::COMP_GZIP).is_err());
+        assert!(matches!(decompress(b"x", meta::COMP_ZSTD), Err(CacheError::Io(std::io::ErrorKind::Unsupported))));
+    }
+
+    #[test]
+    fn gunzip_foreign_stream() {
+        // `printf 'hello hello hello hello' | gzip -n`
+        let gz = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xcb, 0x48, 0xcd, 0xc9,
+            0xc9, 0x57, 0xc8, 0x40, 0x27, 0x01, 0xe3, 0x51, 0x3d, 0x8d, 0x17, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(gunzip(&gz).unwrap(), b"hello hello hello hello".to_vec());
+        let binary: Vec<u8> = (0..70_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+        assert_eq!(gunzip(&gzip(&binary)).unwrap(), binary);
+
+        // a forged ISIZE beyond what the body can expand to is refused up front
+        let mut forged = gz.to_vec();
+        let n = forged.len();
+        forged[n - 4..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(gunzip(&forged), Err(CacheError::Corrupt)));
+        let zeros = vec![0u8; 1 << 20];
+        assert_eq!(gunzip(&gzip(&zeros)).unwrap(), zeros);
+    }
+}
@@ -0,0 +1,636 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/disk.rs
+// Role: Final persistent L3 store (append-only segments, index in RAM)
+// ----------------------------------------------------------------------------
+// Built with the `disk` feature. Writes append one record to the active
+// segment; deletes append a tombstone. Only key -> location is kept in
+// memory, so large bodies cost no RAM and survive restarts.
+//
+// On open every segment is replayed oldest first. Each record carries a
+// CRC-32; replay stops at the first bad or truncated record of a segment and
+// cuts the file there (a torn tail after a crash). Segments with a bad header
+// and files not named like segments are left alone. Tombstones are fsynced
+// before remove() returns, so a torn tail never resurrects a removed key.
+// Reads verify the CRC again and use per-segment handles outside the lock.
+//
+// Compaction rewrites the live records of a sealed segment into the active
+// one and deletes the old file, once `garbage_ratio` of it is dead
+// (overwritten, deleted or expired). `start_compactor` runs it periodically.
+//
+// Segment file "<id:016x>.seg": magic "OLSG" | version u8 | records...
+// Record (big endian):
+//   crc32 u32 | kind u8 | flags u32 | created_unix_ms u64 | ttl_ms u64
+//   | grace_ms u64 | key_len u32 | value_len u32 | key | value
+// With VALIDATED in kind, value starts with the block described in
+// validators.rs and value_len covers both.
+// The crc32 covers everything after itself.
+// ============================================================================
+
+use crate::checksum::crc32;
+use crate::l3::L3Backend;
+use crate::validators::{decode_block, encode_block, has_validators};
+use crate::{CacheError, Entry};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+#[cfg(not(unix))]
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const MAGIC: &[u8; 4] = b"OLSG";
+const VERSION: u8 = 1;
+const SEG_HEADER: u64 = 5;
+const REC_HEADER: usize = 4 + 1 + 4 + 8 + 8 + 8 + 4 + 4;
+const EXT: &str = "seg";
+
+const PUT: u8 = 0x01;
+const DEL: u8 = 0x02;
+const SUMMED: u8 = 0x80; // entry carried a checksum (re-attached on read)
+const VALIDATED: u8 = 0x40; // a validator block precedes the value
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiskConfig {
+    /// The active segment is sealed once it reaches this size.
+    pub segment_bytes: u64,
+    /// Share of dead bytes at which a sealed segment is compacted (0.0..=1.0).
+    pub garbage_ratio: f64,
+}
+
+impl Default for DiskConfig {
+    fn default() -> Self {
+        return DiskConfig { segment_bytes: 64 * 1024 * 1024, garbage_ratio: 0.5 };
+    }
+}
+
+/// What `DiskStore::open` found on disk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    pub segments: usize,
+    pub entries: usize,            // live entries indexed
+    pub corrupt_segments: usize,   // segments cut at a bad or truncated record
+    pub truncated_bytes: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Loc {
+    seg: u64,
+    off: u64,
+    len: u64, // whole record
+    ts: Instant,
+    ttl: Duration,
+    grace: Duration,
+}
+
+impl Loc {
+    fn is_dead_at(&self, now: Instant) -> bool {
+        return now.saturating_duration_since(self.ts) > self.ttl.saturating_add(self.grace);
+    }
+    // time left before the entry stops being servable
+    fn remaining(&self, now: Instant) -> Duration {
+        return self.ttl.saturating_add(self.grace).saturating_sub(now.saturating_duration_since(self.ts));
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Segment {
+    total: u64, // bytes written, header included
+    live: u64,  // bytes of records the index points at
+}
+
+struct State {
+    index: HashMap<Vec<u8>, Loc>,
+    segments: BTreeMap<u64, Segment>,
+    active: u64,
+    file: File, // active segment, append mode
+    readers: HashMap<u64, Arc<File>>, // opened on first read
+}
+
+pub struct DiskStore {
+    dir: PathBuf,
+    cfg: DiskConfig,
+    inner: Mutex<State>,
+    report: LoadReport,
+}
+
+impl DiskStore {
+    /// Open (or create) a store directory, replaying and repairing its segments.
+    pub fn open(dir: impl AsRef<Path>, cfg: DiskConfig) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut ids = Vec::new();
+        for item in fs::read_dir(&dir)? {
+            ids.extend(segment_id(&item?.path()));
+        }
+        ids.sort_unstable();
+
+        let mut report = LoadReport { segments: ids.len(), ..LoadReport::default() };
+        let mut index: HashMap<Vec<u8>, Loc> = HashMap::new();
+        let mut segments: BTreeMap<u64, Segment> = BTreeMap::new();
+        let now = Instant::now();
+        for &id in ids.iter() {
+            let path = segment_path(&dir, id);
+            let bytes = fs::read(&path)?;
+            let mut seg = Segment { total: SEG_HEADER, live: 0 };
+            let mut off = SEG_HEADER as usize;
+            let valid_header = bytes.len() >= off && &bytes[0..4] == MAGIC && bytes[4] == VERSION;
+            if valid_header {
+                while off < bytes.len() {
+                    let Some((kind, key, loc)) = parse(&bytes[off..], id, off as u64) else { break };
+                    seg.total += loc.len;
+                    if let Some(old) = index.remove(&key) {
+                        match segments.get_mut(&old.seg) {
+                            Some(s) => s.live -= old.len,
+                            None => seg.live -= old.len, // superseded within this segment
+                        }
+                    }
+                    if kind & PUT != 0 && !loc.is_dead_at(now) {
+                        seg.live += loc.len;
+                        index.insert(key, loc);
+                    }
+                    off += loc.len as usize;
+                }
+            }
+            if !valid_header {
+                // not ours to repair: skipped, and never reused as an id
+                report.corrupt_segments += 1;
+                continue;
+            }
+            if off < bytes.len() {
+                report.corrupt_segments += 1;
+                report.truncated_bytes += (bytes.len() - off) as u64;
+                OpenOptions::new().write(true).open(&path)?.set_len(off as u64)?;
+            }
+            segments.insert(id, seg);
+        }
+        report.entries = index.len();
+
+        // always start a fresh segment; replayed ones are sealed
+        let active = ids.last().map(|id| id + 1).unwrap_or(0);
+        let file = create_segment(&dir, active)?;
+        segments.insert(active, Segment { total: SEG_HEADER, live: 0 });
+        let st = State { index, segments, active, file, readers: HashMap::new() };
+        return Ok(DiskStore { dir, cfg, inner: Mutex::new(st), report });
+    }
+
+    pub fn load_report(&self) -> LoadReport {
+        return self.report;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.inner.lock().unwrap().index.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        return self.inner.lock().unwrap().index.contains_key(key);
+    }
+
+    /// Bytes in segment files, dead records included.
+    pub fn disk_bytes(&self) -> u64 {
+        return self.inner.lock().unwrap().segments.values().map(|s| s.total).sum();
+    }
+
+    pub fn segments(&self) -> usize {
+        return self.inner.lock().unwrap().segments.len();
+    }
+
+    /// Ok(None) when absent. A record that fails its CRC is dropped and
+    /// reported as `CacheError::Corrupt`.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Entry>, CacheError> {
+        let (loc, file) = {
+            let mut st = self.inner.lock().unwrap();
+            let Some(loc) = st.index.get(key).copied() else { return Ok(None) };
+            let file = self.reader(&mut st, loc.seg).map_err(|e| CacheError::Io(e.kind()))?;
+            (loc, file)
+        };
+        match read_record(&file, &loc) {
+            Ok(Some((_, k, mut entry))) if k == key => {
+                // RAM index is authoritative for the monotonic timestamp
+                entry.ts = loc.ts;
+                return Ok(Some(entry));
+            }
+            Ok(_) => {
+                let mut st = self.inner.lock().unwrap();
+                if st.index.get(key).is_some_and(|l| l.seg == loc.seg && l.off == loc.off) {
+                    Self::unindex(&mut st, key);
+                }
+                return Err(CacheError::Corrupt);
+            }
+            Err(e) => return Err(CacheError::Io(e.kind())),
+        }
+    }
+
+    pub fn put(&self, key: &[u8], entry: &Entry) -> Result<(), CacheError> {
+        let mut st = self.inner.lock().unwrap();
+        let mut kind = if entry.checksum.is_some() { PUT | SUMMED } else { PUT };
+        if has_validators(entry) {
+            kind |= VALIDATED;
+        }
+        let rec = encode(kind, key, entry);
+        let loc = self.append(&mut st, &rec, entry).map_err(|e| CacheError::Io(e.kind()))?;
+        Self::unindex(&mut st, key);
+        if let Some(s) = st.segments.get_mut(&loc.seg) {
+            s.live += loc.len;
+        }
+        st.index.insert(key.to_vec(), loc);
+        return Ok(());
+    }
+
+    /// Ok(false) when the key was not stored.
+    pub fn remove(&self, key: &[u8]) -> Result<bool, CacheError> {
+        let mut st = self.inner.lock().unwrap();
+        if !st.index.contains_key(key) {
+            return Ok(false);
+        }
+        let tomb = Entry::new(Vec::new(), 0, Duration::ZERO);
+        self.append(&mut st, &encode(DEL, key, &tomb), &tomb).map_err(|e| CacheError::Io(e.kind()))?;
+        // durable before we report it: a lost tombstone would uncover the put
+        st.file.sync_data().map_err(|e| CacheError::Io(e.kind()))?;
+        Self::unindex(&mut st, key);
+        return Ok(true);
+    }
+
+    /// Tombstones every entry (fsynced, like `remove`); returns how many.
+    pub fn clear(&self) -> Result<usize, CacheError> {
+        let mut st = self.inner.lock().unwrap();
+        let keys: Vec<Vec<u8>> = st.index.keys().cloned().collect();
+        let tomb = Entry::new(Vec::new(), 0, Duration::ZERO);
+        for k in keys.iter() {
+            self.append(&mut st, &encode(DEL, k, &tomb), &tomb).map_err(|e| CacheError::Io(e.kind()))?;
+            Self::unindex(&mut st, k);
+        }
+        st.file.sync_data().map_err(|e| CacheError::Io(e.kind()))?;
+        return Ok(keys.len());
+    }
+
+    /// Frees room: drops every dead entry, or when there is none the entry
+    /// closest to expiry. Returns the number of entries dropped.
+    pub fn evict(&self, now: Instant) -> usize {
+        let mut st = self.inner.lock().unwrap();
+        let mut dead: Vec<Vec<u8>> = st.index.iter().filter(|(_, l)| l.is_dead_at(now)).map(|(k, _)| k.clone()).collect();
+        if dead.is_empty() {
+            dead.extend(st.index.iter().min_by_key(|(_, l)| l.remaining(now)).map(|(k, _)| k.clone()));
+        }
+        // tombstoned so an eviction still holds after a restart
+        let tomb = Entry::new(Vec::new(), 0, Duration::ZERO);
+        for k in dead.iter() {
+            let _ = self.append(&mut st, &encode(DEL, k, &tomb), &tomb);
+            Self::unindex(&mut st, k);
+        }
+        return dead.len();
+    }
+
+    /// Compact every sealed segment past `garbage_ratio`; returns the number
+    /// of segments rewritten.
+    pub fn compact(&self) -> io::Result<usize> {
+        let candidates: Vec<u64> = {
+            let st = self.inner.lock().unwrap();
+            st.segments
+                .iter()
+                .filter(|(id, s)| **id != st.active && (s.total - s.live) as f64 >= self.cfg.garbage_ratio * s.total as f64)
+                .map(|(id, _)| *id)
+                .collect()
+        };
+        for &id in candidates.iter() {
+            let mut st = self.inner.lock().unwrap();
+            self.compact_segment(&mut st, id)?;
+        }
+        return Ok(candidates.len());
+    }
+
+    /// Runs `compact` every `interval` on a background thread until the
+    /// returned handle is dropped.
+    pub fn start_compactor(self: &Arc<Self>, interval: Duration) -> Compactor {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (store, flag) = (Arc::clone(self), Arc::clone(&stop));
+        let thread = std::thread::spawn(move || {
+            while !flag.load(Ordering::Acquire) {
+                let _ = store.compact();
+                std::thread::park_timeout(interval);
+            }
+        });
+        return Compactor { stop, thread: Some(thread) };
+    }
+
+    // Copies the live records (and tombstones still shadowing an older
+    // segment) of `id` to the active segment, then deletes it.
+    fn compact_segment(&self, st: &mut State, id: u64) -> io::Result<()> {
+        let path = segment_path(&self.dir, id);
+        let bytes = fs::read(&path)?;
+        let older_exists = st.segments.keys().next().is_some_and(|first| *first < id);
+        let now = Instant::now();
+        let mut off = SEG_HEADER as usize;
+        while off < bytes.len() {
+            let Some((kind, key, loc)) = parse(&bytes[off..], id, off as u64) else { break };
+            let rec = &bytes[off..off + loc.len as usize];
+            off += loc.len as usize;
+            let current = st.index.get(&key).is_some_and(|l| l.seg == id && l.off == loc.off);
+            if kind & PUT != 0 && current && !loc.is_dead_at(now) {
+                let moved = self.append_raw(st, rec, loc)?;
+                if let Some(s) = st.segments.get_mut(&moved.seg) {
+                    s.live += moved.len;
+                }
+                st.index.insert(key, moved);
+            } else if kind & PUT != 0 && current {
+                // expired: keep it from uncovering an older put on replay
+                st.index.remove(&key);
+                if older_exists {
+                    let tomb = Entry::new(Vec::new(), 0, Duration::ZERO);
+                    self.append(st, &encode(DEL, &key, &tomb), &tomb)?;
+                }
+            } else if kind == DEL && older_exists && !st.index.contains_key(&key) {
+                self.append_raw(st, rec, loc)?;
+            }
+        }
+        // the copies must be on disk before the originals go away
+        st.file.sync_data()?;
+        st.segments.remove(&id);
+        st.readers.remove(&id);
+        fs::remove_file(&path)?;
+        return Ok(());
+    }
+
+    fn append(&self, st: &mut State, rec: &[u8], entry: &Entry) -> io::Result<Loc> {
+        let loc = Loc { seg: 0, off: 0, len: rec.len() as u64, ts: entry.ts, ttl: entry.ttl, grace: entry.grace };
+        return self.append_raw(st, rec, loc);
+    }
+
+    fn append_raw(&self, st: &mut State, rec: &[u8], mut loc: Loc) -> io::Result<Loc> {
+        let full = st.segments.get(&st.active).map(|s| s.total).unwrap_or(0);
+        if full > SEG_HEADER && full + rec.len() as u64 > self.cfg.segment_bytes {
+            let next = st.active + 1;
+            st.file.sync_data()?; // sealed
+            st.file = create_segment(&self.dir, next)?;
+            st.active = next;
+            st.segments.insert(next, Segment { total: SEG_HEADER, live: 0 });
+        }
+        let seg = st.segments.get_mut(&st.active).unwrap();
+        st.file.write_all(rec)?;
+        loc.seg = st.active;
+        loc.off = seg.total;
+        seg.total += rec.len() as u64;
+        return Ok(loc);
+    }
+
+    fn unindex(st: &mut State, key: &[u8]) {
+        if let Some(old) = st.index.remove(key)
+            && let Some(s) = st.segments.get_mut(&old.seg)
+        {
+            s.live -= old.len;
+        }
+    }
+
+    // Shared read handle for segment `id`; it stays valid after compaction
+    // unlinks the file, for reads that already hold it.
+    fn reader(&self, st: &mut State, id: u64) -> io::Result<Arc<File>> {
+        if let Some(f) = st.readers.get(&id) {
+            return Ok(Arc::clone(f));
+        }
+        let f = Arc::new(File::open(segment_path(&self.dir, id))?);
+        st.readers.insert(id, Arc::clone(&f));
+        return Ok(f);
+    }
+}
+
+fn read_record(f: &File, loc: &Loc) -> io::Result<Option<(u8, Vec<u8>, Entry)>> {
+    let mut buf = vec![0u8; loc.len as usize];
+    if read_exact_at(f, &mut buf, loc.off).is_err() {
+        return Ok(None);
+    }
+    return Ok(decode(&buf));
+}
+
+#[cfg(unix)]
+fn read_exact_at(f: &File, buf: &mut [u8], off: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    return f.read_exact_at(buf, off);
+}
+
+#[cfg(not(unix))]
+fn read_exact_at(f: &File, buf: &mut [u8], off: u64) -> io::Result<()> {
+    let mut f = f.try_clone()?;
+    f.seek(SeekFrom::Start(off))?;
+    return f.read_exact(buf);
+}
+
+impl L3Backend for DiskStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Entry>, CacheError> {
+        return DiskStore::get(self, key);
+    }
+    fn put(&self, key: &[u8], entry: &Entry) -> Result<(), CacheError> {
+        return DiskStore::put(self, key, entry);
+    }
+    fn remove(&self, key: &[u8]) -> Result<bool, CacheError> {
+        return DiskStore::remove(self, key);
+    }
+    fn clear(&self) -> Result<(), CacheError> {
+        return DiskStore::clear(self).map(|_| ());
+    }
+    fn make_room(&self, key: &[u8], max_entries: usize, now: Instant) {
+        while !self.contains(key) && self.len() >= max_entries && self.evict(now) > 0 {}
+    }
+}
+
+/// Background compaction handle; stops the thread when dropped.
+pub struct Compactor {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Compactor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(t) = self.thread.take() {
+            t.thread().unpark();
+            let _ = t.join();
+        }
+    }
+}
+
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    return dir.join(format!("{:016x}.{}", id, EXT));
+}
+
+// Id of a file named like segment_path produces.
+fn segment_id(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    if stem.len() != 16 || path.extension()? != EXT {
+        return None;
+    }
+    return u64::from_str_radix(stem, 16).ok();
+}
+
+fn create_segment(dir: &Path, id: u64) -> io::Result<File> {
+    let mut f = OpenOptions::new().create(true).write(true).truncate(true).open(segment_path(dir, id))?;
+    f.write_all(MAGIC)?;
+    f.write_all(&[VERSION])?;
+    return Ok(f);
+}
+
+fn encode(kind: u8, key: &[u8], e: &Entry) -> Vec<u8> {
+    // Instants are process-local; persist the wall-clock creation time instead.
+    let created = unix_ms().saturating_sub(e.ts.elapsed().as_millis() as u64);
+    let mut body = Vec::new();
+    if kind & VALIDATED != 0 {
+        encode_block(e, &mut body);
+    }
+    let mut out = Vec::with_capacity(REC_HEADER + key.len() + body.len() + e.value.len());
+    out.extend_from_slice(&[0u8; 4]);
+    out.push(kind);
+    out.extend_from_slice(&e.flags.to_be_bytes());
+    out.extend_from_slice(&created.to_be_bytes());
+    out.extend_from_slice(&(e.ttl.as_millis() as u64).to_be_bytes());
+    out.extend_from_slice(&(e.grace.as_millis() as u64).to_be_bytes());
+    out.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    out.extend_from_slice(&((body.len() + e.value.len()) as u32).to_be_bytes());
+    out.extend_from_slice(key);
+    out.extend_from_slice(&body);
+    out.extend_from_slice(&e.value);
+    let crc = crc32(&out[4..]);
+    out[0..4].copy_from_slice(&crc.to_be_bytes());
+    return out;
+}
+
+// Header fields and total length of the record at the start of `b`, if it is
+// complete and its CRC matches.
+fn parse(b: &[u8], seg: u64, off: u64) -> Option<(u8, Vec<u8>, Loc)> {
+    let h = b.get(..REC_HEADER)?;
+    let key_len = u32::from_be_bytes(h[33..37].try_into().ok()?) as usize;
+    let value_len = u32::from_be_bytes(h[37..41].try_into().ok()?) as usize;
+    let len = REC_HEADER.checked_add(key_len)?.checked_add(value_len)?;
+    let rec = b.get(..len)?;
+    if crc32(&rec[4..]) != u32::from_be_bytes(h[0..4].try_into().ok()?) {
+        return None;
+    }
+    let kind = h[4];
+    if kind & !(SUMMED | VALIDATED) != PUT && kind != DEL {
+        return None;
+    }
+    let created = u64::from_be_bytes(h[9..17].try_into().ok()?);
+    let ttl = Duration::from_millis(u64::from_be_bytes(h[17..25].try_into().ok()?));
+    let grace = Duration::from_millis(u64::from_be_bytes(h[25..33].try_into().ok()?));
+    let age = Duration::from_millis(unix_ms().saturating_sub(created));
+    let ts = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+    let key = rec[REC_HEADER..REC_HEADER + key_len].to_vec();
+    return Some((kind, key, Loc { seg, off, len: len as u64, ts, ttl, grace }));
+}
+
+fn decode(rec: &[u8]) -> Option<(u8, Vec<u8>, Entry)> {
+    let (kind, key, loc) = parse(rec, 0, 0)?;
+    let flags = u32::from_be_bytes(rec[5..9].try_into().ok()?);
+    let mut value = &rec[REC_HEADER + key.len()..];
+    let (mut etag, mut last_modified) = (None, None);
+    if kind & VALIDATED != 0 {
+        let used;
+        (etag, last_modified, used) = decode_block(value)?;
+        value = &value[used..];
+    }
+    let mut entry = Entry { etag, last_modified, ..Entry::new_at(value.to_vec(), flags, loc.ttl, loc.ts).with_grace(loc.grace) };
+    if kind & SUMMED != 0 {
+        entry = entry.with_checksum();
+    }
+    return Some((kind, key, entry));
+}
+
+fn unix_ms() -> u64 {
+    return SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tmpdir(name: &str) -> PathBuf {
+        let d = std::env::temp_dir().join(format!("olwsx-disk-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&d);
+        return d;
+    }
+
+    fn entry(v: &[u8]) -> Entry {
+        return Entry::new(v.to_vec(), 0x4, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn survives_reopen_and_cuts_torn_tail() {
+        let dir = tmpdir("reopen");
+        {
+            let s = DiskStore::open(&dir, DiskConfig::default()).unwrap();
+            s.put(b"big", &entry(&vec![9u8; 1 << 20]).with_checksum().with_etag("\"v1\"")).unwrap();
+            s.put(b"gone", &entry(b"x")).unwrap();
+            assert!(s.remove(b"gone").unwrap());
+            s.put(b"torn", &entry(b"y")).unwrap();
+        }
+        // foreign files and a segment with a bad header are left alone
+        fs::write(dir.join("README"), b"operator notes").unwrap();
+        fs::write(dir.join("00000000000000aa.seg"), b"JUNK").unwrap();
+        // crash in the middle of the last record
+        let seg = segment_path(&dir, 0);
+        let len = fs::metadata(&seg).unwrap().len();
+        OpenOptions::new().write(true).open(&seg).unwrap().set_len(len - 3).unwrap();
+
+        let s = DiskStore::open(&dir, DiskConfig::default()).unwrap();
+        let r = s.load_report();
+        assert_eq!((r.segments, r.corrupt_segments), (2, 2));
+        let big = s.get(b"big").unwrap().unwrap();
+        assert_eq!((big.value.len(), big.flags), (1 << 20, 0x4));
+        assert!(big.verify() && big.checksum.is_some());
+        assert_eq!((big.etag.as_deref(), big.last_modified), (Some("\"v1\""), None));
+        // the torn record was the last put; the synced tombstone held
+        assert!(s.get(b"gone").unwrap().is_none());
+        assert!(s.get(b"torn").unwrap().is_none());
+        assert_eq!(fs::read(dir.join("00000000000000aa.seg")).unwrap(), b"JUNK");
+        assert!(dir.join("README").exists());
+        assert!(segment_path(&dir, 0xab).exists(), "new active segment skips the foreign id");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn bit_rot_is_detected_on_read() {
+        let dir = tmpdir("rot");
+        let s = DiskStore::open(&dir, DiskConfig::default()).unwrap();
+        s.put(b"k", &entry(b"value")).unwrap();
+        let seg = segment_path(&dir, 0);
+        let mut raw = fs::read(&seg).unwrap();
+        let last = raw.len() - 1;
+        raw[last] ^= 0xFF;
+        fs::write(&seg, raw).unwrap();
+        assert!(matches!(s.get(b"k"), Err(CacheError::Corrupt)));
+        assert!(s.get(b"k").unwrap().is_none());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn compaction_reclaims_dead_segments() {
+        let dir = tmpdir("compact");
+        let cfg = DiskConfig { segment_bytes: 4096, garbage_ratio: 0.5 };
+        let s = DiskStore::open(&dir, cfg).unwrap();
+        for round in 0..8u8 {
+            for k in 0..8u8 {
+                s.put(&[k], &entry(&[round; 200])).unwrap();
+            }
+        }
+        s.put(b"del", &entry(b"x")).unwrap();
+        s.remove(b"del").unwrap();
+        let before = (s.segments(), s.disk_bytes());
+        assert!(s.compact().unwrap() > 0);
+        assert!(s.segments() < before.0 && s.disk_bytes() < before.1);
+        drop(s);
+
+        let s = DiskStore::open(&dir, cfg).unwrap();
+        assert_eq!(s.load_report().corrupt_segments, 0);
+        assert_eq!(s.len(), 8);
+        for k in 0..8u8 {
+            assert_eq!(s.get(&[k]).unwrap().unwrap().value, vec![7u8; 200]);
+        }
+        assert!(s.get(b"del").unwrap().is_none());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
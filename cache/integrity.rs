@@ -0,0 +1,105 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/integrity.rs
+// Role: Final integrity guard (checksum verification on lookup, any tier)
+// ----------------------------------------------------------------------------
+// Wraps a tier and verifies `Entry::checksum` on every hit. Entries without a
+// checksum pass through untouched. Corrupt entries are always removed from
+// the wrapped tier; the policy decides whether callers see a plain miss or
+// `CacheError::Corrupt`. Corruption events are counted for metrics export.
+// ============================================================================
+
+use crate::{Cache, CacheError, Entry};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegrityPolicy {
+    /// Drop the entry and report `NotFound`, so callers refill transparently.
+    DropAndMiss,
+    /// Drop the entry and report `Corrupt`.
+    Error,
+}
+
+#[derive(Clone)]
+pub struct Verified<C: Cache> {
+    inner: C,
+    policy: IntegrityPolicy,
+    checksum_on_insert: bool,
+    corruptions: Arc<AtomicU64>,
+}
+
+impl<C: Cache> Verified<C> {
+    pub fn new(inner: C, policy: IntegrityPolicy) -> Self {
+        return Verified { inner, policy, checksum_on_insert: false, corruptions: Arc::new(AtomicU64::new(0)) };
+    }
+
+    /// Stamp a checksum on every inserted entry that does not carry one.
+    pub fn checksum_on_insert(mut self, on: bool) -> Self {
+        self.checksum_on_insert = on;
+        return self;
+    }
+
+    pub fn corruption_events(&self) -> u64 {
+        return self.corruptions.load(Ordering::Relaxed);
+    }
+
+    pub fn inner(&self) -> &C {
+        return &self.inner;
+    }
+}
+
+impl<C: Cache> Cache for Verified<C> {
+    fn lookup(&self, key: &[u8]) -> Result<Entry, CacheError> {
+        let e = self.inner.lookup(key)?;
+        if e.verify() {
+            return Ok(e);
+        }
+        self.corruptions.fetch_add(1, Ordering::Relaxed);
+        let _ = self.inner.invalidate(key);
+        return match self.policy {
+            IntegrityPolicy::DropAndMiss => Err(CacheError::NotFound),
+            IntegrityPolicy::Error => Err(CacheError::Corrupt),
+        };
+    }
+
+    fn insert(&self, key: &[u8], entry: Entry) -> Result<(), CacheError> {
+        if self.checksum_on_insert && entry.checksum.is_none() {
+            return self.inner.insert(key, entry.with_checksum());
+        }
+        return self.inner.insert(key, entry);
+    }
+
+    fn invalidate(&self, key: &[u8]) -> Result<(), CacheError> {
+        return self.inner.invalidate(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::l3::L3;
+    use std::time::Duration;
+
+    #[test]
+    fn detects_corruption() {
+        let l3 = L3::new();
+        let guarded = Verified::new(l3.clone(), IntegrityPolicy::Error).checksum_on_insert(true);
+        guarded.insert(b"k", Entry::new(b"payload".to_vec(), 0, Duration::from_secs(60))).unwrap();
+        assert_eq!(guarded.lookup(b"k").unwrap().value, b"payload".to_vec());
+
+        // simulate bit rot underneath the guard
+        let mut bad = l3.lookup(b"k").unwrap();
+        bad.value.make_mut()[0] ^= 0x01;
+        l3.insert(b"k", bad).unwrap();
+        assert!(matches!(guarded.lookup(b"k"), Err(CacheError::Corrupt)));
+        assert!(matches!(l3.lookup(b"k"), Err(CacheError::NotFound)));
+        assert_eq!(guarded.corruption_events(), 1);
+
+        let lenient = Verified::new(L3::new(), IntegrityPolicy::DropAndMiss);
+        let mut e = Entry::new(b"v".to_vec(), 0, Duration::from_secs(60)).with_checksum();
+        e.value = b"w".to_vec().into();
+        lenient.insert(b"k", e).unwrap();
+        assert!(matches!(lenient.lookup(b"k"), Err(CacheError::NotFound)));
+    }
+}
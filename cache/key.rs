@@ -0,0 +1,316 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/key.rs
+// Role: Final cache key normalization (query string canonical form, Vary)
+// ----------------------------------------------------------------------------
+// Equivalent URLs should map to one cache key: tracking parameters are
+// dropped, parameters sorted by name (repeated names keep their order, since
+// `a=2&a=1` and `a=1&a=2` can mean different things), and percent-encoding of unreserved characters
+// (RFC 3986 2.3) undone while other escapes get uppercase hex.
+//
+// `CacheKeyBuilder` appends the request headers named by a `VaryPolicy`, so
+// variants of one URL (gzip and identity bodies, per-language pages) get
+// keys of their own. Header values are reduced to what selects the variant
+// (the negotiated encoding, not the whole Accept-Encoding list) to keep the
+// number of variants small. Key layout:
+//   normalized target | (0x00 header-name 0x00 reduced-value)*
+// Neither targets nor header values may contain NUL, so keys cannot collide.
+// ============================================================================
+
+#[derive(Clone, Debug)]
+pub struct QueryNormalizer {
+    pub sort: bool,
+    pub lowercase_keys: bool,
+    pub decode_unreserved: bool,
+    /// Parameter names to drop; a trailing `*` matches by prefix (`utm_*`).
+    pub drop: Vec<String>,
+}
+
+impl Default for QueryNormalizer {
+    fn default() -> Self {
+        return QueryNormalizer {
+            sort: true,
+            lowercase_keys: false,
+            decode_unreserved: true,
+            drop: vec!["utm_*".to_string(), "fbclid".to_string(), "gclid".to_string()],
+        };
+    }
+}
+
+impl QueryNormalizer {
+    /// Normalized (key, value) pairs, still percent-encoded where required.
+    pub fn params(&self, query: &str) -> Vec<(String, String)> {
+        let mut out: Vec<(String, String)> = Vec::new();
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            let mut k = self.canon(k);
+            if self.lowercase_keys {
+                k = k.to_ascii_lowercase();
+            }
+            if self.dropped(&k) {
+                continue;
+            }
+            out.push((k, self.canon(v)));
+        }
+        if self.sort {
+            // stable: values of a repeated parameter keep their order
+            out.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        return out;
+    }
+
+    pub fn normalize(&self, query: &str) -> String {
+        let parts: Vec<String> = self
+            .params(query)
+            .into_iter()
+            .map(|(k, v)| if v.is_empty() { k } else { format!("{}={}", k, v) })
+            .collect();
+        return parts.join("&");
+    }
+
+    /// Normalize the query part of a request target (`/path?query`).
+    pub fn normalize_target(&self, target: &str) -> String {
+        return match target.split_once('?') {
+            Some((path, q)) => {
+                let nq = self.normalize(q);
+                if nq.is_empty() { path.to_string() } else { format!("{}?{}", path, nq) }
+            }
+            None => target.to_string(),
+        };
+    }
+
+    fn dropped(&self, key: &str) -> bool {
+        return self.drop.iter().any(|d| match d.strip_suffix('*') {
+            Some(prefix) => key.starts_with(prefix),
+            None => key == d,
+        });
+    }
+
+    fn canon(&self, s: &str) -> String {
+        let b = s.as_bytes();
+        let mut out: Vec<u8> = Vec::with_capacity(b.len());
+        let mut i = 0;
+        while i < b.len() {
+            let escape = match (b[i], b.get(i + 1).and_then(|c| hex_val(*c)), b.get(i + 2).and_then(|c| hex_val(*c))) {
+                (b'%', Some(hi), Some(lo)) => Some(hi * 16 + lo),
+                _ => None,
+            };
+            match escape {
+                Some(c) if self.decode_unreserved && is_unreserved(c) => out.push(c),
+                Some(_) => {
+                    out.push(b'%');
+                    out.push(b[i + 1].to_ascii_uppercase());
+                    out.push(b[i + 2].to_ascii_uppercase());
+                }
+                None => {
+                    out.push(b[i]);
+                    i += 1;
+                    continue;
+                }
+            }
+            i += 3;
+        }
+        // only ASCII bytes were substituted, so valid UTF-8 input stays valid
+        return String::from_utf8_lossy(&out).into_owned();
+    }
+}
+
+/// How a varied header's value is reduced before it joins the key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VaryRule {
+    /// The value itself, with whitespace trimmed and collapsed.
+    Exact,
+    /// Accept-Encoding: the supported coding the client prefers (by q, then
+    /// list order), or "identity".
+    Encoding(Vec<String>),
+    /// Accept-Language: the supported language the client prefers, matched
+    /// by tag or prefix ("en" serves "en-US"), or "" for the default.
+    Language(Vec<String>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VaryPolicy {
+    /// Lowercase header names, in key order.
+    pub headers: Vec<(String, VaryRule)>,
+}
+
+impl Default for VaryPolicy {
+    /// Accept-Encoding only, between gzip (what `compression` produces) and identity.
+    fn default() -> Self {
+        return VaryPolicy { headers: vec![("accept-encoding".to_string(), VaryRule::Encoding(vec!["gzip".to_string()]))] };
+    }
+}
+
+impl VaryPolicy {
+    pub fn none() -> Self {
+        return VaryPolicy { headers: Vec::new() };
+    }
+
+    /// Policy from an origin's `Vary` response header: the named headers vary
+    /// on their exact value, plus Accept-Encoding with the default reduction
+    /// (listed or not, since bodies may be stored compressed). None for
+    /// `Vary: *` (every request is its own variant: do not cache).
+    pub fn from_vary_header(vary: &str) -> Option<Self> {
+        let mut policy = VaryPolicy::none();
+        for name in vary.split(',').map(|n| n.trim().to_ascii_lowercase()).filter(|n| !n.is_empty()) {
+            if name == "*" {
+                return None;
+            }
+            policy = policy.with_header(&name, VaryRule::Exact);
+        }
+        if let Some(rule) = VaryPolicy::default().rule("accept-encoding") {
+            policy = policy.with_header("accept-encoding", rule.clone());
+        }
+        return Some(policy);
+    }
+
+    /// Adds a header, or replaces its rule if already present.
+    pub fn with_header(mut self, name: &str, rule: VaryRule) -> Self {
+        let name = name.to_ascii_lowercase();
+        match self.headers.iter_mut().find(|(n, _)| *n == name) {
+            Some(h) => h.1 = rule,
+            None => self.headers.push((name, rule)),
+        }
+        return self;
+    }
+
+    fn rule(&self, name: &str) -> Option<&VaryRule> {
+        return self.headers.iter().find(|(n, _)| n == name).map(|(_, r)| r);
+    }
+}
+
+impl VaryRule {
+    fn reduce(&self, value: &str) -> String {
+        return match self {
+            VaryRule::Exact => value.split_whitespace().collect::<Vec<_>>().join(" "),
+            VaryRule::Encoding(supported) => {
+                let accepted = weighted(value);
+                let q = |coding: &str| {
+                    accepted.iter().find(|(t, _)| t == coding).or_else(|| accepted.iter().find(|(t, _)| t == "*")).map_or(0, |(_, q)| *q)
+                };
+                best(supported, q).unwrap_or_else(|| "identity".to_string())
+            }
+            VaryRule::Language(supported) => {
+                let accepted = weighted(value);
+                let q = |lang: &str| {
+                    accepted
+                        .iter()
+                        .filter(|(t, _)| t == lang || t.strip_prefix(lang).is_some_and(|rest| rest.starts_with('-')))
+                        .map(|(_, q)| *q)
+                        .max()
+                        .unwrap_or(0)
+                };
+                best(supported, q).unwrap_or_default()
+            }
+        };
+    }
+}
+
+// Lowercase tokens of a comma list with their q-values in thousandths.
+fn weighted(list: &str) -> Vec<(String, u16)> {
+    let mut out = Vec::new();
+    for item in list.split(',') {
+        let mut parts = item.split(';');
+        let token = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        if token.is_empty() {
+            continue;
+        }
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .next()
+            .map_or(Some(1000), |q| q.trim().parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q)).map(|q| (q * 1000.0).round() as u16));
+        if let Some(q) = q {
+            out.push((token, q));
+        }
+    }
+    return out;
+}
+
+// The supported option with the highest non-zero q; earlier options win ties.
+fn best(supported: &[String], q: impl Fn(&str) -> u16) -> Option<String> {
+    let mut pick: Option<(&String, u16)> = None;
+    for s in supported.iter() {
+        let w = q(&s.to_ascii_lowercase());
+        if w > 0 && pick.is_none_or(|(_, b)| w > b) {
+            pick = Some((s, w));
+        }
+    }
+    return pick.map(|(s, _)| s.to_ascii_lowercase());
+}
+
+/// Cache keys from a request target and its headers.
+#[derive(Clone, Debug, Default)]
+pub struct CacheKeyBuilder {
+    pub query: QueryNormalizer,
+    pub vary: VaryPolicy,
+}
+
+impl CacheKeyBuilder {
+    pub fn new(vary: VaryPolicy) -> Self {
+        return CacheKeyBuilder { query: QueryNormalizer::default(), vary };
+    }
+
+    /// `headers` as received (any name case); repeated headers are combined
+    /// as one comma list, and a missing header varies as an empty value.
+    pub fn build(&self, target: &str, headers: &[(&str, &str)]) -> Vec<u8> {
+        let mut key = self.query.normalize_target(target).into_bytes();
+        for (name, rule) in self.vary.headers.iter() {
+            let value: Vec<&str> = headers.iter().filter(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| *v).collect();
+            key.push(0);
+            key.extend_from_slice(name.as_bytes());
+            key.push(0);
+            key.extend_from_slice(rule.reduce(&value.join(",")).as_bytes());
+        }
+        return key;
+    }
+}
+
+fn hex_val(c: u8) -> Option<u8> {
+    return (c as char).to_digit(16).map(|d| d as u8);
+}
+
+fn is_unreserved(c: u8) -> bool {
+    return c.is_ascii_alphanumeric() || matches!(c, b'-' | b'.' | b'_' | b'~');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_query() {
+        let n = QueryNormalizer::default();
+        assert_eq!(n.normalize_target("/p?b=2&utm_source=x&a=%7euser&c=%2f&fbclid=1"), "/p?a=~user&b=2&c=%2F");
+        assert_eq!(n.normalize_target("/p?utm_campaign=x"), "/p");
+        let lower = QueryNormalizer { lowercase_keys: true, ..QueryNormalizer::default() };
+        assert_eq!(lower.normalize("B=1&a"), "a&b=1");
+        assert_eq!(n.normalize("q=café&x=%"), "q=café&x=%");
+        assert_eq!(n.normalize("id=2&b=x&id=1"), "b=x&id=2&id=1");
+        assert_ne!(n.normalize("id=2&id=1"), n.normalize("id=1&id=2"));
+    }
+
+    #[test]
+    fn vary_keys_separate_variants() {
+        let b = CacheKeyBuilder::default();
+        let gz = b.build("/a?utm_source=x", &[("Accept-Encoding", "br, gzip;q=0.8")]);
+        assert_eq!(gz, b"/a\0accept-encoding\0gzip".to_vec());
+        assert_eq!(b.build("/a", &[("accept-encoding", "deflate, *;q=0.5")]), gz);
+        let plain = b.build("/a", &[("Accept-Encoding", "gzip;q=0")]);
+        assert_eq!(plain, b.build("/a", &[]));
+        assert_ne!(plain, gz);
+
+        let policy = VaryPolicy::default().with_header("Accept-Language", VaryRule::Language(vec!["de".into(), "en".into()]));
+        let b = CacheKeyBuilder::new(policy);
+        let lang = |v: &str| b.build("/a", &[("Accept-Language", v)]);
+        assert_eq!(lang("en-US, de;q=0.9"), lang("en"));
+        assert_ne!(lang("de-AT"), lang("en"));
+        assert_eq!(lang("fr"), lang(""));
+
+        let origin = VaryPolicy::from_vary_header("Accept-Encoding, X-Theme").unwrap();
+        assert_eq!(origin.headers.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>(), ["accept-encoding", "x-theme"]);
+        assert_eq!(origin.rule("accept-encoding"), VaryPolicy::default().rule("accept-encoding"));
+        let b = CacheKeyBuilder::new(origin);
+        assert_eq!(b.build("/", &[("X-Theme", " dark  mode ")]), b.build("/", &[("x-theme", "dark mode")]));
+        assert!(VaryPolicy::from_vary_header("*").is_none());
+    }
+}
@@ -1,64 +1,265 @@
-use crate::{Cache, CacheError, Entry};
-use std::collections::VecDeque;
-use std::collections::HashMap;
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/l1.rs
+// Role: Final L1 cache (LRU bounded by entry count and bytes)
+// ----------------------------------------------------------------------------
+
+use crate::clock::{self, Clock};
+use crate::stats::{CacheStats, StatsCell};
+use crate::sweep::Sweep;
+use crate::{footprint, Cache, CacheError, Entry, TierUsage};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
 use std::sync::{Arc, Mutex};
 
-const MAX_ENTRIES: usize = 1024; // frozen cap
+const MAX_ENTRIES: usize = 1024; // entry cap (default)
+const DEFAULT_MAX_BYTES: usize = 64 * 1024 * 1024; // footprint budget (default)
+const MAX_ENTRY_SHARE: usize = 8; // one entry may use at most 1/8 of the byte budget
 
 #[derive(Clone)]
 pub struct L1 {
     inner: Arc<Mutex<State>>,
+    clock: Arc<dyn Clock>,
+    stats: Arc<StatsCell>,
+}
+
+struct Slot {
+    entry: Entry,
+    tick: u64,
+    bytes: usize, // footprint: key + value + ENTRY_OVERHEAD
 }
 
 struct State {
-    map: HashMap<Vec<u8>, Entry>,
-    order: VecDeque<Vec<u8>>, // simple FIFO eviction
+    map: HashMap<Vec<u8>, Slot>,
+    order: BTreeMap<u64, Vec<u8>>, // recency: lowest tick is least recently used
+    tick: u64,
+    bytes: usize,
+    max_entries: usize,
+    max_bytes: usize,
+    sweep_at: u64, // tick of the last entry examined by `sweep`
 }
 
 impl L1 {
     pub fn new() -> Self {
-        let st = State { map: HashMap::new(), order: VecDeque::new() };
-        return L1 { inner: Arc::new(Mutex::new(st)) };
+        return Self::build(MAX_ENTRIES, DEFAULT_MAX_BYTES);
+    }
+
+    /// LRU holding at most `max_entries` (default byte budget; see `with_max_bytes`).
+    pub fn with_capacity(max_entries: usize) -> Self {
+        return Self::build(max_entries, DEFAULT_MAX_BYTES);
+    }
+
+    /// Byte-bounded L1 without an entry cap: eviction keeps the total
+    /// footprint under `budget_bytes`, and a single entry larger than 1/8 of
+    /// the budget is rejected so it cannot flush the whole tier.
+    pub fn weighted(budget_bytes: usize) -> Self {
+        return Self::build(usize::MAX, budget_bytes);
+    }
+
+    fn build(max_entries: usize, max_bytes: usize) -> Self {
+        let st = State {
+            map: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+            max_entries,
+            max_bytes,
+            sweep_at: 0,
+        };
+        return L1 { inner: Arc::new(Mutex::new(st)), clock: clock::system(), stats: Arc::default() };
+    }
+
+    /// Replace the time source used for expiry checks.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        return self;
+    }
+
+    /// Bound the total footprint (keys, values and per-entry overhead).
+    pub fn with_max_bytes(self, max_bytes: usize) -> Self {
+        self.inner.lock().unwrap().max_bytes = max_bytes;
+        return self;
+    }
+
+    pub fn stats(&self) -> TierUsage {
+        let st = self.inner.lock().unwrap();
+        return TierUsage { items: st.map.len(), bytes: st.bytes, max_items: st.max_entries, max_bytes: st.max_bytes };
+    }
+
+    /// Hit/miss/eviction counters and current usage; lock-free.
+    pub fn cache_stats(&self) -> CacheStats {
+        return self.stats.snapshot();
+    }
+}
+
+impl Default for L1 {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl State {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        return self.tick;
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<Slot> {
+        let slot = self.map.remove(key)?;
+        self.order.remove(&slot.tick);
+        self.bytes -= slot.bytes;
+        return Some(slot);
     }
 }
 
 impl Cache for L1 {
     fn lookup(&self, key: &[u8]) -> Result<Entry, CacheError> {
+        let now = self.clock.now();
         let mut st = self.inner.lock().unwrap();
-        if let Some(e) = st.map.get(key) {
-            if e.is_expired() {
-                st.map.remove(key);
+        let tick = st.next_tick();
+        let st = &mut *st;
+        if let Some(slot) = st.map.get_mut(key) {
+            if slot.entry.is_dead_at(now) {
+                st.remove(key);
+                self.stats.expired();
+                self.stats.usage(st.map.len(), st.bytes);
                 return Err(CacheError::Expired);
             }
-            return Ok(e.clone());
+            // LRU touch
+            let k = st.order.remove(&slot.tick).unwrap_or_else(|| key.to_vec());
+            slot.tick = tick;
+            st.order.insert(tick, k);
+            self.stats.hit();
+            return Ok(slot.entry.clone());
         }
+        self.stats.miss();
         return Err(CacheError::NotFound);
     }
 
     fn insert(&self, key: &[u8], entry: Entry) -> Result<(), CacheError> {
         let mut st = self.inner.lock().unwrap();
-        let k = key.to_vec();
-        if !st.map.contains_key(&k) {
-            st.order.push_back(k.clone());
+        let bytes = footprint(key, &entry);
+        // an overwrite replaces the old value even when the new one is refused
+        st.remove(key);
+        if bytes > st.max_bytes / MAX_ENTRY_SHARE {
+            self.stats.usage(st.map.len(), st.bytes);
+            return Err(CacheError::TooLarge);
         }
-        st.map.insert(k.clone(), entry);
-        // eviction if over cap
-        while st.order.len() > MAX_ENTRIES {
-            if let Some(old) = st.order.pop_front() {
-                st.map.remove(&old);
+        let tick = st.next_tick();
+        let k = key.to_vec();
+        st.order.insert(tick, k.clone());
+        st.map.insert(k, Slot { entry, tick, bytes });
+        st.bytes += bytes;
+        // evict least recently used until within both limits
+        while st.map.len() > st.max_entries || st.bytes > st.max_bytes {
+            let Some((_, old)) = st.order.pop_first() else { break };
+            if let Some(slot) = st.map.remove(&old) {
+                st.bytes -= slot.bytes;
+                self.stats.evicted(1);
             }
         }
+        self.stats.usage(st.map.len(), st.bytes);
         return Ok(());
     }
 
     fn invalidate(&self, key: &[u8]) -> Result<(), CacheError> {
         let mut st = self.inner.lock().unwrap();
-        let k = key.to_vec();
-        if st.map.remove(&k).is_some() {
-            // remove from order (linear scan, bounded by cap)
-            st.order = st.order.iter().filter(|x| **x != k).cloned().collect();
+        if st.remove(key).is_some() {
+            self.stats.usage(st.map.len(), st.bytes);
             return Ok(());
         }
         return Err(CacheError::NotFound);
     }
-}
\ No newline at end of file
+}
+
+// Walks entries in recency order from the sweep cursor; touched entries move
+// to the end and are met again later in the cycle.
+impl Sweep for L1 {
+    fn sweep(&self, budget: usize) -> usize {
+        let now = self.clock.now();
+        let mut st = self.inner.lock().unwrap();
+        let from = st.sweep_at;
+        let mut last = from;
+        let mut dead = Vec::new();
+        let ring = st.order.range((Bound::Excluded(from), Bound::Unbounded)).chain(st.order.range(..=from));
+        for (&tick, key) in ring.take(budget) {
+            last = tick;
+            if st.map.get(key).is_some_and(|s| s.entry.is_dead_at(now)) {
+                dead.push(key.clone());
+            }
+        }
+        st.sweep_at = last;
+        for k in dead.iter() {
+            st.remove(k);
+        }
+        self.stats.usage(st.map.len(), st.bytes);
+        return dead.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn entry(len: usize) -> Entry {
+        return Entry::new(vec![0u8; len], 0, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn lru_keeps_recently_used() {
+        let l1 = L1::new();
+        for i in 0..MAX_ENTRIES {
+            l1.insert(&i.to_be_bytes(), entry(1)).unwrap();
+        }
+        // touch the oldest key so it becomes most recent
+        l1.lookup(&0usize.to_be_bytes()).unwrap();
+        l1.insert(b"new", entry(1)).unwrap();
+        assert!(l1.lookup(&0usize.to_be_bytes()).is_ok());
+        assert!(matches!(l1.lookup(&1usize.to_be_bytes()), Err(CacheError::NotFound)));
+    }
+
+    #[test]
+    fn weighted_rejects_oversize_and_evicts_by_bytes() {
+        let per = footprint(&[0u8], &entry(100));
+        let l1 = L1::weighted(8 * per);
+        assert!(matches!(l1.insert(&[9u8], entry(101)), Err(CacheError::TooLarge)));
+        l1.insert(b"k", entry(3)).unwrap();
+        assert!(matches!(l1.insert(b"k", entry(8 * per)), Err(CacheError::TooLarge)));
+        assert!(matches!(l1.lookup(b"k"), Err(CacheError::NotFound)), "refused overwrite drops the old value");
+        for i in 0..8u8 {
+            l1.insert(&[i], entry(100)).unwrap();
+        }
+        assert_eq!(l1.stats(), TierUsage { items: 8, bytes: 8 * per, max_items: usize::MAX, max_bytes: 8 * per });
+        l1.insert(b"hot", entry(10)).unwrap();
+        assert!(matches!(l1.lookup(&[0u8]), Err(CacheError::NotFound)));
+        assert!(l1.lookup(&[1u8]).is_ok());
+        assert!(l1.lookup(b"hot").is_ok());
+        assert!(l1.stats().bytes <= 8 * per);
+    }
+
+    #[test]
+    fn default_tier_is_bounded_by_bytes_too() {
+        // 1024 entries of 1MB would be 1GB; the 64MB budget evicts first
+        let l1 = L1::new();
+        assert!(matches!(l1.insert(b"big", entry(DEFAULT_MAX_BYTES / 4)), Err(CacheError::TooLarge)));
+        for i in 0..128usize {
+            l1.insert(&i.to_be_bytes(), entry(1024 * 1024)).unwrap();
+        }
+        let usage = l1.stats();
+        assert!(usage.bytes <= DEFAULT_MAX_BYTES);
+        assert!(usage.items < 64);
+        assert!(l1.lookup(&127usize.to_be_bytes()).is_ok());
+        assert!(matches!(l1.lookup(&0usize.to_be_bytes()), Err(CacheError::NotFound)));
+    }
+
+    #[test]
+    fn with_capacity_sets_the_entry_cap() {
+        let l1 = L1::with_capacity(2).with_max_bytes(1 << 20);
+        for k in [b"a", b"b", b"c"] {
+            l1.insert(k, entry(1)).unwrap();
+        }
+        assert_eq!((l1.stats().items, l1.stats().max_items, l1.stats().max_bytes), (2, 2, 1 << 20));
+        assert!(matches!(l1.lookup(b"a"), Err(CacheError::NotFound)));
+    }
+}
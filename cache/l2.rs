@@ -4,125 +4,497 @@
 // Role: Final L2 cache (ARC-like with bounded memory, concurrent R/W)
 // ----------------------------------------------------------------------------
 
-use crate::{Cache, CacheError, Entry};
-use std::collections::{HashMap, VecDeque};
-use std::sync::{Arc, RwLock};
+use crate::clock::{self, Clock};
+use crate::stats::{CacheStats, StatsCell};
+use crate::sweep::Sweep;
+use crate::{footprint, Cache, CacheError, Entry, TierUsage};
+use std::collections::HashMap;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
-// Frozen limits
+// Defaults (see L2Config)
 const MAX_ITEMS: usize = 65_536;
 const MAX_VALUE_BYTES: usize = 64 * 1024 * 1024; // 64MB
 const DEFAULT_TTL: Duration = Duration::from_secs(300);
+const DEFAULT_MAX_BYTES: usize = 1024 * 1024 * 1024; // footprint budget
+
+// Hit-path touch buffering: hits are recorded into per-thread shards and
+// applied to the recency lists under the write lock in batches.
+const TOUCH_SHARDS: usize = 16;
+const TOUCH_BATCH: usize = 64; // try to apply once a shard holds this many
+const TOUCH_MAX: usize = 4096; // beyond this, touches are dropped (recency is approximate)
+
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD: Cell<usize> = Cell::new(NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % TOUCH_SHARDS);
+}
+
+/// Sizing of an L2 tier; `Default` is the historical fixed sizing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct L2Config {
+    pub max_items: usize,       // resident entries; each ghost list keeps as many keys
+    pub max_bytes: usize,       // footprint of resident entries
+    pub max_value_bytes: usize, // larger values are rejected
+    pub default_ttl: Duration,  // applied to entries inserted with a zero TTL
+}
+
+impl Default for L2Config {
+    fn default() -> Self {
+        return L2Config {
+            max_items: MAX_ITEMS,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_value_bytes: MAX_VALUE_BYTES,
+            default_ttl: DEFAULT_TTL,
+        };
+    }
+}
 
 #[derive(Clone)]
 pub struct L2 {
     inner: Arc<RwLock<State>>,
+    touches: Arc<Vec<Mutex<Vec<Vec<u8>>>>>,
+    clock: Arc<dyn Clock>,
+    stats: Arc<StatsCell>,
+}
+
+// Simplified ARC partitions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum List {
+    T1 = 0, // recent
+    T2 = 1, // frequent
+    B1 = 2, // ghost recent
+    B2 = 3, // ghost frequent
+}
+
+const NIL: usize = usize::MAX;
+
+struct Node {
+    key: Vec<u8>,
+    entry: Option<Entry>, // Some only while on T1/T2
+    list: List,
+    prev: usize,
+    next: usize,
 }
 
+#[derive(Clone, Copy)]
+struct Ring {
+    head: usize, // least recent
+    tail: usize, // most recent
+    len: usize,
+}
+
+// Every tracked key sits on exactly one of the four lists. Nodes live in a
+// slab and are linked by index; `index` points a key at its node, so lookup,
+// move-to-back and pop-front are all O(1).
 struct State {
-    // Simplified ARC partitions
-    t1: VecDeque<Vec<u8>>, // recent
-    t2: VecDeque<Vec<u8>>, // frequent
-    b1: VecDeque<Vec<u8>>, // ghost recent
-    b2: VecDeque<Vec<u8>>, // ghost frequent
-    map: HashMap<Vec<u8>, Entry>,
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    index: HashMap<Vec<u8>, usize>,
+    lists: [Ring; 4],
     p_target: usize, // balancing target
+    bytes: usize,    // footprint of resident entries
+    cfg: L2Config,
+    sweep_at: usize, // next slab slot `sweep` examines
+}
+
+impl State {
+    fn new(cfg: L2Config) -> Self {
+        let empty = Ring { head: NIL, tail: NIL, len: 0 };
+        return State {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            index: HashMap::new(),
+            lists: [empty; 4],
+            p_target: cfg.max_items / 2,
+            bytes: 0,
+            cfg,
+            sweep_at: 0,
+        };
+    }
+
+    fn len(&self, l: List) -> usize {
+        return self.lists[l as usize].len;
+    }
+
+    fn resident(&self) -> usize {
+        return self.len(List::T1) + self.len(List::T2);
+    }
+
+    fn list_of(&self, key: &[u8]) -> Option<List> {
+        return self.index.get(key).map(|&i| self.nodes[i].list);
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&Entry> {
+        return self.index.get(key).and_then(|&i| self.nodes[i].entry.as_ref());
+    }
+
+    fn over_budget(&self) -> bool {
+        return self.resident() > self.cfg.max_items || self.bytes > self.cfg.max_bytes;
+    }
+
+    // Swap the entry held by a node, keeping the byte count in step.
+    fn set_entry(&mut self, i: usize, entry: Option<Entry>) {
+        let node = &mut self.nodes[i];
+        if let Some(old) = node.entry.take() {
+            self.bytes -= footprint(&node.key, &old);
+        }
+        if let Some(e) = entry {
+            self.bytes += footprint(&node.key, &e);
+            node.entry = Some(e);
+        }
+    }
+
+    fn unlink(&mut self, i: usize) {
+        let (l, prev, next) = (self.nodes[i].list as usize, self.nodes[i].prev, self.nodes[i].next);
+        if prev == NIL { self.lists[l].head = next; } else { self.nodes[prev].next = next; }
+        if next == NIL { self.lists[l].tail = prev; } else { self.nodes[next].prev = prev; }
+        self.lists[l].len -= 1;
+    }
+
+    fn push_back(&mut self, i: usize, l: List) {
+        let tail = self.lists[l as usize].tail;
+        let node = &mut self.nodes[i];
+        node.list = l;
+        node.prev = tail;
+        node.next = NIL;
+        if tail == NIL { self.lists[l as usize].head = i; } else { self.nodes[tail].next = i; }
+        self.lists[l as usize].tail = i;
+        self.lists[l as usize].len += 1;
+    }
+
+    fn move_to_back(&mut self, i: usize, l: List) {
+        self.unlink(i);
+        self.push_back(i, l);
+    }
+
+    fn add(&mut self, key: &[u8], entry: Entry, l: List) {
+        let node = Node { key: key.to_vec(), entry: None, list: l, prev: NIL, next: NIL };
+        let i = match self.free.pop() {
+            Some(i) => {
+                self.nodes[i] = node;
+                i
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        self.index.insert(key.to_vec(), i);
+        self.push_back(i, l);
+        self.set_entry(i, Some(entry));
+    }
+
+    // Forget a key entirely (resident or ghost).
+    fn drop_node(&mut self, i: usize) {
+        self.unlink(i);
+        self.set_entry(i, None);
+        let key = std::mem::take(&mut self.nodes[i].key);
+        self.index.remove(&key);
+        self.free.push(i);
+    }
+
+    fn remove(&mut self, key: &[u8]) -> bool {
+        let Some(&i) = self.index.get(key) else { return false };
+        self.drop_node(i);
+        return true;
+    }
 }
 
 impl L2 {
     pub fn new() -> Self {
-        let st = State {
-            t1: VecDeque::new(),
-            t2: VecDeque::new(),
-            b1: VecDeque::new(),
-            b2: VecDeque::new(),
-            map: HashMap::new(),
-            p_target: MAX_ITEMS / 2,
-        };
-        return L2 { inner: Arc::new(RwLock::new(st)) };
+        return Self::with_config(L2Config::default());
+    }
+
+    pub fn with_config(cfg: L2Config) -> Self {
+        let touches = (0..TOUCH_SHARDS).map(|_| Mutex::new(Vec::new())).collect();
+        return L2 { inner: Arc::new(RwLock::new(State::new(cfg))), touches: Arc::new(touches), clock: clock::system(), stats: Arc::default() };
+    }
+
+    /// Replace the time source used for expiry checks.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        return self;
+    }
+
+    /// Bound the total footprint (keys, values and per-entry overhead) of
+    /// resident entries; ghost keys are not counted.
+    pub fn with_max_bytes(self, max_bytes: usize) -> Self {
+        self.inner.write().unwrap().cfg.max_bytes = max_bytes;
+        return self;
     }
 
-    fn replace(st: &mut State, miss_key: &[u8]) {
+    pub fn stats(&self) -> TierUsage {
+        let st = self.inner.read().unwrap();
+        return TierUsage { items: st.resident(), bytes: st.bytes, max_items: st.cfg.max_items, max_bytes: st.cfg.max_bytes };
+    }
+
+    /// Hit/miss/eviction counters and current usage; lock-free. Evictions
+    /// count entries demoted to a ghost list.
+    pub fn cache_stats(&self) -> CacheStats {
+        return self.stats.snapshot();
+    }
+
+    // Evicts one resident entry to its ghost list; false when none is left.
+    fn replace(st: &mut State, miss_in_b2: bool) -> bool {
         // Balance between t1 and t2 by p_target using ghost hits in b1/b2
-        if st.t1.len() > 0 && (st.t1.len() > st.p_target || (st.b2.contains(&miss_key.to_vec()) && st.t1.len() == st.p_target)) {
-            if let Some(k) = st.t1.pop_front() {
-                st.map.remove(&k);
-                st.b1.push_back(k);
-                if st.b1.len() > MAX_ITEMS { st.b1.pop_front(); }
-            }
+        let t1 = st.len(List::T1);
+        let (from, ghost) = if t1 > 0 && (t1 > st.p_target || (miss_in_b2 && t1 == st.p_target)) {
+            (List::T1, List::B1)
+        } else {
+            (List::T2, List::B2)
+        };
+        let (from, ghost) = if st.len(from) > 0 {
+            (from, ghost)
+        } else if from == List::T1 {
+            (List::T2, List::B2)
         } else {
-            if let Some(k) = st.t2.pop_front() {
-                st.map.remove(&k);
-                st.b2.push_back(k);
-                if st.b2.len() > MAX_ITEMS { st.b2.pop_front(); }
+            (List::T1, List::B1)
+        };
+        let i = st.lists[from as usize].head;
+        if i == NIL {
+            return false;
+        }
+        st.set_entry(i, None);
+        st.move_to_back(i, ghost);
+        if st.len(ghost) > st.cfg.max_items {
+            let oldest = st.lists[ghost as usize].head;
+            st.drop_node(oldest);
+        }
+        return true;
+    }
+
+    // Record a hit without taking the write lock; applies pending touches
+    // opportunistically when the shard is full and the lock is free.
+    fn record_touch(&self, key: &[u8]) {
+        let shard = SHARD.with(|s| s.get());
+        let pending = {
+            let mut buf = self.touches[shard].lock().unwrap();
+            if buf.len() < TOUCH_MAX {
+                buf.push(key.to_vec());
             }
+            buf.len()
+        };
+        if pending >= TOUCH_BATCH
+            && let Ok(mut st) = self.inner.try_write()
+        {
+            self.apply_touches(&mut st);
         }
     }
 
-    fn touch(st: &mut State, key: &[u8]) {
-        let k = key.to_vec();
-        // Promote to t2 if present in t1
-        if let Some(pos) = st.t1.iter().position(|x| *x == k) {
-            st.t1.remove(pos);
-            st.t2.push_back(k);
-        } else {
-            // If in t2, move to back
-            if let Some(pos) = st.t2.iter().position(|x| *x == k) {
-                st.t2.remove(pos);
-                st.t2.push_back(k);
-            } else {
-                // New item goes to t1
-                st.t1.push_back(k);
-                while st.t1.len() + st.t2.len() > MAX_ITEMS {
-                    Self::replace(st, key);
-                }
+    // Must be called with the write lock held, before any recency decision.
+    fn apply_touches(&self, st: &mut State) {
+        for shard in self.touches.iter() {
+            let keys = std::mem::take(&mut *shard.lock().unwrap());
+            for k in keys.iter() {
+                Self::touch(st, k);
             }
         }
     }
+
+    // Hit on a resident key: promote t1 -> t2, or refresh its place in t2.
+    // Keys evicted or invalidated since the hit are skipped.
+    fn touch(st: &mut State, key: &[u8]) {
+        let Some(&i) = st.index.get(key) else { return };
+        if st.nodes[i].entry.is_some() {
+            st.move_to_back(i, List::T2);
+        }
+    }
+}
+
+impl Default for L2 {
+    fn default() -> Self {
+        return Self::new();
+    }
 }
 
 impl Cache for L2 {
     fn lookup(&self, key: &[u8]) -> Result<Entry, CacheError> {
-        let mut st = self.inner.write().unwrap();
-        if let Some(e) = st.map.get(key) {
-            if e.is_expired() {
-                st.map.remove(key);
+        let now = self.clock.now();
+        let (hit, ghost) = {
+            let st = self.inner.read().unwrap();
+            match st.get(key) {
+                Some(e) => (Some(e.clone()), None),
+                None => (None, st.list_of(key)),
+            }
+        };
+        if let Some(e) = hit {
+            if e.is_dead_at(now) {
+                let mut st = self.inner.write().unwrap();
+                if st.get(key).is_some_and(|cur| cur.is_dead_at(now)) {
+                    st.remove(key);
+                    self.stats.usage(st.resident(), st.bytes);
+                }
+                self.stats.expired();
                 return Err(CacheError::Expired);
             }
-            Self::touch(&mut st, key);
-            return Ok(e.clone());
+            self.record_touch(key);
+            self.stats.hit();
+            return Ok(e);
         }
-        // ghost hit tuning
-        let k = key.to_vec();
-        if st.b1.contains(&k) {
-            st.p_target = std::cmp::min(MAX_ITEMS, st.p_target + 1);
-        } else if st.b2.contains(&k) {
-            st.p_target = st.p_target.saturating_sub(1);
+        // ghost hit tuning (miss path only, so the write lock stays off hits)
+        if ghost.is_some() {
+            let mut st = self.inner.write().unwrap();
+            match st.list_of(key) {
+                Some(List::B1) => st.p_target = std::cmp::min(st.cfg.max_items, st.p_target + 1),
+                Some(List::B2) => st.p_target = st.p_target.saturating_sub(1),
+                _ => {}
+            }
         }
+        self.stats.miss();
         return Err(CacheError::NotFound);
     }
 
     fn insert(&self, key: &[u8], entry: Entry) -> Result<(), CacheError> {
-        if entry.value.len() > MAX_VALUE_BYTES {
+        let mut st = self.inner.write().unwrap();
+        self.apply_touches(&mut st);
+        if entry.value.len() > st.cfg.max_value_bytes || footprint(key, &entry) > st.cfg.max_bytes {
+            // an overwrite replaces the old value even when the new one is refused
+            if st.get(key).is_some() {
+                st.remove(key);
+                self.stats.usage(st.resident(), st.bytes);
+            }
             return Err(CacheError::TooLarge);
         }
-        let mut st = self.inner.write().unwrap();
-        let k = key.to_vec();
-        st.map.insert(k.clone(), Entry { ttl: if entry.ttl == Duration::ZERO { DEFAULT_TTL } else { entry.ttl }, ..entry });
-        Self::touch(&mut st, &k);
-        while st.t1.len() + st.t2.len() > MAX_ITEMS {
-            Self::replace(&mut st, &k);
+        let entry = Entry { ttl: if entry.ttl == Duration::ZERO { st.cfg.default_ttl } else { entry.ttl }, ..entry };
+        let miss_in_b2 = st.list_of(key) == Some(List::B2);
+        match st.index.get(key).copied() {
+            // resident or remembered by a ghost list: (re)admit as frequent
+            Some(i) => {
+                st.set_entry(i, Some(entry));
+                st.move_to_back(i, List::T2);
+            }
+            // new item goes to t1
+            None => st.add(key, entry, List::T1),
         }
+        while st.over_budget() && Self::replace(&mut st, miss_in_b2) {
+            self.stats.evicted(1);
+        }
+        self.stats.usage(st.resident(), st.bytes);
         return Ok(());
     }
 
     fn invalidate(&self, key: &[u8]) -> Result<(), CacheError> {
         let mut st = self.inner.write().unwrap();
-        let k = key.to_vec();
-        let existed = st.map.remove(&k).is_some();
-        st.t1 = st.t1.iter().filter(|x| **x != k).cloned().collect();
-        st.t2 = st.t2.iter().filter(|x| **x != k).cloned().collect();
-        if existed { return Ok(()); }
+        self.apply_touches(&mut st);
+        if st.get(key).is_some() {
+            st.remove(key);
+            self.stats.usage(st.resident(), st.bytes);
+            return Ok(());
+        }
         return Err(CacheError::NotFound);
     }
-}
\ No newline at end of file
+}
+// Walks the node slab from the sweep cursor; free slots and ghosts count
+// against the budget but hold nothing to drop.
+impl Sweep for L2 {
+    fn sweep(&self, budget: usize) -> usize {
+        let now = self.clock.now();
+        let mut st = self.inner.write().unwrap();
+        let slots = st.nodes.len();
+        if slots == 0 {
+            return 0;
+        }
+        let from = st.sweep_at % slots;
+        let n = budget.min(slots);
+        let mut dropped = 0;
+        for i in (from..slots).chain(0..from).take(n) {
+            if st.nodes[i].entry.as_ref().is_some_and(|e| e.is_dead_at(now)) {
+                st.drop_node(i);
+                dropped += 1;
+            }
+        }
+        st.sweep_at = (from + n) % slots;
+        self.stats.usage(st.resident(), st.bytes);
+        return dropped;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_from_many_threads_are_applied_lazily() {
+        let l2 = L2::new();
+        l2.insert(b"hot", Entry::new(b"v".to_vec(), 0, Duration::from_secs(60))).unwrap();
+        l2.insert(b"cold", Entry::new(b"v".to_vec(), 0, Duration::from_secs(60))).unwrap();
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let l2 = l2.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        assert!(l2.lookup(b"hot").is_ok());
+                    }
+                })
+            })
+            .collect();
+        for w in workers {
+            w.join().unwrap();
+        }
+        // the next write applies whatever is still buffered
+        l2.insert(b"other", Entry::new(b"v".to_vec(), 0, Duration::from_secs(60))).unwrap();
+        let st = l2.inner.read().unwrap();
+        assert_eq!(st.list_of(b"hot"), Some(List::T2), "hot key promoted to frequent list");
+        assert_eq!(st.list_of(b"cold"), Some(List::T1));
+        assert!(l2.touches.iter().all(|s| s.lock().unwrap().is_empty()));
+    }
+
+    #[test]
+    fn evicted_keys_become_ghosts_and_return_as_frequent() {
+        let l2 = L2::new();
+        for i in 0..=MAX_ITEMS {
+            l2.insert(format!("k{}", i).as_bytes(), Entry::new(b"v".to_vec(), 0, Duration::from_secs(60))).unwrap();
+        }
+        let p_before = l2.inner.read().unwrap().p_target;
+        assert!(matches!(l2.lookup(b"k0"), Err(CacheError::NotFound)));
+        {
+            let st = l2.inner.read().unwrap();
+            assert_eq!(st.list_of(b"k0"), Some(List::B1));
+            assert_eq!(st.p_target, p_before + 1, "ghost hit in b1 grows the recent target");
+            assert_eq!(st.resident(), MAX_ITEMS);
+        }
+        l2.insert(b"k0", Entry::new(b"back".to_vec(), 0, Duration::from_secs(60))).unwrap();
+        assert_eq!(l2.lookup(b"k0").unwrap().value, b"back");
+        assert_eq!(l2.inner.read().unwrap().list_of(b"k0"), Some(List::T2));
+        l2.invalidate(b"k0").unwrap();
+        assert!(l2.inner.read().unwrap().list_of(b"k0").is_none());
+        assert!(matches!(l2.invalidate(b"k0"), Err(CacheError::NotFound)));
+    }
+
+    #[test]
+    fn byte_budget_evicts_under_memory_pressure() {
+        let per = footprint(b"k0", &Entry::new(vec![0u8; 1000], 0, Duration::from_secs(60)));
+        let l2 = L2::new().with_max_bytes(4 * per);
+        assert!(matches!(l2.insert(b"big", Entry::new(vec![0u8; 5 * per], 0, Duration::from_secs(60))), Err(CacheError::TooLarge)));
+        for i in 0..6 {
+            l2.insert(format!("k{}", i).as_bytes(), Entry::new(vec![0u8; 1000], 0, Duration::from_secs(60))).unwrap();
+        }
+        assert_eq!(l2.stats(), TierUsage { items: 4, bytes: 4 * per, max_items: MAX_ITEMS, max_bytes: 4 * per });
+        assert!(matches!(l2.lookup(b"k0"), Err(CacheError::NotFound)));
+        assert!(l2.lookup(b"k5").is_ok());
+        // replacing a value re-accounts it rather than adding to it
+        l2.insert(b"k5", Entry::new(vec![0u8; 10], 0, Duration::from_secs(60))).unwrap();
+        assert_eq!(l2.stats().bytes, 3 * per + per - 990);
+        l2.invalidate(b"k5").unwrap();
+        assert_eq!(l2.stats().bytes, 3 * per);
+    }
+
+    #[test]
+    fn sized_by_config() {
+        let cfg = L2Config { max_items: 2, max_value_bytes: 8, default_ttl: Duration::from_secs(5), ..L2Config::default() };
+        let l2 = L2::with_config(cfg);
+        assert!(matches!(l2.insert(b"big", Entry::new(vec![0u8; 9], 0, Duration::from_secs(60))), Err(CacheError::TooLarge)));
+        l2.insert(b"big", Entry::new(vec![0u8; 8], 0, Duration::from_secs(60))).unwrap();
+        assert!(l2.insert(b"big", Entry::new(vec![0u8; 9], 0, Duration::from_secs(60))).is_err());
+        assert!(matches!(l2.lookup(b"big"), Err(CacheError::NotFound)), "refused overwrite drops the old value");
+        for k in [b"a", b"b", b"c"] {
+            l2.insert(k, Entry::new(b"v".to_vec(), 0, Duration::ZERO)).unwrap();
+        }
+        assert_eq!(l2.stats().items, 2);
+        assert!(matches!(l2.lookup(b"a"), Err(CacheError::NotFound)));
+        assert_eq!(l2.lookup(b"c").unwrap().ttl, Duration::from_secs(5));
+    }
+}
@@ -3,48 +3,559 @@
 // File: cache/l3.rs
 // Role: Final L3 cache (distributed-ready facade with local store)
 // ----------------------------------------------------------------------------
+// Entries live in an `L3Backend` when one is configured: a shared remote
+// tier (`resp::RespBackend`) or, with the `disk` feature, a persistent
+// `disk::DiskStore` (see `L3::on_disk`). Without one they stay in a map in RAM.
+//
+// A backend that fails with an I/O error puts L3 in local mode: the RAM map
+// serves for `L3Config::backend_retry`, then the backend is tried again.
+// Keys invalidated meanwhile are replayed against it on recovery, and the
+// map is dropped so nothing written during the outage outlives it. Past
+// `L3Config::max_missed` keys the list is dropped and the backend is
+// flushed on recovery instead.
 
-use crate::{Cache, CacheError, Entry};
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use crate::clock::{self, Clock};
+use crate::stats::{CacheStats, StatsCell};
+use crate::sweep::Sweep;
+use crate::{footprint, Cache, CacheError, Entry};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::ops::Bound;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+#[cfg(feature = "disk")]
+use crate::disk::{Compactor, DiskConfig, DiskStore};
+#[cfg(feature = "disk")]
+use std::{io, path::Path};
 
-/// L3 is designed as a facade: for now a local concurrent map,
-/// but keeping the interface future-proof for sharding/clustered backends.
+/// Where L3 keeps its entries when not in RAM. Implementations handle their
+/// own expiry bookkeeping; L3 still checks `Entry::is_dead_at` on reads.
+pub trait L3Backend: Send + Sync {
+    /// Ok(None) when absent.
+    fn get(&self, key: &[u8]) -> Result<Option<Entry>, CacheError>;
+    fn put(&self, key: &[u8], entry: &Entry) -> Result<(), CacheError>;
+    /// Ok(false) when the key was not stored.
+    fn remove(&self, key: &[u8]) -> Result<bool, CacheError>;
+    /// Drops every entry; used when too many invalidations were missed to
+    /// replay them one by one.
+    fn clear(&self) -> Result<(), CacheError>;
+    /// Called before storing `key` with the configured entry cap; backends
+    /// that size themselves (e.g. Redis maxmemory) keep the default no-op.
+    fn make_room(&self, _key: &[u8], _max_entries: usize, _now: Instant) {}
+}
+
+/// Limits of the local store; the default is unbounded (the backend behind a
+/// real deployment sizes itself).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct L3Config {
+    pub max_entries: usize,
+    pub max_value_bytes: usize,
+    /// How long a failed backend is bypassed before it is tried again.
+    pub backend_retry: Duration,
+    /// Invalidations remembered during an outage; past this the backend is
+    /// flushed on recovery instead.
+    pub max_missed: usize,
+}
+
+impl Default for L3Config {
+    fn default() -> Self {
+        return L3Config { max_entries: usize::MAX, max_value_bytes: usize::MAX, backend_retry: Duration::from_secs(5), max_missed: 10_000 };
+    }
+}
+
+/// L3 is designed as a facade: a local concurrent map, or a pluggable
+/// backend for sharded/clustered or persistent storage.
 #[derive(Clone)]
 pub struct L3 {
-    inner: Arc<RwLock<HashMap<Vec<u8>, Entry>>>,
+    inner: Arc<RwLock<Local>>,
+    clock: Arc<dyn Clock>,
+    cfg: L3Config,
+    backend: Option<Arc<dyn L3Backend>>,
+    health: Arc<Mutex<Health>>,
+    stats: Arc<StatsCell>,
+    sweep_at: Arc<Mutex<Option<Vec<u8>>>>, // key `sweep` resumes after
+    #[cfg(feature = "disk")]
+    _compactor: Option<Arc<Compactor>>, // stops with the last clone
+}
+
+// The RAM store: entries by key, plus an index of when they die so a full
+// store finds its victims without a scan.
+#[derive(Default)]
+struct Local {
+    map: BTreeMap<Vec<u8>, Entry>,
+    deadlines: BTreeSet<(Instant, Vec<u8>)>, // entries whose deadline fits an Instant
+}
+
+impl Local {
+    fn insert(&mut self, key: &[u8], entry: Entry) -> Option<Entry> {
+        if let Some(t) = deadline(&entry) {
+            self.deadlines.insert((t, key.to_vec()));
+        }
+        let old = self.map.insert(key.to_vec(), entry)?;
+        self.unindex(key, &old);
+        return Some(old);
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<Entry> {
+        let old = self.map.remove(key)?;
+        self.unindex(key, &old);
+        return Some(old);
+    }
+
+    fn unindex(&mut self, key: &[u8], old: &Entry) {
+        if let Some(t) = deadline(old) {
+            self.deadlines.remove(&(t, key.to_vec()));
+        }
+    }
+
+    fn clear(&mut self) {
+        self.map.clear();
+        self.deadlines.clear();
+    }
+
+    // Every dead entry or, when none is, the one closest to expiry.
+    fn victims(&self, now: Instant) -> Vec<Vec<u8>> {
+        let mut dead: Vec<Vec<u8>> = self.deadlines.iter().take_while(|(t, _)| *t < now).map(|(_, k)| k.clone()).collect();
+        if dead.is_empty() {
+            // entries that outlive the Instant range go last
+            let next = self.deadlines.first().map(|(_, k)| k).or_else(|| self.map.keys().next());
+            dead.extend(next.cloned());
+        }
+        return dead;
+    }
+}
+
+// The instant past which `e` is dead (`Entry::is_dead_at`).
+fn deadline(e: &Entry) -> Option<Instant> {
+    return e.ts.checked_add(e.ttl.saturating_add(e.grace));
+}
+
+#[derive(Default)]
+struct Health {
+    down_until: Option<Instant>,
+    missed: HashSet<Vec<u8>>, // invalidated while the backend was bypassed
+    overflowed: bool,          // `missed` hit the cap: flush on recovery
+    replaying: bool,           // a caller is catching the backend up
+}
+
+impl Health {
+    fn miss(&mut self, key: Vec<u8>, max: usize) {
+        if self.overflowed {
+            return;
+        }
+        if self.missed.len() >= max {
+            self.missed.clear();
+            self.overflowed = true;
+            return;
+        }
+        self.missed.insert(key);
+    }
 }
 
+/// How often `L3::on_disk` compacts its segments.
+#[cfg(feature = "disk")]
+pub const COMPACT_INTERVAL: Duration = Duration::from_secs(60);
+
 impl L3 {
     pub fn new() -> Self {
-        return L3 { inner: Arc::new(RwLock::new(HashMap::new())) };
+        return Self::with_config(L3Config::default());
     }
-}
 
-impl Cache for L3 {
-    fn lookup(&self, key: &[u8]) -> Result<Entry, CacheError> {
-        let mut map = self.inner.write().unwrap();
-        if let Some(e) = map.get(key) {
-            if e.is_expired() {
-                map.remove(key);
+    pub fn with_config(cfg: L3Config) -> Self {
+        return L3 {
+            inner: Arc::default(),
+            clock: clock::system(),
+            cfg,
+            backend: None,
+            health: Arc::new(Mutex::new(Health::default())),
+            stats: Arc::default(),
+            sweep_at: Arc::default(),
+            #[cfg(feature = "disk")]
+            _compactor: None,
+        };
+    }
+
+    /// L3 storing its entries in `backend`, with local mode as the fallback.
+    pub fn with_backend(cfg: L3Config, backend: Arc<dyn L3Backend>) -> Self {
+        let mut l3 = Self::with_config(cfg);
+        l3.backend = Some(backend);
+        return l3;
+    }
+
+    /// L3 backed by a persistent store in `dir` (replayed on open, compacted
+    /// every `COMPACT_INTERVAL` in the background).
+    #[cfg(feature = "disk")]
+    pub fn on_disk(dir: impl AsRef<Path>, cfg: L3Config, disk: DiskConfig) -> io::Result<Self> {
+        let store = Arc::new(DiskStore::open(dir, disk)?);
+        let compactor = Arc::new(store.start_compactor(COMPACT_INTERVAL));
+        let mut l3 = Self::with_backend(cfg, store);
+        l3._compactor = Some(compactor);
+        return Ok(l3);
+    }
+
+    /// Replace the time source used for expiry checks.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        return self;
+    }
+
+    /// Hit/miss/eviction counters; lock-free. Items and bytes cover the RAM
+    /// map only (a backend sizes itself).
+    pub fn cache_stats(&self) -> CacheStats {
+        return self.stats.snapshot();
+    }
+
+    /// True while a failed backend is bypassed and the RAM map serves.
+    pub fn degraded(&self) -> bool {
+        let h = self.health.lock().unwrap();
+        return h.down_until.is_some_and(|t| self.clock.now() < t);
+    }
+
+    // The backend to use now, if any. The first call after the retry window
+    // replays missed invalidations (or flushes the backend after an
+    // overflow) without holding the health lock; other callers stay local
+    // meanwhile. The backend is back once nothing is left to replay.
+    fn active_backend(&self) -> Option<&Arc<dyn L3Backend>> {
+        let b = self.backend.as_ref()?;
+        let mut h = self.health.lock().unwrap();
+        let Some(until) = h.down_until else { return Some(b) };
+        if h.replaying || self.clock.now() < until {
+            return None;
+        }
+        h.replaying = true;
+        loop {
+            // invalidations recorded while we replayed are picked up here
+            let (keys, flush) = (std::mem::take(&mut h.missed), std::mem::take(&mut h.overflowed));
+            if keys.is_empty() && !flush {
+                break;
+            }
+            drop(h);
+            let left = Self::replay(&**b, keys, flush);
+            h = self.health.lock().unwrap();
+            if let Some((keys, flush)) = left {
+                h.overflowed |= flush;
+                for k in keys {
+                    h.miss(k, self.cfg.max_missed);
+                }
+                h.down_until = Some(self.clock.now() + self.cfg.backend_retry);
+                h.replaying = false;
+                return None;
+            }
+        }
+        h.down_until = None;
+        h.replaying = false;
+        self.inner.write().unwrap().clear();
+        self.stats.usage(0, 0);
+        return Some(b);
+    }
+
+    // Removes `keys` from `b`, or clears it when `flush`; on failure returns
+    // what is still to be done.
+    fn replay(b: &dyn L3Backend, keys: HashSet<Vec<u8>>, flush: bool) -> Option<(Vec<Vec<u8>>, bool)> {
+        if flush {
+            return b.clear().err().map(|_| (Vec::new(), true));
+        }
+        let mut keys: Vec<Vec<u8>> = keys.into_iter().collect();
+        while let Some(k) = keys.last() {
+            if b.remove(k).is_err() {
+                return Some((keys, false));
+            }
+            keys.pop();
+        }
+        return None;
+    }
+
+    // Passes `r` through; an I/O failure switches to local mode and comes
+    // back as Err(None) so the caller retries locally.
+    fn checked<T>(&self, r: Result<T, CacheError>) -> Result<T, Option<CacheError>> {
+        return match r {
+            Ok(v) => Ok(v),
+            Err(CacheError::Io(_)) => {
+                self.health.lock().unwrap().down_until = Some(self.clock.now() + self.cfg.backend_retry);
+                Err(None)
+            }
+            Err(e) => Err(Some(e)),
+        };
+    }
+
+    fn lookup_local(&self, key: &[u8], now: Instant) -> Result<Entry, CacheError> {
+        let mut local = self.inner.write().unwrap();
+        if let Some(e) = local.map.get(key) {
+            if e.is_dead_at(now) {
+                self.stats.adjust(-1, -(footprint(key, e) as i64));
+                self.stats.expired();
+                local.remove(key);
                 return Err(CacheError::Expired);
             }
+            self.stats.hit();
             return Ok(e.clone());
         }
+        self.stats.miss();
         return Err(CacheError::NotFound);
     }
 
+    fn insert_local(&self, key: &[u8], entry: Entry) {
+        let mut local = self.inner.write().unwrap();
+        if !local.map.contains_key(key) && local.map.len() >= self.cfg.max_entries {
+            // full: drop dead entries, then the one closest to expiry
+            let victims = local.victims(self.clock.now());
+            for k in victims.iter() {
+                if let Some(e) = local.remove(k) {
+                    self.stats.adjust(-1, -(footprint(k, &e) as i64));
+                }
+            }
+            self.stats.evicted(victims.len());
+        }
+        self.stats.adjust(1, footprint(key, &entry) as i64);
+        if let Some(old) = local.insert(key, entry) {
+            self.stats.adjust(-1, -(footprint(key, &old) as i64));
+        }
+    }
+}
+
+impl Default for L3 {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Cache for L3 {
+    fn lookup(&self, key: &[u8]) -> Result<Entry, CacheError> {
+        let now = self.clock.now();
+        if let Some(b) = self.active_backend() {
+            match self.checked(b.get(key)) {
+                Ok(Some(e)) if e.is_dead_at(now) => {
+                    let _ = self.checked(b.remove(key));
+                    self.stats.expired();
+                    return Err(CacheError::Expired);
+                }
+                Ok(Some(e)) => {
+                    self.stats.hit();
+                    return Ok(e);
+                }
+                Ok(None) => {
+                    self.stats.miss();
+                    return Err(CacheError::NotFound);
+                }
+                Err(Some(e)) => {
+                    self.stats.miss();
+                    return Err(e);
+                }
+                Err(None) => {}
+            }
+        }
+        return self.lookup_local(key, now);
+    }
+
     fn insert(&self, key: &[u8], entry: Entry) -> Result<(), CacheError> {
-        let mut map = self.inner.write().unwrap();
-        map.insert(key.to_vec(), entry);
+        if entry.value.len() > self.cfg.max_value_bytes {
+            // an overwrite replaces the old value even when the new one is refused
+            let _ = self.invalidate(key);
+            return Err(CacheError::TooLarge);
+        }
+        if let Some(b) = self.active_backend() {
+            b.make_room(key, self.cfg.max_entries, self.clock.now());
+            match self.checked(b.put(key, &entry)) {
+                Ok(()) => return Ok(()),
+                Err(Some(e)) => return Err(e),
+                Err(None) => {}
+            }
+        }
+        self.insert_local(key, entry);
         return Ok(());
     }
 
     fn invalidate(&self, key: &[u8]) -> Result<(), CacheError> {
-        let mut map = self.inner.write().unwrap();
-        if map.remove(key).is_some() {
+        if let Some(b) = self.active_backend() {
+            match self.checked(b.remove(key)) {
+                Ok(true) => return Ok(()),
+                Ok(false) => return Err(CacheError::NotFound),
+                Err(Some(e)) => return Err(e),
+                Err(None) => {}
+            }
+        }
+        if self.backend.is_some() {
+            self.health.lock().unwrap().miss(key.to_vec(), self.cfg.max_missed);
+        }
+        let mut local = self.inner.write().unwrap();
+        if let Some(e) = local.remove(key) {
+            self.stats.adjust(-1, -(footprint(key, &e) as i64));
             return Ok(());
         }
         return Err(CacheError::NotFound);
     }
-}
\ No newline at end of file
+}
+
+// Sweeps the RAM map only; backends expire entries themselves (PX on Redis,
+// compaction on disk). The map is key ordered and the cursor is the last key
+// visited, so each call resumes where the previous one stopped in
+// O(budget log n), whatever was inserted or removed in between.
+impl Sweep for L3 {
+    fn sweep(&self, budget: usize) -> usize {
+        let now = self.clock.now();
+        let mut local = self.inner.write().unwrap();
+        let mut cursor = self.sweep_at.lock().unwrap();
+        let n = budget.min(local.map.len());
+        let after = match cursor.as_deref() {
+            Some(k) => Bound::Excluded(k),
+            None => Bound::Unbounded,
+        };
+        // past the last key the walk wraps around to the first
+        let mut last = None;
+        let mut dead: Vec<Vec<u8>> = Vec::new();
+        for (k, e) in local.map.range::<[u8], _>((after, Bound::Unbounded)).chain(local.map.iter()).take(n) {
+            if e.is_dead_at(now) {
+                dead.push(k.clone());
+            }
+            last = Some(k);
+        }
+        *cursor = last.cloned();
+        for k in dead.iter() {
+            if let Some(e) = local.remove(k) {
+                self.stats.adjust(-1, -(footprint(k, &e) as i64));
+            }
+        }
+        return dead.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[test]
+    fn ttl_with_mock_clock() {
+        let clock = Arc::new(MockClock::new());
+        let l3 = L3::new().with_clock(clock.clone());
+        l3.insert(b"k", Entry::new_at(b"v".to_vec(), 0, Duration::from_secs(10), clock.now())).unwrap();
+        clock.advance(Duration::from_secs(9));
+        assert!(l3.lookup(b"k").is_ok());
+        clock.advance(Duration::from_secs(2));
+        assert!(matches!(l3.lookup(b"k"), Err(CacheError::Expired)));
+    }
+
+    #[test]
+    fn full_store_evicts_closest_to_expiry() {
+        let l3 = L3::with_config(L3Config { max_entries: 2, max_value_bytes: 4, ..L3Config::default() });
+        assert!(matches!(l3.insert(b"big", Entry::new(b"12345".to_vec(), 0, Duration::from_secs(60))), Err(CacheError::TooLarge)));
+        l3.insert(b"big", Entry::new(b"1234".to_vec(), 0, Duration::from_secs(60))).unwrap();
+        assert!(l3.insert(b"big", Entry::new(b"12345".to_vec(), 0, Duration::from_secs(60))).is_err());
+        assert!(matches!(l3.lookup(b"big"), Err(CacheError::NotFound)), "refused overwrite drops the old value");
+        l3.insert(b"long", Entry::new(b"v".to_vec(), 0, Duration::from_secs(600))).unwrap();
+        l3.insert(b"short", Entry::new(b"v".to_vec(), 0, Duration::from_secs(10))).unwrap();
+        l3.insert(b"new", Entry::new(b"v".to_vec(), 0, Duration::from_secs(60))).unwrap();
+        assert!(l3.lookup(b"long").is_ok());
+        assert!(matches!(l3.lookup(b"short"), Err(CacheError::NotFound)));
+        assert!(l3.lookup(b"new").is_ok());
+        l3.insert(b"new", Entry::new(b"w".to_vec(), 0, Duration::from_secs(5))).unwrap();
+        let local = l3.inner.read().unwrap();
+        assert_eq!(local.deadlines.iter().map(|(_, k)| k.as_slice()).collect::<Vec<_>>(), vec![&b"new"[..], b"long"], "index follows overwrites");
+    }
+
+    // Map-backed backend that fails with an I/O error while `down`.
+    #[derive(Default)]
+    struct Flaky {
+        map: Mutex<HashMap<Vec<u8>, Entry>>,
+        down: std::sync::atomic::AtomicBool,
+    }
+
+    impl Flaky {
+        fn up(&self) -> Result<(), CacheError> {
+            if self.down.load(std::sync::atomic::Ordering::SeqCst) {
+                return Err(CacheError::Io(std::io::ErrorKind::ConnectionRefused));
+            }
+            return Ok(());
+        }
+    }
+
+    impl L3Backend for Flaky {
+        fn get(&self, key: &[u8]) -> Result<Option<Entry>, CacheError> {
+            self.up()?;
+            return Ok(self.map.lock().unwrap().get(key).cloned());
+        }
+        fn put(&self, key: &[u8], entry: &Entry) -> Result<(), CacheError> {
+            self.up()?;
+            self.map.lock().unwrap().insert(key.to_vec(), entry.clone());
+            return Ok(());
+        }
+        fn remove(&self, key: &[u8]) -> Result<bool, CacheError> {
+            self.up()?;
+            return Ok(self.map.lock().unwrap().remove(key).is_some());
+        }
+        fn clear(&self) -> Result<(), CacheError> {
+            self.up()?;
+            self.map.lock().unwrap().clear();
+            return Ok(());
+        }
+    }
+
+    #[test]
+    fn backend_outage_replays_invalidations() {
+        let clock = Arc::new(MockClock::new());
+        let backend = Arc::new(Flaky::default());
+        let l3 = L3::with_backend(L3Config::default(), backend.clone()).with_clock(clock.clone());
+        let e = |v: &[u8]| Entry::new_at(v.to_vec(), 0, Duration::from_secs(600), clock.now());
+        l3.insert(b"a", e(b"1")).unwrap();
+
+        backend.down.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(l3.lookup(b"a").is_err());
+        assert!(l3.degraded());
+        l3.insert(b"b", e(b"2")).unwrap();
+        assert_eq!(l3.lookup(b"b").unwrap().value, b"2");
+        let _ = l3.invalidate(b"a"); // only recorded: the backend is bypassed
+
+        backend.down.store(false, std::sync::atomic::Ordering::SeqCst);
+        assert!(l3.degraded(), "bypassed until the retry window ends");
+        clock.advance(L3Config::default().backend_retry);
+        assert!(matches!(l3.lookup(b"a"), Err(CacheError::NotFound)), "missed invalidation replayed");
+        assert!(!l3.degraded());
+        assert!(matches!(l3.lookup(b"b"), Err(CacheError::NotFound)), "outage-only entries dropped");
+    }
+
+    #[test]
+    fn too_many_missed_invalidations_flush_the_backend() {
+        let clock = Arc::new(MockClock::new());
+        let backend = Arc::new(Flaky::default());
+        let cfg = L3Config { max_missed: 2, ..L3Config::default() };
+        let l3 = L3::with_backend(cfg, backend.clone()).with_clock(clock.clone());
+        let e = |v: &[u8]| Entry::new_at(v.to_vec(), 0, Duration::from_secs(600), clock.now());
+        for k in [&b"a"[..], b"b", b"c", b"kept?"] {
+            l3.insert(k, e(b"1")).unwrap();
+        }
+
+        backend.down.store(true, std::sync::atomic::Ordering::SeqCst);
+        for k in [&b"a"[..], b"b", b"c"] {
+            let _ = l3.invalidate(k);
+        }
+        assert!(l3.health.lock().unwrap().missed.is_empty(), "queue dropped at the cap");
+
+        backend.down.store(false, std::sync::atomic::Ordering::SeqCst);
+        clock.advance(cfg.backend_retry);
+        assert!(matches!(l3.lookup(b"c"), Err(CacheError::NotFound)));
+        assert!(!l3.degraded());
+        assert!(backend.map.lock().unwrap().is_empty(), "flushed instead of replayed");
+    }
+
+    #[cfg(feature = "disk")]
+    #[test]
+    fn on_disk_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("olwsx-l3-disk-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cfg = L3Config { max_entries: 2, ..L3Config::default() };
+        {
+            let l3 = L3::on_disk(&dir, cfg, DiskConfig::default()).unwrap();
+            l3.insert(b"short", Entry::new(b"a".to_vec(), 0, Duration::from_secs(10))).unwrap();
+            l3.insert(b"long", Entry::new(vec![1u8; 256 * 1024], 0, Duration::from_secs(600))).unwrap();
+            l3.insert(b"new", Entry::new(b"c".to_vec(), 0, Duration::from_secs(60))).unwrap();
+            assert!(matches!(l3.lookup(b"short"), Err(CacheError::NotFound)));
+            l3.invalidate(b"new").unwrap();
+        }
+        let l3 = L3::on_disk(&dir, cfg, DiskConfig::default()).unwrap();
+        assert_eq!(l3.lookup(b"long").unwrap().value.len(), 256 * 1024);
+        assert!(matches!(l3.lookup(b"new"), Err(CacheError::NotFound)));
+        assert!(matches!(l3.lookup(b"short"), Err(CacheError::NotFound)), "eviction survives the restart");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -13,8 +13,31 @@ pub mod l1;
 pub mod l2;
 pub mod l3;
 pub mod compression;
+pub mod clock;
+pub mod checksum;
+pub mod spill;
+pub mod integrity;
+pub mod key;
+pub mod warmup;
+pub mod namespace;
+pub mod resp;
+pub mod cluster;
+pub mod value;
+pub mod stats;
+pub mod sweep;
+pub mod validators;
 
-use std::time::{Duration, Instant};
+pub use stats::CacheStats;
+pub use sweep::{Sweep, SweepConfig, Sweeper};
+pub use validators::Conditional;
+pub use value::{Chunk, EntryStream, Value};
+#[cfg(feature = "disk")]
+pub mod disk;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Meta flags (frozen; mirror core)
 pub mod meta {
@@ -27,36 +50,121 @@ pub mod meta {
     pub const CACHE_L1: u32    = 0x0002_0000;
     pub const CACHE_L2: u32    = 0x0004_0000;
     pub const CACHE_L3: u32    = 0x0008_0000;
+    pub const CACHE_NEGATIVE: u32 = 0x0080_0000;
 
     pub const SEC_OK: u32      = 0x0010_0000;
     pub const SEC_WAF: u32     = 0x0020_0000;
     pub const SEC_RATELIM: u32 = 0x0040_0000;
 }
 
+/// Bookkeeping charged per entry on top of its key and value bytes (map slot,
+/// recency node, `Entry` header).
+pub const ENTRY_OVERHEAD: usize = std::mem::size_of::<Entry>() + 64;
+
+/// Bytes a tier accounts for one entry against its budget.
+pub fn footprint(key: &[u8], entry: &Entry) -> usize {
+    return key.len() + entry.value.len() + entry.etag.as_ref().map_or(0, |t| t.len()) + ENTRY_OVERHEAD;
+}
+
+/// Current memory use of a tier against its limits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TierUsage {
+    pub items: usize,
+    pub bytes: usize, // sum of entry footprints
+    pub max_items: usize,
+    pub max_bytes: usize,
+}
+
 /// Canonical cache entry (frozen)
 #[derive(Clone, Debug)]
 pub struct Entry {
-    pub value: Vec<u8>,
+    pub value: Value,          // shared: clones and tier promotion never copy the body
     pub flags: u32,
     pub ts: Instant,
     pub ttl: Duration,
+    pub checksum: Option<u32>, // CRC-32 of `value`, when integrity checking is enabled
+    pub grace: Duration,       // stale-while-revalidate window after `ttl`
+    pub etag: Option<String>,  // as sent by the origin, quotes (and W/) included
+    pub last_modified: Option<SystemTime>, // whole seconds, as in HTTP dates
 }
 
 impl Entry {
-    pub fn new(value: Vec<u8>, flags: u32, ttl: Duration) -> Self {
-        return Entry { value, flags, ts: Instant::now(), ttl };
+    pub fn new(value: impl Into<Value>, flags: u32, ttl: Duration) -> Self {
+        return Self::new_at(value, flags, ttl, Instant::now());
+    }
+    /// Entry stamped with an explicit creation time (e.g. from a `clock::Clock`).
+    pub fn new_at(value: impl Into<Value>, flags: u32, ttl: Duration, ts: Instant) -> Self {
+        return Entry { value: value.into(), flags, ts, ttl, checksum: None, grace: Duration::ZERO, etag: None, last_modified: None };
+    }
+    /// A "not found" marker (upstream 404, failed origin fetch) with an empty
+    /// value, cached for `ttl` so repeated requests skip the backend.
+    /// `TieredCache::lookup_outcome` reports it as `NegativeHit`,
+    /// `Cache::lookup` as `CacheError::Negative`.
+    pub fn negative(ttl: Duration) -> Self {
+        return Self::new(Vec::new(), meta::CACHE_NEGATIVE, ttl);
+    }
+    pub fn is_negative(&self) -> bool {
+        return self.flags & meta::CACHE_NEGATIVE != 0;
+    }
+    /// Keep serving the entry as stale for `grace` after it expires, while a
+    /// refresh runs (see `TieredCache::lookup_outcome`). Tiers only drop an
+    /// entry once the grace window has passed too.
+    pub fn with_grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        return self;
+    }
+    /// Validators for conditional requests; see `validators::evaluate`.
+    pub fn with_etag(mut self, etag: impl Into<String>) -> Self {
+        self.etag = Some(etag.into());
+        return self;
+    }
+    pub fn with_last_modified(mut self, t: SystemTime) -> Self {
+        let secs = t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        self.last_modified = Some(UNIX_EPOCH + Duration::from_secs(secs));
+        return self;
+    }
+    /// `validators::evaluate` with this entry's validators.
+    pub fn conditional(&self, if_none_match: Option<&str>, if_modified_since: Option<&str>) -> Conditional {
+        return validators::evaluate(self, if_none_match, if_modified_since);
+    }
+    /// Attach a CRC-32 of the current value; verified by `integrity::Verified`.
+    pub fn with_checksum(mut self) -> Self {
+        self.checksum = Some(checksum::crc32(&self.value));
+        return self;
+    }
+    /// True when no checksum is stored or the value still matches it.
+    pub fn verify(&self) -> bool {
+        return match self.checksum {
+            Some(sum) => checksum::crc32(&self.value) == sum,
+            None => true,
+        };
     }
     pub fn is_expired(&self) -> bool {
-        return self.ts.elapsed() > self.ttl;
+        return self.is_expired_at(Instant::now());
+    }
+    pub fn is_expired_at(&self, now: Instant) -> bool {
+        return now.saturating_duration_since(self.ts) > self.ttl;
+    }
+    /// Expired, but still inside the grace window.
+    pub fn is_stale_at(&self, now: Instant) -> bool {
+        return self.is_expired_at(now) && !self.is_dead_at(now);
+    }
+    /// Past `ttl + grace`: no longer servable at all.
+    pub fn is_dead_at(&self, now: Instant) -> bool {
+        return now.saturating_duration_since(self.ts) > self.ttl.saturating_add(self.grace);
     }
 }
 
 /// Unified errors (frozen)
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum CacheError {
     TooLarge,
     NotFound,
     Expired,
+    Io(std::io::ErrorKind),
+    Corrupt,
+    /// A live `Entry::negative` marker: the key is known to be missing.
+    Negative,
 }
 
 /// Cache trait (frozen)
@@ -64,4 +172,650 @@ pub trait Cache {
     fn lookup(&self, key: &[u8]) -> Result<Entry, CacheError>;
     fn insert(&self, key: &[u8], entry: Entry) -> Result<(), CacheError>;
     fn invalidate(&self, key: &[u8]) -> Result<(), CacheError>;
-}
\ No newline at end of file
+}
+
+/// Chunked reads for large bodies, available on every `Cache`. The chunks
+/// share the stored value, so serving a hit copies nothing.
+pub trait CacheStream: Cache {
+    fn lookup_stream(&self, key: &[u8], chunk_size: usize) -> Result<EntryStream, CacheError> {
+        return Ok(EntryStream::new(self.lookup(key)?, chunk_size));
+    }
+}
+
+impl<C: Cache + ?Sized> CacheStream for C {}
+
+/// Coalesces concurrent fills of the same key: the first caller (leader) runs
+/// the fill closure, later callers for that key wait for its result instead
+/// of recomputing it. Waiters give up after `timeout` with
+/// `CacheError::Io(TimedOut)`; a leader that panics releases them with
+/// `CacheError::NotFound`.
+pub struct SingleFlight {
+    inflight: Mutex<HashMap<Vec<u8>, Arc<Flight>>>,
+    timeout: Duration,
+}
+
+struct Flight {
+    result: Mutex<Option<Result<Entry, CacheError>>>,
+    done: Condvar,
+}
+
+// Publishes the leader's result (or a failure if it unwinds) and retires the key.
+struct Leader<'a> {
+    sf: &'a SingleFlight,
+    key: &'a [u8],
+    flight: Arc<Flight>,
+    result: Option<Result<Entry, CacheError>>,
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        let result = self.result.take().unwrap_or(Err(CacheError::NotFound));
+        self.sf.inflight.lock().unwrap_or_else(|e| e.into_inner()).remove(self.key);
+        *self.flight.result.lock().unwrap_or_else(|e| e.into_inner()) = Some(result);
+        self.flight.done.notify_all();
+    }
+}
+
+impl SingleFlight {
+    pub fn new(timeout: Duration) -> Self {
+        return SingleFlight { inflight: Mutex::new(HashMap::new()), timeout };
+    }
+
+    pub fn run<F>(&self, key: &[u8], fill: F) -> Result<Entry, CacheError>
+    where
+        F: FnOnce() -> Result<Entry, CacheError>,
+    {
+        let (flight, leader) = {
+            let mut inflight = self.inflight.lock().unwrap();
+            match inflight.get(key) {
+                Some(f) => (Arc::clone(f), false),
+                None => {
+                    let f = Arc::new(Flight { result: Mutex::new(None), done: Condvar::new() });
+                    inflight.insert(key.to_vec(), Arc::clone(&f));
+                    (f, true)
+                }
+            }
+        };
+        if leader {
+            let mut guard = Leader { sf: self, key, flight, result: None };
+            let result = fill();
+            guard.result = Some(result.clone());
+            return result;
+        }
+        let slot = flight.result.lock().unwrap_or_else(|e| e.into_inner());
+        let (slot, _) = flight
+            .done
+            .wait_timeout_while(slot, self.timeout, |r| r.is_none())
+            .unwrap_or_else(|e| e.into_inner());
+        return match slot.as_ref() {
+            Some(r) => r.clone(),
+            None => Err(CacheError::Io(std::io::ErrorKind::TimedOut)),
+        };
+    }
+
+    /// Keys with a fill currently running.
+    pub fn in_flight(&self) -> usize {
+        return self.inflight.lock().unwrap().len();
+    }
+}
+
+/// Write policy for `TieredCache::insert`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Insert into every tier before returning.
+    WriteThrough,
+    /// Insert into the top tier only; lower tiers are written by `flush`,
+    /// which runs automatically once `max_dirty` keys are pending.
+    WriteBack { max_dirty: usize },
+}
+
+/// Result of `TieredCache::lookup_outcome`.
+#[derive(Debug)]
+pub enum LookupOutcome {
+    Fresh(Entry),
+    /// Expired but inside its grace window. This caller now holds the refresh
+    /// marker: serve the entry, refresh in the background, then `insert` the
+    /// new entry (or `release_refresh` if the refresh failed).
+    Stale(Entry),
+    /// Stale, and another caller is already refreshing it: just serve it.
+    Revalidating(Entry),
+    /// A live `Entry::negative` marker: answer "not found" without asking
+    /// the origin. Expired markers are a `Miss`, never served stale.
+    NegativeHit(Entry),
+    Miss,
+}
+
+type Tier = Arc<dyn Cache + Send + Sync>;
+
+const REFRESH_TIMEOUT: Duration = Duration::from_secs(30);
+const FILL_TIMEOUT: Duration = Duration::from_secs(10);
+
+const CACHE_TIER_FLAGS: u32 = meta::CACHE_MISS | meta::CACHE_L1 | meta::CACHE_L2 | meta::CACHE_L3;
+
+// A write-back key. While a flush is writing it below the top tier the
+// record stays put: invalidate and insert bump `seq` instead of removing it
+// (`entry: None` for an invalidate), so the flush can tell that its write
+// went stale and undo or leave it.
+struct Dirty {
+    entry: Option<Entry>,
+    seq: u64,
+    flushing: bool,
+}
+
+/// Composes tiers (fastest first, e.g. L1, L2, L3) behind one `Cache`.
+/// Lookups fall through tiers and promote hits into every faster tier;
+/// returned entries carry the `meta::CACHE_L*` flag of the tier that served them.
+pub struct TieredCache {
+    tiers: Vec<Tier>,
+    mode: WriteMode,
+    dirty: Mutex<HashMap<Vec<u8>, Dirty>>,
+    pending: AtomicUsize, // dirty records holding an entry; changed under the `dirty` lock
+    next_seq: AtomicU64,
+    refreshing: Mutex<HashMap<Vec<u8>, Instant>>, // stale keys with a refresh in flight
+    refresh_timeout: Duration,
+    flights: SingleFlight,
+}
+
+impl TieredCache {
+    pub fn new(tiers: Vec<Tier>) -> Self {
+        return TieredCache {
+            tiers,
+            mode: WriteMode::WriteThrough,
+            dirty: Mutex::new(HashMap::new()),
+            pending: AtomicUsize::new(0),
+            next_seq: AtomicU64::new(0),
+            refreshing: Mutex::new(HashMap::new()),
+            refresh_timeout: REFRESH_TIMEOUT,
+            flights: SingleFlight::new(FILL_TIMEOUT),
+        };
+    }
+
+    /// The usual L1 -> L2 -> L3 stack.
+    pub fn standard(l1: l1::L1, l2: l2::L2, l3: l3::L3) -> Self {
+        return Self::new(vec![Arc::new(l1), Arc::new(l2), Arc::new(l3)]);
+    }
+
+    pub fn with_mode(mut self, mode: WriteMode) -> Self {
+        self.mode = mode;
+        return self;
+    }
+
+    /// A refresh marker older than this is handed to the next stale lookup,
+    /// so a refresher that never reports back cannot pin the stale entry.
+    pub fn with_refresh_timeout(mut self, timeout: Duration) -> Self {
+        self.refresh_timeout = timeout;
+        return self;
+    }
+
+    /// How long concurrent `get_or_fill` callers wait for the one filling.
+    pub fn with_fill_timeout(mut self, timeout: Duration) -> Self {
+        self.flights = SingleFlight::new(timeout);
+        return self;
+    }
+
+    /// Lookup, and on a miss run `fill` once per key no matter how many
+    /// callers miss concurrently; the filled entry is inserted before the
+    /// waiters are released. A fill may return `Entry::negative` for a
+    /// missing or failing origin; that call and later ones get
+    /// `CacheError::Negative` until the marker expires, without running `fill`.
+    pub fn get_or_fill<F>(&self, key: &[u8], fill: F) -> Result<Entry, CacheError>
+    where
+        F: FnOnce() -> Result<Entry, CacheError>,
+    {
+        match self.lookup(key) {
+            Ok(e) => return Ok(e),
+            Err(CacheError::Negative) => return Err(CacheError::Negative),
+            Err(_) => {}
+        }
+        return self.flights.run(key, || {
+            // a previous leader may have filled it between our miss and now
+            match self.lookup(key) {
+                Ok(e) => return Ok(e),
+                Err(CacheError::Negative) => return Err(CacheError::Negative),
+                Err(_) => {}
+            }
+            let e = fill()?;
+            self.insert(key, e.clone())?;
+            if e.is_negative() {
+                return Err(CacheError::Negative);
+            }
+            return Ok(e);
+        });
+    }
+
+    pub fn fills_in_flight(&self) -> usize {
+        return self.flights.in_flight();
+    }
+
+    /// Lookup with stale-while-revalidate: entries past their TTL but inside
+    /// their grace window come back as `Stale` to exactly one caller (until it
+    /// inserts, releases, or the refresh times out) and as `Revalidating` to
+    /// everyone else. `Cache::lookup` treats such entries as expired.
+    pub fn lookup_outcome(&self, key: &[u8]) -> LookupOutcome {
+        let Ok(e) = self.find(key) else { return LookupOutcome::Miss };
+        let now = Instant::now();
+        if e.is_negative() {
+            if e.is_expired_at(now) {
+                return LookupOutcome::Miss;
+            }
+            return LookupOutcome::NegativeHit(e);
+        }
+        if !e.is_expired_at(now) {
+            return LookupOutcome::Fresh(e);
+        }
+        let mut refreshing = self.refreshing.lock().unwrap();
+        if let Some(since) = refreshing.get(key)
+            && now.saturating_duration_since(*since) < self.refresh_timeout
+        {
+            return LookupOutcome::Revalidating(e);
+        }
+        // markers whose refresher never reported back would otherwise stay
+        // until their own key is looked up again
+        let timeout = self.refresh_timeout;
+        refreshing.retain(|_, since| now.saturating_duration_since(*since) < timeout);
+        refreshing.insert(key.to_vec(), now);
+        return LookupOutcome::Stale(e);
+    }
+
+    /// Give up the refresh marker taken by a `Stale` lookup (refresh failed).
+    pub fn release_refresh(&self, key: &[u8]) {
+        self.refreshing.lock().unwrap().remove(key);
+    }
+
+    /// Write pending write-back entries into the lower tiers. A key
+    /// invalidated or rewritten while its write is in flight is not
+    /// resurrected: the stale write is skipped, or undone once it lands.
+    pub fn flush(&self) -> Result<(), CacheError> {
+        let pending: Vec<(Vec<u8>, Entry, u64)> = {
+            let mut dirty = self.dirty.lock().unwrap();
+            dirty
+                .iter_mut()
+                .filter(|(_, d)| !d.flushing)
+                .filter_map(|(k, d)| {
+                    let e = d.entry.clone()?;
+                    d.flushing = true;
+                    return Some((k.clone(), e, d.seq));
+                })
+                .collect()
+        };
+        let mut result = Ok(());
+        for (k, e, seq) in pending {
+            if e.is_dead_at(Instant::now()) || !self.still_pending(&k, seq) {
+                self.settle(&k, seq, false);
+                continue;
+            }
+            if let Err(err) = self.write_tiers(1, &k, &e) {
+                result = Err(err);
+            }
+            self.settle(&k, seq, true);
+        }
+        return result;
+    }
+
+    /// Write-back entries not yet flushed.
+    pub fn pending(&self) -> usize {
+        return self.pending.load(Ordering::Relaxed);
+    }
+
+    // Keeps `pending` in step when a dirty record goes from holding an entry
+    // (`was`) to holding one (`is`) or not.
+    fn count_pending(&self, was: bool, is: bool) {
+        if is && !was {
+            self.pending.fetch_add(1, Ordering::Relaxed);
+        } else if was && !is {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    fn still_pending(&self, key: &[u8], seq: u64) -> bool {
+        return self.dirty.lock().unwrap().get(key).is_some_and(|d| d.seq == seq);
+    }
+
+    // Ends a flush of `key` started at `seq`. Unchanged: the record is done.
+    // Rewritten meanwhile: left for the next flush. Invalidated meanwhile:
+    // what this flush wrote (`wrote`) is cleared from the lower tiers again
+    // before the record goes, so later invalidates still see it in flight.
+    fn settle(&self, key: &[u8], seq: u64, wrote: bool) {
+        {
+            let mut dirty = self.dirty.lock().unwrap();
+            let Some(d) = dirty.get_mut(key) else { return };
+            if d.seq == seq || (d.entry.is_none() && !wrote) {
+                let was = d.entry.is_some();
+                dirty.remove(key);
+                self.count_pending(was, false);
+                return;
+            }
+            if d.entry.is_some() {
+                d.flushing = false;
+                return;
+            }
+        }
+        for t in self.tiers.iter().skip(1) {
+            let _ = t.invalidate(key);
+        }
+        let mut dirty = self.dirty.lock().unwrap();
+        match dirty.get_mut(key) {
+            Some(d) if d.entry.is_some() => d.flushing = false,
+            Some(_) => {
+                dirty.remove(key);
+            }
+            None => {}
+        }
+    }
+
+    // Records a write-back insert (Some) or an invalidate (None) of `key`.
+    fn mark(&self, key: &[u8], entry: Option<Entry>) {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut dirty = self.dirty.lock().unwrap();
+        match dirty.get_mut(key) {
+            Some(d) if d.flushing => {
+                self.count_pending(d.entry.is_some(), entry.is_some());
+                d.entry = entry;
+                d.seq = seq;
+            }
+            _ => {
+                let is = entry.is_some();
+                let old = match entry {
+                    Some(entry) => dirty.insert(key.to_vec(), Dirty { entry: Some(entry), seq, flushing: false }),
+                    None => dirty.remove(key),
+                };
+                self.count_pending(old.is_some_and(|d| d.entry.is_some()), is);
+            }
+        }
+    }
+
+    fn tier_flag(idx: usize) -> u32 {
+        return match idx {
+            0 => meta::CACHE_L1,
+            1 => meta::CACHE_L2,
+            _ => meta::CACHE_L3,
+        };
+    }
+
+    // Ok if at least one tier from `from` accepted the entry. A tier that
+    // refuses it must not keep serving an older value for the key.
+    fn write_tiers(&self, from: usize, key: &[u8], entry: &Entry) -> Result<(), CacheError> {
+        let mut last = Err(CacheError::NotFound);
+        let mut stored = false;
+        for t in self.tiers.iter().skip(from) {
+            match t.insert(key, entry.clone()) {
+                Ok(()) => stored = true,
+                Err(e) => {
+                    let _ = t.invalidate(key);
+                    last = Err(e);
+                }
+            }
+        }
+        if stored {
+            return Ok(());
+        }
+        return last;
+    }
+
+    // Fall through the tiers, promoting the hit; stale entries included.
+    fn find(&self, key: &[u8]) -> Result<Entry, CacheError> {
+        for (idx, t) in self.tiers.iter().enumerate() {
+            let mut e = match t.lookup(key) {
+                Ok(e) => e,
+                Err(_) if idx == 0 => match self.dirty.lock().unwrap().get(key).and_then(|d| d.entry.as_ref()) {
+                    // evicted from the top tier before reaching the lower ones
+                    Some(d) if !d.is_dead_at(Instant::now()) => d.clone(),
+                    _ => continue,
+                },
+                Err(_) => continue,
+            };
+            e.flags &= !CACHE_TIER_FLAGS;
+            for faster in self.tiers.iter().take(idx) {
+                // best effort: a faster tier may reject the entry (e.g. TooLarge)
+                let _ = faster.insert(key, e.clone());
+            }
+            e.flags |= Self::tier_flag(idx);
+            return Ok(e);
+        }
+        return Err(CacheError::NotFound);
+    }
+}
+
+impl Cache for TieredCache {
+    fn lookup(&self, key: &[u8]) -> Result<Entry, CacheError> {
+        let e = self.find(key)?;
+        if e.is_expired() {
+            return Err(CacheError::Expired);
+        }
+        if e.is_negative() {
+            return Err(CacheError::Negative);
+        }
+        return Ok(e);
+    }
+
+    fn insert(&self, key: &[u8], entry: Entry) -> Result<(), CacheError> {
+        self.refreshing.lock().unwrap().remove(key);
+        let mut entry = entry;
+        entry.flags &= !CACHE_TIER_FLAGS;
+        let max_dirty = match self.mode {
+            WriteMode::WriteThrough => return self.write_tiers(0, key, &entry),
+            WriteMode::WriteBack { max_dirty } => max_dirty,
+        };
+        let Some(top) = self.tiers.first() else { return Err(CacheError::NotFound) };
+        if top.insert(key, entry.clone()).is_err() {
+            // too large (or otherwise refused) for the top tier: write through
+            // below it; an in-flight flush of an older value keeps this one
+            // pending so it is written again after that flush lands
+            let in_flight = self.dirty.lock().unwrap().get(key).is_some_and(|d| d.flushing);
+            self.mark(key, in_flight.then(|| entry.clone()));
+            return self.write_tiers(1, key, &entry);
+        }
+        self.mark(key, Some(entry));
+        if self.pending() >= max_dirty {
+            return self.flush();
+        }
+        return Ok(());
+    }
+
+    fn invalidate(&self, key: &[u8]) -> Result<(), CacheError> {
+        self.refreshing.lock().unwrap().remove(key);
+        let mut existed = self.dirty.lock().unwrap().get(key).is_some_and(|d| d.entry.is_some());
+        self.mark(key, None);
+        for t in self.tiers.iter() {
+            existed |= t.invalidate(key).is_ok();
+        }
+        if existed {
+            return Ok(());
+        }
+        return Err(CacheError::NotFound);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(v: &[u8]) -> Entry {
+        return Entry::new(v.to_vec(), meta::COMP_GZIP, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn tiered_promotes_and_writes_back() {
+        let (l1, l2, l3) = (l1::L1::new(), l2::L2::new(), l3::L3::new());
+        let tiered = TieredCache::new(vec![Arc::new(l1.clone()), Arc::new(l2.clone()), Arc::new(l3.clone())]);
+
+        l3.insert(b"k", entry(b"v")).unwrap();
+        let e = tiered.lookup(b"k").unwrap();
+        assert_eq!(e.flags, meta::COMP_GZIP | meta::CACHE_L3);
+        assert_eq!(l1.lookup(b"k").unwrap().flags, meta::COMP_GZIP);
+        assert!(l2.lookup(b"k").is_ok());
+        assert_eq!(tiered.lookup(b"k").unwrap().flags, meta::COMP_GZIP | meta::CACHE_L1);
+
+        let wb = TieredCache::new(vec![Arc::new(l1.clone()), Arc::new(l3.clone())]).with_mode(WriteMode::WriteBack { max_dirty: 2 });
+        wb.insert(b"a", entry(b"1")).unwrap();
+        assert!(l3.lookup(b"a").is_err());
+        assert_eq!(wb.pending(), 1);
+        wb.insert(b"a", entry(b"1")).unwrap();
+        assert_eq!(wb.pending(), 1, "a rewrite of a pending key is still one");
+        wb.insert(b"b", entry(b"2")).unwrap();
+        assert_eq!(wb.pending(), 0);
+        assert!(l3.lookup(b"a").is_ok() && l3.lookup(b"b").is_ok());
+
+        wb.invalidate(b"a").unwrap();
+        assert!(matches!(wb.lookup(b"a"), Err(CacheError::NotFound)));
+        wb.insert(b"c", entry(b"3")).unwrap();
+        wb.invalidate(b"c").unwrap();
+        assert_eq!(wb.pending(), 0);
+    }
+
+    // Runs a one-shot hook before an insert lands, to interleave with a flush.
+    struct OnInsert {
+        inner: l3::L3,
+        hook: Mutex<Option<Box<dyn FnOnce() + Send>>>,
+    }
+
+    impl Cache for OnInsert {
+        fn lookup(&self, key: &[u8]) -> Result<Entry, CacheError> {
+            return self.inner.lookup(key);
+        }
+        fn insert(&self, key: &[u8], entry: Entry) -> Result<(), CacheError> {
+            let hook = self.hook.lock().unwrap().take();
+            if let Some(hook) = hook {
+                hook();
+            }
+            return self.inner.insert(key, entry);
+        }
+        fn invalidate(&self, key: &[u8]) -> Result<(), CacheError> {
+            return self.inner.invalidate(key);
+        }
+    }
+
+    #[test]
+    fn flush_does_not_resurrect_invalidated_keys() {
+        let low = Arc::new(OnInsert { inner: l3::L3::new(), hook: Mutex::new(None) });
+        let wb = Arc::new(TieredCache::new(vec![Arc::new(l1::L1::new()), low.clone()]).with_mode(WriteMode::WriteBack { max_dirty: 8 }));
+
+        wb.insert(b"a", entry(b"1")).unwrap();
+        let w = wb.clone();
+        *low.hook.lock().unwrap() = Some(Box::new(move || w.invalidate(b"a").unwrap()));
+        wb.flush().unwrap();
+        assert!(low.inner.lookup(b"a").is_err(), "invalidate during the flush write must win");
+        assert!(matches!(wb.lookup(b"a"), Err(CacheError::NotFound)));
+        assert_eq!(wb.pending(), 0);
+
+        // rewritten mid-flush: the newer value stays pending and wins next flush
+        wb.insert(b"b", entry(b"old")).unwrap();
+        let w = wb.clone();
+        *low.hook.lock().unwrap() = Some(Box::new(move || w.insert(b"b", entry(b"new")).unwrap()));
+        wb.flush().unwrap();
+        assert_eq!(wb.pending(), 1);
+        wb.flush().unwrap();
+        assert_eq!(low.inner.lookup(b"b").unwrap().value, b"new");
+        assert_eq!(wb.pending(), 0);
+    }
+
+    #[test]
+    fn concurrent_misses_fill_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let tiered = Arc::new(TieredCache::standard(l1::L1::new(), l2::L2::new(), l3::L3::new()));
+        let fills = Arc::new(AtomicUsize::new(0));
+        let workers: Vec<_> = (0..8)
+            .map(|_| {
+                let (tiered, fills) = (tiered.clone(), fills.clone());
+                std::thread::spawn(move || {
+                    tiered.get_or_fill(b"k", || {
+                        fills.fetch_add(1, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(50));
+                        return Ok(entry(b"v"));
+                    })
+                })
+            })
+            .collect();
+        for w in workers {
+            assert_eq!(w.join().unwrap().unwrap().value, b"v");
+        }
+        assert_eq!(fills.load(Ordering::SeqCst), 1);
+        assert_eq!(tiered.fills_in_flight(), 0);
+
+        let sf = Arc::new(SingleFlight::new(Duration::from_millis(10)));
+        let leader = {
+            let sf = sf.clone();
+            std::thread::spawn(move || sf.run(b"slow", || {
+                std::thread::sleep(Duration::from_millis(100));
+                return Ok(entry(b"late"));
+            }))
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(matches!(sf.run(b"slow", || Ok(entry(b"dup"))), Err(CacheError::Io(std::io::ErrorKind::TimedOut))));
+        assert!(leader.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn abandoned_refresh_markers_are_dropped() {
+        let tiered = TieredCache::standard(l1::L1::new(), l2::L2::new(), l3::L3::new()).with_refresh_timeout(Duration::from_millis(20));
+        let old = Instant::now() - Duration::from_secs(15);
+        for k in [b"a", b"b"] {
+            tiered.insert(k, Entry::new_at(b"old".to_vec(), 0, Duration::from_secs(10), old).with_grace(Duration::from_secs(30))).unwrap();
+        }
+        assert!(matches!(tiered.lookup_outcome(b"a"), LookupOutcome::Stale(_)));
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(matches!(tiered.lookup_outcome(b"b"), LookupOutcome::Stale(_)));
+        let refreshing = tiered.refreshing.lock().unwrap();
+        assert_eq!(refreshing.keys().collect::<Vec<_>>(), [b"b"]);
+    }
+
+    #[test]
+    fn stale_while_revalidate_single_refresher() {
+        let tiered = TieredCache::standard(l1::L1::new(), l2::L2::new(), l3::L3::new());
+        let old = Instant::now() - Duration::from_secs(15);
+        let e = Entry::new_at(b"old".to_vec(), 0, Duration::from_secs(10), old).with_grace(Duration::from_secs(30));
+        tiered.insert(b"k", e).unwrap();
+
+        assert!(matches!(tiered.lookup(b"k"), Err(CacheError::Expired)));
+        assert!(matches!(tiered.lookup_outcome(b"k"), LookupOutcome::Stale(ref e) if e.value == b"old"));
+        assert!(matches!(tiered.lookup_outcome(b"k"), LookupOutcome::Revalidating(_)));
+        tiered.release_refresh(b"k");
+        assert!(matches!(tiered.lookup_outcome(b"k"), LookupOutcome::Stale(_)));
+
+        tiered.insert(b"k", entry(b"new")).unwrap();
+        assert!(matches!(tiered.lookup_outcome(b"k"), LookupOutcome::Fresh(ref e) if e.value == b"new"));
+        assert!(tiered.refreshing.lock().unwrap().is_empty());
+
+        let dead = Entry::new_at(b"x".to_vec(), 0, Duration::from_secs(1), old).with_grace(Duration::from_secs(5));
+        tiered.insert(b"d", dead).unwrap();
+        assert!(matches!(tiered.lookup_outcome(b"d"), LookupOutcome::Miss));
+    }
+
+    #[test]
+    fn negative_entries_short_circuit_fills() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let tiered = TieredCache::standard(l1::L1::new(), l2::L2::new(), l3::L3::new());
+        let fills = AtomicUsize::new(0);
+        let fill = || {
+            fills.fetch_add(1, Ordering::SeqCst);
+            return Ok(Entry::negative(Duration::from_secs(5)));
+        };
+        assert!(matches!(tiered.get_or_fill(b"gone", fill), Err(CacheError::Negative)));
+        assert!(matches!(tiered.get_or_fill(b"gone", fill), Err(CacheError::Negative)));
+        assert_eq!(fills.load(Ordering::SeqCst), 1);
+        assert!(matches!(tiered.lookup(b"gone"), Err(CacheError::Negative)));
+        assert!(matches!(tiered.lookup_outcome(b"gone"), LookupOutcome::NegativeHit(ref e) if e.flags & meta::CACHE_L1 != 0));
+
+        // an expired marker is a miss even inside a grace window
+        let old = Instant::now() - Duration::from_secs(10);
+        let expired = Entry { ts: old, ..Entry::negative(Duration::from_secs(5)) }.with_grace(Duration::from_secs(60));
+        tiered.insert(b"gone", expired).unwrap();
+        assert!(matches!(tiered.lookup_outcome(b"gone"), LookupOutcome::Miss));
+        tiered.insert(b"gone", entry(b"back")).unwrap();
+        assert!(matches!(tiered.lookup_outcome(b"gone"), LookupOutcome::Fresh(ref e) if !e.is_negative()));
+    }
+
+    #[test]
+    fn streamed_hits_share_the_stored_body() {
+        let (l1, l3) = (l1::L1::new(), l3::L3::new());
+        let tiered = TieredCache::standard(l1.clone(), l2::L2::new(), l3.clone());
+        let body = Value::from(vec![7u8; 3 * value::DEFAULT_CHUNK + 5]);
+        l3.insert(b"k", Entry::new(body.clone(), 0, Duration::from_secs(60))).unwrap();
+
+        let s = tiered.lookup_stream(b"k", value::DEFAULT_CHUNK).unwrap();
+        assert_eq!((s.flags & meta::CACHE_L3, s.len, s.len()), (meta::CACHE_L3, body.len(), 4));
+        assert_eq!(s.map(|c| c.len()).sum::<usize>(), body.len());
+        // promoted into L1 without copying the body
+        assert!(l1.lookup(b"k").unwrap().value.shares(&body));
+    }
+}
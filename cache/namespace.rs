@@ -0,0 +1,227 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/namespace.rs
+// Role: Final cache namespaces (per-tenant key spaces with quotas)
+// ----------------------------------------------------------------------------
+// A `Namespace` is a tenant plus a route class. `Namespaced` wraps any tier
+// (usually a `TieredCache`) and prefixes every key with an unambiguous
+// encoding of its namespace, so a caller holding one namespace can neither
+// read nor invalidate another namespace's entries. Each namespace tracks its
+// own keys and is held to a `Quota`: when it goes over, its own least
+// recently used keys are dropped, never another tenant's.
+// Quotas bound each tenant's share of the tiers; as long as their sum stays
+// under the tiers' capacity, tier eviction never pushes one tenant out for
+// another.
+// ============================================================================
+
+use crate::{Cache, CacheError, Entry};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Namespace {
+    pub tenant: String,
+    pub route_class: String,
+}
+
+impl Namespace {
+    pub fn new(tenant: &str, route_class: &str) -> Self {
+        return Namespace { tenant: tenant.to_string(), route_class: route_class.to_string() };
+    }
+
+    /// Key as stored in the inner tiers. Lengths are encoded so that no
+    /// (tenant, class, key) triple can collide with another.
+    pub fn qualify(&self, key: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(12 + self.tenant.len() + self.route_class.len() + key.len());
+        out.extend_from_slice(b"ns");
+        out.extend_from_slice(&(self.tenant.len() as u32).to_be_bytes());
+        out.extend_from_slice(self.tenant.as_bytes());
+        out.extend_from_slice(&(self.route_class.len() as u32).to_be_bytes());
+        out.extend_from_slice(self.route_class.as_bytes());
+        out.extend_from_slice(key);
+        return out;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quota {
+    pub max_entries: usize,
+    pub max_bytes: usize, // sum of value lengths
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        return Quota { max_entries: 4096, max_bytes: 64 * 1024 * 1024 };
+    }
+}
+
+#[derive(Default)]
+struct Space {
+    keys: HashMap<Vec<u8>, (u64, usize)>, // key -> (recency tick, value bytes)
+    order: BTreeMap<u64, Vec<u8>>,        // lowest tick is least recently used
+    bytes: usize,
+}
+
+impl Space {
+    fn remove(&mut self, key: &[u8]) -> bool {
+        let Some((tick, bytes)) = self.keys.remove(key) else { return false };
+        self.order.remove(&tick);
+        self.bytes -= bytes;
+        return true;
+    }
+}
+
+struct State {
+    spaces: HashMap<Namespace, Space>,
+    tick: u64,
+}
+
+pub struct Namespaced<C: Cache> {
+    inner: C,
+    default_quota: Quota,
+    quotas: HashMap<Namespace, Quota>,
+    state: Mutex<State>,
+}
+
+impl<C: Cache> Namespaced<C> {
+    pub fn new(inner: C, default_quota: Quota) -> Self {
+        return Namespaced {
+            inner,
+            default_quota,
+            quotas: HashMap::new(),
+            state: Mutex::new(State { spaces: HashMap::new(), tick: 0 }),
+        };
+    }
+
+    /// Override the quota of one namespace.
+    pub fn with_quota(mut self, ns: Namespace, quota: Quota) -> Self {
+        self.quotas.insert(ns, quota);
+        return self;
+    }
+
+    pub fn inner(&self) -> &C {
+        return &self.inner;
+    }
+
+    pub fn quota(&self, ns: &Namespace) -> Quota {
+        return self.quotas.get(ns).copied().unwrap_or(self.default_quota);
+    }
+
+    pub fn lookup(&self, ns: &Namespace, key: &[u8]) -> Result<Entry, CacheError> {
+        let res = self.inner.lookup(&ns.qualify(key));
+        let mut st = self.state.lock().unwrap();
+        let tick = next_tick(&mut st);
+        let Some(space) = st.spaces.get_mut(ns) else { return res };
+        match res {
+            Ok(_) => {
+                if let Some(slot) = space.keys.get_mut(key) {
+                    space.order.remove(&slot.0);
+                    slot.0 = tick;
+                    space.order.insert(tick, key.to_vec());
+                }
+            }
+            // evicted or expired in the tiers: stop counting it
+            Err(_) => {
+                space.remove(key);
+            }
+        }
+        return res;
+    }
+
+    pub fn insert(&self, ns: &Namespace, key: &[u8], entry: Entry) -> Result<(), CacheError> {
+        let quota = self.quota(ns);
+        let bytes = entry.value.len();
+        if quota.max_entries == 0 || bytes > quota.max_bytes {
+            return Err(CacheError::TooLarge);
+        }
+        self.inner.insert(&ns.qualify(key), entry)?;
+        let victims = {
+            let mut st = self.state.lock().unwrap();
+            let tick = next_tick(&mut st);
+            let space = st.spaces.entry(ns.clone()).or_default();
+            space.remove(key);
+            space.keys.insert(key.to_vec(), (tick, bytes));
+            space.order.insert(tick, key.to_vec());
+            space.bytes += bytes;
+            let mut victims = Vec::new();
+            while space.keys.len() > quota.max_entries || space.bytes > quota.max_bytes {
+                let Some((_, old)) = space.order.first_key_value().map(|(t, k)| (*t, k.clone())) else { break };
+                space.remove(&old);
+                victims.push(old);
+            }
+            victims
+        };
+        // outside the lock: tiers may be slow (L3)
+        for k in victims {
+            let _ = self.inner.invalidate(&ns.qualify(&k));
+        }
+        return Ok(());
+    }
+
+    pub fn invalidate(&self, ns: &Namespace, key: &[u8]) -> Result<(), CacheError> {
+        if let Some(space) = self.state.lock().unwrap().spaces.get_mut(ns) {
+            space.remove(key);
+        }
+        return self.inner.invalidate(&ns.qualify(key));
+    }
+
+    /// Drop every entry of one namespace; returns how many keys were tracked.
+    pub fn invalidate_namespace(&self, ns: &Namespace) -> usize {
+        let Some(space) = self.state.lock().unwrap().spaces.remove(ns) else { return 0 };
+        for k in space.keys.keys() {
+            let _ = self.inner.invalidate(&ns.qualify(k));
+        }
+        return space.keys.len();
+    }
+
+    /// (entries, bytes) currently accounted to a namespace.
+    pub fn usage(&self, ns: &Namespace) -> (usize, usize) {
+        return match self.state.lock().unwrap().spaces.get(ns) {
+            Some(s) => (s.keys.len(), s.bytes),
+            None => (0, 0),
+        };
+    }
+}
+
+fn next_tick(st: &mut State) -> u64 {
+    st.tick += 1;
+    return st.tick;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::l3::L3;
+    use std::time::Duration;
+
+    fn entry(v: &[u8]) -> Entry {
+        return Entry::new(v.to_vec(), 0, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn tenants_are_isolated() {
+        let acme = Namespace::new("acme", "api");
+        let globex = Namespace::new("globex", "api");
+        let cache = Namespaced::new(L3::new(), Quota { max_entries: 100, max_bytes: 1024 })
+            .with_quota(acme.clone(), Quota { max_entries: 2, max_bytes: 1024 });
+
+        cache.insert(&globex, b"k", entry(b"g")).unwrap();
+        cache.insert(&acme, b"k", entry(b"a")).unwrap();
+        assert_eq!(cache.lookup(&acme, b"k").unwrap().value, b"a");
+        assert_eq!(cache.lookup(&globex, b"k").unwrap().value, b"g");
+        assert!(cache.lookup(&Namespace::new("acme", "static"), b"k").is_err());
+
+        // acme over quota: its own LRU key goes, globex is untouched
+        cache.insert(&acme, b"k2", entry(b"a2")).unwrap();
+        cache.lookup(&acme, b"k").unwrap();
+        cache.insert(&acme, b"k3", entry(b"a3")).unwrap();
+        assert!(cache.lookup(&acme, b"k2").is_err());
+        assert_eq!(cache.usage(&acme), (2, 3));
+        assert!(matches!(cache.insert(&acme, b"big", entry(&[0u8; 2048])), Err(CacheError::TooLarge)));
+
+        assert_eq!(cache.invalidate_namespace(&acme), 2);
+        assert!(cache.lookup(&acme, b"k").is_err());
+        assert_eq!(cache.lookup(&globex, b"k").unwrap().value, b"g");
+        assert_eq!(cache.usage(&globex), (1, 1));
+    }
+}
@@ -0,0 +1,359 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/resp.rs
+// Role: Final shared L3 backend over the Redis protocol (RESP2)
+// ----------------------------------------------------------------------------
+// Speaks GET / SET .. PX / DEL to Redis or anything RESP-compatible (KeyDB,
+// Dragonfly, Valkey); `clear` runs a SCAN + DEL script over the key prefix. Connections are dialed on demand and kept in a small
+// idle pool; a connection that saw any error is dropped, not reused.
+//
+// Entries are stored as one string value; the server expires it after
+// ttl + grace so stale-while-revalidate keeps working across nodes.
+//
+// Value layout (big endian):
+//   magic "OLE" | version u8 | flags u32 | created_unix_ms u64 | ttl_ms u64
+//   | grace_ms u64 | crc32(value) u32 | summed u8 | value
+// ============================================================================
+
+use crate::checksum::crc32;
+use crate::l3::L3Backend;
+use crate::validators::{decode_block, encode_block, has_validators};
+use crate::{CacheError, Entry};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const MAGIC: &[u8; 3] = b"OLE";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 3 + 1 + 4 + 8 + 8 + 8 + 4 + 1;
+// Bits of the header's last byte.
+const SUMMED: u8 = 0x01; // checksum re-attached on read
+const VALIDATED: u8 = 0x02; // a validator block precedes the value
+const MAX_BULK: usize = 512 * 1024 * 1024; // Redis' own proto-max-bulk-len
+// Deletes every key matching ARGV[1]; returns how many.
+const CLEAR_SCRIPT: &[u8] = b"local n, c = 0, '0' \
+repeat local r = redis.call('SCAN', c, 'MATCH', ARGV[1], 'COUNT', 1000) c = r[1] \
+for _, k in ipairs(r[2]) do n = n + redis.call('DEL', k) end until c == '0' return n";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RespConfig {
+    pub addr: String,             // "host:port"
+    pub prefix: Vec<u8>,          // prepended to every key, e.g. b"olwsx:l3:"
+    pub pool_size: usize,         // idle connections kept
+    pub connect_timeout: Duration,
+    pub io_timeout: Duration,
+}
+
+impl Default for RespConfig {
+    fn default() -> Self {
+        return RespConfig {
+            addr: "127.0.0.1:6379".to_string(),
+            prefix: b"olwsx:l3:".to_vec(),
+            pool_size: 8,
+            connect_timeout: Duration::from_millis(200),
+            io_timeout: Duration::from_millis(500),
+        };
+    }
+}
+
+struct Conn {
+    r: BufReader<TcpStream>,
+    w: TcpStream,
+}
+
+enum Reply {
+    Simple,
+    Int(i64),
+    Bulk(Option<Vec<u8>>),
+}
+
+pub struct RespBackend {
+    cfg: RespConfig,
+    idle: Mutex<Vec<Conn>>,
+}
+
+impl RespBackend {
+    /// No connection is made until the first command.
+    pub fn new(cfg: RespConfig) -> Self {
+        return RespBackend { cfg, idle: Mutex::new(Vec::new()) };
+    }
+
+    pub fn idle_connections(&self) -> usize {
+        return self.idle.lock().unwrap().len();
+    }
+
+    fn command(&self, args: &[&[u8]]) -> Result<Reply, CacheError> {
+        let pooled = self.idle.lock().unwrap().pop();
+        let mut conn = match pooled {
+            Some(c) => c,
+            None => self.dial().map_err(|e| CacheError::Io(e.kind()))?,
+        };
+        let reply = round_trip(&mut conn, args).map_err(|e| CacheError::Io(e.kind()))?;
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.cfg.pool_size {
+            idle.push(conn);
+        }
+        return Ok(reply);
+    }
+
+    fn dial(&self) -> io::Result<Conn> {
+        let mut last = io::Error::new(ErrorKind::AddrNotAvailable, "no address");
+        for addr in self.cfg.addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.cfg.connect_timeout) {
+                Ok(s) => {
+                    s.set_nodelay(true)?;
+                    s.set_read_timeout(Some(self.cfg.io_timeout))?;
+                    s.set_write_timeout(Some(self.cfg.io_timeout))?;
+                    return Ok(Conn { r: BufReader::new(s.try_clone()?), w: s });
+                }
+                Err(e) => last = e,
+            }
+        }
+        return Err(last);
+    }
+
+    fn key(&self, key: &[u8]) -> Vec<u8> {
+        let mut k = Vec::with_capacity(self.cfg.prefix.len() + key.len());
+        k.extend_from_slice(&self.cfg.prefix);
+        k.extend_from_slice(key);
+        return k;
+    }
+}
+
+impl L3Backend for RespBackend {
+    fn get(&self, key: &[u8]) -> Result<Option<Entry>, CacheError> {
+        return match self.command(&[b"GET", &self.key(key)])? {
+            Reply::Bulk(None) => Ok(None),
+            Reply::Bulk(Some(v)) => decode(&v).map(Some).ok_or(CacheError::Corrupt),
+            _ => Err(CacheError::Io(ErrorKind::InvalidData)),
+        };
+    }
+
+    fn put(&self, key: &[u8], entry: &Entry) -> Result<(), CacheError> {
+        // PX 0 is rejected by the server; keep at least a millisecond
+        let px = (entry.ttl.saturating_add(entry.grace).as_millis() as u64).max(1).to_string();
+        return match self.command(&[b"SET", &self.key(key), &encode(entry), b"PX", px.as_bytes()])? {
+            Reply::Simple => Ok(()),
+            _ => Err(CacheError::Io(ErrorKind::InvalidData)),
+        };
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<bool, CacheError> {
+        return match self.command(&[b"DEL", &self.key(key)])? {
+            Reply::Int(n) => Ok(n > 0),
+            _ => Err(CacheError::Io(ErrorKind::InvalidData)),
+        };
+    }
+
+    fn clear(&self) -> Result<(), CacheError> {
+        // the prefix is matched literally: escape glob metacharacters
+        let mut pattern = Vec::with_capacity(self.cfg.prefix.len() + 1);
+        for &c in self.cfg.prefix.iter() {
+            if matches!(c, b'*' | b'?' | b'[' | b']' | b'\\') {
+                pattern.push(b'\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push(b'*');
+        return match self.command(&[b"EVAL", CLEAR_SCRIPT, b"0", &pattern])? {
+            Reply::Int(_) => Ok(()),
+            _ => Err(CacheError::Io(ErrorKind::InvalidData)),
+        };
+    }
+}
+
+fn round_trip(c: &mut Conn, args: &[&[u8]]) -> io::Result<Reply> {
+    let mut out = Vec::with_capacity(16 + args.iter().map(|a| a.len() + 16).sum::<usize>());
+    out.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+    for a in args.iter() {
+        out.extend_from_slice(format!("${}\r\n", a.len()).as_bytes());
+        out.extend_from_slice(a);
+        out.extend_from_slice(b"\r\n");
+    }
+    c.w.write_all(&out)?;
+    return read_reply(&mut c.r);
+}
+
+fn read_reply(r: &mut impl BufRead) -> io::Result<Reply> {
+    let line = read_line(r)?;
+    let (kind, rest) = line.split_first().ok_or_else(|| invalid("empty reply"))?;
+    let text = std::str::from_utf8(rest).map_err(|_| invalid("non-utf8 reply line"))?;
+    return match kind {
+        b'+' => Ok(Reply::Simple),
+        b'-' => Err(io::Error::other(format!("server error: {}", text))),
+        b':' => text.parse().map(Reply::Int).map_err(|_| invalid("bad integer")),
+        b'$' => {
+            let n: i64 = text.parse().map_err(|_| invalid("bad bulk length"))?;
+            if n < 0 {
+                return Ok(Reply::Bulk(None));
+            }
+            if n as usize > MAX_BULK {
+                return Err(invalid("bulk too large"));
+            }
+            let mut buf = vec![0u8; n as usize + 2];
+            r.read_exact(&mut buf)?;
+            if !buf.ends_with(b"\r\n") {
+                return Err(invalid("bulk not terminated"));
+            }
+            buf.truncate(n as usize);
+            Ok(Reply::Bulk(Some(buf)))
+        }
+        _ => Err(invalid("unexpected reply type")),
+    };
+}
+
+fn read_line(r: &mut impl BufRead) -> io::Result<Vec<u8>> {
+    let mut line = Vec::new();
+    r.take(64 * 1024).read_until(b'\n', &mut line)?;
+    if !line.ends_with(b"\r\n") {
+        return Err(io::Error::new(ErrorKind::UnexpectedEof, "truncated reply"));
+    }
+    line.truncate(line.len() - 2);
+    return Ok(line);
+}
+
+fn invalid(msg: &str) -> io::Error {
+    return io::Error::new(ErrorKind::InvalidData, msg.to_string());
+}
+
+fn encode(e: &Entry) -> Vec<u8> {
+    // Instants are process-local; persist the wall-clock creation time instead.
+    let created = unix_ms().saturating_sub(e.ts.elapsed().as_millis() as u64);
+    let mut body = Vec::with_capacity(e.value.len());
+    let mut bits = if e.checksum.is_some() { SUMMED } else { 0 };
+    if has_validators(e) {
+        bits |= VALIDATED;
+        encode_block(e, &mut body);
+    }
+    body.extend_from_slice(&e.value);
+    let mut out = Vec::with_capacity(HEADER_LEN + body.len());
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&e.flags.to_be_bytes());
+    out.extend_from_slice(&created.to_be_bytes());
+    out.extend_from_slice(&(e.ttl.as_millis() as u64).to_be_bytes());
+    out.extend_from_slice(&(e.grace.as_millis() as u64).to_be_bytes());
+    out.extend_from_slice(&crc32(&body).to_be_bytes());
+    out.push(bits);
+    out.extend_from_slice(&body);
+    return out;
+}
+
+fn decode(b: &[u8]) -> Option<Entry> {
+    if b.len() < HEADER_LEN || &b[0..3] != MAGIC || b[3] != VERSION {
+        return None;
+    }
+    let flags = u32::from_be_bytes(b[4..8].try_into().ok()?);
+    let created = u64::from_be_bytes(b[8..16].try_into().ok()?);
+    let ttl = Duration::from_millis(u64::from_be_bytes(b[16..24].try_into().ok()?));
+    let grace = Duration::from_millis(u64::from_be_bytes(b[24..32].try_into().ok()?));
+    let crc = u32::from_be_bytes(b[32..36].try_into().ok()?);
+    let mut value = &b[HEADER_LEN..];
+    if crc32(value) != crc {
+        return None;
+    }
+    let (mut etag, mut last_modified) = (None, None);
+    if b[36] & VALIDATED != 0 {
+        let used;
+        (etag, last_modified, used) = decode_block(value)?;
+        value = &value[used..];
+    }
+    let age = Duration::from_millis(unix_ms().saturating_sub(created));
+    let ts = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+    let mut entry = Entry { etag, last_modified, ..Entry::new_at(value.to_vec(), flags, ttl, ts).with_grace(grace) };
+    if b[36] & SUMMED != 0 {
+        entry = entry.with_checksum();
+    }
+    return Some(entry);
+}
+
+fn unix_ms() -> u64 {
+    return SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::l3::{L3, L3Config};
+    use crate::Cache;
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Minimal RESP server: GET/SET/DEL on a map, PX ignored.
+    fn fake_redis() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let (store, count) = (Arc::new(Mutex::new(HashMap::<Vec<u8>, Vec<u8>>::new())), Arc::clone(&accepted));
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { return };
+                count.fetch_add(1, Ordering::SeqCst);
+                let store = Arc::clone(&store);
+                std::thread::spawn(move || {
+                    let mut r = BufReader::new(stream.try_clone().unwrap());
+                    let mut w = stream;
+                    while let Ok(head) = read_line(&mut r) {
+                        let n: usize = std::str::from_utf8(&head[1..]).unwrap().parse().unwrap();
+                        let mut args = Vec::new();
+                        for _ in 0..n {
+                            let Ok(Reply::Bulk(Some(a))) = read_reply(&mut r) else { return };
+                            args.push(a);
+                        }
+                        let mut map = store.lock().unwrap();
+                        let out = match args[0].as_slice() {
+                            b"GET" => match map.get(&args[1]) {
+                                Some(v) => [format!("${}\r\n", v.len()).into_bytes(), v.clone(), b"\r\n".to_vec()].concat(),
+                                None => b"$-1\r\n".to_vec(),
+                            },
+                            b"SET" => {
+                                map.insert(args[1].clone(), args[2].clone());
+                                b"+OK\r\n".to_vec()
+                            }
+                            b"DEL" => format!(":{}\r\n", map.remove(&args[1]).is_some() as u8).into_bytes(),
+                            _ => b"-ERR unknown command\r\n".to_vec(),
+                        };
+                        if w.write_all(&out).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        return (addr, accepted);
+    }
+
+    #[test]
+    fn roundtrip_through_pool() {
+        let (addr, accepted) = fake_redis();
+        let b = RespBackend::new(RespConfig { addr, ..RespConfig::default() });
+        let e = Entry::new(b"body".to_vec(), 0x4, Duration::from_secs(60)).with_grace(Duration::from_secs(5)).with_checksum();
+        let e = e.with_etag("W/\"1\"").with_last_modified(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+        b.put(b"k", &e).unwrap();
+        let got = b.get(b"k").unwrap().unwrap();
+        assert_eq!((&got.value[..], got.flags, got.ttl, got.grace), (&b"body"[..], 0x4, e.ttl, e.grace));
+        assert_eq!((&got.etag, got.last_modified), (&e.etag, e.last_modified));
+        assert!(got.checksum.is_some() && got.verify());
+        assert!(b.remove(b"k").unwrap());
+        assert!(!b.remove(b"k").unwrap());
+        assert!(b.get(b"k").unwrap().is_none());
+        assert_eq!(accepted.load(Ordering::SeqCst), 1, "one pooled connection");
+        assert_eq!(b.idle_connections(), 1);
+    }
+
+    #[test]
+    fn l3_degrades_to_local_mode() {
+        // nothing listens on a port we just released
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let backend = Arc::new(RespBackend::new(RespConfig { addr, ..RespConfig::default() }));
+        let l3 = L3::with_backend(L3Config::default(), backend);
+        l3.insert(b"k", Entry::new(b"v".to_vec(), 0, Duration::from_secs(60))).unwrap();
+        assert!(l3.degraded());
+        assert_eq!(l3.lookup(b"k").unwrap().value, b"v");
+        l3.invalidate(b"k").unwrap();
+        assert!(matches!(l3.lookup(b"k"), Err(CacheError::NotFound)));
+    }
+}
@@ -0,0 +1,251 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/spill.rs
+// Role: Final large-object spill tier (values on disk, metadata in RAM)
+// ----------------------------------------------------------------------------
+// Each value lives in its own file; only key -> (file, size, ttl) is
+// kept in memory. Files carry a CRC-32 over header and payload so torn writes
+// and bit rot are detected. On open, the directory is reconciled: valid files
+// are re-indexed, our partial (.tmp), corrupt and expired files are removed,
+// and files not named like ours are left alone. File reads and writes happen
+// outside the index lock.
+//
+// File layout (big endian):
+//   magic "OLSX" | version u8 | crc32 u32 | flags u32 | created_unix_ms u64
+//   | ttl_ms u64 | key_len u32 | key | value
+// The crc32 covers everything after itself.
+// ============================================================================
+
+use crate::checksum::crc32;
+use crate::{Cache, CacheError, Entry};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const MAGIC: &[u8; 4] = b"OLSX";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 4 + 1 + 4 + 4 + 8 + 8 + 4;
+const EXT: &str = "olc";
+
+#[derive(Clone)]
+pub struct SpillStore {
+    dir: PathBuf,
+    inner: Arc<Mutex<State>>,
+}
+
+#[derive(Clone)]
+struct Meta {
+    file: PathBuf,
+    size: u64,
+    ts: Instant,
+    ttl: Duration,
+}
+
+struct State {
+    index: HashMap<Vec<u8>, Meta>,
+    next_id: u64,
+}
+
+impl SpillStore {
+    /// Open (or create) a spill directory and reconcile its contents.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut st = State { index: HashMap::new(), next_id: 0 };
+        for item in fs::read_dir(&dir)? {
+            let path = item?.path();
+            let Some((id, is_data)) = own_name(&path) else { continue };
+            st.next_id = st.next_id.max(id + 1);
+            let decoded = if is_data { fs::read(&path).ok().and_then(|b| decode(&b)) } else { None };
+            match decoded {
+                Some((key, entry)) if !entry.is_expired() => {
+                    let size = entry.value.len() as u64;
+                    st.index.insert(key, Meta { file: path, size, ts: entry.ts, ttl: entry.ttl });
+                }
+                // orphaned temp file, corrupt or expired payload
+                _ => {
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+        return Ok(SpillStore { dir, inner: Arc::new(Mutex::new(st)) });
+    }
+
+    pub fn len(&self) -> usize {
+        return self.inner.lock().unwrap().index.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    /// Total payload bytes on disk (headers and keys excluded).
+    pub fn bytes(&self) -> u64 {
+        return self.inner.lock().unwrap().index.values().map(|m| m.size).sum();
+    }
+
+    // Drops `key` if it still points at `file`, then deletes the file.
+    fn forget(&self, key: &[u8], file: &Path) {
+        let mut st = self.inner.lock().unwrap();
+        if st.index.get(key).is_some_and(|m| m.file == file) {
+            st.index.remove(key);
+            drop(st);
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+impl Cache for SpillStore {
+    fn lookup(&self, key: &[u8]) -> Result<Entry, CacheError> {
+        let meta = self.inner.lock().unwrap().index.get(key).cloned().ok_or(CacheError::NotFound)?;
+        if meta.ts.elapsed() > meta.ttl {
+            self.forget(key, &meta.file);
+            return Err(CacheError::Expired);
+        }
+        let decoded = fs::read(&meta.file).ok().and_then(|b| decode(&b));
+        match decoded {
+            Some((k, mut entry)) if k == key => {
+                // RAM metadata is authoritative for the monotonic timestamp
+                entry.ts = meta.ts;
+                return Ok(entry);
+            }
+            _ => {
+                // unreadable or corrupt on disk (or replaced meanwhile): report a miss
+                self.forget(key, &meta.file);
+                return Err(CacheError::NotFound);
+            }
+        }
+    }
+
+    fn insert(&self, key: &[u8], entry: Entry) -> Result<(), CacheError> {
+        let id = {
+            let mut st = self.inner.lock().unwrap();
+            st.next_id += 1;
+            st.next_id - 1
+        };
+        let file = self.dir.join(format!("{:016x}.{}", id, EXT));
+        let tmp = file.with_extension("tmp");
+        let bytes = encode(key, &entry);
+        let written = fs::write(&tmp, &bytes).and_then(|_| fs::rename(&tmp, &file));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&tmp);
+            return Err(CacheError::Io(e.kind()));
+        }
+        let meta = Meta { file, size: entry.value.len() as u64, ts: entry.ts, ttl: entry.ttl };
+        let old = self.inner.lock().unwrap().index.insert(key.to_vec(), meta);
+        if let Some(old) = old {
+            let _ = fs::remove_file(&old.file);
+        }
+        return Ok(());
+    }
+
+    fn invalidate(&self, key: &[u8]) -> Result<(), CacheError> {
+        let removed = self.inner.lock().unwrap().index.remove(key);
+        match removed {
+            Some(m) => {
+                let _ = fs::remove_file(&m.file);
+                return Ok(());
+            }
+            None => return Err(CacheError::NotFound),
+        }
+    }
+}
+
+// "<16 hex digits>.olc" (data) or ".tmp" (partial write): (id, is_data).
+fn own_name(path: &Path) -> Option<(u64, bool)> {
+    let stem = path.file_stem()?.to_str()?;
+    if stem.len() != 16 {
+        return None;
+    }
+    let id = u64::from_str_radix(stem, 16).ok()?;
+    match path.extension()?.to_str()? {
+        EXT => return Some((id, true)),
+        "tmp" => return Some((id, false)),
+        _ => return None,
+    }
+}
+
+fn encode(key: &[u8], e: &Entry) -> Vec<u8> {
+    // Instants are process-local; persist the wall-clock creation time instead.
+    let created = unix_ms().saturating_sub(e.ts.elapsed().as_millis() as u64);
+    let mut body = Vec::with_capacity(HEADER_LEN + key.len() + e.value.len());
+    body.extend_from_slice(&e.flags.to_be_bytes());
+    body.extend_from_slice(&created.to_be_bytes());
+    body.extend_from_slice(&(e.ttl.as_millis() as u64).to_be_bytes());
+    body.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    body.extend_from_slice(key);
+    body.extend_from_slice(&e.value);
+
+    let mut out = Vec::with_capacity(9 + body.len());
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.extend_from_slice(&crc32(&body).to_be_bytes());
+    out.extend_from_slice(&body);
+    return out;
+}
+
+fn decode(b: &[u8]) -> Option<(Vec<u8>, Entry)> {
+    if b.len() < HEADER_LEN || &b[0..4] != MAGIC || b[4] != VERSION {
+        return None;
+    }
+    let crc = u32::from_be_bytes(b[5..9].try_into().ok()?);
+    let body = &b[9..];
+    if crc32(body) != crc {
+        return None;
+    }
+    let flags = u32::from_be_bytes(body[0..4].try_into().ok()?);
+    let created = u64::from_be_bytes(body[4..12].try_into().ok()?);
+    let ttl_ms = u64::from_be_bytes(body[12..20].try_into().ok()?);
+    let key_len = u32::from_be_bytes(body[20..24].try_into().ok()?) as usize;
+    let key = body.get(24..24 + key_len)?.to_vec();
+    let value = body[24 + key_len..].to_vec();
+    let age = Duration::from_millis(unix_ms().saturating_sub(created));
+    let ts = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+    return Some((key, Entry::new_at(value, flags, Duration::from_millis(ttl_ms), ts)));
+}
+
+fn unix_ms() -> u64 {
+    return SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tmpdir(name: &str) -> PathBuf {
+        let d = std::env::temp_dir().join(format!("olwsx-spill-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&d);
+        return d;
+    }
+
+    #[test]
+    fn roundtrip_and_reconcile() {
+        let dir = tmpdir("reconcile");
+        let s = SpillStore::open(&dir).unwrap();
+        let big = vec![7u8; 3 * 1024 * 1024];
+        s.insert(b"big", Entry::new(big.clone(), 0x2, Duration::from_secs(60))).unwrap();
+        s.insert(b"other", Entry::new(b"x".to_vec(), 0, Duration::from_secs(60))).unwrap();
+        assert_eq!(s.lookup(b"big").unwrap().value, big);
+
+        // orphan temp file and a corrupted data file; foreign files are kept
+        fs::write(dir.join("00000000000000ff.tmp"), b"partial").unwrap();
+        fs::write(dir.join("README"), b"operator notes").unwrap();
+        fs::write(dir.join("backup.olc"), b"not ours").unwrap();
+        let other = s.inner.lock().unwrap().index.get(&b"other"[..]).unwrap().file.clone();
+        let mut raw = fs::read(&other).unwrap();
+        let last = raw.len() - 1;
+        raw[last] ^= 0xFF;
+        fs::write(&other, raw).unwrap();
+
+        let reopened = SpillStore::open(&dir).unwrap();
+        assert_eq!(reopened.len(), 1);
+        assert_eq!(reopened.lookup(b"big").unwrap().flags, 0x2);
+        assert!(matches!(reopened.lookup(b"other"), Err(CacheError::NotFound)));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        assert_eq!(fs::read(dir.join("README")).unwrap(), b"operator notes");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,126 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/stats.rs
+// Role: Final per-tier cache statistics (lock-free counters, plain snapshots)
+// ----------------------------------------------------------------------------
+// Each tier owns a `StatsCell` and bumps it on every lookup and eviction;
+// `cache_stats()` on the tier returns a `CacheStats` snapshot. Counters are
+// cumulative since the tier was built; exporters take differences
+// (observability/metrics.rs `cache_tier`).
+// ============================================================================
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,    // key not present
+    pub expired: u64,   // present but past ttl + grace (counted apart from misses)
+    pub evictions: u64, // entries dropped to stay within capacity
+    pub bytes: u64,     // current footprint
+    pub items: u64,     // current entries
+}
+
+impl CacheStats {
+    /// Share of lookups served, in 0.0..=1.0 (0 without lookups).
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses + self.expired;
+        if lookups == 0 {
+            return 0.0;
+        }
+        return self.hits as f64 / lookups as f64;
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct StatsCell {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    expired: AtomicU64,
+    evictions: AtomicU64,
+    bytes: AtomicU64,
+    items: AtomicU64,
+}
+
+impl StatsCell {
+    pub(crate) fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn expired(&self) {
+        self.expired.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn evicted(&self, n: usize) {
+        self.evictions.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    // Tiers call this with their lock held, after each change.
+    pub(crate) fn usage(&self, items: usize, bytes: usize) {
+        self.items.store(items as u64, Ordering::Relaxed);
+        self.bytes.store(bytes as u64, Ordering::Relaxed);
+    }
+
+    // For tiers that track usage by difference; negative deltas wrap back.
+    pub(crate) fn adjust(&self, items: i64, bytes: i64) {
+        self.items.fetch_add(items as u64, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> CacheStats {
+        return CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            expired: self.expired.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            items: self.items.load(Ordering::Relaxed),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use crate::l1::L1;
+    use crate::l2::{L2, L2Config};
+    use crate::l3::{L3, L3Config};
+    use crate::{footprint, Cache, Entry};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn tiers_count_lookups_and_evictions() {
+        let clock = Arc::new(MockClock::new());
+        let e = |ttl: u64| Entry::new_at(b"value".to_vec(), 0, Duration::from_secs(ttl), clock.now());
+        let per = footprint(b"k0", &e(1)) as u64;
+
+        let l1 = L1::with_capacity(2).with_clock(clock.clone());
+        let l2 = L2::with_config(L2Config { max_items: 2, ..L2Config::default() }).with_clock(clock.clone());
+        let l3 = L3::with_config(L3Config { max_entries: 2, ..L3Config::default() }).with_clock(clock.clone());
+        let tiers: [&dyn Cache; 3] = [&l1, &l2, &l3];
+        for t in tiers {
+            t.insert(b"k0", e(10)).unwrap();
+            t.insert(b"k1", e(60)).unwrap();
+            t.insert(b"k2", e(60)).unwrap(); // evicts k0
+            assert!(t.lookup(b"k2").is_ok());
+            assert!(t.lookup(b"k0").is_err());
+        }
+        clock.advance(Duration::from_secs(61));
+        for t in tiers {
+            assert!(t.lookup(b"k1").is_err());
+        }
+
+        let want = CacheStats { hits: 1, misses: 1, expired: 1, evictions: 1, bytes: per, items: 1 };
+        assert_eq!(l1.cache_stats(), want);
+        assert_eq!(l2.cache_stats(), want);
+        assert_eq!(l3.cache_stats(), want);
+        assert!((want.hit_ratio() - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(CacheStats::default().hit_ratio(), 0.0);
+    }
+}
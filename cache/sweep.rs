@@ -0,0 +1,141 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/sweep.rs
+// Role: Final expiry sweeping (bounded passes, optional background thread)
+// ----------------------------------------------------------------------------
+// Tiers drop dead entries lazily on lookup, so a key that is never read again
+// stays resident until capacity pushes it out. `Sweep` lets a tier drop them
+// proactively: `sweep(budget)` examines a bounded slice of the tier, resuming
+// where the previous call stopped, and `purge_expired()` does a full pass.
+// Embedders without threads call either from their own loop; `Sweeper` runs
+// `sweep` on a set of tiers every `SweepConfig::interval`.
+// ============================================================================
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Tiers that can drop expired entries without waiting for a lookup. An
+/// entry counts as expired once it is past `ttl + grace`, as on lookup.
+pub trait Sweep: Send + Sync {
+    /// Examines at most `budget` entries, continuing after the ones examined
+    /// by the previous call and wrapping around, and drops the dead ones.
+    /// Returns how many were dropped.
+    fn sweep(&self, budget: usize) -> usize;
+
+    /// Drops every dead entry in one pass; returns how many were dropped.
+    fn purge_expired(&self) -> usize {
+        return self.sweep(usize::MAX);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SweepConfig {
+    pub interval: Duration, // pause between passes
+    pub batch: usize,       // entries examined per tier per pass
+}
+
+impl Default for SweepConfig {
+    fn default() -> Self {
+        return SweepConfig { interval: Duration::from_secs(30), batch: 1024 };
+    }
+}
+
+/// Background sweeping thread; stops when dropped.
+pub struct Sweeper {
+    stop: Arc<AtomicBool>,
+    swept: Arc<AtomicU64>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Sweeper {
+    pub fn start(tiers: Vec<Arc<dyn Sweep>>, cfg: SweepConfig) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let swept = Arc::new(AtomicU64::new(0));
+        let (flag, count) = (Arc::clone(&stop), Arc::clone(&swept));
+        let thread = std::thread::spawn(move || {
+            while !flag.load(Ordering::Acquire) {
+                for t in tiers.iter() {
+                    count.fetch_add(t.sweep(cfg.batch) as u64, Ordering::Relaxed);
+                }
+                std::thread::park_timeout(cfg.interval);
+            }
+        });
+        return Sweeper { stop, swept, thread: Some(thread) };
+    }
+
+    /// Entries dropped by this sweeper so far.
+    pub fn swept(&self) -> u64 {
+        return self.swept.load(Ordering::Relaxed);
+    }
+}
+
+impl Drop for Sweeper {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(t) = self.thread.take() {
+            t.thread().unpark();
+            let _ = t.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use crate::l1::L1;
+    use crate::l2::L2;
+    use crate::l3::L3;
+    use crate::{Cache, Entry};
+    use std::time::Instant;
+
+    fn fill(c: &dyn Cache, clock: &MockClock) {
+        for i in 0..10u8 {
+            let ttl = Duration::from_secs(if i % 2 == 0 { 10 } else { 600 });
+            c.insert(&[i], Entry::new_at(b"v".to_vec(), 0, ttl, clock.now())).unwrap();
+        }
+    }
+
+    #[test]
+    fn bounded_passes_cover_every_tier() {
+        let clock = Arc::new(MockClock::new());
+        let l1 = L1::new().with_clock(clock.clone());
+        let l2 = L2::new().with_clock(clock.clone());
+        let l3 = L3::new().with_clock(clock.clone());
+        let tiers: [(&dyn Cache, &dyn Sweep); 3] = [(&l1, &l1), (&l2, &l2), (&l3, &l3)];
+        for (c, s) in tiers {
+            fill(c, &clock);
+            assert_eq!(s.purge_expired(), 0);
+        }
+        clock.advance(Duration::from_secs(11));
+        for (c, s) in tiers {
+            // three entries per call: all five dead ones within four calls
+            let swept: Vec<usize> = (0..4).map(|_| s.sweep(3)).collect();
+            assert!(swept.iter().all(|n| *n <= 3));
+            assert_eq!(swept.iter().sum::<usize>(), 5);
+            assert_eq!(s.purge_expired(), 0);
+            for i in 0..10u8 {
+                assert_eq!(c.lookup(&[i]).is_ok(), i % 2 == 1);
+            }
+        }
+        assert_eq!((l1.cache_stats().items, l2.cache_stats().items, l3.cache_stats().items), (5, 5, 5));
+        assert_eq!(l1.cache_stats().expired, 0, "sweeping is not a lookup");
+    }
+
+    #[test]
+    fn sweeper_runs_in_the_background() {
+        let clock = Arc::new(MockClock::new());
+        let l1 = L1::new().with_clock(clock.clone());
+        fill(&l1, &clock);
+        clock.advance(Duration::from_secs(11));
+        let sweeper = Sweeper::start(vec![Arc::new(l1.clone())], SweepConfig { interval: Duration::from_millis(5), batch: 2 });
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while sweeper.swept() < 5 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        drop(sweeper);
+        assert_eq!(l1.stats().items, 5);
+    }
+}
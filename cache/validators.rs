@@ -0,0 +1,194 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/validators.rs
+// Role: Final conditional request evaluation (ETag, Last-Modified, HTTP dates)
+// ----------------------------------------------------------------------------
+// Entries may carry the validators the origin sent (`Entry::with_etag`,
+// `Entry::with_last_modified`). `evaluate` checks a request's If-None-Match
+// and If-Modified-Since against them per RFC 9110 section 13: If-None-Match
+// wins when present (weak comparison, `*` matches any entry), and an
+// unparsable If-Modified-Since is ignored.
+//
+// The L3 stores persist validators as a block ahead of the value:
+//   etag_len u16 | etag | last_modified_unix_s u64 (0 = none)
+// ============================================================================
+
+use crate::Entry;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What a conditional GET/HEAD should get for a cached entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conditional {
+    /// Validators match: answer 304 with the entry's validators, no body.
+    NotModified,
+    /// No condition, or it failed: serve the entry in full.
+    Full,
+}
+
+/// Evaluates the raw If-None-Match / If-Modified-Since header values.
+pub fn evaluate(entry: &Entry, if_none_match: Option<&str>, if_modified_since: Option<&str>) -> Conditional {
+    if let Some(inm) = if_none_match {
+        let hit = match entry.etag.as_deref() {
+            _ if inm.trim() == "*" => true,
+            Some(tag) => inm.split(',').any(|t| weak_eq(t.trim(), tag)),
+            None => false,
+        };
+        return if hit { Conditional::NotModified } else { Conditional::Full };
+    }
+    if let (Some(ims), Some(modified)) = (if_modified_since.and_then(parse_http_date), entry.last_modified)
+        && modified <= ims
+    {
+        return Conditional::NotModified;
+    }
+    return Conditional::Full;
+}
+
+// Weak comparison: opaque tags equal, ignoring any W/ prefix.
+fn weak_eq(a: &str, b: &str) -> bool {
+    return !a.is_empty() && opaque(a) == opaque(b);
+}
+
+fn opaque(tag: &str) -> &str {
+    return tag.strip_prefix("W/").unwrap_or(tag);
+}
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// IMF-fixdate, e.g. "Sun, 06 Nov 1994 08:49:37 GMT" (for Last-Modified).
+pub fn format_http_date(t: SystemTime) -> String {
+    let secs = t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let days = (secs / 86_400) as i64;
+    let (y, m, d) = civil_from_days(days);
+    let rem = secs % 86_400;
+    return format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[((days + 4) % 7) as usize],
+        d,
+        MONTHS[(m - 1) as usize],
+        y,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    );
+}
+
+/// Parses the three HTTP-date forms: IMF-fixdate, obsolete RFC 850
+/// ("Sunday, 06-Nov-94 08:49:37 GMT") and asctime ("Sun Nov  6 08:49:37 1994").
+pub fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let s = s.trim();
+    let (day, month, year, time) = match s.split_once(',') {
+        Some((_, rest)) => {
+            let parts: Vec<&str> = rest.split_whitespace().collect();
+            match parts.as_slice() {
+                [d, m, y, t, "GMT"] => (*d, *m, y.parse::<i64>().ok()?, *t),
+                [dmy, t, "GMT"] => {
+                    let mut it = dmy.split('-');
+                    let (d, m, yy) = (it.next()?, it.next()?, it.next()?);
+                    let yy = yy.parse::<i64>().ok()?;
+                    if yy >= 100 {
+                        return None;
+                    }
+                    (d, m, if yy < 70 { 2000 + yy } else { 1900 + yy }, *t)
+                }
+                _ => return None,
+            }
+        }
+        None => match s.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            [_, m, d, t, y] => (*d, *m, y.parse::<i64>().ok()?, *t),
+            _ => return None,
+        },
+    };
+    let day: i64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let mut hms = time.split(':').map(|p| p.parse::<u64>().ok());
+    let (h, mi, se) = (hms.next()??, hms.next()??, hms.next()??);
+    if hms.next().is_some() || !(1..=31).contains(&day) || year < 1970 || h > 23 || mi > 59 || se > 60 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    let secs = days as u64 * 86_400 + h * 3600 + mi * 60 + se.min(59);
+    return Some(UNIX_EPOCH + Duration::from_secs(secs));
+}
+
+// Proleptic Gregorian conversions (H. Hinnant's algorithms).
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    return era * 146_097 + doe - 719_468;
+}
+
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    return (yoe + era * 400 + i64::from(m <= 2), m, d);
+}
+
+pub(crate) fn has_validators(e: &Entry) -> bool {
+    return e.etag.is_some() || e.last_modified.is_some();
+}
+
+pub(crate) fn encode_block(e: &Entry, out: &mut Vec<u8>) {
+    let tag = e.etag.as_deref().unwrap_or("").as_bytes();
+    let tag = &tag[..tag.len().min(u16::MAX as usize)];
+    out.extend_from_slice(&(tag.len() as u16).to_be_bytes());
+    out.extend_from_slice(tag);
+    let modified = e.last_modified.and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map(|d| d.as_secs()).unwrap_or(0);
+    out.extend_from_slice(&modified.to_be_bytes());
+}
+
+// Validators and the bytes the block took at the start of `b`.
+pub(crate) fn decode_block(b: &[u8]) -> Option<(Option<String>, Option<SystemTime>, usize)> {
+    let tag_len = u16::from_be_bytes(b.get(0..2)?.try_into().ok()?) as usize;
+    let tag = std::str::from_utf8(b.get(2..2 + tag_len)?).ok()?;
+    let modified = u64::from_be_bytes(b.get(2 + tag_len..10 + tag_len)?.try_into().ok()?);
+    let tag = if tag.is_empty() { None } else { Some(tag.to_string()) };
+    let modified = if modified == 0 { None } else { Some(UNIX_EPOCH + Duration::from_secs(modified)) };
+    return Some((tag, modified, 10 + tag_len));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_dates_round_trip_in_all_forms() {
+        let t = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!(format_http_date(t), "Sun, 06 Nov 1994 08:49:37 GMT");
+        for s in ["Sun, 06 Nov 1994 08:49:37 GMT", "Sunday, 06-Nov-94 08:49:37 GMT", "Sun Nov  6 08:49:37 1994"] {
+            assert_eq!(parse_http_date(s), Some(t), "{}", s);
+        }
+        let leap = UNIX_EPOCH + Duration::from_secs(951_782_400); // 2000-02-29
+        assert_eq!(parse_http_date(&format_http_date(leap)), Some(leap));
+        for bad in ["", "Sun, 06 Nov 1994 08:49:37 PST", "Sun, 32 Nov 1994 08:49:37 GMT", "yesterday"] {
+            assert_eq!(parse_http_date(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn conditionals_follow_rfc_precedence() {
+        let modified = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        let e = Entry::new(b"body".to_vec(), 0, Duration::from_secs(60)).with_etag("\"v2\"").with_last_modified(modified);
+        assert_eq!(evaluate(&e, Some("\"v1\", W/\"v2\""), None), Conditional::NotModified);
+        assert_eq!(evaluate(&e, Some("*"), None), Conditional::NotModified);
+        // If-None-Match present: If-Modified-Since is not consulted
+        assert_eq!(evaluate(&e, Some("\"v1\""), Some("Sun, 06 Nov 1994 08:49:37 GMT")), Conditional::Full);
+        assert_eq!(evaluate(&e, None, Some("Sun, 06 Nov 1994 08:49:37 GMT")), Conditional::NotModified);
+        assert_eq!(evaluate(&e, None, Some("Sat, 05 Nov 1994 08:49:37 GMT")), Conditional::Full);
+        assert_eq!(evaluate(&e, None, Some("garbage")), Conditional::Full);
+        assert_eq!(evaluate(&Entry::new(b"x".to_vec(), 0, Duration::from_secs(60)), Some("\"v2\""), None), Conditional::Full);
+
+        let mut block = Vec::new();
+        encode_block(&e, &mut block);
+        assert_eq!(decode_block(&block), Some((Some("\"v2\"".to_string()), Some(modified), block.len())));
+    }
+}
@@ -0,0 +1,225 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/value.rs
+// Role: Final shared value storage (reference-counted bodies, chunked reads)
+// ----------------------------------------------------------------------------
+// `Value` holds an entry body as `Arc<[u8]>`: cloning an `Entry`, promoting it
+// between tiers or handing it to a response never copies the bytes. `Chunk`
+// is a window into a value that keeps it alive, like `bytes::Bytes`, so a
+// large body can be written out piece by piece (`EntryStream`) without a
+// second buffer.
+// ============================================================================
+
+use crate::Entry;
+use std::fmt;
+use std::ops::{Deref, Range};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default chunk size for `lookup_stream` callers without a preference.
+pub const DEFAULT_CHUNK: usize = 64 * 1024;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Value(Arc<[u8]>);
+
+impl Value {
+    /// Mutable access, copying the bytes first if another clone shares them.
+    pub fn make_mut(&mut self) -> &mut [u8] {
+        if Arc::get_mut(&mut self.0).is_none() {
+            self.0 = Arc::from(&self.0[..]);
+        }
+        return Arc::get_mut(&mut self.0).unwrap();
+    }
+
+    /// True when both values share one allocation (no copy was made).
+    pub fn shares(&self, other: &Value) -> bool {
+        return Arc::ptr_eq(&self.0, &other.0);
+    }
+
+    /// A zero-copy window; panics if `range` is out of bounds, like slicing.
+    pub fn slice(&self, range: Range<usize>) -> Chunk {
+        assert!(range.start <= range.end && range.end <= self.0.len(), "slice {:?} out of bounds", range);
+        return Chunk { buf: Arc::clone(&self.0), range };
+    }
+
+    /// Consecutive windows of at most `size` bytes (at least one byte each).
+    pub fn chunks(&self, size: usize) -> Chunks {
+        return Chunks { value: self.clone(), size: size.max(1), pos: 0 };
+    }
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        return Value(Arc::from(&[][..]));
+    }
+}
+
+impl Deref for Value {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        return &self.0;
+    }
+}
+
+impl AsRef<[u8]> for Value {
+    fn as_ref(&self) -> &[u8] {
+        return &self.0;
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "Value({} bytes)", self.0.len());
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(v: Vec<u8>) -> Self {
+        return Value(Arc::from(v));
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(v: &[u8]) -> Self {
+        return Value(Arc::from(v));
+    }
+}
+
+impl From<Arc<[u8]>> for Value {
+    fn from(v: Arc<[u8]>) -> Self {
+        return Value(v);
+    }
+}
+
+impl PartialEq<[u8]> for Value {
+    fn eq(&self, other: &[u8]) -> bool {
+        return *self.0 == *other;
+    }
+}
+
+impl PartialEq<&[u8]> for Value {
+    fn eq(&self, other: &&[u8]) -> bool {
+        return *self.0 == **other;
+    }
+}
+
+impl PartialEq<Vec<u8>> for Value {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        return *self.0 == other[..];
+    }
+}
+
+impl<const N: usize> PartialEq<[u8; N]> for Value {
+    fn eq(&self, other: &[u8; N]) -> bool {
+        return *self.0 == other[..];
+    }
+}
+
+impl<const N: usize> PartialEq<&[u8; N]> for Value {
+    fn eq(&self, other: &&[u8; N]) -> bool {
+        return *self.0 == other[..];
+    }
+}
+
+/// A shared window into a `Value`.
+#[derive(Clone)]
+pub struct Chunk {
+    buf: Arc<[u8]>,
+    range: Range<usize>,
+}
+
+impl Deref for Chunk {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        return &self.buf[self.range.clone()];
+    }
+}
+
+impl AsRef<[u8]> for Chunk {
+    fn as_ref(&self) -> &[u8] {
+        return self;
+    }
+}
+
+impl fmt::Debug for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "Chunk({:?})", self.range);
+    }
+}
+
+pub struct Chunks {
+    value: Value,
+    size: usize,
+    pos: usize,
+}
+
+impl Iterator for Chunks {
+    type Item = Chunk;
+
+    fn next(&mut self) -> Option<Chunk> {
+        if self.pos >= self.value.len() {
+            return None;
+        }
+        let end = self.pos.saturating_add(self.size).min(self.value.len());
+        let c = self.value.slice(self.pos..end);
+        self.pos = end;
+        return Some(c);
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = (self.value.len() - self.pos).div_ceil(self.size);
+        return (n, Some(n));
+    }
+}
+
+impl ExactSizeIterator for Chunks {}
+
+/// A cache hit delivered as chunks. The metadata is available up front (for
+/// status line and headers); iterating yields the body.
+pub struct EntryStream {
+    pub flags: u32,
+    pub len: usize,
+    pub ttl: Duration,
+    chunks: Chunks,
+}
+
+impl EntryStream {
+    pub fn new(entry: Entry, chunk_size: usize) -> Self {
+        return EntryStream { flags: entry.flags, len: entry.value.len(), ttl: entry.ttl, chunks: entry.value.chunks(chunk_size) };
+    }
+}
+
+impl Iterator for EntryStream {
+    type Item = Chunk;
+
+    fn next(&mut self) -> Option<Chunk> {
+        return self.chunks.next();
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        return self.chunks.size_hint();
+    }
+}
+
+impl ExactSizeIterator for EntryStream {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_share_the_value() {
+        let v = Value::from((0..=255u8).cycle().take(1000).collect::<Vec<u8>>());
+        let chunks: Vec<Chunk> = v.chunks(256).collect();
+        assert_eq!(chunks.iter().map(|c| c.len()).collect::<Vec<_>>(), vec![256, 256, 256, 232]);
+        assert_eq!(chunks.iter().flat_map(|c| c.iter().copied()).collect::<Vec<u8>>(), v.to_vec());
+        assert!(chunks.iter().all(|c| std::ptr::eq(c.buf.as_ptr(), v.as_ptr())));
+        assert_eq!(Value::default().chunks(16).count(), 0);
+
+        let mut w = v.clone();
+        assert!(w.shares(&v));
+        w.make_mut()[0] = 0xFF;
+        assert!(!w.shares(&v));
+        assert_eq!((v[0], w[0]), (0, 0xFF));
+    }
+}
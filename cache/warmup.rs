@@ -0,0 +1,334 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: cache/warmup.rs
+// Role: Final warmup scheduler (refresh hot entries before their TTL expires)
+// ----------------------------------------------------------------------------
+// Wraps a tier and counts hits per key together with the entry's expiry.
+// Each tick picks the top-K hottest keys expiring within `lead`, re-fetches
+// them through the `Loader` and re-inserts them, so hot keys never miss.
+// A key is refreshed by at most one worker at a time, and no more than
+// `max_concurrent` refreshes run at once across all ticks. Hit counters are
+// halved every tick so "hot" reflects recent traffic. A refresh only lands if
+// the key was neither invalidated nor rewritten since the tick picked it.
+// ============================================================================
+
+use crate::clock::{self, Clock};
+use crate::{Cache, CacheError, Entry};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Source of fresh entries (read-through loader or upstream fetch).
+pub trait Loader: Send + Sync {
+    fn load(&self, key: &[u8]) -> Result<Entry, CacheError>;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WarmupConfig {
+    pub top_k: usize,
+    pub lead: Duration,        // refresh once remaining TTL drops below this
+    pub max_concurrent: usize, // global cap on in-flight refreshes
+    pub interval: Duration,    // background tick period
+    pub max_tracked: usize,    // keys with hit stats; new keys are ignored when full
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        return WarmupConfig {
+            top_k: 64,
+            lead: Duration::from_secs(5),
+            max_concurrent: 4,
+            interval: Duration::from_secs(1),
+            max_tracked: 4096,
+        };
+    }
+}
+
+struct Stat {
+    hits: u64,
+    expires: Instant,
+    version: u64, // bumped by every insert; a refresh carries the one it saw
+}
+
+struct Shared<C> {
+    inner: C,
+    loader: Arc<dyn Loader>,
+    clock: Arc<dyn Clock>,
+    cfg: WarmupConfig,
+    stats: Mutex<HashMap<Vec<u8>, Stat>>,
+    inflight: Mutex<HashSet<Vec<u8>>>,
+    writes: Mutex<()>, // orders refresh inserts against insert/invalidate
+    next_version: AtomicU64,
+    refreshed: AtomicU64,
+    failed: AtomicU64,
+    stop: AtomicBool,
+}
+
+pub struct Warmed<C: Cache + Send + Sync + 'static> {
+    shared: Arc<Shared<C>>,
+}
+
+impl<C: Cache + Send + Sync + 'static> Clone for Warmed<C> {
+    fn clone(&self) -> Self {
+        return Warmed { shared: Arc::clone(&self.shared) };
+    }
+}
+
+impl<C: Cache + Send + Sync + 'static> Warmed<C> {
+    pub fn new(inner: C, loader: Arc<dyn Loader>, cfg: WarmupConfig) -> Self {
+        let shared = Shared {
+            inner,
+            loader,
+            clock: clock::system(),
+            cfg,
+            stats: Mutex::new(HashMap::new()),
+            inflight: Mutex::new(HashSet::new()),
+            writes: Mutex::new(()),
+            next_version: AtomicU64::new(0),
+            refreshed: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            stop: AtomicBool::new(false),
+        };
+        return Warmed { shared: Arc::new(shared) };
+    }
+
+    /// Replace the time source used to decide what is about to expire.
+    /// Panics if the wrapper was already cloned or spawned.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        let s = Arc::get_mut(&mut self.shared).expect("Warmed::with_clock called after the wrapper was cloned or spawned");
+        s.clock = clock;
+        return self;
+    }
+
+    pub fn inner(&self) -> &C {
+        return &self.shared.inner;
+    }
+
+    /// Schedule one round of refreshes; returns how many were started.
+    pub fn tick(&self) -> usize {
+        let s = &self.shared;
+        let now = s.clock.now();
+        let due: Vec<(Vec<u8>, u64)> = {
+            let mut stats = s.stats.lock().unwrap();
+            stats.retain(|_, st| st.expires > now);
+            let mut due: Vec<(&Vec<u8>, &Stat)> = stats
+                .iter()
+                .filter(|(_, st)| st.hits > 0 && st.expires.saturating_duration_since(now) <= s.cfg.lead)
+                .collect();
+            due.sort_by(|a, b| b.1.hits.cmp(&a.1.hits).then_with(|| a.0.cmp(b.0)));
+            let picked = due.into_iter().take(s.cfg.top_k).map(|(k, st)| (k.clone(), st.version)).collect();
+            for st in stats.values_mut() {
+                st.hits /= 2;
+            }
+            picked
+        };
+
+        let mut started = 0;
+        for (key, version) in due {
+            {
+                let mut inflight = s.inflight.lock().unwrap();
+                if inflight.len() >= s.cfg.max_concurrent {
+                    break;
+                }
+                if !inflight.insert(key.clone()) {
+                    continue;
+                }
+            }
+            let shared = Arc::clone(&self.shared);
+            thread::spawn(move || shared.refresh(key, version));
+            started += 1;
+        }
+        return started;
+    }
+
+    /// Run `tick` every `interval` on a background thread until `stop`.
+    pub fn spawn(&self) -> JoinHandle<()> {
+        let w = self.clone();
+        return thread::spawn(move || {
+            while !w.shared.stop.load(Ordering::Relaxed) {
+                w.tick();
+                thread::sleep(w.shared.cfg.interval);
+            }
+        });
+    }
+
+    pub fn stop(&self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn in_flight(&self) -> usize {
+        return self.shared.inflight.lock().unwrap().len();
+    }
+
+    pub fn refreshed(&self) -> u64 {
+        return self.shared.refreshed.load(Ordering::Relaxed);
+    }
+
+    pub fn failed(&self) -> u64 {
+        return self.shared.failed.load(Ordering::Relaxed);
+    }
+
+    // `hit` for lookups; inserts instead move the key to a new version.
+    fn track(&self, key: &[u8], e: &Entry, hit: bool) {
+        let version = if hit { None } else { Some(self.shared.next_version.fetch_add(1, Ordering::Relaxed) + 1) };
+        let mut stats = self.shared.stats.lock().unwrap();
+        let expires = e.ts + e.ttl;
+        if let Some(st) = stats.get_mut(key) {
+            st.expires = expires;
+            st.hits += hit as u64;
+            st.version = version.unwrap_or(st.version);
+            return;
+        }
+        if stats.len() < self.shared.cfg.max_tracked {
+            stats.insert(key.to_vec(), Stat { hits: hit as u64, expires, version: version.unwrap_or(0) });
+        }
+    }
+}
+
+impl<C: Cache + Send + Sync + 'static> Shared<C> {
+    fn refresh(&self, key: Vec<u8>, version: u64) {
+        let loaded = self.loader.load(&key).and_then(|e| {
+            // checked and written under `writes`, so an invalidate or insert
+            // either lands first (and the refresh is dropped) or after it
+            let _w = self.writes.lock().unwrap();
+            if self.stats.lock().unwrap().get(&key).is_none_or(|st| st.version != version) {
+                return Err(CacheError::NotFound); // invalidated or rewritten while loading
+            }
+            let expires = e.ts + e.ttl;
+            self.inner.insert(&key, e)?;
+            return Ok(expires);
+        });
+        match loaded {
+            Ok(expires) => {
+                if let Some(st) = self.stats.lock().unwrap().get_mut(&key) {
+                    st.expires = expires;
+                }
+                self.refreshed.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.inflight.lock().unwrap().remove(&key);
+    }
+}
+
+impl<C: Cache + Send + Sync + 'static> Cache for Warmed<C> {
+    fn lookup(&self, key: &[u8]) -> Result<Entry, CacheError> {
+        let e = self.shared.inner.lookup(key)?;
+        self.track(key, &e, true);
+        return Ok(e);
+    }
+
+    fn insert(&self, key: &[u8], entry: Entry) -> Result<(), CacheError> {
+        let _w = self.shared.writes.lock().unwrap();
+        self.track(key, &entry, false);
+        return self.shared.inner.insert(key, entry);
+    }
+
+    fn invalidate(&self, key: &[u8]) -> Result<(), CacheError> {
+        // invalidated keys must not be resurrected by a refresh
+        let _w = self.shared.writes.lock().unwrap();
+        self.shared.stats.lock().unwrap().remove(key);
+        return self.shared.inner.invalidate(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::l3::L3;
+
+    struct Counting(AtomicU64);
+
+    impl Loader for Counting {
+        fn load(&self, key: &[u8]) -> Result<Entry, CacheError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            return Ok(Entry::new(key.to_vec(), 0, Duration::from_secs(60)));
+        }
+    }
+
+    #[test]
+    fn refreshes_hottest_keys_before_expiry() {
+        let clock = MockClock::new();
+        let loader = Arc::new(Counting(AtomicU64::new(0)));
+        let cfg = WarmupConfig { top_k: 1, lead: Duration::from_secs(10), ..WarmupConfig::default() };
+        let w = Warmed::new(L3::new(), loader.clone(), cfg).with_clock(Arc::new(clock.clone()));
+
+        w.insert(b"hot", Entry::new(b"old".to_vec(), 0, Duration::from_secs(30))).unwrap();
+        w.insert(b"warm", Entry::new(b"old".to_vec(), 0, Duration::from_secs(30))).unwrap();
+        for _ in 0..8 {
+            w.lookup(b"hot").unwrap();
+        }
+        for _ in 0..4 {
+            w.lookup(b"warm").unwrap();
+        }
+
+        assert_eq!(w.tick(), 0); // nothing close to expiry yet
+        clock.advance(Duration::from_secs(25));
+        assert_eq!(w.tick(), 1);
+        while w.in_flight() > 0 {
+            thread::yield_now();
+        }
+        assert_eq!(w.refreshed(), 1);
+        assert_eq!(loader.0.load(Ordering::Relaxed), 1);
+        assert_eq!(w.inner().lookup(b"hot").unwrap().value, b"hot".to_vec());
+        assert_eq!(w.inner().lookup(b"warm").unwrap().value, b"old".to_vec());
+
+        // next round only "warm" is still due
+        assert_eq!(w.tick(), 1);
+    }
+
+    // Signals when a load starts and holds it until released.
+    struct Gated {
+        started: Mutex<std::sync::mpsc::Sender<()>>,
+        release: Mutex<std::sync::mpsc::Receiver<()>>,
+    }
+
+    impl Loader for Gated {
+        fn load(&self, key: &[u8]) -> Result<Entry, CacheError> {
+            self.started.lock().unwrap().send(()).unwrap();
+            self.release.lock().unwrap().recv().unwrap();
+            return Ok(Entry::new(key.to_vec(), 0, Duration::from_secs(60)));
+        }
+    }
+
+    #[test]
+    fn refresh_loses_to_concurrent_writes() {
+        let clock = MockClock::new();
+        let (started_tx, started) = std::sync::mpsc::channel();
+        let (release, release_rx) = std::sync::mpsc::channel();
+        let loader = Arc::new(Gated { started: Mutex::new(started_tx), release: Mutex::new(release_rx) });
+        let cfg = WarmupConfig { lead: Duration::from_secs(10), ..WarmupConfig::default() };
+        let w = Warmed::new(L3::new(), loader, cfg).with_clock(Arc::new(clock.clone()));
+        let settle = |w: &Warmed<L3>| {
+            while w.in_flight() > 0 {
+                thread::yield_now();
+            }
+        };
+
+        w.insert(b"k", Entry::new(b"old".to_vec(), 0, Duration::from_secs(30))).unwrap();
+        w.lookup(b"k").unwrap();
+        clock.advance(Duration::from_secs(25));
+        assert_eq!(w.tick(), 1);
+        started.recv().unwrap();
+        w.invalidate(b"k").unwrap();
+        release.send(()).unwrap();
+        settle(&w);
+        assert!(w.inner().lookup(b"k").is_err(), "invalidated key resurrected by refresh");
+        assert_eq!(w.failed(), 1);
+
+        w.insert(b"k", Entry::new(b"user".to_vec(), 0, Duration::from_secs(30))).unwrap();
+        w.lookup(b"k").unwrap();
+        assert_eq!(w.tick(), 1);
+        started.recv().unwrap();
+        w.insert(b"k", Entry::new(b"newer".to_vec(), 0, Duration::from_secs(30))).unwrap();
+        release.send(()).unwrap();
+        settle(&w);
+        assert_eq!(w.inner().lookup(b"k").unwrap().value, b"newer".to_vec());
+        assert_eq!(w.refreshed(), 0);
+    }
+}
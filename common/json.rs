@@ -0,0 +1,245 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: common/json.rs
+// Role: Minimal JSON reader with line numbers (config files, API specs)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - RFC 8259 text to `Value`; objects keep key order and the line each
+//   member is on, arrays the line of each item, so errors can point at them.
+// - Numbers are f64; duplicate keys are kept (callers decide).
+// - `Reader` also parses a single value starting at a given line, for
+//   line-oriented formats that embed JSON values (waf_config's TOML).
+// - No escaping or writing; responses are built with sdk::Json.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Str(String),
+    Num(f64),
+    Bool(bool),
+    Null,
+    Arr(Vec<(usize, Value)>),         // (line, item)
+    Obj(Vec<(String, usize, Value)>), // (key, line, value)
+}
+
+impl Value {
+    // First member named `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Obj(kv) => kv.iter().find(|(k, _, _)| k == key).map(|(_, _, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Num(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    // JSON type name, as JSON Schema spells it ("integer" for whole numbers).
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "string",
+            Value::Num(n) if n.fract() == 0.0 => "integer",
+            Value::Num(_) => "number",
+            Value::Bool(_) => "boolean",
+            Value::Null => "null",
+            Value::Arr(_) => "array",
+            Value::Obj(_) => "object",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonError {
+    pub line: usize, // 1-based
+    pub msg: String,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+// Parses a whole document.
+pub fn parse(src: &str) -> Result<Value, JsonError> {
+    let mut r = Reader::new(src, 1);
+    let v = r.value()?;
+    if !r.at_end() {
+        return Err(r.err("trailing characters after document"));
+    }
+    Ok(v)
+}
+
+pub struct Reader<'a> {
+    s: &'a [u8],
+    i: usize,
+    line: usize,
+}
+
+impl<'a> Reader<'a> {
+    // `line` is the line `src` starts on.
+    pub fn new(src: &'a str, line: usize) -> Self {
+        Self { s: src.as_bytes(), i: 0, line }
+    }
+
+    // The current line, after skipping whitespace.
+    pub fn line(&mut self) -> usize {
+        self.ws();
+        self.line
+    }
+
+    // True once only whitespace is left.
+    pub fn at_end(&mut self) -> bool {
+        self.ws();
+        self.i >= self.s.len()
+    }
+
+    pub fn err(&self, msg: &str) -> JsonError {
+        JsonError { line: self.line, msg: msg.to_string() }
+    }
+
+    fn ws(&mut self) {
+        while let Some(&c) = self.s.get(self.i) {
+            match c {
+                b'\n' => self.line += 1,
+                b' ' | b'\t' | b'\r' => {}
+                _ => return,
+            }
+            self.i += 1;
+        }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        self.ws();
+        if self.s.get(self.i) == Some(&c) {
+            self.i += 1;
+            return true;
+        }
+        false
+    }
+
+    pub fn value(&mut self) -> Result<Value, JsonError> {
+        self.ws();
+        match self.s.get(self.i) {
+            None => Err(self.err("unexpected end of input")),
+            Some(b'{') => {
+                self.i += 1;
+                let mut out = Vec::new();
+                if self.eat(b'}') {
+                    return Ok(Value::Obj(out));
+                }
+                loop {
+                    self.ws();
+                    let line = self.line;
+                    if self.s.get(self.i) != Some(&b'"') {
+                        return Err(self.err("expected a string key"));
+                    }
+                    let k = self.string()?;
+                    if !self.eat(b':') {
+                        return Err(self.err("expected ':'"));
+                    }
+                    self.ws();
+                    let vline = self.line;
+                    let v = self.value()?;
+                    // report a value on its own line, otherwise the key's line
+                    out.push((k, if vline > line { vline } else { line }, v));
+                    if self.eat(b'}') {
+                        return Ok(Value::Obj(out));
+                    }
+                    if !self.eat(b',') {
+                        return Err(self.err("expected ',' or '}'"));
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.i += 1;
+                let mut out = Vec::new();
+                if self.eat(b']') {
+                    return Ok(Value::Arr(out));
+                }
+                loop {
+                    self.ws();
+                    let line = self.line;
+                    out.push((line, self.value()?));
+                    if self.eat(b']') {
+                        return Ok(Value::Arr(out));
+                    }
+                    if !self.eat(b',') {
+                        return Err(self.err("expected ',' or ']'"));
+                    }
+                }
+            }
+            Some(b'"') => Ok(Value::Str(self.string()?)),
+            Some(_) => {
+                let start = self.i;
+                while self.s.get(self.i).is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, b'-' | b'+' | b'.')) {
+                    self.i += 1;
+                }
+                let word = std::str::from_utf8(&self.s[start..self.i]).unwrap_or("");
+                match word {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "null" => Ok(Value::Null),
+                    _ => word.parse::<f64>().map(Value::Num).map_err(|_| self.err(&format!("invalid value '{}'", word))),
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.i += 1; // opening quote
+        let mut out = Vec::new();
+        loop {
+            let Some(&c) = self.s.get(self.i) else { return Err(self.err("unterminated string")) };
+            self.i += 1;
+            match c {
+                b'"' => return String::from_utf8(out).map_err(|_| self.err("invalid UTF-8 in string")),
+                b'\n' => return Err(self.err("unterminated string")),
+                b'\\' => {
+                    let Some(&e) = self.s.get(self.i) else { return Err(self.err("unterminated string")) };
+                    self.i += 1;
+                    match e {
+                        b'"' | b'\\' | b'/' => out.push(e),
+                        b'n' => out.push(b'\n'),
+                        b't' => out.push(b'\t'),
+                        b'r' => out.push(b'\r'),
+                        b'b' => out.push(0x08),
+                        b'f' => out.push(0x0c),
+                        b'u' => {
+                            let hex = self.s.get(self.i..self.i + 4).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u32::from_str_radix(h, 16).ok());
+                            let ch = hex.and_then(char::from_u32).ok_or_else(|| self.err("invalid \\u escape"))?;
+                            self.i += 4;
+                            let mut buf = [0u8; 4];
+                            out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                        }
+                        _ => return Err(self.err("invalid escape")),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: common/report.rs
+// Role: Error reports shared by config and init paths (plugins, WAF)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Init and config paths collect every problem before failing, so operators
+//   can fix a whole config in one pass instead of one error per restart.
+// - Issues carry their source, line and key; warnings alone never fail.
+// - Plugins see these types through the SDK (sdk::{ErrorReport, Issue});
+//   other subsystems use this module directly, not the plugin SDK.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IssueLevel {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Issue {
+    pub level: IssueLevel,
+    pub source: String,      // plugin key, file or section name
+    pub line: Option<usize>, // 1-based, when the source is text
+    pub key: Option<String>, // config key or rule id the issue is about
+    pub message: String,
+}
+
+impl Issue {
+    pub fn error(source: &str, message: impl Into<String>) -> Self {
+        Self { level: IssueLevel::Error, source: source.to_string(), line: None, key: None, message: message.into() }
+    }
+
+    pub fn warning(source: &str, message: impl Into<String>) -> Self {
+        Self { level: IssueLevel::Warning, ..Self::error(source, message) }
+    }
+
+    pub fn at_line(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = if self.level == IssueLevel::Error { "error" } else { "warning" };
+        write!(f, "{}: {}", level, self.source)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, " [{}]", key)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ErrorReport {
+    pub issues: Vec<Issue>,
+}
+
+impl ErrorReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, issue: Issue) {
+        self.issues.push(issue);
+    }
+
+    pub fn merge(&mut self, other: ErrorReport) {
+        self.issues.extend(other.issues);
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Issue> {
+        self.issues.iter().filter(|i| i.level == IssueLevel::Error)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    // Err(self) when any issue is an error; warnings alone do not fail.
+    pub fn into_result<T>(self, value: T) -> Result<T, ErrorReport> {
+        if self.has_errors() { Err(self) } else { Ok(value) }
+    }
+}
+
+impl std::fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, issue) in self.issues.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
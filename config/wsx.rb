@@ -37,6 +37,7 @@ module WSX
     'CACHE_L1' => 0x00020000,
     'CACHE_L2' => 0x00040000,
     'CACHE_L3' => 0x00080000,
+    'CACHE_NEGATIVE' => 0x00800000,
     'SEC_OK' => 0x00100000,
     'SEC_WAF' => 0x00200000,
     'SEC_RATELIM' => 0x00400000
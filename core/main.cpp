@@ -27,6 +27,7 @@
 // Internal includes from core modules
 #include "memory/allocator.cpp"
 #include "memory/arena.cpp"
+#include "memory/numa.cpp"
 #include "routing/router.cpp"
 #include "filters/gzip_filter.cpp"
 
@@ -59,11 +60,16 @@ static constexpr uint32_t META_CACHE_MISS  = 0x00010000u;
 static constexpr uint32_t META_CACHE_L1    = 0x00020000u;
 static constexpr uint32_t META_CACHE_L2    = 0x00040000u;
 static constexpr uint32_t META_CACHE_L3    = 0x00080000u;
+//static constexpr uint32_t META_CACHE_NEGATIVE = 0x00800000u; // cached "not found" marker (cache crate only)
 
 static constexpr uint32_t META_SEC_OK      = 0x00100000u;
 static constexpr uint32_t META_SEC_WAF     = 0x00200000u;
 static constexpr uint32_t META_SEC_RATELIM = 0x00400000u;
 
+// Edge hints (olwsx_request_t::edge_hints; bits 0x1/0x2/0x4 see SecurityGate)
+static constexpr uint32_t EDGE_HINT_HEADER_PHASE = 0x00000008u; // Expect: 100-continue, body not read yet
+static constexpr uint32_t EDGE_HINT_HOST_MISMATCH = 0x00000010u; // TLS SNI differs from Host
+
 // ----------------------------------------------------------------------------
 // Cache (L2 implemented; L1/L3 stubs maintained locally)
 // ----------------------------------------------------------------------------
@@ -130,10 +136,11 @@ class SecurityGate {
 public:
     // Decides security outcome based on edge_hints and simple heuristics.
     // Stable semantics: if edge_hints has bit 1 => rate-limited; bit 2 => WAF.
+    // A header-phase pass is not counted: the same request comes back with its body.
     uint32_t decide(uint32_t edge_hints) {
         if (edge_hints & 0x2u) { counters_.waf_total.fetch_add(1, std::memory_order_relaxed); return META_SEC_WAF; }
         if (edge_hints & 0x1u) { counters_.rl_total.fetch_add(1, std::memory_order_relaxed); return META_SEC_RATELIM; }
-        counters_.ok_total.fetch_add(1, std::memory_order_relaxed);
+        if (!(edge_hints & EDGE_HINT_HEADER_PHASE)) counters_.ok_total.fetch_add(1, std::memory_order_relaxed);
         return META_SEC_OK;
     }
 
@@ -174,6 +181,11 @@ struct Core {
     CacheL3Stub cache_l3;
     Router      router;
     SecurityGate sec;
+
+    // Worker placement plan (OLWSX_PIN_MODE=off|compact|spread, OLWSX_WORKERS=n),
+    // reported at startup and applied by the launcher (see memory/numa.cpp)
+    Topology     topology;
+    WorkerLayout layout;
 };
 
 static Core g_core;
@@ -245,6 +257,13 @@ int olwsx__core_init_impl(olwsx_core_state_t* out_state) {
         out_state->v_patch  = OLWSX_CORE_VERSION_PATCH;
     }
 
+    // Topology and worker layout, reported once at startup
+    g_core.topology = detect_topology();
+    const char* workers = std::getenv("OLWSX_WORKERS");
+    g_core.layout = plan_workers(g_core.topology, parse_pin_mode(std::getenv("OLWSX_PIN_MODE")),
+                                 workers ? std::atoi(workers) : 0);
+    std::fprintf(stderr, "olwsx core: %s\n", g_core.layout.report(g_core.topology).c_str());
+
     // Warm-up: insert a known cache L2 entry
     g_core.cache_l2.insert("/hello", "Hello from OLWSX Core (L2 cached)", META_COMP_NONE);
 
@@ -378,6 +397,22 @@ int olwsx__process_request_impl(const olwsx_request_t* req, olwsx_response_t* re
     // Routing (deterministic rules)
     RouteRule rr{};
     bool matched = g_core.router.match(path, rr);
+
+    // Header phase: the client is waiting for 100 Continue before uploading.
+    // Security rejections above and redirect/error routes are final answers
+    // (sent below as usual); anything else gets 100 and the edge calls again
+    // with the body.
+    if ((req->edge_hints & EDGE_HINT_HEADER_PHASE) && !(matched && rr.status_override >= 300)) {
+        resp->status       = 100;
+        resp->headers_flat = nullptr;
+        resp->headers_len  = 0;
+        resp->body         = nullptr;
+        resp->body_len     = 0;
+        resp->meta_flags   = sec_flag | META_CACHE_MISS;
+        resp->reserved     = 0;
+        return OLWSX_OK;
+    }
+
     if (matched) {
         int status = rr.status_override > 0 ? rr.status_override : 200;
         std::string core_hdrs = "Cache: MISS\r\n";
@@ -0,0 +1,158 @@
+// ============================================================================
+// OLWSX - OverLab Web ServerX
+// File: core/memory/numa.cpp
+// Role: Topology detection and the worker placement plan
+// ----------------------------------------------------------------------------
+// The plan maps workers to CPUs by pin mode: compact fills one node before
+// the next, spread alternates nodes. It is reported at startup for whoever
+// launches the process (taskset, numactl, CPUAffinity=) to apply. The core
+// itself never changes thread affinity: the threads calling into it belong
+// to the Go runtime, which moves goroutines between them, and pinning one
+// would pin whatever it runs next. Without NUMA info (non-Linux, containers)
+// the machine is treated as one node.
+// ============================================================================
+
+#include <algorithm>
+#include <cstdio>
+#include <cstdlib>
+#include <cstring>
+#include <fstream>
+#include <sstream>
+#include <string>
+#include <thread>
+#include <vector>
+
+namespace olwsx {
+
+static constexpr int kMaxNumaNodes = 256;
+
+struct NumaNode {
+    int id;
+    std::vector<int> cpus;
+};
+
+struct Topology {
+    std::vector<NumaNode> nodes;
+
+    int cpu_count() const {
+        int n = 0;
+        for (const auto& node : nodes) n += static_cast<int>(node.cpus.size());
+        return n;
+    }
+
+    int node_of(int cpu) const {
+        for (const auto& node : nodes) {
+            if (std::find(node.cpus.begin(), node.cpus.end(), cpu) != node.cpus.end()) return node.id;
+        }
+        return nodes.empty() ? 0 : nodes.front().id;
+    }
+};
+
+enum class PinMode { Off, Compact, Spread };
+
+struct WorkerSlot {
+    int worker;
+    int cpu;   // -1 when the plan leaves it unpinned
+    int node;
+};
+
+struct WorkerLayout {
+    PinMode mode = PinMode::Off;
+    std::vector<WorkerSlot> slots;
+
+    // One line for the startup log; the layout is advice for the launcher.
+    std::string report(const Topology& topo) const {
+        static const char* names[] = {"off", "compact", "spread"};
+        std::ostringstream os;
+        os << "topology nodes=" << topo.nodes.size() << " cpus=" << topo.cpu_count()
+           << " pin=" << names[static_cast<int>(mode)] << " workers=" << slots.size();
+        if (mode != PinMode::Off) os << " applied=launcher";
+        for (const auto& node : topo.nodes) {
+            os << " node" << node.id << "=" << node.cpus.size() << "cpu";
+        }
+        if (mode != PinMode::Off) {
+            os << " layout=";
+            for (std::size_t i = 0; i < slots.size(); ++i) {
+                if (i) os << ",";
+                os << "w" << slots[i].worker << ":c" << slots[i].cpu << "/n" << slots[i].node;
+            }
+        }
+        return os.str();
+    }
+};
+
+// "0-3,8,10-11" -> {0,1,2,3,8,10,11}
+static std::vector<int> parse_cpulist(const std::string& s) {
+    std::vector<int> out;
+    std::stringstream ss(s);
+    std::string part;
+    while (std::getline(ss, part, ',')) {
+        if (part.empty() || part == "\n") continue;
+        int lo = 0, hi = 0;
+        if (std::sscanf(part.c_str(), "%d-%d", &lo, &hi) == 2) {
+            for (int c = lo; c <= hi; ++c) out.push_back(c);
+        } else if (std::sscanf(part.c_str(), "%d", &lo) == 1) {
+            out.push_back(lo);
+        }
+    }
+    return out;
+}
+
+static Topology detect_topology() {
+    Topology topo;
+#if defined(__linux__)
+    for (int id = 0; id < kMaxNumaNodes; ++id) {
+        std::ifstream f("/sys/devices/system/node/node" + std::to_string(id) + "/cpulist");
+        if (!f) continue;
+        std::string line;
+        std::getline(f, line);
+        auto cpus = parse_cpulist(line);
+        if (!cpus.empty()) topo.nodes.push_back(NumaNode{id, cpus});
+    }
+#endif
+    if (topo.nodes.empty()) {
+        int n = static_cast<int>(std::max(1u, std::thread::hardware_concurrency()));
+        NumaNode node{0, {}};
+        for (int c = 0; c < n; ++c) node.cpus.push_back(c);
+        topo.nodes.push_back(node);
+    }
+    return topo;
+}
+
+// `workers` <= 0 means one per CPU. Workers beyond the CPU count wrap around.
+static WorkerLayout plan_workers(const Topology& topo, PinMode mode, int workers) {
+    WorkerLayout layout;
+    layout.mode = mode;
+    int total = topo.cpu_count();
+    if (workers <= 0) workers = total;
+
+    std::vector<std::pair<int, int>> order; // (cpu, node)
+    if (mode == PinMode::Spread) {
+        std::size_t depth = 0;
+        for (const auto& node : topo.nodes) depth = std::max(depth, node.cpus.size());
+        for (std::size_t i = 0; i < depth; ++i) {
+            for (const auto& node : topo.nodes) {
+                if (i < node.cpus.size()) order.emplace_back(node.cpus[i], node.id);
+            }
+        }
+    } else {
+        for (const auto& node : topo.nodes) {
+            for (int c : node.cpus) order.emplace_back(c, node.id);
+        }
+    }
+
+    for (int w = 0; w < workers; ++w) {
+        const auto& [cpu, node] = order[static_cast<std::size_t>(w) % order.size()];
+        layout.slots.push_back(WorkerSlot{w, mode == PinMode::Off ? -1 : cpu, node});
+    }
+    return layout;
+}
+
+static PinMode parse_pin_mode(const char* s) {
+    if (!s) return PinMode::Off;
+    if (std::strcmp(s, "compact") == 0) return PinMode::Compact;
+    if (std::strcmp(s, "spread") == 0) return PinMode::Spread;
+    return PinMode::Off;
+}
+
+} // namespace olwsx
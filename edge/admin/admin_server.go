@@ -2,20 +2,24 @@ package admin
 
 import (
 	"log"
+	"net"
 	"net/http"
+
+	edgehttp "olwsx/edge/http"
 )
 
-// ListenAndServe starts a minimal admin server providing health and metrics endpoints.
-func ListenAndServe(addr string, health http.HandlerFunc, metrics http.HandlerFunc) {
+// Serve runs a minimal admin server providing health, readiness and metrics
+// endpoints on ln, which the caller has already bound and tuned, with the
+// keep-alive policy ka.
+func Serve(ln net.Listener, ka edgehttp.KeepAlive, health, ready, metrics http.HandlerFunc) {
 	mux := http.NewServeMux()
 	mux.HandleFunc("/health", health)
+	mux.HandleFunc("/ready", ready)
 	mux.HandleFunc("/metrics", metrics)
-	s := &http.Server{
-		Addr:    addr,
-		Handler: mux,
-	}
-	log.Printf("Admin server on %s", addr)
-	if err := s.ListenAndServe(); err != nil && err != http.ErrServerClosed {
+	s := &http.Server{Handler: mux}
+	edgehttp.ApplyKeepAlive(s, ka)
+	log.Printf("Admin server on %s", ln.Addr())
+	if err := s.Serve(ln); err != nil && err != http.ErrServerClosed {
 		log.Printf("admin server error: %v", err)
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,94 @@
+package admin
+
+import (
+	"fmt"
+	"net/http"
+	"strings"
+	"sync"
+)
+
+// Subsystem states as shown in the readiness detail.
+const (
+	StatePending = "pending"
+	StateReady   = "ready"
+	StateFailed  = "failed"
+)
+
+// Readiness tracks subsystems whose initialization may finish after the
+// listeners are already accepting (cold-start profile).
+type Readiness struct {
+	mu    sync.Mutex
+	order []string
+	state map[string]string
+}
+
+// Subsystems is the process-wide tracker behind ReadyHandler.
+var Subsystems = &Readiness{state: map[string]string{}}
+
+// Register adds a subsystem in the pending state.
+func (r *Readiness) Register(name string) {
+	r.mu.Lock()
+	defer r.mu.Unlock()
+	if _, ok := r.state[name]; !ok {
+		r.order = append(r.order, name)
+	}
+	r.state[name] = StatePending
+}
+
+// MarkReady records that a subsystem finished initializing.
+func (r *Readiness) MarkReady(name string) {
+	r.set(name, StateReady)
+}
+
+// MarkFailed records that a subsystem gave up; the edge keeps serving without it.
+func (r *Readiness) MarkFailed(name string, err error) {
+	r.set(name, fmt.Sprintf("%s(%v)", StateFailed, err))
+}
+
+func (r *Readiness) set(name, state string) {
+	r.mu.Lock()
+	defer r.mu.Unlock()
+	if _, ok := r.state[name]; !ok {
+		r.order = append(r.order, name)
+	}
+	r.state[name] = state
+}
+
+// Degraded is true while any subsystem is not ready.
+func (r *Readiness) Degraded() bool {
+	r.mu.Lock()
+	defer r.mu.Unlock()
+	for _, s := range r.state {
+		if s != StateReady {
+			return true
+		}
+	}
+	return false
+}
+
+// Detail lists every subsystem in registration order, e.g. "waf_rules=ready".
+func (r *Readiness) Detail() string {
+	r.mu.Lock()
+	defer r.mu.Unlock()
+	parts := make([]string, 0, len(r.order))
+	for _, name := range r.order {
+		parts = append(parts, name+"="+r.state[name])
+	}
+	return strings.Join(parts, " ")
+}
+
+// ReadyHandler answers 200 as soon as the listeners accept; while subsystems
+// are still initializing (or failed) the body says DEGRADED with the detail,
+// so traffic is not withheld but reduced functionality is visible.
+func ReadyHandler(w http.ResponseWriter, r *http.Request) {
+	w.Header().Set("Content-Type", "text/plain")
+	status := "OK"
+	if Subsystems.Degraded() {
+		status = "DEGRADED"
+	}
+	if detail := Subsystems.Detail(); detail != "" {
+		fmt.Fprintf(w, "%s %s\n", status, detail)
+		return
+	}
+	fmt.Fprintln(w, status)
+}
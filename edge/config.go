@@ -1,6 +1,12 @@
 package main
 
-import "time"
+import (
+	"time"
+
+	edgehttp "olwsx/edge/http"
+	edgetcp "olwsx/edge/tcp"
+	edgetls "olwsx/edge/tls"
+)
 
 // Immutable defaults (can be staged via external config if needed).
 const (
@@ -40,4 +46,124 @@ const (
 	// WAF/Challenge toggles
 	EnableWAF       = true
 	EnableChallenge = true
-)
\ No newline at end of file
+
+	// 103 Early Hints: paths whose handler-requested hints are remembered
+	EarlyHintsLearned = 4096
+
+	// Cold-start profile (opt-in): defer heavy subsystems (see startup.go)
+	// until the listeners accept; /ready reports DEGRADED until they are
+	// loaded. The WAF blocks every request until its rules are in.
+	LazyInit = false
+)
+
+// Request body limits narrower (or wider) than MaxBodyBytes: per tenant,
+// keyed by Host without the port, and per route path prefix. A route limit
+// beats a tenant limit; the longest matching prefix wins.
+var (
+	TenantBodyLimits = map[string]int64{}
+	RouteBodyLimits  = map[string]int64{}
+)
+
+// Mutual TLS on the h2/h1 and HTTP/3 listeners. Empty CAFile: no client
+// certificates are asked for. CRLs and the fingerprint allowlist are read at
+// startup.
+var TLSClientAuth = edgetls.ClientAuth{
+	CAFile:   "",
+	Required: false,
+	CRLFiles: nil,
+	Allow:    nil,
+}
+
+// OCSP stapling for the served certificate (needs its issuer in server.crt
+// and a responder URL in it; a self-signed one is skipped). Responses are
+// cached in CacheDir across restarts.
+var TLSOCSPStapling = edgetls.OCSPStapling{
+	Enabled:  true,
+	CacheDir: "/var/lib/olwsx/ocsp",
+	Timeout:  10 * time.Second,
+	Retry:    5 * time.Minute,
+	Event:    TLSEvent,
+}
+
+// Days-to-expiry gauges per SNI name, and a warning event from WarnDays out.
+var TLSCertExpiry = edgetls.CertExpiry{
+	Every:    time.Hour,
+	WarnDays: 21,
+	Gauge:    MetricCertExpiry,
+	Warn:     CertExpiring,
+}
+
+// Virtual hosts served (exact names or "*.example.com" for subdomains).
+// Empty accepts any well-formed Host. A Host not listed gets 421, or is
+// rewritten to DefaultVHost when that is set.
+var (
+	VHostNames   = []string{}
+	DefaultVHost = ""
+)
+
+// Link values sent in a 103 Early Hints for requests under a route path
+// prefix (longest prefix wins), e.g. "/app/": {"</app.css>; rel=preload; as=style"}.
+var EarlyHintRoutes = map[string][]string{}
+
+// Response bandwidth, in bytes/s (0: unpaced). A route rate beats a tenant
+// rate beats the default; bodies over LargeAbove bytes get at most LargeRate;
+// PerConn caps one client connection. Handlers and WAF throttle rules can
+// only lower these (X-OLWSX-Throttle).
+var ResponseThrottle = edgehttp.Throttle{
+	Default:    0,
+	Tenants:    map[string]int64{},
+	Routes:     map[string]int64{},
+	LargeAbove: 0,
+	LargeRate:  0,
+	PerConn:    0,
+}
+
+// Per-listener keep-alive policy: connections are closed after MaxRequests,
+// reaped IdleTimeout (+ up to IdleJitter) into idleness, and told to close
+// once CloseAbove of MaxConns are open.
+var (
+	TLSKeepAlive = edgehttp.KeepAlive{
+		MaxRequests: 10000,
+		IdleTimeout: IdleTimeout,
+		IdleJitter:  10 * time.Second,
+		MaxConns:    50000,
+		CloseAbove:  0.9,
+	}
+	WSKeepAlive = edgehttp.KeepAlive{
+		IdleTimeout: 30 * time.Second,
+		IdleJitter:  5 * time.Second,
+		MaxConns:    10000,
+		CloseAbove:  0.9,
+	}
+	AdminKeepAlive = edgehttp.KeepAlive{
+		MaxRequests: 100,
+		IdleTimeout: 30 * time.Second,
+	}
+)
+
+// Per-listener TCP tuning (reported in the startup events). Backlog and
+// FastOpen take effect at bind time, the rest on every accepted connection.
+var (
+	TLSListenerTuning = edgetcp.Tuning{
+		NoDelay:           true,
+		KeepAlive:         true,
+		KeepAliveIdle:     60 * time.Second,
+		KeepAliveInterval: 15 * time.Second,
+		KeepAliveCount:    4,
+		Backlog:           4096,
+		FastOpen:          256,
+	}
+	WSListenerTuning = edgetcp.Tuning{
+		NoDelay:           true,
+		KeepAlive:         true,
+		KeepAliveIdle:     30 * time.Second,
+		KeepAliveInterval: 10 * time.Second,
+		KeepAliveCount:    3,
+		Backlog:           1024,
+	}
+	AdminListenerTuning = edgetcp.Tuning{
+		NoDelay:   true,
+		KeepAlive: true,
+		Backlog:   128,
+	}
+)
@@ -5,6 +5,7 @@ go 1.24.4
 require (
 	github.com/gorilla/websocket v1.5.1
 	github.com/quic-go/quic-go v0.44.0
+	golang.org/x/crypto v0.23.0
 )
 
 require (
@@ -13,7 +14,6 @@ require (
 	github.com/onsi/ginkgo/v2 v2.9.5 // indirect
 	github.com/quic-go/qpack v0.4.0 // indirect
 	go.uber.org/mock v0.4.0 // indirect
-	golang.org/x/crypto v0.23.0 // indirect
 	golang.org/x/exp v0.0.0-20240506185415-9bf2ced13842 // indirect
 	golang.org/x/mod v0.17.0 // indirect
 	golang.org/x/net v0.25.0 // indirect
@@ -0,0 +1,39 @@
+package http
+
+import (
+	"net"
+	"strings"
+)
+
+// BodyLimits caps request bodies. The most specific limit applies: the
+// longest route prefix matching the path, else the tenant's (the Host, port
+// stripped, lowercase), else Default. Zero entries fall through.
+type BodyLimits struct {
+	Default int64
+	Tenants map[string]int64 // host -> max bytes
+	Routes  map[string]int64 // path prefix -> max bytes
+}
+
+// For returns the limit for a request to host and path.
+func (l BodyLimits) For(host, path string) int64 {
+	best, limit := -1, int64(0)
+	for prefix, n := range l.Routes {
+		if n > 0 && len(prefix) > best && strings.HasPrefix(path, prefix) {
+			best, limit = len(prefix), n
+		}
+	}
+	if best >= 0 {
+		return limit
+	}
+	if n := l.Tenants[tenantHost(host)]; n > 0 {
+		return n
+	}
+	return l.Default
+}
+
+func tenantHost(host string) string {
+	if h, _, err := net.SplitHostPort(host); err == nil {
+		host = h
+	}
+	return strings.ToLower(strings.TrimSuffix(strings.Trim(host, "[]"), "."))
+}
@@ -2,12 +2,15 @@ package http
 
 import (
 	"bytes"
+	"context"
+	"errors"
 	"fmt"
-	"io"
 	stdhttp "net/http"
+	"strconv"
 	"strings"
 	"time"
 
+	edgetls "olwsx/edge/tls"
 	"olwsx/edge/wire"
 )
 
@@ -19,39 +22,92 @@ type CoreResp struct {
 	MetaFlags   uint32
 }
 
-type CoreCaller func(method, path, headers string, body []byte, traceID, spanID uint64, hints uint32) (CoreResp, int)
+// CoreCaller must give up (and release its connection) once ctx is done; the
+// context is cancelled when the client disconnects.
+type CoreCaller func(ctx context.Context, method, path, headers string, body []byte, traceID, spanID uint64, hints uint32) (CoreResp, int)
 type IDGen func() (uint64, uint64)
 type RateCheck func(remote string) bool
 type WAFCheck func(path, ua string) bool
 type ChallengeCheck func(remote string) bool
-type AccessLogger func(method, path string, status, bodyLen int, hints uint32, dur time.Duration, remote, ua string)
+type AccessLogger func(method, path string, status, bodyLen int, hints uint32, dur time.Duration, remote, ua, outcome string)
 type MetricReject func(reason string)
 type MetricError func(name string)
+type MetricAbort func(stage string)
+
+// Access log outcomes. A client abort is logged with StatusClientClosed and
+// counted apart from errors: nothing was sent and the edge is not at fault.
+const (
+	OutcomeOK          = "ok"
+	OutcomeClientAbort = "client_abort"
+	StatusClientClosed = 499
+)
 
 // Handler wires normalization, limits, waf, rate-limit hooks, tracing, and calls into actor/core via CoreCaller.
-func Handler(maxHeaderBytes, maxBodyBytes int,
+func Handler(maxHeaderBytes int,
+	vhosts VHosts,
+	bodyLimits BodyLimits,
 	rateCheck RateCheck,
 	wafCheck WAFCheck,
 	challengeCheck ChallengeCheck,
 	coreCall CoreCaller,
+	earlyHints *EarlyHints, // nil: no 103 Early Hints
+	throttle *Throttle, // nil: bodies unpaced unless a response asks (HeaderThrottle)
 	newIDs IDGen,
 	accessLog AccessLogger,
 	metricReject MetricReject,
 	metricError MetricError,
+	metricAbort MetricAbort,
 ) stdhttp.Handler {
 	return stdhttp.HandlerFunc(func(w stdhttp.ResponseWriter, r *stdhttp.Request) {
 		start := time.Now()
 
-		// Hard body limit
-		if r.ContentLength > int64(maxBodyBytes) && r.ContentLength >= 0 {
-			errorTooLarge(w, "Body too large")
-			metricReject("body_too_large")
+		// Security hints
+		var hints uint32
+
+		// Host (absolute-form targets carry theirs in r.Host). A TLS client
+		// that asks for another host than its SNI named is flagged, not refused.
+		if r.URL.IsAbs() && r.URL.Scheme != "http" && r.URL.Scheme != "https" {
+			errorHost(w, stdhttp.StatusBadRequest, "Unsupported target scheme")
+			metricReject("invalid_host")
 			return
 		}
-		r.Body = io.NopCloser(io.LimitReader(r.Body, int64(maxBodyBytes)))
+		requested := r.Host
+		host, status := vhosts.Resolve(requested)
+		if status != 0 {
+			errorHost(w, status, "Unknown or invalid host")
+			if status == stdhttp.StatusBadRequest {
+				metricReject("invalid_host")
+			} else {
+				metricReject("unknown_host")
+			}
+			return
+		}
+		r.Host = host
+		r.Header.Del(HeaderHostMismatch)
 
-		// Security hints
-		var hints uint32
+		// mTLS identity: only what the handshake verified, never a client's copy
+		r.Header.Del(HeaderClientCertCN)
+		r.Header.Del(HeaderClientCertFingerprint)
+		if cn, fp := edgetls.ClientIdentity(r.TLS); fp != "" {
+			r.Header.Set(HeaderClientCertCN, cn)
+			r.Header.Set(HeaderClientCertFingerprint, fp)
+		}
+		if r.TLS != nil && r.TLS.ServerName != "" && !strings.EqualFold(r.TLS.ServerName, tenantHost(requested)) {
+			hints |= wire.HintHostMismatch
+			r.Header.Set(HeaderHostMismatch, r.TLS.ServerName)
+		}
+
+		// Body limit (route, tenant or global). A declared length over it is
+		// refused before any of the body is read; a body that grows past it
+		// (chunked, or more than declared) fails the read below. Either way
+		// the connection is closed after the 413 instead of being drained.
+		maxBody := bodyLimits.For(r.Host, r.URL.Path)
+		if r.ContentLength > maxBody {
+			errorBodyTooLarge(w)
+			metricReject("body_too_large")
+			return
+		}
+		r.Body = stdhttp.MaxBytesReader(w, r.Body, maxBody)
 
 		// Challenge gate
 		if challengeCheck != nil && challengeCheck(r.RemoteAddr) {
@@ -77,20 +133,77 @@ func Handler(maxHeaderBytes, maxBodyBytes int,
 			return
 		}
 
+		// Client gone: stop here, record it, write nothing
+		clientAbort := func(stage string) {
+			if metricAbort != nil {
+				metricAbort(stage)
+			}
+			if accessLog != nil {
+				accessLog(method, path, StatusClientClosed, 0, hints, time.Since(start), r.RemoteAddr, r.UserAgent(), OutcomeClientAbort)
+			}
+		}
+
+		// IDs
+		traceID, spanID := newIDs()
+
+		// Expect: 100-continue: ask core with headers only. net/http sends the
+		// 100 Continue on the first body read, so a rejection answered here
+		// never makes the client upload.
+		if expectsContinue(r) {
+			pre, code := coreCall(r.Context(), method, path, headersFlat, nil, traceID, spanID, hints|wire.HintHeaderPhase)
+			if r.Context().Err() != nil {
+				clientAbort("header_phase")
+				return
+			}
+			if code != 0 {
+				errorBadGateway(w, fmt.Sprintf("Core/Actor error: %d", code))
+				metricError("core_actor_error")
+				return
+			}
+			if pre.Status != stdhttp.StatusContinue {
+				w.Header().Set("Connection", "close") // body left unread
+				writeCoreResp(w, r, pre, traceID, throttle)
+				metricReject("expect_rejected")
+				if accessLog != nil {
+					accessLog(method, path, pre.Status, len(pre.Body), hints, time.Since(start), r.RemoteAddr, r.UserAgent(), OutcomeOK)
+				}
+				return
+			}
+		}
+
 		// Read body
 		var bodyBuf bytes.Buffer
 		if _, err := bodyBuf.ReadFrom(r.Body); err != nil {
+			if r.Context().Err() != nil {
+				clientAbort("read_body")
+				return
+			}
+			var tooLarge *stdhttp.MaxBytesError
+			if errors.As(err, &tooLarge) {
+				errorBodyTooLarge(w)
+				metricReject("body_too_large_streamed")
+				if accessLog != nil {
+					accessLog(method, path, stdhttp.StatusRequestEntityTooLarge, 0, hints, time.Since(start), r.RemoteAddr, r.UserAgent(), OutcomeOK)
+				}
+				return
+			}
 			errorBadGateway(w, "Read body failed")
 			metricError("read_body_error")
 			return
 		}
 		bodyBytes := bodyBuf.Bytes()
 
-		// IDs
-		traceID, spanID := newIDs()
+		// 103 Early Hints while core works, unless the request is already flagged
+		if earlyHints != nil && hints&^wire.HintHostMismatch == 0 {
+			earlyHints.send(w, r)
+		}
 
 		// Core/Actor call
-		resp, code := coreCall(method, path, headersFlat, bodyBytes, traceID, spanID, hints)
+		resp, code := coreCall(r.Context(), method, path, headersFlat, bodyBytes, traceID, spanID, hints)
+		if r.Context().Err() != nil {
+			clientAbort("core")
+			return
+		}
 		if code != 0 {
 			errorBadGateway(w, fmt.Sprintf("Core/Actor error: %d", code))
 			metricError("core_actor_error")
@@ -98,30 +211,87 @@ func Handler(maxHeaderBytes, maxBodyBytes int,
 		}
 
 		// Emit response
-		for _, hv := range ParseFlat(resp.HeadersFlat) {
-			parts := strings.SplitN(hv, ":", 2)
-			if len(parts) == 2 {
-				w.Header().Add(strings.TrimSpace(parts[0]), strings.TrimSpace(parts[1]))
-			}
-		}
-		w.Header().Set("X-Trace-ID", fmt.Sprintf("%016x", traceID))
-		w.WriteHeader(resp.Status)
-		if len(resp.Body) > 0 {
-			_, _ = w.Write(resp.Body)
+		if earlyHints != nil && resp.Status >= 200 && resp.Status < 300 {
+			earlyHints.Learn(r.URL.Path, earlyHintLinks(resp.HeadersFlat))
 		}
+		writeCoreResp(w, r, resp, traceID, throttle)
 
 		// Access log
 		if accessLog != nil {
-			accessLog(method, path, resp.Status, len(resp.Body), hints, time.Since(start), r.RemoteAddr, r.UserAgent())
+			accessLog(method, path, resp.Status, len(resp.Body), hints, time.Since(start), r.RemoteAddr, r.UserAgent(), OutcomeOK)
 		}
 	})
 }
 
+// The body is paced to the stricter of throttle's rate and the response's
+// own HeaderThrottle, and to the connection's PerConn budget.
+func writeCoreResp(w stdhttp.ResponseWriter, r *stdhttp.Request, resp CoreResp, traceID uint64, throttle *Throttle) {
+	rate := throttle.For(r.Host, r.URL.Path, len(resp.Body))
+	for _, hv := range ParseFlat(resp.HeadersFlat) {
+		parts := strings.SplitN(hv, ":", 2)
+		if len(parts) != 2 {
+			continue
+		}
+		name, value := strings.TrimSpace(parts[0]), strings.TrimSpace(parts[1])
+		if n, ok := throttleHeader(name, value); ok {
+			rate = lowerRate(rate, n)
+			continue
+		}
+		if !strings.EqualFold(name, HeaderEarlyHint) {
+			w.Header().Add(name, value)
+		}
+	}
+	w.Header().Set("X-Trace-ID", fmt.Sprintf("%016x", traceID))
+	var buckets []*bucket
+	if rate > 0 {
+		buckets = append(buckets, newBucket(rate, throttle.burst(rate)))
+	}
+	if b := throttle.connBucket(r); b != nil {
+		buckets = append(buckets, b)
+	}
+	if len(buckets) > 0 && len(resp.Body) > 0 && w.Header().Get("Content-Length") == "" {
+		// paced writes would otherwise switch HTTP/1.1 to chunked encoding
+		w.Header().Set("Content-Length", strconv.Itoa(len(resp.Body)))
+	}
+	w.WriteHeader(resp.Status)
+	if len(resp.Body) == 0 {
+		return
+	}
+	if len(buckets) == 0 {
+		_, _ = w.Write(resp.Body)
+		return
+	}
+	writePaced(r.Context(), w, resp.Body, buckets)
+}
+
+// expectsContinue reports a request whose client waits for 100 Continue
+// before sending a (non-empty) body.
+func expectsContinue(r *stdhttp.Request) bool {
+	return r.ContentLength != 0 && strings.EqualFold(r.Header.Get("Expect"), "100-continue")
+}
+
 func errorTooLarge(w stdhttp.ResponseWriter, msg string) {
 	w.Header().Set("Content-Type", "text/plain")
 	w.WriteHeader(stdhttp.StatusRequestEntityTooLarge)
 	_, _ = w.Write([]byte(msg))
 }
+
+// errorHost refuses a request for its Host and closes the connection, so
+// later requests on it cannot ride on a host that was never checked.
+func errorHost(w stdhttp.ResponseWriter, status int, msg string) {
+	w.Header().Set("Connection", "close")
+	w.Header().Set("Content-Type", "text/plain")
+	w.WriteHeader(status)
+	_, _ = w.Write([]byte(msg))
+}
+
+// errorBodyTooLarge answers 413 and closes the connection: the rest of the
+// body is never read, so the client must not reuse it.
+func errorBodyTooLarge(w stdhttp.ResponseWriter) {
+	w.Header().Set("Connection", "close")
+	errorTooLarge(w, "Body too large")
+}
+
 func errorBadGateway(w stdhttp.ResponseWriter, msg string) {
 	w.Header().Set("Content-Type", "text/plain")
 	w.WriteHeader(stdhttp.StatusBadGateway)
@@ -0,0 +1,90 @@
+package http
+
+import (
+	stdhttp "net/http"
+	"strings"
+	"sync"
+)
+
+// HeaderEarlyHint is the internal response header a handler sets (through
+// sdk::early_hint) to ask for a Link in a 103; never sent to clients.
+const HeaderEarlyHint = "X-OLWSX-Early-Hint"
+
+// EarlyHints picks the Link values sent in a 103 Early Hints before a
+// request's final response: those configured for the longest matching route
+// prefix, then those the last 2xx response for the same path asked for. A
+// handler's hints can only help the requests after it, since its final
+// response is already on the way. Up to maxLearned paths are remembered.
+type EarlyHints struct {
+	routes     map[string][]string
+	maxLearned int
+
+	mu      sync.Mutex
+	learned map[string][]string
+}
+
+func NewEarlyHints(routes map[string][]string, maxLearned int) *EarlyHints {
+	return &EarlyHints{routes: routes, maxLearned: maxLearned, learned: make(map[string][]string)}
+}
+
+// For returns the links to hint for path, or nil.
+func (e *EarlyHints) For(path string) []string {
+	var links []string
+	best := -1
+	for prefix, l := range e.routes {
+		if len(prefix) > best && strings.HasPrefix(path, prefix) {
+			best, links = len(prefix), l
+		}
+	}
+	e.mu.Lock()
+	learned := e.learned[path]
+	e.mu.Unlock()
+	if len(learned) == 0 {
+		return links
+	}
+	return append(append([]string(nil), links...), learned...)
+}
+
+// Learn records the links a response for path asked for; none forgets them.
+func (e *EarlyHints) Learn(path string, links []string) {
+	e.mu.Lock()
+	defer e.mu.Unlock()
+	if len(links) == 0 {
+		delete(e.learned, path)
+		return
+	}
+	if _, ok := e.learned[path]; !ok && len(e.learned) >= e.maxLearned {
+		return
+	}
+	e.learned[path] = links
+}
+
+// send writes the 103 (HTTP/1.1 and HTTP/2 only; HTTP/1.0 clients cannot
+// take an interim response) and removes the Link headers again so they are
+// not repeated on the final response.
+func (e *EarlyHints) send(w stdhttp.ResponseWriter, r *stdhttp.Request) {
+	if !r.ProtoAtLeast(1, 1) || r.ProtoMajor > 2 {
+		return
+	}
+	links := e.For(r.URL.Path)
+	if len(links) == 0 {
+		return
+	}
+	for _, l := range links {
+		w.Header().Add("Link", l)
+	}
+	w.WriteHeader(stdhttp.StatusEarlyHints)
+	w.Header().Del("Link")
+}
+
+// earlyHintLinks returns the HeaderEarlyHint values in a core response's
+// headers (writeCoreResp leaves them out).
+func earlyHintLinks(headersFlat string) []string {
+	var links []string
+	for _, hv := range ParseFlat(headersFlat) {
+		if k, v, ok := strings.Cut(hv, ":"); ok && strings.EqualFold(strings.TrimSpace(k), HeaderEarlyHint) {
+			links = append(links, strings.TrimSpace(v))
+		}
+	}
+	return links
+}
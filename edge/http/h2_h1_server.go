@@ -12,9 +12,10 @@ type Timeouts struct {
 	ReadHeader time.Duration
 }
 
-// NewH2H1Server constructs a net/http server ready for TLS ALPN (h2 + http/1.1).
-func NewH2H1Server(handler stdhttp.Handler, maxHeaderBytes int, timeouts Timeouts) *stdhttp.Server {
-	return &stdhttp.Server{
+// NewH2H1Server constructs a net/http server ready for TLS ALPN (h2 + http/1.1),
+// with the keep-alive policy ka (see ApplyKeepAlive).
+func NewH2H1Server(handler stdhttp.Handler, maxHeaderBytes int, timeouts Timeouts, ka KeepAlive) *stdhttp.Server {
+	srv := &stdhttp.Server{
 		Handler:           handler,
 		ReadTimeout:       timeouts.Read,
 		WriteTimeout:      timeouts.Write,
@@ -22,4 +23,6 @@ func NewH2H1Server(handler stdhttp.Handler, maxHeaderBytes int, timeouts Timeout
 		ReadHeaderTimeout: timeouts.ReadHeader,
 		MaxHeaderBytes:    maxHeaderBytes,
 	}
-}
\ No newline at end of file
+	ApplyKeepAlive(srv, ka)
+	return srv
+}
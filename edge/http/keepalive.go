@@ -0,0 +1,134 @@
+package http
+
+import (
+	"context"
+	"math/rand/v2"
+	"net"
+	stdhttp "net/http"
+	"sync"
+	"sync/atomic"
+	"time"
+)
+
+// KeepAlive bounds how long and how much one client connection is reused.
+// Zero fields are off.
+type KeepAlive struct {
+	MaxRequests int           // requests (HTTP/2: streams) per connection, then Connection: close
+	IdleTimeout time.Duration // idle connections are closed by the reaper after this...
+	IdleJitter  time.Duration // ...plus up to this much, drawn per idle period, so a burst of connections does not close at once
+	MaxConns    int           // the connection count the listener is sized for
+	CloseAbove  float64       // past this fraction of MaxConns, every response asks the client to close (HTTP/2: GOAWAY)
+}
+
+// ApplyKeepAlive installs ka on srv before it serves: request limits and
+// the close signal around its handler and, with ka.IdleTimeout, the idle
+// reaper (stopped by Shutdown); srv.IdleTimeout is then only its backstop.
+func ApplyKeepAlive(srv *stdhttp.Server, ka KeepAlive) {
+	t := newConnTracker(ka)
+	srv.Handler = t.wrap(srv.Handler)
+	srv.ConnContext = t.connContext
+	srv.ConnState = t.connState
+	if ka.IdleTimeout > 0 {
+		srv.IdleTimeout = max(srv.IdleTimeout, ka.IdleTimeout+ka.IdleJitter+time.Second)
+		ctx, stop := context.WithCancel(context.Background())
+		srv.RegisterOnShutdown(stop)
+		go t.reap(ctx)
+	}
+}
+
+type connKey struct{}
+
+type connInfo struct {
+	requests  atomic.Int64
+	idleSince time.Time     // zero while active; guarded by connTracker.mu
+	idleFor   time.Duration // this idle period's jittered timeout
+	paceOnce  sync.Once
+	pace      *bucket // Throttle.PerConn, shared by the connection's responses
+}
+
+// connTracker follows the server's connections (ConnContext, ConnState) for
+// KeepAlive: request counts, the open count, and idle times for the reaper.
+type connTracker struct {
+	ka    KeepAlive
+	open  atomic.Int64
+	mu    sync.Mutex
+	conns map[net.Conn]*connInfo
+}
+
+func newConnTracker(ka KeepAlive) *connTracker {
+	return &connTracker{ka: ka, conns: make(map[net.Conn]*connInfo)}
+}
+
+func (t *connTracker) connContext(ctx context.Context, c net.Conn) context.Context {
+	info := &connInfo{}
+	t.mu.Lock()
+	t.conns[c] = info
+	t.mu.Unlock()
+	t.open.Add(1)
+	return context.WithValue(ctx, connKey{}, info)
+}
+
+func (t *connTracker) connState(c net.Conn, state stdhttp.ConnState) {
+	t.mu.Lock()
+	defer t.mu.Unlock()
+	info, ok := t.conns[c]
+	if !ok {
+		return
+	}
+	switch state {
+	case stdhttp.StateActive:
+		info.idleSince = time.Time{}
+	case stdhttp.StateIdle:
+		info.idleSince = time.Now()
+		info.idleFor = t.ka.IdleTimeout
+		if t.ka.IdleJitter > 0 {
+			info.idleFor += rand.N(t.ka.IdleJitter)
+		}
+	case stdhttp.StateHijacked, stdhttp.StateClosed:
+		delete(t.conns, c)
+		t.open.Add(-1)
+	}
+}
+
+// wrap asks the client to close the connection after its last allowed
+// request, or on any request while the server is near its connection limit.
+func (t *connTracker) wrap(h stdhttp.Handler) stdhttp.Handler {
+	return stdhttp.HandlerFunc(func(w stdhttp.ResponseWriter, r *stdhttp.Request) {
+		if info, ok := r.Context().Value(connKey{}).(*connInfo); ok {
+			n := info.requests.Add(1)
+			if t.ka.MaxRequests > 0 && n >= int64(t.ka.MaxRequests) || t.nearLimit() {
+				w.Header().Set("Connection", "close")
+			}
+		}
+		h.ServeHTTP(w, r)
+	})
+}
+
+func (t *connTracker) nearLimit() bool {
+	return t.ka.MaxConns > 0 && t.ka.CloseAbove > 0 && float64(t.open.Load()) >= t.ka.CloseAbove*float64(t.ka.MaxConns)
+}
+
+// reap closes connections idle past their timeout until ctx is done.
+func (t *connTracker) reap(ctx context.Context) {
+	every := min(t.ka.IdleTimeout/4, time.Second)
+	tick := time.NewTicker(every)
+	defer tick.Stop()
+	for {
+		select {
+		case <-ctx.Done():
+			return
+		case now := <-tick.C:
+			var idle []net.Conn
+			t.mu.Lock()
+			for c, info := range t.conns {
+				if !info.idleSince.IsZero() && now.Sub(info.idleSince) >= info.idleFor {
+					idle = append(idle, c)
+				}
+			}
+			t.mu.Unlock()
+			for _, c := range idle {
+				_ = c.Close()
+			}
+		}
+	}
+}
@@ -10,6 +10,12 @@ func Normalize(r *stdhttp.Request, maxHeaderBytes int) (method, path, headersFla
 	method = r.Method
 	path = r.URL.RequestURI()
 	headersFlat, hdrSize = FlattenHeaders(r.Header)
+	// net/http moves Host out of the header map; core routes on it.
+	if r.Host != "" {
+		line := "Host: " + r.Host + "\r\n"
+		headersFlat = line + headersFlat
+		hdrSize += len(line)
+	}
 	return
 }
 
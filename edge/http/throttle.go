@@ -0,0 +1,144 @@
+package http
+
+import (
+	"context"
+	stdhttp "net/http"
+	"strconv"
+	"strings"
+	"sync"
+	"time"
+)
+
+// HeaderThrottle is the internal response header a handler or WAF rule sets
+// (sdk::throttle) to cap that response's body rate, in bytes/s. It can only
+// lower the rate Throttle would apply; never sent to clients.
+const HeaderThrottle = "X-OLWSX-Throttle"
+
+// throttleChunk is the most written between two pacing waits.
+const throttleChunk = 16 * 1024
+
+// Throttle paces response bodies, in bytes/s; zero rates are off. The most
+// specific rate applies, as for BodyLimits: the longest route prefix, else the
+// tenant's, else Default. Bodies over LargeAbove bytes are held to LargeRate
+// when that is lower. PerConn caps what one client connection receives in
+// total, across keep-alive responses and concurrent HTTP/2 streams.
+type Throttle struct {
+	Default    int64
+	Tenants    map[string]int64 // host -> bytes/s
+	Routes     map[string]int64 // path prefix -> bytes/s
+	LargeAbove int64
+	LargeRate  int64
+	PerConn    int64
+	Burst      int64 // bytes sent unpaced after a quiet period; 0: one second's worth
+}
+
+// For returns the rate for a size-byte response to host and path; 0 is unpaced.
+func (t *Throttle) For(host, path string, size int) int64 {
+	if t == nil {
+		return 0
+	}
+	rate := BodyLimits{Default: t.Default, Tenants: t.Tenants, Routes: t.Routes}.For(host, path)
+	if t.LargeAbove > 0 && int64(size) > t.LargeAbove {
+		rate = lowerRate(rate, t.LargeRate)
+	}
+	return rate
+}
+
+func (t *Throttle) burst(rate int64) int64 {
+	if t != nil && t.Burst > 0 {
+		return t.Burst
+	}
+	return rate
+}
+
+// lowerRate is the stricter of two rates, where 0 is unlimited.
+func lowerRate(a, b int64) int64 {
+	if a <= 0 || (b > 0 && b < a) {
+		return b
+	}
+	return a
+}
+
+// throttleHeader reports whether name is HeaderThrottle and its rate (0,
+// unlimited, when malformed).
+func throttleHeader(name, value string) (int64, bool) {
+	if !strings.EqualFold(name, HeaderThrottle) {
+		return 0, false
+	}
+	n, err := strconv.ParseInt(value, 10, 64)
+	if err != nil || n < 0 {
+		return 0, true
+	}
+	return n, true
+}
+
+// bucket is a token bucket in bytes. take may drive it negative; the debt is
+// the wait before those bytes may go out, so concurrent writers queue fairly.
+type bucket struct {
+	mu     sync.Mutex
+	rate   float64
+	burst  float64
+	tokens float64
+	last   time.Time
+}
+
+func newBucket(rate, burst int64) *bucket {
+	return &bucket{rate: float64(rate), burst: float64(burst), tokens: float64(burst), last: time.Now()}
+}
+
+// take reserves n bytes and returns how long to wait before sending them.
+func (b *bucket) take(n int) time.Duration {
+	b.mu.Lock()
+	defer b.mu.Unlock()
+	now := time.Now()
+	b.tokens = min(b.burst, b.tokens+now.Sub(b.last).Seconds()*b.rate)
+	b.last = now
+	b.tokens -= float64(n)
+	if b.tokens >= 0 {
+		return 0
+	}
+	return time.Duration(-b.tokens / b.rate * float64(time.Second))
+}
+
+// connBucket is the per-connection bucket, created on first use; nil without
+// a PerConn rate or outside ApplyKeepAlive's connection tracking.
+func (t *Throttle) connBucket(r *stdhttp.Request) *bucket {
+	info, _ := r.Context().Value(connKey{}).(*connInfo)
+	if t == nil || t.PerConn <= 0 || info == nil {
+		return nil
+	}
+	info.paceOnce.Do(func() { info.pace = newBucket(t.PerConn, t.burst(t.PerConn)) })
+	return info.pace
+}
+
+// writePaced writes body in chunks no faster than every bucket allows,
+// flushing each so the pacing reaches the client. It stops when ctx is done.
+func writePaced(ctx context.Context, w stdhttp.ResponseWriter, body []byte, buckets []*bucket) {
+	rc := stdhttp.NewResponseController(w)
+	var timer *time.Timer
+	for len(body) > 0 {
+		n := min(len(body), throttleChunk)
+		var wait time.Duration
+		for _, b := range buckets {
+			wait = max(wait, b.take(n))
+		}
+		if wait > 0 {
+			if timer == nil {
+				timer = time.NewTimer(wait)
+				defer timer.Stop()
+			} else {
+				timer.Reset(wait)
+			}
+			select {
+			case <-ctx.Done():
+				return
+			case <-timer.C:
+			}
+		}
+		if _, err := w.Write(body[:n]); err != nil {
+			return
+		}
+		_ = rc.Flush()
+		body = body[n:]
+	}
+}
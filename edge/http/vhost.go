@@ -0,0 +1,90 @@
+package http
+
+import "strings"
+
+// HeaderHostMismatch is set on requests whose TLS SNI names another host
+// than the Host they ask for (value: the SNI name), so WAF rules can match
+// on it; a client-sent copy is always dropped.
+const HeaderHostMismatch = "X-OLWSX-Host-Mismatch"
+
+// Verified mTLS client certificate (see edgetls.ClientAuth): subject CN and
+// SHA-256 fingerprint. Set only when the handshake verified a certificate;
+// client-sent copies are always dropped. Read by the WAF and plugins.
+const (
+	HeaderClientCertCN          = "X-OLWSX-Client-Cert-CN"
+	HeaderClientCertFingerprint = "X-OLWSX-Client-Cert-Fingerprint"
+)
+
+// VHosts validates the host of each request: the Host header, or the
+// authority of an absolute-form target (net/http puts that in r.Host and
+// ignores the header, as RFC 9112 3.2.2 requires).
+type VHosts struct {
+	Names   []string // lowercase, no port; "*.example.com" matches any subdomain. Empty: any valid host
+	Default string   // unknown hosts are served as this one; "" answers them 421
+}
+
+// Resolve returns the host to serve the request as, or the status to refuse
+// it with: 400 for a malformed host, 421 Misdirected Request for an unknown one.
+func (v VHosts) Resolve(hostport string) (string, int) {
+	if hostport == "" && v.Default != "" { // HTTP/1.0 without Host
+		return v.Default, 0
+	}
+	if !validHost(hostport) {
+		return "", 400
+	}
+	host := tenantHost(hostport)
+	if len(v.Names) == 0 {
+		return host, 0
+	}
+	for _, n := range v.Names {
+		if n == host || (strings.HasPrefix(n, "*.") && strings.HasSuffix(host, n[1:])) {
+			return host, 0
+		}
+	}
+	if v.Default != "" {
+		return v.Default, 0
+	}
+	return "", 421
+}
+
+// validHost accepts reg-name or IP-literal hosts with an optional numeric
+// port: letters, digits, '-' and '.' only, so no control characters, spaces,
+// userinfo or paths reach the vhost match, the cache key or the core.
+func validHost(hostport string) bool {
+	host, port := hostport, ""
+	if strings.HasPrefix(hostport, "[") {
+		end := strings.IndexByte(hostport, ']')
+		if end < 0 {
+			return false
+		}
+		host, port = hostport[1:end], hostport[end+1:]
+		if port != "" && port[0] != ':' {
+			return false
+		}
+		port = strings.TrimPrefix(port, ":")
+		if host == "" || strings.Trim(host, "0123456789abcdefABCDEF:.") != "" {
+			return false
+		}
+	} else {
+		if i := strings.IndexByte(hostport, ':'); i >= 0 {
+			host, port = hostport[:i], hostport[i+1:]
+		}
+		if host == "" || len(host) > 253 {
+			return false
+		}
+		for _, c := range []byte(host) {
+			if !(c >= 'a' && c <= 'z' || c >= 'A' && c <= 'Z' || c >= '0' && c <= '9' || c == '-' || c == '.') {
+				return false
+			}
+		}
+	}
+	if len(port) > 5 {
+		return false
+	}
+	for _, c := range []byte(port) {
+		if c < '0' || c > '9' {
+			return false
+		}
+	}
+	return true
+}
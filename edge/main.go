@@ -3,6 +3,7 @@ package main
 import (
 	"context"
 	"crypto/rand"
+	"crypto/tls"
 	"encoding/binary"
 	"fmt"
 	"log"
@@ -16,6 +17,8 @@ import (
 
 	edgehttp "olwsx/edge/http"
 	edgequic "olwsx/edge/quic"
+	"olwsx/edge/systemd"
+	edgetcp "olwsx/edge/tcp"
 	edgetls "olwsx/edge/tls"
 	edgews "olwsx/edge/websocket"
 	"olwsx/edge/wire"
@@ -36,22 +39,33 @@ func newIDs() (uint64, uint64) {
 
 // coreCall bridges edge to Actor Manager via Unix domain socket.
 // Edge forms a stable envelope and expects a binary response using wire.Response layout.
-func coreCall(method, path, headers string, body []byte, traceID, spanID uint64, hints uint32) (edgehttp.CoreResp, int) {
+// When ctx is cancelled (client gone) the pending I/O is cut short and the
+// connection closed, so the actor side sees the abort as EOF.
+func coreCall(ctx context.Context, method, path, headers string, body []byte, traceID, spanID uint64, hints uint32) (edgehttp.CoreResp, int) {
 	// Ensure socket exists
 	sock := ActorManagerSocket
 	if sock == "" {
 		return edgehttp.CoreResp{}, 1
 	}
-	conn, err := net.Dial("unix", sock)
+	var d net.Dialer
+	conn, err := d.DialContext(ctx, "unix", sock)
 	if err != nil {
+		if ctx.Err() != nil {
+			return edgehttp.CoreResp{}, 6
+		}
 		log.Printf("actor dial error: %v", err)
 		return edgehttp.CoreResp{}, 2
 	}
 	defer conn.Close()
+	stop := context.AfterFunc(ctx, func() { _ = conn.SetDeadline(time.Now()) })
+	defer stop()
 
 	// Write envelope
 	env := wire.WriteEnvelope(method, path, headers, body, traceID, spanID, hints)
 	if _, err := conn.Write(env); err != nil {
+		if ctx.Err() != nil {
+			return edgehttp.CoreResp{}, 6
+		}
 		log.Printf("actor write error: %v", err)
 		return edgehttp.CoreResp{}, 3
 	}
@@ -61,6 +75,9 @@ func coreCall(method, path, headers string, body []byte, traceID, spanID uint64,
 	buf := make([]byte, 1<<20) // 1MB temp buffer; actor should respect edge limits
 	n, err := conn.Read(buf)
 	if err != nil && n == 0 {
+		if ctx.Err() != nil {
+			return edgehttp.CoreResp{}, 6
+		}
 		log.Printf("actor read error: %v", err)
 		return edgehttp.CoreResp{}, 4
 	}
@@ -77,6 +94,27 @@ func coreCall(method, path, headers string, body []byte, traceID, spanID uint64,
 	}, 0
 }
 
+// Sockets passed by systemd socket activation, by listener name
+// (FileDescriptorName= in the socket unit; unnamed ones in the order
+// h2_h1_tls, ws, admin). Empty when started without activation.
+var activated map[string]net.Listener
+
+// listen adopts the activated socket for name, or binds addr itself.
+func listen(name, addr string, t edgetcp.Tuning) (net.Listener, error) {
+	if ln, ok := activated[name]; ok {
+		delete(activated, name)
+		return edgetcp.Wrap(ln, t)
+	}
+	return edgetcp.Listen("tcp", addr, t)
+}
+
+// notify reports state to systemd, when running under it.
+func notify(state string) {
+	if _, err := systemd.Notify(state); err != nil {
+		log.Printf("sd_notify %q failed: %v", state, err)
+	}
+}
+
 func main() {
 	// Ensure socket directory exists (edge doesn't create actor socket, only path directory)
 	if dir := filepath.Dir(ActorManagerSocket); dir != "" {
@@ -92,25 +130,51 @@ func main() {
 		cancel()
 	}()
 
+	// Socket activation (builds with -tags systemd)
+	var err error
+	activated, err = systemd.Listeners("h2_h1_tls", "ws", "admin")
+	if err != nil {
+		log.Fatalf("socket activation failed: %v", err)
+	}
+
 	// TLS config
 	cert, err := edgetls.LoadOrSelfSign("server.crt", "server.key")
 	if err != nil {
 		log.Fatalf("TLS cert load failed: %v", err)
 	}
 	tlsCfg := edgetls.ServerConfig(cert, TLSMinVersion13)
+	if err := TLSClientAuth.Apply(tlsCfg); err != nil {
+		log.Fatalf("mTLS config failed: %v", err)
+	}
+	if TLSOCSPStapling.CacheDir != "" {
+		_ = os.MkdirAll(TLSOCSPStapling.CacheDir, 0700)
+	}
+	if err := TLSOCSPStapling.Apply(ctx, tlsCfg); err != nil {
+		log.Fatalf("OCSP stapling config failed: %v", err)
+	}
+	go TLSCertExpiry.Watch(ctx, []tls.Certificate{cert})
+
+	// Heavy subsystems block startup unless the cold-start profile is on
+	if !LazyInit {
+		InitSubsystems(false)
+	}
 
 	// Handler wiring
 	handler := edgehttp.Handler(
 		MaxHeaderBytes,
-		MaxBodyBytes,
+		edgehttp.VHosts{Names: VHostNames, Default: DefaultVHost},
+		edgehttp.BodyLimits{Default: MaxBodyBytes, Tenants: TenantBodyLimits, Routes: RouteBodyLimits},
 		Limited,
 		func(path, ua string) bool { return Blocked(path, ua) },
 		func(remote string) bool { return Challenge(remote) },
 		coreCall,
+		edgehttp.NewEarlyHints(EarlyHintRoutes, EarlyHintsLearned),
+		&ResponseThrottle,
 		newIDs,
 		AccessLog,
 		MetricReject,
 		MetricError,
+		MetricClientAbort,
 	)
 
 	// HTTP/1.1 + HTTP/2
@@ -119,13 +183,15 @@ func main() {
 		Write:      WriteTimeout,
 		Idle:       IdleTimeout,
 		ReadHeader: ReadHeaderTO,
-	})
+	}, TLSKeepAlive)
 
-	ln, err := edgetls.ListenTLS("tcp", TLSListenAddr, tlsCfg)
+	tcpLn, err := listen("h2_h1_tls", TLSListenAddr, TLSListenerTuning)
 	if err != nil {
 		log.Fatalf("TLS listen failed: %v", err)
 	}
+	ln := edgetls.NewListener(tcpLn, tlsCfg)
 	defer ln.Close()
+	StartupListener("h2_h1_tls", TLSListenAddr, TLSListenerTuning)
 
 	go func() {
 		MetricTransport("h2_h1_tls")
@@ -141,12 +207,51 @@ func main() {
 	}
 
 	// WebSocket/SSE
-	go edgews.ListenAndServe(WSListenAddr)
+	if wsLn, err := listen("ws", WSListenAddr, WSListenerTuning); err != nil {
+		log.Printf("WS listen failed: %v", err)
+	} else {
+		defer wsLn.Close()
+		StartupListener("ws", WSListenAddr, WSListenerTuning)
+		go edgews.Serve(wsLn, WSKeepAlive)
+	}
 
 	// Admin health + metrics
-	go admin.ListenAndServe(AdminListenAddr, admin.HealthHandler, admin.MetricsHandler)
+	if adminLn, err := listen("admin", AdminListenAddr, AdminListenerTuning); err != nil {
+		log.Printf("admin listen failed: %v", err)
+	} else {
+		defer adminLn.Close()
+		StartupListener("admin", AdminListenAddr, AdminListenerTuning)
+		go admin.Serve(adminLn, AdminKeepAlive, admin.HealthHandler, admin.ReadyHandler, admin.MetricsHandler)
+	}
+
+	// Cold start: listeners are up, load the rest in the background
+	if LazyInit {
+		InitSubsystems(true)
+	}
+	for name, ln := range activated {
+		log.Printf("socket activation: no listener named %q, closing it", name)
+		ln.Close()
+	}
+
+	// Accepting: tell systemd, and keep its watchdog fed while running
+	notify("READY=1")
+	if wd := systemd.WatchdogInterval(); wd > 0 {
+		go func() {
+			t := time.NewTicker(wd / 2)
+			defer t.Stop()
+			for {
+				select {
+				case <-ctx.Done():
+					return
+				case <-t.C:
+					notify("WATCHDOG=1")
+				}
+			}
+		}()
+	}
 
 	<-ctx.Done()
+	notify("STOPPING=1")
 	log.Println("Shutting down edge...")
 	shutdownCtx, cancelSD := context.WithTimeout(context.Background(), ShutdownTimeout)
 	defer cancelSD()
@@ -2,18 +2,53 @@ package main
 
 import (
 	"log"
+	"strings"
 	"time"
+
+	edgetcp "olwsx/edge/tcp"
 )
 
 // In production this integrates real OTel and Prometheus exporters.
 // Here: stable hooks with structured fields for deterministic behavior.
 
-func AccessLog(method, path string, status, bodyLen int, hints uint32, dur time.Duration, remote, ua string) {
+// Query parameters whose values never reach the access log; the same list as
+// redact::SENSITIVE_PARAMS on the Rust side.
+var sensitiveParams = []string{"access_token", "api_key", "apikey", "password", "secret", "sig", "signature", "token"}
+
+func AccessLog(method, path string, status, bodyLen int, hints uint32, dur time.Duration, remote, ua, outcome string) {
 	if !AccessLogEnabled {
 		return
 	}
-	log.Printf("access method=%s path=%q status=%d body=%d hints=0x%08x dur=%s remote=%s ua=%q",
-		method, path, status, bodyLen, hints, dur, remote, ua)
+	log.Printf("access method=%s path=%q status=%d body=%d hints=0x%08x dur=%s remote=%s ua=%q outcome=%s",
+		method, redactTarget(path), status, bodyLen, hints, dur, remote, ua, outcome)
+}
+
+// redactTarget replaces the values of sensitive query parameters with
+// [REDACTED]; the path itself and other parameters are kept as sent.
+func redactTarget(target string) string {
+	path, query, ok := strings.Cut(target, "?")
+	if !ok {
+		return target
+	}
+	query, frag, hasFrag := strings.Cut(query, "#")
+	pairs := strings.Split(query, "&")
+	for i, p := range pairs {
+		name, _, isPair := strings.Cut(p, "=")
+		if !isPair {
+			continue
+		}
+		for _, s := range sensitiveParams {
+			if strings.EqualFold(name, s) {
+				pairs[i] = name + "=[REDACTED]"
+				break
+			}
+		}
+	}
+	out := path + "?" + strings.Join(pairs, "&")
+	if hasFrag {
+		out += "#" + frag
+	}
+	return out
 }
 
 func MetricReject(reason string) {
@@ -28,6 +63,14 @@ func MetricError(name string) {
 	}
 }
 
+// MetricClientAbort counts requests whose client disconnected before the
+// response; stage is where it was noticed (read_body, core).
+func MetricClientAbort(stage string) {
+	if MetricsEnabled {
+		log.Printf("metric client_abort stage=%s", stage)
+	}
+}
+
 func MetricTransport(name string) {
 	if MetricsEnabled {
 		log.Printf("metric transport name=%s", name)
@@ -40,8 +83,41 @@ func MetricWS(event string) {
 	}
 }
 
+// MetricCertExpiry is a gauge: days left on the certificate served for sni.
+func MetricCertExpiry(sni string, days float64) {
+	if MetricsEnabled {
+		log.Printf("metric tls_cert_days_to_expiry sni=%s days=%.1f", sni, days)
+	}
+}
+
+// CertExpiring is always emitted: the certificate for sni is close to (or past) expiry.
+func CertExpiring(sni string, days float64) {
+	log.Printf("event tls_cert_expiring sni=%s days=%.1f", sni, days)
+}
+
+// TLSEvent records OCSP stapling progress for a certificate (see edgetls.OCSPStapling).
+func TLSEvent(name, event string, err error) {
+	if err != nil {
+		log.Printf("event tls_ocsp name=%s event=%s err=%q", name, event, err)
+		return
+	}
+	if MetricsEnabled {
+		log.Printf("metric tls_ocsp name=%s event=%s", name, event)
+	}
+}
+
 func MetricAdmin(event string) {
 	if MetricsEnabled {
 		log.Printf("metric admin event=%s", event)
 	}
-}
\ No newline at end of file
+}
+
+// Startup events are always emitted; they record what each listener was bound with.
+func StartupListener(name, addr string, t edgetcp.Tuning) {
+	log.Printf("startup listener name=%s addr=%s %s", name, addr, t.Effective())
+}
+
+// StartupSubsystem records when a (possibly deferred) subsystem finished initializing.
+func StartupSubsystem(name, state string, dur time.Duration) {
+	log.Printf("startup subsystem name=%s state=%s dur=%s", name, state, dur)
+}
@@ -0,0 +1,48 @@
+package main
+
+import (
+	"log"
+	"time"
+
+	admin "olwsx/edge/admin"
+)
+
+// Subsystem is a piece of initialization that can be deferred until the
+// listeners are accepting (LazyInit). Until its init returns, the edge serves
+// without it and /ready reports it as pending.
+type Subsystem struct {
+	Name string
+	Init func() error
+}
+
+// Heavy subsystems, initialized in this order when run in the foreground.
+var Subsystems = []Subsystem{
+	{Name: "waf_rules", Init: LoadWAFRules},
+}
+
+// InitSubsystems registers every subsystem with the readiness tracker and
+// runs its init, either inline (blocking startup) or on its own goroutine.
+func InitSubsystems(background bool) {
+	for _, s := range Subsystems {
+		admin.Subsystems.Register(s.Name)
+	}
+	for _, s := range Subsystems {
+		if background {
+			go runSubsystem(s)
+		} else {
+			runSubsystem(s)
+		}
+	}
+}
+
+func runSubsystem(s Subsystem) {
+	start := time.Now()
+	if err := s.Init(); err != nil {
+		admin.Subsystems.MarkFailed(s.Name, err)
+		StartupSubsystem(s.Name, admin.StateFailed, time.Since(start))
+		log.Printf("subsystem %s init failed, serving without it: %v", s.Name, err)
+		return
+	}
+	admin.Subsystems.MarkReady(s.Name)
+	StartupSubsystem(s.Name, admin.StateReady, time.Since(start))
+}
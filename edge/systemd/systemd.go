@@ -0,0 +1,100 @@
+//go:build systemd
+
+// Package systemd adopts listeners passed by socket activation (LISTEN_FDS)
+// and reports readiness and watchdog pings over sd_notify. Built only with
+// the "systemd" tag; without it every call is a no-op (systemd_off.go).
+package systemd
+
+import (
+	"fmt"
+	"net"
+	"os"
+	"strconv"
+	"strings"
+	"syscall"
+	"time"
+)
+
+// Enabled reports whether this build talks to systemd.
+const Enabled = true
+
+const listenFdsStart = 3 // SD_LISTEN_FDS_START
+
+// Listeners returns the sockets systemd passed to this process, by the name
+// given in the socket unit (FileDescriptorName=). Sockets without a name
+// take the one at the same position in defaults. Nil when the process was
+// not socket activated. The LISTEN_* variables are cleared so children do
+// not inherit them.
+func Listeners(defaults ...string) (map[string]net.Listener, error) {
+	pid, err := strconv.Atoi(os.Getenv("LISTEN_PID"))
+	if err != nil || pid != os.Getpid() {
+		return nil, nil
+	}
+	n, err := strconv.Atoi(os.Getenv("LISTEN_FDS"))
+	if err != nil || n <= 0 {
+		return nil, nil
+	}
+	names := strings.Split(os.Getenv("LISTEN_FDNAMES"), ":")
+	for _, v := range []string{"LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"} {
+		_ = os.Unsetenv(v)
+	}
+	out := make(map[string]net.Listener, n)
+	for i := 0; i < n; i++ {
+		fd := listenFdsStart + i
+		syscall.CloseOnExec(fd)
+		name := ""
+		if i < len(names) && names[i] != "unknown" {
+			name = names[i]
+		}
+		if name == "" && i < len(defaults) {
+			name = defaults[i]
+		}
+		f := os.NewFile(uintptr(fd), name)
+		ln, err := net.FileListener(f) // dups the fd
+		f.Close()
+		if err != nil {
+			for _, l := range out {
+				l.Close()
+			}
+			return nil, fmt.Errorf("systemd: fd %d (%s): %w", fd, name, err)
+		}
+		out[name] = ln
+	}
+	return out, nil
+}
+
+// Notify sends state ("READY=1", "STOPPING=1", "WATCHDOG=1", "STATUS=...")
+// to the service manager. False, with no error, when NOTIFY_SOCKET is unset.
+func Notify(state string) (bool, error) {
+	sock := os.Getenv("NOTIFY_SOCKET")
+	if sock == "" {
+		return false, nil
+	}
+	if strings.HasPrefix(sock, "@") {
+		sock = "\x00" + sock[1:] // abstract namespace
+	}
+	conn, err := net.DialUnix("unixgram", nil, &net.UnixAddr{Name: sock, Net: "unixgram"})
+	if err != nil {
+		return false, err
+	}
+	defer conn.Close()
+	if _, err := conn.Write([]byte(state)); err != nil {
+		return false, err
+	}
+	return true, nil
+}
+
+// WatchdogInterval is the WatchdogSec= of the unit, or 0 when the watchdog is
+// off or meant for another process. Ping at half of it.
+func WatchdogInterval() time.Duration {
+	if p := os.Getenv("WATCHDOG_PID"); p != "" {
+		if pid, err := strconv.Atoi(p); err != nil || pid != os.Getpid() {
+			return 0
+		}
+	}
+	usec, err := strconv.ParseInt(os.Getenv("WATCHDOG_USEC"), 10, 64)
+	if err != nil || usec <= 0 {
+		return 0
+	}
+	return time.Duration(usec) * time.Microsecond
+}
@@ -0,0 +1,26 @@
+//go:build !systemd
+
+package systemd
+
+import (
+	"net"
+	"time"
+)
+
+// Enabled reports whether this build talks to systemd.
+const Enabled = false
+
+// Listeners: never socket activated without the "systemd" build tag.
+func Listeners(defaults ...string) (map[string]net.Listener, error) {
+	return nil, nil
+}
+
+// Notify: nothing to notify without the "systemd" build tag.
+func Notify(state string) (bool, error) {
+	return false, nil
+}
+
+// WatchdogInterval: no watchdog without the "systemd" build tag.
+func WatchdogInterval() time.Duration {
+	return 0
+}
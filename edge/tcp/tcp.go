@@ -0,0 +1,137 @@
+package tcp
+
+import (
+	"context"
+	"fmt"
+	"log"
+	"net"
+	"syscall"
+	"time"
+)
+
+// Tuning holds per-listener socket options. Zero values keep kernel defaults,
+// except NoDelay and KeepAlive, which are applied as given on every accepted
+// connection.
+type Tuning struct {
+	NoDelay           bool          // TCP_NODELAY on accepted connections
+	KeepAlive         bool          // SO_KEEPALIVE on accepted connections
+	KeepAliveIdle     time.Duration // idle time before the first probe
+	KeepAliveInterval time.Duration // time between probes
+	KeepAliveCount    int           // unanswered probes before the peer is dropped
+	Backlog           int           // listen(2) backlog; 0 = net.core.somaxconn
+	RecvBuffer        int           // SO_RCVBUF bytes, set before listen so window scaling applies
+	SendBuffer        int           // SO_SNDBUF bytes
+	FastOpen          int           // TCP Fast Open queue length; 0 = off (Linux only)
+}
+
+// LowLatency suits small request/response APIs: no Nagle, short keepalive.
+var LowLatency = Tuning{NoDelay: true, KeepAlive: true, KeepAliveIdle: 30 * time.Second, KeepAliveInterval: 10 * time.Second, KeepAliveCount: 3}
+
+// Bulk suits large downloads: big buffers, Nagle left on.
+var Bulk = Tuning{KeepAlive: true, KeepAliveIdle: 60 * time.Second, KeepAliveInterval: 15 * time.Second, KeepAliveCount: 4, RecvBuffer: 1 << 20, SendBuffer: 4 << 20}
+
+// String is the form reported in startup events.
+func (t Tuning) String() string {
+	backlog := "system"
+	if t.Backlog > 0 {
+		backlog = fmt.Sprint(t.Backlog)
+	}
+	return fmt.Sprintf("nodelay=%t keepalive=%t ka_idle=%s ka_interval=%s ka_count=%d backlog=%s rcvbuf=%d sndbuf=%d fastopen=%d",
+		t.NoDelay, t.KeepAlive, t.KeepAliveIdle, t.KeepAliveInterval, t.KeepAliveCount, backlog, t.RecvBuffer, t.SendBuffer, t.FastOpen)
+}
+
+// Effective is t with the options this platform cannot apply reset to their
+// defaults; it is what Listen actually binds with.
+func (t Tuning) Effective() Tuning {
+	e, _ := forPlatform(t)
+	return e
+}
+
+// Listen opens a TCP listener with t applied at bind time (buffers, Fast Open,
+// backlog) and at accept time (nodelay, keepalive). Options the platform does
+// not support are dropped with a warning rather than failing the bind.
+func Listen(network, addr string, t Tuning) (net.Listener, error) {
+	t, dropped := forPlatform(t)
+	for _, opt := range dropped {
+		log.Printf("tcp: %s: %s is not supported on this platform; using the system default", addr, opt)
+	}
+	var ln net.Listener
+	var err error
+	if t.Backlog > 0 {
+		ln, err = listenBacklog(network, addr, t)
+	} else {
+		lc := net.ListenConfig{
+			KeepAlive: -1, // accepted connections are configured in Accept
+			Control: func(_, _ string, c syscall.RawConn) error {
+				var serr error
+				if err := c.Control(func(fd uintptr) { serr = setListenerOpts(fd, t) }); err != nil {
+					return err
+				}
+				return serr
+			},
+		}
+		ln, err = lc.Listen(context.Background(), network, addr)
+	}
+	if err != nil {
+		return nil, err
+	}
+	tl, ok := ln.(*net.TCPListener)
+	if !ok {
+		ln.Close()
+		return nil, fmt.Errorf("tcp: %s is not a TCP listener", addr)
+	}
+	return &listener{TCPListener: tl, t: t}, nil
+}
+
+// Wrap applies t's accept-time options (nodelay, keepalive, buffers) to a
+// listener bound elsewhere, e.g. one inherited through socket activation;
+// bind-time options (backlog, Fast Open) stay as its creator set them.
+func Wrap(ln net.Listener, t Tuning) (net.Listener, error) {
+	tl, ok := ln.(*net.TCPListener)
+	if !ok {
+		return nil, fmt.Errorf("tcp: %s is not a TCP listener", ln.Addr())
+	}
+	return &listener{TCPListener: tl, t: t}, nil
+}
+
+type listener struct {
+	*net.TCPListener
+	t Tuning
+}
+
+func (l *listener) Accept() (net.Conn, error) {
+	c, err := l.AcceptTCP()
+	if err != nil {
+		return nil, err
+	}
+	if err := applyConn(c, l.t); err != nil {
+		c.Close()
+		return nil, err
+	}
+	return c, nil
+}
+
+func applyConn(c *net.TCPConn, t Tuning) error {
+	if err := c.SetNoDelay(t.NoDelay); err != nil {
+		return err
+	}
+	if err := c.SetKeepAliveConfig(net.KeepAliveConfig{
+		Enable:   t.KeepAlive,
+		Idle:     t.KeepAliveIdle,
+		Interval: t.KeepAliveInterval,
+		Count:    t.KeepAliveCount,
+	}); err != nil {
+		return err
+	}
+	if t.RecvBuffer > 0 {
+		if err := c.SetReadBuffer(t.RecvBuffer); err != nil {
+			return err
+		}
+	}
+	if t.SendBuffer > 0 {
+		if err := c.SetWriteBuffer(t.SendBuffer); err != nil {
+			return err
+		}
+	}
+	return nil
+}
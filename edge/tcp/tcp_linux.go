@@ -0,0 +1,87 @@
+//go:build linux
+
+package tcp
+
+import (
+	"net"
+	"os"
+	"syscall"
+)
+
+const tcpFastOpen = 0x17 // TCP_FASTOPEN (linux/tcp.h); not exported by syscall
+
+// Every Tuning field is supported on Linux.
+func forPlatform(t Tuning) (Tuning, []string) {
+	return t, nil
+}
+
+func setListenerOpts(fd uintptr, t Tuning) error {
+	s := int(fd)
+	if t.RecvBuffer > 0 {
+		if err := syscall.SetsockoptInt(s, syscall.SOL_SOCKET, syscall.SO_RCVBUF, t.RecvBuffer); err != nil {
+			return os.NewSyscallError("setsockopt SO_RCVBUF", err)
+		}
+	}
+	if t.SendBuffer > 0 {
+		if err := syscall.SetsockoptInt(s, syscall.SOL_SOCKET, syscall.SO_SNDBUF, t.SendBuffer); err != nil {
+			return os.NewSyscallError("setsockopt SO_SNDBUF", err)
+		}
+	}
+	if t.FastOpen > 0 {
+		if err := syscall.SetsockoptInt(s, syscall.IPPROTO_TCP, tcpFastOpen, t.FastOpen); err != nil {
+			return os.NewSyscallError("setsockopt TCP_FASTOPEN", err)
+		}
+	}
+	return nil
+}
+
+// net.ListenConfig always uses somaxconn, so a custom backlog needs the
+// socket built by hand and handed to the runtime afterwards.
+func listenBacklog(network, addr string, t Tuning) (net.Listener, error) {
+	ta, err := net.ResolveTCPAddr(network, addr)
+	if err != nil {
+		return nil, err
+	}
+	family := syscall.AF_INET6
+	var sa syscall.Sockaddr
+	if ip4 := ta.IP.To4(); ip4 != nil || network == "tcp4" {
+		family = syscall.AF_INET
+		a := &syscall.SockaddrInet4{Port: ta.Port}
+		copy(a.Addr[:], ip4)
+		sa = a
+	} else {
+		a := &syscall.SockaddrInet6{Port: ta.Port}
+		copy(a.Addr[:], ta.IP.To16())
+		sa = a
+	}
+	fd, err := syscall.Socket(family, syscall.SOCK_STREAM|syscall.SOCK_CLOEXEC, syscall.IPPROTO_TCP)
+	if err != nil {
+		return nil, os.NewSyscallError("socket", err)
+	}
+	fail := func(op string, err error) (net.Listener, error) {
+		syscall.Close(fd)
+		return nil, os.NewSyscallError(op, err)
+	}
+	if err := syscall.SetsockoptInt(fd, syscall.SOL_SOCKET, syscall.SO_REUSEADDR, 1); err != nil {
+		return fail("setsockopt SO_REUSEADDR", err)
+	}
+	if family == syscall.AF_INET6 && network != "tcp6" {
+		// ":port" listens on both stacks, like net.Listen
+		if err := syscall.SetsockoptInt(fd, syscall.IPPROTO_IPV6, syscall.IPV6_V6ONLY, 0); err != nil {
+			return fail("setsockopt IPV6_V6ONLY", err)
+		}
+	}
+	if err := setListenerOpts(uintptr(fd), t); err != nil {
+		syscall.Close(fd)
+		return nil, err
+	}
+	if err := syscall.Bind(fd, sa); err != nil {
+		return fail("bind", err)
+	}
+	if err := syscall.Listen(fd, t.Backlog); err != nil {
+		return fail("listen", err)
+	}
+	f := os.NewFile(uintptr(fd), "tcp-listener:"+addr)
+	defer f.Close() // FileListener dups the descriptor
+	return net.FileListener(f)
+}
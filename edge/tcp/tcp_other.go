@@ -0,0 +1,35 @@
+//go:build !linux
+
+package tcp
+
+import (
+	"errors"
+	"fmt"
+	"net"
+)
+
+// Custom backlogs and Fast Open are Linux only; Listen falls back to the
+// system defaults for both.
+func forPlatform(t Tuning) (Tuning, []string) {
+	var dropped []string
+	if t.Backlog > 0 {
+		dropped = append(dropped, fmt.Sprintf("backlog=%d", t.Backlog))
+		t.Backlog = 0
+	}
+	if t.FastOpen > 0 {
+		dropped = append(dropped, fmt.Sprintf("fastopen=%d", t.FastOpen))
+		t.FastOpen = 0
+	}
+	return t, dropped
+}
+
+// Buffers are applied per accepted connection; forPlatform has already
+// cleared Fast Open.
+func setListenerOpts(_ uintptr, _ Tuning) error {
+	return nil
+}
+
+// Unreachable: forPlatform clears Backlog before Listen gets here.
+func listenBacklog(_, _ string, _ Tuning) (net.Listener, error) {
+	return nil, errors.New("tcp: custom backlog is only supported on linux")
+}
@@ -0,0 +1,77 @@
+package tls
+
+import (
+	"context"
+	"crypto/tls"
+	"crypto/x509"
+	"time"
+)
+
+// CertExpiry reports the days left on the served certificates per SNI name
+// they cover, so an expiring one is caught before clients refuse it.
+type CertExpiry struct {
+	Every    time.Duration // 0: hourly
+	WarnDays float64       // Warn fires at or below this; 0: 21 days
+	Gauge    func(name string, days float64)
+	Warn     func(name string, days float64)
+}
+
+// Watch reports once now, then every e.Every until ctx is done.
+func (e CertExpiry) Watch(ctx context.Context, certs []tls.Certificate) {
+	every := e.Every
+	if every <= 0 {
+		every = time.Hour
+	}
+	warn := e.WarnDays
+	if warn <= 0 {
+		warn = 21
+	}
+	t := time.NewTicker(every)
+	defer t.Stop()
+	for {
+		for name, days := range DaysToExpiry(certs, time.Now()) {
+			if e.Gauge != nil {
+				e.Gauge(name, days)
+			}
+			if e.Warn != nil && days <= warn {
+				e.Warn(name, days)
+			}
+		}
+		select {
+		case <-ctx.Done():
+			return
+		case <-t.C:
+		}
+	}
+}
+
+// DaysToExpiry maps each SNI name to the days left on the latest-expiring
+// certificate that covers it (negative once expired).
+func DaysToExpiry(certs []tls.Certificate, now time.Time) map[string]float64 {
+	out := map[string]float64{}
+	for _, c := range certs {
+		leaf, err := leafOf(c)
+		if err != nil {
+			continue
+		}
+		days := leaf.NotAfter.Sub(now).Hours() / 24
+		for _, name := range certNames(leaf) {
+			if cur, ok := out[name]; !ok || days > cur {
+				out[name] = days
+			}
+		}
+	}
+	return out
+}
+
+// certNames are the SNI names a certificate answers for: its DNS SANs, else
+// its CN; never empty.
+func certNames(leaf *x509.Certificate) []string {
+	if len(leaf.DNSNames) > 0 {
+		return leaf.DNSNames
+	}
+	if leaf.Subject.CommonName != "" {
+		return []string{leaf.Subject.CommonName}
+	}
+	return []string{"-"}
+}
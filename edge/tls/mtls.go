@@ -0,0 +1,103 @@
+package tls
+
+import (
+	"crypto/sha256"
+	"crypto/tls"
+	"crypto/x509"
+	"encoding/hex"
+	"encoding/pem"
+	"errors"
+	"fmt"
+	"os"
+	"strings"
+)
+
+// ClientAuth is listener-side mutual TLS. Client certificates are verified
+// against CAFile during the handshake; one whose serial a CRL lists, or (with
+// an allowlist) whose fingerprint is not listed, is refused there too.
+type ClientAuth struct {
+	CAFile   string   // PEM bundle of accepted client CAs; "" disables mTLS
+	Required bool     // false: a certificate is optional, but verified when sent
+	CRLFiles []string // PEM or DER revocation lists issued by those CAs
+	Allow    []string // SHA-256 fingerprints (hex, ':' allowed); empty: any the CA signed
+}
+
+// Apply turns on client certificate verification in cfg. Files are read
+// once; a reload builds a new config.
+func (a ClientAuth) Apply(cfg *tls.Config) error {
+	if a.CAFile == "" {
+		return nil
+	}
+	pemCAs, err := os.ReadFile(a.CAFile)
+	if err != nil {
+		return err
+	}
+	pool := x509.NewCertPool()
+	if !pool.AppendCertsFromPEM(pemCAs) {
+		return fmt.Errorf("mtls: no certificates in %s", a.CAFile)
+	}
+	revoked := map[string]bool{} // issuer DER subject + serial
+	for _, path := range a.CRLFiles {
+		raw, err := os.ReadFile(path)
+		if err != nil {
+			return err
+		}
+		if b, _ := pem.Decode(raw); b != nil {
+			raw = b.Bytes
+		}
+		crl, err := x509.ParseRevocationList(raw)
+		if err != nil {
+			return fmt.Errorf("mtls: %s: %w", path, err)
+		}
+		for _, e := range crl.RevokedCertificateEntries {
+			revoked[string(crl.RawIssuer)+e.SerialNumber.String()] = true
+		}
+	}
+	allow := map[string]bool{}
+	for _, fp := range a.Allow {
+		allow[normalizeFingerprint(fp)] = true
+	}
+
+	cfg.ClientCAs = pool
+	cfg.ClientAuth = tls.VerifyClientCertIfGiven
+	if a.Required {
+		cfg.ClientAuth = tls.RequireAndVerifyClientCert
+	}
+	cfg.VerifyConnection = func(cs tls.ConnectionState) error {
+		if len(cs.VerifiedChains) == 0 {
+			return nil // no certificate sent (optional mode)
+		}
+		for _, chain := range cs.VerifiedChains {
+			for _, c := range chain {
+				if revoked[string(c.RawIssuer)+c.SerialNumber.String()] {
+					return errors.New("mtls: client certificate revoked")
+				}
+			}
+		}
+		if len(allow) > 0 && !allow[Fingerprint(cs.VerifiedChains[0][0])] {
+			return errors.New("mtls: client certificate not allowed")
+		}
+		return nil
+	}
+	return nil
+}
+
+// ClientIdentity returns the subject CN and SHA-256 fingerprint of a
+// verified client certificate; empty when none was verified.
+func ClientIdentity(cs *tls.ConnectionState) (cn, fingerprint string) {
+	if cs == nil || len(cs.VerifiedChains) == 0 || len(cs.VerifiedChains[0]) == 0 {
+		return "", ""
+	}
+	leaf := cs.VerifiedChains[0][0]
+	return leaf.Subject.CommonName, Fingerprint(leaf)
+}
+
+// Fingerprint is the lowercase hex SHA-256 of the certificate's DER.
+func Fingerprint(c *x509.Certificate) string {
+	sum := sha256.Sum256(c.Raw)
+	return hex.EncodeToString(sum[:])
+}
+
+func normalizeFingerprint(fp string) string {
+	return strings.ToLower(strings.ReplaceAll(strings.TrimSpace(fp), ":", ""))
+}
@@ -0,0 +1,246 @@
+package tls
+
+import (
+	"bytes"
+	"context"
+	"crypto"
+	"crypto/tls"
+	"crypto/x509"
+	"errors"
+	"fmt"
+	"io"
+	"net/http"
+	"os"
+	"path/filepath"
+	"sync/atomic"
+	"time"
+
+	"golang.org/x/crypto/ocsp"
+)
+
+// OCSPStapling staples a fresh OCSP response to each served certificate. The
+// response is fetched from the responder the certificate names, kept in
+// memory (and in CacheDir, so a restart staples at once) and refreshed
+// halfway through its validity. A response past its NextUpdate is dropped
+// rather than served, and only "good" responses are stapled.
+type OCSPStapling struct {
+	Enabled  bool
+	CacheDir string        // "": memory only
+	Timeout  time.Duration // per fetch; 0: 10s
+	Retry    time.Duration // after a failed fetch; 0: 5m
+
+	// Event reports fetched, cached, failed, expired, revoked, unknown and
+	// skipped (no responder or issuer: self-signed, say) per certificate.
+	Event func(name, event string, err error)
+}
+
+// Apply serves cfg.Certificates through cfg.GetCertificate, so the staple
+// can change under a live listener, and refreshes staples until ctx is done.
+func (o OCSPStapling) Apply(ctx context.Context, cfg *tls.Config) error {
+	if !o.Enabled || len(cfg.Certificates) == 0 {
+		return nil
+	}
+	var staplers []*stapler
+	for _, c := range cfg.Certificates {
+		s, err := o.newStapler(c)
+		if err != nil {
+			return err
+		}
+		staplers = append(staplers, s)
+	}
+	cfg.Certificates = nil
+	cfg.GetCertificate = func(hello *tls.ClientHelloInfo) (*tls.Certificate, error) {
+		for _, s := range staplers {
+			if c := s.cur.Load(); hello.SupportsCertificate(c) == nil {
+				return c, nil
+			}
+		}
+		return staplers[0].cur.Load(), nil
+	}
+	for _, s := range staplers {
+		if s.issuer == nil || len(s.leaf.OCSPServer) == 0 {
+			o.event(s.name, "skipped", nil)
+			continue
+		}
+		go s.run(ctx)
+	}
+	return nil
+}
+
+func (o OCSPStapling) event(name, event string, err error) {
+	if o.Event != nil {
+		o.Event(name, event, err)
+	}
+}
+
+type stapler struct {
+	o            OCSPStapling
+	name         string // for events: the first SNI name the certificate covers
+	base         tls.Certificate
+	leaf, issuer *x509.Certificate // issuer nil when the chain has none
+	cur          atomic.Pointer[tls.Certificate]
+	nextUpdate   time.Time // of the stapled response; zero: nothing stapled
+}
+
+func (o OCSPStapling) newStapler(c tls.Certificate) (*stapler, error) {
+	leaf, err := leafOf(c)
+	if err != nil {
+		return nil, err
+	}
+	s := &stapler{o: o, name: certNames(leaf)[0], base: c, leaf: leaf}
+	if len(c.Certificate) > 1 {
+		if s.issuer, err = x509.ParseCertificate(c.Certificate[1]); err != nil {
+			return nil, fmt.Errorf("ocsp: %s: issuer: %w", s.name, err)
+		}
+	}
+	s.cur.Store(&s.base)
+	return s, nil
+}
+
+func (s *stapler) run(ctx context.Context) {
+	next := time.Now()
+	if raw, err := os.ReadFile(s.cachePath()); err == nil {
+		if r, err := ocsp.ParseResponseForCert(raw, s.leaf, s.issuer); err == nil && s.staple(raw, r) {
+			s.o.event(s.name, "cached", nil)
+			next = refreshAt(r)
+		}
+	}
+	timer := time.NewTimer(time.Until(next))
+	defer timer.Stop()
+	for {
+		select {
+		case <-ctx.Done():
+			return
+		case <-timer.C:
+		}
+		timer.Reset(time.Until(s.refresh(ctx)))
+	}
+}
+
+// refresh fetches a new response and returns when to try again.
+func (s *stapler) refresh(ctx context.Context) time.Time {
+	raw, r, err := s.fetch(ctx)
+	if err == nil && s.staple(raw, r) {
+		s.o.event(s.name, "fetched", nil)
+		s.save(raw)
+		return refreshAt(r)
+	}
+	if err != nil {
+		s.o.event(s.name, "failed", err)
+	}
+	if !s.nextUpdate.IsZero() && time.Now().After(s.nextUpdate) {
+		s.cur.Store(&s.base)
+		s.nextUpdate = time.Time{}
+		s.o.event(s.name, "expired", nil)
+	}
+	retry := s.o.Retry
+	if retry <= 0 {
+		retry = 5 * time.Minute
+	}
+	return time.Now().Add(retry)
+}
+
+// staple installs raw if r says the certificate is good and is still valid.
+func (s *stapler) staple(raw []byte, r *ocsp.Response) bool {
+	switch {
+	case r.Status == ocsp.Revoked:
+		s.o.event(s.name, "revoked", nil)
+		return false
+	case r.Status != ocsp.Good:
+		s.o.event(s.name, "unknown", nil)
+		return false
+	case !r.NextUpdate.IsZero() && time.Now().After(r.NextUpdate):
+		return false
+	}
+	c := s.base
+	c.OCSPStaple = raw
+	s.cur.Store(&c)
+	s.nextUpdate = r.NextUpdate
+	return true
+}
+
+func (s *stapler) fetch(ctx context.Context) ([]byte, *ocsp.Response, error) {
+	der, err := ocsp.CreateRequest(s.leaf, s.issuer, &ocsp.RequestOptions{Hash: crypto.SHA1})
+	if err != nil {
+		return nil, nil, err
+	}
+	timeout := s.o.Timeout
+	if timeout <= 0 {
+		timeout = 10 * time.Second
+	}
+	ctx, cancel := context.WithTimeout(ctx, timeout)
+	defer cancel()
+	req, err := http.NewRequestWithContext(ctx, http.MethodPost, s.leaf.OCSPServer[0], bytes.NewReader(der))
+	if err != nil {
+		return nil, nil, err
+	}
+	req.Header.Set("Content-Type", "application/ocsp-request")
+	req.Header.Set("Accept", "application/ocsp-response")
+	resp, err := http.DefaultClient.Do(req)
+	if err != nil {
+		return nil, nil, err
+	}
+	defer resp.Body.Close()
+	if resp.StatusCode != http.StatusOK {
+		return nil, nil, fmt.Errorf("ocsp: responder answered %s", resp.Status)
+	}
+	raw, err := io.ReadAll(io.LimitReader(resp.Body, 1<<20))
+	if err != nil {
+		return nil, nil, err
+	}
+	r, err := ocsp.ParseResponseForCert(raw, s.leaf, s.issuer)
+	if err != nil {
+		return nil, nil, err
+	}
+	return raw, r, nil
+}
+
+func (s *stapler) cachePath() string {
+	if s.o.CacheDir == "" {
+		return ""
+	}
+	return filepath.Join(s.o.CacheDir, Fingerprint(s.leaf)+".ocsp")
+}
+
+// save writes the response next to its final name and renames it into place.
+func (s *stapler) save(raw []byte) {
+	path := s.cachePath()
+	if path == "" {
+		return
+	}
+	tmp := path + ".tmp"
+	err := os.WriteFile(tmp, raw, 0o600)
+	if err == nil {
+		err = os.Rename(tmp, path)
+	}
+	if err != nil {
+		s.o.event(s.name, "failed", err)
+	}
+}
+
+// refreshAt is halfway through r's validity, or an hour out when the
+// responder gives no NextUpdate, and never sooner than a minute from now.
+func refreshAt(r *ocsp.Response) time.Time {
+	at := time.Now().Add(time.Hour)
+	if !r.NextUpdate.IsZero() {
+		at = r.ThisUpdate.Add(r.NextUpdate.Sub(r.ThisUpdate) / 2)
+	}
+	return later(at, time.Now().Add(time.Minute))
+}
+
+func later(a, b time.Time) time.Time {
+	if a.After(b) {
+		return a
+	}
+	return b
+}
+
+func leafOf(c tls.Certificate) (*x509.Certificate, error) {
+	if c.Leaf != nil {
+		return c.Leaf, nil
+	}
+	if len(c.Certificate) == 0 {
+		return nil, errors.New("tls: certificate has no chain")
+	}
+	return x509.ParseCertificate(c.Certificate[0])
+}
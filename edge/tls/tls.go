@@ -11,6 +11,8 @@ import (
 	"net"
 	"os"
 	"time"
+
+	edgetcp "olwsx/edge/tcp"
 )
 
 // LoadOrSelfSign loads cert/key if present, otherwise generates a short-lived self-signed cert.
@@ -40,6 +42,20 @@ func ListenTLS(network, addr string, cfg *tls.Config) (net.Listener, error) {
 	return tls.Listen(network, addr, cfg)
 }
 
+// ListenTLSTuned is ListenTLS with socket tuning applied underneath the TLS layer.
+func ListenTLSTuned(network, addr string, cfg *tls.Config, t edgetcp.Tuning) (net.Listener, error) {
+	ln, err := edgetcp.Listen(network, addr, t)
+	if err != nil {
+		return nil, err
+	}
+	return tls.NewListener(ln, cfg), nil
+}
+
+// NewListener serves TLS on a listener that is already bound (socket activation).
+func NewListener(inner net.Listener, cfg *tls.Config) net.Listener {
+	return tls.NewListener(inner, cfg)
+}
+
 func fileExists(path string) bool {
 	_, err := os.Stat(path)
 	return err == nil
@@ -3,21 +3,46 @@ package main
 import (
 	"regexp"
 	"strings"
+	"sync/atomic"
 )
 
 var (
-	pathTraversal = regexp.MustCompile(`(\.\./)|(/\.{2})`)
-	uaBlacklist   = []string{"sqlmap", "nmap", "nikto", "wpscan", "masscan", "curl/", "wget"}
+	pathRulePatterns = []string{`(\.\./)|(/\.{2})`}
+	uaBlacklist      = []string{"sqlmap", "nmap", "nikto", "wpscan", "masscan", "curl/", "wget"}
+
+	// Compiled path rules; nil until LoadWAFRules has run. Until then the WAF
+	// fails closed: every request is blocked.
+	pathRules atomic.Pointer[[]*regexp.Regexp]
 )
 
+// LoadWAFRules compiles the path ruleset and swaps it in.
+func LoadWAFRules() error {
+	rules := make([]*regexp.Regexp, 0, len(pathRulePatterns))
+	for _, p := range pathRulePatterns {
+		re, err := regexp.Compile(p)
+		if err != nil {
+			return err
+		}
+		rules = append(rules, re)
+	}
+	pathRules.Store(&rules)
+	return nil
+}
+
 // Blocked returns true if path or UA is suspicious.
 func Blocked(path, ua string) bool {
 	if !EnableWAF {
 		return false
 	}
-	if pathTraversal.MatchString(path) {
+	rules := pathRules.Load()
+	if rules == nil {
 		return true
 	}
+	for _, re := range *rules {
+		if re.MatchString(path) {
+			return true
+		}
+	}
 	ua = strings.ToLower(ua)
 	for _, sig := range uaBlacklist {
 		if strings.Contains(ua, sig) {
@@ -25,4 +50,4 @@ func Blocked(path, ua string) bool {
 		}
 	}
 	return false
-}
\ No newline at end of file
+}
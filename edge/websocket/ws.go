@@ -2,9 +2,12 @@ package websocket
 
 import (
 	"log"
+	"net"
 	"net/http"
 
 	"github.com/gorilla/websocket"
+
+	edgehttp "olwsx/edge/http"
 )
 
 var upgrader = websocket.Upgrader{
@@ -12,15 +15,15 @@ var upgrader = websocket.Upgrader{
 	CheckOrigin: func(r *http.Request) bool { return true },
 }
 
-func ListenAndServe(addr string) {
+// Serve runs the WebSocket endpoint on ln; ka applies to connections until
+// they upgrade.
+func Serve(ln net.Listener, ka edgehttp.KeepAlive) {
 	mux := http.NewServeMux()
 	mux.HandleFunc("/ws", wsHandler)
-	s := &http.Server{
-		Addr:    addr,
-		Handler: mux,
-	}
-	log.Printf("Edge WebSocket server on %s", addr)
-	if err := s.ListenAndServe(); err != nil && err != http.ErrServerClosed {
+	s := &http.Server{Handler: mux}
+	edgehttp.ApplyKeepAlive(s, ka)
+	log.Printf("Edge WebSocket server on %s", ln.Addr())
+	if err := s.Serve(ln); err != nil && err != http.ErrServerClosed {
 		log.Printf("WS server error: %v", err)
 	}
 }
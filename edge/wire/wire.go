@@ -5,6 +5,12 @@ const (
 	HintRateLimited uint32 = 0x1
 	HintWAFBlocked  uint32 = 0x2
 	HintChallenged  uint32 = 0x4
+	// Header phase of an Expect: 100-continue request: the body is withheld.
+	// Core answers 100 to let the upload proceed, or the final status.
+	HintHeaderPhase uint32 = 0x8
+	// TLS SNI named another host than the request's Host (sent in
+	// X-OLWSX-Host-Mismatch). Core decides whether that matters.
+	HintHostMismatch uint32 = 0x10
 )
 
 // Envelope binary layout (length-prefixed slices). Edge serializes requests to Actor Manager:
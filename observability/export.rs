@@ -0,0 +1,210 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: observability/export.rs
+// Role: JSON exposition of a metric Registry (cumulative and delta modes)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Cumulative snapshot: every metric as of now.
+// - Delta since a scrape cursor; each response hands out the next cursor.
+// - Bounded cursor memory; unknown/evicted cursors restart with `reset: true`.
+// - Tenant snapshot: one tenant's series only, safe for tenant-facing pages.
+// =============================================================================
+
+use crate::metrics::LAT_BOUNDS;
+use crate::registry::{Registry, Sample, SampleValue};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAX_CURSORS: usize = 64;
+
+struct Snapshot {
+    cursor: String,
+    ts_ms: u64,
+    samples: Vec<Sample>,
+}
+
+pub struct JsonExporter {
+    registry: Registry,
+    cursors: Mutex<(u64, VecDeque<Snapshot>)>, // (next id, retained snapshots)
+}
+
+impl JsonExporter {
+    pub fn new(registry: Registry) -> Self {
+        Self { registry, cursors: Mutex::new((1, VecDeque::new())) }
+    }
+
+    // {"mode":"cumulative","ts_ms":..,"metrics":[..]}
+    pub fn cumulative(&self) -> String {
+        let ts = now_ms();
+        let samples = self.registry.gather();
+        let mut out = format!("{{\"mode\":\"cumulative\",\"ts_ms\":{},\"metrics\":", ts);
+        write_samples(&mut out, &samples);
+        out.push('}');
+        out
+    }
+
+    // {"mode":"tenant","tenant":"..","ts_ms":..,"metrics":[..]}
+    // Cumulative values of that tenant's series only (see Registry::gather_tenant).
+    pub fn tenant(&self, tenant: &str) -> String {
+        let samples = self.registry.gather_tenant(tenant);
+        let mut out = String::from("{\"mode\":\"tenant\",\"tenant\":");
+        write_str(&mut out, tenant);
+        out.push_str(&format!(",\"ts_ms\":{},\"metrics\":", now_ms()));
+        write_samples(&mut out, &samples);
+        out.push('}');
+        out
+    }
+
+    // {"mode":"delta","ts_ms":..,"cursor":"..","reset":bool,"interval_ms":..,"metrics":[..]}
+    // Counters and histograms are differences since `cursor`; gauges are current values.
+    // Without a known cursor the full cumulative values are returned with reset=true.
+    pub fn delta(&self, cursor: Option<&str>) -> String {
+        self.delta_at(cursor, now_ms())
+    }
+
+    fn delta_at(&self, cursor: Option<&str>, ts: u64) -> String {
+        let samples = self.registry.gather();
+        let mut st = self.cursors.lock().unwrap_or_else(|e| e.into_inner());
+        let prev = cursor.and_then(|c| st.1.iter().find(|s| s.cursor == c));
+        let (reset, interval, delta) = match prev {
+            Some(p) => (false, ts.saturating_sub(p.ts_ms), diff(&p.samples, &samples)),
+            None => (true, 0, samples.clone()),
+        };
+        let next = format!("c{}", st.0);
+        st.0 += 1;
+        st.1.push_back(Snapshot { cursor: next.clone(), ts_ms: ts, samples });
+        while st.1.len() > MAX_CURSORS {
+            st.1.pop_front();
+        }
+        drop(st);
+
+        let mut out = format!("{{\"mode\":\"delta\",\"ts_ms\":{},\"cursor\":\"{}\",\"reset\":{},\"interval_ms\":{},\"metrics\":", ts, next, reset, interval);
+        write_samples(&mut out, &delta);
+        out.push('}');
+        out
+    }
+}
+
+fn diff(prev: &[Sample], cur: &[Sample]) -> Vec<Sample> {
+    cur.iter()
+        .map(|c| {
+            let p = prev.iter().find(|p| p.name == c.name && p.labels == c.labels);
+            let value = match (&c.value, p.map(|p| &p.value)) {
+                // a counter that went backwards was reset; report its current value
+                (SampleValue::Counter(v), Some(SampleValue::Counter(pv))) if v >= pv => SampleValue::Counter(v - pv),
+                (SampleValue::Histogram { bins, count, sum_us }, Some(SampleValue::Histogram { bins: pb, count: pc, sum_us: ps })) if count >= pc => {
+                    let mut d = [0u64; 16];
+                    for (i, slot) in d.iter_mut().enumerate() {
+                        *slot = bins[i].saturating_sub(pb[i]);
+                    }
+                    SampleValue::Histogram { bins: d, count: count - pc, sum_us: sum_us.saturating_sub(*ps) }
+                }
+                (v, _) => v.clone(),
+            };
+            Sample { name: c.name.clone(), labels: c.labels.clone(), value }
+        })
+        .collect()
+}
+
+fn write_samples(out: &mut String, samples: &[Sample]) {
+    out.push('[');
+    for (i, s) in samples.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"name\":");
+        write_str(out, &s.name);
+        out.push_str(",\"labels\":{");
+        for (j, (k, v)) in s.labels.iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            write_str(out, k);
+            out.push(':');
+            write_str(out, v);
+        }
+        out.push('}');
+        match &s.value {
+            SampleValue::Counter(v) => out.push_str(&format!(",\"type\":\"counter\",\"value\":{}", v)),
+            SampleValue::Gauge(v) => out.push_str(&format!(",\"type\":\"gauge\",\"value\":{}", v)),
+            SampleValue::Histogram { bins, count, sum_us } => {
+                // fractional, so sub-millisecond observations add up
+                out.push_str(&format!(",\"type\":\"histogram\",\"count\":{},\"sum_ms\":{},\"buckets\":[", count, *sum_us as f64 / 1000.0));
+                for (j, (b, le)) in bins.iter().zip(LAT_BOUNDS.iter()).enumerate() {
+                    if j > 0 {
+                        out.push(',');
+                    }
+                    // last bucket is unbounded; u64::MAX is not representable in JSON numbers
+                    match *le {
+                        u64::MAX => out.push_str(&format!("{{\"le_ms\":null,\"count\":{}}}", b)),
+                        le => out.push_str(&format!("{{\"le_ms\":{},\"count\":{}}}", le, b)),
+                    }
+                }
+                out.push(']');
+            }
+        }
+        out.push('}');
+    }
+    out.push(']');
+}
+
+// Appends `s` as a quoted JSON string.
+pub(crate) fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cumulative_and_delta() {
+        let reg = Registry::new().with_namespace("olwsx").unwrap();
+        let hits = reg.counter("hits_total", &[("route", "/a\"b")]).unwrap();
+        let inflight = reg.gauge("inflight", &[]).unwrap();
+        let lat = reg.histogram("latency_ms", &[]).unwrap();
+        hits.add(5);
+        inflight.set(3);
+        lat.observe_ms(7);
+        lat.observe_us(500);
+        let exp = JsonExporter::new(reg);
+
+        let cum = exp.cumulative();
+        assert!(cum.contains(r#"{"name":"olwsx_hits_total","labels":{"route":"/a\"b"},"type":"counter","value":5}"#));
+        assert!(cum.contains(r#""type":"histogram","count":2,"sum_ms":7.5,"#));
+        assert!(cum.contains(r#""le_ms":10,"count":1"#) && cum.contains(r#""le_ms":null,"count":0"#));
+
+        let first = exp.delta_at(None, 1_000);
+        assert!(first.starts_with(r#"{"mode":"delta","ts_ms":1000,"cursor":"c1","reset":true,"interval_ms":0"#));
+        assert!(first.contains(r#""type":"counter","value":5"#));
+
+        hits.add(2);
+        inflight.set(1);
+        let second = exp.delta_at(Some("c1"), 16_000);
+        assert!(second.contains(r#""cursor":"c2","reset":false,"interval_ms":15000"#));
+        assert!(second.contains(r#""type":"counter","value":2"#));
+        assert!(second.contains(r#""type":"gauge","value":1"#));
+        assert!(second.contains(r#""type":"histogram","count":0,"sum_ms":0"#));
+
+        // unknown cursor restarts the chain
+        assert!(exp.delta_at(Some("c999"), 17_000).contains(r#""reset":true"#));
+    }
+}
@@ -8,6 +8,7 @@
 // - Fixed metric envelope with integer-friendly wire format.
 // - High-performance HDR-like histogram for latency (p50/p90/p99).
 // - Counter/gauge/summary with bounded memory and zero unsafe shared state.
+// - Per-tier cache hit/miss/eviction envelopes (cache_tier).
 // =============================================================================
 
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -28,28 +29,38 @@ pub enum MetricKind {
     LatencyHist { bins: [u64; 16] }, // fixed bins
 }
 
-// Fixed latency bins (ms): 0..5, 5..10, ..., 300..inf
-const LAT_BOUNDS: [u64; 16] = [5, 10, 20, 30, 40, 50, 60, 80, 100, 150, 200, 250, 300, 400, 600, u64::MAX];
+// Fixed latency bins (ms): 0..5, 5..10, ..., 300..inf. Observations and the
+// running sum are kept in microseconds so sub-millisecond stages still count.
+pub const LAT_BOUNDS: [u64; 16] = [5, 10, 20, 30, 40, 50, 60, 80, 100, 150, 200, 250, 300, 400, 600, u64::MAX];
 
 #[derive(Clone, Debug)]
 pub struct LatencyHistogram {
     bins: [u64; 16],
     count: u64,
-    sum_ms: u64,
+    sum_us: u64,
 }
 
 impl LatencyHistogram {
     pub fn new() -> Self {
-        Self { bins: [0; 16], count: 0, sum_ms: 0 }
+        Self { bins: [0; 16], count: 0, sum_us: 0 }
+    }
+
+    // Rebuild from gathered values (e.g. a registry Sample) to query quantiles.
+    pub fn from_parts(bins: [u64; 16], count: u64, sum_us: u64) -> Self {
+        Self { bins, count, sum_us }
     }
 
     pub fn observe_ms(&mut self, ms: u64) {
+        self.observe_us(ms.saturating_mul(1000));
+    }
+
+    pub fn observe_us(&mut self, us: u64) {
         let mut idx = 0;
-        while idx < LAT_BOUNDS.len() && ms > LAT_BOUNDS[idx] { idx += 1; }
+        while idx < LAT_BOUNDS.len() && us > LAT_BOUNDS[idx].saturating_mul(1000) { idx += 1; }
         if idx >= self.bins.len() { idx = self.bins.len() - 1; }
         self.bins[idx] += 1;
         self.count += 1;
-        self.sum_ms += ms;
+        self.sum_us = self.sum_us.saturating_add(us);
     }
 
     pub fn export(&self, name: &'static str, labels: &'static [(&'static str, &'static str)]) -> MetricEnvelope {
@@ -78,8 +89,10 @@ impl LatencyHistogram {
     pub fn p90(&self) -> u64 { self.quantile(0.90) }
     pub fn p99(&self) -> u64 { self.quantile(0.99) }
 
+    pub fn bins(&self) -> [u64; 16] { self.bins }
     pub fn count(&self) -> u64 { self.count }
-    pub fn sum_ms(&self) -> u64 { self.sum_ms }
+    pub fn sum_ms(&self) -> u64 { self.sum_us / 1000 }
+    pub fn sum_us(&self) -> u64 { self.sum_us }
 }
 
 // Counter/Gauge helpers
@@ -110,6 +123,42 @@ pub fn summary(name: &'static str, count: u64, sum: u64, labels: &'static [(&'st
     }
 }
 
+// Per-tier cache statistics, field for field as cache::CacheStats (the cache
+// crate does not depend on this one; the host copies a snapshot over).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheTierStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub expired: u64,
+    pub evictions: u64,
+    pub bytes: u64,
+    pub items: u64,
+}
+
+pub const CACHE_L1: &[(&str, &str)] = &[("tier", "l1")];
+pub const CACHE_L2: &[(&str, &str)] = &[("tier", "l2")];
+pub const CACHE_L3: &[(&str, &str)] = &[("tier", "l3")];
+
+// Envelopes for one tier since the `prev` snapshot: counters carry the
+// difference, gauges the current value. The hit ratio covers the same
+// interval, in permille (0 without lookups).
+pub fn cache_tier(cur: &CacheTierStats, prev: &CacheTierStats, labels: &'static [(&'static str, &'static str)]) -> Vec<MetricEnvelope> {
+    let hits = cur.hits.saturating_sub(prev.hits);
+    let misses = cur.misses.saturating_sub(prev.misses);
+    let expired = cur.expired.saturating_sub(prev.expired);
+    let lookups = hits + misses + expired;
+    let ratio = (hits * 1000).checked_div(lookups).unwrap_or(0) as i64;
+    vec![
+        counter("cache_hits_total", hits, labels),
+        counter("cache_misses_total", misses, labels),
+        counter("cache_expired_total", expired, labels),
+        counter("cache_evictions_total", cur.evictions.saturating_sub(prev.evictions), labels),
+        gauge("cache_bytes", cur.bytes as i64, labels),
+        gauge("cache_items", cur.items as i64, labels),
+        gauge("cache_hit_ratio_permille", ratio, labels),
+    ]
+}
+
 // Wire encoder (simple, deterministic; Version 1)
 // Format: [ts_ms u64][name_len u16][name bytes][labels_count u16][each: k_len u16 k_bytes v_len u16 v_bytes][kind_tag u8][payload...]
 pub fn encode_wire(m: &MetricEnvelope) -> Vec<u8> {
@@ -168,15 +217,34 @@ mod tests {
             h.observe_ms(*ms);
         }
         assert!(h.p90() >= 240);
+        h.observe_us(250);
+        assert_eq!((h.count(), h.sum_us()), (8, 1_007_250));
+        assert_eq!(h.bins()[0], 2, "a quarter millisecond is not lost");
         let env = h.export("latency", &[("route", "/hello"), ("method", "GET")]);
         let wire = encode_wire(&env);
         assert!(wire.len() > 16);
     }
 
+    #[test]
+    fn test_cache_tier() {
+        let prev = CacheTierStats { hits: 10, misses: 5, expired: 0, evictions: 2, bytes: 0, items: 0 };
+        let cur = CacheTierStats { hits: 40, misses: 8, expired: 2, evictions: 3, bytes: 4096, items: 7 };
+        let envs = cache_tier(&cur, &prev, CACHE_L2);
+        let by_name = |n: &str| envs.iter().find(|e| e.name == n).unwrap().kind.clone();
+        assert!(matches!(by_name("cache_hits_total"), MetricKind::Counter { delta: 30 }));
+        assert!(matches!(by_name("cache_evictions_total"), MetricKind::Counter { delta: 1 }));
+        assert!(matches!(by_name("cache_items"), MetricKind::Gauge { value: 7 }));
+        assert!(matches!(by_name("cache_hit_ratio_permille"), MetricKind::Gauge { value: 857 }));
+        assert!(envs.iter().all(|e| e.labels == CACHE_L2));
+    }
+
     #[test]
     fn test_counter_encode() {
         let env = counter("requests_total", 1, &[("tenant", "default")]);
         let wire = encode_wire(&env);
-        assert_eq!(wire[16 + 2 + "requests_total".len() + 2 + (6+7+2+7),  /* rough index */], 1u8);
+        // ts(8) name(2+14) labels(2 + 2+6 + 2+7) -> kind tag, then delta
+        let tag = 8 + 2 + "requests_total".len() + 2 + (2 + 6 + 2 + 7);
+        assert_eq!(wire[tag], 1u8);
+        assert_eq!(wire[tag + 1..], 1u64.to_be_bytes());
     }
 }
\ No newline at end of file
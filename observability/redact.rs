@@ -0,0 +1,710 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: observability/redact.rs
+// Role: Central redaction policy for captured requests (logs, audit, traces, replay)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - One policy for every diagnostic sink; sinks differ only in whether they
+//   keep bodies (access logs and traces do not, by default).
+// - Header names, cookie names, query parameter names, JSON pointers and
+//   regex-lite text patterns.
+// - Fail closed: unparseable JSON or binary bodies are never passed through.
+// - Matching is linear in the input (Pike VM), whatever the pattern.
+// =============================================================================
+
+pub const REDACTED: &str = "[REDACTED]";
+
+const MAX_JSON_DEPTH: usize = 128;
+const MAX_INSTS: usize = 10_000;
+
+// Query (and form) parameters whose values are redacted by default; the Go
+// access log and span targets keep the same list (edge/observability.go,
+// observability/tracing.go).
+pub const SENSITIVE_PARAMS: [&str; 8] = ["access_token", "api_key", "apikey", "password", "secret", "sig", "signature", "token"];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Sink {
+    AccessLog,
+    AuditLog,
+    Trace,
+    Replay,
+}
+
+impl Sink {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "access_log" => Some(Sink::AccessLog),
+            "audit_log" => Some(Sink::AuditLog),
+            "trace" => Some(Sink::Trace),
+            "replay" => Some(Sink::Replay),
+            _ => None,
+        }
+    }
+}
+
+// Captured request/response fragment handed to a diagnostic sink.
+#[derive(Clone, Debug, Default)]
+pub struct Capture {
+    pub headers: Vec<(String, String)>,
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RedactionPolicy {
+    headers: Vec<String>,       // lowercase names; value fully replaced
+    cookies: Vec<String>,       // cookie names; "*" = every cookie
+    params: Vec<String>,        // lowercase query/form parameter names
+    pointers: Vec<Vec<String>>, // RFC 6901 pointers, "*" segment matches any key/index
+    patterns: Vec<Pattern>,     // applied to header values and text bodies
+    capture_binary: bool,
+    no_body: Vec<Sink>,         // sinks that get a size note instead of the body
+}
+
+impl Default for RedactionPolicy {
+    fn default() -> Self {
+        Self {
+            headers: ["authorization", "proxy-authorization", "x-api-key", "x-auth-token"].iter().map(|s| s.to_string()).collect(),
+            cookies: Vec::new(),
+            params: SENSITIVE_PARAMS.iter().map(|s| s.to_string()).collect(),
+            pointers: Vec::new(),
+            patterns: Vec::new(),
+            capture_binary: false,
+            no_body: vec![Sink::AccessLog, Sink::Trace],
+        }
+    }
+}
+
+impl RedactionPolicy {
+    // One directive per line, added on top of the defaults:
+    //   header <name> | cookie <name|*> | param <name> | json <pointer> | pattern <regex-lite>
+    //   | binary on|off | body <access_log|audit_log|trace|replay> on|off
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut p = Self::default();
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (kw, arg) = line.split_once(char::is_whitespace).map(|(k, a)| (k, a.trim())).unwrap_or((line, ""));
+            if arg.is_empty() {
+                return Err(format!("line {}: '{}' needs an argument", n + 1, kw));
+            }
+            match kw {
+                "header" => p.headers.push(arg.to_ascii_lowercase()),
+                "cookie" => p.cookies.push(arg.to_string()),
+                "param" => p.params.push(arg.to_ascii_lowercase()),
+                "json" => p.pointers.push(parse_pointer(arg).map_err(|e| format!("line {}: {}", n + 1, e))?),
+                "pattern" => p.patterns.push(Pattern::compile(arg).map_err(|e| format!("line {}: {}", n + 1, e))?),
+                "binary" => p.capture_binary = arg == "on",
+                "body" => {
+                    let (sink, on) = match arg.split_whitespace().collect::<Vec<_>>()[..] {
+                        [s, "on"] => (Sink::parse(s), true),
+                        [s, "off"] => (Sink::parse(s), false),
+                        _ => (None, false),
+                    };
+                    let sink = sink.ok_or_else(|| format!("line {}: expected 'body <sink> on|off'", n + 1))?;
+                    p.no_body.retain(|s| *s != sink);
+                    if !on {
+                        p.no_body.push(sink);
+                    }
+                }
+                _ => return Err(format!("line {}: unknown directive '{}'", n + 1, kw)),
+            }
+        }
+        Ok(p)
+    }
+
+    // Every sink goes through here. Headers are redacted the same for all of
+    // them; sinks in `no_body` only learn the body's size.
+    pub fn apply(&self, sink: Sink, cap: &Capture) -> Capture {
+        let body = if !self.no_body.contains(&sink) {
+            self.redact_body(&cap.content_type, &cap.body)
+        } else if cap.body.is_empty() {
+            Vec::new()
+        } else {
+            format!("[body omitted, {} bytes]", cap.body.len()).into_bytes()
+        };
+        Capture { headers: self.redact_headers(&cap.headers), content_type: cap.content_type.clone(), body }
+    }
+
+    pub fn redact_headers(&self, headers: &[(String, String)]) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(k, v)| {
+                let lk = k.to_ascii_lowercase();
+                let v = if self.headers.contains(&lk) {
+                    REDACTED.to_string()
+                } else if lk == "cookie" {
+                    self.redact_text(&self.redact_cookies(v, "; ", true))
+                } else if lk == "set-cookie" {
+                    self.redact_text(&self.redact_cookies(v, "; ", false))
+                } else {
+                    self.redact_text(v)
+                };
+                (k.clone(), v)
+            })
+            .collect()
+    }
+
+    pub fn redact_body(&self, content_type: &str, body: &[u8]) -> Vec<u8> {
+        let ct = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        if ct == "application/json" || ct.ends_with("+json") {
+            return match self.redact_json(body) {
+                Some(j) => self.redact_text(&String::from_utf8_lossy(&j)).into_bytes(),
+                None => format!("[unparseable json, {} bytes]", body.len()).into_bytes(),
+            };
+        }
+        let textual = ct.starts_with("text/") || ct == "application/x-www-form-urlencoded" || ct == "application/xml";
+        match std::str::from_utf8(body) {
+            Ok(s) if textual => self.redact_text(s).into_bytes(),
+            _ if body.is_empty() => Vec::new(),
+            _ if self.capture_binary => body.to_vec(),
+            _ => format!("[binary, {} bytes]", body.len()).into_bytes(),
+        }
+    }
+
+    // Sensitive `name=value` parameters, then every leftmost match of each
+    // pattern. Also used on free-form lines (access log entries).
+    pub fn redact_text(&self, s: &str) -> String {
+        let mut out = self.redact_params(s);
+        for p in self.patterns.iter() {
+            out = p.replace_all(&out, REDACTED);
+        }
+        out
+    }
+
+    // Values of `params` wherever the name follows start of text, whitespace,
+    // `?`, `&` or `;`, up to the next `&`, `;`, `#`, quote or whitespace.
+    fn redact_params(&self, s: &str) -> String {
+        let stop = |c: char| matches!(c, '&' | ';' | '#' | '"' | '\'') || c.is_whitespace();
+        let mut out = String::with_capacity(s.len());
+        let mut rest = s;
+        let mut at_boundary = true;
+        while let Some(c) = rest.chars().next() {
+            if at_boundary {
+                let name_len = rest.find(|c: char| c == '=' || c == '?' || stop(c)).unwrap_or(rest.len());
+                let (name, tail) = rest.split_at(name_len);
+                if tail.starts_with('=') && self.params.iter().any(|p| p.eq_ignore_ascii_case(name)) {
+                    let value_len = tail[1..].find(stop).unwrap_or(tail.len() - 1);
+                    out.push_str(name);
+                    out.push('=');
+                    out.push_str(REDACTED);
+                    rest = &tail[1 + value_len..];
+                    at_boundary = false;
+                    continue;
+                }
+            }
+            at_boundary = matches!(c, '?' | '&' | ';') || c.is_whitespace();
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+        out
+    }
+
+    // Cookie: every pair is a cookie; Set-Cookie: only the first pair is, the rest are attributes.
+    fn redact_cookies(&self, v: &str, sep: &str, all_pairs: bool) -> String {
+        if self.cookies.is_empty() {
+            return v.to_string();
+        }
+        v.split(';')
+            .enumerate()
+            .map(|(i, part)| {
+                let part = part.trim();
+                match part.split_once('=') {
+                    Some((name, _)) if (all_pairs || i == 0) && self.cookies.iter().any(|c| c == "*" || c == name.trim()) => {
+                        format!("{}={}", name.trim(), REDACTED)
+                    }
+                    _ => part.to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(sep)
+    }
+
+    // Re-serializes compactly with matched values replaced; None if the input is not JSON.
+    pub fn redact_json(&self, input: &[u8]) -> Option<Vec<u8>> {
+        let mut w = JsonWalk { s: input, i: 0, pointers: &self.pointers, path: Vec::new(), out: Vec::with_capacity(input.len()) };
+        w.value(true).ok()?;
+        w.ws();
+        (w.i == input.len()).then_some(w.out)
+    }
+}
+
+fn parse_pointer(p: &str) -> Result<Vec<String>, String> {
+    if p.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rest = p.strip_prefix('/').ok_or_else(|| format!("json pointer '{}' must start with '/'", p))?;
+    Ok(rest.split('/').map(|seg| seg.replace("~1", "/").replace("~0", "~")).collect())
+}
+
+struct JsonWalk<'a> {
+    s: &'a [u8],
+    i: usize,
+    pointers: &'a [Vec<String>],
+    path: Vec<String>,
+    out: Vec<u8>,
+}
+
+impl JsonWalk<'_> {
+    fn matched(&self) -> bool {
+        self.pointers.iter().any(|p| p.len() == self.path.len() && p.iter().zip(self.path.iter()).all(|(a, b)| a == "*" || a == b))
+    }
+
+    fn ws(&mut self) {
+        while self.i < self.s.len() && matches!(self.s[self.i], b' ' | b'\t' | b'\n' | b'\r') {
+            self.i += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.i).copied()
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), ()> {
+        self.ws();
+        if self.peek() != Some(b) {
+            return Err(());
+        }
+        self.i += 1;
+        self.out.push(b);
+        Ok(())
+    }
+
+    // `emit` false while skipping a redacted subtree.
+    fn value(&mut self, emit: bool) -> Result<(), ()> {
+        if self.path.len() > MAX_JSON_DEPTH {
+            return Err(());
+        }
+        self.ws();
+        if emit && self.matched() {
+            let mark = self.out.len();
+            self.value(false)?;
+            self.out.truncate(mark);
+            self.out.extend_from_slice(b"\"");
+            self.out.extend_from_slice(REDACTED.as_bytes());
+            self.out.extend_from_slice(b"\"");
+            return Ok(());
+        }
+        match self.peek().ok_or(())? {
+            b'{' => {
+                self.i += 1;
+                self.out.push(b'{');
+                self.ws();
+                if self.peek() == Some(b'}') {
+                    self.i += 1;
+                    self.out.push(b'}');
+                    return Ok(());
+                }
+                loop {
+                    self.ws();
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    self.path.push(key);
+                    let r = self.value(emit);
+                    self.path.pop();
+                    r?;
+                    self.ws();
+                    match self.peek() {
+                        Some(b',') => self.expect(b',')?,
+                        Some(b'}') => return self.expect(b'}'),
+                        _ => return Err(()),
+                    }
+                }
+            }
+            b'[' => {
+                self.i += 1;
+                self.out.push(b'[');
+                self.ws();
+                if self.peek() == Some(b']') {
+                    self.i += 1;
+                    self.out.push(b']');
+                    return Ok(());
+                }
+                let mut idx = 0usize;
+                loop {
+                    self.path.push(idx.to_string());
+                    let r = self.value(emit);
+                    self.path.pop();
+                    r?;
+                    idx += 1;
+                    self.ws();
+                    match self.peek() {
+                        Some(b',') => self.expect(b',')?,
+                        Some(b']') => return self.expect(b']'),
+                        _ => return Err(()),
+                    }
+                }
+            }
+            b'"' => self.string().map(|_| ()),
+            _ => {
+                let start = self.i;
+                while self.i < self.s.len() && (self.s[self.i].is_ascii_alphanumeric() || matches!(self.s[self.i], b'-' | b'+' | b'.')) {
+                    self.i += 1;
+                }
+                let tok = &self.s[start..self.i];
+                let ok = matches!(tok, b"true" | b"false" | b"null") || (!tok.is_empty() && std::str::from_utf8(tok).ok().and_then(|t| t.parse::<f64>().ok()).is_some());
+                if !ok {
+                    return Err(());
+                }
+                self.out.extend_from_slice(tok);
+                Ok(())
+            }
+        }
+    }
+
+    // Copies the raw string to out and returns its decoded form (for key matching).
+    fn string(&mut self) -> Result<String, ()> {
+        if self.peek() != Some(b'"') {
+            return Err(());
+        }
+        let start = self.i;
+        self.i += 1;
+        let mut dec = Vec::new();
+        loop {
+            let b = self.peek().ok_or(())?;
+            self.i += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let e = self.peek().ok_or(())?;
+                    self.i += 1;
+                    match e {
+                        b'"' | b'\\' | b'/' => dec.push(e),
+                        b'b' | b'f' | b'n' | b'r' | b't' => dec.push(b' '),
+                        b'u' => {
+                            let hex = self.s.get(self.i..self.i + 4).ok_or(())?;
+                            let cp = u32::from_str_radix(std::str::from_utf8(hex).map_err(|_| ())?, 16).map_err(|_| ())?;
+                            self.i += 4;
+                            let c = char::from_u32(cp).unwrap_or('\u{fffd}');
+                            dec.extend_from_slice(c.encode_utf8(&mut [0u8; 4]).as_bytes());
+                        }
+                        _ => return Err(()),
+                    }
+                }
+                0x00..=0x1f => return Err(()),
+                _ => dec.push(b),
+            }
+        }
+        self.out.extend_from_slice(&self.s[start..self.i]);
+        String::from_utf8(dec).map_err(|_| ())
+    }
+}
+
+// ------------------------------- Regex-lite ---------------------------------
+// Supported: literals, `.`, `\d \w \s` (and `\D \W \S`), `\` escapes, `[a-z0-9_]`
+// and `[^...]` classes, quantifiers `* + ? {n} {n,} {n,m}`. No groups,
+// alternation or anchors. Quantifiers are greedy and the leftmost non-empty
+// match wins, as with backtracking, but matches run on a Pike VM: time is
+// O(input x program) and the program is capped at MAX_INSTS.
+
+#[derive(Clone, Debug)]
+enum Class {
+    Any,
+    Lit(char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+    Set(Vec<(char, char)>, bool), // ranges, negated
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Class::Any => c != '\n',
+            Class::Lit(l) => *l == c,
+            Class::Digit(neg) => c.is_ascii_digit() != *neg,
+            Class::Word(neg) => (c.is_alphanumeric() || c == '_') != *neg,
+            Class::Space(neg) => c.is_whitespace() != *neg,
+            Class::Set(ranges, neg) => ranges.iter().any(|(a, b)| *a <= c && c <= *b) != *neg,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Atom {
+    class: Class,
+    min: usize,
+    max: usize,
+}
+
+#[derive(Clone, Debug)]
+enum Inst {
+    Char(Class),
+    Split(usize, usize), // prefer the first
+    Jmp(usize),
+    Match,
+}
+
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    prog: Vec<Inst>,
+}
+
+impl Pattern {
+    pub fn compile(src: &str) -> Result<Self, String> {
+        let cs: Vec<char> = src.chars().collect();
+        let mut atoms: Vec<Atom> = Vec::new();
+        let mut i = 0;
+        while i < cs.len() {
+            let class = match cs[i] {
+                '.' => Class::Any,
+                '\\' => {
+                    i += 1;
+                    match cs.get(i).ok_or("trailing '\\'")? {
+                        'd' => Class::Digit(false),
+                        'D' => Class::Digit(true),
+                        'w' => Class::Word(false),
+                        'W' => Class::Word(true),
+                        's' => Class::Space(false),
+                        'S' => Class::Space(true),
+                        c => Class::Lit(*c),
+                    }
+                }
+                '[' => {
+                    let close = cs[i + 1..].iter().position(|c| *c == ']').ok_or("unterminated '['")? + i + 1;
+                    let mut body = &cs[i + 1..close];
+                    let neg = body.first() == Some(&'^');
+                    if neg {
+                        body = &body[1..];
+                    }
+                    let mut ranges = Vec::new();
+                    let mut j = 0;
+                    while j < body.len() {
+                        if j + 2 < body.len() && body[j + 1] == '-' {
+                            ranges.push((body[j], body[j + 2]));
+                            j += 3;
+                        } else {
+                            ranges.push((body[j], body[j]));
+                            j += 1;
+                        }
+                    }
+                    if ranges.is_empty() {
+                        return Err("empty character class".to_string());
+                    }
+                    i = close;
+                    Class::Set(ranges, neg)
+                }
+                '*' | '+' | '?' | '{' => return Err(format!("quantifier '{}' without an atom", cs[i])),
+                c => Class::Lit(c),
+            };
+            i += 1;
+            let (min, max) = match cs.get(i) {
+                Some('*') => (0, usize::MAX),
+                Some('+') => (1, usize::MAX),
+                Some('?') => (0, 1),
+                Some('{') => {
+                    let close = cs[i..].iter().position(|c| *c == '}').ok_or("unterminated '{'")? + i;
+                    let body: String = cs[i + 1..close].iter().collect();
+                    let num = |s: &str| s.trim().parse::<usize>().map_err(|_| format!("bad repeat '{{{}}}'", body));
+                    let r = match body.split_once(',') {
+                        None => (num(&body)?, num(&body)?),
+                        Some((a, "")) => (num(a)?, usize::MAX),
+                        Some((a, b)) => (num(a)?, num(b)?),
+                    };
+                    if r.0 > r.1 {
+                        return Err(format!("bad repeat '{{{}}}'", body));
+                    }
+                    i = close;
+                    r
+                }
+                _ => {
+                    atoms.push(Atom { class, min: 1, max: 1 });
+                    continue;
+                }
+            };
+            i += 1;
+            atoms.push(Atom { class, min, max });
+        }
+        if atoms.is_empty() {
+            return Err("empty pattern".to_string());
+        }
+        Self::lower(atoms)
+    }
+
+    fn lower(atoms: Vec<Atom>) -> Result<Self, String> {
+        let mut prog = Vec::new();
+        let too_large = || format!("pattern expands past {} instructions", MAX_INSTS);
+        for a in atoms {
+            for _ in 0..a.min {
+                prog.push(Inst::Char(a.class.clone()));
+                if prog.len() > MAX_INSTS {
+                    return Err(too_large());
+                }
+            }
+            if a.max == usize::MAX {
+                let top = prog.len();
+                prog.push(Inst::Split(top + 1, top + 3));
+                prog.push(Inst::Char(a.class.clone()));
+                prog.push(Inst::Jmp(top));
+            } else {
+                // x{0,2} -> split(a, end) a: x split(b, end) b: x end
+                let mut splits = Vec::new();
+                for _ in a.min..a.max {
+                    splits.push(prog.len());
+                    prog.push(Inst::Split(prog.len() + 1, 0));
+                    prog.push(Inst::Char(a.class.clone()));
+                    if prog.len() > MAX_INSTS {
+                        return Err(too_large());
+                    }
+                }
+                let end = prog.len();
+                for s in splits {
+                    if let Inst::Split(_, out) = &mut prog[s] {
+                        *out = end;
+                    }
+                }
+            }
+            if prog.len() > MAX_INSTS {
+                return Err(too_large());
+            }
+        }
+        prog.push(Inst::Match);
+        Ok(Self { prog })
+    }
+
+    // Leftmost non-empty match at or after `from`, as (start, end). Threads
+    // are kept in priority order (earlier start first, then greedy choice),
+    // so the first to reach Match is the one backtracking would have found.
+    fn find(&self, s: &[char], from: usize) -> Option<(usize, usize)> {
+        let mut seen = vec![usize::MAX; self.prog.len()];
+        let (mut clist, mut nlist) = (Vec::new(), Vec::new());
+        let mut found = None;
+        for pos in from..=s.len() {
+            if found.is_none() {
+                self.add(&mut clist, &mut seen, pos, 0, pos);
+            }
+            if clist.is_empty() {
+                break;
+            }
+            for &(pc, start) in clist.iter() {
+                match &self.prog[pc] {
+                    Inst::Match if pos > start => {
+                        found = Some((start, pos));
+                        break; // lower-priority threads are cut
+                    }
+                    Inst::Char(c) if pos < s.len() && c.matches(s[pos]) => self.add(&mut nlist, &mut seen, pos + 1, pc + 1, start),
+                    _ => {}
+                }
+            }
+            std::mem::swap(&mut clist, &mut nlist);
+            nlist.clear();
+        }
+        found
+    }
+
+    // Follows Split/Jmp from `pc`, queueing the Char/Match states it reaches
+    // at `pos`; a state already queued there keeps its higher priority.
+    fn add(&self, list: &mut Vec<(usize, usize)>, seen: &mut [usize], pos: usize, pc: usize, start: usize) {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if seen[pc] == pos {
+                continue;
+            }
+            seen[pc] = pos;
+            match self.prog[pc] {
+                Inst::Jmp(x) => stack.push(x),
+                Inst::Split(x, y) => {
+                    stack.push(y);
+                    stack.push(x);
+                }
+                _ => list.push((pc, start)),
+            }
+        }
+    }
+
+    pub fn is_match(&self, s: &str) -> bool {
+        let cs: Vec<char> = s.chars().collect();
+        self.find(&cs, 0).is_some()
+    }
+
+    pub fn replace_all(&self, s: &str, with: &str) -> String {
+        let cs: Vec<char> = s.chars().collect();
+        let mut out = String::with_capacity(s.len());
+        let mut i = 0;
+        while let Some((start, end)) = self.find(&cs, i) {
+            out.extend(&cs[i..start]);
+            out.push_str(with);
+            i = end;
+        }
+        out.extend(&cs[i..]);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_capture() {
+        let policy = RedactionPolicy::parse("cookie session\njson /password\njson /cards/*/number\npattern \\d{4}-\\d{4}-\\d{4}-\\d{4}").unwrap();
+        let cap = Capture {
+            headers: vec![
+                ("Authorization".into(), "Bearer abc".into()),
+                ("Cookie".into(), "theme=dark; session=s3cr3t".into()),
+                ("X-Note".into(), "card 1234-5678-9012-3456 used".into()),
+            ],
+            content_type: "application/json; charset=utf-8".into(),
+            body: br#"{"user":"bob", "password":"hunter2","cards":[{"number":"4111","exp":"12/30"}],"memo":"1111-2222-3333-4444"}"#.to_vec(),
+        };
+        for sink in [Sink::AccessLog, Sink::AuditLog, Sink::Trace, Sink::Replay] {
+            let keeps_body = matches!(sink, Sink::AuditLog | Sink::Replay);
+            let out = policy.apply(sink, &cap);
+            assert_eq!(out.headers[0].1, REDACTED);
+            assert_eq!(out.headers[1].1, "theme=dark; session=[REDACTED]");
+            assert_eq!(out.headers[2].1, "card [REDACTED] used");
+            let body = String::from_utf8(out.body).unwrap();
+            match keeps_body {
+                true => assert_eq!(body, r#"{"user":"bob","password":"[REDACTED]","cards":[{"number":"[REDACTED]","exp":"12/30"}],"memo":"[REDACTED]"}"#),
+                false => assert_eq!(body, format!("[body omitted, {} bytes]", cap.body.len())),
+            }
+        }
+        let verbose = RedactionPolicy::parse("body trace on\nbody replay off").unwrap();
+        assert!(verbose.apply(Sink::Trace, &cap).body.starts_with(b"{"));
+        assert!(verbose.apply(Sink::Replay, &cap).body.starts_with(b"[body omitted"));
+        assert!(RedactionPolicy::parse("body logs on").is_err());
+        assert_eq!(policy.redact_body("application/json", b"{\"password\":"), b"[unparseable json, 12 bytes]");
+        assert_eq!(policy.redact_body("application/octet-stream", &[0xff, 0x00]), b"[binary, 2 bytes]");
+    }
+
+    #[test]
+    fn test_pattern() {
+        let p = Pattern::compile("[A-Za-z0-9._]+@[a-z]+\\.[a-z]{2,}").unwrap();
+        assert_eq!(p.replace_all("mail ann.lee@example.org now", "*"), "mail * now");
+        assert!(!p.is_match("no address here"));
+        assert!(Pattern::compile("+x").is_err());
+        assert!(RedactionPolicy::parse("json password").is_err());
+
+        // greedy, leftmost, as before; empty matches never replace
+        assert_eq!(Pattern::compile("a{1,2}b?").unwrap().replace_all("xaaab", "*"), "x**");
+        assert_eq!(Pattern::compile("\\d*").unwrap().replace_all("ab12c", "#"), "ab#c");
+        assert!(Pattern::compile("a{0,20000}").is_err());
+    }
+
+    #[test]
+    fn test_pattern_is_linear() {
+        // exponential for a backtracker: every split of the run is tried
+        let p = Pattern::compile("a*a*a*a*a*a*a*a*b").unwrap();
+        let hostile = "a".repeat(20_000);
+        let started = std::time::Instant::now();
+        assert!(!p.is_match(&hostile));
+        assert_eq!(p.replace_all(&hostile, "*"), hostile);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_sensitive_params() {
+        let policy = RedactionPolicy::parse("param session_id").unwrap();
+        assert_eq!(
+            policy.redact_text(r#"GET /cb?code=1&access_token=abc.def&x=2 ua="curl" ref=/p;Session_ID=7#top"#),
+            r#"GET /cb?code=1&access_token=[REDACTED]&x=2 ua="curl" ref=/p;Session_ID=[REDACTED]#top"#
+        );
+        assert_eq!(policy.redact_text("password=hunter2&user=bob"), "password=[REDACTED]&user=bob");
+        assert_eq!(policy.redact_text("mytoken=1 retoken=2"), "mytoken=1 retoken=2", "names match whole");
+        let cap = Capture { headers: vec![("Referer".into(), "https://x.test/?sig=ff".into())], ..Capture::default() };
+        assert_eq!(policy.apply(Sink::AccessLog, &cap).headers[0].1, "https://x.test/?sig=[REDACTED]");
+    }
+}
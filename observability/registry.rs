@@ -0,0 +1,279 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: observability/registry.rs
+// Role: Metric registry (namespacing, default labels, isolated instances)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Get-or-create counters, gauges and latency histograms by name + labels.
+// - Namespace prefix and default labels applied at gather, not at record.
+// - Any number of isolated registries; one process-wide default for convenience.
+// - Tenant-scoped gather: only series labelled with that tenant, without the
+//   host-level default labels, for tenant-facing status pages.
+// =============================================================================
+
+use crate::metrics::LatencyHistogram;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+type Labels = Vec<(String, String)>;
+
+pub const TENANT_LABEL: &str = "tenant";
+
+#[derive(Clone, Debug)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Gauge(Arc<AtomicI64>);
+
+impl Gauge {
+    pub fn set(&self, v: i64) {
+        self.0.store(v, Ordering::Relaxed);
+    }
+    pub fn add(&self, d: i64) {
+        self.0.fetch_add(d, Ordering::Relaxed);
+    }
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Histogram(Arc<Mutex<LatencyHistogram>>);
+
+impl Histogram {
+    pub fn observe_ms(&self, ms: u64) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).observe_ms(ms);
+    }
+    pub fn observe_us(&self, us: u64) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).observe_us(us);
+    }
+    pub fn snapshot(&self) -> LatencyHistogram {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Handle {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+impl Handle {
+    fn kind(&self) -> &'static str {
+        match self {
+            Handle::Counter(_) => "counter",
+            Handle::Gauge(_) => "gauge",
+            Handle::Histogram(_) => "histogram",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SampleValue {
+    Counter(u64),
+    Gauge(i64),
+    Histogram { bins: [u64; 16], count: u64, sum_us: u64 },
+}
+
+// Gathered metric: fully qualified name, merged labels (sorted by key).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub labels: Labels,
+    pub value: SampleValue,
+}
+
+#[derive(Clone)]
+pub struct Registry {
+    namespace: Option<String>,
+    default_labels: Labels,
+    metrics: Arc<Mutex<BTreeMap<(String, Labels), Handle>>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self { namespace: None, default_labels: Vec::new(), metrics: Arc::new(Mutex::new(BTreeMap::new())) }
+    }
+
+    // Prefix for every gathered name: "<ns>_<name>".
+    pub fn with_namespace(mut self, ns: &str) -> Result<Self, String> {
+        validate_name(ns)?;
+        self.namespace = Some(ns.to_string());
+        Ok(self)
+    }
+
+    // Added to every sample at gather; a metric's own label with the same key wins.
+    pub fn with_default_labels(mut self, labels: &[(&str, &str)]) -> Result<Self, String> {
+        for (k, _) in labels.iter() {
+            validate_name(k)?;
+        }
+        self.default_labels = normalize(labels);
+        Ok(self)
+    }
+
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> Result<Counter, String> {
+        match self.get_or_insert(name, labels, "counter", || Handle::Counter(Counter(Arc::new(AtomicU64::new(0)))))? {
+            Handle::Counter(c) => Ok(c),
+            _ => Err(format!("metric '{}' already registered with a different kind", name)),
+        }
+    }
+
+    pub fn gauge(&self, name: &str, labels: &[(&str, &str)]) -> Result<Gauge, String> {
+        match self.get_or_insert(name, labels, "gauge", || Handle::Gauge(Gauge(Arc::new(AtomicI64::new(0)))))? {
+            Handle::Gauge(g) => Ok(g),
+            _ => Err(format!("metric '{}' already registered with a different kind", name)),
+        }
+    }
+
+    pub fn histogram(&self, name: &str, labels: &[(&str, &str)]) -> Result<Histogram, String> {
+        match self.get_or_insert(name, labels, "histogram", || Handle::Histogram(Histogram(Arc::new(Mutex::new(LatencyHistogram::new())))))? {
+            Handle::Histogram(h) => Ok(h),
+            _ => Err(format!("metric '{}' already registered with a different kind", name)),
+        }
+    }
+
+    // Snapshot of every metric, ordered by name then labels.
+    pub fn gather(&self) -> Vec<Sample> {
+        self.collect(|_| true, true)
+    }
+
+    // Only series recorded with label `tenant=<tenant>`. Default labels are not
+    // merged in: they describe the host (instance, region), not the tenant.
+    pub fn gather_tenant(&self, tenant: &str) -> Vec<Sample> {
+        self.collect(|labels| labels.iter().any(|(k, v)| k == TENANT_LABEL && v == tenant), false)
+    }
+
+    // Fully qualified name as it appears in gathered samples.
+    pub fn qualify(&self, name: &str) -> String {
+        match &self.namespace {
+            Some(ns) => format!("{}_{}", ns, name),
+            None => name.to_string(),
+        }
+    }
+
+    fn collect(&self, keep: impl Fn(&Labels) -> bool, with_defaults: bool) -> Vec<Sample> {
+        let metrics = self.lock().clone();
+        metrics
+            .into_iter()
+            .filter(|((_, labels), _)| keep(labels))
+            .map(|((name, labels), h)| {
+                let value = match h {
+                    Handle::Counter(c) => SampleValue::Counter(c.get()),
+                    Handle::Gauge(g) => SampleValue::Gauge(g.get()),
+                    Handle::Histogram(h) => {
+                        let s = h.snapshot();
+                        SampleValue::Histogram { bins: s.bins(), count: s.count(), sum_us: s.sum_us() }
+                    }
+                };
+                let labels = if with_defaults { self.merge_labels(labels) } else { labels };
+                Sample { name: self.qualify(&name), labels, value }
+            })
+            .collect()
+    }
+
+    // A name has one kind across all its label sets; the first series
+    // registered under it decides.
+    fn get_or_insert(&self, name: &str, labels: &[(&str, &str)], kind: &'static str, make: impl FnOnce() -> Handle) -> Result<Handle, String> {
+        validate_name(name)?;
+        for (k, _) in labels.iter() {
+            validate_name(k)?;
+        }
+        let key = (name.to_string(), normalize(labels));
+        let mut metrics = self.lock();
+        // labels sort after the empty set, so this is the name's first series
+        if let Some((_, h)) = metrics.range((name.to_string(), Labels::new())..).next().filter(|((n, _), h)| n == name && h.kind() != kind) {
+            return Err(format!("metric '{}' already registered as a {}", name, h.kind()));
+        }
+        Ok(metrics.entry(key).or_insert_with(make).clone())
+    }
+
+    fn merge_labels(&self, own: Labels) -> Labels {
+        let mut out = own;
+        for (k, v) in self.default_labels.iter() {
+            if !out.iter().any(|(ok, _)| ok == k) {
+                out.push((k.clone(), v.clone()));
+            }
+        }
+        out.sort();
+        out
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<(String, Labels), Handle>> {
+        self.metrics.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Process-wide registry for code that has no registry threaded through.
+// Embedders that need isolation should create their own instead.
+pub fn default_registry() -> &'static Registry {
+    static DEFAULT: OnceLock<Registry> = OnceLock::new();
+    DEFAULT.get_or_init(Registry::new)
+}
+
+fn normalize(labels: &[(&str, &str)]) -> Labels {
+    let mut v: Labels = labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    v.sort();
+    v.dedup_by(|a, b| a.0 == b.0);
+    v
+}
+
+// Prometheus-compatible: [a-zA-Z_:][a-zA-Z0-9_:]*
+fn validate_name(name: &str) -> Result<(), String> {
+    let mut cs = name.chars();
+    let ok = cs.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':') && cs.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':');
+    if ok { Ok(()) } else { Err(format!("invalid metric or label name '{}'", name)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespaced_isolated_registries() {
+        let a = Registry::new().with_namespace("olwsx").unwrap().with_default_labels(&[("instance", "edge-1"), ("tenant", "default")]).unwrap();
+        let b = Registry::new().with_namespace("host_app").unwrap();
+
+        a.counter("requests_total", &[("tenant", "acme")]).unwrap().inc();
+        a.counter("requests_total", &[("tenant", "acme")]).unwrap().add(2);
+        b.counter("requests_total", &[]).unwrap().inc();
+        a.histogram("latency_ms", &[]).unwrap().observe_ms(12);
+        assert!(a.gauge("requests_total", &[("tenant", "acme")]).is_err());
+        assert!(a.gauge("requests_total", &[("tenant", "other")]).is_err(), "kind is per name, not per series");
+        assert!(a.counter("bad-name", &[]).is_err());
+
+        let ga = a.gather();
+        assert_eq!(ga.len(), 2);
+        assert_eq!(ga[1].name, "olwsx_requests_total");
+        assert_eq!(ga[1].labels, vec![("instance".into(), "edge-1".into()), ("tenant".into(), "acme".into())]);
+        assert_eq!(ga[1].value, SampleValue::Counter(3));
+        assert!(matches!(ga[0].value, SampleValue::Histogram { count: 1, sum_us: 12_000, .. }));
+
+        let gb = b.gather();
+        assert_eq!((gb.len(), gb[0].name.as_str()), (1, "host_app_requests_total"));
+        assert_eq!(gb[0].value, SampleValue::Counter(1));
+
+        default_registry().gauge("inflight", &[]).unwrap().set(4);
+        assert_eq!(default_registry().gauge("inflight", &[]).unwrap().get(), 4);
+    }
+}
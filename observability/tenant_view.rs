@@ -0,0 +1,198 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: observability/tenant_view.rs
+// Role: Tenant-facing status views (metrics summary + access log tail)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Summarize one tenant's series: request count, latency p50/p99, cache hit
+//   ratio, WAF decisions by action. Built from Registry::gather_tenant only,
+//   so other tenants' series and labels are never read.
+// - Per-tenant bounded access-log rings; a tenant can only tail its own.
+// - Metric names are the convention below (without namespace):
+//     requests_total, request_latency_ms, cache_hits_total,
+//     cache_misses_total, waf_decisions_total{action=...},
+//     client_aborts_total (client gone before the response; status 499)
+//   and, not summarized here, pipeline_stage_latency_ms{route,stage,key}
+//   (per filter/handler timings from Pipeline::execute) and
+//   cache_shielded_total{status} (a stale copy served for an upstream error).
+// =============================================================================
+
+use crate::export::write_str;
+use crate::metrics::LatencyHistogram;
+use crate::redact::RedactionPolicy;
+use crate::registry::{Registry, SampleValue};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+pub const REQUESTS: &str = "requests_total";
+pub const LATENCY: &str = "request_latency_ms";
+pub const CACHE_HITS: &str = "cache_hits_total";
+pub const CACHE_MISSES: &str = "cache_misses_total";
+pub const WAF_DECISIONS: &str = "waf_decisions_total";
+pub const CLIENT_ABORTS: &str = "client_aborts_total";
+pub const STAGE_LATENCY: &str = "pipeline_stage_latency_ms";
+pub const CACHE_SHIELDED: &str = "cache_shielded_total";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TenantStatus {
+    pub tenant: String,
+    pub requests: u64,
+    pub latency_p50_ms: u64,
+    pub latency_p99_ms: u64,
+    pub cache_hit_ratio: Option<f64>, // None before the first cache lookup
+    pub waf: Vec<(String, u64)>,      // (action, count), sorted by action
+}
+
+impl TenantStatus {
+    // Series of the same metric with other labels (route, status...) are summed.
+    pub fn collect(reg: &Registry, tenant: &str) -> Self {
+        let (requests_n, latency_n, hits_n, misses_n, waf_n) =
+            (reg.qualify(REQUESTS), reg.qualify(LATENCY), reg.qualify(CACHE_HITS), reg.qualify(CACHE_MISSES), reg.qualify(WAF_DECISIONS));
+        let mut requests = 0;
+        let mut lat = ([0u64; 16], 0u64, 0u64);
+        let (mut hits, mut misses) = (0u64, 0u64);
+        let mut waf: Vec<(String, u64)> = Vec::new();
+        for s in reg.gather_tenant(tenant) {
+            match s.value {
+                SampleValue::Counter(v) if s.name == requests_n => requests += v,
+                SampleValue::Counter(v) if s.name == hits_n => hits += v,
+                SampleValue::Counter(v) if s.name == misses_n => misses += v,
+                SampleValue::Counter(v) if s.name == waf_n => {
+                    let action = s.labels.iter().find(|(k, _)| k == "action").map(|(_, a)| a.clone()).unwrap_or_default();
+                    match waf.iter_mut().find(|(a, _)| *a == action) {
+                        Some(slot) => slot.1 += v,
+                        None => waf.push((action, v)),
+                    }
+                }
+                SampleValue::Histogram { bins, count, sum_us } if s.name == latency_n => {
+                    for (acc, b) in lat.0.iter_mut().zip(bins.iter()) {
+                        *acc += b;
+                    }
+                    lat.1 += count;
+                    lat.2 += sum_us;
+                }
+                _ => {}
+            }
+        }
+        waf.sort();
+        let h = LatencyHistogram::from_parts(lat.0, lat.1, lat.2);
+        let lookups = hits + misses;
+        Self {
+            tenant: tenant.to_string(),
+            requests,
+            latency_p50_ms: h.p50(),
+            latency_p99_ms: h.p99(),
+            cache_hit_ratio: if lookups == 0 { None } else { Some(hits as f64 / lookups as f64) },
+            waf,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"tenant\":");
+        write_str(&mut out, &self.tenant);
+        out.push_str(&format!(",\"requests\":{}", self.requests));
+        out.push_str(&format!(",\"latency_ms\":{{\"p50\":{},\"p99\":{}}}", cap(self.latency_p50_ms), cap(self.latency_p99_ms)));
+        match self.cache_hit_ratio {
+            Some(r) => out.push_str(&format!(",\"cache_hit_ratio\":{:.4}", r)),
+            None => out.push_str(",\"cache_hit_ratio\":null"),
+        }
+        out.push_str(",\"waf\":{");
+        for (i, (action, n)) in self.waf.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write_str(&mut out, action);
+            out.push_str(&format!(":{}", n));
+        }
+        out.push_str("}}");
+        out
+    }
+}
+
+// The open-ended top bucket is reported as null rather than u64::MAX.
+fn cap(ms: u64) -> String {
+    if ms == u64::MAX { "null".to_string() } else { ms.to_string() }
+}
+
+// Access-log lines kept per tenant for the status page, run through the
+// redaction policy (the default one unless `with_policy`) as they are recorded.
+pub struct TenantAccessLog {
+    per_tenant: usize,
+    max_tenants: usize,
+    policy: RedactionPolicy,
+    rings: Mutex<HashMap<String, VecDeque<String>>>,
+}
+
+impl TenantAccessLog {
+    pub fn new(per_tenant: usize, max_tenants: usize) -> Self {
+        Self { per_tenant: per_tenant.max(1), max_tenants: max_tenants.max(1), policy: RedactionPolicy::default(), rings: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn with_policy(mut self, policy: RedactionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    // Lines for tenants beyond `max_tenants` are dropped rather than evicting
+    // an existing tenant's history.
+    pub fn record(&self, tenant: &str, line: String) {
+        let mut rings = self.rings.lock().unwrap_or_else(|e| e.into_inner());
+        if !rings.contains_key(tenant) && rings.len() >= self.max_tenants {
+            return;
+        }
+        let ring = rings.entry(tenant.to_string()).or_default();
+        if ring.len() == self.per_tenant {
+            ring.pop_front();
+        }
+        ring.push_back(self.policy.redact_text(&line));
+    }
+
+    // Most recent `n` lines of this tenant, oldest first.
+    pub fn tail(&self, tenant: &str, n: usize) -> Vec<String> {
+        let rings = self.rings.lock().unwrap_or_else(|e| e.into_inner());
+        match rings.get(tenant) {
+            Some(r) => r.iter().skip(r.len().saturating_sub(n)).cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tenant_isolation() {
+        let reg = Registry::new().with_namespace("olwsx").unwrap().with_default_labels(&[("instance", "edge-1")]).unwrap();
+        reg.counter(REQUESTS, &[("tenant", "acme"), ("route", "/a")]).unwrap().add(3);
+        reg.counter(REQUESTS, &[("tenant", "acme"), ("route", "/b")]).unwrap().add(2);
+        reg.counter(REQUESTS, &[("tenant", "globex"), ("route", "/secret")]).unwrap().add(100);
+        reg.histogram(LATENCY, &[("tenant", "acme")]).unwrap().observe_ms(7);
+        reg.counter(CACHE_HITS, &[("tenant", "acme")]).unwrap().add(3);
+        reg.counter(CACHE_MISSES, &[("tenant", "acme")]).unwrap().inc();
+        reg.counter(WAF_DECISIONS, &[("tenant", "acme"), ("action", "deny")]).unwrap().add(2);
+        reg.gauge("inflight", &[]).unwrap().set(9);
+
+        let s = TenantStatus::collect(&reg, "acme");
+        assert_eq!((s.requests, s.latency_p50_ms, s.cache_hit_ratio), (5, 10, Some(0.75)));
+        assert_eq!(s.waf, vec![("deny".to_string(), 2)]);
+        assert_eq!(s.to_json(), r#"{"tenant":"acme","requests":5,"latency_ms":{"p50":10,"p99":10},"cache_hit_ratio":0.7500,"waf":{"deny":2}}"#);
+
+        let view = reg.gather_tenant("acme");
+        assert!(view.iter().all(|x| x.labels.iter().all(|(k, v)| k != "instance" && v != "globex")));
+        assert!(view.iter().all(|x| x.name != "olwsx_inflight"));
+
+        let log = TenantAccessLog::new(2, 8);
+        for l in ["a1", "a2", "a3"] {
+            log.record("acme", l.to_string());
+        }
+        log.record("globex", "g1".to_string());
+        assert_eq!(log.tail("acme", 10), vec!["a2".to_string(), "a3".to_string()]);
+        assert_eq!(log.tail("initech", 10), Vec::<String>::new());
+
+        let log = TenantAccessLog::new(2, 8).with_policy(RedactionPolicy::parse("pattern \\d{3}-\\d{4}").unwrap());
+        log.record("acme", "GET /login?token=abc 200 note=555-1234".to_string());
+        assert_eq!(log.tail("acme", 1), vec!["GET /login?token=[REDACTED] 200 note=[REDACTED]".to_string()]);
+    }
+}
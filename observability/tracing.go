@@ -8,6 +8,8 @@
 // - Minimal, self-contained tracer with deterministic span envelopes.
 // - Correlation IDs propagation (trace_id, span_id, actor_id).
 // - Fixed attributes set tailored for OLWSX (method, path, status, latency).
+// - http.target goes through RedactTarget (sensitive query values removed,
+//   as in the edge access log) before it is recorded.
 // - Context-safe helpers with zero allocations on hot path.
 // =============================================================================
 
@@ -15,6 +17,7 @@ package observability
 
 import (
 	"fmt"
+	"strings"
 	"sync"
 	"time"
 )
@@ -120,10 +123,42 @@ func (h *SpanHandle) End() {
 func (t *Tracer) StartHTTPSpan(method, path string, actor uint64) SpanHandle {
 	h := t.Start("http.server", 0, actor)
 	h.Set("http.method", method)
-	h.Set("http.target", path)
+	h.Set("http.target", RedactTarget(path))
 	return h
 }
 
+// Query parameters whose values are dropped from span targets; the same
+// list as redact::SENSITIVE_PARAMS on the Rust side.
+var SensitiveParams = []string{"access_token", "api_key", "apikey", "password", "secret", "sig", "signature", "token"}
+
+// RedactTarget replaces the values of SensitiveParams in a request target with
+// [REDACTED].
+func RedactTarget(target string) string {
+	path, query, ok := strings.Cut(target, "?")
+	if !ok {
+		return target
+	}
+	query, frag, hasFrag := strings.Cut(query, "#")
+	pairs := strings.Split(query, "&")
+	for i, p := range pairs {
+		name, _, isPair := strings.Cut(p, "=")
+		if !isPair {
+			continue
+		}
+		for _, s := range SensitiveParams {
+			if strings.EqualFold(name, s) {
+				pairs[i] = name + "=[REDACTED]"
+				break
+			}
+		}
+	}
+	out := path + "?" + strings.Join(pairs, "&")
+	if hasFrag {
+		out += "#" + frag
+	}
+	return out
+}
+
 func (t *Tracer) EndHTTPSpan(h SpanHandle, status int, bytes int, latencyMs float64) {
 	h.Set("http.status_code", fmt.Sprintf("%d", status))
 	h.Set("net.response_bytes", fmt.Sprintf("%d", bytes))
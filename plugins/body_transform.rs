@@ -0,0 +1,155 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/body_transform.rs
+// Role: Streaming response body substitution (bounded find/replace)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Find/replace across chunk boundaries without buffering the whole body;
+//   at most (longest needle - 1) bytes are held back between chunks.
+// - Optional cap on the number of replacements (e.g. inject a tag once).
+// - Keep framing honest: drop or recompute Content-Length after rewriting.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use olwsx_plugins_sdk::Response;
+
+mod olwsx_plugins_sdk {
+    pub use crate::sdk::Response;
+}
+
+#[derive(Clone, Debug)]
+pub struct Substitution {
+    pub find: Vec<u8>,
+    pub replace: Vec<u8>,
+}
+
+impl Substitution {
+    pub fn new(find: &[u8], replace: &[u8]) -> Self {
+        Self { find: find.to_vec(), replace: replace.to_vec() }
+    }
+}
+
+pub struct SubstitutionStream {
+    rules: Vec<Substitution>,
+    carry: Vec<u8>,
+    lookahead: usize,
+    remaining: Option<usize>, // replacements left; None = unlimited
+}
+
+impl SubstitutionStream {
+    // Rules are tried in order at each position; empty needles are ignored.
+    pub fn new(rules: Vec<Substitution>) -> Self {
+        let rules: Vec<Substitution> = rules.into_iter().filter(|r| !r.find.is_empty()).collect();
+        let lookahead = rules.iter().map(|r| r.find.len()).max().unwrap_or(1);
+        Self { rules, carry: Vec::new(), lookahead, remaining: None }
+    }
+
+    pub fn with_max_replacements(mut self, n: usize) -> Self {
+        self.remaining = Some(n);
+        self
+    }
+
+    // Feed the next body chunk; returns the bytes that are final.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        let mut buf = std::mem::take(&mut self.carry);
+        buf.extend_from_slice(chunk);
+        let (out, consumed) = self.scan(&buf, false);
+        self.carry = buf[consumed..].to_vec();
+        out
+    }
+
+    // End of body: flush held-back bytes.
+    pub fn finish(&mut self) -> Vec<u8> {
+        let buf = std::mem::take(&mut self.carry);
+        let (out, _) = self.scan(&buf, true);
+        out
+    }
+
+    fn scan(&mut self, buf: &[u8], last: bool) -> (Vec<u8>, usize) {
+        let mut out = Vec::with_capacity(buf.len());
+        let mut i = 0;
+        while i < buf.len() {
+            if self.remaining == Some(0) {
+                out.extend_from_slice(&buf[i..]);
+                return (out, buf.len());
+            }
+            // Without the full lookahead a needle might still complete in the next chunk.
+            if !last && buf.len() - i < self.lookahead {
+                break;
+            }
+            match self.rules.iter().find(|r| buf[i..].starts_with(&r.find)) {
+                Some(r) => {
+                    out.extend_from_slice(&r.replace);
+                    i += r.find.len();
+                    if let Some(n) = self.remaining.as_mut() {
+                        *n -= 1;
+                    }
+                }
+                None => {
+                    out.push(buf[i]);
+                    i += 1;
+                }
+            }
+        }
+        (out, i)
+    }
+}
+
+// Streaming output length is unknown up front; the writer falls back to chunked.
+pub fn prepare_streaming_headers(resp: &mut Response) {
+    resp.headers.retain(|(k, _)| !k.eq_ignore_ascii_case("content-length"));
+}
+
+// Whole-body convenience for already buffered responses; keeps Content-Length exact.
+pub fn apply(resp: &mut Response, rules: Vec<Substitution>) {
+    let mut s = SubstitutionStream::new(rules);
+    let mut body = s.push(&resp.body);
+    body.extend(s.finish());
+    resp.body = body;
+    let len = resp.body.len().to_string();
+    for (k, v) in resp.headers.iter_mut() {
+        if k.eq_ignore_ascii_case("content-length") {
+            *v = len.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_across_chunks() {
+        let rules = vec![
+            Substitution::new(b"</body>", b"<script src=\"/x.js\"></script></body>"),
+            Substitution::new(b"http://upstream:8080", b"https://proxy.example"),
+        ];
+        let mut s = SubstitutionStream::new(rules);
+        let mut out = Vec::new();
+        for chunk in [&b"<a href=\"http://ups"[..], b"tream:8080/p\">x</a></bo", b"dy>", b"</html>"] {
+            out.extend(s.push(chunk));
+        }
+        out.extend(s.finish());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "<a href=\"https://proxy.example/p\">x</a><script src=\"/x.js\"></script></body></html>"
+        );
+    }
+
+    #[test]
+    fn bounded_replacements_and_length() {
+        let mut r = Response::new(200);
+        r.headers.push(("Content-Length".to_string(), "6".to_string()));
+        r.body = b"aaXaaX".to_vec();
+        let mut s = SubstitutionStream::new(vec![Substitution::new(b"X", b"YY")]).with_max_replacements(1);
+        let mut out = s.push(&r.body);
+        out.extend(s.finish());
+        assert_eq!(out, b"aaYYaaX".to_vec());
+
+        apply(&mut r, vec![Substitution::new(b"X", b"YY")]);
+        assert_eq!(r.body, b"aaYYaaYY".to_vec());
+        assert_eq!(r.headers[0].1, "8");
+    }
+}
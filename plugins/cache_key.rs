@@ -0,0 +1,185 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/cache_key.rs
+// Role: Cache key participation for filters and handlers
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - The host opens a `KeyScope` per cacheable request; while it is open,
+//   plugins call `sdk::vary_cache_key(name, value)` to add a component (auth
+//   scope, A/B bucket, feature flag...) to that request's cache key.
+// - Components are attributed to the plugin in scope (crash breadcrumb), so
+//   two plugins using the same name never clash.
+// - `CacheKeyParts::apply` is deterministic: components sorted by
+//   "<plugin>.<name>", last value per component wins, values escaped.
+// - Keys share cache/key.rs's layout (CacheKeyBuilder):
+//     target | (0x00 "plugin:<plugin>.<name>" 0x00 value)*
+//   "plugin:" cannot start a header name, so components never collide with
+//   Vary headers, and NUL bytes in the target are escaped, so no request
+//   target can pose as another request's scoped key.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use crate::crash;
+use std::cell::RefCell;
+
+const MAX_PARTS: usize = 32; // per request; further components are refused
+const MAX_VALUE_LEN: usize = 256;
+const COMPONENT_PREFIX: &str = "plugin:";
+
+thread_local! {
+    static PARTS: RefCell<Option<Vec<(String, String)>>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheKeyParts {
+    parts: Vec<(String, String)>, // ("<plugin>.<name>", value), sorted by component
+}
+
+impl CacheKeyParts {
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    pub fn parts(&self) -> &[(String, String)] {
+        &self.parts
+    }
+
+    // Key for normalized target `base` with the plugin components appended;
+    // just `base` (NUL-escaped) when no plugin varied the key.
+    pub fn apply(&self, base: &str) -> String {
+        let mut out = base.replace('\0', "%00");
+        for (k, v) in self.parts.iter() {
+            out.push('\0');
+            out.push_str(COMPONENT_PREFIX);
+            out.push_str(&escape(k));
+            out.push('\0');
+            out.push_str(&escape(v));
+        }
+        out
+    }
+
+    // Appends the components to a key already built by cache::key::CacheKeyBuilder.
+    pub fn append_to(&self, key: &mut Vec<u8>) {
+        key.extend_from_slice(self.apply("").as_bytes());
+    }
+}
+
+// Collects components for one request on this thread; restores any outer
+// scope when dropped, like crash::ScopeGuard.
+pub struct KeyScope {
+    prev: Option<Vec<(String, String)>>,
+    done: bool,
+}
+
+pub fn scope() -> KeyScope {
+    let prev = PARTS.with(|p| p.borrow_mut().replace(Vec::new()));
+    KeyScope { prev, done: false }
+}
+
+impl KeyScope {
+    pub fn finish(mut self) -> CacheKeyParts {
+        self.done = true;
+        let raw = PARTS.with(|p| std::mem::replace(&mut *p.borrow_mut(), self.prev.take())).unwrap_or_default();
+        let mut parts: Vec<(String, String)> = Vec::with_capacity(raw.len());
+        for (k, v) in raw {
+            match parts.iter_mut().find(|(pk, _)| *pk == k) {
+                Some(slot) => slot.1 = v,
+                None => parts.push((k, v)),
+            }
+        }
+        parts.sort();
+        CacheKeyParts { parts }
+    }
+}
+
+impl Drop for KeyScope {
+    fn drop(&mut self) {
+        if !self.done {
+            let prev = self.prev.take();
+            PARTS.with(|p| *p.borrow_mut() = prev);
+        }
+    }
+}
+
+// Backs sdk::vary_cache_key. Outside a scope (response not cacheable) the call
+// is a no-op and returns Ok(false).
+pub(crate) fn vary(name: &str, value: &str) -> Result<bool, String> {
+    if name.is_empty() || !name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-') {
+        return Err(format!("invalid cache key component name '{}'", name));
+    }
+    if value.len() > MAX_VALUE_LEN {
+        return Err(format!("cache key component '{}' longer than {} bytes", name, MAX_VALUE_LEN));
+    }
+    let component = format!("{}.{}", crash::current_plugin().unwrap_or("host"), name);
+    PARTS.with(|p| match p.borrow_mut().as_mut() {
+        None => Ok(false),
+        Some(parts) if parts.len() >= MAX_PARTS && !parts.iter().any(|(k, _)| *k == component) => {
+            Err(format!("more than {} cache key components", MAX_PARTS))
+        }
+        Some(parts) => {
+            parts.push((component, value.to_string()));
+            Ok(true)
+        }
+    })
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'%' | b'=' | b';' | b'|' | 0..=0x1f | 0x7f.. => out.push_str(&format!("%{:02X}", b)),
+            _ => out.push(b as char),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdk::{vary_cache_key, FilterPlugin, FilterVerdict, PluginMeta, Registry, Request};
+    use std::collections::HashMap;
+
+    struct AbBucket;
+    impl FilterPlugin for AbBucket {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "ab", version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
+        fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
+        fn process(&self, req: &Request) -> FilterVerdict {
+            let bucket = if req.headers.iter().any(|(k, _)| k == "x-beta") { "b" } else { "a" };
+            vary_cache_key("bucket", bucket).unwrap();
+            FilterVerdict::Continue
+        }
+    }
+
+    #[test]
+    fn plugins_vary_the_key() {
+        let mut reg = Registry::new();
+        reg.register_filter("ab", Box::new(AbBucket)).unwrap();
+        let req = Request { method: "GET", path: "/home", headers: vec![("x-beta".into(), "1".into())], body: vec![], tenant: "default" };
+
+        // no scope: not cacheable, nothing recorded
+        assert_eq!(vary_cache_key("scope", "admin"), Ok(false));
+
+        let s = scope();
+        reg.filter("ab", &req);
+        vary_cache_key("scope", "read;write").unwrap();
+        vary_cache_key("scope", "read").unwrap();
+        let parts = s.finish();
+        assert_eq!(parts.apply("/home"), "/home\0plugin:ab.bucket\0b\0plugin:host.scope\0read");
+        assert_eq!(scope().finish().apply("/home"), "/home");
+
+        // a target cannot forge another request's scoped key
+        let s = scope();
+        vary_cache_key("scope", "admin").unwrap();
+        let scoped = s.finish().apply("/x");
+        assert_ne!(scope().finish().apply("/x\0plugin:host.scope\0admin"), scoped);
+        assert_ne!(scope().finish().apply("/x|vary:host.scope=admin"), scoped);
+
+        let mut key = b"/home\0accept-encoding\0gzip".to_vec();
+        parts.append_to(&mut key);
+        assert_eq!(key, b"/home\0accept-encoding\0gzip\0plugin:ab.bucket\0b\0plugin:host.scope\0read".to_vec());
+        assert!(vary_cache_key("bad name", "x").is_err());
+    }
+}
@@ -0,0 +1,106 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/cancel.rs
+// Role: Request cancellation for synchronous filters and handlers
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - The host creates a `CancelToken` per request and cancels it when the
+//   client disconnects; `scope(&token)` makes it current on the thread that
+//   runs the pipeline.
+// - Plugins doing long work poll `sdk::cancelled()` and return early; the
+//   host discards whatever they return for a cancelled request.
+// - The registry skips handlers entirely once the request is cancelled.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+thread_local! {
+    static CURRENT: RefCell<Option<CancelToken>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Idempotent; may be called from any thread (e.g. a disconnect watcher).
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Acquire)
+    }
+}
+
+// Makes a token current on this thread; restores any outer one when dropped,
+// like crash::ScopeGuard.
+pub struct CancelScope {
+    prev: Option<CancelToken>,
+}
+
+pub fn scope(token: &CancelToken) -> CancelScope {
+    let prev = CURRENT.with(|c| c.borrow_mut().replace(token.clone()));
+    CancelScope { prev }
+}
+
+impl Drop for CancelScope {
+    fn drop(&mut self) {
+        let prev = self.prev.take();
+        CURRENT.with(|c| *c.borrow_mut() = prev);
+    }
+}
+
+// Backs sdk::cancelled. Outside a scope nothing can cancel the work.
+pub(crate) fn is_cancelled() -> bool {
+    CURRENT.with(|c| c.borrow().as_ref().is_some_and(CancelToken::is_cancelled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdk::{cancelled, HandlerPlugin, HandlerResult, PluginMeta, Registry, Request, Response};
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicUsize;
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Report;
+    impl HandlerPlugin for Report {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "report", version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
+        fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
+        fn handle(&self, _req: &Request) -> HandlerResult {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            HandlerResult { resp: Response::new(if cancelled() { 503 } else { 200 }), meta_flags: 0 }
+        }
+    }
+
+    #[test]
+    fn cancelled_requests_skip_handlers() {
+        let mut reg = Registry::new();
+        reg.register_handler("report", Box::new(Report)).unwrap();
+        let req = Request { method: "GET", path: "/r", headers: vec![], body: vec![], tenant: "default" };
+
+        let token = CancelToken::new();
+        {
+            let _s = scope(&token);
+            assert!(!cancelled());
+            assert_eq!(reg.handle("report", &req).unwrap().resp.status, 200);
+            token.cancel();
+            assert!(cancelled());
+            assert_eq!(reg.handle("report", &req).unwrap().resp.status, 499);
+        }
+        assert!(!cancelled(), "scope restored on drop");
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,244 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/crash.rs
+// Role: Crash reporting (panic hook + per-thread last-request breadcrumb)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Keep a cheap thread-local breadcrumb: request id, route, plugin in scope.
+// - On panic, write a structured report to a dedicated sink *before* the
+//   previous hook runs and the panic propagates.
+// - Truncated backtrace so reports stay bounded.
+// - The hook is process-wide: installed once, explicitly, at host startup.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use olwsx_plugins_sdk::Json;
+use std::cell::RefCell;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+mod olwsx_plugins_sdk {
+    pub use crate::sdk::Json;
+}
+
+const MAX_BACKTRACE_LINES: usize = 32;
+
+#[derive(Clone, Debug, Default)]
+struct Breadcrumb {
+    request_id: Option<String>,
+    route: Option<String>,
+    plugin: Option<&'static str>,
+}
+
+thread_local! {
+    static CRUMB: RefCell<Breadcrumb> = RefCell::new(Breadcrumb::default());
+}
+
+#[derive(Clone, Debug)]
+pub struct CrashReport {
+    pub thread: String,
+    pub location: String, // file:line of the panic
+    pub message: String,
+    pub request_id: Option<String>,
+    pub route: Option<String>,
+    pub plugin: Option<&'static str>,
+    pub backtrace: Vec<String>,
+}
+
+impl CrashReport {
+    pub fn to_json(&self) -> Json {
+        Json::obj()
+            .set("thread", self.thread.as_str())
+            .set("location", self.location.as_str())
+            .set("message", self.message.as_str())
+            .set("request_id", self.request_id.clone())
+            .set("route", self.route.clone())
+            .set("plugin", self.plugin)
+            .set("backtrace", self.backtrace.clone())
+    }
+}
+
+pub trait CrashSink: Send + Sync {
+    fn write(&self, report: &CrashReport);
+}
+
+// Appends one JSON object per line; errors are swallowed (we are already crashing).
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl CrashSink for FileSink {
+    fn write(&self, report: &CrashReport) {
+        if let Ok(mut f) = OpenOptions::new().create(true).append(true).open(&self.path) {
+            let mut line = report.to_json().to_bytes();
+            line.push(b'\n');
+            let _ = f.write_all(&line);
+        }
+    }
+}
+
+// In-memory sink for tests and embedders that forward reports themselves.
+#[derive(Clone, Default)]
+pub struct MemorySink {
+    pub reports: Arc<Mutex<Vec<CrashReport>>>,
+}
+
+impl CrashSink for MemorySink {
+    fn write(&self, report: &CrashReport) {
+        if let Ok(mut r) = self.reports.lock() {
+            r.push(report.clone());
+        }
+    }
+}
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+// Install the reporting hook; the previously installed hook still runs
+// afterwards. The hook is process-wide, so only the host's startup should
+// call this, once: later calls leave the first sink in place and return false.
+pub fn install(sink: Arc<dyn CrashSink>) -> bool {
+    if INSTALLED.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+    let prev = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // a panic inside update() still holds the breadcrumb mutably
+        let crumb = CRUMB.try_with(|c| c.try_borrow().map(|b| b.clone()).unwrap_or_default()).unwrap_or_default();
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "non-string panic payload".to_string());
+        let bt = std::backtrace::Backtrace::force_capture().to_string();
+        let report = CrashReport {
+            thread: std::thread::current().name().unwrap_or("unnamed").to_string(),
+            location: info.location().map(|l| format!("{}:{}", l.file(), l.line())).unwrap_or_default(),
+            message,
+            request_id: crumb.request_id,
+            route: crumb.route,
+            plugin: crumb.plugin,
+            backtrace: bt.lines().take(MAX_BACKTRACE_LINES).map(|l| l.to_string()).collect(),
+        };
+        sink.write(&report);
+        prev(info);
+    }));
+    true
+}
+
+// Restores the previous breadcrumb when dropped, so scopes nest correctly.
+pub struct ScopeGuard {
+    prev: Breadcrumb,
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let prev = std::mem::take(&mut self.prev);
+        CRUMB.with(|c| *c.borrow_mut() = prev);
+    }
+}
+
+// Mark the start of a request on this thread (request id + route).
+pub fn request_scope(request_id: &str, route: &str) -> ScopeGuard {
+    update(|c| {
+        c.request_id = Some(request_id.to_string());
+        c.route = Some(route.to_string());
+        c.plugin = None;
+    })
+}
+
+// Mark the plugin currently executing (set by the Registry around each call).
+pub fn plugin_scope(key: &'static str) -> ScopeGuard {
+    update(|c| c.plugin = Some(key))
+}
+
+// Plugin key of the call in progress on this thread, if any.
+pub fn current_plugin() -> Option<&'static str> {
+    CRUMB.try_with(|c| c.try_borrow().ok().and_then(|b| b.plugin)).ok().flatten()
+}
+
+fn update<F: FnOnce(&mut Breadcrumb)>(f: F) -> ScopeGuard {
+    CRUMB.with(|c| {
+        let mut cur = c.borrow_mut();
+        let prev = cur.clone();
+        f(&mut cur);
+        ScopeGuard { prev }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdk::{HandlerPlugin, HandlerResult, PluginMeta, Registry, Request};
+    use std::collections::HashMap;
+
+    struct Boom;
+    impl HandlerPlugin for Boom {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "boom", version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
+        fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
+        fn handle(&self, _req: &Request) -> HandlerResult { panic!("handler exploded") }
+    }
+
+    const CHILD_ENV: &str = "OLWSX_CRASH_TEST_REPORT";
+
+    // The hook is process-wide, so it is installed in a child copy of this
+    // test binary (running only `crash_child`) rather than in the test runner.
+    #[test]
+    fn report_has_breadcrumb() {
+        let out = std::env::temp_dir().join(format!("olwsx-crash-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&out);
+        let module = module_path!().split_once("::").map_or(module_path!(), |(_, m)| m);
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", &format!("{module}::crash_child"), "--test-threads=1"])
+            .env(CHILD_ENV, &out)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let report = std::fs::read_to_string(&out).expect("report written");
+        let _ = std::fs::remove_file(&out);
+        assert_eq!(report.lines().count(), 2, "{report}");
+        assert!(report.contains(r#""thread":"crash-test""#), "{report}");
+        assert!(report.contains(r#""message":"handler exploded""#));
+        assert!(report.contains(r#""request_id":"req-42""#));
+        assert!(report.contains(r#""plugin":"boom""#));
+        assert!(report.contains(r#""message":"inside update""#));
+    }
+
+    #[test]
+    fn crash_child() {
+        let Some(out) = std::env::var_os(CHILD_ENV) else { return };
+        assert!(install(Arc::new(FileSink::new(out))));
+        assert!(!install(Arc::new(MemorySink::default())), "second install is a no-op");
+
+        let mut reg = Registry::new();
+        reg.register_handler("boom", Box::new(Boom)).unwrap();
+        let req = Request { method: "GET", path: "/explode", headers: vec![], body: vec![], tenant: "default" };
+        let res = std::thread::Builder::new()
+            .name("crash-test".to_string())
+            .spawn(move || {
+                let _r = request_scope("req-42", "/explode");
+                reg.handle("boom", &req)
+            })
+            .unwrap()
+            .join();
+        assert!(res.is_err());
+
+        // a panic while the breadcrumb is mutably borrowed is still reported
+        let nested = std::thread::spawn(|| update(|_| panic!("inside update"))).join();
+        assert!(nested.is_err());
+    }
+}
@@ -0,0 +1,604 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/expr.rs
+// Role: Sandboxed condition/key expressions for config (Lua-flavoured syntax)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Compile once at config load; typed statically (string, int, bool), so a
+//   compiled expression cannot fail at request time.
+// - No loops, no recursion, no assignment: evaluation visits each node once,
+//   and source length, node count, nesting and string sizes are capped.
+// - Request accessors: req.path, req.method, req.tenant, req.host,
+//   req.header("n"), req.query("n"), req.cookie("n") (absent -> "").
+// - Operators: or/||, and/&&, not/!, == ~= != < <= > >=, startswith,
+//   endswith, contains, .. (concat). Functions: lower, upper, len, tostring,
+//   flag("name") (runtime feature flag, see flags.rs; read per evaluation).
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use olwsx_plugins_sdk::{header, Request};
+
+mod olwsx_plugins_sdk {
+    pub use crate::sdk::{header, Request};
+}
+
+const MAX_SOURCE: usize = 1024;
+const MAX_NODES: usize = 128;
+const MAX_DEPTH: usize = 16;
+const MAX_STRING: usize = 4096; // concat results are truncated here
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Type {
+    Str,
+    Int,
+    Bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+}
+
+impl Value {
+    pub fn as_bool(&self) -> bool {
+        matches!(self, Value::Bool(true))
+    }
+
+    pub fn into_string(self) -> String {
+        match self {
+            Value::Str(s) => s,
+            Value::Int(i) => i.to_string(),
+            Value::Bool(b) => b.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    StartsWith,
+    EndsWith,
+    Contains,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Path,
+    Method,
+    Tenant,
+    Host,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Lookup {
+    Header,
+    Query,
+    Cookie,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Func {
+    Lower,
+    Upper,
+    Len,
+    ToString,
+    Flag,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Lit(Value),
+    Field(Field),
+    Lookup(Lookup, Box<Node>),
+    Call(Func, Box<Node>),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Cmp(Cmp, Box<Node>, Box<Node>),
+    Concat(Box<Node>, Box<Node>),
+}
+
+// A compiled expression; `source` is kept for diagnostics and Debug output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    source: String,
+    root: Node,
+    ty: Type,
+}
+
+impl Expr {
+    pub fn compile(src: &str) -> Result<Self, String> {
+        if src.len() > MAX_SOURCE {
+            return Err(format!("expression longer than {} bytes", MAX_SOURCE));
+        }
+        let tokens = lex(src)?;
+        let mut p = Parser { tokens, pos: 0, nodes: 0 };
+        let root = p.or(0)?;
+        if let Some(t) = p.tokens.get(p.pos) {
+            return Err(format!("unexpected {} in '{}'", t.describe(), src));
+        }
+        let ty = type_of(&root)?;
+        Ok(Self { source: src.to_string(), root, ty })
+    }
+
+    // Route conditions and rewrite guards: must be boolean.
+    pub fn condition(src: &str) -> Result<Self, String> {
+        let e = Self::compile(src)?;
+        if e.ty != Type::Bool {
+            return Err(format!("condition '{}' is {:?}, expected a boolean", src, e.ty));
+        }
+        Ok(e)
+    }
+
+    // Rate-limit keys: must produce a string.
+    pub fn key(src: &str) -> Result<Self, String> {
+        let e = Self::compile(src)?;
+        if e.ty != Type::Str {
+            return Err(format!("key expression '{}' is {:?}, expected a string", src, e.ty));
+        }
+        Ok(e)
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn ty(&self) -> Type {
+        self.ty
+    }
+
+    pub fn eval(&self, req: &Request) -> Value {
+        eval(&self.root, req)
+    }
+
+    pub fn matches(&self, req: &Request) -> bool {
+        self.eval(req).as_bool()
+    }
+}
+
+// ------------------------------- Lexer --------------------------------------
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Str(String),
+    Int(i64),
+    Ident(String),
+    Op(&'static str),
+}
+
+impl Tok {
+    fn describe(&self) -> String {
+        match self {
+            Tok::Str(s) => format!("string \"{}\"", s),
+            Tok::Int(i) => format!("number {}", i),
+            Tok::Ident(s) => format!("'{}'", s),
+            Tok::Op(o) => format!("'{}'", o),
+        }
+    }
+}
+
+const OPS: [&str; 16] = ["&&", "||", "==", "~=", "!=", "<=", ">=", "..", "<", ">", "!", "(", ")", ".", ",", "-"];
+
+fn lex(src: &str) -> Result<Vec<Tok>, String> {
+    let b = src.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < b.len() {
+        let c = b[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if c == b'"' || c == b'\'' {
+            let mut s = String::new();
+            i += 1;
+            loop {
+                match b.get(i) {
+                    None => return Err("unterminated string".to_string()),
+                    Some(q) if *q == c => break,
+                    Some(b'\\') => {
+                        match b.get(i + 1) {
+                            Some(b'n') => s.push('\n'),
+                            Some(b't') => s.push('\t'),
+                            Some(e @ (b'\\' | b'"' | b'\'')) => s.push(*e as char),
+                            _ => return Err("invalid escape in string".to_string()),
+                        }
+                        i += 2;
+                    }
+                    Some(_) => {
+                        // copy one UTF-8 character
+                        let ch = src[i..].chars().next().unwrap_or('\u{fffd}');
+                        s.push(ch);
+                        i += ch.len_utf8();
+                    }
+                }
+            }
+            i += 1;
+            out.push(Tok::Str(s));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < b.len() && b[i].is_ascii_digit() {
+                i += 1;
+            }
+            let n = src[start..i].parse::<i64>().map_err(|_| format!("number '{}' out of range", &src[start..i]))?;
+            out.push(Tok::Int(n));
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            let start = i;
+            while i < b.len() && (b[i].is_ascii_alphanumeric() || b[i] == b'_') {
+                i += 1;
+            }
+            out.push(Tok::Ident(src[start..i].to_string()));
+        } else {
+            let op = OPS.iter().find(|o| src[i..].starts_with(**o)).ok_or_else(|| format!("unexpected character '{}'", &src[i..].chars().next().unwrap_or('?')))?;
+            i += op.len();
+            out.push(Tok::Op(op));
+        }
+    }
+    Ok(out)
+}
+
+// ------------------------------- Parser -------------------------------------
+
+struct Parser {
+    tokens: Vec<Tok>,
+    pos: usize,
+    nodes: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos)
+    }
+
+    fn is_op(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Op(o)) if *o == op)
+    }
+
+    fn is_word(&self, w: &str) -> bool {
+        matches!(self.peek(), Some(Tok::Ident(s)) if s == w)
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<(), String> {
+        if self.is_op(op) {
+            self.pos += 1;
+            return Ok(());
+        }
+        Err(match self.peek() {
+            Some(t) => format!("expected '{}', found {}", op, t.describe()),
+            None => format!("expected '{}' at end of expression", op),
+        })
+    }
+
+    fn node(&mut self, n: Node) -> Result<Node, String> {
+        self.nodes += 1;
+        if self.nodes > MAX_NODES {
+            return Err(format!("expression has more than {} nodes", MAX_NODES));
+        }
+        Ok(n)
+    }
+
+    fn or(&mut self, depth: usize) -> Result<Node, String> {
+        if depth > MAX_DEPTH {
+            return Err(format!("expression nested deeper than {}", MAX_DEPTH));
+        }
+        let mut lhs = self.and(depth)?;
+        while self.is_op("||") || self.is_word("or") {
+            self.pos += 1;
+            let rhs = self.and(depth)?;
+            lhs = self.node(Node::Or(Box::new(lhs), Box::new(rhs)))?;
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self, depth: usize) -> Result<Node, String> {
+        let mut lhs = self.not(depth)?;
+        while self.is_op("&&") || self.is_word("and") {
+            self.pos += 1;
+            let rhs = self.not(depth)?;
+            lhs = self.node(Node::And(Box::new(lhs), Box::new(rhs)))?;
+        }
+        Ok(lhs)
+    }
+
+    fn not(&mut self, depth: usize) -> Result<Node, String> {
+        if self.is_op("!") || self.is_word("not") {
+            self.pos += 1;
+            if depth + 1 > MAX_DEPTH {
+                return Err(format!("expression nested deeper than {}", MAX_DEPTH));
+            }
+            let inner = self.not(depth + 1)?;
+            return self.node(Node::Not(Box::new(inner)));
+        }
+        self.cmp(depth)
+    }
+
+    fn cmp(&mut self, depth: usize) -> Result<Node, String> {
+        let lhs = self.concat(depth)?;
+        let op = match self.peek() {
+            Some(Tok::Op("==")) => Cmp::Eq,
+            Some(Tok::Op("!=")) | Some(Tok::Op("~=")) => Cmp::Ne,
+            Some(Tok::Op("<")) => Cmp::Lt,
+            Some(Tok::Op("<=")) => Cmp::Le,
+            Some(Tok::Op(">")) => Cmp::Gt,
+            Some(Tok::Op(">=")) => Cmp::Ge,
+            Some(Tok::Ident(w)) if w == "startswith" => Cmp::StartsWith,
+            Some(Tok::Ident(w)) if w == "endswith" => Cmp::EndsWith,
+            Some(Tok::Ident(w)) if w == "contains" => Cmp::Contains,
+            _ => return Ok(lhs),
+        };
+        self.pos += 1;
+        let rhs = self.concat(depth)?;
+        self.node(Node::Cmp(op, Box::new(lhs), Box::new(rhs)))
+    }
+
+    fn concat(&mut self, depth: usize) -> Result<Node, String> {
+        let mut lhs = self.primary(depth)?;
+        while self.is_op("..") {
+            self.pos += 1;
+            let rhs = self.primary(depth)?;
+            lhs = self.node(Node::Concat(Box::new(lhs), Box::new(rhs)))?;
+        }
+        Ok(lhs)
+    }
+
+    fn primary(&mut self, depth: usize) -> Result<Node, String> {
+        let tok = self.peek().cloned().ok_or("unexpected end of expression")?;
+        self.pos += 1;
+        let node = match tok {
+            Tok::Str(s) => Node::Lit(Value::Str(s)),
+            Tok::Int(i) => Node::Lit(Value::Int(i)),
+            Tok::Op("-") => match self.peek().cloned() {
+                Some(Tok::Int(i)) => {
+                    self.pos += 1;
+                    Node::Lit(Value::Int(-i))
+                }
+                _ => return Err("'-' must be followed by a number".to_string()),
+            },
+            Tok::Op("(") => {
+                let inner = self.or(depth + 1)?;
+                self.expect_op(")")?;
+                return Ok(inner);
+            }
+            Tok::Ident(w) if w == "true" => Node::Lit(Value::Bool(true)),
+            Tok::Ident(w) if w == "false" => Node::Lit(Value::Bool(false)),
+            Tok::Ident(w) if w == "req" => return self.request(depth),
+            Tok::Ident(w) => {
+                let f = match w.as_str() {
+                    "lower" => Func::Lower,
+                    "upper" => Func::Upper,
+                    "len" => Func::Len,
+                    "tostring" => Func::ToString,
+                    "flag" => Func::Flag,
+                    _ => return Err(format!("unknown name '{}'", w)),
+                };
+                let arg = self.argument(depth)?;
+                Node::Call(f, Box::new(arg))
+            }
+            t => return Err(format!("unexpected {}", t.describe())),
+        };
+        self.node(node)
+    }
+
+    fn request(&mut self, depth: usize) -> Result<Node, String> {
+        self.expect_op(".")?;
+        let Some(Tok::Ident(name)) = self.peek().cloned() else { return Err("expected a field after 'req.'".to_string()) };
+        self.pos += 1;
+        let node = match name.as_str() {
+            "path" => Node::Field(Field::Path),
+            "method" => Node::Field(Field::Method),
+            "tenant" => Node::Field(Field::Tenant),
+            "host" => Node::Field(Field::Host),
+            "header" | "query" | "cookie" => {
+                let l = match name.as_str() {
+                    "header" => Lookup::Header,
+                    "query" => Lookup::Query,
+                    _ => Lookup::Cookie,
+                };
+                let arg = self.argument(depth)?;
+                Node::Lookup(l, Box::new(arg))
+            }
+            _ => return Err(format!("unknown request field 'req.{}'", name)),
+        };
+        self.node(node)
+    }
+
+    fn argument(&mut self, depth: usize) -> Result<Node, String> {
+        self.expect_op("(")?;
+        let arg = self.or(depth + 1)?;
+        self.expect_op(")")?;
+        Ok(arg)
+    }
+}
+
+// ------------------------------- Types --------------------------------------
+
+fn type_of(n: &Node) -> Result<Type, String> {
+    let want = |n: &Node, t: Type, what: &str| -> Result<(), String> {
+        let got = type_of(n)?;
+        if got != t {
+            return Err(format!("{} expects {:?}, got {:?}", what, t, got));
+        }
+        Ok(())
+    };
+    Ok(match n {
+        Node::Lit(Value::Str(_)) | Node::Field(_) => Type::Str,
+        Node::Lit(Value::Int(_)) => Type::Int,
+        Node::Lit(Value::Bool(_)) => Type::Bool,
+        Node::Lookup(_, arg) => {
+            want(arg, Type::Str, "lookup name")?;
+            Type::Str
+        }
+        Node::Call(Func::Lower | Func::Upper, a) => {
+            want(a, Type::Str, "lower/upper")?;
+            Type::Str
+        }
+        Node::Call(Func::Len, a) => {
+            want(a, Type::Str, "len")?;
+            Type::Int
+        }
+        Node::Call(Func::ToString, a) => {
+            type_of(a)?;
+            Type::Str
+        }
+        Node::Call(Func::Flag, a) => {
+            want(a, Type::Str, "flag")?;
+            Type::Bool
+        }
+        Node::Not(a) => {
+            want(a, Type::Bool, "not")?;
+            Type::Bool
+        }
+        Node::And(a, b) | Node::Or(a, b) => {
+            want(a, Type::Bool, "and/or")?;
+            want(b, Type::Bool, "and/or")?;
+            Type::Bool
+        }
+        Node::Concat(a, b) => {
+            let (ta, tb) = (type_of(a)?, type_of(b)?);
+            if ta == Type::Bool || tb == Type::Bool {
+                return Err("'..' expects strings or numbers".to_string());
+            }
+            Type::Str
+        }
+        Node::Cmp(op, a, b) => {
+            let (ta, tb) = (type_of(a)?, type_of(b)?);
+            match op {
+                Cmp::StartsWith | Cmp::EndsWith | Cmp::Contains if ta != Type::Str || tb != Type::Str => {
+                    return Err(format!("{:?} expects strings", op).to_lowercase());
+                }
+                Cmp::Lt | Cmp::Le | Cmp::Gt | Cmp::Ge if ta != tb || ta == Type::Bool => {
+                    return Err(format!("cannot order {:?} and {:?}", ta, tb));
+                }
+                Cmp::Eq | Cmp::Ne if ta != tb => return Err(format!("cannot compare {:?} with {:?}", ta, tb)),
+                _ => {}
+            }
+            Type::Bool
+        }
+    })
+}
+
+// ------------------------------- Evaluation ---------------------------------
+
+fn eval(n: &Node, req: &Request) -> Value {
+    match n {
+        Node::Lit(v) => v.clone(),
+        Node::Field(f) => Value::Str(match f {
+            Field::Path => req.path.split('?').next().unwrap_or("").to_string(),
+            Field::Method => req.method.to_string(),
+            Field::Tenant => req.tenant.to_string(),
+            Field::Host => header(req, "host").unwrap_or("").to_string(),
+        }),
+        Node::Lookup(l, arg) => {
+            let name = eval(arg, req).into_string();
+            Value::Str(match l {
+                Lookup::Header => header(req, &name).unwrap_or("").to_string(),
+                Lookup::Query => query_param(req.path, &name),
+                Lookup::Cookie => cookie(req, &name),
+            })
+        }
+        Node::Call(f, a) => {
+            let v = eval(a, req);
+            match f {
+                Func::Lower => Value::Str(v.into_string().to_lowercase()),
+                Func::Upper => Value::Str(v.into_string().to_uppercase()),
+                Func::Len => Value::Int(v.into_string().len() as i64),
+                Func::ToString => Value::Str(v.into_string()),
+                Func::Flag => Value::Bool(crate::flags::enabled(&v.into_string())),
+            }
+        }
+        Node::Not(a) => Value::Bool(!eval(a, req).as_bool()),
+        Node::And(a, b) => Value::Bool(eval(a, req).as_bool() && eval(b, req).as_bool()),
+        Node::Or(a, b) => Value::Bool(eval(a, req).as_bool() || eval(b, req).as_bool()),
+        Node::Concat(a, b) => {
+            let mut s = eval(a, req).into_string();
+            s.push_str(&eval(b, req).into_string());
+            if s.len() > MAX_STRING {
+                let mut cut = MAX_STRING;
+                while !s.is_char_boundary(cut) {
+                    cut -= 1;
+                }
+                s.truncate(cut);
+            }
+            Value::Str(s)
+        }
+        Node::Cmp(op, a, b) => {
+            let (x, y) = (eval(a, req), eval(b, req));
+            Value::Bool(match (op, &x, &y) {
+                (Cmp::Eq, _, _) => x == y,
+                (Cmp::Ne, _, _) => x != y,
+                (Cmp::StartsWith, Value::Str(s), Value::Str(p)) => s.starts_with(p.as_str()),
+                (Cmp::EndsWith, Value::Str(s), Value::Str(p)) => s.ends_with(p.as_str()),
+                (Cmp::Contains, Value::Str(s), Value::Str(p)) => s.contains(p.as_str()),
+                (Cmp::Lt, Value::Int(i), Value::Int(j)) => i < j,
+                (Cmp::Le, Value::Int(i), Value::Int(j)) => i <= j,
+                (Cmp::Gt, Value::Int(i), Value::Int(j)) => i > j,
+                (Cmp::Ge, Value::Int(i), Value::Int(j)) => i >= j,
+                (Cmp::Lt, Value::Str(s), Value::Str(t)) => s < t,
+                (Cmp::Le, Value::Str(s), Value::Str(t)) => s <= t,
+                (Cmp::Gt, Value::Str(s), Value::Str(t)) => s > t,
+                (Cmp::Ge, Value::Str(s), Value::Str(t)) => s >= t,
+                _ => false, // ruled out by type_of
+            })
+        }
+    }
+}
+
+// Raw (still percent-encoded) value of the first matching parameter.
+fn query_param(target: &str, name: &str) -> String {
+    let Some((_, q)) = target.split_once('?') else { return String::new() };
+    q.split('&')
+        .filter_map(|p| {
+            let (k, v) = p.split_once('=').unwrap_or((p, ""));
+            (k == name).then(|| v.to_string())
+        })
+        .next()
+        .unwrap_or_default()
+}
+
+fn cookie(req: &Request, name: &str) -> String {
+    req.headers
+        .iter()
+        .filter(|(k, _)| k.eq_ignore_ascii_case("cookie"))
+        .flat_map(|(_, v)| v.split(';'))
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v.to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_and_eval() {
+        let req = Request {
+            method: "GET",
+            path: "/v2/items?page=3",
+            headers: vec![("X-Api-Key".into(), "k1".into()), ("Cookie".into(), "ab=b; sid=9".into())],
+            body: vec![],
+            tenant: "acme",
+        };
+        let c = Expr::condition(r#"req.header("x-api-key") != "" && req.path startswith "/v2""#).unwrap();
+        assert!(c.matches(&req));
+        let c = Expr::condition(r#"not (req.method == "POST" or len(req.query("page")) > 1)"#).unwrap();
+        assert!(c.matches(&req));
+        let k = Expr::key(r#"req.tenant .. ":" .. lower(req.header("X-API-KEY")) .. ":" .. req.cookie("ab")"#).unwrap();
+        assert_eq!(k.eval(&req), Value::Str("acme:k1:b".to_string()));
+
+        // rejected at load time
+        assert!(Expr::condition(r#"req.path"#).is_err());
+        assert!(Expr::condition(r#"req.path == 1"#).is_err());
+        assert!(Expr::condition(r#"req.body == """#).is_err());
+        assert!(Expr::condition(r#"os.execute("x")"#).is_err());
+        assert!(Expr::condition(&"(".repeat(40)).is_err());
+        assert!(Expr::key(&vec![r#""a""#; 200].join(" .. ")).is_err());
+    }
+}
@@ -12,11 +12,11 @@
 #![forbid(unsafe_code)]
 
 use std::collections::HashMap;
-use olwsx_plugins_sdk::{Request, Response, FilterVerdict, PluginMeta, FilterPlugin, add_header};
+use olwsx_plugins_sdk::{Request, Response, FilterVerdict, PluginMeta, FilterPlugin, add_header, caps};
 
 mod olwsx_plugins_sdk {
     // Re-export types from sdk.rs (assuming path alias when building)
-    pub use crate::sdk::{Request, Response, FilterVerdict, PluginMeta, FilterPlugin, add_header};
+    pub use crate::sdk::{Request, Response, FilterVerdict, PluginMeta, FilterPlugin, add_header, caps};
 }
 
 pub struct GuardFilter {
@@ -30,7 +30,7 @@ pub struct GuardFilter {
 impl GuardFilter {
     pub fn new() -> Self {
         Self {
-            meta: PluginMeta { name: "guard_filter", version: "1.0.0", author: "OverLab", flags: 0x0010_0000 },
+            meta: PluginMeta { name: "guard_filter", version: "1.0.0", author: "OverLab", flags: 0x0010_0000, caps: caps::MUTATE_PATH },
             deny_traversal: true,
             add_server_header: true,
             rewrite_prefix_from: None,
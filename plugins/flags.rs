@@ -0,0 +1,390 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/flags.rs
+// Role: Runtime feature flags (persisted key-value store with change notify)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Named string flags, local to the node (nothing is replicated), persisted
+//   to one file of "name=value" lines; every write replaces the file
+//   atomically (temp file + rename).
+// - The admin API edits the same file; `watch_file` picks external edits up
+//   within one poll interval, so flags flip without a config reload.
+// - Writers on either side hold an advisory lock on "<file>.lock" and start
+//   from the file as it is on disk, so neither overwrites an edit the other
+//   made since it last looked.
+// - Watchers are called once per changed flag, after the change is visible
+//   and without any store lock held.
+// - `start` (or `start_from_env`, reading OLWSX_FLAGS_FILE) opens, installs
+//   and watches the node's flag file in one step.
+// - Consumers read the installed store: sdk::flag / sdk::flag_enabled for
+//   plugins, flag("name") in expressions, `flag "name"` in WAF rules.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+pub const FLAGS_FILE_ENV: &str = "OLWSX_FLAGS_FILE";
+
+const MAX_NAME: usize = 64;
+const MAX_VALUE: usize = 1024;
+
+static INSTALLED: RwLock<Option<Arc<FlagStore>>> = RwLock::new(None);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlagChange {
+    pub name: String,
+    pub old: Option<String>,
+    pub new: Option<String>, // None: removed
+    pub version: u64,
+}
+
+type Watcher = Arc<dyn Fn(&FlagChange) + Send + Sync>;
+
+#[derive(Default)]
+struct State {
+    values: BTreeMap<String, String>,
+    version: u64,
+    stamp: Option<(SystemTime, u64)>, // (mtime, len) of the file as last read or written
+}
+
+pub struct FlagStore {
+    state: RwLock<State>,
+    path: Option<PathBuf>,
+    watchers: Mutex<Vec<(u64, Watcher)>>,
+    next_watch: Mutex<u64>,
+}
+
+impl FlagStore {
+    pub fn in_memory() -> Self {
+        Self { state: RwLock::new(State::default()), path: None, watchers: Mutex::new(Vec::new()), next_watch: Mutex::new(0) }
+    }
+
+    // Loads `path` if it exists; later writes go there.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let store = Self { path: Some(path.clone()), ..Self::in_memory() };
+        if path.exists() {
+            let (values, stamp) = read_file(&path)?;
+            let mut st = store.state.write().unwrap_or_else(|e| e.into_inner());
+            st.values = values;
+            st.stamp = stamp;
+        }
+        Ok(store)
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.read().values.get(name).cloned()
+    }
+
+    // "1", "true", "on", "yes" (any case) are enabled; anything else, or an
+    // unset flag, is not.
+    pub fn enabled(&self, name: &str) -> bool {
+        self.read().values.get(name).is_some_and(|v| truthy(v))
+    }
+
+    pub fn all(&self) -> Vec<(String, String)> {
+        self.read().values.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+
+    // Bumped on every change, including ones picked up from the file.
+    pub fn version(&self) -> u64 {
+        self.read().version
+    }
+
+    pub fn set(&self, name: &str, value: &str) -> Result<u64, String> {
+        validate(name, value)?;
+        Ok(self.apply(vec![(name.to_string(), Some(value.to_string()))], true)?.0)
+    }
+
+    // Ok(false) when the flag was not set.
+    pub fn remove(&self, name: &str) -> Result<bool, String> {
+        if self.get(name).is_none() {
+            return Ok(false);
+        }
+        self.apply(vec![(name.to_string(), None)], true)?;
+        Ok(true)
+    }
+
+    pub fn watch(&self, f: impl Fn(&FlagChange) + Send + Sync + 'static) -> u64 {
+        let mut next = self.next_watch.lock().unwrap_or_else(|e| e.into_inner());
+        *next += 1;
+        self.watchers.lock().unwrap_or_else(|e| e.into_inner()).push((*next, Arc::new(f)));
+        *next
+    }
+
+    pub fn unwatch(&self, id: u64) {
+        self.watchers.lock().unwrap_or_else(|e| e.into_inner()).retain(|(w, _)| *w != id);
+    }
+
+    // Re-reads the file when it changed since we last read or wrote it;
+    // returns the number of flags that changed.
+    pub fn reload(&self) -> Result<usize, String> {
+        let Some(path) = &self.path else { return Ok(0) };
+        if file_stamp(path) == self.read().stamp {
+            return Ok(0);
+        }
+        Ok(self.apply(Vec::new(), false)?.1)
+    }
+
+    // Polls the file every `interval` on a background thread until the
+    // returned handle is dropped.
+    pub fn watch_file(self: &Arc<Self>, interval: Duration) -> FileWatch {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (store, flag) = (Arc::clone(self), Arc::clone(&stop));
+        let thread = std::thread::spawn(move || {
+            while !flag.load(Ordering::Acquire) {
+                let _ = store.reload();
+                std::thread::park_timeout(interval);
+            }
+        });
+        FileWatch { stop, thread: Some(thread) }
+    }
+
+    // Applies `changes` on top of the file as it is now (re-read when it
+    // changed since we last saw it), so an external edit is neither lost
+    // nor overwritten; returns (version, number of flags that changed).
+    fn apply(&self, changes: Vec<(String, Option<String>)>, persist: bool) -> Result<(u64, usize), String> {
+        let (version, notices) = {
+            let _lock = match (&self.path, persist) {
+                (Some(path), true) => Some(lock_file(path)?),
+                _ => None,
+            };
+            let mut st = self.state.write().unwrap_or_else(|e| e.into_inner());
+            let mut next = st.values.clone();
+            if let Some(path) = &self.path {
+                let stamp = file_stamp(path);
+                if stamp != st.stamp {
+                    next = if stamp.is_some() { read_file(path)?.0 } else { BTreeMap::new() };
+                    st.stamp = stamp;
+                }
+            }
+            for (k, v) in changes.into_iter() {
+                match v {
+                    Some(v) => next.insert(k, v),
+                    None => next.remove(&k),
+                };
+            }
+            if persist && let Some(path) = &self.path {
+                st.stamp = write_file(path, &next).map_err(|e| format!("flags: write {}: {}", path.display(), e))?;
+            }
+            let notices = diff(&st.values, &next, st.version + 1);
+            if !notices.is_empty() {
+                st.version += 1;
+            }
+            st.values = next;
+            (st.version, notices)
+        };
+        // outside every lock: watchers may read flags, or (un)watch
+        let watchers: Vec<Watcher> = self.watchers.lock().unwrap_or_else(|e| e.into_inner()).iter().map(|(_, w)| Arc::clone(w)).collect();
+        for c in notices.iter() {
+            for w in watchers.iter() {
+                w(c);
+            }
+        }
+        Ok((version, notices.len()))
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, State> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+pub struct FileWatch {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for FileWatch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(t) = self.thread.take() {
+            t.thread().unpark();
+            let _ = t.join();
+        }
+    }
+}
+
+// Makes `store` the one consulted by plugins, expressions and WAF rules.
+pub fn install(store: Arc<FlagStore>) {
+    *INSTALLED.write().unwrap_or_else(|e| e.into_inner()) = Some(store);
+}
+
+// Opens the flag file at `path`, installs it and polls it every `interval`;
+// the store stays installed after the returned handle is dropped.
+pub fn start(path: impl Into<PathBuf>, interval: Duration) -> Result<FileWatch, String> {
+    let store = Arc::new(FlagStore::open(path)?);
+    install(Arc::clone(&store));
+    Ok(store.watch_file(interval))
+}
+
+// `start` on $OLWSX_FLAGS_FILE; Ok(None) when it is unset or empty.
+pub fn start_from_env(interval: Duration) -> Result<Option<FileWatch>, String> {
+    match std::env::var(FLAGS_FILE_ENV) {
+        Ok(path) if !path.is_empty() => start(path, interval).map(Some),
+        _ => Ok(None),
+    }
+}
+
+pub fn installed() -> Option<Arc<FlagStore>> {
+    INSTALLED.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+// Value of a flag in the installed store; None when unset or no store.
+pub fn value(name: &str) -> Option<String> {
+    installed().and_then(|s| s.get(name))
+}
+
+pub fn enabled(name: &str) -> bool {
+    installed().is_some_and(|s| s.enabled(name))
+}
+
+pub fn truthy(v: &str) -> bool {
+    ["1", "true", "on", "yes"].iter().any(|t| v.eq_ignore_ascii_case(t))
+}
+
+pub fn validate(name: &str, value: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME || !name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_' || b == b'.' || b == b'-') {
+        return Err(format!("invalid flag name '{}' (1..{} of [a-z0-9_.-])", name, MAX_NAME));
+    }
+    if value.len() > MAX_VALUE || value.contains(['\n', '\r']) {
+        return Err(format!("invalid value for flag '{}' (one line, at most {} bytes)", name, MAX_VALUE));
+    }
+    Ok(())
+}
+
+// Sets and removals (each in name order), numbered `version`.
+fn diff(old: &BTreeMap<String, String>, new: &BTreeMap<String, String>, version: u64) -> Vec<FlagChange> {
+    let mut out: Vec<FlagChange> = new
+        .iter()
+        .filter(|(k, v)| old.get(*k) != Some(*v))
+        .map(|(k, v)| FlagChange { name: k.clone(), old: old.get(k).cloned(), new: Some(v.clone()), version })
+        .collect();
+    out.extend(old.iter().filter(|(k, _)| !new.contains_key(*k)).map(|(k, v)| FlagChange { name: k.clone(), old: Some(v.clone()), new: None, version }));
+    out
+}
+
+// Exclusive advisory lock on "<path>.lock", shared with admin/api/flags.go;
+// released when the returned file is dropped.
+fn lock_file(path: &Path) -> Result<fs::File, String> {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    let f = fs::OpenOptions::new().create(true).truncate(false).write(true).open(&name).map_err(|e| format!("flags: lock {}: {}", path.display(), e))?;
+    f.lock().map_err(|e| format!("flags: lock {}: {}", path.display(), e))?;
+    Ok(f)
+}
+
+fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let md = fs::metadata(path).ok()?;
+    Some((md.modified().ok()?, md.len()))
+}
+
+type Loaded = (BTreeMap<String, String>, Option<(SystemTime, u64)>);
+
+fn read_file(path: &Path) -> Result<Loaded, String> {
+    let stamp = file_stamp(path);
+    let text = fs::read_to_string(path).map_err(|e| format!("flags: read {}: {}", path.display(), e))?;
+    let mut values = BTreeMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((k, v)) = line.split_once('=') else {
+            return Err(format!("flags: {}:{}: expected name=value", path.display(), i + 1));
+        };
+        let (k, v) = (k.trim(), v.trim());
+        validate(k, v).map_err(|e| format!("flags: {}:{}: {}", path.display(), i + 1, e))?;
+        values.insert(k.to_string(), v.to_string());
+    }
+    Ok((values, stamp))
+}
+
+fn write_file(path: &Path, values: &BTreeMap<String, String>) -> io::Result<Option<(SystemTime, u64)>> {
+    let tmp = path.with_extension("tmp");
+    {
+        let mut f = fs::File::create(&tmp)?;
+        writeln!(f, "# OLWSX feature flags (name=value); edited via the admin API")?;
+        for (k, v) in values.iter() {
+            writeln!(f, "{}={}", k, v)?;
+        }
+        f.sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(file_stamp(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persisted_and_watched() {
+        let path = std::env::temp_dir().join(format!("olwsx-flags-{}.conf", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let store = Arc::new(FlagStore::open(&path).unwrap());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        store.watch(move |c| sink.lock().unwrap().push((c.name.clone(), c.new.clone())));
+
+        store.set("maintenance_mode", "on").unwrap();
+        store.set("maintenance_mode", "on").unwrap(); // unchanged: no notice
+        assert!(store.enabled("maintenance_mode"));
+        assert!(store.set("Bad Name", "x").is_err());
+
+        // survives a restart
+        let reopened = FlagStore::open(&path).unwrap();
+        assert_eq!(reopened.get("maintenance_mode").as_deref(), Some("on"));
+
+        // an external edit (admin API) is picked up by reload
+        fs::write(&path, "beta.checkout = true\n").unwrap();
+        assert_eq!(store.reload().unwrap(), 2);
+        assert!(!store.enabled("maintenance_mode"));
+        assert!(store.enabled("beta.checkout"));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                ("maintenance_mode".to_string(), Some("on".to_string())),
+                ("beta.checkout".to_string(), Some("true".to_string())),
+                ("maintenance_mode".to_string(), None),
+            ]
+        );
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(path.with_extension("conf.lock"));
+    }
+
+    #[test]
+    fn set_keeps_unpolled_external_edits() {
+        let path = std::env::temp_dir().join(format!("olwsx-flags-ext-{}.conf", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let store = FlagStore::open(&path).unwrap();
+        store.set("a", "1").unwrap();
+
+        // the admin API adds a flag; no reload before the next local write
+        fs::write(&path, "a=1\nb=2\n").unwrap();
+        store.set("c", "3").unwrap();
+        assert_eq!(store.get("b").as_deref(), Some("2"));
+        let reopened = FlagStore::open(&path).unwrap();
+        assert_eq!(reopened.all(), vec![("a".into(), "1".into()), ("b".into(), "2".into()), ("c".into(), "3".into())]);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(path.with_extension("conf.lock"));
+    }
+
+    #[test]
+    fn watchers_may_unwatch_themselves() {
+        let store = Arc::new(FlagStore::in_memory());
+        let id = Arc::new(Mutex::new(0));
+        let (s, i) = (Arc::clone(&store), Arc::clone(&id));
+        *id.lock().unwrap() = store.watch(move |_| s.unwatch(*i.lock().unwrap()));
+        store.set("x", "1").unwrap(); // would deadlock on the watchers mutex
+        store.set("x", "2").unwrap();
+    }
+}
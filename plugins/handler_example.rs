@@ -12,25 +12,25 @@
 #![forbid(unsafe_code)]
 
 use std::collections::HashMap;
-use olwsx_plugins_sdk::{Request, HandlerResult, Response, PluginMeta, HandlerPlugin, add_header, set_body};
+use olwsx_plugins_sdk::{Request, HandlerResult, Response, PluginMeta, HandlerPlugin, Json, add_header, set_body, caps};
 
 mod olwsx_plugins_sdk {
-    pub use crate::sdk::{Request, HandlerResult, Response, PluginMeta, HandlerPlugin, add_header, set_body};
+    pub use crate::sdk::{Request, HandlerResult, Response, PluginMeta, HandlerPlugin, Json, add_header, set_body, caps};
 }
 
 pub struct StaticJsonHandler {
     meta: PluginMeta,
     route: &'static str,
-    content: &'static [u8],
+    content: Json,
     status: u16,
 }
 
 impl StaticJsonHandler {
     pub fn new() -> Self {
         Self {
-            meta: PluginMeta { name: "static_json", version: "1.0.0", author: "OverLab", flags: 0x0010_0000 },
+            meta: PluginMeta { name: "static_json", version: "1.0.0", author: "OverLab", flags: 0x0010_0000, caps: caps::READ_BODY },
             route: "/__health",
-            content: br#"{"status":"ok","server":"OLWSX"}"#,
+            content: Json::obj().set("status", "ok").set("server", "OLWSX"),
             status: 200,
         }
     }
@@ -54,7 +54,7 @@ impl HandlerPlugin for StaticJsonHandler {
             let mut resp = Response::new(self.status);
             add_header(&mut resp, "Content-Type", "application/json");
             add_header(&mut resp, "Cache-Control", "no-store");
-            set_body(&mut resp, self.content);
+            set_body(&mut resp, &self.content.to_bytes());
             HandlerResult { resp, meta_flags: 0x0010_0000 }
         } else if req.path.starts_with("/echo") && req.method == "POST" {
             let mut resp = Response::new(200);
@@ -0,0 +1,273 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/header_transform.rs
+// Role: Declarative header transforms for proxy routes (upstream + downstream)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - add / set / set-if-absent / remove / rename, in declaration order.
+// - Value templates with request variables: ${client_ip}, ${request_id},
+//   ${method}, ${path}, ${host}, ${tenant}, ${header.<name>}.
+// - Separate rule lists for the upstream request and the downstream response,
+//   selected per route (same patterns as pipelines); a pipeline names its set
+//   with PipelineBuilder::headers and Pipeline::execute applies it.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use olwsx_plugins_sdk::{header, route_matches, Request, Response};
+use olwsx_security::canonical;
+
+mod olwsx_plugins_sdk {
+    pub use crate::pipeline::route_matches;
+    pub use crate::sdk::{header, Request, Response};
+}
+
+mod olwsx_security {
+    pub use crate::path::canonical;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Lit(String),
+    Var(String),
+}
+
+// Parsed once at config load; unknown variables are rejected there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+const VARS: [&str; 6] = ["client_ip", "request_id", "method", "path", "host", "tenant"];
+
+impl Template {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("${") {
+            if start > 0 {
+                parts.push(Part::Lit(rest[..start].to_string()));
+            }
+            let end = rest[start..].find('}').ok_or_else(|| format!("unterminated variable in '{}'", s))? + start;
+            let var = &rest[start + 2..end];
+            if !VARS.contains(&var) && var.strip_prefix("header.").is_none_or(|h| h.is_empty()) {
+                return Err(format!("unknown variable '${{{}}}'", var));
+            }
+            parts.push(Part::Var(var.to_string()));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Lit(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    // Missing values (absent header, no request id) render as empty strings.
+    pub fn render(&self, vars: &Vars) -> String {
+        let mut out = String::new();
+        for p in self.parts.iter() {
+            match p {
+                Part::Lit(s) => out.push_str(s),
+                Part::Var(v) => out.push_str(&vars.get(v)),
+            }
+        }
+        out
+    }
+}
+
+// Per-request values available to templates.
+#[derive(Clone, Debug)]
+pub struct Vars<'a> {
+    pub req: &'a Request,
+    pub client_ip: &'a str,
+    pub request_id: &'a str,
+}
+
+impl Vars<'_> {
+    fn get(&self, name: &str) -> String {
+        match name {
+            "client_ip" => self.client_ip.to_string(),
+            "request_id" => self.request_id.to_string(),
+            "method" => self.req.method.to_string(),
+            "path" => self.req.path.to_string(),
+            "host" => header(self.req, "Host").unwrap_or("").to_string(),
+            "tenant" => self.req.tenant.to_string(),
+            other => other.strip_prefix("header.").and_then(|h| header(self.req, h)).unwrap_or("").to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    Add(String, Template),         // append another field line
+    Set(String, Template),         // replace all existing values
+    SetIfAbsent(String, Template), // only when the field is missing
+    Remove(String),
+    Rename(String, String),        // keeps values and order
+}
+
+impl Op {
+    fn apply(&self, headers: &mut Vec<(String, String)>, vars: &Vars) {
+        let has = |hs: &Vec<(String, String)>, n: &str| hs.iter().any(|(k, _)| k.eq_ignore_ascii_case(n));
+        match self {
+            Op::Add(n, t) => headers.push((n.clone(), t.render(vars))),
+            Op::Set(n, t) => {
+                let v = t.render(vars);
+                headers.retain(|(k, _)| !k.eq_ignore_ascii_case(n));
+                headers.push((n.clone(), v));
+            }
+            Op::SetIfAbsent(n, t) => {
+                if !has(headers, n) {
+                    headers.push((n.clone(), t.render(vars)));
+                }
+            }
+            Op::Remove(n) => headers.retain(|(k, _)| !k.eq_ignore_ascii_case(n)),
+            Op::Rename(from, to) => {
+                for (k, _) in headers.iter_mut().filter(|(k, _)| k.eq_ignore_ascii_case(from)) {
+                    *k = to.clone();
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeaderTransforms {
+    pub request: Vec<Op>,  // applied to the request sent upstream
+    pub response: Vec<Op>, // applied to the response sent downstream
+}
+
+impl HeaderTransforms {
+    // One rule per line: `<request|response> <op> <Header> [value...]`
+    //   request set X-Request-Id ${request_id}
+    //   request rename X-Token X-Upstream-Token
+    //   response remove Server
+    //   response set-if-absent Cache-Control no-store
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut out = Self::default();
+        for (n, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |m: String| format!("line {}: {}", n + 1, m);
+            // three words, then the value as written (inner spacing kept)
+            let (dir, rest) = split_word(line);
+            let (op, rest) = split_word(rest);
+            let (name, rest) = split_word(rest);
+            if name.is_empty() {
+                return Err(err("expected '<request|response> <op> <header> [value]'".to_string()));
+            }
+            let (name, arg) = (name.to_string(), rest.trim());
+            let tmpl = || Template::parse(arg).map_err(err);
+            let op = match op {
+                "add" => Op::Add(name, tmpl()?),
+                "set" => Op::Set(name, tmpl()?),
+                "set-if-absent" => Op::SetIfAbsent(name, tmpl()?),
+                "remove" if arg.is_empty() => Op::Remove(name),
+                "rename" if !arg.is_empty() && !arg.contains(char::is_whitespace) => Op::Rename(name, arg.to_string()),
+                other => return Err(err(format!("invalid operation '{}'", other))),
+            };
+            match dir {
+                "request" => out.request.push(op),
+                "response" => out.response.push(op),
+                other => return Err(err(format!("unknown direction '{}'", other))),
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn apply_request(&self, req: &mut Request, client_ip: &str, request_id: &str) {
+        // variables always refer to the request as received, not as transformed
+        let original = req.clone();
+        let vars = Vars { req: &original, client_ip, request_id };
+        for op in self.request.iter() {
+            op.apply(&mut req.headers, &vars);
+        }
+    }
+
+    pub fn apply_response(&self, req: &Request, resp: &mut Response, client_ip: &str, request_id: &str) {
+        let vars = Vars { req, client_ip, request_id };
+        for op in self.response.iter() {
+            op.apply(&mut resp.headers, &vars);
+        }
+    }
+}
+
+// First word of `s` (any run of whitespace delimits) and the rest after it.
+fn split_word(s: &str) -> (&str, &str) {
+    let s = s.trim_start();
+    s.split_at(s.find(char::is_whitespace).unwrap_or(s.len()))
+}
+
+// Per-route transforms; the first matching route wins, otherwise none apply.
+#[derive(Clone, Debug, Default)]
+pub struct RouteTransforms {
+    routes: Vec<(String, HeaderTransforms)>,
+}
+
+impl RouteTransforms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn route(mut self, pattern: &str, t: HeaderTransforms) -> Self {
+        self.routes.push((pattern.to_string(), t));
+        self
+    }
+
+    pub fn for_path(&self, path: &str) -> Option<&HeaderTransforms> {
+        let path = canonical(path);
+        self.routes.iter().find(|(p, _)| route_matches(p, &path)).map(|(_, t)| t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transforms_apply_both_directions() {
+        let t = HeaderTransforms::parse(
+            "request set X-Forwarded-For ${client_ip}\n\
+             request set-if-absent X-Request-Id ${request_id}\n\
+             request rename X-Token X-Upstream-Token\n\
+             request add Via 1.1 olwsx (${method} ${header.host})\n\
+             response remove Server\n\
+             response set-if-absent Cache-Control no-store",
+        )
+        .unwrap();
+        let routes = RouteTransforms::new().route("/api/*", t);
+        assert!(routes.for_path("/static/x").is_none());
+        let t = routes.for_path("/api/users?page=2").unwrap();
+
+        let mut req = Request {
+            method: "GET",
+            path: "/api/users",
+            headers: vec![("Host".into(), "api.example.com".into()), ("x-token".into(), "abc".into()), ("X-Forwarded-For".into(), "6.6.6.6".into())],
+            body: vec![],
+            tenant: "default",
+        };
+        t.apply_request(&mut req, "203.0.113.7", "req-1");
+        assert_eq!(header(&req, "X-Forwarded-For"), Some("203.0.113.7"));
+        assert_eq!(req.headers.iter().filter(|(k, _)| k == "X-Forwarded-For").count(), 1);
+        assert_eq!(header(&req, "X-Request-Id"), Some("req-1"));
+        assert_eq!(header(&req, "X-Upstream-Token"), Some("abc"));
+        assert_eq!(header(&req, "Via"), Some("1.1 olwsx (GET api.example.com)"));
+
+        let mut resp = Response::new(200);
+        resp.headers.push(("server".into(), "nginx".into()));
+        resp.headers.push(("Cache-Control".into(), "max-age=60".into()));
+        t.apply_response(&req, &mut resp, "203.0.113.7", "req-1");
+        assert_eq!(resp.headers, vec![("Cache-Control".to_string(), "max-age=60".to_string())]);
+
+        assert!(HeaderTransforms::parse("request set X ${nope}").unwrap_err().contains("unknown variable"));
+        assert!(HeaderTransforms::parse("upstream set X y").is_err());
+
+        // runs of spaces or tabs between words; the value keeps its own
+        let t = HeaderTransforms::parse("response  set\tX-Note   a  b\nrequest   remove  Cookie").unwrap();
+        assert_eq!(t.response, vec![Op::Set("X-Note".into(), Template::parse("a  b").unwrap())]);
+        assert_eq!(t.request, vec![Op::Remove("Cookie".into())]);
+    }
+}
@@ -0,0 +1,165 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/headers.rs
+// Role: Response header policy (casing, dedup, hop-by-hop, forbidden headers)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Normalize response headers after plugins ran: canonical casing,
+//   deduplication of singleton fields, RFC 9110 hop-by-hop stripping.
+// - Drop headers plugins are not allowed to set.
+// - Per-route overrides on top of a base policy (same patterns as pipelines).
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use olwsx_plugins_sdk::{route_matches, Response};
+use olwsx_security::canonical as canonical_path;
+
+mod olwsx_plugins_sdk {
+    pub use crate::pipeline::route_matches;
+    pub use crate::sdk::Response;
+}
+
+mod olwsx_security {
+    pub use crate::path::canonical;
+}
+
+// RFC 9110 7.6.1 connection-specific fields (plus the legacy Proxy-Connection).
+// `Trailer` is end-to-end and must survive so declared trailers reach the client.
+const HOP_BY_HOP: [&str; 6] = [
+    "connection", "keep-alive", "proxy-connection", "te",
+    "transfer-encoding", "upgrade",
+];
+
+// Fields that must appear at most once; the last value written wins.
+const SINGLETONS: [&str; 10] = [
+    "content-type", "content-length", "content-encoding", "location", "etag",
+    "last-modified", "date", "server", "expires", "age",
+];
+
+// Casing that does not follow the dash-capitalize rule.
+const CASING_EXCEPTIONS: [&str; 4] = ["ETag", "TE", "WWW-Authenticate", "DNT"];
+
+#[derive(Clone, Debug)]
+pub struct HeaderPolicy {
+    pub canonical_casing: bool,
+    pub dedupe: bool,
+    pub strip_hop_by_hop: bool,
+    pub forbidden: Vec<String>, // lowercase names plugins may not emit
+}
+
+impl Default for HeaderPolicy {
+    fn default() -> Self {
+        Self { canonical_casing: true, dedupe: true, strip_hop_by_hop: true, forbidden: Vec::new() }
+    }
+}
+
+impl HeaderPolicy {
+    pub fn apply(&self, resp: &mut Response) {
+        let mut drop_named: Vec<String> = Vec::new();
+        if self.strip_hop_by_hop {
+            // Connection may name additional hop-by-hop fields.
+            for (k, v) in resp.headers.iter() {
+                if k.eq_ignore_ascii_case("connection") {
+                    drop_named.extend(v.split(',').map(|t| t.trim().to_ascii_lowercase()).filter(|t| !t.is_empty()));
+                }
+            }
+        }
+
+        let mut out: Vec<(String, String)> = Vec::with_capacity(resp.headers.len());
+        for (k, v) in resp.headers.drain(..) {
+            let lower = k.to_ascii_lowercase();
+            if self.strip_hop_by_hop && (HOP_BY_HOP.contains(&lower.as_str()) || drop_named.contains(&lower)) {
+                continue;
+            }
+            if self.forbidden.iter().any(|f| f.eq_ignore_ascii_case(&lower)) {
+                continue;
+            }
+            if self.dedupe && SINGLETONS.contains(&lower.as_str()) {
+                out.retain(|(ok, _)| !ok.eq_ignore_ascii_case(&lower));
+            }
+            let name = if self.canonical_casing { canonical(&lower) } else { k };
+            out.push((name, v));
+        }
+        resp.headers = out;
+    }
+}
+
+// Base policy plus per-route overrides; the first matching override wins.
+#[derive(Clone, Debug, Default)]
+pub struct HeaderPolicies {
+    pub base: HeaderPolicy,
+    pub overrides: Vec<(String, HeaderPolicy)>,
+}
+
+impl HeaderPolicies {
+    pub fn for_path(&self, path: &str) -> &HeaderPolicy {
+        let path = canonical_path(path);
+        self.overrides
+            .iter()
+            .find(|(pattern, _)| route_matches(pattern, &path))
+            .map(|(_, p)| p)
+            .unwrap_or(&self.base)
+    }
+
+    pub fn apply(&self, path: &str, resp: &mut Response) {
+        self.for_path(path).apply(resp);
+    }
+}
+
+pub fn canonical(name: &str) -> String {
+    if let Some(e) = CASING_EXCEPTIONS.iter().find(|e| e.eq_ignore_ascii_case(name)) {
+        return e.to_string();
+    }
+    let mut out = String::with_capacity(name.len());
+    let mut upper = true;
+    for c in name.chars() {
+        if upper {
+            out.push(c.to_ascii_uppercase());
+        } else {
+            out.push(c.to_ascii_lowercase());
+        }
+        upper = c == '-';
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdk::add_header;
+
+    #[test]
+    fn normalize_response_headers() {
+        let mut r = Response::new(200);
+        add_header(&mut r, "content-type", "text/plain");
+        add_header(&mut r, "Connection", "close, x-internal");
+        add_header(&mut r, "x-internal", "secret");
+        add_header(&mut r, "Transfer-Encoding", "chunked");
+        add_header(&mut r, "CONTENT-TYPE", "application/json");
+        add_header(&mut r, "set-cookie", "a=1");
+        add_header(&mut r, "set-cookie", "b=2");
+        add_header(&mut r, "etag", "\"v1\"");
+        add_header(&mut r, "server", "plugin");
+
+        let policies = HeaderPolicies {
+            base: HeaderPolicy { forbidden: vec!["server".to_string()], ..HeaderPolicy::default() },
+            overrides: vec![("/raw/*".to_string(), HeaderPolicy { canonical_casing: false, ..HeaderPolicy::default() })],
+        };
+        let mut raw = r.clone();
+        policies.apply("/page", &mut r);
+        assert_eq!(
+            r.headers,
+            vec![
+                ("Content-Type".to_string(), "application/json".to_string()),
+                ("Set-Cookie".to_string(), "a=1".to_string()),
+                ("Set-Cookie".to_string(), "b=2".to_string()),
+                ("ETag".to_string(), "\"v1\"".to_string()),
+            ]
+        );
+
+        policies.apply("/raw/file", &mut raw);
+        assert!(raw.headers.iter().any(|(k, _)| k == "server"));
+    }
+}
@@ -0,0 +1,669 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/httpcache.rs
+// Role: HTTP response cache between the pipeline filters and the handler
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Keys: "<tenant>:" + cache/key.rs CacheKeyBuilder over the request target
+//   and the headers named by the resource's Vary, + the plugin components
+//   collected by cache_key.rs (CacheKeyParts::append_to).
+// - Storage in a cache::TieredCache; responses are stored encoded (status,
+//   headers, body) in Entry::value.
+// - Cache-Control: request no-store bypasses the cache, no-cache skips the
+//   lookup; responses are stored for s-maxage, else max-age, else the
+//   configured default TTL, unless no-store / no-cache / private, Set-Cookie,
+//   Vary: *, or an Authorization request without public / s-maxage.
+//   stale-while-revalidate: one request refreshes the entry through the
+//   handler, concurrent ones get the stale copy.
+// - 404s are kept for HttpCacheConfig::negative_ttl (capped by their own
+//   max-age) as Entry::negative markers carrying the response.
+// - stale-if-error (the response's, else HttpCacheConfig::stale_if_error):
+//   when the handler answers 5xx (a proxy's 502/504 for an unreachable or
+//   timed-out upstream) and an expired copy is still inside that window,
+//   the copy is served instead, with `Warning: 111`; `store` reports it so
+//   the host can count the shield activation. The entry's grace covers the
+//   longer of the two windows.
+// - Hits carry `Age` (seconds since stored) and `X-Cache: HIT`, stale
+//   answers `X-Cache: STALE`, stored or passed responses `X-Cache: MISS`.
+// - Purge: the PURGE method (after the route's ACL) and HttpCache::purge for
+//   the admin side; a path drops every variant and query string of that
+//   path, a trailing `*` every path under the prefix.
+// - Optional coalescing (HttpCacheConfig::coalesce): GETs that miss on a key
+//   another GET is already fetching wait for that one's response instead of
+//   running the handler, cacheable or not (`X-Cache: COALESCED`). Requests
+//   with Authorization, and responses with Set-Cookie / private / no-store,
+//   are never shared; waiters then run the handler themselves.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use olwsx_cache::{Cache, CacheKeyBuilder, Entry, LookupOutcome, TieredCache, VaryPolicy};
+use olwsx_plugins_sdk::{add_header, header, json_status, CacheKeyParts, Json, Request, Response};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+mod olwsx_cache {
+    pub use cache::key::{CacheKeyBuilder, VaryPolicy};
+    pub use cache::{Cache, Entry, LookupOutcome, TieredCache};
+}
+
+mod olwsx_plugins_sdk {
+    pub use crate::cache_key::CacheKeyParts;
+    pub use crate::sdk::{add_header, header, json_status, Json, Request, Response};
+}
+
+pub const PURGE_METHOD: &str = "PURGE";
+
+// Statuses stored when the response allows it (RFC 9110 15.1 heuristically
+// cacheable ones, minus the 4xx/5xx a retry may fix; 404 per negative_ttl).
+const CACHEABLE: [u16; 7] = [200, 203, 204, 300, 301, 308, 410];
+
+// Never stored with the response: per-hop or added on the way out.
+const UNSTORED: [&str; 6] = ["age", "x-cache", "connection", "keep-alive", "transfer-encoding", "upgrade"];
+
+#[derive(Clone, Debug)]
+pub struct HttpCacheConfig {
+    pub default_ttl: Option<Duration>, // for responses without max-age / s-maxage; None stores only those
+    pub max_body: usize,
+    pub vary: VaryPolicy, // until a response for the path names its own Vary
+    pub coalesce: Option<Duration>, // how long a coalesced GET waits for the leader; None: off
+    pub negative_ttl: Option<Duration>, // how long a 404 is kept; None: not at all
+    pub stale_if_error: Option<Duration>, // for responses without the directive
+}
+
+impl Default for HttpCacheConfig {
+    fn default() -> Self {
+        Self {
+            default_ttl: None,
+            max_body: 1024 * 1024,
+            vary: VaryPolicy::default(),
+            coalesce: None,
+            negative_ttl: None,
+            stale_if_error: None,
+        }
+    }
+}
+
+// Cache-Control directives the cache acts on; several headers combine.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Directives {
+    pub no_store: bool,
+    pub no_cache: bool,
+    pub private: bool,
+    pub public: bool,
+    pub max_age: Option<u64>,
+    pub s_maxage: Option<u64>,
+    pub stale_while_revalidate: Option<u64>,
+    pub stale_if_error: Option<u64>,
+}
+
+impl Directives {
+    pub fn parse<'a>(values: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut d = Directives::default();
+        for item in values.into_iter().flat_map(|v| v.split(',')) {
+            let (name, arg) = match item.split_once('=') {
+                Some((n, a)) => (n.trim(), Some(a.trim().trim_matches('"'))),
+                None => (item.trim(), None),
+            };
+            let secs = arg.and_then(|a| a.parse::<u64>().ok());
+            match name.to_ascii_lowercase().as_str() {
+                "no-store" => d.no_store = true,
+                "no-cache" => d.no_cache = true,
+                "private" => d.private = true,
+                "public" => d.public = true,
+                "max-age" => d.max_age = secs.or(Some(0)),
+                "s-maxage" => d.s_maxage = secs.or(Some(0)),
+                "stale-while-revalidate" => d.stale_while_revalidate = secs,
+                "stale-if-error" => d.stale_if_error = secs,
+                _ => {}
+            }
+        }
+        d
+    }
+}
+
+pub enum Lookup {
+    Hit(Response), // answer with this; the handler does not run
+    Miss(Pending), // run the handler, then hand its response to `store`
+    Bypass,        // not cacheable: run the handler, store nothing
+}
+
+// A miss in progress. `refresh`: this request holds the refresh marker of a
+// stale entry and must store or give it back. `stale`: the expired copy, for
+// stale-if-error. `lead`: other GETs for the key wait on this one (released,
+// unshared, if it is dropped without `store`).
+pub struct Pending {
+    key: Vec<u8>,
+    vary: VaryPolicy,
+    refresh: bool,
+    stale: Option<Box<Entry>>,
+    lead: Option<Lead>,
+}
+
+type Flights = Arc<Mutex<HashMap<Vec<u8>, Arc<Flight>>>>;
+
+#[derive(Default)]
+struct Flight {
+    resp: Mutex<Option<Option<Response>>>, // Some(None): nothing to share
+    done: Condvar,
+}
+
+// Publishes the leader's response (None unless set) and retires the key.
+pub struct Lead {
+    flights: Flights,
+    key: Vec<u8>,
+    flight: Arc<Flight>,
+    resp: Option<Response>,
+}
+
+impl Drop for Lead {
+    fn drop(&mut self) {
+        {
+            let mut flights = self.flights.lock().unwrap_or_else(|e| e.into_inner());
+            if flights.get(&self.key).is_some_and(|f| Arc::ptr_eq(f, &self.flight)) {
+                flights.remove(&self.key);
+            }
+        }
+        *self.flight.resp.lock().unwrap_or_else(|e| e.into_inner()) = Some(self.resp.take());
+        self.flight.done.notify_all();
+    }
+}
+
+enum Joined {
+    Lead(Lead),
+    Shared(Response),
+    Alone,
+}
+
+// Keys stored for one "<tenant>:<path>", and the Vary its responses named.
+struct Resource {
+    vary: VaryPolicy,
+    keys: HashSet<Vec<u8>>,
+}
+
+pub struct HttpCache {
+    store: TieredCache,
+    cfg: HttpCacheConfig,
+    resources: Mutex<HashMap<String, Resource>>,
+    flights: Flights,
+}
+
+impl HttpCache {
+    pub fn new(store: TieredCache, cfg: HttpCacheConfig) -> Self {
+        Self { store, cfg, resources: Mutex::new(HashMap::new()), flights: Flights::default() }
+    }
+
+    pub fn lookup(&self, req: &Request, parts: &CacheKeyParts) -> Lookup {
+        if !matches!(req.method, "GET" | "HEAD") {
+            return Lookup::Bypass;
+        }
+        let cc = Directives::parse(headers(&req.headers, "cache-control"));
+        if cc.no_store {
+            return Lookup::Bypass;
+        }
+        let id = resource_id(req);
+        let vary = self.lock().get(&id).map_or_else(|| self.cfg.vary.clone(), |r| r.vary.clone());
+        let key = self.key(req, parts, &vary);
+        if cc.no_cache || header(req, "pragma").is_some_and(|p| p.eq_ignore_ascii_case("no-cache")) {
+            return Lookup::Miss(Pending { key, vary, refresh: false, stale: None, lead: None });
+        }
+        let (e, x_cache) = match self.store.lookup_outcome(&key) {
+            LookupOutcome::Fresh(e) | LookupOutcome::NegativeHit(e) => (e, "HIT"),
+            LookupOutcome::Revalidating(e) if servable(&e, |d| d.stale_while_revalidate, None) => (e, "STALE"),
+            // kept only for stale-if-error: ask the handler
+            LookupOutcome::Revalidating(e) => return Lookup::Miss(Pending { key, vary, refresh: false, stale: Some(Box::new(e)), lead: None }),
+            LookupOutcome::Stale(e) => return Lookup::Miss(Pending { key, vary, refresh: true, stale: Some(Box::new(e)), lead: None }),
+            LookupOutcome::Miss => {
+                self.forget(&id, &key);
+                let lead = match self.join(req, &key) {
+                    Joined::Lead(lead) => Some(lead),
+                    Joined::Shared(mut resp) => {
+                        add_header(&mut resp, "X-Cache", "COALESCED");
+                        return Lookup::Hit(resp);
+                    }
+                    Joined::Alone => None,
+                };
+                return Lookup::Miss(Pending { key, vary, refresh: false, stale: None, lead });
+            }
+        };
+        match decode(&e.value) {
+            Some(mut resp) => {
+                add_header(&mut resp, "Age", &e.ts.elapsed().as_secs().to_string());
+                add_header(&mut resp, "X-Cache", x_cache);
+                if req.method == "HEAD" {
+                    resp.body.clear();
+                }
+                Lookup::Hit(resp)
+            }
+            None => {
+                let _ = self.store.invalidate(&key);
+                self.forget(&id, &key);
+                Lookup::Miss(Pending { key, vary, refresh: false, stale: None, lead: None })
+            }
+        }
+    }
+
+    // Stores the handler's response for a miss when it is eligible, hands it
+    // to any coalesced waiters, and marks it `X-Cache: MISS`. A 5xx with a
+    // stale copy inside its stale-if-error window is replaced by that copy
+    // instead; returns true when that happened.
+    pub fn store(&self, req: &Request, parts: &CacheKeyParts, mut pending: Pending, resp: &mut Response) -> bool {
+        if let Some(stale) = pending.stale.take().filter(|e| resp.status >= 500 && servable(e, |d| d.stale_if_error, self.cfg.stale_if_error))
+            && let Some(mut copy) = decode(&stale.value)
+        {
+            if pending.refresh {
+                self.store.release_refresh(&pending.key);
+            }
+            if let Some(mut lead) = pending.lead.take() {
+                lead.resp = Some(copy.clone());
+            }
+            add_header(&mut copy, "Age", &stale.ts.elapsed().as_secs().to_string());
+            add_header(&mut copy, "X-Cache", "STALE");
+            add_header(&mut copy, "Warning", "111 - \"Revalidation Failed\"");
+            if req.method == "HEAD" {
+                copy.body.clear();
+            }
+            *resp = copy;
+            return true;
+        }
+        let stored = match self.entry(req, resp) {
+            Some((entry, vary)) => {
+                let key = if vary == pending.vary { pending.key.clone() } else { self.key(req, parts, &vary) };
+                let ok = self.store.insert(&key, entry).is_ok();
+                if ok {
+                    let mut resources = self.lock();
+                    let r = resources.entry(resource_id(req)).or_insert_with(|| Resource { vary: vary.clone(), keys: HashSet::new() });
+                    r.vary = vary;
+                    r.keys.insert(key.clone());
+                }
+                ok && key == pending.key
+            }
+            None => false,
+        };
+        if pending.refresh && !stored {
+            self.store.release_refresh(&pending.key);
+        }
+        if let Some(mut lead) = pending.lead.take() {
+            lead.resp = shareable(resp).then(|| resp.clone());
+        }
+        add_header(resp, "X-Cache", "MISS");
+        false
+    }
+
+    // Drops every stored variant of `path` (query ignored) for `tenant`, or
+    // of every path under it when `path` ends in `*`. Returns how many entries went.
+    pub fn purge(&self, tenant: &str, path: &str) -> usize {
+        let prefix = format!("{}:{}", tenant, strip_query(path));
+        let dropped: Vec<Resource> = {
+            let mut resources = self.lock();
+            let ids: Vec<String> = match prefix.strip_suffix('*') {
+                Some(p) => resources.keys().filter(|id| id.starts_with(p)).cloned().collect(),
+                None => resources.contains_key(&prefix).then(|| prefix.clone()).into_iter().collect(),
+            };
+            ids.iter().filter_map(|id| resources.remove(id)).collect()
+        };
+        dropped.iter().flat_map(|r| r.keys.iter()).filter(|k| self.store.invalidate(k).is_ok()).count()
+    }
+
+    // A PURGE request for its own path: {"purged":n}, 404 when nothing was cached.
+    pub fn purge_request(&self, req: &Request) -> Response {
+        let n = self.purge(req.tenant, req.path);
+        json_status(if n == 0 { 404 } else { 200 }, &Json::obj().set("purged", n))
+    }
+
+    // Stored keys (lossy UTF-8, sorted), for tests and the admin listing.
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> =
+            self.lock().values().flat_map(|r| r.keys.iter()).map(|k| String::from_utf8_lossy(k).into_owned()).collect();
+        keys.sort();
+        keys
+    }
+
+    fn key(&self, req: &Request, parts: &CacheKeyParts, vary: &VaryPolicy) -> Vec<u8> {
+        let hdrs: Vec<(&str, &str)> = req.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        let mut key = format!("{}:", req.tenant).into_bytes();
+        key.extend_from_slice(&CacheKeyBuilder::new(vary.clone()).build(req.path, &hdrs));
+        parts.append_to(&mut key);
+        key
+    }
+
+    // The entry to store for `resp` and the Vary policy to key it by, or None
+    // when the response may not be stored.
+    fn entry(&self, req: &Request, resp: &Response) -> Option<(Entry, VaryPolicy)> {
+        let negative = resp.status == 404 && self.cfg.negative_ttl.is_some();
+        if req.method != "GET" || !(negative || CACHEABLE.contains(&resp.status)) || resp.body.len() > self.cfg.max_body {
+            return None;
+        }
+        let cc = Directives::parse(headers(&resp.headers, "cache-control"));
+        if cc.no_store || cc.no_cache || cc.private || Directives::parse(headers(&req.headers, "cache-control")).no_store {
+            return None;
+        }
+        if headers(&resp.headers, "set-cookie").next().is_some() {
+            return None;
+        }
+        if header(req, "authorization").is_some() && !cc.public && cc.s_maxage.is_none() {
+            return None;
+        }
+        let explicit = cc.s_maxage.or(cc.max_age).map(Duration::from_secs);
+        let ttl = match self.cfg.negative_ttl.filter(|_| negative) {
+            Some(neg) => explicit.map_or(neg, |t| t.min(neg)),
+            None => explicit.or(self.cfg.default_ttl)?,
+        };
+        if ttl.is_zero() {
+            return None;
+        }
+        let vary: Vec<&str> = headers(&resp.headers, "vary").collect();
+        let vary = if vary.is_empty() { self.cfg.vary.clone() } else { VaryPolicy::from_vary_header(&vary.join(","))? };
+        if negative {
+            return Some((Entry { value: encode(resp).into(), ..Entry::negative(ttl) }, vary));
+        }
+        let swr = cc.stale_while_revalidate.map(Duration::from_secs).unwrap_or_default();
+        let sie = cc.stale_if_error.map(Duration::from_secs).or(self.cfg.stale_if_error).unwrap_or_default();
+        let mut entry = Entry::new(encode(resp), 0, ttl).with_grace(swr.max(sie));
+        if let Some(tag) = headers(&resp.headers, "etag").next() {
+            entry = entry.with_etag(tag);
+        }
+        Some((entry, vary))
+    }
+
+    // With coalescing on, the first GET to miss on `key` leads; later ones
+    // wait (bounded) for its response, or run alone when there is none to share.
+    fn join(&self, req: &Request, key: &[u8]) -> Joined {
+        let Some(wait) = self.cfg.coalesce else { return Joined::Alone };
+        if req.method != "GET" || header(req, "authorization").is_some() {
+            return Joined::Alone;
+        }
+        let flight = {
+            let mut flights = self.flights.lock().unwrap_or_else(|e| e.into_inner());
+            match flights.get(key) {
+                Some(f) => Arc::clone(f),
+                None => {
+                    let flight = Arc::new(Flight::default());
+                    flights.insert(key.to_vec(), Arc::clone(&flight));
+                    return Joined::Lead(Lead { flights: Arc::clone(&self.flights), key: key.to_vec(), flight, resp: None });
+                }
+            }
+        };
+        let slot = flight.resp.lock().unwrap_or_else(|e| e.into_inner());
+        let (slot, _) = flight.done.wait_timeout_while(slot, wait, |r| r.is_none()).unwrap_or_else(|e| e.into_inner());
+        match slot.as_ref() {
+            Some(Some(resp)) => Joined::Shared(resp.clone()),
+            _ => Joined::Alone,
+        }
+    }
+
+    // The entry behind `key` is gone: stop listing it for purge.
+    fn forget(&self, id: &str, key: &[u8]) {
+        let mut resources = self.lock();
+        if let Some(r) = resources.get_mut(id) {
+            r.keys.remove(key);
+            if r.keys.is_empty() {
+                resources.remove(id);
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Resource>> {
+        self.resources.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn headers<'a>(list: &'a [(String, String)], name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+    list.iter().filter(move |(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+}
+
+// Is expired entry `e` still inside the window its stored Cache-Control
+// gives it (`directive`, else `fallback`)?
+fn servable(e: &Entry, directive: fn(&Directives) -> Option<u64>, fallback: Option<Duration>) -> bool {
+    let Some(resp) = decode(&e.value) else { return false };
+    let cc = Directives::parse(headers(&resp.headers, "cache-control"));
+    let window = directive(&cc).map(Duration::from_secs).or(fallback).unwrap_or_default();
+    Instant::now().saturating_duration_since(e.ts) <= e.ttl.saturating_add(window)
+}
+
+// May another client's request be answered with `resp`?
+fn shareable(resp: &Response) -> bool {
+    let cc = Directives::parse(headers(&resp.headers, "cache-control"));
+    !cc.private && !cc.no_store && headers(&resp.headers, "set-cookie").next().is_none()
+}
+
+fn strip_query(path: &str) -> &str {
+    path.split_once('?').map_or(path, |(p, _)| p)
+}
+
+fn resource_id(req: &Request) -> String {
+    format!("{}:{}", req.tenant, strip_query(req.path))
+}
+
+// status u16 | header count u32 | (name len u32, name, value len u32, value)* | body
+fn encode(resp: &Response) -> Vec<u8> {
+    let kept: Vec<&(String, String)> = resp.headers.iter().filter(|(k, _)| !UNSTORED.iter().any(|u| k.eq_ignore_ascii_case(u))).collect();
+    let mut out = Vec::with_capacity(resp.body.len() + 64);
+    out.extend_from_slice(&resp.status.to_be_bytes());
+    out.extend_from_slice(&(kept.len() as u32).to_be_bytes());
+    for (k, v) in kept {
+        for s in [k, v] {
+            out.extend_from_slice(&(s.len() as u32).to_be_bytes());
+            out.extend_from_slice(s.as_bytes());
+        }
+    }
+    out.extend_from_slice(&resp.body);
+    out
+}
+
+fn decode(mut buf: &[u8]) -> Option<Response> {
+    fn take<'a>(buf: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+        let (head, rest) = buf.split_at_checked(n)?;
+        *buf = rest;
+        Some(head)
+    }
+    fn take_u32(buf: &mut &[u8]) -> Option<usize> {
+        Some(u32::from_be_bytes(take(buf, 4)?.try_into().ok()?) as usize)
+    }
+    fn take_str(buf: &mut &[u8]) -> Option<String> {
+        let n = take_u32(buf)?;
+        String::from_utf8(take(buf, n)?.to_vec()).ok()
+    }
+    let status = u16::from_be_bytes(take(&mut buf, 2)?.try_into().ok()?);
+    let count = take_u32(&mut buf)?;
+    let mut resp = Response::new(status);
+    for _ in 0..count {
+        let k = take_str(&mut buf)?;
+        let v = take_str(&mut buf)?;
+        resp.headers.push((k, v));
+    }
+    resp.body = buf.to_vec();
+    Some(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cache::l1::L1;
+    use std::sync::Arc;
+
+    fn cache(default_ttl: Option<Duration>) -> HttpCache {
+        let store = TieredCache::new(vec![Arc::new(L1::new())]);
+        HttpCache::new(store, HttpCacheConfig { default_ttl, vary: VaryPolicy::none(), ..HttpCacheConfig::default() })
+    }
+
+    fn get(path: &'static str, headers: &[(&str, &str)]) -> Request {
+        let headers = headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Request { method: "GET", path, headers, body: vec![], tenant: "t1" }
+    }
+
+    fn ok(body: &[u8], headers: &[(&str, &str)]) -> Response {
+        let mut resp = Response::new(200);
+        resp.body = body.to_vec();
+        for (k, v) in headers {
+            add_header(&mut resp, k, v);
+        }
+        resp
+    }
+
+    fn header_of<'a>(resp: &'a Response, name: &'a str) -> Option<&'a str> {
+        headers(&resp.headers, name).next()
+    }
+
+    // lookup, then store `resp` on a miss; returns what the client gets
+    fn serve(c: &HttpCache, req: &Request, resp: Response) -> Response {
+        let parts = CacheKeyParts::default();
+        match c.lookup(req, &parts) {
+            Lookup::Hit(r) => r,
+            Lookup::Miss(pending) => {
+                let mut resp = resp;
+                c.store(req, &parts, pending, &mut resp);
+                resp
+            }
+            Lookup::Bypass => resp,
+        }
+    }
+
+    #[test]
+    fn directives_combine_headers() {
+        let d = Directives::parse(["public, max-age=60", "S-MAXAGE=\"120\", stale-while-revalidate=5, no-cache"]);
+        assert_eq!(d, Directives { public: true, no_cache: true, max_age: Some(60), s_maxage: Some(120), stale_while_revalidate: Some(5), ..Directives::default() });
+    }
+
+    #[test]
+    fn hits_carry_age_and_x_cache() {
+        let c = cache(None);
+        let req = get("/a?utm_source=x", &[]);
+        let first = serve(&c, &req, ok(b"one", &[("Cache-Control", "max-age=60"), ("Age", "7")]));
+        assert_eq!(header_of(&first, "x-cache"), Some("MISS"));
+        let hit = serve(&c, &get("/a", &[]), ok(b"two", &[]));
+        assert_eq!((hit.body.as_slice(), header_of(&hit, "x-cache"), header_of(&hit, "age")), (b"one".as_slice(), Some("HIT"), Some("0")));
+        assert_eq!(c.keys(), vec!["t1:/a"]);
+
+        let head = Request { method: "HEAD", ..get("/a", &[]) };
+        let Lookup::Hit(r) = c.lookup(&head, &CacheKeyParts::default()) else { panic!("HEAD should hit") };
+        assert!(r.body.is_empty());
+        assert!(matches!(c.lookup(&get("/a", &[("Cache-Control", "no-store")]), &CacheKeyParts::default()), Lookup::Bypass));
+    }
+
+    #[test]
+    fn ineligible_responses_are_not_stored() {
+        let c = cache(Some(Duration::from_secs(60)));
+        for (path, resp) in [
+            ("/private", ok(b"x", &[("Cache-Control", "private")])),
+            ("/cookie", ok(b"x", &[("Set-Cookie", "s=1")])),
+            ("/star", ok(b"x", &[("Vary", "*")])),
+            ("/zero", ok(b"x", &[("Cache-Control", "max-age=0")])),
+            ("/error", Response::new(503)),
+        ] {
+            serve(&c, &get(path, &[]), resp);
+        }
+        serve(&c, &get("/auth", &[("Authorization", "Bearer t")]), ok(b"x", &[]));
+        assert!(c.keys().is_empty());
+        serve(&c, &get("/auth", &[("Authorization", "Bearer t")]), ok(b"x", &[("Cache-Control", "public")]));
+        assert_eq!(c.keys(), vec!["t1:/auth"]);
+        assert!(cache(None).entry(&get("/", &[]), &ok(b"x", &[])).is_none());
+    }
+
+    #[test]
+    fn vary_splits_variants_and_purge_drops_them() {
+        let c = cache(Some(Duration::from_secs(60)));
+        let fr = get("/page?x=1", &[("Accept-Language", "fr")]);
+        serve(&c, &fr, ok(b"bonjour", &[("Vary", "Accept-Language")]));
+        serve(&c, &fr, ok(b"bonjour", &[("Vary", "Accept-Language")]));
+        let en = serve(&c, &get("/page?x=1", &[("Accept-Language", "en")]), ok(b"hello", &[("Vary", "Accept-Language")]));
+        assert_eq!((en.body.as_slice(), header_of(&en, "x-cache")), (b"hello".as_slice(), Some("MISS")));
+        serve(&c, &get("/page/sub", &[]), ok(b"sub", &[]));
+        assert_eq!(c.keys().len(), 3);
+
+        assert_eq!(c.purge("t1", "/page"), 2);
+        assert_eq!(c.keys(), vec!["t1:/page/sub"]);
+        let purge = Request { method: PURGE_METHOD, ..get("/page*", &[]) };
+        assert_eq!(c.purge_request(&purge).status, 200);
+        assert_eq!(c.purge_request(&purge).status, 404);
+    }
+
+    #[test]
+    fn stale_while_revalidate_refreshes_once() {
+        let c = cache(None);
+        let req = get("/s", &[]);
+        serve(&c, &req, ok(b"v1", &[("Cache-Control", "max-age=1, stale-while-revalidate=60")]));
+        std::thread::sleep(Duration::from_millis(1100));
+
+        let Lookup::Miss(pending) = c.lookup(&req, &CacheKeyParts::default()) else { panic!("the first stale lookup refreshes") };
+        let stale = serve(&c, &req, ok(b"unused", &[]));
+        assert_eq!((stale.body.as_slice(), header_of(&stale, "x-cache")), (b"v1".as_slice(), Some("STALE")));
+        c.store(&req, &CacheKeyParts::default(), pending, &mut ok(b"v2", &[("Cache-Control", "max-age=60")]));
+        assert_eq!(serve(&c, &req, ok(b"unused", &[])).body, b"v2");
+    }
+
+    #[test]
+    fn not_found_is_kept_for_the_negative_ttl() {
+        let c = HttpCache::new(
+            TieredCache::new(vec![Arc::new(L1::new())]),
+            HttpCacheConfig { negative_ttl: Some(Duration::from_millis(100)), vary: VaryPolicy::none(), ..HttpCacheConfig::default() },
+        );
+        let req = get("/gone", &[]);
+        let mut nf = Response::new(404);
+        nf.body = b"no such page".to_vec();
+        serve(&c, &req, nf);
+        let hit = serve(&c, &req, ok(b"back", &[]));
+        assert_eq!((hit.status, hit.body.as_slice(), header_of(&hit, "x-cache")), (404, b"no such page".as_slice(), Some("HIT")));
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(serve(&c, &req, ok(b"back", &[])).status, 200);
+        assert!(cache(None).entry(&req, &Response::new(404)).is_none());
+    }
+
+    #[test]
+    fn upstream_errors_serve_stale_if_error() {
+        let c = HttpCache::new(
+            TieredCache::new(vec![Arc::new(L1::new())]),
+            HttpCacheConfig { stale_if_error: Some(Duration::from_secs(60)), vary: VaryPolicy::none(), ..HttpCacheConfig::default() },
+        );
+        let (a, b) = (get("/a", &[]), get("/b", &[]));
+        serve(&c, &a, ok(b"a1", &[("Cache-Control", "max-age=1")]));
+        serve(&c, &b, ok(b"b1", &[("Cache-Control", "max-age=1, stale-if-error=0")]));
+        std::thread::sleep(Duration::from_millis(1100));
+
+        let Lookup::Miss(pending) = c.lookup(&a, &CacheKeyParts::default()) else { panic!("expired") };
+        let mut resp = Response::new(504);
+        assert!(c.store(&a, &CacheKeyParts::default(), pending, &mut resp));
+        assert_eq!((resp.status, resp.body.as_slice(), header_of(&resp, "x-cache")), (200, b"a1".as_slice(), Some("STALE")));
+        assert!(header_of(&resp, "warning").is_some_and(|w| w.starts_with("111")));
+        assert_eq!(serve(&c, &a, ok(b"a2", &[("Cache-Control", "max-age=60")])).body, b"a2");
+
+        // the response's own stale-if-error=0 wins over the config
+        let Lookup::Miss(pending) = c.lookup(&b, &CacheKeyParts::default()) else { panic!("expired") };
+        let mut resp = Response::new(502);
+        assert!(!c.store(&b, &CacheKeyParts::default(), pending, &mut resp));
+        assert_eq!(resp.status, 502);
+    }
+
+    #[test]
+    fn concurrent_misses_share_one_response() {
+        let c = HttpCache::new(
+            TieredCache::new(vec![Arc::new(L1::new())]),
+            HttpCacheConfig { coalesce: Some(Duration::from_secs(5)), vary: VaryPolicy::none(), ..HttpCacheConfig::default() },
+        );
+        let req = get("/slow", &[]);
+        let Lookup::Miss(pending) = c.lookup(&req, &CacheKeyParts::default()) else { panic!("first GET leads") };
+        std::thread::scope(|s| {
+            let waiters: Vec<_> = (0..3).map(|_| s.spawn(|| c.lookup(&get("/slow", &[]), &CacheKeyParts::default()))).collect();
+            std::thread::sleep(Duration::from_millis(50));
+            let mut resp = ok(b"once", &[]); // not cacheable, still shared
+            c.store(&req, &CacheKeyParts::default(), pending, &mut resp);
+            for w in waiters {
+                let Lookup::Hit(r) = w.join().unwrap() else { panic!("waiter should get the leader's response") };
+                assert_eq!((r.body.as_slice(), header_of(&r, "x-cache")), (b"once".as_slice(), Some("COALESCED")));
+            }
+        });
+        assert!(c.keys().is_empty());
+
+        // a leader that gives up (or answers with Set-Cookie) releases its waiters unshared
+        let Lookup::Miss(pending) = c.lookup(&req, &CacheKeyParts::default()) else { panic!("the key is free again") };
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| c.lookup(&get("/slow", &[]), &CacheKeyParts::default()));
+            std::thread::sleep(Duration::from_millis(50));
+            drop(pending);
+            assert!(matches!(waiter.join().unwrap(), Lookup::Miss(Pending { lead: None, .. })));
+        });
+        assert!(c.flights.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/introspect.rs
+// Role: OAuth2 opaque bearer token validation (RFC 7662 introspection filter)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Extract bearer tokens and validate them through an Introspector.
+// - Cache results, bounded by the token's `exp` and a configured max TTL.
+// - Per-route failure policy when the introspection endpoint is unavailable.
+// - Propagate the principal to downstream plugins via trusted headers.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use olwsx_plugins_sdk::{caps, header, json_error, route_matches, FilterPlugin, FilterVerdict, PluginMeta, Request};
+use olwsx_security::canonical;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod olwsx_plugins_sdk {
+    pub use crate::pipeline::route_matches;
+    pub use crate::sdk::{caps, header, json_error, FilterPlugin, FilterVerdict, PluginMeta, Request};
+}
+
+mod olwsx_security {
+    pub use crate::path::canonical;
+}
+
+// Trusted headers set by this filter; client-supplied copies are always stripped.
+pub const PRINCIPAL_HEADER: &str = "x-olwsx-principal";
+pub const SCOPE_HEADER: &str = "x-olwsx-scope";
+pub const CLIENT_HEADER: &str = "x-olwsx-client-id";
+
+// Subset of the RFC 7662 response the gateway acts on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Introspection {
+    pub active: bool,
+    pub sub: Option<String>,
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+    pub exp: Option<u64>, // unix seconds
+}
+
+// Transport to the introspection endpoint (POST token=...), supplied by the
+// embedder; Err means the endpoint could not answer, not that the token is bad.
+pub trait Introspector: Send + Sync {
+    fn introspect(&self, token: &str) -> Result<Introspection, String>;
+}
+
+#[derive(Clone, Debug)]
+pub struct IntrospectConfig {
+    pub max_cache_ttl_secs: u64,
+    pub negative_ttl_secs: u64,   // inactive tokens
+    pub max_entries: usize,
+    pub fail_open: Vec<String>,   // route patterns allowed through when the endpoint fails
+}
+
+impl Default for IntrospectConfig {
+    fn default() -> Self {
+        Self { max_cache_ttl_secs: 300, negative_ttl_secs: 10, max_entries: 100_000, fail_open: Vec::new() }
+    }
+}
+
+pub struct IntrospectFilter {
+    cfg: IntrospectConfig,
+    backend: Arc<dyn Introspector>,
+    cache: Mutex<HashMap<String, (Introspection, u64)>>, // token -> (result, valid until unix secs)
+}
+
+impl IntrospectFilter {
+    pub fn new(backend: Arc<dyn Introspector>) -> Self {
+        Self { cfg: IntrospectConfig::default(), backend, cache: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn with_config(mut self, cfg: IntrospectConfig) -> Self {
+        self.cfg = cfg;
+        self
+    }
+
+    pub fn process_at(&self, req: &Request, now: u64) -> FilterVerdict {
+        let token = match header(req, "Authorization").and_then(bearer) {
+            Some(t) => t,
+            None => return unauthorized("missing_token", "bearer token required"),
+        };
+        match self.lookup(token, now) {
+            Ok(i) if i.active && i.exp.is_none_or(|e| e > now) => FilterVerdict::Mutate(with_principal(req, Some(&i))),
+            Ok(_) => unauthorized("invalid_token", "token is not active"),
+            Err(_) if self.fails_open(req.path) => FilterVerdict::Mutate(with_principal(req, None)),
+            Err(_) => FilterVerdict::ShortCircuit(json_error(503, "introspection_unavailable", "token could not be validated")),
+        }
+    }
+
+    // Matched on the canonical path, so "/public/../admin" is not public.
+    fn fails_open(&self, path: &str) -> bool {
+        let path = canonical(path);
+        self.cfg.fail_open.iter().any(|p| route_matches(p, &path))
+    }
+
+    fn lookup(&self, token: &str, now: u64) -> Result<Introspection, String> {
+        if let Some((i, until)) = self.lock().get(token)
+            && *until > now
+        {
+            return Ok(i.clone());
+        }
+        let i = self.backend.introspect(token)?;
+        let ttl = if i.active { self.cfg.max_cache_ttl_secs } else { self.cfg.negative_ttl_secs };
+        let until = match i.exp {
+            Some(exp) if i.active => exp.min(now.saturating_add(ttl)),
+            _ => now.saturating_add(ttl),
+        };
+        let mut cache = self.lock();
+        if cache.len() >= self.cfg.max_entries {
+            cache.retain(|_, (_, u)| *u > now);
+            if cache.len() >= self.cfg.max_entries {
+                cache.clear();
+            }
+        }
+        cache.insert(token.to_string(), (i.clone(), until));
+        Ok(i)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, (Introspection, u64)>> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl FilterPlugin for IntrospectFilter {
+    // NETWORK: every uncached token is a call to the introspection endpoint.
+    fn meta(&self) -> PluginMeta {
+        PluginMeta { name: "oauth2_introspect", version: "1.0.0", author: "OLWSX", flags: 0, caps: caps::NETWORK }
+    }
+
+    // Keys: max_cache_ttl_secs, negative_ttl_secs, max_entries, fail_open (comma-separated routes).
+    fn init(&mut self, cfg: &HashMap<String, String>) -> Result<(), String> {
+        let num = |k: &str, d: u64| -> Result<u64, String> {
+            cfg.get(k).map_or(Ok(d), |v| v.trim().parse().map_err(|_| format!("{}: invalid number '{}'", k, v)))
+        };
+        self.cfg.max_cache_ttl_secs = num("max_cache_ttl_secs", self.cfg.max_cache_ttl_secs)?;
+        self.cfg.negative_ttl_secs = num("negative_ttl_secs", self.cfg.negative_ttl_secs)?;
+        self.cfg.max_entries = num("max_entries", self.cfg.max_entries as u64)?.max(1) as usize;
+        if let Some(v) = cfg.get("fail_open") {
+            self.cfg.fail_open = v.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect();
+        }
+        Ok(())
+    }
+
+    fn process(&self, req: &Request) -> FilterVerdict {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        self.process_at(req, now)
+    }
+
+    fn teardown(&mut self) {
+        self.lock().clear();
+    }
+}
+
+fn bearer(v: &str) -> Option<&str> {
+    let (scheme, token) = v.trim().split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
+fn unauthorized(code: &str, msg: &str) -> FilterVerdict {
+    let mut r = json_error(401, code, msg);
+    r.headers.push(("WWW-Authenticate".to_string(), format!("Bearer error=\"{}\"", code)));
+    FilterVerdict::ShortCircuit(r)
+}
+
+fn with_principal(req: &Request, i: Option<&Introspection>) -> Request {
+    let mut out = req.clone();
+    out.headers.retain(|(k, _)| ![PRINCIPAL_HEADER, SCOPE_HEADER, CLIENT_HEADER].iter().any(|h| k.eq_ignore_ascii_case(h)));
+    if let Some(i) = i {
+        for (h, v) in [(PRINCIPAL_HEADER, &i.sub), (SCOPE_HEADER, &i.scope), (CLIENT_HEADER, &i.client_id)] {
+            if let Some(v) = v {
+                out.headers.push((h.to_string(), v.clone()));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct Stub {
+        calls: AtomicUsize,
+        down: AtomicBool,
+    }
+    impl Introspector for Stub {
+        fn introspect(&self, token: &str) -> Result<Introspection, String> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            if self.down.load(Ordering::Relaxed) {
+                return Err("connect timeout".to_string());
+            }
+            Ok(match token {
+                "good" => Introspection { active: true, sub: Some("alice".into()), scope: Some("read".into()), client_id: None, exp: Some(1_060) },
+                _ => Introspection::default(),
+            })
+        }
+    }
+
+    fn req(path: &'static str, auth: &str) -> Request {
+        Request { method: "GET", path, headers: vec![("Authorization".into(), auth.into()), (PRINCIPAL_HEADER.into(), "spoofed".into())], body: vec![], tenant: "default" }
+    }
+
+    #[test]
+    fn validates_caches_and_fails_per_route() {
+        let stub = Arc::new(Stub::default());
+        let f = IntrospectFilter::new(stub.clone())
+            .with_config(IntrospectConfig { fail_open: vec!["/public/*".into()], ..IntrospectConfig::default() });
+
+        match f.process_at(&req("/api", "Bearer good"), 1_000) {
+            FilterVerdict::Mutate(r) => {
+                assert_eq!(header(&r, PRINCIPAL_HEADER), Some("alice"));
+                assert_eq!(r.headers.iter().filter(|(k, _)| k == PRINCIPAL_HEADER).count(), 1);
+            }
+            v => panic!("unexpected {:?}", v),
+        }
+        f.process_at(&req("/api", "Bearer good"), 1_030);
+        assert_eq!(stub.calls.load(Ordering::Relaxed), 1, "cached until exp");
+
+        // Cache entry is bounded by exp; past it the token is re-introspected and rejected.
+        assert!(matches!(f.process_at(&req("/api", "Bearer good"), 1_061), FilterVerdict::ShortCircuit(ref r) if r.status == 401));
+        assert!(matches!(f.process_at(&req("/api", "Bearer bad"), 1_000), FilterVerdict::ShortCircuit(ref r) if r.status == 401));
+        assert!(matches!(f.process_at(&req("/api", "Basic x"), 1_000), FilterVerdict::ShortCircuit(ref r) if r.status == 401));
+
+        stub.down.store(true, Ordering::Relaxed);
+        assert!(matches!(f.process_at(&req("/api", "Bearer other"), 1_000), FilterVerdict::ShortCircuit(ref r) if r.status == 503));
+        match f.process_at(&req("/public/x", "Bearer other"), 1_000) {
+            FilterVerdict::Mutate(r) => assert_eq!(header(&r, PRINCIPAL_HEADER), None),
+            v => panic!("unexpected {:?}", v),
+        }
+        for path in ["/public/../admin", "/public/%2e%2e/admin", "/public/.%2E/admin?x=/public/", "/publicity"] {
+            assert!(matches!(f.process_at(&req(path, "Bearer other"), 1_000), FilterVerdict::ShortCircuit(ref r) if r.status == 503), "{}", path);
+        }
+    }
+}
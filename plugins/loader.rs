@@ -0,0 +1,865 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/loader.rs
+// Role: Final native plugin loader (shared libraries over the frozen C ABI)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Load `.so`/`.dylib`/`.dll` plugins and resolve their versioned entry point.
+// - Validate the ABI version and descriptor before any other field is trusted.
+// - Wrap native instances in the safe FilterPlugin/HandlerPlugin traits.
+// - Unload the library once the last instance created from it is dropped.
+// -----------------------------------------------------------------------------
+// C ABI (version 1). The library exports
+//   const OlwsxPluginV1 *olwsx_plugin_entry(void);
+// returning a descriptor that outlives the library handle. Strings are
+// (ptr, len) UTF-8 slices, never NUL-terminated. Function pointers may be
+// NULL in C; only `teardown` is allowed to be. Per instance:
+//   create()                      -> opaque instance (NULL = failure)
+//   init(inst, cfg, n)            -> 0 ok; else last_error(inst) says why
+//   call(inst, req, out)          -> filter: 0 continue, 1 short-circuit
+//                                    (out is the response), 2 mutate (out
+//                                    carries headers/body, optional path);
+//                                    handler: 0 ok. Anything else is a 500.
+//   release(inst, out)            -> after every call; frees what `out` holds
+//   teardown(inst)                -> optional, mirrors the trait hook
+//   destroy(inst)                 -> frees the instance
+// `call` runs concurrently from many threads; `init`, `teardown` and `destroy`
+// never overlap with anything else on the same instance.
+// Request paths are `&'static str`, so mutated paths are interned per library
+// (at most MAX_REWRITE_PATHS distinct ones), as are the descriptor strings.
+// -----------------------------------------------------------------------------
+// This is the only plugins file that needs `unsafe`: every block below crosses
+// into foreign code, and all of them stay private to this module.
+// =============================================================================
+
+#![deny(unsafe_op_in_unsafe_fn)]
+
+use olwsx_plugins_sdk::{intern, json_error, FilterPlugin, FilterVerdict, HandlerPlugin, HandlerResult, Plugin, PluginMeta, Registry, Request, Response};
+use std::collections::{HashMap, HashSet};
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+mod olwsx_plugins_sdk {
+    pub use crate::sdk::{intern, json_error, FilterPlugin, FilterVerdict, HandlerPlugin, HandlerResult, Plugin, PluginMeta, Registry, Request, Response};
+}
+
+pub const ABI_VERSION: u32 = 1;
+pub const ENTRY_SYMBOL: &str = "olwsx_plugin_entry";
+
+pub const KIND_FILTER: u32 = 1;
+pub const KIND_HANDLER: u32 = 2;
+
+pub const VERDICT_CONTINUE: i32 = 0;
+pub const VERDICT_SHORT_CIRCUIT: i32 = 1;
+pub const VERDICT_MUTATE: i32 = 2;
+
+// Distinct rewrite targets one library may produce; past this a Mutate with a
+// new path is answered 500 instead of growing the interner.
+pub const MAX_REWRITE_PATHS: usize = 4096;
+
+// ------------------------------- Frozen C types -----------------------------
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct OlwsxStr {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct OlwsxPair {
+    pub name: OlwsxStr,
+    pub value: OlwsxStr,
+}
+
+// Borrowed from the host for the duration of `call`.
+#[repr(C)]
+pub struct OlwsxRequest {
+    pub method: OlwsxStr,
+    pub path: OlwsxStr,
+    pub tenant: OlwsxStr,
+    pub headers: *const OlwsxPair,
+    pub headers_len: usize,
+    pub body: OlwsxStr,
+}
+
+// Filled by the plugin, copied by the host, then handed back to `release`.
+// Starts zeroed; `opaque` is the plugin's own bookkeeping.
+#[repr(C)]
+pub struct OlwsxOutput {
+    pub status: u16,
+    pub meta_flags: u32,
+    pub path: OlwsxStr, // mutate only; empty keeps the request path
+    pub headers: *const OlwsxPair,
+    pub headers_len: usize,
+    pub body: OlwsxStr,
+    pub trailers: *const OlwsxPair,
+    pub trailers_len: usize,
+    pub opaque: *mut c_void,
+}
+
+// `abi_version` stays the first field of every future descriptor so a host
+// can reject a mismatch before reading anything else.
+#[repr(C)]
+pub struct OlwsxPluginV1 {
+    pub abi_version: u32,
+    pub kind: u32,
+    pub name: OlwsxStr,
+    pub version: OlwsxStr,
+    pub author: OlwsxStr,
+    pub flags: u32,
+    pub caps: u32,
+    pub create: Option<unsafe extern "C" fn() -> *mut c_void>,
+    pub init: Option<unsafe extern "C" fn(inst: *mut c_void, cfg: *const OlwsxPair, cfg_len: usize) -> i32>,
+    pub call: Option<unsafe extern "C" fn(inst: *mut c_void, req: *const OlwsxRequest, out: *mut OlwsxOutput) -> i32>,
+    pub release: Option<unsafe extern "C" fn(inst: *mut c_void, out: *mut OlwsxOutput)>,
+    pub last_error: Option<unsafe extern "C" fn(inst: *mut c_void) -> OlwsxStr>,
+    pub teardown: Option<unsafe extern "C" fn(inst: *mut c_void)>,
+    pub destroy: Option<unsafe extern "C" fn(inst: *mut c_void)>,
+}
+
+type EntryFn = unsafe extern "C" fn() -> *const OlwsxPluginV1;
+
+// The entry points of a validated descriptor, none of them NULL.
+#[derive(Clone, Copy)]
+struct Fns {
+    create: unsafe extern "C" fn() -> *mut c_void,
+    init: unsafe extern "C" fn(inst: *mut c_void, cfg: *const OlwsxPair, cfg_len: usize) -> i32,
+    call: unsafe extern "C" fn(inst: *mut c_void, req: *const OlwsxRequest, out: *mut OlwsxOutput) -> i32,
+    release: unsafe extern "C" fn(inst: *mut c_void, out: *mut OlwsxOutput),
+    last_error: unsafe extern "C" fn(inst: *mut c_void) -> OlwsxStr,
+    teardown: Option<unsafe extern "C" fn(inst: *mut c_void)>,
+    destroy: unsafe extern "C" fn(inst: *mut c_void),
+}
+
+impl OlwsxStr {
+    fn of(s: &str) -> Self {
+        OlwsxStr { ptr: s.as_ptr(), len: s.len() }
+    }
+
+    // SAFETY (callers): ptr/len must describe live memory, or len must be 0.
+    unsafe fn bytes<'a>(self) -> &'a [u8] {
+        if self.ptr.is_null() || self.len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+
+    unsafe fn lossy(self) -> String {
+        String::from_utf8_lossy(unsafe { self.bytes() }).into_owned()
+    }
+}
+
+unsafe fn pairs(ptr: *const OlwsxPair, len: usize) -> Vec<(String, String)> {
+    if ptr.is_null() || len == 0 {
+        return Vec::new();
+    }
+    let raw = unsafe { std::slice::from_raw_parts(ptr, len) };
+    raw.iter().map(|p| unsafe { (p.name.lossy(), p.value.lossy()) }).collect()
+}
+
+// ------------------------------- Library handles ----------------------------
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{c_char, c_int, c_void, CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    const RTLD_NOW: c_int = 2;
+
+    #[cfg_attr(target_os = "linux", link(name = "dl"))]
+    unsafe extern "C" {
+        fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+        fn dlclose(handle: *mut c_void) -> c_int;
+        fn dlerror() -> *mut c_char;
+    }
+
+    pub struct Handle(*mut c_void);
+
+    fn last_error() -> String {
+        // SAFETY: dlerror returns NULL or a NUL-terminated thread-local message.
+        let msg = unsafe { dlerror() };
+        if msg.is_null() {
+            return "unknown dynamic loader error".to_string();
+        }
+        unsafe { CStr::from_ptr(msg) }.to_string_lossy().into_owned()
+    }
+
+    // SAFETY (callers): runs the library's initializers.
+    pub unsafe fn open(path: &Path) -> Result<Handle, String> {
+        let c = CString::new(path.as_os_str().as_bytes()).map_err(|_| "path contains a NUL byte".to_string())?;
+        let h = unsafe { dlopen(c.as_ptr(), RTLD_NOW) };
+        if h.is_null() {
+            return Err(last_error());
+        }
+        Ok(Handle(h))
+    }
+
+    pub fn symbol(h: &Handle, name: &str) -> Result<*mut c_void, String> {
+        let c = CString::new(name).map_err(|_| "symbol contains a NUL byte".to_string())?;
+        // SAFETY: the handle is open for as long as `h` lives.
+        let s = unsafe { dlsym(h.0, c.as_ptr()) };
+        if s.is_null() {
+            return Err(last_error());
+        }
+        Ok(s)
+    }
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            // SAFETY: nothing resolved from the handle outlives it (see Module).
+            unsafe { dlclose(self.0) };
+        }
+    }
+
+    // Whether `path` is still mapped, without loading it.
+    #[cfg(test)]
+    pub fn resident(path: &Path) -> bool {
+        const RTLD_NOLOAD: c_int = if cfg!(target_os = "linux") { 4 } else { 0x10 };
+        let c = CString::new(path.as_os_str().as_bytes()).unwrap();
+        let h = unsafe { dlopen(c.as_ptr(), RTLD_NOW | RTLD_NOLOAD) };
+        if h.is_null() {
+            return false;
+        }
+        unsafe { dlclose(h) };
+        true
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::ffi::{c_char, c_void, CString};
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn LoadLibraryW(name: *const u16) -> *mut c_void;
+        fn GetProcAddress(module: *mut c_void, name: *const c_char) -> *mut c_void;
+        fn FreeLibrary(module: *mut c_void) -> i32;
+        fn GetLastError() -> u32;
+    }
+
+    pub struct Handle(*mut c_void);
+
+    // SAFETY (callers): runs DllMain.
+    pub unsafe fn open(path: &Path) -> Result<Handle, String> {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+        let h = unsafe { LoadLibraryW(wide.as_ptr()) };
+        if h.is_null() {
+            return Err(format!("LoadLibraryW failed (error {})", unsafe { GetLastError() }));
+        }
+        Ok(Handle(h))
+    }
+
+    pub fn symbol(h: &Handle, name: &str) -> Result<*mut c_void, String> {
+        let c = CString::new(name).map_err(|_| "symbol contains a NUL byte".to_string())?;
+        // SAFETY: the module stays loaded for as long as `h` lives.
+        let s = unsafe { GetProcAddress(h.0, c.as_ptr()) };
+        if s.is_null() {
+            return Err(format!("GetProcAddress failed (error {})", unsafe { GetLastError() }));
+        }
+        Ok(s)
+    }
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            // SAFETY: nothing resolved from the handle outlives it (see Module).
+            unsafe { FreeLibrary(self.0) };
+        }
+    }
+}
+
+// ------------------------------- Native plugins -----------------------------
+
+// One validated descriptor plus the library it lives in. Instances hold an
+// Arc, so the library is closed only after the last of them is destroyed.
+struct Module {
+    desc: *const OlwsxPluginV1,
+    fns: Fns,
+    meta: PluginMeta,
+    path: PathBuf,
+    rewrites: Mutex<HashSet<&'static str>>, // interned Mutate paths
+    _lib: Option<sys::Handle>, // None for descriptors linked into the binary
+}
+
+// SAFETY: the descriptor is immutable and the ABI requires `call` to be
+// thread-safe; every other entry point is serialized by the Registry.
+unsafe impl Send for Module {}
+unsafe impl Sync for Module {}
+
+impl Module {
+    fn desc(&self) -> &OlwsxPluginV1 {
+        // SAFETY: validated non-null at load; lives as long as the library.
+        unsafe { &*self.desc }
+    }
+
+    // `path` as a 'static str, or None once MAX_REWRITE_PATHS are in use.
+    fn rewrite_path(&self, path: String) -> Option<&'static str> {
+        let mut set = self.rewrites.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(p) = set.get(path.as_str()) {
+            return Some(p);
+        }
+        if set.len() >= MAX_REWRITE_PATHS {
+            return None;
+        }
+        let p: &'static str = Box::leak(path.into_boxed_str());
+        set.insert(p);
+        Some(p)
+    }
+}
+
+// A loaded plugin library: a factory for instances of the plugin it exports.
+#[derive(Clone)]
+pub struct NativePlugin {
+    module: Arc<Module>,
+}
+
+impl NativePlugin {
+    /// Loads the library at `path`, resolves `olwsx_plugin_entry` and
+    /// validates the descriptor it returns.
+    ///
+    /// # Safety
+    /// Loading runs the library's initializers, and the library must honour
+    /// the C ABI above; neither can be checked from here.
+    pub unsafe fn load(path: &Path) -> Result<Self, String> {
+        let ctx = |e: String| format!("{}: {}", path.display(), e);
+        let lib = unsafe { sys::open(path) }.map_err(ctx)?;
+        let sym = sys::symbol(&lib, ENTRY_SYMBOL).map_err(ctx)?;
+        // SAFETY: the ABI fixes the entry point's signature.
+        let entry = unsafe { std::mem::transmute::<*mut c_void, EntryFn>(sym) };
+        let desc = unsafe { entry() };
+        unsafe { Self::validate(desc, path.to_path_buf(), Some(lib)) }.map_err(ctx)
+    }
+
+    /// Wraps a descriptor linked into the binary (no library to unload).
+    ///
+    /// # Safety
+    /// `desc` must be null or point to a descriptor that lives for the rest
+    /// of the process and honours the C ABI above.
+    pub unsafe fn from_descriptor(desc: *const OlwsxPluginV1, label: &str) -> Result<Self, String> {
+        unsafe { Self::validate(desc, PathBuf::from(label), None) }
+    }
+
+    unsafe fn validate(desc: *const OlwsxPluginV1, path: PathBuf, lib: Option<sys::Handle>) -> Result<Self, String> {
+        if desc.is_null() {
+            return Err("entry point returned no descriptor".to_string());
+        }
+        // Only the version is read until it matches.
+        let version = unsafe { std::ptr::read(desc as *const u32) };
+        if version != ABI_VERSION {
+            return Err(format!("ABI version {} not supported (host speaks {})", version, ABI_VERSION));
+        }
+        let d = unsafe { &*desc };
+        if d.kind != KIND_FILTER && d.kind != KIND_HANDLER {
+            return Err(format!("unknown plugin kind {}", d.kind));
+        }
+        let name = unsafe { d.name.lossy() };
+        if name.is_empty() {
+            return Err("descriptor has no plugin name".to_string());
+        }
+        let missing = |f: &str| format!("descriptor has no '{}' entry point", f);
+        let fns = Fns {
+            create: d.create.ok_or_else(|| missing("create"))?,
+            init: d.init.ok_or_else(|| missing("init"))?,
+            call: d.call.ok_or_else(|| missing("call"))?,
+            release: d.release.ok_or_else(|| missing("release"))?,
+            last_error: d.last_error.ok_or_else(|| missing("last_error"))?,
+            teardown: d.teardown,
+            destroy: d.destroy.ok_or_else(|| missing("destroy"))?,
+        };
+        // PluginMeta is 'static; reloading a library reuses the same strings.
+        let meta = PluginMeta {
+            name: intern(&name),
+            version: intern(&unsafe { d.version.lossy() }),
+            author: intern(&unsafe { d.author.lossy() }),
+            flags: d.flags,
+            caps: d.caps,
+        };
+        let rewrites = Mutex::new(HashSet::new());
+        Ok(NativePlugin { module: Arc::new(Module { desc, fns, meta, path, rewrites, _lib: lib }) })
+    }
+
+    pub fn meta(&self) -> PluginMeta {
+        self.module.meta.clone()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.module.path
+    }
+
+    pub fn is_filter(&self) -> bool {
+        self.module.desc().kind == KIND_FILTER
+    }
+
+    // A fresh, uninitialized instance, ready for register_* or Registry::replace.
+    pub fn instantiate(&self) -> Result<Plugin, String> {
+        // SAFETY: create takes no arguments; NULL signals failure.
+        let inst = unsafe { (self.module.fns.create)() };
+        if inst.is_null() {
+            return Err(format!("plugin '{}' failed to create an instance", self.module.meta.name));
+        }
+        let native = Instance { inst, module: self.module.clone() };
+        Ok(if self.is_filter() { Plugin::Filter(Box::new(NativeFilter(native))) } else { Plugin::Handler(Box::new(NativeHandler(native))) })
+    }
+
+    // instantiate() + register_filter/register_handler under `key`.
+    pub fn register(&self, reg: &mut Registry, key: &'static str) -> Result<(), String> {
+        match self.instantiate()? {
+            Plugin::Filter(p) => reg.register_filter(key, p),
+            Plugin::Handler(p) => reg.register_handler(key, p),
+        }
+    }
+}
+
+struct Instance {
+    inst: *mut c_void,
+    module: Arc<Module>,
+}
+
+// SAFETY: see Module.
+unsafe impl Send for Instance {}
+unsafe impl Sync for Instance {}
+
+impl Instance {
+    fn init(&mut self, cfg: &HashMap<String, String>) -> Result<(), String> {
+        let mut keys: Vec<&String> = cfg.keys().collect();
+        keys.sort();
+        let kv: Vec<OlwsxPair> = keys.iter().map(|k| OlwsxPair { name: OlwsxStr::of(k), value: OlwsxStr::of(&cfg[*k]) }).collect();
+        let d = self.module.fns;
+        // SAFETY: `kv` and the strings it points into outlive the call.
+        let rc = unsafe { (d.init)(self.inst, kv.as_ptr(), kv.len()) };
+        if rc == 0 {
+            return Ok(());
+        }
+        let msg = unsafe { (d.last_error)(self.inst).lossy() };
+        Err(if msg.is_empty() { format!("native init failed ({})", rc) } else { msg })
+    }
+
+    // Runs `call` and copies out whatever `read` needs before `release`.
+    fn call<T>(&self, req: &Request, read: impl FnOnce(i32, &OlwsxOutput) -> T) -> T {
+        let headers: Vec<OlwsxPair> = req.headers.iter().map(|(k, v)| OlwsxPair { name: OlwsxStr::of(k), value: OlwsxStr::of(v) }).collect();
+        let raw = OlwsxRequest {
+            method: OlwsxStr::of(req.method),
+            path: OlwsxStr::of(req.path),
+            tenant: OlwsxStr::of(req.tenant),
+            headers: headers.as_ptr(),
+            headers_len: headers.len(),
+            body: OlwsxStr { ptr: req.body.as_ptr(), len: req.body.len() },
+        };
+        let empty = OlwsxStr { ptr: std::ptr::null(), len: 0 };
+        let mut out = OlwsxOutput {
+            status: 0,
+            meta_flags: 0,
+            path: empty,
+            headers: std::ptr::null(),
+            headers_len: 0,
+            body: empty,
+            trailers: std::ptr::null(),
+            trailers_len: 0,
+            opaque: std::ptr::null_mut(),
+        };
+        let d = self.module.fns;
+        // SAFETY: `raw` borrows `req` and `headers`, both alive across the call;
+        // `out` stays valid until release.
+        let rc = unsafe { (d.call)(self.inst, &raw, &mut out) };
+        let value = read(rc, &out);
+        unsafe { (d.release)(self.inst, &mut out) };
+        value
+    }
+
+    fn teardown(&mut self) {
+        if let Some(t) = self.module.fns.teardown {
+            // SAFETY: teardown has exclusive access to the instance.
+            unsafe { t(self.inst) };
+        }
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        // SAFETY: last use of `inst`; the Arc (and library) is dropped after this.
+        unsafe { (self.module.fns.destroy)(self.inst) };
+    }
+}
+
+unsafe fn response(out: &OlwsxOutput) -> Response {
+    let mut r = Response::new(out.status);
+    r.headers = unsafe { pairs(out.headers, out.headers_len) };
+    r.body = unsafe { out.body.bytes() }.to_vec();
+    r.trailers = unsafe { pairs(out.trailers, out.trailers_len) };
+    r
+}
+
+fn failed(name: &str, rc: i32) -> Response {
+    json_error(500, "plugin_error", &format!("native plugin '{}' failed ({})", name, rc))
+}
+
+struct NativeFilter(Instance);
+
+impl FilterPlugin for NativeFilter {
+    fn meta(&self) -> PluginMeta {
+        self.0.module.meta.clone()
+    }
+
+    fn init(&mut self, cfg: &HashMap<String, String>) -> Result<(), String> {
+        self.0.init(cfg)
+    }
+
+    fn process(&self, req: &Request) -> FilterVerdict {
+        let module = &self.0.module;
+        let name = module.meta.name;
+        // SAFETY: `out` is read before release, as the ABI requires.
+        self.0.call(req, |rc, out| match rc {
+            VERDICT_CONTINUE => FilterVerdict::Continue,
+            VERDICT_SHORT_CIRCUIT => FilterVerdict::ShortCircuit(unsafe { response(out) }),
+            VERDICT_MUTATE => {
+                let path = unsafe { out.path.lossy() };
+                let path = if path.is_empty() || path == req.path { Some(req.path) } else { module.rewrite_path(path) };
+                let Some(path) = path else {
+                    let msg = format!("native plugin '{}' exceeded {} distinct rewrite paths", name, MAX_REWRITE_PATHS);
+                    return FilterVerdict::ShortCircuit(json_error(500, "plugin_error", &msg));
+                };
+                FilterVerdict::Mutate(Request {
+                    method: req.method,
+                    path,
+                    headers: unsafe { pairs(out.headers, out.headers_len) },
+                    body: unsafe { out.body.bytes() }.to_vec(),
+                    tenant: req.tenant,
+                })
+            }
+            rc => FilterVerdict::ShortCircuit(failed(name, rc)),
+        })
+    }
+
+    fn teardown(&mut self) {
+        self.0.teardown();
+    }
+}
+
+struct NativeHandler(Instance);
+
+impl HandlerPlugin for NativeHandler {
+    fn meta(&self) -> PluginMeta {
+        self.0.module.meta.clone()
+    }
+
+    fn init(&mut self, cfg: &HashMap<String, String>) -> Result<(), String> {
+        self.0.init(cfg)
+    }
+
+    fn handle(&self, req: &Request) -> HandlerResult {
+        let name = self.0.module.meta.name;
+        self.0.call(req, |rc, out| match rc {
+            0 => HandlerResult { resp: unsafe { response(out) }, meta_flags: out.meta_flags },
+            rc => HandlerResult { resp: failed(name, rc), meta_flags: 0 },
+        })
+    }
+
+    fn teardown(&mut self) {
+        self.0.teardown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdk::caps;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // A plugin written against the C ABI, linked in rather than dlopen'ed.
+    static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+    struct Greeter {
+        greeting: String,
+    }
+
+    const fn s(b: &'static [u8]) -> OlwsxStr {
+        OlwsxStr { ptr: b.as_ptr(), len: b.len() }
+    }
+
+    unsafe extern "C" fn create() -> *mut c_void {
+        LIVE.fetch_add(1, Ordering::SeqCst);
+        Box::into_raw(Box::new(Greeter { greeting: String::new() })) as *mut c_void
+    }
+
+    unsafe extern "C" fn init(inst: *mut c_void, cfg: *const OlwsxPair, n: usize) -> i32 {
+        let g = unsafe { &mut *(inst as *mut Greeter) };
+        match unsafe { pairs(cfg, n) }.into_iter().find(|(k, _)| k == "greeting") {
+            Some((_, v)) => {
+                g.greeting = v;
+                0
+            }
+            None => 1,
+        }
+    }
+
+    unsafe extern "C" fn call(inst: *mut c_void, req: *const OlwsxRequest, out: *mut OlwsxOutput) -> i32 {
+        let (g, req, out) = unsafe { (&*(inst as *const Greeter), &*req, &mut *out) };
+        let body = format!("{}, {}", g.greeting, String::from_utf8_lossy(unsafe { req.path.bytes() }));
+        let body = Box::leak(body.into_boxed_str());
+        out.status = 200;
+        out.meta_flags = 0x0010_0000;
+        out.body = OlwsxStr::of(body);
+        out.opaque = body.as_mut_ptr() as *mut c_void;
+        0
+    }
+
+    unsafe extern "C" fn release(_inst: *mut c_void, out: *mut OlwsxOutput) {
+        let out = unsafe { &mut *out };
+        if !out.opaque.is_null() {
+            let raw = std::ptr::slice_from_raw_parts_mut(out.opaque as *mut u8, out.body.len);
+            drop(unsafe { Box::from_raw(raw as *mut str) });
+        }
+    }
+
+    unsafe extern "C" fn last_error(_inst: *mut c_void) -> OlwsxStr {
+        s(b"missing 'greeting'")
+    }
+
+    unsafe extern "C" fn destroy(inst: *mut c_void) {
+        drop(unsafe { Box::from_raw(inst as *mut Greeter) });
+        LIVE.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    // Raw pointers make the descriptor !Sync; the ABI promises it is immutable.
+    struct Static(OlwsxPluginV1);
+    unsafe impl Sync for Static {}
+
+    const GREETER: OlwsxPluginV1 = OlwsxPluginV1 {
+        abi_version: ABI_VERSION,
+        kind: KIND_HANDLER,
+        name: s(b"greeter"),
+        version: s(b"1.0.0"),
+        author: s(b"OLWSX"),
+        flags: 0,
+        caps: 0,
+        create: Some(create),
+        init: Some(init),
+        call: Some(call),
+        release: Some(release),
+        last_error: Some(last_error),
+        teardown: None,
+        destroy: Some(destroy),
+    };
+
+    static HELLO: Static = Static(GREETER);
+    static FUTURE: Static = Static(OlwsxPluginV1 { abi_version: 2, ..GREETER });
+    static ODD: Static = Static(OlwsxPluginV1 { kind: 9, ..GREETER });
+    static NO_CALL: Static = Static(OlwsxPluginV1 { call: None, ..GREETER });
+    static NO_DESTROY: Static = Static(OlwsxPluginV1 { destroy: None, ..GREETER });
+
+    // A filter whose verdict is picked by the request path. Every output it
+    // hands out is counted in RELEASED once the host gives it back.
+    static CALLED: AtomicUsize = AtomicUsize::new(0);
+    static RELEASED: AtomicUsize = AtomicUsize::new(0);
+    struct Pair(OlwsxPair);
+    unsafe impl Sync for Pair {}
+    static DENIED: Pair = Pair(OlwsxPair { name: s(b"x-denied"), value: s(b"1") });
+    static TAGGED: Pair = Pair(OlwsxPair { name: s(b"x-tagged"), value: s(b"yes") });
+
+    unsafe extern "C" fn route(_inst: *mut c_void, req: *const OlwsxRequest, out: *mut OlwsxOutput) -> i32 {
+        let (req, out) = unsafe { (&*req, &mut *out) };
+        CALLED.fetch_add(1, Ordering::SeqCst);
+        match unsafe { req.path.bytes() } {
+            b"/stop" => {
+                out.status = 403;
+                out.headers = &DENIED.0;
+                out.headers_len = 1;
+                out.body = s(b"no");
+                VERDICT_SHORT_CIRCUIT
+            }
+            b"/old" | b"/same" => {
+                out.path = if req.path.len == 4 { s(b"/new") } else { s(b"") };
+                out.headers = &TAGGED.0;
+                out.headers_len = 1;
+                out.body = s(b"changed");
+                VERDICT_MUTATE
+            }
+            b"/broken" => 7,
+            _ => VERDICT_CONTINUE,
+        }
+    }
+
+    unsafe extern "C" fn blank() -> *mut c_void {
+        Box::into_raw(Box::new(0u8)) as *mut c_void
+    }
+
+    unsafe extern "C" fn accept(_inst: *mut c_void, _cfg: *const OlwsxPair, _n: usize) -> i32 {
+        0
+    }
+
+    unsafe extern "C" fn drop_blank(inst: *mut c_void) {
+        drop(unsafe { Box::from_raw(inst as *mut u8) });
+    }
+
+    unsafe extern "C" fn count_release(_inst: *mut c_void, _out: *mut OlwsxOutput) {
+        RELEASED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    static ROUTER: Static = Static(OlwsxPluginV1 {
+        kind: KIND_FILTER,
+        name: s(b"router"),
+        caps: caps::MUTATE_PATH | caps::READ_BODY,
+        create: Some(blank),
+        init: Some(accept),
+        call: Some(route),
+        release: Some(count_release),
+        destroy: Some(drop_blank),
+        ..GREETER
+    });
+
+    #[test]
+    fn native_handler_round_trip_and_unload() {
+        let native = unsafe { NativePlugin::from_descriptor(&HELLO.0, "greeter") }.unwrap();
+        assert_eq!(native.meta().name, "greeter");
+        assert!(!native.is_filter());
+
+        let mut reg = Registry::new();
+        native.register(&mut reg, "hello").unwrap();
+        assert!(reg.init_all(&HashMap::new()).is_err(), "init error surfaces from last_error");
+        let cfg = HashMap::from([("hello".to_string(), HashMap::from([("greeting".to_string(), "hi".to_string())]))]);
+        reg.init_all(&cfg).unwrap();
+
+        let req = Request { method: "GET", path: "/there", headers: vec![], body: vec![], tenant: "default" };
+        let out = reg.handle("hello", &req).unwrap();
+        assert_eq!((out.resp.status, out.meta_flags), (200, 0x0010_0000));
+        assert_eq!(out.resp.body, b"hi, /there".to_vec());
+
+        reg.teardown_all();
+        drop(reg);
+        drop(native);
+        assert_eq!(LIVE.load(Ordering::SeqCst), 0, "every instance destroyed");
+    }
+
+    #[test]
+    fn descriptors_are_validated() {
+        let err = |d: *const OlwsxPluginV1| unsafe { NativePlugin::from_descriptor(d, "x") }.err().unwrap();
+        assert!(err(std::ptr::null()).contains("no descriptor"));
+        assert!(err(&FUTURE.0).contains("ABI version 2"));
+        assert!(err(&ODD.0).contains("unknown plugin kind"));
+        assert!(err(&NO_CALL.0).contains("no 'call' entry point"));
+        assert!(err(&NO_DESTROY.0).contains("no 'destroy' entry point"));
+        assert!(unsafe { NativePlugin::load(Path::new("/nonexistent/libolwsx_missing.so")) }.is_err());
+    }
+
+    #[test]
+    fn native_filter_verdicts_and_release() {
+        let native = unsafe { NativePlugin::from_descriptor(&ROUTER.0, "router") }.unwrap();
+        assert!(native.is_filter());
+        let again = unsafe { NativePlugin::from_descriptor(&ROUTER.0, "router") }.unwrap();
+        assert!(std::ptr::eq(native.meta().name, again.meta().name), "descriptor strings are shared across loads");
+
+        let mut reg = Registry::new();
+        native.register(&mut reg, "router").unwrap();
+        reg.init_all(&HashMap::new()).unwrap();
+        let req = |path: &'static str| Request { method: "GET", path, headers: vec![], body: vec![], tenant: "default" };
+
+        assert!(matches!(reg.filter("router", &req("/pass")), FilterVerdict::Continue));
+        match reg.filter("router", &req("/stop")) {
+            FilterVerdict::ShortCircuit(r) => {
+                assert_eq!((r.status, r.body.as_slice()), (403, &b"no"[..]));
+                assert_eq!(r.headers, vec![("x-denied".to_string(), "1".to_string())]);
+            }
+            v => panic!("expected a short circuit, got {:?}", v),
+        }
+        let mut rewritten = Vec::new();
+        for _ in 0..3 {
+            match reg.filter("router", &req("/old")) {
+                FilterVerdict::Mutate(m) => {
+                    assert_eq!((m.method, m.path, m.body.as_slice()), ("GET", "/new", &b"changed"[..]));
+                    assert_eq!(m.headers, vec![("x-tagged".to_string(), "yes".to_string())]);
+                    rewritten.push(m.path);
+                }
+                v => panic!("expected a mutation, got {:?}", v),
+            }
+        }
+        assert!(rewritten.windows(2).all(|w| std::ptr::eq(w[0], w[1])), "one copy per distinct rewrite path");
+        assert!(matches!(reg.filter("router", &req("/same")), FilterVerdict::Mutate(m) if m.path == "/same"));
+        assert_eq!(native.module.rewrites.lock().unwrap().len(), 1);
+        assert!(matches!(reg.filter("router", &req("/broken")), FilterVerdict::ShortCircuit(r) if r.status == 500));
+
+        assert_eq!(CALLED.load(Ordering::SeqCst), 7);
+        assert_eq!(RELEASED.load(Ordering::SeqCst), 7, "every output released exactly once");
+        reg.teardown_all();
+    }
+
+    // Builds a real shared library with the system C compiler ($CC, else cc),
+    // loads it and checks it is unmapped once the last instance is gone. A
+    // missing compiler fails the test rather than passing it unexercised.
+    #[cfg(unix)]
+    #[test]
+    fn dlopen_round_trip_and_unload() {
+        const SOURCE: &str = r#"
+            #include <stdint.h>
+            #include <stdlib.h>
+            #include <string.h>
+            typedef struct { const uint8_t *ptr; size_t len; } S;
+            typedef struct { S name, value; } P;
+            typedef struct { S method, path, tenant; const P *headers; size_t headers_len; S body; } Req;
+            typedef struct {
+                uint16_t status; uint32_t meta_flags; S path; const P *headers; size_t headers_len;
+                S body; const P *trailers; size_t trailers_len; void *opaque;
+            } Out;
+            typedef struct {
+                uint32_t abi_version, kind; S name, version, author; uint32_t flags, caps;
+                void *(*create)(void);
+                int32_t (*init)(void *, const P *, size_t);
+                int32_t (*call)(void *, const Req *, Out *);
+                void (*release)(void *, Out *);
+                S (*last_error)(void *);
+                void (*teardown)(void *);
+                void (*destroy)(void *);
+            } D;
+            static void *create(void) { return malloc(1); }
+            static int32_t init(void *i, const P *c, size_t n) { (void)i; (void)c; (void)n; return 0; }
+            static int32_t call(void *i, const Req *r, Out *o) {
+                (void)i;
+                char *b = malloc(r->path.len + 7);
+                memcpy(b, "native:", 7);
+                memcpy(b + 7, r->path.ptr, r->path.len);
+                o->status = 200;
+                o->body.ptr = (const uint8_t *)b;
+                o->body.len = r->path.len + 7;
+                o->opaque = b;
+                return 0;
+            }
+            static void release(void *i, Out *o) { (void)i; free(o->opaque); }
+            static S last_error(void *i) { (void)i; S s = {0, 0}; return s; }
+            static void destroy(void *i) { free(i); }
+            static const D desc = {
+                1, 2, {(const uint8_t *)"cnative", 7}, {(const uint8_t *)"0.1.0", 5}, {(const uint8_t *)"OLWSX", 5},
+                0, 0, create, init, call, release, last_error, 0, destroy,
+            };
+            const D *olwsx_plugin_entry(void) { return &desc; }
+        "#;
+        let dir = std::env::temp_dir().join(format!("olwsx-loader-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (src, lib) = (dir.join("cnative.c"), dir.join("libcnative.so"));
+        std::fs::write(&src, SOURCE).unwrap();
+        let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+        let built = std::process::Command::new(&cc).arg("-shared").arg("-fPIC").arg("-o").arg(&lib).arg(&src).status();
+        assert!(matches!(built, Ok(s) if s.success()), "building the test plugin with {} failed ({:?}); set CC to a working C compiler", cc, built);
+
+        let native = unsafe { NativePlugin::load(&lib) }.unwrap();
+        assert_eq!((native.meta().name, native.meta().version), ("cnative", "0.1.0"));
+        assert!(sys::resident(&lib));
+        let mut reg = Registry::new();
+        native.register(&mut reg, "cnative").unwrap();
+        reg.init_all(&HashMap::new()).unwrap();
+        let req = Request { method: "GET", path: "/x", headers: vec![], body: vec![], tenant: "default" };
+        assert_eq!(reg.handle("cnative", &req).unwrap().resp.body, b"native:/x".to_vec());
+
+        drop(native);
+        assert!(sys::resident(&lib), "a registered instance keeps the library open");
+        reg.teardown_all();
+        drop(reg);
+        assert!(!sys::resident(&lib), "closed after the last instance");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
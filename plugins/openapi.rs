@@ -0,0 +1,769 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/openapi.rs
+// Role: OpenAPI 3.x request validation filter
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Load a JSON OpenAPI 3.x document once (config key `spec`: file path);
+//   every problem in it is reported at once, with its line.
+// - Match the canonical path to a path template (literal segments beat
+//   `{params}`) and the method to an operation: 404 / 405 (with Allow).
+// - Parameters (path, query, header): required ones present, values of the
+//   declared type (integer, number, boolean, string, arrays of those),
+//   enum, minimum/maximum, minLength/maxLength.
+// - Request body: required or not allowed, Content-Type among the declared
+//   media types (415), and for JSON media types the schema subset: type,
+//   nullable, enum, bounds, properties/required/additionalProperties, items,
+//   allOf and local `$ref`s (#/components/...). oneOf/anyOf/pattern/format
+//   are not checked.
+// - Failures answer a structured JSON error listing every violation, and
+//   count in `openapi_validation_failures_total` per operationId and reason;
+//   passing requests count in `openapi_validated_total`.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use olwsx_common::{json_parse, JsonValue as Value};
+use olwsx_observability::{default_registry, Registry as Metrics};
+use olwsx_plugins_sdk::{add_header, caps, header, json_status, ErrorReport, FilterPlugin, FilterVerdict, Issue, Json, PluginMeta, Request, Response};
+use olwsx_security::{canonical, percent_decode};
+use std::collections::HashMap;
+
+mod olwsx_common {
+    pub use crate::json::{parse as json_parse, Value as JsonValue};
+}
+
+mod olwsx_observability {
+    pub use crate::registry::{default_registry, Registry};
+}
+
+mod olwsx_plugins_sdk {
+    pub use crate::sdk::{add_header, caps, header, json_status, ErrorReport, FilterPlugin, FilterVerdict, Issue, Json, PluginMeta, Request, Response};
+}
+
+mod olwsx_security {
+    pub use crate::path::{canonical, percent_decode};
+}
+
+pub const VALIDATED: &str = "openapi_validated_total";
+pub const FAILURES: &str = "openapi_validation_failures_total";
+
+const SOURCE: &str = "openapi";
+const METHODS: [&str; 8] = ["get", "put", "post", "delete", "options", "head", "patch", "trace"];
+const MAX_VIOLATIONS: usize = 20; // listed per response; the rest are dropped
+const MAX_DEPTH: usize = 64; // body nesting (and $ref chains) checked
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum In {
+    Path,
+    Query,
+    Header,
+}
+
+impl In {
+    fn name(self) -> &'static str {
+        match self {
+            In::Path => "path",
+            In::Query => "query",
+            In::Header => "header",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Schema {
+    reference: Option<String>, // components/schemas name
+    types: Vec<String>,        // empty: any
+    nullable: bool,
+    enumeration: Vec<Value>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+    items: Option<Box<Schema>>,
+    properties: Vec<(String, Schema)>,
+    required: Vec<String>,
+    additional: Option<Box<Schema>>, // additionalProperties as a schema
+    closed: bool,                    // additionalProperties: false
+    all_of: Vec<Schema>,
+}
+
+#[derive(Clone, Debug)]
+struct Param {
+    name: String,
+    location: In,
+    required: bool,
+    schema: Schema,
+}
+
+#[derive(Clone, Debug)]
+struct Body {
+    required: bool,
+    content: Vec<(String, Option<Schema>)>, // media type range -> schema
+}
+
+#[derive(Clone, Debug)]
+struct Operation {
+    id: String,
+    params: Vec<Param>,
+    body: Option<Body>,
+}
+
+#[derive(Clone, Debug)]
+struct PathItem {
+    segments: Vec<Option<String>>, // None: a {param}
+    names: Vec<String>,            // param names, in order
+    ops: Vec<(String, Operation)>, // uppercase method
+}
+
+#[derive(Clone, Debug)]
+struct Violation {
+    location: &'static str,
+    name: String,
+    message: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Spec {
+    paths: Vec<PathItem>,
+    schemas: HashMap<String, Schema>,
+}
+
+impl Spec {
+    pub fn parse(src: &str) -> Result<Self, ErrorReport> {
+        let doc = json_parse(src).map_err(|e| ErrorReport { issues: vec![Issue::error(SOURCE, e.msg).at_line(e.line)] })?;
+        let mut c = Compiler { doc: &doc, errors: ErrorReport::new() };
+        let spec = c.spec();
+        c.errors.into_result(spec)
+    }
+}
+
+struct Compiler<'a> {
+    doc: &'a Value,
+    errors: ErrorReport,
+}
+
+impl<'a> Compiler<'a> {
+    fn error(&mut self, line: usize, msg: impl Into<String>) {
+        self.errors.push(Issue::error(SOURCE, msg).at_line(line));
+    }
+
+    fn spec(&mut self) -> Spec {
+        let mut spec = Spec::default();
+        match self.doc.get("openapi").and_then(Value::as_str) {
+            Some(v) if v.starts_with("3.") => {}
+            _ => self.error(1, "not an OpenAPI 3.x document (\"openapi\": \"3.x.y\")"),
+        }
+        if let Some(Value::Obj(schemas)) = self.doc.get("components").and_then(|c| c.get("schemas")) {
+            for (name, line, s) in schemas {
+                let s = self.schema(*line, s);
+                spec.schemas.insert(name.clone(), s);
+            }
+        }
+        let Some(Value::Obj(paths)) = self.doc.get("paths") else {
+            self.error(1, "\"paths\" must be an object");
+            return spec;
+        };
+        for (template, line, item) in paths {
+            if let Some(p) = self.path_item(template, *line, item) {
+                spec.paths.push(p);
+            }
+        }
+        for (name, line) in refs_in(self.doc) {
+            if !spec.schemas.contains_key(&name) {
+                self.error(line, format!("$ref to undefined schema \"{}\"", name));
+            }
+        }
+        spec
+    }
+
+    fn path_item(&mut self, template: &str, line: usize, item: &'a Value) -> Option<PathItem> {
+        if !template.starts_with('/') {
+            self.error(line, format!("path \"{}\" must start with '/'", template));
+            return None;
+        }
+        let mut segments = Vec::new();
+        let mut names = Vec::new();
+        for seg in template[1..].split('/') {
+            match seg.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                Some(name) => {
+                    names.push(name.to_string());
+                    segments.push(None);
+                }
+                None => segments.push(Some(seg.to_string())),
+            }
+        }
+        let shared = self.params(item.get("parameters"), line);
+        let mut ops = Vec::new();
+        let Value::Obj(members) = item else {
+            self.error(line, format!("path \"{}\" must be an object", template));
+            return None;
+        };
+        for (method, mline, op) in members {
+            if !METHODS.contains(&method.as_str()) {
+                continue; // summary, parameters, servers, x-...
+            }
+            let mut params = shared.clone();
+            for p in self.params(op.get("parameters"), *mline) {
+                params.retain(|q: &Param| !(q.name == p.name && q.location == p.location));
+                params.push(p);
+            }
+            for name in names.iter() {
+                if !params.iter().any(|p| p.location == In::Path && &p.name == name) {
+                    // undeclared path params still match any segment
+                    params.push(Param { name: name.clone(), location: In::Path, required: true, schema: Schema::default() });
+                }
+            }
+            let id = op.get("operationId").and_then(Value::as_str).map(str::to_string);
+            let id = id.unwrap_or_else(|| format!("{} {}", method.to_uppercase(), template));
+            let body = op.get("requestBody").map(|b| self.body(*mline, b));
+            ops.push((method.to_uppercase(), Operation { id, params, body }));
+        }
+        Some(PathItem { segments, names, ops })
+    }
+
+    fn params(&mut self, list: Option<&'a Value>, line: usize) -> Vec<Param> {
+        let mut out = Vec::new();
+        let Some(list) = list else { return out };
+        let Value::Arr(items) = list else {
+            self.error(line, "\"parameters\" must be an array");
+            return out;
+        };
+        for (pline, p) in items {
+            let p = self.resolve(p, "parameters", *pline);
+            let name = p.get("name").and_then(Value::as_str);
+            let location = match p.get("in").and_then(Value::as_str) {
+                Some("path") => In::Path,
+                Some("query") => In::Query,
+                Some("header") => In::Header,
+                Some("cookie") => continue, // not validated
+                _ => {
+                    self.error(*pline, "parameter \"in\" must be path, query, header or cookie");
+                    continue;
+                }
+            };
+            let Some(name) = name else {
+                self.error(*pline, "parameter without a \"name\"");
+                continue;
+            };
+            let required = location == In::Path || p.get("required").and_then(Value::as_bool).unwrap_or(false);
+            let schema = p.get("schema").map(|s| self.schema(*pline, s)).unwrap_or_default();
+            out.push(Param { name: name.to_string(), location, required, schema });
+        }
+        out
+    }
+
+    fn body(&mut self, line: usize, b: &'a Value) -> Body {
+        let b = self.resolve(b, "requestBodies", line);
+        let required = b.get("required").and_then(Value::as_bool).unwrap_or(false);
+        let mut content = Vec::new();
+        match b.get("content") {
+            Some(Value::Obj(media)) => {
+                for (range, mline, m) in media {
+                    let schema = m.get("schema").map(|s| self.schema(*mline, s));
+                    content.push((range.to_ascii_lowercase(), schema));
+                }
+            }
+            _ => self.error(line, "requestBody needs a \"content\" object"),
+        }
+        Body { required, content }
+    }
+
+    // Follows a `$ref` into components/<section>; anything else as is.
+    fn resolve(&mut self, v: &'a Value, section: &str, line: usize) -> &'a Value {
+        let Some(r) = v.get("$ref").and_then(Value::as_str) else { return v };
+        let name = r.strip_prefix("#/components/").and_then(|r| r.strip_prefix(section)).and_then(|r| r.strip_prefix('/'));
+        match name.and_then(|n| self.doc.get("components").and_then(|c| c.get(section)).and_then(|s| s.get(n))) {
+            Some(target) => target,
+            None => {
+                self.error(line, format!("unresolvable $ref \"{}\"", r));
+                v
+            }
+        }
+    }
+
+    fn schema(&mut self, line: usize, v: &Value) -> Schema {
+        let mut s = Schema::default();
+        if let Some(r) = v.get("$ref").and_then(Value::as_str) {
+            match r.strip_prefix("#/components/schemas/") {
+                Some(name) => s.reference = Some(name.to_string()),
+                None => self.error(line, format!("only local schema $refs are supported, not \"{}\"", r)),
+            }
+            return s;
+        }
+        let Value::Obj(members) = v else {
+            self.error(line, "schema must be an object");
+            return s;
+        };
+        for (k, kline, val) in members {
+            let count = |v: &Value| v.as_f64().filter(|n| *n >= 0.0 && n.fract() == 0.0).map(|n| n as usize);
+            match (k.as_str(), val) {
+                ("type", Value::Str(t)) => s.types = vec![t.clone()],
+                ("type", Value::Arr(ts)) => s.types = ts.iter().filter_map(|(_, t)| t.as_str().map(str::to_string)).collect(),
+                ("nullable", Value::Bool(b)) => s.nullable = *b,
+                ("enum", Value::Arr(vs)) => s.enumeration = vs.iter().map(|(_, v)| v.clone()).collect(),
+                ("minimum", Value::Num(n)) => s.minimum = Some(*n),
+                ("maximum", Value::Num(n)) => s.maximum = Some(*n),
+                ("minLength", v) => s.min_length = count(v),
+                ("maxLength", v) => s.max_length = count(v),
+                ("minItems", v) => s.min_items = count(v),
+                ("maxItems", v) => s.max_items = count(v),
+                ("items", v) => s.items = Some(Box::new(self.schema(*kline, v))),
+                ("properties", Value::Obj(props)) => {
+                    for (name, pline, p) in props {
+                        let p = self.schema(*pline, p);
+                        s.properties.push((name.clone(), p));
+                    }
+                }
+                ("required", Value::Arr(names)) => s.required = names.iter().filter_map(|(_, n)| n.as_str().map(str::to_string)).collect(),
+                ("additionalProperties", Value::Bool(b)) => s.closed = !*b,
+                ("additionalProperties", v @ Value::Obj(_)) => s.additional = Some(Box::new(self.schema(*kline, v))),
+                ("allOf", Value::Arr(parts)) => s.all_of = parts.iter().map(|(pline, p)| self.schema(*pline, p)).collect(),
+                ("type" | "nullable" | "enum" | "minimum" | "maximum" | "properties" | "required" | "additionalProperties" | "allOf", _) => {
+                    self.error(*kline, format!("schema \"{}\" has the wrong type", k));
+                }
+                _ => {} // description, format, example, oneOf, ...
+            }
+        }
+        if let Some(t) = s.types.iter().find(|t| !matches!(t.as_str(), "string" | "integer" | "number" | "boolean" | "array" | "object" | "null")) {
+            self.error(line, format!("unknown schema type \"{}\"", t));
+        }
+        s
+    }
+}
+
+// Every `#/components/schemas/<name>` $ref in the document, with its line.
+fn refs_in(v: &Value) -> Vec<(String, usize)> {
+    let mut out = Vec::new();
+    match v {
+        Value::Obj(kv) => {
+            for (k, l, v) in kv {
+                match (k.as_str(), v) {
+                    ("$ref", Value::Str(r)) => {
+                        if let Some(name) = r.strip_prefix("#/components/schemas/") {
+                            out.push((name.to_string(), *l));
+                        }
+                    }
+                    _ => out.extend(refs_in(v)),
+                }
+            }
+        }
+        Value::Arr(items) => {
+            for (_, v) in items {
+                out.extend(refs_in(v));
+            }
+        }
+        _ => {}
+    }
+    out
+}
+
+pub struct OpenApiFilter {
+    meta: PluginMeta,
+    spec: Spec,
+    metrics: Metrics,
+}
+
+impl Default for OpenApiFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenApiFilter {
+    pub fn new() -> Self {
+        Self {
+            meta: PluginMeta { name: "openapi_validator", version: "1.0.0", author: "OverLab", flags: 0, caps: caps::READ_BODY },
+            spec: Spec::default(),
+            metrics: default_registry().clone(),
+        }
+    }
+
+    pub fn with_spec(mut self, spec: Spec) -> Self {
+        self.spec = spec;
+        self
+    }
+
+    // Record into `metrics` instead of the process-wide registry.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    fn count(&self, name: &str, labels: &[(&str, &str)]) {
+        if let Ok(c) = self.metrics.counter(name, labels) {
+            c.inc();
+        }
+    }
+
+    fn reject(&self, req: &Request, status: u16, op: &str, reason: &str, violations: Vec<Violation>) -> Response {
+        self.count(FAILURES, &[("operation", op), ("reason", reason), ("tenant", req.tenant)]);
+        let total = violations.len();
+        let details: Vec<Json> = violations
+            .into_iter()
+            .take(MAX_VIOLATIONS)
+            .map(|v| Json::obj().set("in", v.location).set("name", v.name).set("message", v.message))
+            .collect();
+        let error = Json::obj()
+            .set("code", "validation_failed")
+            .set("message", "request does not match the API description")
+            .set("operation", op)
+            .set("violations", total)
+            .set("details", Json::Arr(details));
+        json_status(status, &Json::obj().set("error", error))
+    }
+
+    fn check_params(&self, req: &Request, op: &Operation, path_args: &HashMap<&str, String>, query: &[(String, String)], out: &mut Vec<Violation>) {
+        for p in op.params.iter() {
+            let values: Vec<String> = match p.location {
+                In::Path => path_args.get(p.name.as_str()).cloned().into_iter().collect(),
+                In::Query => query.iter().filter(|(k, _)| *k == p.name).map(|(_, v)| v.clone()).collect(),
+                In::Header => header(req, &p.name).map(str::to_string).into_iter().collect(),
+            };
+            if values.is_empty() {
+                if p.required {
+                    out.push(Violation { location: p.location.name(), name: p.name.clone(), message: "required parameter is missing".to_string() });
+                }
+                continue;
+            }
+            let value = self.coerce(&p.schema, &values);
+            self.check(&p.schema, &value, p.location.name(), &p.name, 0, out);
+        }
+    }
+
+    // Parameter text as the JSON value its schema describes; what does not
+    // parse stays a string and fails the type check.
+    fn coerce(&self, s: &Schema, values: &[String]) -> Value {
+        let s = self.deref(s);
+        if s.types.iter().any(|t| t == "array") {
+            let parts: Vec<&str> = if values.len() > 1 { values.iter().map(String::as_str).collect() } else { values[0].split(',').collect() };
+            let item = s.items.as_deref().cloned().unwrap_or_default();
+            return Value::Arr(parts.iter().map(|v| (0, self.scalar(&item, v))).collect());
+        }
+        self.scalar(s, &values[0])
+    }
+
+    fn scalar(&self, s: &Schema, v: &str) -> Value {
+        let s = self.deref(s);
+        let wants = |t: &str| s.types.iter().any(|x| x == t);
+        if let (true, Ok(n)) = (wants("integer") || wants("number"), v.parse::<f64>()) {
+            return Value::Num(n);
+        }
+        if wants("boolean") && (v == "true" || v == "false") {
+            return Value::Bool(v == "true");
+        }
+        Value::Str(v.to_string())
+    }
+
+    fn deref<'s>(&'s self, mut s: &'s Schema) -> &'s Schema {
+        for _ in 0..MAX_DEPTH {
+            match s.reference.as_ref().and_then(|r| self.spec.schemas.get(r)) {
+                Some(target) => s = target,
+                None => break,
+            }
+        }
+        s
+    }
+
+    fn check(&self, s: &Schema, v: &Value, location: &'static str, at: &str, depth: usize, out: &mut Vec<Violation>) {
+        if depth > MAX_DEPTH || out.len() >= MAX_VIOLATIONS {
+            return;
+        }
+        let s = self.deref(s);
+        let mut fail = |message: String| out.push(Violation { location, name: at.to_string(), message });
+        if matches!(v, Value::Null) && (s.nullable || s.types.iter().any(|t| t == "null")) {
+            return;
+        }
+        let actual = v.type_name();
+        let type_ok = s.types.is_empty() || s.types.iter().any(|t| t == actual || (t == "number" && actual == "integer"));
+        if !type_ok {
+            fail(format!("expected {}, got {}", s.types.join(" or "), actual));
+            return;
+        }
+        if !s.enumeration.is_empty() && !s.enumeration.iter().any(|e| same_value(e, v)) {
+            fail("value is not one of the allowed values".to_string());
+        }
+        match v {
+            Value::Num(n) if s.minimum.is_some_and(|m| *n < m) || s.maximum.is_some_and(|m| *n > m) => {
+                fail(format!("{} is out of range", n));
+            }
+            Value::Str(t) => {
+                let len = t.chars().count();
+                if s.min_length.is_some_and(|m| len < m) || s.max_length.is_some_and(|m| len > m) {
+                    fail(format!("length {} is out of range", len));
+                }
+            }
+            Value::Arr(items) => {
+                if s.min_items.is_some_and(|m| items.len() < m) || s.max_items.is_some_and(|m| items.len() > m) {
+                    fail(format!("{} items is out of range", items.len()));
+                }
+                if let Some(item) = s.items.as_deref() {
+                    for (i, (_, v)) in items.iter().enumerate() {
+                        self.check(item, v, location, &format!("{}/{}", at, i), depth + 1, out);
+                    }
+                }
+            }
+            Value::Obj(members) => {
+                for name in s.required.iter() {
+                    if !members.iter().any(|(k, _, _)| k == name) {
+                        out.push(Violation { location, name: format!("{}/{}", at, name), message: "required property is missing".to_string() });
+                    }
+                }
+                for (k, _, v) in members {
+                    let at = format!("{}/{}", at, k);
+                    match (s.properties.iter().find(|(name, _)| name == k), &s.additional) {
+                        (Some((_, p)), _) => self.check(p, v, location, &at, depth + 1, out),
+                        (None, Some(extra)) => self.check(extra, v, location, &at, depth + 1, out),
+                        (None, None) if s.closed => out.push(Violation { location, name: at, message: "property is not allowed".to_string() }),
+                        (None, None) => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        for part in s.all_of.iter() {
+            self.check(part, v, location, at, depth + 1, out);
+        }
+    }
+
+    fn check_body(&self, req: &Request, op: &Operation, out: &mut Vec<Violation>) -> Option<u16> {
+        let Some(body) = &op.body else {
+            if !req.body.is_empty() {
+                out.push(Violation { location: "body", name: String::new(), message: "operation takes no request body".to_string() });
+            }
+            return None;
+        };
+        if req.body.is_empty() {
+            if body.required {
+                out.push(Violation { location: "body", name: String::new(), message: "request body is required".to_string() });
+            }
+            return None;
+        }
+        let media = header(req, "content-type").unwrap_or("").split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        let Some((_, schema)) = body.content.iter().find(|(range, _)| media_matches(range, &media)) else {
+            let allowed: Vec<&str> = body.content.iter().map(|(r, _)| r.as_str()).collect();
+            out.push(Violation { location: "header", name: "content-type".to_string(), message: format!("expected {}", allowed.join(" or ")) });
+            return Some(415);
+        };
+        let json = media == "application/json" || media.ends_with("+json");
+        if let (Some(schema), true) = (schema, json) {
+            match std::str::from_utf8(&req.body).ok().map(json_parse) {
+                Some(Ok(v)) => self.check(schema, &v, "body", "", 0, out),
+                _ => out.push(Violation { location: "body", name: String::new(), message: "body is not valid JSON".to_string() }),
+            }
+        }
+        None
+    }
+}
+
+impl FilterPlugin for OpenApiFilter {
+    fn meta(&self) -> PluginMeta {
+        self.meta.clone()
+    }
+
+    fn init(&mut self, cfg: &HashMap<String, String>) -> Result<(), String> {
+        let path = cfg.get("spec").ok_or("missing \"spec\" (path to an OpenAPI 3.x JSON document)")?;
+        let src = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.spec = Spec::parse(&src).map_err(|r| r.to_string())?;
+        Ok(())
+    }
+
+    fn process(&self, req: &Request) -> FilterVerdict {
+        let path = canonical(req.path);
+        let segments: Vec<&str> = path[1..].split('/').collect();
+        let Some(item) = self.spec.paths.iter().filter(|p| template_matches(p, &segments)).max_by_key(|p| specificity(p)) else {
+            let v = Violation { location: "path", name: path.clone(), message: "no such path".to_string() };
+            return FilterVerdict::ShortCircuit(self.reject(req, 404, "_unmatched", "path", vec![v]));
+        };
+        let method = req.method.to_ascii_uppercase();
+        let Some((_, op)) = item.ops.iter().find(|(m, _)| *m == method || (method == "HEAD" && m == "GET")) else {
+            let allowed: Vec<&str> = item.ops.iter().map(|(m, _)| m.as_str()).collect();
+            let v = Violation { location: "method", name: method.clone(), message: format!("expected {}", allowed.join(", ")) };
+            let mut r = self.reject(req, 405, "_unmatched", "method", vec![v]);
+            add_header(&mut r, "Allow", &allowed.join(", "));
+            return FilterVerdict::ShortCircuit(r);
+        };
+
+        let params = segments.iter().zip(item.segments.iter()).filter(|(_, lit)| lit.is_none()).map(|(seg, _)| seg.to_string());
+        let path_args: HashMap<&str, String> = item.names.iter().map(String::as_str).zip(params).collect();
+        let query: Vec<(String, String)> = match req.path.split_once('?') {
+            Some((_, q)) => q
+                .split('&')
+                .filter(|p| !p.is_empty())
+                .map(|p| p.split_once('=').unwrap_or((p, "")))
+                .map(|(k, v)| (percent_decode(&k.replace('+', " ")), percent_decode(&v.replace('+', " "))))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let mut violations = Vec::new();
+        self.check_params(req, op, &path_args, &query, &mut violations);
+        let params_failed = !violations.is_empty();
+        let status = self.check_body(req, op, &mut violations);
+        if let Some(status) = status {
+            return FilterVerdict::ShortCircuit(self.reject(req, status, &op.id, "content_type", violations));
+        }
+        if !violations.is_empty() {
+            let reason = if params_failed { "parameter" } else { "body" };
+            return FilterVerdict::ShortCircuit(self.reject(req, 400, &op.id, reason, violations));
+        }
+        self.count(VALIDATED, &[("operation", &op.id), ("tenant", req.tenant)]);
+        FilterVerdict::Continue
+    }
+}
+
+fn template_matches(p: &PathItem, segments: &[&str]) -> bool {
+    p.segments.len() == segments.len()
+        && p.segments.iter().zip(segments.iter()).all(|(lit, seg)| match lit {
+            Some(l) => l == seg,
+            None => !seg.is_empty(),
+        })
+}
+
+// Literal segments, earliest first, beat parameters: /users/me over /users/{id}.
+fn specificity(p: &PathItem) -> Vec<bool> {
+    p.segments.iter().map(Option::is_some).collect()
+}
+
+fn media_matches(range: &str, media: &str) -> bool {
+    match range.split_once('/') {
+        Some(("*", "*")) => true,
+        Some((ty, "*")) => media.split_once('/').is_some_and(|(t, _)| t == ty),
+        _ => range == media,
+    }
+}
+
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Num(x), Value::Num(y)) => x == y,
+        (Value::Str(x), Value::Str(y)) => x == y,
+        (Value::Bool(x), Value::Bool(y)) => x == y,
+        (Value::Null, Value::Null) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r##"{
+      "openapi": "3.0.3",
+      "paths": {
+        "/users/{id}": {
+          "parameters": [{"name": "id", "in": "path", "required": true, "schema": {"type": "integer", "minimum": 1}}],
+          "get": {"operationId": "getUser"}
+        },
+        "/users/me": {"get": {"operationId": "getMe"}},
+        "/users": {
+          "get": {
+            "operationId": "listUsers",
+            "parameters": [
+              {"name": "limit", "in": "query", "schema": {"type": "integer", "maximum": 100}},
+              {"name": "role", "in": "query", "schema": {"type": "array", "items": {"type": "string", "enum": ["admin", "user"]}}},
+              {"name": "X-Tenant", "in": "header", "required": true, "schema": {"type": "string"}}
+            ]
+          },
+          "post": {
+            "operationId": "createUser",
+            "requestBody": {"required": true, "content": {"application/json": {"schema": {"$ref": "#/components/schemas/User"}}}}
+          }
+        }
+      },
+      "components": {
+        "schemas": {
+          "User": {
+            "type": "object",
+            "required": ["name"],
+            "additionalProperties": false,
+            "properties": {
+              "name": {"type": "string", "minLength": 1},
+              "age": {"type": "integer", "minimum": 0, "nullable": true},
+              "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 2}
+            }
+          }
+        }
+      }
+    }"##;
+
+    fn req(method: &'static str, path: &'static str, headers: &[(&str, &str)], body: &str) -> Request {
+        let headers = headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Request { method, path, headers, body: body.as_bytes().to_vec(), tenant: "t1" }
+    }
+
+    fn status(v: FilterVerdict) -> u16 {
+        match v {
+            FilterVerdict::Continue => 0,
+            FilterVerdict::ShortCircuit(r) => r.status,
+            FilterVerdict::Mutate(_) => panic!("validation never mutates"),
+        }
+    }
+
+    #[test]
+    fn operations_and_parameters() {
+        let f = OpenApiFilter::new().with_spec(Spec::parse(SPEC).unwrap()).with_metrics(Metrics::new());
+        assert_eq!(status(f.process(&req("GET", "/users/42", &[], ""))), 0);
+        assert_eq!(status(f.process(&req("GET", "/users/me", &[], ""))), 0, "literal segment beats {{id}}");
+        assert_eq!(status(f.process(&req("GET", "/users/0", &[], ""))), 400);
+        assert_eq!(status(f.process(&req("GET", "/users/abc", &[], ""))), 400);
+        assert_eq!(status(f.process(&req("GET", "/orders", &[], ""))), 404);
+        match f.process(&req("DELETE", "/users/42", &[], "")) {
+            FilterVerdict::ShortCircuit(r) => {
+                assert_eq!(r.status, 405);
+                assert!(r.headers.contains(&("Allow".to_string(), "GET".to_string())));
+            }
+            _ => panic!("expected 405"),
+        }
+
+        let tenant = [("X-Tenant", "acme")];
+        assert_eq!(status(f.process(&req("GET", "/users?limit=10&role=admin,user", &tenant, ""))), 0);
+        assert_eq!(status(f.process(&req("GET", "/users?limit=10", &[], ""))), 400, "required header");
+        assert_eq!(status(f.process(&req("GET", "/users?limit=500", &tenant, ""))), 400);
+        assert_eq!(status(f.process(&req("GET", "/users?role=root", &tenant, ""))), 400);
+    }
+
+    #[test]
+    fn request_bodies() {
+        let metrics = Metrics::new();
+        let f = OpenApiFilter::new().with_spec(Spec::parse(SPEC).unwrap()).with_metrics(metrics.clone());
+        let json = [("Content-Type", "application/json; charset=utf-8")];
+        assert_eq!(status(f.process(&req("POST", "/users", &json, r#"{"name": "ann", "age": null, "tags": ["a"]}"#))), 0);
+        assert_eq!(status(f.process(&req("POST", "/users", &json, ""))), 400, "body required");
+        assert_eq!(status(f.process(&req("POST", "/users", &[("Content-Type", "text/plain")], "ann"))), 415);
+        assert_eq!(status(f.process(&req("POST", "/users", &json, "{name: ann}"))), 400);
+        assert_eq!(status(f.process(&req("GET", "/users/1", &json, "{}"))), 400, "no body declared");
+
+        let bad = r#"{"age": -1, "tags": ["a", 2, "c"], "admin": true}"#;
+        let FilterVerdict::ShortCircuit(r) = f.process(&req("POST", "/users", &json, bad)) else { panic!("expected 400") };
+        let body = String::from_utf8(r.body).unwrap();
+        let doc = json_parse(&body).unwrap();
+        let error = doc.get("error").unwrap();
+        assert_eq!(error.get("operation").and_then(Value::as_str), Some("createUser"));
+        let Some(Value::Arr(details)) = error.get("details") else { panic!("details: {}", body) };
+        let names: Vec<&str> = details.iter().filter_map(|(_, d)| d.get("name").and_then(Value::as_str)).collect();
+        assert_eq!(names, ["/name", "/age", "/tags", "/tags/1", "/admin"], "{}", body);
+
+        let failures = |op, reason| metrics.counter(FAILURES, &[("operation", op), ("reason", reason), ("tenant", "t1")]).unwrap().get();
+        assert_eq!((failures("createUser", "body"), failures("createUser", "content_type")), (3, 1));
+        assert_eq!(metrics.counter(VALIDATED, &[("operation", "createUser"), ("tenant", "t1")]).unwrap().get(), 1);
+    }
+
+    #[test]
+    fn spec_errors_are_reported_with_lines() {
+        let src = "{\n \"openapi\": \"2.0\",\n \"paths\": {\n  \"/a\": {\"get\": {\"requestBody\": {\"content\": {\"application/json\":\n   {\"schema\": {\"$ref\": \"#/components/schemas/Missing\"}}}}}},\n  \"b\": {}\n }\n}";
+        let e = Spec::parse(src).unwrap_err();
+        let got: Vec<(Option<usize>, &str)> = e.issues.iter().map(|i| (i.line, i.message.as_str())).collect();
+        assert_eq!(
+            got,
+            vec![
+                (Some(1), "not an OpenAPI 3.x document (\"openapi\": \"3.x.y\")"),
+                (Some(6), "path \"b\" must start with '/'"),
+                (Some(5), "$ref to undefined schema \"Missing\""),
+            ]
+        );
+        assert!(Spec::parse("{\"openapi\": \"3.1.0\", \"paths\": {}").unwrap_err().issues[0].line.is_some());
+    }
+}
@@ -0,0 +1,776 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/pipeline.rs
+// Role: Per-route pipeline builder and dispatch table (programmatic config)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Fluent builder: route -> acl -> waf ruleset -> filters -> rate limit -> handler.
+// - A pipeline cannot be built without a handler (typestate, checked by rustc).
+// - Compile pipelines into a dispatch table, validating keys against Registry.
+// - Optional expressions (expr.rs): route condition, per-filter guards (e.g.
+//   only rewrite when ...), and a computed rate-limit key.
+// - Execution: ACL, rate limit and WAF ruleset (named in Policies) before any
+//   plugin runs, then filters in order (guards honored, Mutate feeds the next
+//   stage, ShortCircuit answers), then the handler; each plugin stage is timed
+//   (in microseconds) into the pipeline_stage_latency_ms histogram.
+// - Header transforms (header_transform.rs, named in Policies): request ops
+//   after the filters, response ops on filter and handler responses.
+// - One execution path: probes, the test server and the host all run
+//   pipelines through `execute`/`execute_with`.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use crate::expr::Expr;
+use crate::header_transform::HeaderTransforms;
+use olwsx_observability::{Registry as Metrics, STAGE_LATENCY};
+use olwsx_plugins_sdk::{add_header, client_cert, header, json_error, set_body, throttle, FilterVerdict, HandlerResult, Registry, Request, Response};
+use olwsx_security::{
+    canonical, Acl, AclVerdict, Action, Admission, ChallengeVerifier, ClientAddr, Decision, Engine, RateKey, RateLimiter,
+    RequestView, CHALLENGE_HEADER,
+};
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+mod olwsx_plugins_sdk {
+    pub use crate::sdk::{add_header, client_cert, header, json_error, set_body, throttle, FilterVerdict, HandlerResult, Registry, Request, Response};
+}
+
+mod olwsx_security {
+    pub use crate::acl::{Acl, Verdict as AclVerdict};
+    pub use crate::addr::ClientAddr;
+    pub use crate::path::{canonical, route_matches};
+    pub use crate::challenge::{Admission, ChallengeVerifier, CHALLENGE_HEADER};
+    pub use crate::ratelimit::{RateKey, RateLimiter};
+    pub use crate::waf::{Action, Decision, Engine, RequestView};
+}
+
+mod olwsx_observability {
+    pub use crate::registry::Registry;
+    pub use crate::tenant_view::STAGE_LATENCY;
+}
+
+// Compiled route description; produced only by PipelineBuilder::handler.
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+    pub route: String,
+    pub acl: Option<String>,
+    pub waf: Option<String>,
+    pub filters: Vec<&'static str>,
+    pub rate_limit: Option<String>,
+    pub handler: &'static str,
+    pub condition: Option<Expr>,
+    pub guards: Vec<(&'static str, Expr)>, // filter key -> runs only when true
+    pub rate_limit_key: Option<Expr>,
+    pub headers: Option<String>, // header transform set (Policies::headers)
+}
+
+// How a pipeline run ended.
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    Handled(HandlerResult),
+    ShortCircuit(&'static str, Response), // filter key that answered, "acl", "rate_limit", "waf", or an execute_with stage
+    NoHandler,                            // handler key not registered
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StageTiming {
+    pub stage: &'static str, // "filter" or "handler"
+    pub key: &'static str,
+    pub elapsed: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct Execution {
+    pub outcome: Outcome,
+    pub request: Request, // as the last stage saw it, mutations applied
+    pub stages: Vec<StageTiming>,
+    pub waf: Option<Decision>, // when the pipeline names a ruleset
+}
+
+// The ACLs, WAF rulesets, header transforms and rate limiter that
+// Pipeline::acl/waf/headers/rate_limit refer to by name, and the verifier
+// that answers WAF Challenge decisions (without one, Challenge blocks like
+// Deny).
+#[derive(Default)]
+pub struct Policies {
+    acls: HashMap<String, Acl>,
+    wafs: HashMap<String, Engine>,
+    headers: HashMap<String, HeaderTransforms>,
+    limiter: Option<RateLimiter>,
+    challenge: Option<ChallengeVerifier>,
+}
+
+impl Policies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn acl(mut self, name: &str, acl: Acl) -> Self {
+        self.acls.insert(name.to_string(), acl);
+        self
+    }
+
+    pub fn waf(mut self, name: &str, engine: Engine) -> Self {
+        self.wafs.insert(name.to_string(), engine);
+        self
+    }
+
+    pub fn headers(mut self, name: &str, transforms: HeaderTransforms) -> Self {
+        self.headers.insert(name.to_string(), transforms);
+        self
+    }
+
+    // Applied to pipelines that set rate_limit(..).
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    pub fn challenge(mut self, verifier: ChallengeVerifier) -> Self {
+        self.challenge = Some(verifier);
+        self
+    }
+
+    // Every policy `p` names is provided.
+    pub fn check(&self, p: &Pipeline) -> Result<(), String> {
+        if let Some(a) = p.acl.as_ref().filter(|a| !self.acls.contains_key(a.as_str())) {
+            return Err(format!("route '{}': acl '{}' not provided", p.route, a));
+        }
+        if let Some(w) = p.waf.as_ref().filter(|w| !self.wafs.contains_key(w.as_str())) {
+            return Err(format!("route '{}': waf rule set '{}' not provided", p.route, w));
+        }
+        if let Some(h) = p.headers.as_ref().filter(|h| !self.headers.contains_key(h.as_str())) {
+            return Err(format!("route '{}': header transforms '{}' not provided", p.route, h));
+        }
+        if p.rate_limit.is_some() && self.limiter.is_none() {
+            return Err(format!("route '{}': rate limit set but no rate limiter provided", p.route));
+        }
+        Ok(())
+    }
+}
+
+// Builder without a handler yet; there is deliberately no `build()`.
+#[derive(Clone, Debug)]
+pub struct PipelineBuilder {
+    route: String,
+    acl: Option<String>,
+    waf: Option<String>,
+    filters: Vec<&'static str>,
+    rate_limit: Option<String>,
+    condition: Option<Expr>,
+    guards: Vec<(&'static str, Expr)>,
+    rate_limit_key: Option<Expr>,
+    headers: Option<String>,
+}
+
+impl Pipeline {
+    // Route pattern: exact path, or a prefix ending in `/*`.
+    pub fn for_route(route: &str) -> PipelineBuilder {
+        PipelineBuilder {
+            route: route.to_string(),
+            acl: None,
+            waf: None,
+            filters: Vec::new(),
+            rate_limit: None,
+            condition: None,
+            guards: Vec::new(),
+            rate_limit_key: None,
+            headers: None,
+        }
+    }
+
+    // `path` is a raw request target; it is canonicalized before matching.
+    pub fn matches(&self, path: &str) -> bool {
+        route_matches(&self.route, &canonical(path))
+    }
+
+    // Route pattern plus condition, if any.
+    pub fn matches_request(&self, req: &Request) -> bool {
+        self.matches_canonical(&canonical(req.path), req)
+    }
+
+    fn matches_canonical(&self, path: &str, req: &Request) -> bool {
+        route_matches(&self.route, path) && self.condition.as_ref().is_none_or(|c| c.matches(req))
+    }
+
+    pub fn filter_enabled(&self, key: &str, req: &Request) -> bool {
+        self.guards.iter().filter(|(k, _)| *k == key).all(|(_, g)| g.matches(req))
+    }
+
+    // Computed key when configured, else None (limit by the scope default).
+    pub fn limit_key(&self, req: &Request) -> Option<String> {
+        self.rate_limit_key.as_ref().map(|k| k.eval(req).into_string())
+    }
+
+    // Guards name pipeline filters; filter and handler keys are registered.
+    pub fn validate(&self, reg: &Registry) -> Result<(), String> {
+        for (g, _) in self.guards.iter() {
+            if !self.filters.contains(g) {
+                return Err(format!("route '{}': guard for filter '{}' which is not in the pipeline", self.route, g));
+            }
+        }
+        for f in self.filters.iter() {
+            if !reg.has_filter(f) {
+                return Err(format!("route '{}': filter key '{}' not registered", self.route, f));
+            }
+        }
+        if !reg.has_handler(self.handler) {
+            return Err(format!("route '{}': handler key '{}' not registered", self.route, self.handler));
+        }
+        Ok(())
+    }
+
+    // Runs the chain on `req` from client `ip`: ACL, rate limit and WAF first
+    // (a policy the pipeline names but `policies` lacks fails closed), then
+    // filters and handler. Filters whose guard fails are skipped and not
+    // timed. With `metrics`, every plugin stage is observed in STAGE_LATENCY
+    // under tenant, route, stage and key labels.
+    pub fn execute(&self, reg: &Registry, policies: &Policies, ip: &str, req: Request, metrics: Option<&Metrics>) -> Execution {
+        self.execute_with(reg, policies, ip, req, metrics, |_| None)
+    }
+
+    // As execute, with `before_handler` called once the filters have passed,
+    // on the request the handler would see. When it answers (a response
+    // cache hit, say), that is the outcome under the stage name it gives and
+    // the handler does not run.
+    pub fn execute_with(
+        &self,
+        reg: &Registry,
+        policies: &Policies,
+        ip: &str,
+        req: Request,
+        metrics: Option<&Metrics>,
+        before_handler: impl FnOnce(&Request) -> Option<(&'static str, Response)>,
+    ) -> Execution {
+        let mut run = Execution { outcome: Outcome::NoHandler, request: req, stages: Vec::new(), waf: None };
+        if let Some(denied) = self.enforce(policies, ip, &mut run) {
+            run.outcome = denied;
+            return run;
+        }
+        let transforms = self.headers.as_ref().and_then(|n| policies.headers.get(n));
+        let request_id = header(&run.request, "x-request-id").unwrap_or("").to_string();
+        let respond = |req: &Request, resp: &mut Response| {
+            if let Some(t) = transforms {
+                t.apply_response(req, resp, ip, &request_id);
+            }
+        };
+        for &f in self.filters.iter() {
+            if !self.filter_enabled(f, &run.request) {
+                continue;
+            }
+            let started = Instant::now();
+            let verdict = reg.filter(f, &run.request);
+            self.timed(&mut run, metrics, "filter", f, started);
+            match verdict {
+                FilterVerdict::Continue => {}
+                FilterVerdict::Mutate(m) => run.request = m,
+                FilterVerdict::ShortCircuit(mut resp) => {
+                    respond(&run.request, &mut resp);
+                    run.outcome = Outcome::ShortCircuit(f, resp);
+                    return run;
+                }
+            }
+        }
+        // response variables refer to the request as the filters left it
+        let received = transforms.map(|t| {
+            let received = run.request.clone();
+            t.apply_request(&mut run.request, ip, &request_id);
+            received
+        });
+        // answers from here (a cache hit) went through the transforms already
+        if let Some((stage, resp)) = before_handler(&run.request) {
+            run.outcome = Outcome::ShortCircuit(stage, resp);
+            return run;
+        }
+        let started = Instant::now();
+        if let Some(mut result) = reg.handle(self.handler, &run.request) {
+            self.timed(&mut run, metrics, "handler", self.handler, started);
+            respond(received.as_ref().unwrap_or(&run.request), &mut result.resp);
+            if let Some(Decision { action: Action::Throttle(rate), .. }) = &run.waf {
+                throttle(&mut result.resp, *rate);
+            }
+            run.outcome = Outcome::Handled(result);
+        }
+        run
+    }
+
+    // ACL -> rate limit -> WAF; the first that refuses answers the request.
+    // Named header transforms that are missing fail closed like the rest.
+    fn enforce(&self, policies: &Policies, ip: &str, run: &mut Execution) -> Option<Outcome> {
+        let req = &run.request;
+        if let Some(name) = self.headers.as_ref().filter(|n| !policies.headers.contains_key(n.as_str())) {
+            return Some(Outcome::ShortCircuit("headers", json_error(500, "policy_missing", &format!("header transforms '{}' not provided", name))));
+        }
+        if let Some(name) = &self.acl {
+            let allowed = match (policies.acls.get(name), ClientAddr::parse(ip)) {
+                (Some(acl), Some(addr)) => acl.check(addr, req.method) == AclVerdict::Allow,
+                _ => false,
+            };
+            if !allowed {
+                return Some(Outcome::ShortCircuit("acl", json_error(403, "forbidden", "denied by access list")));
+            }
+        }
+        if self.rate_limit.is_some() {
+            let Some(limiter) = &policies.limiter else {
+                return Some(Outcome::ShortCircuit("rate_limit", json_error(500, "policy_missing", "no rate limiter")));
+            };
+            let route = self.limit_key(req).unwrap_or_else(|| self.route.clone());
+            let out = limiter.check(&RateKey { ip, tenant: req.tenant, route: &route });
+            if !out.allowed {
+                let mut r = json_error(429, "rate_limited", "too many requests");
+                add_header(&mut r, "Retry-After", &out.retry_after_ms.div_ceil(1000).to_string());
+                return Some(Outcome::ShortCircuit("rate_limit", r));
+            }
+        }
+        if let Some(name) = &self.waf {
+            let Some(engine) = policies.wafs.get(name) else {
+                return Some(Outcome::ShortCircuit("waf", json_error(500, "policy_missing", "waf rule set not provided")));
+            };
+            let headers: Vec<(&str, &str)> = req.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+            let view = RequestView {
+                path: req.path,
+                user_agent: header(req, "user-agent").unwrap_or(""),
+                headers: &headers,
+                body: &req.body,
+                ip,
+                client_cert_cn: client_cert(req).map_or("", |(cn, _)| cn),
+            };
+            let d = engine.decide(&view);
+            let answer = match (&d.action, &policies.challenge) {
+                (Action::Deny(s), _) | (Action::Challenge(s), None) => Some(json_error(*s, "blocked", "request blocked by waf")),
+                (_, Some(v)) => Self::challenge(v, &d, req, ip),
+                _ => None,
+            };
+            run.waf = Some(d);
+            if let Some(r) = answer {
+                return Some(Outcome::ShortCircuit("waf", r));
+            }
+        }
+        None
+    }
+
+    // Solution submissions get the pass cookie; Challenge decisions without
+    // a valid pass get the challenge page.
+    fn challenge(v: &ChallengeVerifier, d: &Decision, req: &Request, ip: &str) -> Option<Response> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|t| t.as_secs()).unwrap_or(0);
+        match v.admit(d, &req.headers, ip, now) {
+            Admission::Pass => None,
+            Admission::Solved { set_cookie } => {
+                let mut r = Response::new(204);
+                add_header(&mut r, "Set-Cookie", &set_cookie);
+                Some(r)
+            }
+            Admission::Challenge { status, challenge, body } => {
+                let mut r = Response::new(status);
+                add_header(&mut r, "Content-Type", "text/html; charset=utf-8");
+                add_header(&mut r, "Cache-Control", "no-store");
+                add_header(&mut r, CHALLENGE_HEADER, &challenge.token);
+                set_body(&mut r, body.as_bytes());
+                Some(r)
+            }
+            Admission::Rejected(e) => Some(json_error(e.status(), "challenge_failed", "challenge solution rejected")),
+        }
+    }
+
+    fn timed(&self, run: &mut Execution, metrics: Option<&Metrics>, stage: &'static str, key: &'static str, started: Instant) {
+        let elapsed = started.elapsed();
+        if let Some(h) = metrics.and_then(|m| {
+            m.histogram(STAGE_LATENCY, &[("tenant", run.request.tenant), ("route", &self.route), ("stage", stage), ("key", key)]).ok()
+        }) {
+            h.observe_us(elapsed.as_micros().min(u64::MAX as u128) as u64);
+        }
+        run.stages.push(StageTiming { stage, key, elapsed });
+    }
+
+    fn is_wildcard(&self) -> bool {
+        self.route.ends_with('*')
+    }
+}
+
+impl PipelineBuilder {
+    // Named ACL (Policies::acl) evaluated against the client IP before any
+    // plugin runs.
+    pub fn acl(mut self, name: &str) -> Self {
+        self.acl = Some(name.to_string());
+        self
+    }
+
+    pub fn waf(mut self, ruleset: &str) -> Self {
+        self.waf = Some(ruleset.to_string());
+        self
+    }
+
+    pub fn filters<I: IntoIterator<Item = &'static str>>(mut self, keys: I) -> Self {
+        self.filters.extend(keys);
+        self
+    }
+
+    pub fn rate_limit(mut self, scope: &str) -> Self {
+        self.rate_limit = Some(scope.to_string());
+        self
+    }
+
+    // Compiled with Expr::condition at config load.
+    pub fn when(mut self, condition: Expr) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    // Run filter `key` only when `guard` holds (rewrite guards and the like).
+    pub fn guard(mut self, key: &'static str, guard: Expr) -> Self {
+        self.guards.push((key, guard));
+        self
+    }
+
+    // Compiled with Expr::key at config load.
+    pub fn rate_limit_key(mut self, key: Expr) -> Self {
+        self.rate_limit_key = Some(key);
+        self
+    }
+
+    // Named header transforms (Policies::headers) for this route's upstream
+    // request and downstream response.
+    pub fn headers(mut self, name: &str) -> Self {
+        self.headers = Some(name.to_string());
+        self
+    }
+
+    pub fn handler(self, key: &'static str) -> Pipeline {
+        Pipeline {
+            route: self.route,
+            acl: self.acl,
+            waf: self.waf,
+            filters: self.filters,
+            rate_limit: self.rate_limit,
+            handler: key,
+            condition: self.condition,
+            guards: self.guards,
+            rate_limit_key: self.rate_limit_key,
+            headers: self.headers,
+        }
+    }
+}
+
+// Shared route pattern semantics (security/path.rs): exact canonical path,
+// or a prefix ending in `*` matched on segment boundaries.
+pub use olwsx_security::route_matches;
+
+// Runtime dispatch table: exact routes first, then longest wildcard prefix.
+#[derive(Clone, Debug)]
+pub struct DispatchTable {
+    routes: Vec<Pipeline>,
+}
+
+impl DispatchTable {
+    pub fn compile(pipelines: Vec<Pipeline>, reg: &Registry) -> Result<Self, String> {
+        let mut seen = std::collections::HashSet::new();
+        for p in pipelines.iter() {
+            // the same route may appear once per distinct condition
+            if !seen.insert((p.route.as_str(), p.condition.as_ref().map(|c| c.source()))) {
+                return Err(format!("route '{}' defined twice", p.route));
+            }
+            p.validate(reg)?;
+        }
+        let mut routes = pipelines;
+        routes.sort_by(|a, b| {
+            a.is_wildcard()
+                .cmp(&b.is_wildcard())
+                .then_with(|| b.route.len().cmp(&a.route.len()))
+                .then_with(|| b.condition.is_some().cmp(&a.condition.is_some()))
+        });
+        Ok(Self { routes })
+    }
+
+    // By path alone, so conditional pipelines (which need the request to
+    // evaluate) are never chosen; use lookup_request when serving traffic.
+    pub fn lookup(&self, path: &str) -> Option<&Pipeline> {
+        let path = canonical(path);
+        self.routes.iter().find(|p| p.condition.is_none() && route_matches(&p.route, &path))
+    }
+
+    // Like lookup, but conditional pipelines are only chosen when they hold;
+    // an unconditional pipeline for the same route is the fallback.
+    pub fn lookup_request(&self, req: &Request) -> Option<&Pipeline> {
+        let path = canonical(req.path);
+        self.routes.iter().find(|p| p.matches_canonical(&path, req))
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::SampleValue;
+    use crate::sdk::{json_error, FilterPlugin, HandlerPlugin, PluginMeta};
+    use std::collections::HashMap;
+
+    struct NopFilter;
+    impl FilterPlugin for NopFilter {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "nop_filter", version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
+        fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
+        fn process(&self, _req: &Request) -> FilterVerdict { FilterVerdict::Continue }
+    }
+
+    struct OkHandler;
+    impl HandlerPlugin for OkHandler {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "ok_handler", version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
+        fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
+        fn handle(&self, _req: &Request) -> HandlerResult { HandlerResult { resp: Response::new(200), meta_flags: 0 } }
+    }
+
+    #[test]
+    fn build_and_dispatch() {
+        let mut reg = Registry::new();
+        reg.register_filter("guard", Box::new(NopFilter)).unwrap();
+        reg.register_handler("proxy:pool_api", Box::new(OkHandler)).unwrap();
+        reg.register_handler("static", Box::new(OkHandler)).unwrap();
+
+        let api = Pipeline::for_route("/api/*").waf("strict_json").filters(["guard"]).rate_limit("api").handler("proxy:pool_api");
+        let health = Pipeline::for_route("/api/health").handler("static");
+        let table = DispatchTable::compile(vec![api, health], &reg).unwrap();
+
+        assert_eq!(table.lookup("/api/health").unwrap().handler, "static");
+        let p = table.lookup("/api/users").unwrap();
+        assert_eq!(p.handler, "proxy:pool_api");
+        assert_eq!(p.waf.as_deref(), Some("strict_json"));
+        assert!(table.lookup("/other").is_none());
+        for raw in ["//api/health", "/%61pi/health", "/x/../api/health?q=1"] {
+            assert_eq!(table.lookup(raw).unwrap().handler, "static", "{}", raw);
+        }
+        assert!(table.lookup("/apix").is_none());
+
+        let bad = Pipeline::for_route("/x").filters(["missing"]).handler("static");
+        assert!(DispatchTable::compile(vec![bad], &reg).is_err());
+
+        let keyed = Pipeline::for_route("/api/*")
+            .when(Expr::condition(r#"req.header("x-api-key") != """#).unwrap())
+            .filters(["guard"])
+            .guard("guard", Expr::condition(r#"req.path startswith "/api/v1""#).unwrap())
+            .rate_limit_key(Expr::key(r#""key:" .. req.header("x-api-key")"#).unwrap())
+            .handler("static");
+        let anon = Pipeline::for_route("/api/*").handler("proxy:pool_api");
+        let table = DispatchTable::compile(vec![anon, keyed], &reg).unwrap();
+        assert_eq!(table.lookup("/api/v2/x").unwrap().handler, "proxy:pool_api", "conditions are not evaluated by path");
+        let mut req = Request { method: "GET", path: "/api/v2/x?y=1", headers: vec![], body: vec![], tenant: "default" };
+        assert_eq!(table.lookup_request(&req).unwrap().handler, "proxy:pool_api");
+        req.headers.push(("X-Api-Key".into(), "k9".into()));
+        let p = table.lookup_request(&req).unwrap();
+        assert_eq!((p.handler, p.limit_key(&req).as_deref()), ("static", Some("key:k9")));
+        assert!(!p.filter_enabled("guard", &req));
+    }
+
+    // Appends its tag to the path; short-circuits on "/stop" paths.
+    struct Tag(&'static str);
+    impl FilterPlugin for Tag {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "tag", version: "1.0.0", author: "OLWSX", flags: 0, caps: crate::sdk::caps::MUTATE_PATH } }
+        fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
+        fn process(&self, req: &Request) -> FilterVerdict {
+            if req.path.starts_with("/stop") {
+                return FilterVerdict::ShortCircuit(json_error(403, "stopped", self.0));
+            }
+            let path: &'static str = Box::leak(format!("{}/{}", req.path, self.0).into_boxed_str());
+            FilterVerdict::Mutate(Request { path, ..req.clone() })
+        }
+    }
+
+    struct PathHandler;
+    impl HandlerPlugin for PathHandler {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "path", version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
+        fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
+        fn handle(&self, req: &Request) -> HandlerResult {
+            let mut resp = Response::new(200);
+            resp.body = req.path.as_bytes().to_vec();
+            HandlerResult { resp, meta_flags: 0 }
+        }
+    }
+
+    #[test]
+    fn execute_runs_the_chain_in_order() {
+        let mut reg = Registry::new();
+        reg.register_filter("a", Box::new(Tag("a"))).unwrap();
+        reg.register_filter("b", Box::new(Tag("b"))).unwrap();
+        reg.register_filter("c", Box::new(Tag("c"))).unwrap();
+        reg.register_handler("path", Box::new(PathHandler)).unwrap();
+        let p = Pipeline::for_route("/*")
+            .filters(["a", "b", "c"])
+            .guard("b", Expr::condition(r#"req.path startswith "/x""#).unwrap())
+            .handler("path");
+        let metrics = Metrics::new();
+
+        let req = Request { method: "GET", path: "/p", headers: vec![], body: vec![], tenant: "t1" };
+        let run = p.execute(&reg, &Policies::new(), "127.0.0.1", req, Some(&metrics));
+        let Outcome::Handled(r) = &run.outcome else { panic!("not handled: {:?}", run.outcome) };
+        assert_eq!(r.resp.body, b"/p/a/c", "b skipped by its guard, mutations fed forward");
+        assert_eq!(run.request.path, "/p/a/c");
+        let stages: Vec<(&str, &str)> = run.stages.iter().map(|s| (s.stage, s.key)).collect();
+        assert_eq!(stages, [("filter", "a"), ("filter", "c"), ("handler", "path")]);
+        let observed: u64 = metrics
+            .gather_tenant("t1")
+            .iter()
+            .filter(|s| s.name == metrics.qualify(STAGE_LATENCY))
+            .map(|s| match s.value {
+                SampleValue::Histogram { count, .. } => count,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(observed, 3);
+
+        let stop = Request { method: "GET", path: "/stop", headers: vec![], body: vec![], tenant: "t1" };
+        let run = p.execute(&reg, &Policies::new(), "127.0.0.1", stop, None);
+        assert!(matches!(run.outcome, Outcome::ShortCircuit("a", ref r) if r.status == 403));
+        assert_eq!(run.stages.len(), 1, "handler never ran");
+
+        // a before_handler answer replaces the handler, after the filters ran
+        let req = Request { method: "GET", path: "/c", headers: vec![], body: vec![], tenant: "t1" };
+        let run = p.execute_with(&reg, &Policies::new(), "127.0.0.1", req, None, |r| Some(("cache", json_error(200, "hit", r.path))));
+        assert!(matches!(run.outcome, Outcome::ShortCircuit("cache", ref r) if String::from_utf8_lossy(&r.body).contains("/c/a/c")));
+        assert_eq!(run.stages.len(), 2, "handler never ran");
+
+        let orphan = Pipeline::for_route("/*").handler("missing");
+        let req = Request { method: "GET", path: "/", headers: vec![], body: vec![], tenant: "t1" };
+        assert!(matches!(orphan.execute(&reg, &Policies::new(), "127.0.0.1", req, None).outcome, Outcome::NoHandler));
+    }
+
+    #[test]
+    fn execute_enforces_policies_before_plugins() {
+        use crate::ratelimit::{Limit, RateLimitConfig};
+        use crate::waf::{Field, Matcher, Rule};
+
+        let mut reg = Registry::new();
+        reg.register_filter("a", Box::new(Tag("a"))).unwrap();
+        reg.register_handler("path", Box::new(PathHandler)).unwrap();
+        let p = Pipeline::for_route("/*").acl("office").waf("base").rate_limit("ip").filters(["a"]).handler("path");
+        let rules = vec![Rule { id: 7, field: Field::Path, matcher: Matcher::Contains("../".into()), action: Action::Deny(403), tags: vec!["traversal".into()], severity: 8 }];
+        let limiter = RateLimiter::new(RateLimitConfig { ip: Some(Limit { burst: 2, refill_per_sec: 0.0 }), ..RateLimitConfig::default() });
+        assert!(Policies::new().check(&p).is_err());
+        let policies = Policies::new().acl("office", Acl::parse("allow 10.0.0.0/8; deny all").unwrap()).waf("base", Engine::new(rules)).rate_limiter(limiter);
+        policies.check(&p).unwrap();
+
+        let req = |path: &'static str| Request { method: "GET", path, headers: vec![], body: vec![], tenant: "t1" };
+        let run = p.execute(&reg, &policies, "203.0.113.9", req("/p"), None);
+        assert!(matches!(run.outcome, Outcome::ShortCircuit("acl", ref r) if r.status == 403));
+        assert!(run.stages.is_empty(), "no plugin ran");
+
+        let run = p.execute(&reg, &policies, "10.0.0.1", req("/a/../etc"), None);
+        assert!(matches!(run.outcome, Outcome::ShortCircuit("waf", ref r) if r.status == 403));
+        assert_eq!(run.waf.and_then(|d| d.applied_rule_id), Some(7));
+        assert!(matches!(p.execute(&reg, &policies, "10.0.0.1", req("/p"), None).outcome, Outcome::Handled(_)));
+        let run = p.execute(&reg, &policies, "10.0.0.1", req("/p"), None);
+        assert!(matches!(run.outcome, Outcome::ShortCircuit("rate_limit", ref r) if r.status == 429));
+        // with no policies at all, named ones fail closed
+        assert!(matches!(p.execute(&reg, &Policies::new(), "10.0.0.1", req("/p"), None).outcome, Outcome::ShortCircuit("acl", _)));
+    }
+
+    #[test]
+    fn waf_sees_the_verified_client_cert() {
+        use crate::sdk::{CLIENT_CERT_CN_HEADER, CLIENT_CERT_FINGERPRINT_HEADER};
+        use crate::waf::{Field, Matcher, Rule};
+
+        let mut reg = Registry::new();
+        reg.register_handler("path", Box::new(PathHandler)).unwrap();
+        let p = Pipeline::for_route("/*").waf("mtls").handler("path");
+        let rules = vec![Rule { id: 9, field: Field::ClientCertCn, matcher: Matcher::Eq("revoked.internal".into()), action: Action::Deny(403), tags: vec![], severity: 5 }];
+        let policies = Policies::new().waf("mtls", Engine::new(rules));
+
+        let cert = |cn: &str| vec![(CLIENT_CERT_CN_HEADER.to_string(), cn.to_string()), (CLIENT_CERT_FINGERPRINT_HEADER.to_string(), "ab12".to_string())];
+        let req = Request { method: "GET", path: "/p", headers: cert("revoked.internal"), body: vec![], tenant: "t1" };
+        assert!(matches!(p.execute(&reg, &policies, "10.0.0.1", req, None).outcome, Outcome::ShortCircuit("waf", ref r) if r.status == 403));
+        let req = Request { method: "GET", path: "/p", headers: cert("svc.internal"), body: vec![], tenant: "t1" };
+        assert!(matches!(p.execute(&reg, &policies, "10.0.0.1", req, None).outcome, Outcome::Handled(_)));
+    }
+
+    #[test]
+    fn waf_throttle_marks_the_response() {
+        use crate::sdk::THROTTLE_HEADER;
+        use crate::waf::{Field, Matcher, Rule};
+
+        let mut reg = Registry::new();
+        reg.register_handler("path", Box::new(PathHandler)).unwrap();
+        let p = Pipeline::for_route("/*").waf("dl").handler("path");
+        let rules = vec![Rule { id: 4, field: Field::Path, matcher: Matcher::Prefix("/dl/".into()), action: Action::Throttle(65536), tags: vec![], severity: 3 }];
+        let policies = Policies::new().waf("dl", Engine::new(rules));
+
+        let rate = |path| {
+            let req = Request { method: "GET", path, headers: vec![], body: vec![], tenant: "t1" };
+            match p.execute(&reg, &policies, "10.0.0.1", req, None).outcome {
+                Outcome::Handled(r) => r.resp.headers.iter().find(|(k, _)| k == THROTTLE_HEADER).map(|(_, v)| v.clone()),
+                _ => panic!("throttled requests are still handled"),
+            }
+        };
+        assert_eq!(rate("/dl/iso").as_deref(), Some("65536"));
+        assert_eq!(rate("/index.html"), None);
+    }
+
+    #[test]
+    fn execute_applies_header_transforms() {
+        let mut reg = Registry::new();
+        reg.register_filter("a", Box::new(Tag("a"))).unwrap();
+        reg.register_handler("path", Box::new(PathHandler)).unwrap();
+        let p = Pipeline::for_route("/*").filters(["a"]).headers("api").handler("path");
+        let t = HeaderTransforms::parse("request set X-Forwarded-For ${client_ip}\nresponse set X-Path ${path}\nresponse set-if-absent Cache-Control no-store").unwrap();
+        let policies = Policies::new().headers("api", t);
+        policies.check(&p).unwrap();
+
+        let req = |path: &'static str| Request { method: "GET", path, headers: vec![], body: vec![], tenant: "t1" };
+        let run = p.execute(&reg, &policies, "203.0.113.9", req("/p"), None);
+        assert_eq!(header(&run.request, "x-forwarded-for"), Some("203.0.113.9"));
+        let Outcome::Handled(r) = &run.outcome else { panic!("not handled: {:?}", run.outcome) };
+        assert_eq!((header_of(&r.resp, "x-path"), header_of(&r.resp, "cache-control")), (Some("/p/a"), Some("no-store")));
+
+        // a filter's answer goes downstream too; the request ops never ran
+        let run = p.execute(&reg, &policies, "203.0.113.9", req("/stop"), None);
+        let Outcome::ShortCircuit("a", r) = &run.outcome else { panic!("expected filter answer") };
+        assert_eq!(header_of(r, "cache-control"), Some("no-store"));
+        assert_eq!(header(&run.request, "x-forwarded-for"), None);
+
+        assert!(Policies::new().check(&p).is_err());
+        assert!(matches!(p.execute(&reg, &Policies::new(), "10.0.0.1", req("/p"), None).outcome, Outcome::ShortCircuit("headers", ref r) if r.status == 500));
+    }
+
+    fn header_of<'a>(r: &'a Response, name: &str) -> Option<&'a str> {
+        r.headers.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn waf_challenge_is_issued_solved_and_passed() {
+        use crate::challenge::{solve, ChallengeConfig, SOLUTION_HEADER};
+        use crate::waf::{Field, Matcher, Rule};
+
+        let mut reg = Registry::new();
+        reg.register_handler("path", Box::new(PathHandler)).unwrap();
+        let p = Pipeline::for_route("/*").waf("bots").handler("path");
+        let rules = vec![Rule { id: 9, field: Field::Path, matcher: Matcher::Prefix("/login".into()), action: Action::Challenge(429), tags: vec!["bot".into()], severity: 3 }];
+        let verifier = ChallengeVerifier::new(b"k", ChallengeConfig { difficulty_bits: 4, ..ChallengeConfig::default() });
+        let policies = Policies::new().waf("bots", Engine::new(rules)).challenge(verifier);
+        let ip = "203.0.113.9";
+        let req = |headers: Vec<(String, String)>| Request { method: "POST", path: "/login", headers, body: vec![], tenant: "t1" };
+
+        let run = p.execute(&reg, &policies, ip, req(vec![]), None);
+        let Outcome::ShortCircuit("waf", page) = run.outcome else { panic!("expected challenge page") };
+        assert_eq!(page.status, 429);
+        let token = page.headers.iter().find(|(k, _)| k == CHALLENGE_HEADER).map(|(_, v)| v.clone()).unwrap();
+        assert!(String::from_utf8_lossy(&page.body).contains(&token));
+
+        let solution = solve(&token, 4);
+        let run = p.execute(&reg, &policies, ip, req(vec![(CHALLENGE_HEADER.into(), token), (SOLUTION_HEADER.into(), solution)]), None);
+        let Outcome::ShortCircuit("waf", solved) = run.outcome else { panic!("expected pass cookie") };
+        assert_eq!(solved.status, 204);
+        let set_cookie = solved.headers.iter().find(|(k, _)| k == "Set-Cookie").map(|(_, v)| v.clone()).unwrap();
+
+        let cookie = set_cookie.split(';').next().unwrap().to_string();
+        let run = p.execute(&reg, &policies, ip, req(vec![("Cookie".into(), cookie.clone())]), None);
+        assert!(matches!(run.outcome, Outcome::Handled(_)));
+        // the pass is bound to the client that solved it
+        let run = p.execute(&reg, &policies, "203.0.113.10", req(vec![("Cookie".into(), cookie)]), None);
+        assert!(matches!(run.outcome, Outcome::ShortCircuit("waf", ref r) if r.status == 429));
+    }
+}
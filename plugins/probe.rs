@@ -0,0 +1,277 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/probe.rs
+// Role: Synthetic monitoring (self-probes through the full plugin pipeline)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Issue configured synthetic requests against local routes via DispatchTable,
+//   through Pipeline::execute like live traffic (policies, guards, conditions,
+//   stage metrics under the probe tenant).
+// - Record per-probe success/failure counters and last/max latency.
+// - Flip readiness when a critical probe fails N consecutive times.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use olwsx_observability::Registry as Metrics;
+use olwsx_plugins_sdk::{DispatchTable, Outcome, Policies, Registry, Request};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+mod olwsx_plugins_sdk {
+    pub use crate::pipeline::{DispatchTable, Outcome, Policies};
+    pub use crate::sdk::{Registry, Request};
+}
+
+mod olwsx_observability {
+    pub use crate::registry::Registry;
+}
+
+// Marks synthetic traffic so plugins may exclude it from business metrics.
+pub const PROBE_HEADER: &str = "x-olwsx-probe";
+pub const PROBE_TENANT: &str = "_probe";
+// Client address probes present to ACLs and rate limits.
+pub const PROBE_IP: &str = "127.0.0.1";
+
+#[derive(Clone, Debug)]
+pub struct Probe {
+    pub name: &'static str,
+    pub method: &'static str,
+    pub path: &'static str,
+    pub expect_status: u16,
+    pub critical: bool,
+}
+
+impl Probe {
+    pub fn new(name: &'static str, path: &'static str) -> Self {
+        Self { name, method: "GET", path, expect_status: 200, critical: false }
+    }
+
+    pub fn method(mut self, m: &'static str) -> Self {
+        self.method = m;
+        self
+    }
+
+    pub fn expect(mut self, status: u16) -> Self {
+        self.expect_status = status;
+        self
+    }
+
+    // Critical probes gate readiness; others are only recorded.
+    pub fn critical(mut self) -> Self {
+        self.critical = true;
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProbeConfig {
+    pub interval: Duration,
+    pub fail_threshold: u32, // consecutive failures before a critical probe flips readiness
+    pub probes: Vec<Probe>,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self { interval: Duration::from_secs(10), fail_threshold: 3, probes: Vec::new() }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProbeStats {
+    pub ok: u64,
+    pub failed: u64,
+    pub consecutive_failures: u32,
+    pub last_latency_us: u64,
+    pub max_latency_us: u64,
+    pub last_error: Option<String>,
+}
+
+pub struct Prober {
+    cfg: ProbeConfig,
+    stats: Mutex<Vec<ProbeStats>>,
+    ready: AtomicBool,
+    stop: AtomicBool,
+}
+
+impl Prober {
+    pub fn new(cfg: ProbeConfig) -> Self {
+        let n = cfg.probes.len();
+        Self { cfg, stats: Mutex::new(vec![ProbeStats::default(); n]), ready: AtomicBool::new(true), stop: AtomicBool::new(false) }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    // Snapshot as (probe name, stats) rows for export.
+    pub fn stats(&self) -> Vec<(&'static str, ProbeStats)> {
+        let st = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        self.cfg.probes.iter().map(|p| p.name).zip(st.iter().cloned()).collect()
+    }
+
+    // Run every probe once and update readiness.
+    pub fn run_once(&self, table: &DispatchTable, reg: &Registry, policies: &Policies, metrics: Option<&Metrics>) {
+        let mut st = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        for (p, s) in self.cfg.probes.iter().zip(st.iter_mut()) {
+            let started = Instant::now();
+            let outcome = execute(p, table, reg, policies, metrics);
+            let us = started.elapsed().as_micros().min(u64::MAX as u128) as u64;
+            s.last_latency_us = us;
+            s.max_latency_us = s.max_latency_us.max(us);
+            match outcome {
+                Ok(status) if status == p.expect_status => {
+                    s.ok += 1;
+                    s.consecutive_failures = 0;
+                    s.last_error = None;
+                }
+                Ok(status) => fail(s, format!("status {} (expected {})", status, p.expect_status)),
+                Err(e) => fail(s, e),
+            }
+        }
+        let threshold = self.cfg.fail_threshold.max(1);
+        let ready = self.cfg.probes.iter().zip(st.iter()).all(|(p, s)| !p.critical || s.consecutive_failures < threshold);
+        self.ready.store(ready, Ordering::Release);
+    }
+
+    // Probe loop on a dedicated thread until stop() is called.
+    pub fn spawn(
+        self: Arc<Self>,
+        table: Arc<DispatchTable>,
+        reg: Arc<Registry>,
+        policies: Arc<Policies>,
+        metrics: Option<Arc<Metrics>>,
+    ) -> JoinHandle<()> {
+        std::thread::Builder::new()
+            .name("olwsx-prober".to_string())
+            .spawn(move || {
+                while !self.stop.load(Ordering::Acquire) {
+                    self.run_once(&table, &reg, &policies, metrics.as_deref());
+                    std::thread::park_timeout(self.cfg.interval);
+                }
+            })
+            .expect("spawn prober thread")
+    }
+
+    // Takes effect at the next wakeup; unpark the thread for immediate exit.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Release);
+    }
+}
+
+fn fail(s: &mut ProbeStats, err: String) {
+    s.failed += 1;
+    s.consecutive_failures = s.consecutive_failures.saturating_add(1);
+    s.last_error = Some(err);
+}
+
+// The route live traffic would take, run by the same Pipeline::execute.
+// A panicking plugin counts as a failed probe rather than killing the prober.
+fn execute(p: &Probe, table: &DispatchTable, reg: &Registry, policies: &Policies, metrics: Option<&Metrics>) -> Result<u16, String> {
+    let req = Request {
+        method: p.method,
+        path: p.path,
+        headers: vec![(PROBE_HEADER.to_string(), p.name.to_string())],
+        body: Vec::new(),
+        tenant: PROBE_TENANT,
+    };
+    let pipe = table.lookup_request(&req).ok_or_else(|| format!("no route for {}", p.path))?;
+    let run = std::panic::AssertUnwindSafe(|| pipe.execute(reg, policies, PROBE_IP, req, metrics));
+    match std::panic::catch_unwind(run).map(|r| r.outcome) {
+        Ok(Outcome::Handled(r)) => Ok(r.resp.status),
+        Ok(Outcome::ShortCircuit(_, resp)) => Ok(resp.status),
+        Ok(Outcome::NoHandler) => Err(format!("handler '{}' missing", pipe.handler)),
+        Err(_) => Err("plugin panicked".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::Acl;
+    use crate::pipeline::Pipeline;
+    use crate::registry::SampleValue;
+    use crate::sdk::{HandlerPlugin, HandlerResult, PluginMeta, Response};
+    use crate::tenant_view::STAGE_LATENCY;
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicU16;
+
+    struct Flaky(Arc<AtomicU16>);
+    impl HandlerPlugin for Flaky {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "flaky", version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
+        fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
+        fn handle(&self, _req: &Request) -> HandlerResult {
+            HandlerResult { resp: Response::new(self.0.load(Ordering::Relaxed)), meta_flags: 0 }
+        }
+    }
+
+    #[test]
+    fn critical_probe_flips_readiness() {
+        let status = Arc::new(AtomicU16::new(200));
+        let mut reg = Registry::new();
+        reg.register_handler("flaky", Box::new(Flaky(status.clone()))).unwrap();
+        let table = DispatchTable::compile(vec![Pipeline::for_route("/healthz").handler("flaky")], &reg).unwrap();
+
+        let cfg = ProbeConfig {
+            fail_threshold: 2,
+            probes: vec![Probe::new("health", "/healthz").critical(), Probe::new("missing", "/nope")],
+            ..ProbeConfig::default()
+        };
+        let prober = Prober::new(cfg);
+        let policies = Policies::new();
+        prober.run_once(&table, &reg, &policies, None);
+        assert!(prober.is_ready(), "non-critical failure must not affect readiness");
+
+        status.store(503, Ordering::Relaxed);
+        prober.run_once(&table, &reg, &policies, None);
+        assert!(prober.is_ready());
+        prober.run_once(&table, &reg, &policies, None);
+        assert!(!prober.is_ready());
+
+        status.store(200, Ordering::Relaxed);
+        prober.run_once(&table, &reg, &policies, None);
+        assert!(prober.is_ready());
+
+        let stats = prober.stats();
+        assert_eq!(stats[0].0, "health");
+        assert_eq!((stats[0].1.ok, stats[0].1.failed), (2, 2));
+        assert_eq!(stats[1].1.failed, 4);
+        assert!(stats[1].1.last_error.as_deref().unwrap().contains("no route"));
+    }
+
+    #[test]
+    fn probes_take_the_live_path() {
+        let mut reg = Registry::new();
+        reg.register_handler("flaky", Box::new(Flaky(Arc::new(AtomicU16::new(200))))).unwrap();
+        let table = DispatchTable::compile(
+            vec![Pipeline::for_route("/healthz").handler("flaky"), Pipeline::for_route("/admin").acl("ops").handler("flaky")],
+            &reg,
+        )
+        .unwrap();
+        let policies = Policies::new().acl("ops", Acl::parse("deny 127.0.0.0/8; allow all").unwrap());
+        let metrics = Metrics::new();
+
+        let prober = Prober::new(ProbeConfig {
+            probes: vec![Probe::new("health", "/healthz"), Probe::new("admin", "/admin")],
+            ..ProbeConfig::default()
+        });
+        prober.run_once(&table, &reg, &policies, Some(&metrics));
+        let stats = prober.stats();
+        assert_eq!(stats[0].1.ok, 1);
+        assert_eq!(stats[1].1.last_error.as_deref(), Some("status 403 (expected 200)"), "ACL applies to probes");
+
+        let observed: u64 = metrics
+            .gather_tenant(PROBE_TENANT)
+            .iter()
+            .filter(|s| s.name == metrics.qualify(STAGE_LATENCY))
+            .map(|s| match s.value {
+                SampleValue::Histogram { count, .. } => count,
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(observed, 1, "the handler stage is timed under the probe tenant");
+    }
+}
@@ -12,7 +12,12 @@
 
 #![forbid(unsafe_code)]
 
-use std::collections::HashMap;
+use crate::crash;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+use std::time::{Duration, Instant};
 
 // ------------------------------- Frozen types -------------------------------
 
@@ -30,11 +35,12 @@ pub struct Response {
     pub status: u16,
     pub headers: Vec<(String, String)>,
     pub body: Vec<u8>,
+    pub trailers: Vec<(String, String)>, // sent after the body (chunked/H2 only)
 }
 
 impl Response {
     pub fn new(status: u16) -> Self {
-        Self { status, headers: Vec::new(), body: Vec::new() }
+        Self { status, headers: Vec::new(), body: Vec::new(), trailers: Vec::new() }
     }
 }
 
@@ -60,8 +66,94 @@ pub struct PluginMeta {
     pub version: &'static str,
     pub author: &'static str,
     pub flags: u32,
+    pub caps: u32, // declared capabilities (caps::*); the host grants nothing else
 }
 
+// Capability bits for PluginMeta::caps.
+pub mod caps {
+    pub const READ_BODY: u32   = 0x0000_0001; // without it, plugins see an empty body
+    pub const MUTATE_PATH: u32 = 0x0000_0002; // filters may return Mutate with a new path
+    pub const CACHE: u32       = 0x0000_0004; // HostServices::cache
+    pub const NETWORK: u32     = 0x0000_0008; // HostServices::network
+    pub const STATE: u32       = 0x0000_0010; // HostServices::state
+
+    const NAMES: [(u32, &str); 5] = [
+        (READ_BODY, "read_body"), (MUTATE_PATH, "mutate_path"), (CACHE, "cache"), (NETWORK, "network"), (STATE, "state"),
+    ];
+
+    // Human-readable list for operator review.
+    pub fn names(caps: u32) -> Vec<&'static str> {
+        NAMES.iter().filter(|(bit, _)| caps & bit != 0).map(|(_, n)| *n).collect()
+    }
+}
+
+// ------------------------------- Host services ------------------------------
+
+pub trait HostCache: Send + Sync {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
+    fn put(&self, key: &[u8], value: Vec<u8>, ttl: Duration);
+}
+
+pub trait HostNetwork: Send + Sync {
+    fn fetch(&self, req: &Request) -> Result<Response, String>;
+}
+
+pub trait StateStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<Vec<u8>>;
+    fn set(&self, key: &str, value: Vec<u8>);
+}
+
+// Services the embedding host offers; any of them may be absent.
+#[derive(Clone, Default)]
+pub struct HostBackends {
+    pub cache: Option<Arc<dyn HostCache>>,
+    pub network: Option<Arc<dyn HostNetwork>>,
+    pub state: Option<Arc<dyn StateStore>>,
+}
+
+// Per-plugin view of HostBackends, limited to the plugin's declared caps.
+#[derive(Clone)]
+pub struct HostServices {
+    plugin: &'static str,
+    caps: u32,
+    backends: HostBackends,
+    denials: Arc<AtomicU64>,
+}
+
+impl HostServices {
+    pub fn granted(&self) -> u32 {
+        self.caps
+    }
+
+    pub fn cache(&self) -> Result<&dyn HostCache, String> {
+        self.check(caps::CACHE, "cache")?;
+        self.backends.cache.as_deref().ok_or_else(|| "cache service not available".to_string())
+    }
+
+    pub fn network(&self) -> Result<&dyn HostNetwork, String> {
+        self.check(caps::NETWORK, "network")?;
+        self.backends.network.as_deref().ok_or_else(|| "network service not available".to_string())
+    }
+
+    pub fn state(&self) -> Result<&dyn StateStore, String> {
+        self.check(caps::STATE, "state")?;
+        self.backends.state.as_deref().ok_or_else(|| "state store not available".to_string())
+    }
+
+    fn check(&self, cap: u32, name: &str) -> Result<(), String> {
+        if self.caps & cap != 0 {
+            return Ok(());
+        }
+        self.denials.fetch_add(1, Ordering::Relaxed);
+        Err(format!("plugin '{}' did not declare capability '{}'", self.plugin, name))
+    }
+}
+
+// ------------------------------- Error reports ------------------------------
+// Shared with the security config paths (common/report.rs).
+
+pub use crate::report::{ErrorReport, Issue, IssueLevel};
+
 // ------------------------------- Plugin traits ------------------------------
 
 pub trait FilterPlugin: Send + Sync {
@@ -69,6 +161,14 @@ pub trait FilterPlugin: Send + Sync {
     fn init(&mut self, cfg: &HashMap<String, String>) -> Result<(), String>;
     fn process(&self, req: &Request) -> FilterVerdict;
     fn teardown(&mut self) {}
+    // Called on registration (and on replace) with services limited to meta().caps.
+    fn attach(&mut self, _host: HostServices) {}
+    // Initializes a replacement instead of init(): `old_cfg` is what the
+    // outgoing instance ran with. State meant to survive a reload lives in
+    // HostServices::state (caps::STATE); this is where it gets migrated.
+    fn on_reload(&mut self, _old_cfg: &HashMap<String, String>, new_cfg: &HashMap<String, String>) -> Result<(), String> {
+        self.init(new_cfg)
+    }
 }
 
 pub trait HandlerPlugin: Send + Sync {
@@ -76,72 +176,318 @@ pub trait HandlerPlugin: Send + Sync {
     fn init(&mut self, cfg: &HashMap<String, String>) -> Result<(), String>;
     fn handle(&self, req: &Request) -> HandlerResult;
     fn teardown(&mut self) {}
+    // Called on registration (and on replace) with services limited to meta().caps.
+    fn attach(&mut self, _host: HostServices) {}
+    // Initializes a replacement instead of init(): `old_cfg` is what the
+    // outgoing instance ran with. State meant to survive a reload lives in
+    // HostServices::state (caps::STATE); this is where it gets migrated.
+    fn on_reload(&mut self, _old_cfg: &HashMap<String, String>, new_cfg: &HashMap<String, String>) -> Result<(), String> {
+        self.init(new_cfg)
+    }
 }
 
 // ------------------------------- Registry -----------------------------------
 
+// Replacement instance for Registry::replace; the variant must match the kind
+// already registered under the key.
+pub enum Plugin {
+    Filter(Box<dyn FilterPlugin>),
+    Handler(Box<dyn HandlerPlugin>),
+}
+
+// Each slot is guarded so a replacement can wait for in-flight calls
+// (readers) to finish before the old instance is swapped out. Calls pass a
+// turnstile first; a replacement holds it while it waits, so new calls queue
+// behind the replacement instead of starving it. `caps` is what was granted
+// when the instance was registered (or replaced); later meta() answers do not
+// widen it.
+struct Slot<T: ?Sized> {
+    plugin: RwLock<Box<T>>,
+    turnstile: Mutex<()>,
+    caps: AtomicU32,
+}
+
+impl<T: ?Sized> Slot<T> {
+    fn new(plugin: Box<T>, caps: u32) -> Self {
+        Slot { plugin: RwLock::new(plugin), turnstile: Mutex::new(()), caps: AtomicU32::new(caps) }
+    }
+
+    fn granted(&self) -> u32 {
+        self.caps.load(Ordering::Acquire)
+    }
+}
+
 pub struct Registry {
-    filters: HashMap<&'static str, Box<dyn FilterPlugin>>,
-    handlers: HashMap<&'static str, Box<dyn HandlerPlugin>>,
+    filters: HashMap<&'static str, Slot<dyn FilterPlugin>>,
+    handlers: HashMap<&'static str, Slot<dyn HandlerPlugin>>,
+    backends: HostBackends,
+    denials: Arc<AtomicU64>,
+    configs: RwLock<HashMap<&'static str, HashMap<String, String>>>, // last cfg per key
 }
 
 impl Registry {
     pub fn new() -> Self {
-        Self { filters: HashMap::new(), handlers: HashMap::new() }
+        Self { filters: HashMap::new(), handlers: HashMap::new(), backends: HostBackends::default(), denials: Arc::new(AtomicU64::new(0)), configs: RwLock::new(HashMap::new()) }
+    }
+
+    // Services handed to plugins registered after this call.
+    pub fn with_host(mut self, backends: HostBackends) -> Self {
+        self.backends = backends;
+        self
     }
 
-    pub fn register_filter(&mut self, key: &'static str, plugin: Box<dyn FilterPlugin>) -> Result<(), String> {
+    pub fn register_filter(&mut self, key: &'static str, mut plugin: Box<dyn FilterPlugin>) -> Result<(), String> {
         if self.filters.contains_key(key) {
             return Err(format!("filter key '{}' already registered", key));
         }
-        self.filters.insert(key, plugin);
+        let granted = plugin.meta().caps;
+        plugin.attach(self.services(key, granted));
+        self.filters.insert(key, Slot::new(plugin, granted));
         Ok(())
     }
 
-    pub fn register_handler(&mut self, key: &'static str, plugin: Box<dyn HandlerPlugin>) -> Result<(), String> {
+    pub fn register_handler(&mut self, key: &'static str, mut plugin: Box<dyn HandlerPlugin>) -> Result<(), String> {
         if self.handlers.contains_key(key) {
             return Err(format!("handler key '{}' already registered", key));
         }
-        self.handlers.insert(key, plugin);
+        let granted = plugin.meta().caps;
+        plugin.attach(self.services(key, granted));
+        self.handlers.insert(key, Slot::new(plugin, granted));
         Ok(())
     }
 
-    pub fn init_all(&mut self, cfgs: &HashMap<String, HashMap<String, String>>) -> Result<(), String> {
-        for (k, p) in self.filters.iter_mut() {
+    // (key, granted caps) for every plugin, sorted by key: the reviewable surface.
+    pub fn capabilities(&self) -> Vec<(&'static str, u32)> {
+        let mut out: Vec<(&'static str, u32)> = self
+            .filters
+            .iter()
+            .map(|(k, p)| (*k, p.granted()))
+            .chain(self.handlers.iter().map(|(k, p)| (*k, p.granted())))
+            .collect();
+        out.sort();
+        out
+    }
+
+    // Undeclared capability uses rejected so far (host services and path mutations).
+    pub fn capability_denials(&self) -> u64 {
+        self.denials.load(Ordering::Relaxed)
+    }
+
+    fn services(&self, key: &'static str, caps: u32) -> HostServices {
+        HostServices { plugin: key, caps, backends: self.backends.clone(), denials: self.denials.clone() }
+    }
+
+    // Initializes every plugin even after a failure; the report lists all of
+    // them (ordered by key) plus config sections that match no plugin.
+    pub fn init_all(&mut self, cfgs: &HashMap<String, HashMap<String, String>>) -> Result<(), ErrorReport> {
+        let mut report = ErrorReport::new();
+        let mut filters: Vec<_> = self.filters.iter_mut().collect();
+        filters.sort_by_key(|(k, _)| **k);
+        let configs = self.configs.get_mut().unwrap_or_else(PoisonError::into_inner);
+        for (k, p) in filters {
             let cfg = cfgs.get(*k).cloned().unwrap_or_default();
-            p.init(&cfg)?;
+            if let Err(e) = slot_mut(p).init(&cfg) {
+                report.push(Issue::error(k, e));
+            }
+            configs.insert(*k, cfg);
         }
-        for (k, p) in self.handlers.iter_mut() {
+        let mut handlers: Vec<_> = self.handlers.iter_mut().collect();
+        handlers.sort_by_key(|(k, _)| **k);
+        for (k, p) in handlers {
             let cfg = cfgs.get(*k).cloned().unwrap_or_default();
-            p.init(&cfg)?;
+            if let Err(e) = slot_mut(p).init(&cfg) {
+                report.push(Issue::error(k, e));
+            }
+            configs.insert(*k, cfg);
         }
-        Ok(())
+        let mut unknown: Vec<&String> = cfgs.keys().filter(|k| !self.has_filter(k) && !self.has_handler(k)).collect();
+        unknown.sort();
+        for k in unknown {
+            report.push(Issue::warning(k, "config section matches no registered plugin"));
+        }
+        report.into_result(())
     }
 
+    pub fn has_filter(&self, key: &str) -> bool {
+        self.filters.contains_key(key)
+    }
+
+    pub fn has_handler(&self, key: &str) -> bool {
+        self.handlers.contains_key(key)
+    }
+
+    // Enforces READ_BODY (body hidden) and MUTATE_PATH (undeclared rewrite is
+    // rejected with 500 rather than silently applied).
     pub fn filter(&self, key: &str, req: &Request) -> FilterVerdict {
-        if let Some(p) = self.filters.get(key) {
-            p.process(req)
-        } else {
-            FilterVerdict::Continue
+        let Some((k, p)) = self.filters.get_key_value(key) else { return FilterVerdict::Continue };
+        let _crumb = crash::plugin_scope(k);
+        let plugin = slot_read(p);
+        let granted = p.granted();
+        match plugin.process(&body_view(req, granted)) {
+            FilterVerdict::Mutate(mut m) => {
+                if m.path != req.path && granted & caps::MUTATE_PATH == 0 {
+                    self.denials.fetch_add(1, Ordering::Relaxed);
+                    let msg = format!("plugin '{}' did not declare capability 'mutate_path'", k);
+                    return FilterVerdict::ShortCircuit(json_error(500, "capability_denied", &msg));
+                }
+                if granted & caps::READ_BODY == 0 {
+                    m.body = req.body.clone();
+                }
+                FilterVerdict::Mutate(m)
+            }
+            v => v,
         }
     }
 
+    // A request whose client already went away (cancel::scope) is answered
+    // 499 without running the handler.
     pub fn handle(&self, key: &str, req: &Request) -> Option<HandlerResult> {
-        self.handlers.get(key).map(|p| p.handle(req))
+        self.handlers.get_key_value(key).map(|(k, p)| {
+            if crate::cancel::is_cancelled() {
+                return HandlerResult { resp: json_error(499, "client_abort", "client closed request"), meta_flags: 0 };
+            }
+            let _crumb = crash::plugin_scope(k);
+            let plugin = slot_read(p);
+            plugin.handle(&body_view(req, p.granted()))
+        })
+    }
+
+    // Live replace of either kind; see replace_filter/replace_handler.
+    pub fn replace(&self, key: &str, plugin: Plugin, cfg: &HashMap<String, String>, grace: Duration) -> Result<(), String> {
+        match plugin {
+            Plugin::Filter(new) => self.replace_filter(key, new, cfg, grace),
+            Plugin::Handler(new) => self.replace_handler(key, new, cfg, grace),
+        }
+    }
+
+    // Live replace: the new instance must be the same plugin (meta().name; the
+    // version may differ). We wait up to `grace` for in-flight calls on the old
+    // one to drain, then, still holding the slot, run on_reload(previous cfg,
+    // `cfg`) so it migrates everything the old instance wrote, swap, and tear
+    // the old one down. On a failed hook, a name mismatch or a timeout the old
+    // instance stays in service and the discarded replacement is torn down.
+    pub fn replace_filter(&self, key: &str, mut new: Box<dyn FilterPlugin>, cfg: &HashMap<String, String>, grace: Duration) -> Result<(), String> {
+        let (k, slot) = self.filters.get_key_value(key).ok_or_else(|| format!("filter key '{}' not registered", key))?;
+        same_plugin("filter", k, slot_read(slot).meta(), new.meta())?;
+        let granted = new.meta().caps;
+        new.attach(self.services(k, granted));
+        let Some(mut guard) = quiesce(slot, grace) else {
+            new.teardown();
+            return Err(format!("filter key '{}' still busy after grace period", key));
+        };
+        let mut configs = self.configs.write().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = new.on_reload(&configs.get(k).cloned().unwrap_or_default(), cfg) {
+            drop((configs, guard));
+            new.teardown();
+            return Err(e);
+        }
+        configs.insert(k, cfg.clone());
+        slot.caps.store(granted, Ordering::Release);
+        let mut old = std::mem::replace(&mut *guard, new);
+        drop((configs, guard));
+        old.teardown();
+        Ok(())
+    }
+
+    pub fn replace_handler(&self, key: &str, mut new: Box<dyn HandlerPlugin>, cfg: &HashMap<String, String>, grace: Duration) -> Result<(), String> {
+        let (k, slot) = self.handlers.get_key_value(key).ok_or_else(|| format!("handler key '{}' not registered", key))?;
+        same_plugin("handler", k, slot_read(slot).meta(), new.meta())?;
+        let granted = new.meta().caps;
+        new.attach(self.services(k, granted));
+        let Some(mut guard) = quiesce(slot, grace) else {
+            new.teardown();
+            return Err(format!("handler key '{}' still busy after grace period", key));
+        };
+        let mut configs = self.configs.write().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = new.on_reload(&configs.get(k).cloned().unwrap_or_default(), cfg) {
+            drop((configs, guard));
+            new.teardown();
+            return Err(e);
+        }
+        configs.insert(k, cfg.clone());
+        slot.caps.store(granted, Ordering::Release);
+        let mut old = std::mem::replace(&mut *guard, new);
+        drop((configs, guard));
+        old.teardown();
+        Ok(())
+    }
+
+    // Config the plugin under `key` was last (re)initialized with; empty before init_all.
+    pub fn config(&self, key: &str) -> HashMap<String, String> {
+        self.configs.read().unwrap_or_else(PoisonError::into_inner).get(key).cloned().unwrap_or_default()
     }
 
     pub fn teardown_all(&mut self) {
         for (_, p) in self.filters.iter_mut() {
-            p.teardown();
+            slot_mut(p).teardown();
         }
         for (_, p) in self.handlers.iter_mut() {
-            p.teardown();
+            slot_mut(p).teardown();
+        }
+    }
+}
+
+fn same_plugin(kind: &str, key: &str, old: PluginMeta, new: PluginMeta) -> Result<(), String> {
+    if old.name != new.name {
+        return Err(format!("{} key '{}' runs plugin '{}', not '{}'", kind, key, old.name, new.name));
+    }
+    Ok(())
+}
+
+// Plugins without READ_BODY get a copy of the request with the body removed.
+fn body_view(req: &Request, granted: u32) -> Cow<'_, Request> {
+    if granted & caps::READ_BODY != 0 || req.body.is_empty() {
+        return Cow::Borrowed(req);
+    }
+    Cow::Owned(Request { method: req.method, path: req.path, headers: req.headers.clone(), body: Vec::new(), tenant: req.tenant })
+}
+
+// A panicking plugin poisons its slot; keep serving rather than cascading.
+fn slot_read<T: ?Sized>(slot: &Slot<T>) -> RwLockReadGuard<'_, Box<T>> {
+    drop(slot.turnstile.lock().unwrap_or_else(PoisonError::into_inner));
+    slot.plugin.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn slot_mut<T: ?Sized>(slot: &mut Slot<T>) -> &mut Box<T> {
+    slot.plugin.get_mut().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Holds the turnstile so only calls already in flight remain, and waits up
+// to `grace` for them to finish.
+fn quiesce<T: ?Sized>(slot: &Slot<T>, grace: Duration) -> Option<RwLockWriteGuard<'_, Box<T>>> {
+    let deadline = Instant::now() + grace;
+    let _turnstile = slot.turnstile.lock().unwrap_or_else(PoisonError::into_inner);
+    loop {
+        match slot.plugin.try_write() {
+            Ok(g) => return Some(g),
+            Err(TryLockError::Poisoned(e)) => return Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => {
+                if Instant::now() >= deadline {
+                    return None;
+                }
+                std::thread::sleep(Duration::from_millis(1));
+            }
         }
     }
 }
 
 // ---------------------------- Deterministic helpers -------------------------
 
+// Request and PluginMeta fields are 'static; hosts building them from plugin
+// descriptors or config intern each distinct string once instead of leaking
+// it on every use. Interned strings are never freed, so only bounded sets
+// belong here: never intern wire data (paths, header values) directly.
+pub fn intern(s: &str) -> &'static str {
+    static STRINGS: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    let mut set = STRINGS.get_or_init(|| Mutex::new(HashSet::new())).lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(s) = set.get(s) {
+        return s;
+    }
+    let s: &'static str = Box::leak(s.to_string().into_boxed_str());
+    set.insert(s);
+    s
+}
+
 pub fn add_header(resp: &mut Response, k: &str, v: &str) {
     resp.headers.push((k.to_string(), v.to_string()));
 }
@@ -151,6 +497,106 @@ pub fn set_body(resp: &mut Response, bytes: &[u8]) {
     resp.body.extend_from_slice(bytes);
 }
 
+// Fields that must never be sent as trailers (RFC 9110 6.5.1): framing,
+// routing, auth, and fields that control how the body is processed.
+const FORBIDDEN_TRAILERS: [&str; 12] = [
+    "content-length", "transfer-encoding", "content-type", "content-encoding", "content-range",
+    "host", "authorization", "set-cookie", "cache-control", "expires", "trailer", "te",
+];
+
+// Announce a trailer field in the `Trailer` header; required before set_trailer.
+pub fn declare_trailer(resp: &mut Response, name: &str) -> Result<(), String> {
+    if FORBIDDEN_TRAILERS.iter().any(|f| f.eq_ignore_ascii_case(name)) {
+        return Err(format!("'{}' is not allowed as a trailer", name));
+    }
+    if declared_trailers(resp).iter().any(|d| d.eq_ignore_ascii_case(name)) {
+        return Ok(());
+    }
+    match resp.headers.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case("trailer")) {
+        Some((_, v)) => {
+            v.push_str(", ");
+            v.push_str(name);
+        }
+        None => add_header(resp, "Trailer", name),
+    }
+    Ok(())
+}
+
+// Set a trailer value once the body is complete (e.g. Server-Timing, a digest).
+pub fn set_trailer(resp: &mut Response, name: &str, value: &str) -> Result<(), String> {
+    if !declared_trailers(resp).iter().any(|d| d.eq_ignore_ascii_case(name)) {
+        return Err(format!("trailer '{}' was not declared", name));
+    }
+    resp.trailers.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+    resp.trailers.push((name.to_string(), value.to_string()));
+    Ok(())
+}
+
+fn declared_trailers(resp: &Response) -> Vec<String> {
+    resp.headers
+        .iter()
+        .filter(|(k, _)| k.eq_ignore_ascii_case("trailer"))
+        .flat_map(|(_, v)| v.split(',').map(|t| t.trim().to_string()))
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+// Internal header carrying early hints to the edge, which strips it and sends
+// them as a `103 Early Hints` ahead of later responses for the same path
+// (edge/http/earlyhints.go).
+pub const EARLY_HINT_HEADER: &str = "X-OLWSX-Early-Hint";
+
+// Ask for a Link (`</app.css>; rel=preload; as=style`) to be sent in a
+// `103 Early Hints`. It is also sent as a plain Link header on this response.
+pub fn early_hint(resp: &mut Response, link: &str) -> Result<(), String> {
+    let link = link.trim();
+    if !link.starts_with('<') || !link.contains('>') || link.bytes().any(|b| b.is_ascii_control()) {
+        return Err(format!("invalid Link value '{}'", link));
+    }
+    add_header(resp, "Link", link);
+    add_header(resp, EARLY_HINT_HEADER, link);
+    Ok(())
+}
+
+// Internal response header asking the edge to pace this response's body to at
+// most N bytes/s (edge/http/throttle.go). Never forwarded to clients; it can
+// only lower the rate the edge would otherwise apply.
+pub const THROTTLE_HEADER: &str = "X-OLWSX-Throttle";
+
+// Cap the body rate of `resp`. Repeated calls keep the lowest rate.
+pub fn throttle(resp: &mut Response, bytes_per_sec: u32) {
+    let bytes_per_sec = bytes_per_sec.max(1);
+    for (k, v) in resp.headers.iter_mut() {
+        if k.eq_ignore_ascii_case(THROTTLE_HEADER) {
+            let lowest = v.parse::<u32>().map_or(bytes_per_sec, |cur| cur.min(bytes_per_sec));
+            *v = lowest.to_string();
+            return;
+        }
+    }
+    add_header(resp, THROTTLE_HEADER, &bytes_per_sec.to_string());
+}
+
+// Add a component to the current request's cache key (auth scope, A/B bucket,
+// feature flag...). Ok(false) when the response is not being cached.
+pub fn vary_cache_key(name: &str, value: &str) -> Result<bool, String> {
+    crate::cache_key::vary(name, value)
+}
+
+// True once the client of the current request disconnected; long-running
+// filters and handlers should poll it and return early.
+pub fn cancelled() -> bool {
+    crate::cancel::is_cancelled()
+}
+
+// Runtime feature flags (flags::install'ed store); None when unset.
+pub fn flag(name: &str) -> Option<String> {
+    crate::flags::value(name)
+}
+
+pub fn flag_enabled(name: &str) -> bool {
+    crate::flags::enabled(name)
+}
+
 pub fn json(bytes: &[u8]) -> Response {
     let mut r = Response::new(200);
     add_header(&mut r, "Content-Type", "application/json");
@@ -158,9 +604,323 @@ pub fn json(bytes: &[u8]) -> Response {
     r
 }
 
+// ------------------------------- JSON helpers -------------------------------
+// Dependency-free JSON values for handler plugins. Objects keep insertion
+// order so serialized output is deterministic (cache- and test-friendly).
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn obj() -> Self {
+        Json::Obj(Vec::new())
+    }
+
+    pub fn arr() -> Self {
+        Json::Arr(Vec::new())
+    }
+
+    // Builder: set (or overwrite) a key on an object; no-op on other variants.
+    pub fn set<V: Into<Json>>(mut self, key: &str, value: V) -> Self {
+        if let Json::Obj(fields) = &mut self {
+            let value = value.into();
+            match fields.iter_mut().find(|(k, _)| k == key) {
+                Some(slot) => slot.1 = value,
+                None => fields.push((key.to_string(), value)),
+            }
+        }
+        self
+    }
+
+    // Builder: append to an array; no-op on other variants.
+    pub fn push<V: Into<Json>>(mut self, value: V) -> Self {
+        if let Json::Arr(items) = &mut self {
+            items.push(value.into());
+        }
+        self
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = String::new();
+        self.write(&mut out);
+        out.into_bytes()
+    }
+
+    fn write(&self, out: &mut String) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Json::Int(i) => out.push_str(&i.to_string()),
+            Json::Float(f) if f.is_finite() => out.push_str(&f.to_string()),
+            Json::Float(_) => out.push_str("null"),
+            Json::Str(s) => write_json_str(out, s),
+            Json::Arr(items) => {
+                out.push('[');
+                for (i, v) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    v.write(out);
+                }
+                out.push(']');
+            }
+            Json::Obj(fields) => {
+                out.push('{');
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_json_str(out, k);
+                    out.push(':');
+                    v.write(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn write_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+impl From<bool> for Json {
+    fn from(v: bool) -> Self { Json::Bool(v) }
+}
+impl From<i64> for Json {
+    fn from(v: i64) -> Self { Json::Int(v) }
+}
+impl From<i32> for Json {
+    fn from(v: i32) -> Self { Json::Int(v as i64) }
+}
+impl From<u32> for Json {
+    fn from(v: u32) -> Self { Json::Int(v as i64) }
+}
+impl From<usize> for Json {
+    fn from(v: usize) -> Self { Json::Int(v as i64) }
+}
+impl From<f64> for Json {
+    fn from(v: f64) -> Self { Json::Float(v) }
+}
+impl From<&str> for Json {
+    fn from(v: &str) -> Self { Json::Str(v.to_string()) }
+}
+impl From<String> for Json {
+    fn from(v: String) -> Self { Json::Str(v) }
+}
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(v: Vec<T>) -> Self { Json::Arr(v.into_iter().map(Into::into).collect()) }
+}
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(v: Option<T>) -> Self { v.map(Into::into).unwrap_or(Json::Null) }
+}
+
+pub fn json_ok(value: &Json) -> Response {
+    json_status(200, value)
+}
+
+pub fn json_status(status: u16, value: &Json) -> Response {
+    let mut r = json(&value.to_bytes());
+    r.status = status;
+    r
+}
+
+// {"error":{"code":"...","message":"..."}}
+pub fn json_error(status: u16, code: &str, msg: &str) -> Response {
+    let body = Json::obj().set("error", Json::obj().set("code", code).set("message", msg));
+    json_status(status, &body)
+}
+
+// {"data":[...],"page":n,"per_page":n,"total":n,"has_more":bool}
+pub fn json_page(items: Vec<Json>, page: usize, per_page: usize, total: usize) -> Response {
+    let has_more = page.saturating_mul(per_page) < total;
+    let body = Json::obj()
+        .set("data", Json::Arr(items))
+        .set("page", page)
+        .set("per_page", per_page)
+        .set("total", total)
+        .set("has_more", has_more);
+    json_ok(&body)
+}
+
+// ---------------------------- Content negotiation ---------------------------
+// Accept / Accept-Language with q-values (RFC 9110 12.4-12.5). Weights are
+// kept as integer thousandths so ordering is exact and deterministic.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QItem {
+    pub value: String,
+    pub q: u16, // 0..=1000
+}
+
+// Parse a q-list; sorted by weight descending, header order kept on ties.
+pub fn parse_qlist(header: &str) -> Vec<QItem> {
+    let mut items: Vec<QItem> = header
+        .split(',')
+        .filter_map(|part| {
+            let mut params = part.split(';');
+            let value = params.next()?.trim().to_ascii_lowercase();
+            if value.is_empty() {
+                return None;
+            }
+            let mut q = 1000;
+            for p in params {
+                if let Some((k, v)) = p.split_once('=')
+                    && k.trim().eq_ignore_ascii_case("q")
+                {
+                    q = parse_qvalue(v.trim()).unwrap_or(0);
+                }
+            }
+            Some(QItem { value, q })
+        })
+        .collect();
+    items.sort_by_key(|i| std::cmp::Reverse(i.q));
+    items
+}
+
+fn parse_qvalue(v: &str) -> Option<u16> {
+    let (int, frac) = v.split_once('.').unwrap_or((v, ""));
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let whole: u16 = match int {
+        "0" => 0,
+        "1" => 1000,
+        _ => return None,
+    };
+    let thousandths: u16 = format!("{:0<3}", frac).parse().ok()?;
+    let q = whole + thousandths;
+    if q > 1000 { None } else { Some(q) }
+}
+
+// Best media type among `offers` for an Accept header; None if nothing is acceptable.
+// The most specific matching range decides an offer's weight (type/sub > type/* > */*).
+pub fn negotiate<'a>(accept: &str, offers: &[&'a str]) -> Option<&'a str> {
+    let ranges = parse_qlist(accept);
+    if ranges.is_empty() {
+        return offers.first().copied();
+    }
+    best(offers, |offer| {
+        let offer = offer.to_ascii_lowercase();
+        let (otype, _) = offer.split_once('/').unwrap_or((offer.as_str(), ""));
+        ranges
+            .iter()
+            .filter_map(|r| {
+                let spec = if r.value == offer {
+                    3
+                } else if r.value.strip_suffix("/*") == Some(otype) {
+                    2
+                } else if r.value == "*/*" {
+                    1
+                } else {
+                    return None;
+                };
+                Some((spec, r.q))
+            })
+            .max_by_key(|(spec, _)| *spec)
+            .map(|(_, q)| q)
+    })
+}
+
+// Best language tag among `offers` for Accept-Language (basic prefix filtering).
+pub fn negotiate_language<'a>(accept_language: &str, offers: &[&'a str]) -> Option<&'a str> {
+    let ranges = parse_qlist(accept_language);
+    if ranges.is_empty() {
+        return offers.first().copied();
+    }
+    best(offers, |offer| {
+        let offer = offer.to_ascii_lowercase();
+        ranges
+            .iter()
+            .filter_map(|r| {
+                let matches = r.value == "*"
+                    || r.value == offer
+                    || (offer.starts_with(&r.value) && offer.as_bytes().get(r.value.len()) == Some(&b'-'));
+                if matches { Some((r.value.len(), r.q)) } else { None }
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, q)| q)
+    })
+}
+
+fn best<'a, F: Fn(&str) -> Option<u16>>(offers: &[&'a str], weight: F) -> Option<&'a str> {
+    let mut pick: Option<(&'a str, u16)> = None;
+    for o in offers {
+        if let Some(q) = weight(o)
+            && q > 0
+            && pick.map(|(_, bq)| q > bq).unwrap_or(true)
+        {
+            pick = Some((o, q));
+        }
+    }
+    pick.map(|(o, _)| o)
+}
+
+// Case-insensitive request header lookup (first occurrence).
+pub fn header<'a>(req: &'a Request, name: &str) -> Option<&'a str> {
+    req.headers.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+}
+
+// Client certificate verified by the edge's mTLS listener: subject CN and
+// SHA-256 fingerprint (hex). The edge drops client-sent copies; absent when
+// no certificate was verified.
+pub const CLIENT_CERT_CN_HEADER: &str = "x-olwsx-client-cert-cn";
+pub const CLIENT_CERT_FINGERPRINT_HEADER: &str = "x-olwsx-client-cert-fingerprint";
+
+// (CN, fingerprint) of the verified client certificate, if any.
+pub fn client_cert(req: &Request) -> Option<(&str, &str)> {
+    Some((header(req, CLIENT_CERT_CN_HEADER).unwrap_or(""), header(req, CLIENT_CERT_FINGERPRINT_HEADER)?))
+}
+
+// Path parameters captured by the router (routing/router.rs) travel as
+// request headers under this prefix. HTTP/1 header names cannot contain ':',
+// but other transports may pass such names through, so hosts strip them
+// from every request they accept (strip_params).
+pub const PARAM_PREFIX: &str = ":param:";
+
+// Drops `:param:` headers from a request as received, so `param` only ever
+// sees what the router attached.
+pub fn strip_params(req: &mut Request) {
+    req.headers.retain(|(k, _)| !k.starts_with(PARAM_PREFIX));
+}
+
+// Path parameter `name` of the matched route (`/users/{id}` -> "id").
+pub fn param<'a>(req: &'a Request, name: &str) -> Option<&'a str> {
+    req.headers
+        .iter()
+        .find(|(k, _)| k.strip_prefix(PARAM_PREFIX) == Some(name))
+        .map(|(_, v)| v.as_str())
+}
+
+// negotiate() against the request's Accept header (absent header accepts anything).
+pub fn negotiate_request<'a>(req: &Request, offers: &[&'a str]) -> Option<&'a str> {
+    negotiate(header(req, "Accept").unwrap_or(""), offers)
+}
+
 // ------------------------------- Example wire API ---------------------------
 // Note: The core loads plugins and invokes registry via a thin ABI boundary.
 // In OLWSX, ABI is fixed; here we expose a pure Rust surface for in-process use.
+// Shared-library plugins cross that boundary through loader.rs (NativePlugin).
 
 #[cfg(test)]
 mod tests {
@@ -168,14 +928,14 @@ mod tests {
 
     struct NopFilter;
     impl FilterPlugin for NopFilter {
-        fn meta(&self) -> PluginMeta { PluginMeta { name: "nop_filter", version: "1.0.0", author: "OLWSX", flags: 0 } }
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "nop_filter", version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
         fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
         fn process(&self, _req: &Request) -> FilterVerdict { FilterVerdict::Continue }
     }
 
     struct EchoHandler;
     impl HandlerPlugin for EchoHandler {
-        fn meta(&self) -> PluginMeta { PluginMeta { name: "echo_handler", version: "1.0.0", author: "OLWSX", flags: 0x0010_0000 } }
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "echo_handler", version: "1.0.0", author: "OLWSX", flags: 0x0010_0000, caps: caps::READ_BODY } }
         fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
         fn handle(&self, req: &Request) -> HandlerResult {
             let mut r = Response::new(200);
@@ -203,4 +963,287 @@ mod tests {
         assert_eq!(out.resp.body, b"hi".to_vec());
         reg.teardown_all();
     }
-}
\ No newline at end of file
+
+    struct FixedHandler(&'static [u8]);
+    impl HandlerPlugin for FixedHandler {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "fixed_handler", version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
+        fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
+        fn handle(&self, _req: &Request) -> HandlerResult {
+            let mut r = Response::new(200);
+            set_body(&mut r, self.0);
+            HandlerResult { resp: r, meta_flags: 0 }
+        }
+    }
+
+    #[test]
+    fn live_replace() {
+        let mut reg = Registry::new();
+        reg.register_handler("h", Box::new(FixedHandler(b"v1"))).unwrap();
+        let req = Request { method: "GET", path: "/", headers: vec![], body: vec![], tenant: "default" };
+        assert_eq!(reg.handle("h", &req).unwrap().resp.body, b"v1".to_vec());
+
+        reg.replace("h", Plugin::Handler(Box::new(FixedHandler(b"v2"))), &HashMap::new(), Duration::from_millis(50)).unwrap();
+        assert_eq!(reg.handle("h", &req).unwrap().resp.body, b"v2".to_vec());
+
+        assert!(reg.replace("missing", Plugin::Filter(Box::new(NopFilter)), &HashMap::new(), Duration::ZERO).is_err());
+    }
+
+    struct MemState(std::sync::Mutex<HashMap<String, Vec<u8>>>);
+    impl StateStore for MemState {
+        fn get(&self, key: &str) -> Option<Vec<u8>> { self.0.lock().unwrap().get(key).cloned() }
+        fn set(&self, key: &str, value: Vec<u8>) { self.0.lock().unwrap().insert(key.to_string(), value); }
+    }
+
+    // Counts requests under "<ns>.hits" in the host state store.
+    struct Counter { version: &'static str, ns: String, host: Option<HostServices>, delay: Duration }
+    impl Counter {
+        fn new(version: &'static str) -> Self { Counter { version, ns: String::new(), host: None, delay: Duration::ZERO } }
+        fn slow(mut self, delay: Duration) -> Self { self.delay = delay; self }
+        fn store(&self) -> &dyn StateStore { self.host.as_ref().unwrap().state().unwrap() }
+        fn hits(&self, ns: &str) -> u64 {
+            self.store().get(&format!("{}.hits", ns)).map(|b| u64::from_be_bytes(b.try_into().unwrap())).unwrap_or(0)
+        }
+    }
+    impl HandlerPlugin for Counter {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "counter", version: self.version, author: "OLWSX", flags: 0, caps: caps::STATE } }
+        fn init(&mut self, cfg: &HashMap<String, String>) -> Result<(), String> {
+            self.ns = cfg.get("ns").cloned().ok_or_else(|| "missing 'ns'".to_string())?;
+            Ok(())
+        }
+        fn attach(&mut self, host: HostServices) { self.host = Some(host); }
+        fn on_reload(&mut self, old_cfg: &HashMap<String, String>, new_cfg: &HashMap<String, String>) -> Result<(), String> {
+            self.init(new_cfg)?;
+            let carried = self.hits(&old_cfg["ns"]);
+            self.store().set(&format!("{}.hits", self.ns), carried.to_be_bytes().to_vec());
+            Ok(())
+        }
+        fn handle(&self, _req: &Request) -> HandlerResult {
+            let n = self.hits(&self.ns) + 1;
+            std::thread::sleep(self.delay);
+            self.store().set(&format!("{}.hits", self.ns), n.to_be_bytes().to_vec());
+            let mut r = Response::new(200);
+            set_body(&mut r, format!("{} {}", self.version, n).as_bytes());
+            HandlerResult { resp: r, meta_flags: 0 }
+        }
+    }
+
+    #[test]
+    fn reload_migrates_state() {
+        let state = Arc::new(MemState(std::sync::Mutex::new(HashMap::new())));
+        let mut reg = Registry::new().with_host(HostBackends { state: Some(state), ..HostBackends::default() });
+        reg.register_handler("count", Box::new(Counter::new("1.0.0"))).unwrap();
+        let cfg = |ns: &str| HashMap::from([("ns".to_string(), ns.to_string())]);
+        reg.init_all(&HashMap::from([("count".to_string(), cfg("a"))])).unwrap();
+        let req = Request { method: "GET", path: "/", headers: vec![], body: vec![], tenant: "default" };
+        reg.handle("count", &req);
+        assert_eq!(reg.handle("count", &req).unwrap().resp.body, b"1.0.0 2".to_vec());
+
+        // a failing hook or a different plugin leaves the old instance serving
+        assert!(reg.replace_handler("count", Box::new(Counter::new("2.0.0")), &HashMap::new(), Duration::ZERO).is_err());
+        assert!(reg.replace_handler("count", Box::new(FixedHandler(b"x")), &cfg("b"), Duration::ZERO).unwrap_err().contains("runs plugin 'counter'"));
+        assert_eq!(reg.config("count"), cfg("a"));
+
+        reg.replace_handler("count", Box::new(Counter::new("2.0.0")), &cfg("b"), Duration::from_millis(50)).unwrap();
+        assert_eq!(reg.handle("count", &req).unwrap().resp.body, b"2.0.0 3".to_vec());
+        assert_eq!(reg.config("count"), cfg("b"));
+        assert!(reg.replace_filter("count", Box::new(NopFilter), &cfg("b"), Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn reload_migrates_after_in_flight_calls() {
+        let state = Arc::new(MemState(std::sync::Mutex::new(HashMap::new())));
+        let mut reg = Registry::new().with_host(HostBackends { state: Some(state), ..HostBackends::default() });
+        reg.register_handler("count", Box::new(Counter::new("1.0.0").slow(Duration::from_millis(60)))).unwrap();
+        let cfg = |ns: &str| HashMap::from([("ns".to_string(), ns.to_string())]);
+        reg.init_all(&HashMap::from([("count".to_string(), cfg("a"))])).unwrap();
+        let reg = Arc::new(reg);
+        let req = Request { method: "GET", path: "/", headers: vec![], body: vec![], tenant: "default" };
+
+        let (r, q) = (Arc::clone(&reg), req.clone());
+        let in_flight = std::thread::spawn(move || r.handle("count", &q).unwrap().resp.body);
+        std::thread::sleep(Duration::from_millis(20));
+        reg.replace_handler("count", Box::new(Counter::new("2.0.0")), &cfg("b"), Duration::from_secs(2)).unwrap();
+        assert_eq!(in_flight.join().unwrap(), b"1.0.0 1".to_vec());
+        // the hit the old instance recorded while draining was carried over
+        assert_eq!(reg.handle("count", &req).unwrap().resp.body, b"2.0.0 2".to_vec());
+    }
+
+    #[test]
+    fn replace_is_not_starved_by_steady_calls() {
+        let state = Arc::new(MemState(std::sync::Mutex::new(HashMap::new())));
+        let mut reg = Registry::new().with_host(HostBackends { state: Some(state), ..HostBackends::default() });
+        reg.register_handler("count", Box::new(Counter::new("1.0.0").slow(Duration::from_millis(5)))).unwrap();
+        let cfg = HashMap::from([("ns".to_string(), "a".to_string())]);
+        reg.init_all(&HashMap::from([("count".to_string(), cfg.clone())])).unwrap();
+        let reg = Arc::new(reg);
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let callers: Vec<_> = (0..4)
+            .map(|_| {
+                let (r, s) = (Arc::clone(&reg), Arc::clone(&stop));
+                std::thread::spawn(move || {
+                    let req = Request { method: "GET", path: "/", headers: vec![], body: vec![], tenant: "default" };
+                    while !s.load(Ordering::Relaxed) {
+                        r.handle("count", &req);
+                    }
+                })
+            })
+            .collect();
+        std::thread::sleep(Duration::from_millis(20));
+        let replaced = reg.replace_handler("count", Box::new(Counter::new("2.0.0")), &cfg, Duration::from_secs(2));
+        stop.store(true, Ordering::Relaxed);
+        for c in callers {
+            c.join().unwrap();
+        }
+        replaced.unwrap();
+    }
+
+    #[test]
+    fn trailers() {
+        let mut r = Response::new(200);
+        declare_trailer(&mut r, "Server-Timing").unwrap();
+        declare_trailer(&mut r, "Digest").unwrap();
+        assert!(declare_trailer(&mut r, "Content-Length").is_err());
+        assert!(set_trailer(&mut r, "X-Other", "1").is_err());
+        set_trailer(&mut r, "server-timing", "app;dur=12").unwrap();
+        set_trailer(&mut r, "Server-Timing", "app;dur=15").unwrap();
+        assert_eq!(r.headers, vec![("Trailer".to_string(), "Server-Timing, Digest".to_string())]);
+        assert_eq!(r.trailers, vec![("Server-Timing".to_string(), "app;dur=15".to_string())]);
+    }
+
+    #[test]
+    fn throttle_keeps_the_lowest_rate() {
+        let mut r = Response::new(200);
+        throttle(&mut r, 65536);
+        throttle(&mut r, 131072);
+        throttle(&mut r, 8192);
+        assert_eq!(r.headers, vec![(THROTTLE_HEADER.to_string(), "8192".to_string())]);
+    }
+
+    #[test]
+    fn early_hints() {
+        let mut r = Response::new(200);
+        early_hint(&mut r, " </app.css>; rel=preload; as=style").unwrap();
+        assert!(early_hint(&mut r, "/app.js").is_err());
+        assert!(early_hint(&mut r, "</a.js>\r\nSet-Cookie: x=1").is_err());
+        assert_eq!(r.headers.len(), 2);
+        assert_eq!(r.headers[1], (EARLY_HINT_HEADER.to_string(), "</app.css>; rel=preload; as=style".to_string()));
+    }
+
+    #[test]
+    fn forged_params_are_stripped() {
+        let mut r = Request { method: "GET", path: "/items/1", headers: vec![(":param:id".into(), "forged".into()), ("Accept".into(), "*/*".into())], body: vec![], tenant: "default" };
+        strip_params(&mut r);
+        assert_eq!(param(&r, "id"), None);
+        assert_eq!(r.headers.len(), 1);
+    }
+
+    #[test]
+    fn json_helpers() {
+        let v = Json::obj()
+            .set("name", "a\"b\n")
+            .set("n", 3)
+            .set("tags", vec!["x", "y"])
+            .set("none", Option::<i64>::None)
+            .set("n", 4);
+        assert_eq!(v.to_bytes(), br#"{"name":"a\"b\n","n":4,"tags":["x","y"],"none":null}"#.to_vec());
+
+        let e = json_error(404, "not_found", "no such user");
+        assert_eq!(e.status, 404);
+        assert_eq!(e.body, br#"{"error":{"code":"not_found","message":"no such user"}}"#.to_vec());
+
+        let p = json_page(vec![Json::Int(1), Json::Int(2)], 1, 2, 5);
+        assert_eq!(p.body, br#"{"data":[1,2],"page":1,"per_page":2,"total":5,"has_more":true}"#.to_vec());
+    }
+
+    #[test]
+    fn content_negotiation() {
+        let offers = ["application/json", "text/html"];
+        assert_eq!(negotiate("text/html;q=0.9, application/json;q=0.95", &offers), Some("application/json"));
+        assert_eq!(negotiate("text/*, application/json;q=0.5", &offers), Some("text/html"));
+        assert_eq!(negotiate("*/*;q=0.1, application/json;q=0", &offers), Some("text/html"));
+        assert_eq!(negotiate("image/png", &offers), None);
+        assert_eq!(negotiate("", &offers), Some("application/json"));
+
+        let langs = ["en-US", "fa-IR", "de"];
+        assert_eq!(negotiate_language("fa, en;q=0.8", &langs), Some("fa-IR"));
+        assert_eq!(negotiate_language("de-CH, *;q=0.1", &langs), Some("en-US"));
+        assert_eq!(parse_qlist("a;q=0.5, b, c;q=1.5")[0].value, "b");
+
+        let req = Request { method: "GET", path: "/", headers: vec![("accept".to_string(), "text/html".to_string())], body: vec![], tenant: "default" };
+        assert_eq!(negotiate_request(&req, &offers), Some("text/html"));
+    }
+
+    struct MemCache(std::sync::Mutex<HashMap<Vec<u8>, Vec<u8>>>);
+    impl HostCache for MemCache {
+        fn get(&self, key: &[u8]) -> Option<Vec<u8>> { self.0.lock().unwrap().get(key).cloned() }
+        fn put(&self, key: &[u8], value: Vec<u8>, _ttl: Duration) { self.0.lock().unwrap().insert(key.to_vec(), value); }
+    }
+
+    // Declares only CACHE; tries to rewrite the path and to use the network,
+    // and claims more once attached.
+    struct Sneaky(Option<HostServices>);
+    impl FilterPlugin for Sneaky {
+        fn meta(&self) -> PluginMeta {
+            let later = if self.0.is_some() { caps::MUTATE_PATH | caps::READ_BODY } else { 0 };
+            PluginMeta { name: "sneaky", version: "1.0.0", author: "OLWSX", flags: 0, caps: caps::CACHE | later }
+        }
+        fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
+        fn attach(&mut self, host: HostServices) { self.0 = Some(host); }
+        fn process(&self, req: &Request) -> FilterVerdict {
+            let host = self.0.as_ref().unwrap();
+            assert!(req.body.is_empty(), "body hidden without READ_BODY");
+            assert!(host.network().is_err());
+            host.cache().unwrap().put(b"seen", req.path.as_bytes().to_vec(), Duration::from_secs(1));
+            let mut m = req.clone();
+            if req.path == "/rewrite" {
+                m.path = "/elsewhere";
+            }
+            FilterVerdict::Mutate(m)
+        }
+    }
+
+    #[test]
+    fn capabilities_enforced() {
+        let cache = Arc::new(MemCache(std::sync::Mutex::new(HashMap::new())));
+        let mut reg = Registry::new().with_host(HostBackends { cache: Some(cache.clone()), ..HostBackends::default() });
+        reg.register_filter("sneaky", Box::new(Sneaky(None))).unwrap();
+        reg.register_handler("echo", Box::new(EchoHandler)).unwrap();
+        assert_eq!(reg.capabilities(), vec![("echo", caps::READ_BODY), ("sneaky", caps::CACHE)]);
+        assert_eq!(caps::names(caps::CACHE | caps::READ_BODY), vec!["read_body", "cache"]);
+
+        let req = Request { method: "POST", path: "/keep", headers: vec![], body: b"secret".to_vec(), tenant: "default" };
+        match reg.filter("sneaky", &req) {
+            FilterVerdict::Mutate(m) => assert_eq!(m.body, b"secret".to_vec(), "body restored after the hidden view"),
+            v => panic!("unexpected {:?}", v),
+        }
+        assert_eq!(cache.get(b"seen"), Some(b"/keep".to_vec()));
+
+        let rewrite = Request { path: "/rewrite", ..req };
+        assert!(matches!(reg.filter("sneaky", &rewrite), FilterVerdict::ShortCircuit(ref r) if r.status == 500));
+        assert_eq!(reg.capability_denials(), 3); // two network attempts, one rewrite
+        assert_eq!(reg.handle("echo", &rewrite).unwrap().resp.body, b"secret".to_vec());
+    }
+
+    #[test]
+    fn init_all_reports_every_failure() {
+        struct Strict(&'static str);
+        impl FilterPlugin for Strict {
+            fn meta(&self) -> PluginMeta { PluginMeta { name: self.0, version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
+            fn init(&mut self, cfg: &HashMap<String, String>) -> Result<(), String> {
+                cfg.get("limit").map(|_| ()).ok_or_else(|| "missing 'limit'".to_string())
+            }
+            fn process(&self, _req: &Request) -> FilterVerdict { FilterVerdict::Continue }
+        }
+        let mut reg = Registry::new();
+        reg.register_filter("b_strict", Box::new(Strict("b"))).unwrap();
+        reg.register_filter("a_strict", Box::new(Strict("a"))).unwrap();
+        reg.register_handler("echo", Box::new(EchoHandler)).unwrap();
+        let mut cfgs = HashMap::new();
+        cfgs.insert("typo_strict".to_string(), HashMap::new());
+
+        let report = reg.init_all(&cfgs).unwrap_err();
+        assert_eq!(report.issues.len(), 3);
+        assert_eq!(report.errors().count(), 2);
+        assert_eq!(report.to_string(), "error: a_strict: missing 'limit'\nerror: b_strict: missing 'limit'\nwarning: typo_strict: config section matches no registered plugin");
+    }
+}
@@ -0,0 +1,740 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: plugins/testsupport.rs
+// Role: In-process server for end-to-end tests of the request pipeline
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Assemble plugins, pipelines, WAF rule sets, rate limiter, response cache
+//   and a metrics registry the way the host does, from a test config.
+// - Two transports: `TestServer::send` (in-memory, no socket) and `listen`
+//   (HTTP/1.1 on an ephemeral 127.0.0.1 port, one request per connection;
+//   known methods only, and a capped set of distinct paths and tenants).
+// - Inspection for assertions: WAF decisions, cache entries, metric values.
+// - Request order is Pipeline::execute's: route (+ condition) -> ACL -> rate
+//   limit -> WAF -> filters (guards, cache key scope) -> response cache
+//   (httpcache.rs, on a standard L1/L2/L3 stack; PURGE answered here) ->
+//   handler -> metrics.
+// - Each request runs under a cancel scope; on the socket transport a
+//   watcher cancels it when the client disconnects, and the request is
+//   counted as a client abort (status 499) with no response written.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use crate::cache_key::{self, CacheKeyParts};
+use crate::header_transform::HeaderTransforms;
+use crate::cancel::{self, CancelToken};
+use crate::httpcache::{HttpCache, HttpCacheConfig, Lookup, Pending, PURGE_METHOD};
+use olwsx_cache::{TieredCache, L1, L2, L3};
+use crate::pipeline::{DispatchTable, Outcome, Pipeline, Policies};
+use olwsx_observability::{
+    Registry as Metrics, SampleValue, CACHE_HITS, CACHE_MISSES, CACHE_SHIELDED, CLIENT_ABORTS, LATENCY, REQUESTS, WAF_DECISIONS,
+};
+use olwsx_plugins_sdk::{
+    header, json_error, strip_params, Registry, Request, Response, CLIENT_CERT_CN_HEADER, CLIENT_CERT_FINGERPRINT_HEADER,
+};
+use olwsx_security::{Acl, Action, Decision, Engine, RateLimiter};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+mod olwsx_plugins_sdk {
+    pub use crate::sdk::{
+        header, json_error, strip_params, Registry, Request, Response, CLIENT_CERT_CN_HEADER, CLIENT_CERT_FINGERPRINT_HEADER,
+    };
+}
+
+mod olwsx_cache {
+    pub use cache::l1::L1;
+    pub use cache::l2::L2;
+    pub use cache::l3::L3;
+    pub use cache::TieredCache;
+}
+
+mod olwsx_security {
+    pub use crate::acl::Acl;
+    pub use crate::ratelimit::RateLimiter;
+    pub use crate::waf::{Action, Decision, Engine};
+}
+
+mod olwsx_observability {
+    pub use crate::registry::{Registry, SampleValue};
+    pub use crate::tenant_view::{CACHE_HITS, CACHE_MISSES, CACHE_SHIELDED, CLIENT_ABORTS, LATENCY, REQUESTS, WAF_DECISIONS};
+}
+
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+const DISCONNECT_POLL: Duration = Duration::from_millis(20);
+
+// A request as a test writes it; `ip` is the client address seen by the
+// ACL, the rate limiter and the WAF.
+#[derive(Clone, Debug)]
+pub struct TestRequest {
+    pub method: &'static str,
+    pub path: &'static str,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub tenant: &'static str,
+    pub ip: String,
+}
+
+impl TestRequest {
+    pub fn get(path: &'static str) -> Self {
+        Self::new("GET", path)
+    }
+
+    pub fn post(path: &'static str, body: &[u8]) -> Self {
+        let mut r = Self::new("POST", path);
+        r.body = body.to_vec();
+        r
+    }
+
+    pub fn new(method: &'static str, path: &'static str) -> Self {
+        Self { method, path, headers: Vec::new(), body: Vec::new(), tenant: "default", ip: "127.0.0.1".to_string() }
+    }
+
+    pub fn header(mut self, k: &str, v: &str) -> Self {
+        self.headers.push((k.to_string(), v.to_string()));
+        self
+    }
+
+    pub fn tenant(mut self, tenant: &'static str) -> Self {
+        self.tenant = tenant;
+        self
+    }
+
+    pub fn ip(mut self, ip: &str) -> Self {
+        self.ip = ip.to_string();
+        self
+    }
+}
+
+pub struct TestServerBuilder {
+    plugins: Registry,
+    pipelines: Vec<Pipeline>,
+    config: HashMap<String, HashMap<String, String>>,
+    policies: Policies,
+    cache: Option<HttpCacheConfig>,
+    metrics: Metrics,
+}
+
+impl TestServerBuilder {
+    pub fn pipeline(mut self, p: Pipeline) -> Self {
+        self.pipelines.push(p);
+        self
+    }
+
+    // Plugin config in the usual section form:
+    //   [plugin_key]
+    //   name = value
+    pub fn config(mut self, text: &str) -> Result<Self, String> {
+        let mut section: Option<String> = None;
+        for (n, raw) in text.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = Some(name.trim().to_string());
+                self.config.entry(name.trim().to_string()).or_default();
+                continue;
+            }
+            let (Some(s), Some((k, v))) = (&section, line.split_once('=')) else {
+                return Err(format!("config line {}: expected '[section]' or 'name = value'", n + 1));
+            };
+            self.config.entry(s.clone()).or_default().insert(k.trim().to_string(), v.trim().to_string());
+        }
+        Ok(self)
+    }
+
+    // Access list referenced by Pipeline::acl(name).
+    pub fn acl(mut self, name: &str, acl: Acl) -> Self {
+        self.policies = self.policies.acl(name, acl);
+        self
+    }
+
+    // Rule set referenced by Pipeline::waf(name).
+    pub fn waf(mut self, name: &str, engine: Engine) -> Self {
+        self.policies = self.policies.waf(name, engine);
+        self
+    }
+
+    // Transforms referenced by Pipeline::headers(name).
+    pub fn headers(mut self, name: &str, transforms: HeaderTransforms) -> Self {
+        self.policies = self.policies.headers(name, transforms);
+        self
+    }
+
+    // Applied to pipelines that set rate_limit(..).
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.policies = self.policies.rate_limiter(limiter);
+        self
+    }
+
+    // Cache eligible GET responses; `ttl` for those without max-age / s-maxage.
+    pub fn cache(mut self, ttl: Duration) -> Self {
+        self.cache.get_or_insert_with(HttpCacheConfig::default).default_ttl = Some(ttl);
+        self
+    }
+
+    // Collapse concurrent identical GETs onto one handler run; the others
+    // wait up to `wait` for its response.
+    pub fn coalesce(mut self, wait: Duration) -> Self {
+        self.cache.get_or_insert_with(HttpCacheConfig::default).coalesce = Some(wait);
+        self
+    }
+
+    // Keep 404s for `ttl`.
+    pub fn negative_ttl(mut self, ttl: Duration) -> Self {
+        self.cache.get_or_insert_with(HttpCacheConfig::default).negative_ttl = Some(ttl);
+        self
+    }
+
+    // Serve expired copies for `window` when the handler answers 5xx.
+    pub fn stale_if_error(mut self, window: Duration) -> Self {
+        self.cache.get_or_insert_with(HttpCacheConfig::default).stale_if_error = Some(window);
+        self
+    }
+
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn build(mut self) -> Result<TestServer, String> {
+        self.plugins.init_all(&self.config).map_err(|r| r.to_string())?;
+        for p in self.pipelines.iter() {
+            self.policies.check(p)?;
+        }
+        let table = DispatchTable::compile(self.pipelines, &self.plugins)?;
+        Ok(TestServer {
+            inner: Arc::new(Inner {
+                plugins: self.plugins,
+                table,
+                policies: self.policies,
+                cache: self.cache.map(|cfg| HttpCache::new(TieredCache::standard(L1::new(), L2::new(), L3::new()), cfg)),
+                decisions: Mutex::new(Vec::new()),
+                metrics: self.metrics,
+            }),
+        })
+    }
+}
+
+struct Inner {
+    plugins: Registry,
+    table: DispatchTable,
+    policies: Policies,
+    cache: Option<HttpCache>,
+    decisions: Mutex<Vec<Decision>>,
+    metrics: Metrics,
+}
+
+#[derive(Clone)]
+pub struct TestServer {
+    inner: Arc<Inner>,
+}
+
+impl TestServer {
+    pub fn builder(plugins: Registry) -> TestServerBuilder {
+        TestServerBuilder {
+            plugins,
+            pipelines: Vec::new(),
+            config: HashMap::new(),
+            policies: Policies::new(),
+            cache: None,
+            metrics: Metrics::new(),
+        }
+    }
+
+    // In-memory transport: runs the pipeline on the calling thread.
+    pub fn send(&self, req: TestRequest) -> Response {
+        self.send_with(req, &CancelToken::new())
+    }
+
+    // As `send`, with a token the test can cancel (from a plugin or another
+    // thread) to simulate the client going away mid-request.
+    pub fn send_with(&self, req: TestRequest, token: &CancelToken) -> Response {
+        let ip = req.ip.clone();
+        let req = Request { method: req.method, path: req.path, headers: req.headers, body: req.body, tenant: req.tenant };
+        self.inner.dispatch(req, &ip, token)
+    }
+
+    // Socket transport on 127.0.0.1:<ephemeral>; stopped when dropped.
+    pub fn listen(&self) -> std::io::Result<Listening> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let (inner, flag) = (Arc::clone(&self.inner), Arc::clone(&stop));
+        let thread = std::thread::spawn(move || {
+            for conn in listener.incoming() {
+                if flag.load(Ordering::Acquire) {
+                    break;
+                }
+                let Ok(stream) = conn else { continue };
+                let inner = Arc::clone(&inner);
+                std::thread::spawn(move || serve_conn(&inner, stream));
+            }
+        });
+        Ok(Listening { addr, stop, thread: Some(thread) })
+    }
+
+    // WAF decisions in request order (only requests that reached a rule set).
+    pub fn waf_decisions(&self) -> Vec<Decision> {
+        self.inner.decisions.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    // Cache keys currently stored: "<tenant>:<target>", the Vary components,
+    // then any plugin components.
+    pub fn cache_keys(&self) -> Vec<String> {
+        self.inner.cache.as_ref().map(|c| c.keys()).unwrap_or_default()
+    }
+
+    // Admin purge: every cached variant of `path` (a trailing `*` for a prefix).
+    pub fn purge(&self, tenant: &str, path: &str) -> usize {
+        self.inner.cache.as_ref().map_or(0, |c| c.purge(tenant, path))
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.inner.metrics
+    }
+
+    // Sum of counter `name` over series carrying all of `labels`.
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        let qualified = self.inner.metrics.qualify(name);
+        self.inner
+            .metrics
+            .gather()
+            .into_iter()
+            .filter(|s| s.name == qualified && labels.iter().all(|(k, v)| s.labels.iter().any(|(lk, lv)| lk == k && lv == v)))
+            .map(|s| match s.value {
+                SampleValue::Counter(n) => n,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+impl Inner {
+    fn dispatch(&self, mut req: Request, ip: &str, token: &CancelToken) -> Response {
+        let started = Instant::now();
+        strip_params(&mut req);
+        let tenant = req.tenant;
+        let (route, mut resp) = match self.table.lookup_request(&req) {
+            Some(p) => {
+                let _cancel = cancel::scope(token);
+                (p.route.clone(), self.run(p, req, ip))
+            }
+            None => (String::new(), json_error(404, "not_found", "no route")),
+        };
+        // whatever the plugins produced, nobody is there to receive it
+        if token.is_cancelled() {
+            resp = json_error(499, "client_abort", "client closed request");
+            self.count(CLIENT_ABORTS, &[("tenant", tenant), ("route", &route)]);
+        }
+        let status = resp.status.to_string();
+        self.count(REQUESTS, &[("tenant", tenant), ("route", &route), ("status", &status)]);
+        if let Ok(h) = self.metrics.histogram(LATENCY, &[("tenant", tenant)]) {
+            h.observe_us(started.elapsed().as_micros().min(u64::MAX as u128) as u64);
+        }
+        resp
+    }
+
+    // Pipeline::execute with the response cache between filters and handler.
+    fn run(&self, p: &Pipeline, req: Request, ip: &str) -> Response {
+        let scope = cache_key::scope();
+        let mut miss: Option<(CacheKeyParts, Pending)> = None;
+        let run = p.execute_with(&self.plugins, &self.policies, ip, req, Some(&self.metrics), |req| {
+            let parts = scope.finish();
+            let cache = self.cache.as_ref()?;
+            if req.method == PURGE_METHOD {
+                return Some(("cache", cache.purge_request(req)));
+            }
+            match cache.lookup(req, &parts) {
+                Lookup::Hit(r) => {
+                    self.count(CACHE_HITS, &[("tenant", req.tenant)]);
+                    Some(("cache", r))
+                }
+                Lookup::Miss(pending) => {
+                    self.count(CACHE_MISSES, &[("tenant", req.tenant)]);
+                    miss = Some((parts, pending));
+                    None
+                }
+                Lookup::Bypass => None,
+            }
+        });
+
+        if let Some(d) = run.waf {
+            let action = match d.action {
+                Action::Deny(_) => "deny",
+                Action::Challenge(_) => "challenge",
+                Action::LogOnly => "log_only",
+                Action::Allow => "allow",
+                Action::Throttle(_) => "throttle",
+            };
+            self.count(WAF_DECISIONS, &[("tenant", run.request.tenant), ("action", action)]);
+            self.decisions.lock().unwrap_or_else(|e| e.into_inner()).push(d);
+        }
+        match run.outcome {
+            Outcome::Handled(result) => {
+                let mut resp = result.resp;
+                if let (Some(cache), Some((parts, pending))) = (self.cache.as_ref(), miss) {
+                    let status = resp.status.to_string();
+                    if cache.store(&run.request, &parts, pending, &mut resp) {
+                        self.count(CACHE_SHIELDED, &[("tenant", run.request.tenant), ("status", &status)]);
+                    }
+                }
+                resp
+            }
+            Outcome::ShortCircuit(_, resp) => resp,
+            Outcome::NoHandler => json_error(500, "handler_missing", p.handler),
+        }
+    }
+
+    fn count(&self, name: &str, labels: &[(&str, &str)]) {
+        if let Ok(c) = self.metrics.counter(name, labels) {
+            c.inc();
+        }
+    }
+}
+
+pub struct Listening {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Listening {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn client(&self) -> Client {
+        Client { addr: self.addr }
+    }
+}
+
+impl Drop for Listening {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        // wake the accept loop so it sees the flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+// Minimal HTTP/1.1 client: one request per connection, Content-Length bodies.
+#[derive(Clone, Copy, Debug)]
+pub struct Client {
+    addr: SocketAddr,
+}
+
+impl Client {
+    pub fn get(&self, path: &str) -> std::io::Result<Response> {
+        self.send("GET", path, &[], b"")
+    }
+
+    pub fn send(&self, method: &str, path: &str, headers: &[(&str, &str)], body: &[u8]) -> std::io::Result<Response> {
+        let mut s = TcpStream::connect(self.addr)?;
+        s.set_read_timeout(Some(IO_TIMEOUT))?;
+        let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n", method, path, self.addr, body.len());
+        for (k, v) in headers {
+            head.push_str(&format!("{}: {}\r\n", k, v));
+        }
+        head.push_str("\r\n");
+        s.write_all(head.as_bytes())?;
+        s.write_all(body)?;
+        let mut r = BufReader::new(s);
+        let (start, headers, body) = read_message(&mut r)?;
+        let status = start.split(' ').nth(1).and_then(|c| c.parse().ok()).ok_or_else(|| bad_data("bad status line"))?;
+        let mut resp = Response::new(status);
+        resp.headers = headers.into_iter().filter(|(k, _)| !k.eq_ignore_ascii_case("content-length") && !k.eq_ignore_ascii_case("connection")).collect();
+        resp.body = body;
+        Ok(resp)
+    }
+}
+
+// Methods the socket transport accepts; anything else is answered 501.
+const METHODS: &[&str] = &["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS", PURGE_METHOD];
+
+// Distinct paths and tenants the socket transport will intern; past this
+// requests are refused rather than leaking without bound.
+const MAX_WIRE_STRINGS: usize = 4096;
+
+// Request carries 'static strs. Paths and tenants come off the wire, so unlike
+// sdk::intern the set is capped: None once MAX_WIRE_STRINGS are held.
+fn intern_wire(s: &str) -> Option<&'static str> {
+    static STRINGS: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    let mut set = STRINGS.get_or_init(|| Mutex::new(HashSet::new())).lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(s) = set.get(s) {
+        return Some(s);
+    }
+    if set.len() >= MAX_WIRE_STRINGS {
+        return None;
+    }
+    let s: &'static str = Box::leak(s.to_string().into_boxed_str());
+    set.insert(s);
+    Some(s)
+}
+
+fn serve_conn(inner: &Inner, stream: TcpStream) {
+    let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+    let ip = stream.peer_addr().map(|a| a.ip().to_string()).unwrap_or_default();
+    let Ok(writer) = stream.try_clone() else { return };
+    let mut r = BufReader::new(stream);
+    let resp = match read_message(&mut r) {
+        Ok((start, headers, body)) => {
+            let mut parts = start.split(' ');
+            match (parts.next(), parts.next()) {
+                (Some(m), Some(t)) => {
+                    let Some(method) = METHODS.iter().copied().find(|k| *k == m) else {
+                        let _ = write_response(writer, &json_error(501, "not_implemented", "unknown method"));
+                        return;
+                    };
+                    // no TLS here, so no verified client certificate either
+                    let headers = headers.into_iter().filter(|(k, _)| !k.eq_ignore_ascii_case(CLIENT_CERT_CN_HEADER) && !k.eq_ignore_ascii_case(CLIENT_CERT_FINGERPRINT_HEADER)).collect();
+                    let req = Request { method, path: "", headers, body, tenant: "default" };
+                    let (Some(path), Some(tenant)) = (intern_wire(t), header(&req, "x-olwsx-tenant").map_or(Some("default"), intern_wire)) else {
+                        let _ = write_response(writer, &json_error(503, "unavailable", "too many distinct paths or tenants"));
+                        return;
+                    };
+                    let token = CancelToken::new();
+                    let watcher = watch_disconnect(&writer, &token);
+                    let resp = inner.dispatch(Request { path, tenant, ..req }, &ip, &token);
+                    drop(watcher);
+                    if token.is_cancelled() {
+                        return;
+                    }
+                    resp
+                }
+                _ => json_error(400, "bad_request", "bad request line"),
+            }
+        }
+        Err(_) => json_error(400, "bad_request", "unreadable request"),
+    };
+    let _ = write_response(writer, &resp);
+}
+
+// Cancels `token` when the peer closes its side while the request is being
+// processed; stops polling when dropped.
+struct Watcher {
+    done: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+fn watch_disconnect(stream: &TcpStream, token: &CancelToken) -> Option<Watcher> {
+    let probe = stream.try_clone().ok()?;
+    probe.set_read_timeout(Some(DISCONNECT_POLL)).ok()?;
+    let done = Arc::new(AtomicBool::new(false));
+    let (flag, token) = (Arc::clone(&done), token.clone());
+    let thread = std::thread::spawn(move || {
+        let mut byte = [0u8; 1];
+        while !flag.load(Ordering::Acquire) {
+            match probe.peek(&mut byte) {
+                Ok(0) => return token.cancel(),
+                // pipelined bytes: the client is still there
+                Ok(_) => std::thread::sleep(DISCONNECT_POLL),
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+                Err(_) => return token.cancel(),
+            }
+        }
+    });
+    Some(Watcher { done, thread: Some(thread) })
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Release);
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+fn write_response(mut w: TcpStream, resp: &Response) -> std::io::Result<()> {
+    let mut head = format!("HTTP/1.1 {} {}\r\n", resp.status, reason(resp.status));
+    for (k, v) in resp.headers.iter() {
+        head.push_str(&format!("{}: {}\r\n", k, v));
+    }
+    head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", resp.body.len()));
+    w.write_all(head.as_bytes())?;
+    w.write_all(&resp.body)?;
+    w.flush()
+}
+
+type Message = (String, Vec<(String, String)>, Vec<u8>);
+
+fn read_message<R: BufRead>(r: &mut R) -> std::io::Result<Message> {
+    let mut start = String::new();
+    r.read_line(&mut start)?;
+    let mut headers = Vec::new();
+    let mut total = start.len();
+    loop {
+        let mut line = String::new();
+        if r.read_line(&mut line)? == 0 {
+            return Err(bad_data("connection closed in header"));
+        }
+        total += line.len();
+        if total > MAX_HEAD_BYTES {
+            return Err(bad_data("header too large"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (k, v) = line.split_once(':').ok_or_else(|| bad_data("bad header line"))?;
+        headers.push((k.trim().to_string(), v.trim().to_string()));
+    }
+    let len = headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+        .map(|(_, v)| v.parse::<usize>().map_err(|_| bad_data("bad content-length")))
+        .transpose()?
+        .unwrap_or(0);
+    let mut body = vec![0u8; len];
+    r.read_exact(&mut body)?;
+    Ok((start.trim_end().to_string(), headers, body))
+}
+
+fn bad_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdk::{set_body, vary_cache_key, FilterPlugin, FilterVerdict, HandlerPlugin, HandlerResult, PluginMeta};
+    use crate::waf::{Field, Matcher, Rule};
+    use crate::ratelimit::{Limit, RateLimitConfig};
+
+    struct Bucket;
+    impl FilterPlugin for Bucket {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "bucket", version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
+        fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
+        fn process(&self, req: &Request) -> FilterVerdict {
+            let _ = vary_cache_key("ab", header(req, "x-ab").unwrap_or("a"));
+            FilterVerdict::Continue
+        }
+    }
+
+    struct Greeter {
+        greeting: String,
+    }
+    impl HandlerPlugin for Greeter {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "greeter", version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
+        fn init(&mut self, cfg: &HashMap<String, String>) -> Result<(), String> {
+            self.greeting = cfg.get("greeting").cloned().ok_or("greeting is required")?;
+            Ok(())
+        }
+        fn handle(&self, req: &Request) -> HandlerResult {
+            let mut resp = Response::new(200);
+            set_body(&mut resp, format!("{} {}", self.greeting, req.path).as_bytes());
+            HandlerResult { resp, meta_flags: 0 }
+        }
+    }
+
+    #[test]
+    fn end_to_end() {
+        let mut plugins = Registry::new();
+        plugins.register_filter("bucket", Box::new(Bucket)).unwrap();
+        plugins.register_handler("greeter", Box::new(Greeter { greeting: String::new() })).unwrap();
+        let rules = vec![Rule { id: 1, field: Field::Path, matcher: Matcher::Contains("../".into()), action: Action::Deny(403), tags: vec!["traversal".into()], severity: 8 }];
+        let server = TestServer::builder(plugins)
+            .config("[greeter]\ngreeting = hello\n")
+            .unwrap()
+            .pipeline(Pipeline::for_route("/*").waf("base").filters(["bucket"]).rate_limit("ip").handler("greeter"))
+            .pipeline(Pipeline::for_route("/admin/*").acl("ops").handler("greeter"))
+            .acl("ops", Acl::parse("allow 127.0.0.0/8; deny all").unwrap())
+            .waf("base", Engine::new(rules))
+            .rate_limiter(RateLimiter::new(RateLimitConfig { ip: Some(Limit { burst: 5, refill_per_sec: 0.0 }), ..RateLimitConfig::default() }))
+            .cache(Duration::from_secs(60))
+            .build()
+            .unwrap();
+
+        let r = server.send(TestRequest::get("/home"));
+        assert_eq!((r.status, r.body.as_slice()), (200, b"hello /home".as_slice()));
+        let r = server.send(TestRequest::get("/home"));
+        assert!(r.headers.contains(&("X-Cache".to_string(), "HIT".to_string())));
+        server.send(TestRequest::get("/home").header("x-ab", "b"));
+        assert_eq!(
+            server.cache_keys(),
+            vec!["default:/home\0accept-encoding\0identity\0plugin:bucket.ab\0a", "default:/home\0accept-encoding\0identity\0plugin:bucket.ab\0b"]
+        );
+
+        let r = server.send(TestRequest::get("/a/../etc"));
+        assert_eq!(r.status, 403);
+        assert_eq!(server.waf_decisions().last().unwrap().applied_rule_id, Some(1));
+        assert_eq!(server.send(TestRequest::get("/admin/users").ip("203.0.113.9")).status, 403);
+
+        // same pipeline over a socket (client 127.0.0.1 has one token left)
+        let listening = server.listen().unwrap();
+        let r = listening.client().get("/wire").unwrap();
+        assert_eq!((r.status, r.body.as_slice()), (200, b"hello /wire".as_slice()));
+        assert_eq!(listening.client().get("/wire").unwrap().status, 429);
+        assert_eq!(listening.client().send("BREW", "/wire", &[], b"").unwrap().status, 501);
+
+        assert_eq!(server.counter(REQUESTS, &[("status", "200")]), 4);
+        assert_eq!(server.counter(CACHE_HITS, &[]), 1);
+        assert_eq!(server.counter(WAF_DECISIONS, &[("action", "deny")]), 1);
+        assert_eq!(server.purge("default", "/home"), 2);
+        assert_eq!(server.cache_keys(), vec!["default:/wire\0accept-encoding\0identity\0plugin:bucket.ab\0a"]);
+    }
+
+    // Waits for the client to go away (bounded), the way a long upstream call would.
+    struct Slow;
+    impl HandlerPlugin for Slow {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "slow", version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
+        fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
+        fn handle(&self, _req: &Request) -> HandlerResult {
+            let deadline = Instant::now() + IO_TIMEOUT;
+            while !crate::sdk::cancelled() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            HandlerResult { resp: Response::new(200), meta_flags: 0 }
+        }
+    }
+
+    #[test]
+    fn client_disconnect_cancels_the_request() {
+        let mut plugins = Registry::new();
+        plugins.register_handler("slow", Box::new(Slow)).unwrap();
+        let server = TestServer::builder(plugins).pipeline(Pipeline::for_route("/*").handler("slow")).build().unwrap();
+
+        let listening = server.listen().unwrap();
+        let mut s = TcpStream::connect(listening.addr()).unwrap();
+        s.write_all(b"GET /slow HTTP/1.1\r\nHost: test\r\nContent-Length: 0\r\n\r\n").unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        drop(s);
+
+        let deadline = Instant::now() + IO_TIMEOUT;
+        while server.counter(CLIENT_ABORTS, &[]) == 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(server.counter(CLIENT_ABORTS, &[("route", "/*")]), 1);
+        assert_eq!(server.counter(REQUESTS, &[("status", "499")]), 1);
+
+        // in memory: a token cancelled up front never reaches the handler
+        let token = CancelToken::new();
+        token.cancel();
+        assert_eq!(server.send_with(TestRequest::get("/x"), &token).status, 499);
+        assert_eq!(server.counter(CLIENT_ABORTS, &[]), 2);
+    }
+}
@@ -0,0 +1,422 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: routing/router.rs
+// Role: Request router (radix tree over path patterns, method matching)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Map (method, path) to a Pipeline: the per-route plugin chain (ACL, WAF
+//   ruleset, filters, rate limit, handler) as built by pipeline.rs, whose
+//   `route` is the pattern.
+// - Patterns: static text, `{name}` for one whole path segment, and a final
+//   `{*name}` (or bare `*`, named "*") for the rest of the path, possibly
+//   empty. `/users/{id}/posts/{*tail}`, `/static/*`.
+// - Static prefixes share radix nodes; at each node static children are
+//   tried before the parameter, and the parameter before the catch-all,
+//   backtracking when a branch dead-ends.
+// - Methods: a comma list or `*`; a GET route also answers HEAD. When the
+//   path matches but no route takes the method, the result lists the
+//   methods that would (for a 405 with Allow).
+// - Routes for the same pattern may differ by Pipeline condition; the first
+//   whose condition holds wins, in insertion order.
+// - Captured params reach handlers through the Request: `Params::attach`
+//   adds them as `:param:<name>` headers, read with `sdk::param`. Values are
+//   left percent-encoded, like `Request::path`. Hosts drop client-sent
+//   `:param:` headers at ingress (`sdk::strip_params`) whichever dispatch
+//   they use.
+// =============================================================================
+
+#![forbid(unsafe_code)]
+
+use olwsx_plugins_pipeline::Pipeline;
+use olwsx_plugins_sdk::{strip_params, Registry, Request, PARAM_PREFIX};
+
+mod olwsx_plugins_sdk {
+    pub use crate::sdk::{strip_params, Registry, Request, PARAM_PREFIX};
+}
+
+mod olwsx_plugins_pipeline {
+    pub use crate::pipeline::Pipeline;
+}
+
+#[derive(Clone, Debug)]
+struct Route {
+    methods: Vec<String>, // uppercase; empty = any
+    pipeline: Pipeline,
+}
+
+impl Route {
+    fn allows(&self, method: &str) -> bool {
+        self.methods.is_empty() || self.methods.iter().any(|m| m == method || (m == "GET" && method == "HEAD"))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Node {
+    prefix: String,
+    children: Vec<Node>, // static; first chars are distinct
+    param: Option<(String, Box<Node>)>,
+    catch_all: Option<(String, Vec<usize>)>,
+    routes: Vec<usize>, // indexes into Router::routes ending here
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Static(String),
+    Param(String),
+    CatchAll(String),
+}
+
+// Captured path parameters, in pattern order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Params(pub Vec<(String, String)>);
+
+impl Params {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    // Replaces any `:param:` headers already on the request.
+    pub fn attach(&self, req: &mut Request) {
+        strip_params(req);
+        for (k, v) in self.0.iter() {
+            req.headers.push((format!("{}{}", PARAM_PREFIX, k), v.clone()));
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Routed<'a> {
+    Found(&'a Pipeline, Params),
+    MethodNotAllowed(Vec<String>), // for the Allow header
+    NotFound,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Router {
+    root: Node,
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Router over (methods, pipeline) pairs, each pipeline checked against
+    // the registry like DispatchTable::compile.
+    pub fn compile(routes: Vec<(&str, Pipeline)>, reg: &Registry) -> Result<Self, String> {
+        let mut router = Router::new();
+        for (methods, p) in routes {
+            p.validate(reg)?;
+            router.add(methods, p)?;
+        }
+        Ok(router)
+    }
+
+    // Rejects malformed patterns, and parameters that would shadow each other
+    // (`/u/{id}` next to `/u/{name}`).
+    pub fn add(&mut self, methods: &str, pipeline: Pipeline) -> Result<(), String> {
+        let tokens = parse(&pipeline.route)?;
+        let spec = methods.trim();
+        let methods: Vec<String> = match spec {
+            "*" => Vec::new(),
+            list => list.split(',').map(|m| m.trim().to_ascii_uppercase()).filter(|m| !m.is_empty()).collect(),
+        };
+        if methods.is_empty() && spec != "*" {
+            return Err(format!("route '{}': no methods", pipeline.route));
+        }
+        let idx = self.routes.len();
+        insert(&mut self.root, &tokens, idx).map_err(|e| format!("route '{}': {}", pipeline.route, e))?;
+        self.routes.push(Route { methods, pipeline });
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    // Route for the request's method and path (query ignored); pipeline
+    // conditions are evaluated against it.
+    pub fn route(&self, req: &Request) -> Routed<'_> {
+        let path = req.path.split('?').next().unwrap_or("");
+        let mut m = Matcher { router: self, req, allowed: Vec::new(), params: Vec::new() };
+        match m.walk(&self.root, path) {
+            Some(i) => {
+                let params = m.params.into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+                Routed::Found(&self.routes[i].pipeline, Params(params))
+            }
+            None if !m.allowed.is_empty() => Routed::MethodNotAllowed(m.allowed),
+            None => Routed::NotFound,
+        }
+    }
+}
+
+fn parse(pattern: &str) -> Result<Vec<Token>, String> {
+    if !pattern.starts_with('/') {
+        return Err(format!("route '{}': must start with '/'", pattern));
+    }
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        if c == '*' && rest.len() == 1 {
+            tokens.push(Token::Static(std::mem::take(&mut text)));
+            tokens.push(Token::CatchAll("*".to_string()));
+            break;
+        }
+        if c != '{' {
+            text.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let end = rest.find('}').ok_or_else(|| format!("route '{}': unclosed '{{'", pattern))?;
+        let name = &rest[1..end];
+        let after = &rest[end + 1..];
+        if !text.ends_with('/') {
+            return Err(format!("route '{}': parameter must start a segment", pattern));
+        }
+        tokens.push(Token::Static(std::mem::take(&mut text)));
+        match name.strip_prefix('*') {
+            Some(n) if valid_name(n) && after.is_empty() => tokens.push(Token::CatchAll(n.to_string())),
+            Some(_) => return Err(format!("route '{}': catch-all must be a named final segment", pattern)),
+            None if valid_name(name) && (after.is_empty() || after.starts_with('/')) => tokens.push(Token::Param(name.to_string())),
+            None => return Err(format!("route '{}': bad parameter '{{{}}}'", pattern, name)),
+        }
+        rest = after;
+    }
+    if !text.is_empty() {
+        tokens.push(Token::Static(text));
+    }
+    tokens.retain(|t| *t != Token::Static(String::new()));
+    Ok(tokens)
+}
+
+fn valid_name(n: &str) -> bool {
+    !n.is_empty() && n.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn insert(node: &mut Node, tokens: &[Token], idx: usize) -> Result<(), String> {
+    let Some((first, rest)) = tokens.split_first() else {
+        node.routes.push(idx);
+        return Ok(());
+    };
+    match first {
+        Token::Static(s) => insert_static(node, s, rest, idx),
+        Token::Param(name) => {
+            let (existing, child) = node.param.get_or_insert_with(|| (name.clone(), Box::default()));
+            if existing != name {
+                return Err(format!("parameter '{{{}}}' conflicts with '{{{}}}'", name, existing));
+            }
+            insert(child, rest, idx)
+        }
+        Token::CatchAll(name) => {
+            let (existing, routes) = node.catch_all.get_or_insert_with(|| (name.clone(), Vec::new()));
+            if existing != name {
+                return Err(format!("catch-all '{}' conflicts with '{}'", name, existing));
+            }
+            routes.push(idx);
+            Ok(())
+        }
+    }
+}
+
+fn insert_static(node: &mut Node, s: &str, rest: &[Token], idx: usize) -> Result<(), String> {
+    let first = s.chars().next();
+    let Some(pos) = node.children.iter().position(|c| c.prefix.chars().next() == first) else {
+        let mut child = Node { prefix: s.to_string(), ..Node::default() };
+        insert(&mut child, rest, idx)?;
+        node.children.push(child);
+        return Ok(());
+    };
+    let child = &mut node.children[pos];
+    let common = common_prefix(&child.prefix, s);
+    if common < child.prefix.len() {
+        // split: the shared part becomes the parent of the old child
+        let tail = Node { prefix: child.prefix[common..].to_string(), ..std::mem::take(child) };
+        *child = Node { prefix: s[..common].to_string(), children: vec![tail], ..Node::default() };
+    }
+    if common == s.len() {
+        insert(child, rest, idx)
+    } else {
+        insert_static(child, &s[common..], rest, idx)
+    }
+}
+
+// Length in bytes of the shared prefix, backed off to a char boundary
+// ("é" and "è" share a lead byte but no char).
+fn common_prefix(a: &str, b: &str) -> usize {
+    let mut n = a.bytes().zip(b.bytes()).take_while(|(x, y)| x == y).count();
+    while !a.is_char_boundary(n) {
+        n -= 1;
+    }
+    n
+}
+
+struct Matcher<'r, 'p> {
+    router: &'r Router,
+    req: &'r Request,
+    allowed: Vec<String>,
+    params: Vec<(&'r str, &'p str)>,
+}
+
+impl<'r, 'p> Matcher<'r, 'p> {
+    // `path` is what remains after `node.prefix`.
+    fn walk(&mut self, node: &'r Node, path: &'p str) -> Option<usize> {
+        if path.is_empty()
+            && let Some(i) = self.accept(&node.routes)
+        {
+            return Some(i);
+        }
+        for c in node.children.iter() {
+            if let Some(rest) = path.strip_prefix(c.prefix.as_str())
+                && let Some(i) = self.walk(c, rest)
+            {
+                return Some(i);
+            }
+        }
+        if let Some((name, child)) = &node.param {
+            let end = path.find('/').unwrap_or(path.len());
+            if end > 0 {
+                self.params.push((name, &path[..end]));
+                if let Some(i) = self.walk(child, &path[end..]) {
+                    return Some(i);
+                }
+                self.params.pop();
+            }
+        }
+        if let Some((name, routes)) = &node.catch_all {
+            self.params.push((name, path));
+            if let Some(i) = self.accept(routes) {
+                return Some(i);
+            }
+            self.params.pop();
+        }
+        None
+    }
+
+    // First route taking the method whose condition holds; methods of the
+    // others are remembered for a 405.
+    fn accept(&mut self, routes: &[usize]) -> Option<usize> {
+        for &i in routes.iter() {
+            let r = &self.router.routes[i];
+            if !r.pipeline.condition.as_ref().is_none_or(|c| c.matches(self.req)) {
+                continue;
+            }
+            if r.allows(self.req.method) {
+                return Some(i);
+            }
+            for m in r.methods.iter() {
+                if !self.allowed.contains(m) {
+                    self.allowed.push(m.clone());
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::Expr;
+    use crate::sdk::{param, HandlerPlugin, HandlerResult, PluginMeta, Response};
+    use std::collections::HashMap;
+
+    fn req(method: &'static str, path: &'static str) -> Request {
+        Request { method, path, headers: vec![], body: vec![], tenant: "default" }
+    }
+
+    fn handler_of(r: Routed<'_>) -> Option<(&'static str, Params)> {
+        match r {
+            Routed::Found(p, params) => Some((p.handler, params)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn static_params_and_catch_all() {
+        let mut r = Router::new();
+        r.add("GET", Pipeline::for_route("/users").handler("list")).unwrap();
+        r.add("GET", Pipeline::for_route("/users/new").handler("form")).unwrap();
+        r.add("GET,PUT", Pipeline::for_route("/users/{id}").handler("user")).unwrap();
+        r.add("GET", Pipeline::for_route("/users/{id}/posts/{post}").handler("post")).unwrap();
+        r.add("*", Pipeline::for_route("/static/{*file}").handler("files")).unwrap();
+        r.add("GET", Pipeline::for_route("/u*").handler("short")).unwrap();
+
+        assert_eq!(handler_of(r.route(&req("GET", "/users"))).unwrap().0, "list");
+        assert_eq!(handler_of(r.route(&req("GET", "/users/new"))).unwrap().0, "form");
+        let (h, p) = handler_of(r.route(&req("PUT", "/users/42?x=1"))).unwrap();
+        assert_eq!((h, p.get("id")), ("user", Some("42")));
+        let (h, p) = handler_of(r.route(&req("HEAD", "/users/7/posts/9"))).unwrap();
+        assert_eq!((h, p.0.clone()), ("post", vec![("id".into(), "7".into()), ("post".into(), "9".into())]));
+        let (h, p) = handler_of(r.route(&req("DELETE", "/static/css/site.css"))).unwrap();
+        assert_eq!((h, p.get("file")), ("files", Some("css/site.css")));
+        assert_eq!(handler_of(r.route(&req("GET", "/static/"))).unwrap().1.get("file"), Some(""));
+        // "/users/new/posts/1" backtracks from the static node to {id}
+        assert_eq!(handler_of(r.route(&req("GET", "/users/new/posts/1"))).unwrap().1.get("id"), Some("new"));
+        let (h, p) = handler_of(r.route(&req("GET", "/uploads"))).unwrap();
+        assert_eq!((h, p.get("*")), ("short", Some("ploads")));
+
+        assert!(matches!(r.route(&req("DELETE", "/users/42")), Routed::MethodNotAllowed(ref m) if *m == ["GET", "PUT"]));
+        assert!(matches!(r.route(&req("GET", "/other")), Routed::NotFound));
+        assert!(matches!(r.route(&req("GET", "/static")), Routed::NotFound));
+    }
+
+    #[test]
+    fn routes_sharing_a_utf8_lead_byte() {
+        let mut r = Router::new();
+        for (route, h) in [("/é", "e_acute"), ("/è", "e_grave"), ("/éa", "e_acute_a")] {
+            r.add("GET", Pipeline::for_route(route).handler(h)).unwrap();
+        }
+        assert_eq!(handler_of(r.route(&req("GET", "/é"))).unwrap().0, "e_acute");
+        assert_eq!(handler_of(r.route(&req("GET", "/è"))).unwrap().0, "e_grave");
+        assert_eq!(handler_of(r.route(&req("GET", "/éa"))).unwrap().0, "e_acute_a");
+        assert_eq!(common_prefix("/é", "/è"), 1);
+    }
+
+    #[test]
+    fn rejects_bad_and_conflicting_patterns() {
+        let mut r = Router::new();
+        r.add("GET", Pipeline::for_route("/u/{id}").handler("a")).unwrap();
+        assert!(r.add("GET", Pipeline::for_route("/u/{name}/x").handler("b")).is_err());
+        for bad in ["users", "/u{id}", "/u/{id", "/u/{*rest}/x", "/u/{a-b}", "/u/{id}x"] {
+            assert!(r.add("GET", Pipeline::for_route(bad).handler("c")).is_err(), "{}", bad);
+        }
+        assert_eq!(r.len(), 1);
+    }
+
+    struct Echo;
+    impl HandlerPlugin for Echo {
+        fn meta(&self) -> PluginMeta { PluginMeta { name: "echo", version: "1.0.0", author: "OLWSX", flags: 0, caps: 0 } }
+        fn init(&mut self, _cfg: &HashMap<String, String>) -> Result<(), String> { Ok(()) }
+        fn handle(&self, req: &Request) -> HandlerResult {
+            let mut resp = Response::new(200);
+            resp.body = param(req, "id").unwrap_or("-").as_bytes().to_vec();
+            HandlerResult { resp, meta_flags: 0 }
+        }
+    }
+
+    #[test]
+    fn params_reach_the_handler_and_conditions_pick_routes() {
+        let mut reg = Registry::new();
+        reg.register_handler("echo", Box::new(Echo)).unwrap();
+        let beta = Pipeline::for_route("/items/{id}").when(Expr::condition(r#"req.header("x-beta") == "1""#).unwrap()).handler("echo");
+        let router = Router::compile(vec![("GET", beta), ("GET", Pipeline::for_route("/items/{id}").handler("echo"))], &reg).unwrap();
+        assert!(Router::compile(vec![("GET", Pipeline::for_route("/x").handler("missing"))], &reg).is_err());
+
+        let mut r = req("GET", "/items/a1");
+        r.headers.push((":param:id".into(), "forged".into()));
+        let Routed::Found(p, params) = router.route(&r) else { panic!("no route") };
+        assert!(p.condition.is_none());
+        params.attach(&mut r);
+        assert_eq!(reg.handle(p.handler, &r).unwrap().resp.body, b"a1");
+
+        r.headers.push(("X-Beta".into(), "1".into()));
+        assert!(matches!(router.route(&r), Routed::Found(p, _) if p.condition.is_some()));
+    }
+}
@@ -0,0 +1,138 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/acl.rs
+// Role: Final & Stable access control lists (network + method, first match)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Declarative ACL text: "allow 10.0.0.0/8 methods GET,HEAD; deny all".
+// - First matching entry wins; no match allows (nginx semantics).
+// - Evaluated against the parsed trusted client ClientAddr, before plugins run.
+// =============================================================================
+
+use crate::addr::ClientAddr;
+use std::net::IpAddr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Deny,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl Cidr {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (a, p) = match s.split_once('/') {
+            Some((a, p)) => (a, Some(p)),
+            None => (s, None),
+        };
+        let addr: IpAddr = a.parse().map_err(|_| format!("invalid address '{}'", a))?;
+        let addr = ClientAddr::new(addr).ip();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match p {
+            Some(p) => p.parse::<u8>().map_err(|_| format!("invalid prefix '{}'", p))?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(format!("prefix /{} too long for {}", prefix, addr));
+        }
+        Ok(Self { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: ClientAddr) -> bool {
+        match (self.addr, ip.ip()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => masked(u32::from(net) as u128, 32, self.prefix) == masked(u32::from(ip) as u128, 32, self.prefix),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => masked(u128::from(net), 128, self.prefix) == masked(u128::from(ip), 128, self.prefix),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AclEntry {
+    pub verdict: Verdict,
+    pub network: Option<Cidr>,         // None = all
+    pub methods: Option<Vec<String>>,  // None = any method (uppercase)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Acl {
+    entries: Vec<AclEntry>,
+}
+
+impl Acl {
+    pub fn new(entries: Vec<AclEntry>) -> Self {
+        Self { entries }
+    }
+
+    // Grammar: entry (";" entry)*
+    //          entry := ("allow" | "deny") (cidr | "all") ["methods" M1,M2,...]
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut entries = Vec::new();
+        for (i, stmt) in text.split(';').enumerate() {
+            let toks: Vec<&str> = stmt.split_whitespace().collect();
+            if toks.is_empty() {
+                continue;
+            }
+            let verdict = match toks[0] {
+                "allow" => Verdict::Allow,
+                "deny" => Verdict::Deny,
+                other => return Err(format!("entry {}: expected allow/deny, got '{}'", i + 1, other)),
+            };
+            let target = toks.get(1).ok_or_else(|| format!("entry {}: missing network", i + 1))?;
+            let network = if *target == "all" {
+                None
+            } else {
+                Some(Cidr::parse(target).map_err(|e| format!("entry {}: {}", i + 1, e))?)
+            };
+            let methods = match &toks[2..] {
+                [] => None,
+                ["methods", list] => Some(list.split(',').filter(|m| !m.is_empty()).map(|m| m.to_ascii_uppercase()).collect()),
+                _ => return Err(format!("entry {}: unexpected '{}'", i + 1, toks[2..].join(" "))),
+            };
+            entries.push(AclEntry { verdict, network, methods });
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn check(&self, ip: ClientAddr, method: &str) -> Verdict {
+        for e in self.entries.iter() {
+            let net_ok = e.network.as_ref().map(|n| n.contains(ip)).unwrap_or(true);
+            let method_ok = e.methods.as_ref().map(|ms| ms.iter().any(|m| m.eq_ignore_ascii_case(method))).unwrap_or(true);
+            if net_ok && method_ok {
+                return e.verdict;
+            }
+        }
+        Verdict::Allow
+    }
+}
+
+fn masked(v: u128, bits: u8, prefix: u8) -> u128 {
+    if prefix == 0 {
+        return 0;
+    }
+    let shift = (bits - prefix) as u32;
+    (v >> shift) << shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acl() {
+        let acl = Acl::parse("allow 10.0.0.0/8; allow 2001:db8::/32 methods GET,HEAD; deny all").unwrap();
+        assert_eq!(acl.check("10.1.2.3".parse::<ClientAddr>().unwrap(), "POST"), Verdict::Allow);
+        assert_eq!(acl.check("::ffff:10.1.2.3".parse::<ClientAddr>().unwrap(), "GET"), Verdict::Allow);
+        assert_eq!(acl.check("2001:db8::1".parse::<ClientAddr>().unwrap(), "GET"), Verdict::Allow);
+        assert_eq!(acl.check("2001:db8::1".parse::<ClientAddr>().unwrap(), "DELETE"), Verdict::Deny);
+        assert_eq!(acl.check("203.0.113.9".parse::<ClientAddr>().unwrap(), "GET"), Verdict::Deny);
+        assert!(Acl::parse("allow 10.0.0.0/33").is_err());
+        assert!(Acl::parse("permit all").is_err());
+    }
+}
@@ -0,0 +1,172 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/adaptive.rs
+// Role: Final & Stable adaptive protection (WAF deny rate -> tighter limits)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Track WAF outcomes per scope (route or tenant) in fixed windows.
+// - Trip into protection when the deny ratio crosses `trip_pct`; leave only
+//   after the cool-down AND once the ratio fell to `clear_pct` (hysteresis).
+// - While tripped, report a scaled rate-limit factor and optional challenge.
+// - Every automatic change is recorded as an audit event.
+// =============================================================================
+
+use crate::waf::{Action, Decision};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+const MAX_AUDIT: usize = 1024; // bounded audit backlog (oldest dropped)
+
+#[derive(Clone, Debug)]
+pub struct AdaptiveConfig {
+    pub window_ms: u64,
+    pub min_requests: u64, // windows with fewer requests are not evaluated
+    pub trip_pct: u8,      // deny ratio that enables protection
+    pub clear_pct: u8,     // deny ratio required to relax (< trip_pct)
+    pub limit_pct: u8,     // rate limits scaled to this percentage while tripped
+    pub challenge: bool,   // also switch the scope to challenge mode
+    pub cooldown_ms: u64,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        Self { window_ms: 10_000, min_requests: 50, trip_pct: 20, clear_pct: 5, limit_pct: 25, challenge: true, cooldown_ms: 60_000 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Protection {
+    pub limit_pct: u8, // 100 = configured limits untouched
+    pub challenge: bool,
+}
+
+impl Protection {
+    pub const NORMAL: Protection = Protection { limit_pct: 100, challenge: false };
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuditKind {
+    Tightened { deny_pct: u8 },
+    Extended { deny_pct: u8 },
+    Relaxed { deny_pct: u8 },
+}
+
+#[derive(Clone, Debug)]
+pub struct AuditEvent {
+    pub ts_ms: u64,
+    pub scope: String,
+    pub kind: AuditKind,
+}
+
+#[derive(Default)]
+struct ScopeState {
+    window_start_ms: u64,
+    requests: u64,
+    denies: u64,
+    tripped_until_ms: Option<u64>,
+}
+
+pub struct Adaptive {
+    cfg: AdaptiveConfig,
+    scopes: Mutex<HashMap<String, ScopeState>>,
+    audit: Mutex<VecDeque<AuditEvent>>,
+}
+
+impl Adaptive {
+    pub fn new(cfg: AdaptiveConfig) -> Self {
+        Self { cfg, scopes: Mutex::new(HashMap::new()), audit: Mutex::new(VecDeque::new()) }
+    }
+
+    // Feed one WAF decision for `scope`; closes the window when it has elapsed.
+    pub fn observe(&self, scope: &str, d: &Decision, now_ms: u64) {
+        let mut scopes = self.scopes.lock().unwrap();
+        let st = scopes.entry(scope.to_string()).or_insert_with(|| ScopeState { window_start_ms: now_ms, ..Default::default() });
+        if now_ms.saturating_sub(st.window_start_ms) >= self.cfg.window_ms {
+            if let Some(kind) = self.evaluate(st, now_ms) {
+                self.emit(AuditEvent { ts_ms: now_ms, scope: scope.to_string(), kind });
+            }
+            st.window_start_ms = now_ms;
+            st.requests = 0;
+            st.denies = 0;
+        }
+        st.requests += 1;
+        if matches!(d.action, Action::Deny(_)) {
+            st.denies += 1;
+        }
+    }
+
+    pub fn protection(&self, scope: &str) -> Protection {
+        let scopes = self.scopes.lock().unwrap();
+        match scopes.get(scope) {
+            Some(st) if st.tripped_until_ms.is_some() => Protection { limit_pct: self.cfg.limit_pct, challenge: self.cfg.challenge },
+            _ => Protection::NORMAL,
+        }
+    }
+
+    pub fn drain_audit(&self) -> Vec<AuditEvent> {
+        self.audit.lock().unwrap().drain(..).collect()
+    }
+
+    fn evaluate(&self, st: &mut ScopeState, now_ms: u64) -> Option<AuditKind> {
+        if st.requests < self.cfg.min_requests {
+            // Too little traffic to judge; an expired trip still needs a clean window.
+            return None;
+        }
+        let deny_pct = (st.denies * 100 / st.requests) as u8;
+        match st.tripped_until_ms {
+            None if deny_pct >= self.cfg.trip_pct => {
+                st.tripped_until_ms = Some(now_ms + self.cfg.cooldown_ms);
+                Some(AuditKind::Tightened { deny_pct })
+            }
+            Some(until) if now_ms >= until => {
+                if deny_pct <= self.cfg.clear_pct {
+                    st.tripped_until_ms = None;
+                    Some(AuditKind::Relaxed { deny_pct })
+                } else {
+                    st.tripped_until_ms = Some(now_ms + self.cfg.cooldown_ms);
+                    Some(AuditKind::Extended { deny_pct })
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn emit(&self, ev: AuditEvent) {
+        let mut a = self.audit.lock().unwrap();
+        if a.len() == MAX_AUDIT {
+            a.pop_front();
+        }
+        a.push_back(ev);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(action: Action) -> Decision {
+        Decision { ts_ms: 0, applied_rule_id: None, action, reason: String::new(), tags: vec![], severity: 0, rule_version: 0 }
+    }
+
+    fn window(a: &Adaptive, start: u64, denies: u64, total: u64) {
+        for i in 0..total {
+            let act = if i < denies { Action::Deny(403) } else { Action::Allow };
+            a.observe("/login", &d(act), start + i);
+        }
+    }
+
+    #[test]
+    fn test_trip_extend_relax() {
+        let cfg = AdaptiveConfig { window_ms: 1_000, min_requests: 10, cooldown_ms: 2_000, ..Default::default() };
+        let a = Adaptive::new(cfg);
+        window(&a, 0, 5, 10); // 50% denies
+        window(&a, 1_000, 2, 10); // closes window 1 -> tighten
+        assert_eq!(a.protection("/login"), Protection { limit_pct: 25, challenge: true });
+        window(&a, 3_000, 0, 10); // closes window 2 at t=3000: cooldown over, 20% > clear -> extend
+        window(&a, 5_000, 0, 10); // closes window 3 at t=5000: cooldown over, 0% -> relax
+        assert_eq!(a.protection("/login"), Protection::NORMAL);
+        let kinds: Vec<AuditKind> = a.drain_audit().into_iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, vec![AuditKind::Tightened { deny_pct: 50 }, AuditKind::Extended { deny_pct: 20 }, AuditKind::Relaxed { deny_pct: 0 }]);
+    }
+}
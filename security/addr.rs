@@ -0,0 +1,105 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/addr.rs
+// Role: Final & Stable canonical client address (dual-stack correctness)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - One parsed representation for every IP-keyed subsystem (WAF, ACLs,
+//   limiters, reputation/top-k stats, signed URL binding).
+// - IPv4-mapped IPv6 (::ffff:a.b.c.d) normalized to plain IPv4.
+// - Prefix aggregation for limiting (IPv6 clients usually own a whole /64).
+// =============================================================================
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+pub const DEFAULT_V6_LIMIT_PREFIX: u8 = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClientAddr(IpAddr);
+
+impl ClientAddr {
+    pub fn new(ip: IpAddr) -> Self {
+        match ip {
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => ClientAddr(IpAddr::V4(v4)),
+                None => ClientAddr(ip),
+            },
+            v4 => ClientAddr(v4),
+        }
+    }
+
+    // Accepts "1.2.3.4", "2001:db8::1", "[2001:db8::1]" and "::ffff:1.2.3.4".
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let s = s.strip_prefix('[').and_then(|x| x.strip_suffix(']')).unwrap_or(s);
+        s.parse::<IpAddr>().ok().map(Self::new)
+    }
+
+    pub fn ip(&self) -> IpAddr {
+        self.0
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        self.0.is_ipv6()
+    }
+
+    // Network address of the enclosing prefix: /32 (unchanged) for IPv4,
+    // `v6_prefix` bits for IPv6. Use as the key for rate limiting.
+    pub fn limit_key(&self, v6_prefix: u8) -> ClientAddr {
+        match self.0 {
+            IpAddr::V4(_) => *self,
+            IpAddr::V6(v6) => {
+                let p = v6_prefix.min(128) as u32;
+                let mask = if p == 0 { 0 } else { u128::MAX << (128 - p) };
+                ClientAddr(IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask)))
+            }
+        }
+    }
+}
+
+impl From<IpAddr> for ClientAddr {
+    fn from(ip: IpAddr) -> Self {
+        Self::new(ip)
+    }
+}
+
+impl From<Ipv4Addr> for ClientAddr {
+    fn from(ip: Ipv4Addr) -> Self {
+        ClientAddr(IpAddr::V4(ip))
+    }
+}
+
+impl FromStr for ClientAddr {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s).ok_or_else(|| format!("invalid client address '{}'", s))
+    }
+}
+
+// Canonical text form: dotted quad, or RFC 5952 compressed IPv6.
+impl fmt::Display for ClientAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical() {
+        let a = ClientAddr::parse("::ffff:203.0.113.10").unwrap();
+        assert_eq!(a, ClientAddr::parse("203.0.113.10").unwrap());
+        assert_eq!(a.to_string(), "203.0.113.10");
+        assert_eq!(ClientAddr::parse("[2001:DB8:0::1]").unwrap().to_string(), "2001:db8::1");
+        assert!(ClientAddr::parse("not-an-ip").is_none());
+
+        let v6 = ClientAddr::parse("2001:db8:1:2:aaaa:bbbb:cccc:dddd").unwrap();
+        assert_eq!(v6.limit_key(DEFAULT_V6_LIMIT_PREFIX).to_string(), "2001:db8:1:2::");
+        assert_eq!(a.limit_key(DEFAULT_V6_LIMIT_PREFIX), a);
+    }
+}
@@ -0,0 +1,357 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/challenge.rs
+// Role: Final & Stable proof-of-work challenge behind Action::Challenge
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Issue signed challenges "<nonce>.<exp>.<bits>.<sig>" bound to the client IP.
+// - Solution: decimal counter such that SHA-256("<token>:<counter>") starts
+//   with `bits` zero bits; the JS page below finds it in the browser.
+// - Each challenge is accepted once. Outstanding challenges live in shards
+//   keyed by client IP, each with an expiry queue, so issuing is O(1) and a
+//   flood from one client only displaces challenges in its own shard.
+// - A solved challenge yields a signed pass "<exp>.<sig>" (cookie value) that
+//   lets the same client through Challenge decisions until it expires.
+// - `admit` is the request-path entry: it accepts a submitted solution
+//   (x-olwsx-challenge / x-olwsx-solution headers), honours the pass cookie,
+//   or issues a challenge.
+// =============================================================================
+
+use crate::addr::ClientAddr;
+use crate::crypto::{ct_eq, hex, hmac_sha256, sha256, unhex};
+use crate::waf::{Action, Decision};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Request headers the challenge page submits its solution in.
+pub const CHALLENGE_HEADER: &str = "x-olwsx-challenge";
+pub const SOLUTION_HEADER: &str = "x-olwsx-solution";
+
+const SHARDS: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChallengeError {
+    Malformed,
+    BadSignature,
+    Expired,
+    Unknown,       // never issued here, already solved, or evicted
+    WrongSolution,
+}
+
+impl ChallengeError {
+    // HTTP status hint, in the spirit of Action::Deny(u16)
+    pub fn status(&self) -> u16 {
+        match self {
+            ChallengeError::Malformed => 400,
+            _ => 403,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChallengeConfig {
+    pub difficulty_bits: u8,
+    pub challenge_ttl_secs: u64,
+    pub pass_ttl_secs: u64,
+    pub max_outstanding: usize, // across all shards
+    pub cookie: &'static str,
+}
+
+impl Default for ChallengeConfig {
+    fn default() -> Self {
+        Self { difficulty_bits: 18, challenge_ttl_secs: 120, pass_ttl_secs: 3600, max_outstanding: 100_000, cookie: "olwsx_pass" }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Challenge {
+    pub token: String,
+    pub difficulty_bits: u8,
+    pub expires_unix: u64,
+}
+
+// What a handler should do for a request after the WAF decided.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Gate {
+    Pass,
+    Challenge { status: u16, challenge: Challenge, body: String },
+}
+
+// Outcome of `admit` for one request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Admission {
+    Pass,
+    // Solution accepted: answer with this Set-Cookie header value (pass cookie).
+    Solved { set_cookie: String },
+    Challenge { status: u16, challenge: Challenge, body: String },
+    Rejected(ChallengeError),
+}
+
+// Challenges issued in one shard: nonce -> expiry, plus the nonces in issue
+// order. With one TTL for all, the queue front is always the next to expire;
+// solved nonces leave stale queue slots that are skipped when popped.
+#[derive(Default)]
+struct Outstanding {
+    live: HashMap<String, u64>,
+    queue: VecDeque<(u64, String)>,
+}
+
+impl Outstanding {
+    fn admit(&mut self, nonce: String, exp: u64, now_unix: u64, cap: usize) {
+        while let Some((e, n)) = self.queue.front() {
+            let stale = self.live.get(n) != Some(e);
+            if !(stale || *e <= now_unix || self.queue.len() >= cap) {
+                break;
+            }
+            if let Some((e, n)) = self.queue.pop_front()
+                && self.live.get(&n) == Some(&e)
+            {
+                self.live.remove(&n);
+            }
+        }
+        self.queue.push_back((exp, nonce.clone()));
+        self.live.insert(nonce, exp);
+    }
+}
+
+pub struct ChallengeVerifier {
+    key: Vec<u8>,
+    cfg: ChallengeConfig,
+    counter: AtomicU64,
+    shards: Vec<Mutex<Outstanding>>,
+}
+
+impl ChallengeVerifier {
+    pub fn new(key: &[u8], cfg: ChallengeConfig) -> Self {
+        let shards = (0..SHARDS).map(|_| Mutex::new(Outstanding::default())).collect();
+        Self { key: key.to_vec(), cfg, counter: AtomicU64::new(0), shards }
+    }
+
+    pub fn issue(&self, client_ip: &str, now_unix: u64) -> Challenge {
+        // unpredictable without the key; unique through the counter
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+        let seed = hmac_sha256(&self.key, format!("nonce|{}|{}|{}", n, now_unix, nanos()).as_bytes());
+        let nonce = hex(&seed[..12]);
+        let exp = now_unix + self.cfg.challenge_ttl_secs;
+        let bits = self.cfg.difficulty_bits.min(64);
+        let body = format!("{}.{}.{}", nonce, exp, bits);
+        let sig = hex(&self.mac("chal", &body, client_ip));
+
+        // full shard: the oldest challenge (closest to expiry) is forgotten
+        let cap = self.cfg.max_outstanding.div_ceil(SHARDS).max(1);
+        self.shard(client_ip).lock().unwrap_or_else(|e| e.into_inner()).admit(nonce, exp, now_unix, cap);
+        Challenge { token: format!("{}.{}", body, sig), difficulty_bits: bits, expires_unix: exp }
+    }
+
+    // Verifies a solution and consumes the challenge; returns the pass value.
+    pub fn verify_solution(&self, token: &str, solution: &str, client_ip: &str, now_unix: u64) -> Result<String, ChallengeError> {
+        let parts: Vec<&str> = token.split('.').collect();
+        let [nonce, exp, bits, sig] = parts.as_slice() else { return Err(ChallengeError::Malformed) };
+        let exp: u64 = exp.parse().map_err(|_| ChallengeError::Malformed)?;
+        let bits: u8 = bits.parse().map_err(|_| ChallengeError::Malformed)?;
+        let sig = unhex(sig).ok_or(ChallengeError::Malformed)?;
+        if solution.is_empty() || solution.len() > 20 || !solution.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ChallengeError::Malformed);
+        }
+        let body = format!("{}.{}.{}", nonce, exp, bits);
+        if !ct_eq(&sig, &self.mac("chal", &body, client_ip)) {
+            return Err(ChallengeError::BadSignature);
+        }
+        if now_unix > exp {
+            return Err(ChallengeError::Expired);
+        }
+        if leading_zero_bits(&sha256(format!("{}:{}", token, solution).as_bytes())) < bits as u32 {
+            return Err(ChallengeError::WrongSolution);
+        }
+        if self.shard(client_ip).lock().unwrap_or_else(|e| e.into_inner()).live.remove(*nonce).is_none() {
+            return Err(ChallengeError::Unknown);
+        }
+        Ok(self.pass(client_ip, now_unix))
+    }
+
+    // Signed pass bound to the client; set as the `cfg.cookie` cookie value.
+    pub fn pass(&self, client_ip: &str, now_unix: u64) -> String {
+        let exp = (now_unix + self.cfg.pass_ttl_secs).to_string();
+        format!("{}.{}", exp, hex(&self.mac("pass", &exp, client_ip)))
+    }
+
+    pub fn check_pass(&self, pass: &str, client_ip: &str, now_unix: u64) -> bool {
+        let Some((exp, sig)) = pass.split_once('.') else { return false };
+        let (Ok(e), Some(sig)) = (exp.parse::<u64>(), unhex(sig)) else { return false };
+        ct_eq(&sig, &self.mac("pass", exp, client_ip)) && now_unix <= e
+    }
+
+    // Lets a client with a valid pass through a Challenge decision; otherwise
+    // issues a challenge page. Other decisions pass through untouched.
+    pub fn gate(&self, d: &Decision, pass: Option<&str>, client_ip: &str, now_unix: u64) -> Gate {
+        let Action::Challenge(status) = d.action else { return Gate::Pass };
+        if pass.is_some_and(|p| self.check_pass(p, client_ip, now_unix)) {
+            return Gate::Pass;
+        }
+        let challenge = self.issue(client_ip, now_unix);
+        let body = CHALLENGE_PAGE.replace("{token}", &challenge.token).replace("{bits}", &challenge.difficulty_bits.to_string());
+        Gate::Challenge { status, challenge, body }
+    }
+
+    // Request path after the WAF decided (any action but Deny): a submitted
+    // solution is verified and answered with the pass cookie, whatever the
+    // decision; otherwise `gate` applies with the pass from `cookie_header`.
+    pub fn admit(&self, d: &Decision, headers: &[(String, String)], client_ip: &str, now_unix: u64) -> Admission {
+        let get = |name: &str| headers.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str());
+        if let (Some(token), Some(solution)) = (get(CHALLENGE_HEADER), get(SOLUTION_HEADER)) {
+            return match self.verify_solution(token.trim(), solution.trim(), client_ip, now_unix) {
+                Ok(pass) => Admission::Solved { set_cookie: self.set_cookie(&pass) },
+                Err(e) => Admission::Rejected(e),
+            };
+        }
+        let pass = get("cookie").and_then(|c| self.pass_from_cookie(c));
+        match self.gate(d, pass, client_ip, now_unix) {
+            Gate::Pass => Admission::Pass,
+            Gate::Challenge { status, challenge, body } => Admission::Challenge { status, challenge, body },
+        }
+    }
+
+    // The pass from a Cookie header value ("a=1; olwsx_pass=...").
+    pub fn pass_from_cookie<'a>(&self, cookie_header: &'a str) -> Option<&'a str> {
+        cookie_header.split(';').filter_map(|c| c.trim().split_once('=')).find(|(k, _)| *k == self.cfg.cookie).map(|(_, v)| v)
+    }
+
+    pub fn set_cookie(&self, pass: &str) -> String {
+        format!("{}={}; Max-Age={}; Path=/; HttpOnly; Secure; SameSite=Lax", self.cfg.cookie, pass, self.cfg.pass_ttl_secs)
+    }
+
+    pub fn cookie_name(&self) -> &'static str {
+        self.cfg.cookie
+    }
+
+    pub fn outstanding(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().unwrap_or_else(|e| e.into_inner()).live.len()).sum()
+    }
+
+    fn shard(&self, client_ip: &str) -> &Mutex<Outstanding> {
+        let mut h = DefaultHasher::new();
+        ClientAddr::parse(client_ip).map(|a| a.to_string()).unwrap_or_else(|| client_ip.to_string()).hash(&mut h);
+        &self.shards[h.finish() as usize % SHARDS]
+    }
+
+    fn mac(&self, kind: &str, body: &str, client_ip: &str) -> [u8; 32] {
+        let ip = ClientAddr::parse(client_ip).map(|a| a.to_string()).unwrap_or_else(|| client_ip.to_string());
+        hmac_sha256(&self.key, format!("{}|{}|{}", kind, body, ip).as_bytes())
+    }
+}
+
+// Solver used by clients (and tests); the page runs the same loop in JS.
+pub fn solve(token: &str, bits: u8) -> String {
+    let mut n: u64 = 0;
+    loop {
+        let s = n.to_string();
+        if leading_zero_bits(&sha256(format!("{}:{}", token, s).as_bytes())) >= bits as u32 {
+            return s;
+        }
+        n += 1;
+    }
+}
+
+fn leading_zero_bits(h: &[u8; 32]) -> u32 {
+    let mut n = 0;
+    for b in h.iter() {
+        if *b == 0 {
+            n += 8;
+            continue;
+        }
+        return n + b.leading_zeros();
+    }
+    n
+}
+
+fn nanos() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0)
+}
+
+// POSTs token + solution to the same URL; the handler answers with the pass cookie.
+const CHALLENGE_PAGE: &str = r#"<!doctype html><meta charset="utf-8"><title>Checking your browser</title>
+<p>Checking your browser&hellip;</p>
+<script>
+(async () => {
+  const token = "{token}", bits = {bits}, enc = new TextEncoder();
+  for (let n = 0; ; n++) {
+    const h = new Uint8Array(await crypto.subtle.digest("SHA-256", enc.encode(token + ":" + n)));
+    let z = 0, i = 0;
+    while (i < h.length && h[i] === 0) { z += 8; i++; }
+    if (i < h.length) z += Math.clz32(h[i]) - 24;
+    if (z >= bits) {
+      await fetch(location.href, { method: "POST", headers: { "x-olwsx-challenge": token, "x-olwsx-solution": String(n) } });
+      location.reload();
+      return;
+    }
+  }
+})();
+</script>"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenge_round_trip() {
+        let v = ChallengeVerifier::new(b"k", ChallengeConfig { difficulty_bits: 8, ..ChallengeConfig::default() });
+        let d = Decision { ts_ms: 0, applied_rule_id: Some(7), action: Action::Challenge(429), reason: String::new(), tags: vec![], severity: 3, rule_version: 0 };
+        let Gate::Challenge { status, challenge, body } = v.gate(&d, None, "203.0.113.9", 1_000) else { panic!("expected challenge") };
+        assert_eq!(status, 429);
+        assert!(body.contains(&challenge.token));
+
+        let sol = solve(&challenge.token, challenge.difficulty_bits);
+        assert_eq!(v.verify_solution(&challenge.token, &sol, "198.51.100.1", 1_001), Err(ChallengeError::BadSignature));
+        assert_eq!(v.verify_solution(&challenge.token, &sol, "203.0.113.9", 2_000), Err(ChallengeError::Expired));
+        let pass = v.verify_solution(&challenge.token, &sol, "203.0.113.9", 1_001).unwrap();
+        // one use only
+        assert_eq!(v.verify_solution(&challenge.token, &sol, "203.0.113.9", 1_002), Err(ChallengeError::Unknown));
+
+        assert_eq!(v.gate(&d, Some(&pass), "203.0.113.9", 1_010), Gate::Pass);
+        assert!(!v.check_pass(&pass, "203.0.113.10", 1_010));
+        assert!(!v.check_pass(&pass, "203.0.113.9", 1_001 + 3_601));
+        assert_eq!(v.outstanding(), 0);
+    }
+
+    #[test]
+    fn test_flood_keeps_other_shards() {
+        let v = ChallengeVerifier::new(b"k", ChallengeConfig { difficulty_bits: 1, max_outstanding: 32, ..ChallengeConfig::default() });
+        let victim = v.issue("198.51.100.7", 1_000);
+        let same_shard = |ip: &str| std::ptr::eq(v.shard(ip), v.shard("198.51.100.7"));
+        let attacker = (1..=255).map(|i| format!("203.0.113.{}", i)).find(|ip| !same_shard(ip)).unwrap();
+        for _ in 0..10_000 {
+            v.issue(&attacker, 1_000);
+        }
+        assert!(v.outstanding() <= 32);
+        let sol = solve(&victim.token, victim.difficulty_bits);
+        assert!(v.verify_solution(&victim.token, &sol, "198.51.100.7", 1_001).is_ok());
+
+        // expired challenges leave the queue as new ones arrive
+        let old = v.issue("192.0.2.1", 1_000);
+        v.issue("192.0.2.1", 1_000 + 121);
+        let sol = solve(&old.token, old.difficulty_bits);
+        assert_eq!(v.verify_solution(&old.token, &sol, "192.0.2.1", 1_000), Err(ChallengeError::Unknown));
+    }
+
+    #[test]
+    fn test_admit_solution_and_cookie() {
+        let v = ChallengeVerifier::new(b"k", ChallengeConfig { difficulty_bits: 4, ..ChallengeConfig::default() });
+        let d = Decision { ts_ms: 0, applied_rule_id: Some(7), action: Action::Challenge(429), reason: String::new(), tags: vec![], severity: 3, rule_version: 0 };
+        let ip = "203.0.113.9";
+        let Admission::Challenge { challenge, .. } = v.admit(&d, &[], ip, 1_000) else { panic!("expected challenge") };
+        let sol = solve(&challenge.token, challenge.difficulty_bits);
+        let submit = vec![(CHALLENGE_HEADER.to_string(), challenge.token.clone()), (SOLUTION_HEADER.to_string(), sol)];
+        let Admission::Solved { set_cookie } = v.admit(&d, &submit, ip, 1_001) else { panic!("expected solved") };
+        assert_eq!(v.admit(&d, &submit, ip, 1_002), Admission::Rejected(ChallengeError::Unknown));
+
+        let pass = set_cookie.split(';').next().unwrap().to_string();
+        let cookie = vec![("Cookie".to_string(), format!("theme=dark; {}", pass))];
+        assert_eq!(v.admit(&d, &cookie, ip, 1_010), Admission::Pass);
+        assert!(matches!(v.admit(&d, &cookie, "203.0.113.10", 1_010), Admission::Challenge { .. }));
+    }
+}
@@ -0,0 +1,396 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/decompress.rs
+// Role: Final & Stable inbound body decoding (gzip/deflate) with bomb limits
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Decode Content-Encoding request bodies before the WAF body phase so body
+//   rules see plaintext, and hand the same plaintext to handlers.
+// - Strict limits: absolute output cap and expansion ratio, checked while
+//   inflating (never after), so zip bombs stop early.
+// - Pluggable decoders keyed by coding name; stacked codings decoded in reverse.
+// =============================================================================
+
+use crate::crypto::{adler32, crc32};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub max_output: usize, // bytes after decoding
+    pub max_ratio: usize,  // decoded / encoded
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { max_output: 8 * 1024 * 1024, max_ratio: 100 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecompressError {
+    Unsupported(String),
+    TooLarge,
+    RatioExceeded,
+    Malformed(&'static str),
+}
+
+impl DecompressError {
+    // HTTP status hint for the rejection response
+    pub fn status(&self) -> u16 {
+        match self {
+            DecompressError::Unsupported(_) => 415,
+            DecompressError::TooLarge | DecompressError::RatioExceeded => 413,
+            DecompressError::Malformed(_) => 400,
+        }
+    }
+}
+
+// Decoders must stop with TooLarge as soon as output would exceed `max_output`.
+pub trait BodyDecoder: Send + Sync {
+    fn decode(&self, input: &[u8], max_output: usize) -> Result<Vec<u8>, DecompressError>;
+}
+
+pub struct Identity;
+pub struct Gzip;
+pub struct Zlib; // HTTP "deflate" is zlib-wrapped (RFC 9110 8.4.1.2)
+
+impl BodyDecoder for Identity {
+    fn decode(&self, input: &[u8], max_output: usize) -> Result<Vec<u8>, DecompressError> {
+        if input.len() > max_output {
+            return Err(DecompressError::TooLarge);
+        }
+        Ok(input.to_vec())
+    }
+}
+
+impl BodyDecoder for Gzip {
+    fn decode(&self, input: &[u8], max_output: usize) -> Result<Vec<u8>, DecompressError> {
+        const FHCRC: u8 = 0x02;
+        const FEXTRA: u8 = 0x04;
+        const FNAME: u8 = 0x08;
+        const FCOMMENT: u8 = 0x10;
+        if input.len() < 18 || input[0] != 0x1f || input[1] != 0x8b || input[2] != 8 {
+            return Err(DecompressError::Malformed("gzip header"));
+        }
+        let flags = input[3];
+        let mut pos = 10;
+        if flags & FEXTRA != 0 {
+            let xlen = u16::from_le_bytes([*input.get(pos).ok_or(DecompressError::Malformed("gzip extra"))?, *input.get(pos + 1).ok_or(DecompressError::Malformed("gzip extra"))?]) as usize;
+            pos += 2 + xlen;
+        }
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                let end = input.get(pos..).and_then(|r| r.iter().position(|b| *b == 0)).ok_or(DecompressError::Malformed("gzip name"))?;
+                pos += end + 1;
+            }
+        }
+        if flags & FHCRC != 0 {
+            pos += 2;
+        }
+        let body = input.get(pos..).ok_or(DecompressError::Malformed("gzip header"))?;
+        let (out, used) = inflate(body, max_output)?;
+        let trailer = body.get(used..used + 8).ok_or(DecompressError::Malformed("gzip trailer"))?;
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let isize = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if crc != crc32(&out) || isize != out.len() as u32 {
+            return Err(DecompressError::Malformed("gzip checksum"));
+        }
+        Ok(out)
+    }
+}
+
+impl BodyDecoder for Zlib {
+    fn decode(&self, input: &[u8], max_output: usize) -> Result<Vec<u8>, DecompressError> {
+        if input.len() < 6 {
+            return Err(DecompressError::Malformed("zlib header"));
+        }
+        let (cmf, flg) = (input[0], input[1]);
+        if cmf & 0x0f != 8 || !((cmf as u16) << 8 | flg as u16).is_multiple_of(31) || flg & 0x20 != 0 {
+            return Err(DecompressError::Malformed("zlib header"));
+        }
+        let (out, used) = inflate(&input[2..], max_output)?;
+        let t = input.get(2 + used..2 + used + 4).ok_or(DecompressError::Malformed("zlib trailer"))?;
+        if u32::from_be_bytes([t[0], t[1], t[2], t[3]]) != adler32(&out) {
+            return Err(DecompressError::Malformed("zlib checksum"));
+        }
+        Ok(out)
+    }
+}
+
+pub struct Decoders {
+    by_name: HashMap<String, Box<dyn BodyDecoder>>,
+}
+
+impl Decoders {
+    // identity, gzip, x-gzip, deflate
+    pub fn with_defaults() -> Self {
+        let mut d = Self { by_name: HashMap::new() };
+        d.register("identity", Box::new(Identity));
+        d.register("gzip", Box::new(Gzip));
+        d.register("x-gzip", Box::new(Gzip));
+        d.register("deflate", Box::new(Zlib));
+        d
+    }
+
+    pub fn register(&mut self, coding: &str, dec: Box<dyn BodyDecoder>) {
+        self.by_name.insert(coding.to_ascii_lowercase(), dec);
+    }
+
+    // `content_encoding` lists codings in the order they were applied.
+    pub fn decode_body(&self, content_encoding: &str, body: &[u8], limits: &Limits) -> Result<Vec<u8>, DecompressError> {
+        // The ratio is always measured against the bytes that came off the wire.
+        let by_ratio = body.len().max(1).saturating_mul(limits.max_ratio);
+        let cap = limits.max_output.min(by_ratio);
+        let mut data = body.to_vec();
+        for coding in content_encoding.split(',').map(|c| c.trim()).filter(|c| !c.is_empty()).rev() {
+            let dec = self.by_name.get(&coding.to_ascii_lowercase()).ok_or_else(|| DecompressError::Unsupported(coding.to_string()))?;
+            data = dec.decode(&data, cap).map_err(|e| match e {
+                DecompressError::TooLarge if by_ratio < limits.max_output => DecompressError::RatioExceeded,
+                other => other,
+            })?;
+        }
+        Ok(data)
+    }
+}
+
+// ------------------------------- RFC 1951 inflate ---------------------------
+
+const LEN_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LEN_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+const CLEN_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    cnt: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn need(&mut self, n: u32) -> Result<u32, DecompressError> {
+        while self.cnt < n {
+            let b = *self.data.get(self.pos).ok_or(DecompressError::Malformed("truncated deflate stream"))?;
+            self.pos += 1;
+            self.buf |= (b as u32) << self.cnt;
+            self.cnt += 8;
+        }
+        let v = self.buf & ((1u32 << n) - 1);
+        self.buf >>= n;
+        self.cnt -= n;
+        Ok(v)
+    }
+
+    fn align(&mut self) {
+        self.buf = 0;
+        self.cnt = 0;
+    }
+}
+
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, DecompressError> {
+        let mut counts = [0u16; 16];
+        for l in lengths {
+            counts[*l as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut left: i32 = 1;
+        for c in counts.iter().skip(1) {
+            left = (left << 1) - *c as i32;
+            if left < 0 {
+                return Err(DecompressError::Malformed("oversubscribed code"));
+            }
+        }
+        let mut offs = [0u16; 16];
+        for i in 1..15 {
+            offs[i + 1] = offs[i] + counts[i];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (sym, l) in lengths.iter().enumerate() {
+            if *l != 0 {
+                symbols[offs[*l as usize] as usize] = sym as u16;
+                offs[*l as usize] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, DecompressError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.need(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(DecompressError::Malformed("bad huffman code"))
+    }
+}
+
+// Returns the output and the number of input bytes consumed (byte aligned).
+fn inflate(input: &[u8], cap: usize) -> Result<(Vec<u8>, usize), DecompressError> {
+    let mut out: Vec<u8> = Vec::new();
+    let mut bits = Bits { data: input, pos: 0, buf: 0, cnt: 0 };
+    loop {
+        let last = bits.need(1)?;
+        match bits.need(2)? {
+            0 => {
+                bits.align();
+                let hdr = input.get(bits.pos..bits.pos + 4).ok_or(DecompressError::Malformed("stored block"))?;
+                let len = u16::from_le_bytes([hdr[0], hdr[1]]) as usize;
+                if len != !u16::from_le_bytes([hdr[2], hdr[3]]) as usize {
+                    return Err(DecompressError::Malformed("stored block length"));
+                }
+                bits.pos += 4;
+                let data = input.get(bits.pos..bits.pos + len).ok_or(DecompressError::Malformed("stored block"))?;
+                if out.len() + len > cap {
+                    return Err(DecompressError::TooLarge);
+                }
+                out.extend_from_slice(data);
+                bits.pos += len;
+            }
+            1 => {
+                let mut l = [8u8; 288];
+                l[144..256].fill(9);
+                l[256..280].fill(7);
+                let lit = Huffman::new(&l)?;
+                let dist = Huffman::new(&[5u8; 30])?;
+                codes(&mut bits, &mut out, &lit, &dist, cap)?;
+            }
+            2 => {
+                let (lit, dist) = dynamic_tables(&mut bits)?;
+                codes(&mut bits, &mut out, &lit, &dist, cap)?;
+            }
+            _ => return Err(DecompressError::Malformed("reserved block type")),
+        }
+        if last == 1 {
+            return Ok((out, bits.pos));
+        }
+    }
+}
+
+fn dynamic_tables(bits: &mut Bits) -> Result<(Huffman, Huffman), DecompressError> {
+    let hlit = bits.need(5)? as usize + 257;
+    let hdist = bits.need(5)? as usize + 1;
+    let hclen = bits.need(4)? as usize + 4;
+    if hlit > 286 || hdist > 30 {
+        return Err(DecompressError::Malformed("too many codes"));
+    }
+    let mut cl = [0u8; 19];
+    for i in CLEN_ORDER.iter().take(hclen) {
+        cl[*i] = bits.need(3)? as u8;
+    }
+    let clh = Huffman::new(&cl)?;
+    let mut lengths = vec![0u8; hlit + hdist];
+    let mut i = 0;
+    while i < hlit + hdist {
+        let sym = clh.decode(bits)?;
+        let (val, rep) = match sym {
+            0..=15 => (sym as u8, 1),
+            16 => {
+                let prev = *lengths.get(i.wrapping_sub(1)).ok_or(DecompressError::Malformed("repeat without length"))?;
+                (prev, 3 + bits.need(2)? as usize)
+            }
+            17 => (0, 3 + bits.need(3)? as usize),
+            _ => (0, 11 + bits.need(7)? as usize),
+        };
+        if i + rep > lengths.len() {
+            return Err(DecompressError::Malformed("too many lengths"));
+        }
+        lengths[i..i + rep].fill(val);
+        i += rep;
+    }
+    if lengths[256] == 0 {
+        return Err(DecompressError::Malformed("missing end-of-block code"));
+    }
+    Ok((Huffman::new(&lengths[..hlit])?, Huffman::new(&lengths[hlit..])?))
+}
+
+fn codes(bits: &mut Bits, out: &mut Vec<u8>, lit: &Huffman, dist: &Huffman, cap: usize) -> Result<(), DecompressError> {
+    loop {
+        let sym = lit.decode(bits)? as usize;
+        if sym < 256 {
+            if out.len() >= cap {
+                return Err(DecompressError::TooLarge);
+            }
+            out.push(sym as u8);
+        } else if sym == 256 {
+            return Ok(());
+        } else {
+            let s = sym - 257;
+            if s >= 29 {
+                return Err(DecompressError::Malformed("bad length symbol"));
+            }
+            let len = LEN_BASE[s] as usize + bits.need(LEN_EXTRA[s] as u32)? as usize;
+            let ds = dist.decode(bits)? as usize;
+            if ds >= 30 {
+                return Err(DecompressError::Malformed("bad distance symbol"));
+            }
+            let d = DIST_BASE[ds] as usize + bits.need(DIST_EXTRA[ds] as u32)? as usize;
+            if d > out.len() {
+                return Err(DecompressError::Malformed("distance too far back"));
+            }
+            if out.len() + len > cap {
+                return Err(DecompressError::TooLarge);
+            }
+            let start = out.len() - d;
+            for k in 0..len {
+                let b = out[start + k];
+                out.push(b);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `printf 'hello hello hello hello' | gzip -n`
+    const GZ_HELLO: [u8; 28] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xcb, 0x48, 0xcd, 0xc9,
+        0xc9, 0x57, 0xc8, 0x40, 0x27, 0x01, 0xe3, 0x51, 0x3d, 0x8d, 0x17, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_decode_and_limits() {
+        let d = Decoders::with_defaults();
+        // zlib stream with a single stored block containing "abc"
+        let zlib = [0x78, 0x01, 0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c', 0x02, 0x4d, 0x01, 0x27];
+        assert_eq!(d.decode_body("deflate", &zlib, &Limits::default()).unwrap(), b"abc".to_vec());
+        assert_eq!(d.decode_body("identity", b"raw", &Limits::default()).unwrap(), b"raw".to_vec());
+        assert_eq!(d.decode_body("br", b"x", &Limits::default()).unwrap_err().status(), 415);
+
+        let tight = Limits { max_output: 4, max_ratio: 100 };
+        assert_eq!(d.decode_body("deflate", &zlib, &Limits { max_output: 2, ..tight }), Err(DecompressError::TooLarge));
+
+        let mut corrupt = zlib;
+        corrupt[13] ^= 1;
+        assert_eq!(d.decode_body("deflate", &corrupt, &Limits::default()).unwrap_err().status(), 400);
+    }
+
+    #[test]
+    fn test_gzip_fixed_huffman() {
+        let d = Decoders::with_defaults();
+        assert_eq!(d.decode_body("gzip", &GZ_HELLO, &Limits::default()).unwrap(), b"hello hello hello hello".to_vec());
+
+        // 1000 x 'a' in 29 wire bytes: ratio 10 caps output at 290 bytes
+        let bomb = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x4b, 0x4c, 0x1c, 0x05, 0xa3,
+            0x60, 0x14, 0x0c, 0x77, 0x00, 0x00, 0x03, 0xda, 0x38, 0x9a, 0xe8, 0x03, 0x00, 0x00,
+        ];
+        assert_eq!(d.decode_body("gzip", &bomb, &Limits::default()).unwrap(), vec![b'a'; 1000]);
+        assert_eq!(d.decode_body("gzip", &bomb, &Limits { max_output: 1 << 20, max_ratio: 10 }), Err(DecompressError::RatioExceeded));
+    }
+}
@@ -0,0 +1,107 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/path.rs
+// Role: Final & Stable canonical request paths for security decisions
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Canonical form: query dropped, percent-escapes decoded once (invalid
+//   ones kept literally), `\` read as `/`, empty and `.` segments removed,
+//   `..` resolved (never above the root).
+// - Prefix matching on segment boundaries: "/api" covers "/api" and
+//   "/api/x" but not "/apix".
+// - Guards match on the canonical form so `%2e%2e`, `//` and `/./` spellings
+//   cannot step around a prefix the backend would resolve into.
+// =============================================================================
+
+pub fn canonical(target: &str) -> String {
+    let raw = target.split(['?', '#']).next().unwrap_or(target);
+    let decoded = percent_decode(raw);
+    let mut segments: Vec<&str> = Vec::new();
+    for seg in decoded.split(['/', '\\']) {
+        match seg {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s => segments.push(s),
+        }
+    }
+    let mut out = String::with_capacity(decoded.len() + 1);
+    for s in segments.iter() {
+        out.push('/');
+        out.push_str(s);
+    }
+    if out.is_empty() {
+        out.push('/');
+    }
+    out
+}
+
+// True when canonical `path` is `prefix` or lies below it. A trailing `/` or
+// `*` on the prefix is ignored; "/" and "" cover everything.
+pub fn under(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('*').trim_end_matches('/');
+    if prefix.is_empty() {
+        return true;
+    }
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+// Route pattern semantics shared by dispatch, WAF attachments and per-route
+// policies: an exact path, or a prefix ending in `*` matched on segment
+// boundaries. `path` must already be canonical.
+pub fn route_matches(pattern: &str, path: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => under(path, prefix),
+        None => path == canonical(pattern),
+    }
+}
+
+// %XX escapes decoded; invalid UTF-8 is replaced, malformed escapes kept.
+pub fn percent_decode(s: &str) -> String {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        let hex = |c: u8| (c as char).to_digit(16);
+        match (b[i], b.get(i + 1).and_then(|c| hex(*c)), b.get(i + 2).and_then(|c| hex(*c))) {
+            (b'%', Some(h), Some(l)) => {
+                out.push((h * 16 + l) as u8);
+                i += 3;
+            }
+            (c, _, _) => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonical_and_under() {
+        assert_eq!(canonical("/public/%2e%2e/downloads/a.zip?x=1"), "/downloads/a.zip");
+        assert_eq!(canonical("//downloads/./a.zip"), "/downloads/a.zip");
+        assert_eq!(canonical("/a/..%2f..%2F..%5cetc"), "/etc");
+        assert_eq!(canonical("/%zz/%41"), "/%zz/A");
+        assert_eq!(canonical(""), "/");
+
+        assert!(under("/api", "/api") && under("/api/x", "/api/") && under("/api/x", "/api/*"));
+        assert!(!under("/apix", "/api") && !under("/apix", "/api*"));
+        assert!(under("/anything", "/") && under("/", "*"));
+
+        assert!(route_matches("/api*", "/api/x") && route_matches("/api/x", "/api/x"));
+        assert!(!route_matches("/api*", "/apix") && !route_matches("/api", "/api/x"));
+        for raw in ["//admin/x", "/%61dmin/x", "/x/../admin/x"] {
+            assert!(route_matches("/admin/*", &canonical(raw)), "{}", raw);
+        }
+    }
+}
@@ -0,0 +1,608 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/ratelimit.rs
+// Role: Final & Stable rate limiting (sharded token buckets per IP/tenant/route)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Token bucket per key: `burst` capacity, `refill_per_sec` steady rate.
+// - Independent limits per scope (client IP prefix, tenant, route); a request
+//   spends one token in every configured scope, and only when all allow it.
+// - Sharded state with a bounded key count per shard; the least recently
+//   used bucket goes first, found through an index ordered by last use.
+// - Known keys are looked up by &str; only a new bucket allocates its key.
+// - Outcome converts to a WAF `Decision` (Deny 429 + SEC_RATELIM meta flag);
+//   adaptive protection can scale limits down via `limit_pct`.
+// - Temporary denylist: a client (its IP limit key) refused until a deadline.
+// - Snapshots: buckets still draining and live denylist entries in a compact
+//   binary format, restored on startup so a restart does not release a
+//   client mid-attack; `Snapshotter` writes one periodically and on drop.
+// =============================================================================
+
+use crate::addr::ClientAddr;
+use crate::waf::{Action, Decision};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+
+pub const SEC_RATELIM: u32 = 0x0040_0000; // mirrors core meta flag
+
+// Snapshot layout, little-endian: magic, version, u32 bucket count, then per
+// bucket scope u8, key (u16 length + bytes), tokens f64, last use u64 ms;
+// then u32 denylist count and per entry key, deadline u64 ms.
+const SNAPSHOT_MAGIC: &[u8; 4] = b"OLRL";
+const SNAPSHOT_VERSION: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limit {
+    pub burst: u32,
+    pub refill_per_sec: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    pub ip: Option<Limit>,
+    pub tenant: Option<Limit>,
+    pub route: Option<Limit>,
+    pub v6_prefix: u8,          // IPv6 clients share a bucket per prefix
+    pub shards: usize,
+    pub max_keys_per_shard: usize,
+    pub max_denied: usize,      // denylist entries; the soonest to expire goes first
+    pub status: u16,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            ip: Some(Limit { burst: 100, refill_per_sec: 50.0 }),
+            tenant: None,
+            route: None,
+            v6_prefix: 64,
+            shards: 16,
+            max_keys_per_shard: 4096,
+            max_denied: 65_536,
+            status: 429,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    Ip,
+    Tenant,
+    Route,
+}
+
+impl Scope {
+    const ALL: [Scope; 3] = [Scope::Ip, Scope::Tenant, Scope::Route];
+
+    fn name(self) -> &'static str {
+        match self {
+            Scope::Ip => "ip",
+            Scope::Tenant => "tenant",
+            Scope::Route => "route",
+        }
+    }
+}
+
+// What a request is limited by; empty tenant/route skips that scope.
+#[derive(Clone, Copy, Debug)]
+pub struct RateKey<'a> {
+    pub ip: &'a str,
+    pub tenant: &'a str,
+    pub route: &'a str,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub allowed: bool,
+    pub limited_by: Option<(Scope, String)>, // scope and key that ran out
+    pub remaining: u32,                      // tokens left in the tightest scope
+    pub retry_after_ms: u64,
+    status: u16,
+}
+
+impl Outcome {
+    pub fn meta_flags(&self) -> u32 {
+        if self.allowed { 0 } else { SEC_RATELIM }
+    }
+
+    pub fn to_decision(&self, ts_ms: u64) -> Decision {
+        let (action, reason) = match &self.limited_by {
+            Some((scope, key)) if !self.allowed => {
+                (Action::Deny(self.status), format!("rate limited by {} {} (retry in {} ms)", scope.name(), key, self.retry_after_ms))
+            }
+            _ => (Action::Allow, "within rate limits".to_string()),
+        };
+        Decision { ts_ms, applied_rule_id: None, action, reason, tags: vec!["ratelimit".to_string()], severity: if self.allowed { 0 } else { 3 }, rule_version: 0 }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Bucket {
+    tokens: f64,
+    last_ms: u64,
+    key: Arc<str>, // shared with the shard's map and use index
+}
+
+// Buckets per scope (indexed by `Scope as usize`), plus every bucket ordered
+// by last use for eviction.
+#[derive(Default)]
+struct Shard {
+    buckets: [HashMap<Arc<str>, Bucket>; 3],
+    by_use: BTreeSet<(u64, u8, Arc<str>)>,
+}
+
+impl Shard {
+    fn len(&self) -> usize {
+        self.buckets.iter().map(HashMap::len).sum()
+    }
+
+    // Drops the least recently used bucket; an idle bucket has usually
+    // refilled, so forgetting it costs nothing.
+    fn evict(&mut self) {
+        if let Some((_, scope, key)) = self.by_use.pop_first() {
+            self.buckets[scope as usize].remove(&*key);
+        }
+    }
+}
+
+pub struct RateLimiter {
+    cfg: RateLimitConfig,
+    shards: Vec<Mutex<Shard>>,
+    denied: Mutex<HashMap<String, u64>>, // IP limit key -> deadline (ms)
+}
+
+impl RateLimiter {
+    pub fn new(cfg: RateLimitConfig) -> Self {
+        let n = cfg.shards.max(1);
+        Self { shards: (0..n).map(|_| Mutex::new(Shard::default())).collect(), denied: Mutex::new(HashMap::new()), cfg }
+    }
+
+    // Refuse `ip` (with IPv6, its whole prefix) until `until_ms`. A later
+    // deadline extends an entry; an earlier one does not shorten it.
+    pub fn deny_until(&self, ip: &str, until_ms: u64) {
+        let key = self.ip_key(ip).into_owned();
+        let mut denied = self.denied.lock().unwrap_or_else(|e| e.into_inner());
+        if !denied.contains_key(&key) && denied.len() >= self.cfg.max_denied {
+            let now = now_ms();
+            denied.retain(|_, until| *until > now);
+            if denied.len() >= self.cfg.max_denied {
+                let soonest = denied.iter().min_by_key(|(_, until)| **until).map(|(k, _)| k.clone());
+                if let Some(k) = soonest {
+                    denied.remove(&k);
+                }
+            }
+        }
+        let until = denied.entry(key).or_insert(until_ms);
+        *until = (*until).max(until_ms);
+    }
+
+    pub fn denied_until(&self, ip: &str, now: u64) -> Option<u64> {
+        let denied = self.denied.lock().unwrap_or_else(|e| e.into_inner());
+        denied.get(&*self.ip_key(ip)).copied().filter(|until| *until > now)
+    }
+
+    pub fn check(&self, key: &RateKey) -> Outcome {
+        self.check_at(key, now_ms(), 100)
+    }
+
+    // `limit_pct` scales burst and refill (adaptive::Protection::limit_pct).
+    pub fn check_at(&self, key: &RateKey, now: u64, limit_pct: u8) -> Outcome {
+        if let Some(until) = self.denied_until(key.ip, now) {
+            let k = self.ip_key(key.ip).into_owned();
+            return Outcome { allowed: false, limited_by: Some((Scope::Ip, k)), remaining: 0, retry_after_ms: until - now, status: self.cfg.status };
+        }
+        let scopes = self.scopes(key);
+        let factor = limit_pct.clamp(1, 100) as f64 / 100.0;
+
+        // First pass: refill and look; second pass: spend. A request denied by
+        // one scope does not drain the others. Concurrent requests may both pass
+        // the look; the bucket then goes negative and later requests wait longer.
+        let mut tightest: Option<(usize, f64, Limit)> = None;
+        for (i, (scope, k, lim)) in scopes.iter().enumerate() {
+            let lim = scaled(*lim, factor);
+            let tokens = self.with_bucket(*scope, k, lim, now, |b| b.tokens);
+            if tightest.as_ref().is_none_or(|t| tokens < t.1) {
+                tightest = Some((i, tokens, lim));
+            }
+        }
+        let Some((i, tokens, lim)) = tightest else {
+            return Outcome { allowed: true, limited_by: None, remaining: u32::MAX, retry_after_ms: 0, status: self.cfg.status };
+        };
+        let (scope, k) = (scopes[i].0, scopes[i].1.to_string());
+        if tokens < 1.0 {
+            let retry = if lim.refill_per_sec > 0.0 { ((1.0 - tokens) / lim.refill_per_sec * 1000.0).ceil() as u64 } else { u64::MAX };
+            return Outcome { allowed: false, limited_by: Some((scope, k)), remaining: 0, retry_after_ms: retry, status: self.cfg.status };
+        }
+        for (scope, k, l) in scopes.iter() {
+            self.with_bucket(*scope, k, scaled(*l, factor), now, |b| b.tokens -= 1.0);
+        }
+        Outcome { allowed: true, limited_by: Some((scope, k)), remaining: (tokens - 1.0) as u32, retry_after_ms: 0, status: self.cfg.status }
+    }
+
+    pub fn tracked_keys(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().unwrap_or_else(|e| e.into_inner()).len()).sum()
+    }
+
+    // Buckets that have not refilled and denylist entries still in force;
+    // everything else a restart would recreate as it was.
+    pub fn snapshot(&self, now: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(SNAPSHOT_MAGIC);
+        out.push(SNAPSHOT_VERSION);
+        let mut buckets = Vec::new();
+        for shard in self.shards.iter() {
+            let shard = shard.lock().unwrap_or_else(|e| e.into_inner());
+            for scope in Scope::ALL {
+                let Some(lim) = self.limit(scope) else { continue };
+                for b in shard.buckets[scope as usize].values() {
+                    if refilled(b, lim, now) < lim.burst as f64 && b.key.len() <= u16::MAX as usize {
+                        buckets.push((scope, Arc::clone(&b.key), b.tokens, b.last_ms));
+                    }
+                }
+            }
+        }
+        out.extend_from_slice(&(buckets.len() as u32).to_le_bytes());
+        for (scope, key, tokens, last_ms) in buckets {
+            out.push(scope as u8);
+            put_key(&mut out, &key);
+            out.extend_from_slice(&tokens.to_le_bytes());
+            out.extend_from_slice(&last_ms.to_le_bytes());
+        }
+        let denied = self.denied.lock().unwrap_or_else(|e| e.into_inner());
+        let live: Vec<(&String, &u64)> = denied.iter().filter(|(k, until)| **until > now && k.len() <= u16::MAX as usize).collect();
+        out.extend_from_slice(&(live.len() as u32).to_le_bytes());
+        for (key, until) in live {
+            put_key(&mut out, key);
+            out.extend_from_slice(&until.to_le_bytes());
+        }
+        out
+    }
+
+    // Loads a snapshot taken by `snapshot`; returns how many buckets and
+    // denylist entries were restored. Keys already tracked keep their state,
+    // scopes no longer configured and expired entries are skipped.
+    pub fn restore(&self, bytes: &[u8], now: u64) -> Result<usize, String> {
+        let mut r = Reader { buf: bytes };
+        if r.take(4)? != SNAPSHOT_MAGIC {
+            return Err("not a rate limiter snapshot".to_string());
+        }
+        let version = r.take(1)?[0];
+        if version != SNAPSHOT_VERSION {
+            return Err(format!("unsupported snapshot version {}", version));
+        }
+        let mut buckets = Vec::new();
+        for _ in 0..r.u32()? {
+            let scope = *Scope::ALL.get(r.take(1)?[0] as usize).ok_or("invalid scope")?;
+            let key = r.key()?;
+            let tokens = f64::from_le_bytes(r.array()?);
+            let last_ms = r.u64()?;
+            buckets.push((scope, key, tokens, last_ms.min(now)));
+        }
+        let mut denies = Vec::new();
+        for _ in 0..r.u32()? {
+            denies.push((r.key()?, r.u64()?));
+        }
+        if !r.buf.is_empty() {
+            return Err("trailing bytes after snapshot".to_string());
+        }
+
+        let mut restored = 0;
+        for (scope, key, tokens, last_ms) in buckets {
+            let Some(lim) = self.limit(scope) else { continue };
+            if !tokens.is_finite() {
+                continue;
+            }
+            let mut h = DefaultHasher::new();
+            (scope as u8, key.as_str()).hash(&mut h);
+            let mut shard = self.shards[h.finish() as usize % self.shards.len()].lock().unwrap_or_else(|e| e.into_inner());
+            let i = scope as usize;
+            if shard.buckets[i].contains_key(key.as_str()) {
+                continue;
+            }
+            if shard.len() >= self.cfg.max_keys_per_shard {
+                shard.evict();
+            }
+            let key: Arc<str> = Arc::from(key);
+            let b = Bucket { tokens: tokens.min(lim.burst as f64), last_ms, key: Arc::clone(&key) };
+            shard.by_use.insert((last_ms, scope as u8, Arc::clone(&key)));
+            shard.buckets[i].insert(key, b);
+            restored += 1;
+        }
+        for (key, until) in denies {
+            if until > now {
+                self.deny_until(&key, until);
+                restored += 1;
+            }
+        }
+        Ok(restored)
+    }
+
+    // Writes a snapshot next to `path` and renames it into place, so a crash
+    // mid-write leaves the previous one.
+    pub fn save_snapshot(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        let written = fs::write(&tmp, self.snapshot(now_ms())).and_then(|_| fs::rename(&tmp, path));
+        if written.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        written
+    }
+
+    // Restores from `path`; a missing file is a first start, not an error.
+    pub fn load_snapshot(&self, path: &Path) -> Result<usize, String> {
+        match fs::read(path) {
+            Ok(bytes) => self.restore(&bytes, now_ms()).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    fn limit(&self, scope: Scope) -> Option<Limit> {
+        match scope {
+            Scope::Ip => self.cfg.ip,
+            Scope::Tenant => self.cfg.tenant,
+            Scope::Route => self.cfg.route,
+        }
+    }
+
+    fn ip_key<'a>(&self, ip: &'a str) -> Cow<'a, str> {
+        match ClientAddr::parse(ip) {
+            Some(a) => Cow::Owned(a.limit_key(self.cfg.v6_prefix).to_string()),
+            None => Cow::Borrowed(ip),
+        }
+    }
+
+    fn scopes<'a>(&self, key: &RateKey<'a>) -> Vec<(Scope, Cow<'a, str>, Limit)> {
+        let mut out = Vec::with_capacity(3);
+        if let Some(l) = self.cfg.ip {
+            out.push((Scope::Ip, self.ip_key(key.ip), l));
+        }
+        if let (Some(l), false) = (self.cfg.tenant, key.tenant.is_empty()) {
+            out.push((Scope::Tenant, Cow::Borrowed(key.tenant), l));
+        }
+        if let (Some(l), false) = (self.cfg.route, key.route.is_empty()) {
+            out.push((Scope::Route, Cow::Borrowed(key.route), l));
+        }
+        out
+    }
+
+    fn with_bucket<R>(&self, scope: Scope, key: &str, lim: Limit, now: u64, f: impl FnOnce(&mut Bucket) -> R) -> R {
+        let mut h = DefaultHasher::new();
+        (scope as u8, key).hash(&mut h);
+        let mut shard = self.shards[h.finish() as usize % self.shards.len()].lock().unwrap_or_else(|e| e.into_inner());
+        let i = scope as usize;
+        if !shard.buckets[i].contains_key(key) && shard.len() >= self.cfg.max_keys_per_shard {
+            shard.evict();
+        }
+        let Shard { buckets, by_use } = &mut *shard;
+        let b = match buckets[i].get_mut(key) {
+            Some(b) => b,
+            None => {
+                let key: Arc<str> = Arc::from(key);
+                by_use.insert((now, scope as u8, Arc::clone(&key)));
+                buckets[i].entry(Arc::clone(&key)).or_insert(Bucket { tokens: lim.burst as f64, last_ms: now, key })
+            }
+        };
+        b.tokens = refilled(b, lim, now);
+        if now > b.last_ms {
+            by_use.remove(&(b.last_ms, scope as u8, Arc::clone(&b.key)));
+            by_use.insert((now, scope as u8, Arc::clone(&b.key)));
+            b.last_ms = now;
+        }
+        f(b)
+    }
+}
+
+// Runs `RateLimiter::save_snapshot` every `interval` on a background thread,
+// and once more when dropped.
+pub struct Snapshotter {
+    stop: Arc<AtomicBool>,
+    failed: Arc<AtomicU64>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Snapshotter {
+    pub fn start(limiter: Arc<RateLimiter>, path: PathBuf, interval: Duration) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let failed = Arc::new(AtomicU64::new(0));
+        let (flag, count) = (Arc::clone(&stop), Arc::clone(&failed));
+        let thread = std::thread::spawn(move || {
+            loop {
+                std::thread::park_timeout(interval);
+                let stopping = flag.load(Ordering::Acquire);
+                if limiter.save_snapshot(&path).is_err() {
+                    count.fetch_add(1, Ordering::Relaxed);
+                }
+                if stopping {
+                    break;
+                }
+            }
+        });
+        Self { stop, failed, thread: Some(thread) }
+    }
+
+    // Snapshots that could not be written so far.
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Snapshotter {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(t) = self.thread.take() {
+            t.thread().unpark();
+            let _ = t.join();
+        }
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.buf.len() < n {
+            return Err("truncated snapshot".to_string());
+        }
+        let (head, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        let len = u16::from_le_bytes(self.array()?) as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| "key is not UTF-8".to_string())
+    }
+}
+
+fn put_key(out: &mut Vec<u8>, key: &str) {
+    out.extend_from_slice(&(key.len() as u16).to_le_bytes());
+    out.extend_from_slice(key.as_bytes());
+}
+
+fn refilled(b: &Bucket, lim: Limit, now: u64) -> f64 {
+    let elapsed = now.saturating_sub(b.last_ms) as f64 / 1000.0;
+    (b.tokens + elapsed * lim.refill_per_sec).min(lim.burst as f64)
+}
+
+fn scaled(l: Limit, factor: f64) -> Limit {
+    Limit { burst: ((l.burst as f64 * factor).ceil() as u32).max(1), refill_per_sec: l.refill_per_sec * factor }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_per_scope() {
+        let cfg = RateLimitConfig {
+            ip: Some(Limit { burst: 2, refill_per_sec: 1.0 }),
+            tenant: Some(Limit { burst: 3, refill_per_sec: 10.0 }),
+            ..RateLimitConfig::default()
+        };
+        let rl = RateLimiter::new(cfg);
+        let a = RateKey { ip: "2001:db8::1", tenant: "acme", route: "/api" };
+        let b = RateKey { ip: "2001:db8::2", tenant: "acme", route: "/api" }; // same /64
+
+        assert!(rl.check_at(&a, 0, 100).allowed);
+        assert!(rl.check_at(&b, 0, 100).allowed);
+        let out = rl.check_at(&a, 0, 100);
+        assert!(!out.allowed);
+        assert_eq!(out.limited_by, Some((Scope::Ip, "2001:db8::".to_string())));
+        assert_eq!((out.retry_after_ms, out.meta_flags()), (1000, SEC_RATELIM));
+        let d = out.to_decision(0);
+        assert!(matches!(d.action, Action::Deny(429)) && d.tags == vec!["ratelimit"]);
+
+        // the denied request did not spend a tenant token: one is left
+        let c = RateKey { ip: "198.51.100.7", tenant: "acme", route: "" };
+        assert!(rl.check_at(&c, 0, 100).allowed);
+        assert_eq!(rl.check_at(&c, 0, 100).limited_by.map(|l| l.0), Some(Scope::Tenant));
+
+        // refill after one second; adaptive scaling halves the burst
+        assert!(rl.check_at(&a, 1_000, 100).allowed);
+        let d = RateKey { ip: "192.0.2.1", tenant: "", route: "" };
+        assert!(rl.check_at(&d, 0, 50).allowed);
+        assert!(!rl.check_at(&d, 0, 50).allowed);
+    }
+
+    #[test]
+    fn test_least_recently_used_bucket_evicted() {
+        let cfg = RateLimitConfig { ip: Some(Limit { burst: 1, refill_per_sec: 0.0 }), shards: 1, max_keys_per_shard: 2, ..RateLimitConfig::default() };
+        let rl = RateLimiter::new(cfg);
+        let key = |ip| RateKey { ip, tenant: "", route: "" };
+        assert!(rl.check_at(&key("10.0.0.1"), 0, 100).allowed);
+        assert!(rl.check_at(&key("10.0.0.2"), 1, 100).allowed);
+        assert!(!rl.check_at(&key("10.0.0.1"), 2, 100).allowed); // .1 used last
+        assert!(rl.check_at(&key("10.0.0.3"), 3, 100).allowed); // evicts .2
+        assert_eq!(rl.tracked_keys(), 2);
+        assert!(!rl.check_at(&key("10.0.0.1"), 4, 100).allowed, "kept: still empty");
+        assert!(rl.check_at(&key("10.0.0.2"), 5, 100).allowed, "forgotten: full again");
+    }
+
+    #[test]
+    fn test_denylist() {
+        let rl = RateLimiter::new(RateLimitConfig::default());
+        let key = |ip| RateKey { ip, tenant: "", route: "" };
+        rl.deny_until("2001:db8::1", 5_000);
+        rl.deny_until("2001:db8::1", 1_000); // does not shorten
+        let out = rl.check_at(&key("2001:db8::2"), 0, 100); // same /64
+        assert_eq!((out.allowed, out.retry_after_ms), (false, 5_000));
+        assert_eq!(out.limited_by, Some((Scope::Ip, "2001:db8::".to_string())));
+        assert!(rl.check_at(&key("2001:db8::2"), 5_000, 100).allowed);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let cfg = || RateLimitConfig {
+            ip: Some(Limit { burst: 2, refill_per_sec: 1.0 }),
+            route: Some(Limit { burst: 10, refill_per_sec: 100.0 }),
+            ..RateLimitConfig::default()
+        };
+        let rl = RateLimiter::new(cfg());
+        let attacker = RateKey { ip: "198.51.100.7", tenant: "", route: "/login" };
+        assert!(rl.check_at(&attacker, 0, 100).allowed);
+        assert!(rl.check_at(&attacker, 0, 100).allowed);
+        assert!(rl.check_at(&RateKey { ip: "192.0.2.1", tenant: "", route: "" }, 0, 100).allowed);
+        rl.deny_until("203.0.113.9", 60_000);
+        rl.deny_until("203.0.113.10", 10);
+
+        // at 500 ms the route bucket has refilled and 192.0.2.1's nearly has;
+        // only what still holds a client back is kept
+        let snap = rl.snapshot(500);
+        let restarted = RateLimiter::new(cfg());
+        assert_eq!(restarted.restore(&snap, 500), Ok(3));
+        assert!(!restarted.check_at(&attacker, 500, 100).allowed, "still limited after the restart");
+        assert!(restarted.check_at(&attacker, 1_000, 100).allowed);
+        assert_eq!(restarted.denied_until("203.0.113.9", 500), Some(60_000));
+        assert_eq!(restarted.denied_until("203.0.113.10", 500), None);
+
+        let without_ip = RateLimiter::new(RateLimitConfig { ip: None, ..cfg() });
+        assert_eq!(without_ip.restore(&snap, 500), Ok(1), "the ip scope is off: only the denylist entry");
+
+        assert!(restarted.restore(&snap[..snap.len() - 1], 500).unwrap_err().contains("truncated"));
+        assert!(restarted.restore(b"OLRL\x09", 500).unwrap_err().contains("version"));
+        assert!(restarted.restore(b"nope", 500).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_file() {
+        let dir = std::env::temp_dir().join(format!("olwsx-ratelimit-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("limits.snap");
+        let rl = Arc::new(RateLimiter::new(RateLimitConfig::default()));
+        assert_eq!(rl.load_snapshot(&path), Ok(0), "first start");
+        rl.deny_until("198.51.100.7", now_ms() + 60_000);
+        drop(Snapshotter::start(Arc::clone(&rl), path.clone(), Duration::from_secs(3600))); // writes on drop
+        let restarted = RateLimiter::new(RateLimitConfig::default());
+        assert_eq!(restarted.load_snapshot(&path), Ok(1));
+        assert!(restarted.denied_until("198.51.100.7", now_ms()).is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,566 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/regex.rs
+// Role: Final & Stable linear-time regex for WAF `regex` matchers
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Compile a practical subset: literals, `.`, classes `[a-z]`/`[^..]`,
+//   `\d \w \s \D \W \S \b \B`, escapes, groups `(..)`/`(?:..)`, `|`,
+//   `* + ? {n} {n,} {n,m}` (lazy forms accepted), anchors `^ $`, flag `(?i)`.
+// - Unanchored search over bytes with a Pike VM: O(len(hay) * len(program)),
+//   no backtracking, so attacker-controlled input cannot blow up.
+// - Always ASCII case-insensitive, like every other WAF matcher; `(?i)` is
+//   accepted for compatibility with patterns written elsewhere.
+// - Bounded program size; backreferences and lookaround are rejected.
+// =============================================================================
+
+use std::fmt;
+
+const MAX_INSTS: usize = 10_000;
+const MAX_REPEAT: u32 = 1_000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegexError {
+    pub pos: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "regex error at {}: {}", self.pos, self.msg)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Set([u64; 4]);
+
+impl Set {
+    fn empty() -> Self {
+        Set([0; 4])
+    }
+
+    fn add(&mut self, b: u8) {
+        self.0[(b >> 6) as usize] |= 1 << (b & 63);
+    }
+
+    fn add_range(&mut self, lo: u8, hi: u8) {
+        for b in lo..=hi {
+            self.add(b);
+        }
+    }
+
+    fn has(&self, b: u8) -> bool {
+        self.0[(b >> 6) as usize] & (1 << (b & 63)) != 0
+    }
+
+    fn negate(&mut self) {
+        for w in self.0.iter_mut() {
+            *w = !*w;
+        }
+    }
+
+    fn union(&mut self, o: &Set) {
+        for (a, b) in self.0.iter_mut().zip(o.0.iter()) {
+            *a |= *b;
+        }
+    }
+
+    // Adds the other case of every ASCII letter.
+    fn fold_case(&mut self) {
+        for b in b'a'..=b'z' {
+            if self.has(b) || self.has(b.to_ascii_uppercase()) {
+                self.add(b);
+                self.add(b.to_ascii_uppercase());
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Inst {
+    Byte(Set),
+    Split(usize, usize),
+    Jmp(usize),
+    Start,
+    End,
+    WordBoundary(bool), // false = \B
+    Match,
+}
+
+// Parsed pattern.
+#[derive(Clone, Debug)]
+enum Node {
+    Empty,
+    Set(Set),
+    Start,
+    End,
+    WordBoundary(bool),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat(Box<Node>, u32, Option<u32>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Regex {
+    source: String,
+    prog: Vec<Inst>,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, RegexError> {
+        let mut p = Parser { s: pattern.as_bytes(), pos: 0 };
+        if pattern.starts_with("(?i)") {
+            p.pos = 4;
+        }
+        let node = p.alt()?;
+        if p.pos < p.s.len() {
+            return Err(p.err("unmatched ')'"));
+        }
+        let mut c = Compiler { prog: Vec::new() };
+        c.emit(&node)?;
+        c.push(Inst::Match)?;
+        Ok(Regex { source: pattern.to_string(), prog: c.prog })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    // True when the pattern matches anywhere in `hay`.
+    pub fn is_match(&self, hay: &[u8]) -> bool {
+        let n = self.prog.len();
+        let mut clist = Threads::new(n);
+        let mut nlist = Threads::new(n);
+        for i in 0..=hay.len() {
+            // a new thread starts at every position (unanchored search)
+            if self.add(&mut clist, 0, hay, i) {
+                return true;
+            }
+            if i == hay.len() {
+                break;
+            }
+            nlist.clear();
+            for k in 0..clist.len {
+                let pc = clist.dense[k];
+                if let Inst::Byte(set) = &self.prog[pc]
+                    && set.has(hay[i])
+                    && self.add(&mut nlist, pc + 1, hay, i + 1)
+                {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut clist, &mut nlist);
+        }
+        false
+    }
+
+    // Follows epsilon transitions from `pc` at `at`; true when Match is reached.
+    fn add(&self, list: &mut Threads, pc: usize, hay: &[u8], at: usize) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if !list.insert(pc) {
+                continue;
+            }
+            match &self.prog[pc] {
+                Inst::Match => return true,
+                Inst::Byte(_) => {}
+                Inst::Jmp(t) => stack.push(*t),
+                Inst::Split(a, b) => {
+                    stack.push(*b);
+                    stack.push(*a);
+                }
+                Inst::Start => {
+                    if at == 0 {
+                        stack.push(pc + 1);
+                    }
+                }
+                Inst::End => {
+                    if at == hay.len() {
+                        stack.push(pc + 1);
+                    }
+                }
+                Inst::WordBoundary(want) => {
+                    let before = at > 0 && is_word(hay[at - 1]);
+                    let after = at < hay.len() && is_word(hay[at]);
+                    if (before != after) == *want {
+                        stack.push(pc + 1);
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+fn is_word(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+// Sparse set of program counters.
+struct Threads {
+    dense: Vec<usize>,
+    sparse: Vec<usize>,
+    len: usize,
+}
+
+impl Threads {
+    fn new(n: usize) -> Self {
+        Threads { dense: vec![0; n], sparse: vec![0; n], len: 0 }
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn insert(&mut self, pc: usize) -> bool {
+        let i = self.sparse[pc];
+        if i < self.len && self.dense[i] == pc {
+            return false;
+        }
+        self.sparse[pc] = self.len;
+        self.dense[self.len] = pc;
+        self.len += 1;
+        true
+    }
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn err(&self, msg: &str) -> RegexError {
+        RegexError { pos: self.pos, msg: msg.to_string() }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).copied()
+    }
+
+    fn alt(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.concat()?];
+        while self.peek() == Some(b'|') {
+            self.pos += 1;
+            branches.push(self.concat()?);
+        }
+        Ok(if branches.len() == 1 { branches.pop().unwrap_or(Node::Empty) } else { Node::Alt(branches) })
+    }
+
+    fn concat(&mut self) -> Result<Node, RegexError> {
+        let mut items = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == b'|' || c == b')' {
+                break;
+            }
+            let atom = self.atom()?;
+            items.push(self.quantified(atom)?);
+        }
+        Ok(Node::Concat(items))
+    }
+
+    fn quantified(&mut self, atom: Node) -> Result<Node, RegexError> {
+        let (min, max) = match self.peek() {
+            Some(b'*') => (0, None),
+            Some(b'+') => (1, None),
+            Some(b'?') => (0, Some(1)),
+            Some(b'{') => match self.braces()? {
+                Some(r) => r,
+                None => return Ok(atom),
+            },
+            _ => return Ok(atom),
+        };
+        self.pos += 1; // the quantifier, or the closing '}'
+        if self.peek() == Some(b'?') {
+            self.pos += 1; // lazy: same language, same answer for is_match
+        }
+        if matches!(self.peek(), Some(b'*' | b'+' | b'?')) {
+            return Err(self.err("nested quantifier"));
+        }
+        if matches!(atom, Node::Start | Node::End | Node::WordBoundary(_)) {
+            return Err(self.err("quantified assertion"));
+        }
+        Ok(Node::Repeat(Box::new(atom), min, max))
+    }
+
+    // `{n}`, `{n,}`, `{n,m}`; leaves pos on the closing '}'. A '{' that does
+    // not start a valid counter is a literal.
+    fn braces(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let start = self.pos;
+        let Some(len) = self.s[start..].iter().position(|b| *b == b'}') else { return Ok(None) };
+        let body = std::str::from_utf8(&self.s[start + 1..start + len]).unwrap_or("");
+        let num = |t: &str| t.parse::<u32>().ok().filter(|n| *n <= MAX_REPEAT);
+        let parsed = match body.split_once(',') {
+            None => num(body).map(|n| (n, Some(n))),
+            Some((a, "")) => num(a).map(|n| (n, None)),
+            Some((a, b)) => num(a).zip(num(b)).map(|(a, b)| (a, Some(b))),
+        };
+        match parsed {
+            Some((a, Some(b))) if a > b => Err(self.err("bad repeat range")),
+            Some(r) => {
+                self.pos = start + len;
+                Ok(Some(r))
+            }
+            None if body.chars().all(|c| c.is_ascii_digit() || c == ',') && !body.is_empty() => {
+                Err(self.err("repeat count too large"))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Node, RegexError> {
+        let c = self.peek().ok_or_else(|| self.err("unexpected end"))?;
+        self.pos += 1;
+        match c {
+            b'.' => {
+                let mut s = Set::empty();
+                s.add(b'\n');
+                s.negate();
+                Ok(Node::Set(s))
+            }
+            b'^' => Ok(Node::Start),
+            b'$' => Ok(Node::End),
+            b'(' => {
+                if self.s[self.pos..].starts_with(b"?:") {
+                    self.pos += 2;
+                } else if self.peek() == Some(b'?') {
+                    return Err(self.err("unsupported group flag"));
+                }
+                let inner = self.alt()?;
+                if self.peek() != Some(b')') {
+                    return Err(self.err("missing ')'"));
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            b'[' => self.class().map(Node::Set),
+            b'\\' => self.escape(false),
+            b'*' | b'+' | b'?' => Err(self.err("quantifier without operand")),
+            _ => {
+                let mut s = Set::empty();
+                s.add(c);
+                Ok(Node::Set(s))
+            }
+        }
+    }
+
+    // After a backslash. In a class, \b is a backspace and assertions are errors.
+    fn escape(&mut self, in_class: bool) -> Result<Node, RegexError> {
+        let c = self.peek().ok_or_else(|| self.err("trailing backslash"))?;
+        self.pos += 1;
+        let mut s = Set::empty();
+        match c {
+            b'd' | b'D' => s.add_range(b'0', b'9'),
+            b'w' | b'W' => {
+                s.add_range(b'a', b'z');
+                s.add_range(b'A', b'Z');
+                s.add_range(b'0', b'9');
+                s.add(b'_');
+            }
+            b's' | b'S' => {
+                for b in [b' ', b'\t', b'\n', b'\r', 0x0b, 0x0c] {
+                    s.add(b);
+                }
+            }
+            b'b' if in_class => s.add(0x08),
+            b'b' => return Ok(Node::WordBoundary(true)),
+            b'B' if !in_class => return Ok(Node::WordBoundary(false)),
+            b'n' => s.add(b'\n'),
+            b'r' => s.add(b'\r'),
+            b't' => s.add(b'\t'),
+            b'0' => s.add(0),
+            b'x' => {
+                let hex = self.s.get(self.pos..self.pos + 2).and_then(|h| std::str::from_utf8(h).ok());
+                let v = hex.and_then(|h| u8::from_str_radix(h, 16).ok()).ok_or_else(|| self.err("bad \\x escape"))?;
+                self.pos += 2;
+                s.add(v);
+            }
+            c if c.is_ascii_alphanumeric() => return Err(self.err("unsupported escape")),
+            c => s.add(c),
+        }
+        if matches!(c, b'D' | b'W' | b'S') {
+            s.negate();
+        }
+        Ok(Node::Set(s))
+    }
+
+    fn class(&mut self) -> Result<Set, RegexError> {
+        let mut set = Set::empty();
+        let negated = self.peek() == Some(b'^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut first = true;
+        loop {
+            let c = self.peek().ok_or_else(|| self.err("missing ']'"))?;
+            if c == b']' && !first {
+                self.pos += 1;
+                break;
+            }
+            first = false;
+            let lo = self.class_item()?;
+            let range = self.peek() == Some(b'-') && self.s.get(self.pos + 1).is_some_and(|b| *b != b']');
+            match lo {
+                ClassItem::Byte(lo) if range => {
+                    self.pos += 1;
+                    let ClassItem::Byte(hi) = self.class_item()? else { return Err(self.err("bad class range")) };
+                    if lo > hi {
+                        return Err(self.err("bad class range"));
+                    }
+                    set.add_range(lo, hi);
+                }
+                ClassItem::Byte(b) => set.add(b),
+                ClassItem::Set(s) => set.union(&s),
+            }
+        }
+        if negated {
+            set.fold_case();
+            set.negate();
+        }
+        Ok(set)
+    }
+
+    fn class_item(&mut self) -> Result<ClassItem, RegexError> {
+        let c = self.peek().ok_or_else(|| self.err("missing ']'"))?;
+        self.pos += 1;
+        if c != b'\\' {
+            return Ok(ClassItem::Byte(c));
+        }
+        let Node::Set(s) = self.escape(true)? else { return Err(self.err("assertion in class")) };
+        let single = (0..=255u8).filter(|b| s.has(*b)).collect::<Vec<u8>>();
+        Ok(if single.len() == 1 { ClassItem::Byte(single[0]) } else { ClassItem::Set(s) })
+    }
+}
+
+enum ClassItem {
+    Byte(u8),
+    Set(Set),
+}
+
+struct Compiler {
+    prog: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, i: Inst) -> Result<usize, RegexError> {
+        if self.prog.len() >= MAX_INSTS {
+            return Err(RegexError { pos: 0, msg: "pattern too large".to_string() });
+        }
+        self.prog.push(i);
+        Ok(self.prog.len() - 1)
+    }
+
+    fn emit(&mut self, n: &Node) -> Result<(), RegexError> {
+        match n {
+            Node::Empty => {}
+            Node::Set(s) => {
+                let mut s = *s;
+                s.fold_case();
+                self.push(Inst::Byte(s))?;
+            }
+            Node::Start => {
+                self.push(Inst::Start)?;
+            }
+            Node::End => {
+                self.push(Inst::End)?;
+            }
+            Node::WordBoundary(w) => {
+                self.push(Inst::WordBoundary(*w))?;
+            }
+            Node::Concat(items) => {
+                for it in items {
+                    self.emit(it)?;
+                }
+            }
+            Node::Alt(branches) => {
+                // split L1, next; L1: a; jmp end; next: split ... ; last: z
+                let mut jumps = Vec::new();
+                for (k, b) in branches.iter().enumerate() {
+                    if k + 1 < branches.len() {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.emit(b)?;
+                        jumps.push(self.push(Inst::Jmp(0))?);
+                        let next = self.prog.len();
+                        self.prog[split] = Inst::Split(split + 1, next);
+                    } else {
+                        self.emit(b)?;
+                    }
+                }
+                let end = self.prog.len();
+                for j in jumps {
+                    self.prog[j] = Inst::Jmp(end);
+                }
+            }
+            Node::Repeat(inner, min, max) => {
+                for _ in 0..*min {
+                    self.emit(inner)?;
+                }
+                match max {
+                    None => {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.emit(inner)?;
+                        self.push(Inst::Jmp(split))?;
+                        let end = self.prog.len();
+                        self.prog[split] = Inst::Split(split + 1, end);
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0))?);
+                            self.emit(inner)?;
+                        }
+                        let end = self.prog.len();
+                        for s in splits {
+                            self.prog[s] = Inst::Split(s + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn m(p: &str, hay: &str) -> bool {
+        Regex::new(p).unwrap().is_match(hay.as_bytes())
+    }
+
+    #[test]
+    fn test_matching() {
+        assert!(m(r"(?i)union\s+select", "id=1 UNION   SELECT pass"));
+        assert!(!m(r"(?i)union\s+select", "id=1 unionselect"));
+        assert!(m(r"^/admin(/|$)", "/admin"));
+        assert!(!m(r"^/admin(/|$)", "/administrator"));
+        assert!(m(r"\bor\b\s*1=1", "x' OR 1=1"));
+        assert!(!m(r"\bor\b\s*1=1", "x' FOR 1=1"));
+        assert!(m(r"[^a-z0-9]eval\(", ";EVAL(x)"));
+        assert!(m(r"a{2,3}b", "caaab"));
+        assert!(!m(r"^a{2,3}b", "ab"));
+        assert!(m(r"\.(php|asp)x?$", "/x/shell.ASPX"));
+        assert!(m(r"bad-proxy", "1.2.3.4, bad-proxy"));
+        assert!(m(r"%2e%2e|\.\.", "/a/%2E%2E/b"));
+        assert!(m(r"", "anything"));
+    }
+
+    #[test]
+    fn test_rejects_unsupported() {
+        for p in [r"(a", r"a)", r"[a", r"\1", r"(?=a)", r"a**", r"*a", r"a{5,2}", r"a{1001}", r"\xZZ"] {
+            assert!(Regex::new(p).is_err(), "{}", p);
+        }
+        assert!(Regex::new(&"(a|b)".repeat(5_000)).is_err(), "program size is bounded");
+    }
+
+    #[test]
+    fn test_linear_on_hostile_input() {
+        let re = Regex::new(r"(a+)+$").unwrap();
+        let hay = format!("{}!", "a".repeat(50_000));
+        let t = std::time::Instant::now();
+        assert!(!re.is_match(hay.as_bytes()));
+        assert!(t.elapsed() < std::time::Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,150 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/signedurl.rs
+// Role: Final & Stable signed URLs (HMAC-SHA256, expiry, optional IP binding)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Generate "<path>?exp=<unix>&sig=<hex>" (plus "&bind=ip" when IP-bound).
+// - Verify signature, expiry, and client IP binding in constant time.
+// - Guard that enforces signatures on configured path prefixes, matched on
+//   the canonical path (security/path.rs) at segment boundaries.
+// - Signatures cover the canonical path, so equivalent spellings verify alike.
+// =============================================================================
+
+use crate::addr::ClientAddr;
+use crate::crypto::{ct_eq, hex, hmac_sha256, unhex};
+use crate::path::{canonical, under};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SignedUrlError {
+    Missing,     // no exp/sig on a protected path
+    Malformed,
+    BadSignature,
+    Expired,
+}
+
+impl SignedUrlError {
+    // HTTP status hint, in the spirit of Action::Deny(u16)
+    pub fn status(&self) -> u16 {
+        match self {
+            SignedUrlError::Expired => 410,
+            _ => 403,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Signer {
+    key: Vec<u8>,
+}
+
+impl Signer {
+    pub fn new(key: &[u8]) -> Self {
+        Self { key: key.to_vec() }
+    }
+
+    // `client_ip` binds the URL to one client; None leaves it unbound.
+    pub fn sign(&self, path: &str, expires_unix: u64, client_ip: Option<&str>) -> String {
+        let sig = hex(&self.mac(path, expires_unix, client_ip));
+        match client_ip {
+            Some(_) => format!("{}?exp={}&bind=ip&sig={}", path, expires_unix, sig),
+            None => format!("{}?exp={}&sig={}", path, expires_unix, sig),
+        }
+    }
+
+    pub fn verify(&self, url: &str, client_ip: &str, now_unix: u64) -> Result<(), SignedUrlError> {
+        let (path, query) = url.split_once('?').ok_or(SignedUrlError::Missing)?;
+        let mut exp = None;
+        let mut sig = None;
+        let mut bind = false;
+        for pair in query.split('&') {
+            match pair.split_once('=') {
+                Some(("exp", v)) => exp = Some(v.parse::<u64>().map_err(|_| SignedUrlError::Malformed)?),
+                Some(("sig", v)) => sig = Some(unhex(v).ok_or(SignedUrlError::Malformed)?),
+                Some(("bind", "ip")) => bind = true,
+                _ => {}
+            }
+        }
+        let (exp, sig) = match (exp, sig) {
+            (Some(e), Some(s)) => (e, s),
+            _ => return Err(SignedUrlError::Missing),
+        };
+        let expected = self.mac(path, exp, if bind { Some(client_ip) } else { None });
+        if !ct_eq(&expected, &sig) {
+            return Err(SignedUrlError::BadSignature);
+        }
+        if now_unix > exp {
+            return Err(SignedUrlError::Expired);
+        }
+        Ok(())
+    }
+
+    fn mac(&self, path: &str, exp: u64, ip: Option<&str>) -> [u8; 32] {
+        // bind to the canonical form so ::ffff:a.b.c.d and a.b.c.d verify alike
+        let ip = ip.map(|s| ClientAddr::parse(s).map(|a| a.to_string()).unwrap_or_else(|| s.to_string()));
+        let msg = format!("{}\n{}\n{}", canonical(path), exp, ip.unwrap_or_default());
+        hmac_sha256(&self.key, msg.as_bytes())
+    }
+}
+
+// Enforces signed URLs on configured path prefixes; other paths pass through.
+#[derive(Clone)]
+pub struct SignedUrlGuard {
+    signer: Signer,
+    prefixes: Vec<String>,
+}
+
+impl SignedUrlGuard {
+    pub fn new(signer: Signer, prefixes: Vec<String>) -> Self {
+        Self { signer, prefixes }
+    }
+
+    pub fn check(&self, url: &str, client_ip: &str) -> Result<(), SignedUrlError> {
+        self.check_at(url, client_ip, now_unix())
+    }
+
+    pub fn check_at(&self, url: &str, client_ip: &str, now_unix: u64) -> Result<(), SignedUrlError> {
+        let path = canonical(url);
+        if !self.prefixes.iter().any(|p| under(&path, p)) {
+            return Ok(());
+        }
+        self.signer.verify(url, client_ip, now_unix)
+    }
+}
+
+fn now_unix() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_url() {
+        let signer = Signer::new(b"secret");
+        let guard = SignedUrlGuard::new(signer.clone(), vec!["/downloads/".to_string()]);
+
+        let url = signer.sign("/downloads/a.zip", 1_000, None);
+        assert_eq!(guard.check_at(&url, "198.51.100.1", 999), Ok(()));
+        assert_eq!(guard.check_at(&url, "198.51.100.1", 1_001), Err(SignedUrlError::Expired));
+        assert_eq!(guard.check_at("/downloads/a.zip", "198.51.100.1", 0), Err(SignedUrlError::Missing));
+        assert_eq!(guard.check_at("/public/x", "198.51.100.1", 0), Ok(()));
+        // spellings that resolve into the protected prefix are still guarded
+        for sneaky in ["/public/%2e%2e/downloads/a.zip", "//downloads/a.zip", "/downloads", "/x/../downloads/./a.zip"] {
+            assert_eq!(guard.check_at(sneaky, "198.51.100.1", 0), Err(SignedUrlError::Missing), "{}", sneaky);
+        }
+        assert_eq!(guard.check_at("/downloadsx/a.zip", "198.51.100.1", 0), Ok(()));
+        assert_eq!(guard.check_at(&url.replace("/downloads/", "//downloads/./"), "198.51.100.1", 999), Ok(()));
+
+        let tampered = url.replace("a.zip", "b.zip");
+        assert_eq!(guard.check_at(&tampered, "198.51.100.1", 0), Err(SignedUrlError::BadSignature));
+
+        let bound = signer.sign("/downloads/a.zip", 1_000, Some("198.51.100.1"));
+        assert_eq!(guard.check_at(&bound, "198.51.100.1", 0), Ok(()));
+        assert_eq!(guard.check_at(&bound, "::ffff:198.51.100.1", 0), Ok(()));
+        assert_eq!(guard.check_at(&bound, "198.51.100.2", 0), Err(SignedUrlError::BadSignature));
+    }
+}
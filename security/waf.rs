@@ -5,28 +5,47 @@
 // Philosophy: One version, the most stable version, first and last.
 // -----------------------------------------------------------------------------
 // Responsibilities:
-// - Fixed rule schema: path/user-agent/body/header matchers and actions.
+// - Fixed rule schema: path/user-agent/body/header/flag matchers and actions.
 // - Deterministic evaluation order: deny -> challenge -> log -> allow.
 // - SIMD-friendly scanning and bounded memory; pure Rust, no unsafe.
+// - Hot reload: versioned rule sets swapped atomically (SharedEngine).
 // =============================================================================
 
+use crate::addr::ClientAddr;
+use crate::regex::Regex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Clone, Debug)]
+mod olwsx_plugins_sdk {
+    pub use crate::sdk::flag;
+}
+
+mod olwsx_plugins_flags {
+    pub use crate::flags::FlagStore;
+}
+
+use olwsx_plugins_flags::FlagStore;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Action {
     Deny(u16),         // HTTP status to return (e.g., 403)
     Challenge(u16),    // Lightweight proof-of-work or JS gate (status hint)
     LogOnly,           // Record but allow
     Allow,             // Explicit allow (short-circuit)
+    Throttle(u32),     // Allow, but pace the response body (bytes/s)
 }
 
 #[derive(Clone, Debug)]
 pub enum Field {
     Path,
+    Query,             // raw query string of `path` (after '?'), empty if none
     UserAgent,
     Header(String),
     Body,
-    Ip,                // string representation
+    Ip,                // canonical ClientAddr text (mapped IPv4 unwrapped)
+    ClientCertCn,      // verified mTLS client certificate subject CN
+    Flag(String),      // runtime feature flag value (plugins/flags.rs), empty if unset
 }
 
 #[derive(Clone, Debug)]
@@ -34,7 +53,7 @@ pub enum Matcher {
     Contains(String),
     Prefix(String),
     Suffix(String),
-    Regex(String),     // stored, but evaluated via safe substring (no RE engine here)
+    Regex(String),     // security/regex.rs syntax, compiled by Engine::new
     Eq(String),
 }
 
@@ -44,7 +63,7 @@ pub struct Rule {
     pub field: Field,
     pub matcher: Matcher,
     pub action: Action,
-    pub tags: &'static [&'static str], // e.g., ["sqlmap", "traversal"]
+    pub tags: Vec<String>,             // e.g., ["sqlmap", "traversal"]
     pub severity: u8,                  // 1..10
 }
 
@@ -55,6 +74,7 @@ pub struct RequestView<'a> {
     pub headers: &'a [(&'a str, &'a str)],
     pub body: &'a [u8],
     pub ip: &'a str,
+    pub client_cert_cn: &'a str, // verified mTLS certificate CN (sdk::client_cert); empty without one
 }
 
 #[derive(Clone, Debug)]
@@ -63,25 +83,53 @@ pub struct Decision {
     pub applied_rule_id: Option<u32>,
     pub action: Action,
     pub reason: String,
-    pub tags: Vec<&'static str>,
+    pub tags: Vec<String>,
     pub severity: u8,
+    pub rule_version: u64, // revision of the rule set that decided (0 = unversioned)
 }
 
 pub struct Engine {
     rules: Vec<Rule>,
+    regexes: Vec<Option<Regex>>, // per rule; None for other matchers and invalid patterns
+    version: u64,
+    flags: Option<Arc<FlagStore>>, // for Field::Flag; None reads the installed store
 }
 
 impl Engine {
+    // A regex that does not compile never matches; the loaders (waf_dsl,
+    // waf_config, waf_compile::check) reject such rules before they get here.
     pub fn new(rules: Vec<Rule>) -> Self {
-        Self { rules }
+        let regexes = rules
+            .iter()
+            .map(|r| match &r.matcher {
+                Matcher::Regex(p) => Regex::new(p).ok(),
+                _ => None,
+            })
+            .collect();
+        Self { rules, regexes, version: 0, flags: None }
+    }
+
+    // Evaluate Field::Flag against `store` instead of the installed one.
+    pub fn with_flags(mut self, store: Arc<FlagStore>) -> Self {
+        self.flags = Some(store);
+        self
+    }
+
+    pub fn with_version(mut self, version: u64) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
     }
 
     pub fn decide(&self, req: &RequestView) -> Decision {
-        // Evaluation order: Deny first, then Challenge, LogOnly, Allow
+        // Evaluation order: Deny first, then Challenge, Throttle, LogOnly, Allow
         let mut candidate: Option<(Rule, String)> = None;
 
-        for r in self.rules.iter() {
-            if self.matches(req, r) {
+        for (r, re) in self.rules.iter().zip(self.regexes.iter()) {
+            if self.matches(req, r, re.as_ref()) {
                 let why = Self::describe_match(req, r);
                 match r.action {
                     Action::Deny(_) => {
@@ -95,6 +143,11 @@ impl Engine {
                             // continue to see if any deny appears later; otherwise pick challenge
                         }
                     }
+                    Action::Throttle(_) => {
+                        if candidate.as_ref().is_none_or(|(c, _)| c.action == Action::LogOnly) {
+                            candidate = Some((r.clone(), why));
+                        }
+                    }
                     Action::LogOnly => {
                         if candidate.is_none() {
                             candidate = Some((r.clone(), why));
@@ -107,8 +160,9 @@ impl Engine {
                             applied_rule_id: Some(r.id),
                             action: Action::Allow,
                             reason: "explicit allow".to_string(),
-                            tags: r.tags.to_vec(),
+                            tags: r.tags.clone(),
                             severity: r.severity,
+                            rule_version: self.version,
                         };
                     }
                 }
@@ -121,8 +175,9 @@ impl Engine {
                 applied_rule_id: Some(r.id),
                 action: r.action.clone(),
                 reason: why,
-                tags: r.tags.to_vec(),
+                tags: r.tags.clone(),
                 severity: r.severity,
+                rule_version: self.version,
             }
         } else {
             Decision {
@@ -132,44 +187,59 @@ impl Engine {
                 reason: "no rule matched".to_string(),
                 tags: vec![],
                 severity: 0,
+                rule_version: self.version,
             }
         }
     }
 
-    fn matches(&self, req: &RequestView, r: &Rule) -> bool {
+    fn matches(&self, req: &RequestView, r: &Rule, re: Option<&Regex>) -> bool {
         let hay = match &r.field {
             Field::Path => req.path,
+            Field::Query => req.path.split_once('?').map(|(_, q)| q).unwrap_or(""),
             Field::UserAgent => req.user_agent,
             Field::Header(name) => {
                 for (k, v) in req.headers.iter() {
                     if eq_ci(k, name) {
-                        return self.match_str(v, &r.matcher);
+                        return self.match_str(v, &r.matcher, re);
                     }
                 }
                 return false;
             }
             Field::Body => {
                 // Body matching is only Contains/Eq in bytes (ASCII-safe here)
-                return self.match_bytes(req.body, &r.matcher);
+                return self.match_bytes(req.body, &r.matcher, re);
+            }
+            Field::Ip => {
+                let canon = ClientAddr::parse(req.ip).map(|a| a.to_string());
+                return self.match_str(canon.as_deref().unwrap_or(req.ip), &r.matcher, re);
+            }
+            Field::ClientCertCn => req.client_cert_cn,
+            Field::Flag(name) => {
+                let v = match &self.flags {
+                    Some(store) => store.get(name),
+                    None => olwsx_plugins_sdk::flag(name),
+                };
+                let v = v.unwrap_or_default();
+                return self.match_str(&v, &r.matcher, re);
             }
-            Field::Ip => req.ip,
         };
-        self.match_str(hay, &r.matcher)
+        self.match_str(hay, &r.matcher, re)
     }
 
-    fn match_str(&self, hay: &str, m: &Matcher) -> bool {
+    fn match_str(&self, hay: &str, m: &Matcher, re: Option<&Regex>) -> bool {
         match m {
             Matcher::Contains(needle) => contains_ci(hay, needle),
             Matcher::Prefix(p) => hay.len() >= p.len() && eq_ci(&hay[..p.len()], p),
             Matcher::Suffix(s) => hay.len() >= s.len() && eq_ci(&hay[hay.len()-s.len()..], s),
             Matcher::Eq(x) => eq_ci(hay, x),
-            Matcher::Regex(pseudo) => contains_ci(hay, pseudo), // pseudo-regex: controlled subset
+            Matcher::Regex(_) => re.is_some_and(|re| re.is_match(hay.as_bytes())),
         }
     }
 
-    fn match_bytes(&self, hay: &[u8], m: &Matcher) -> bool {
+    fn match_bytes(&self, hay: &[u8], m: &Matcher, re: Option<&Regex>) -> bool {
         match m {
-            Matcher::Contains(needle) | Matcher::Regex(needle) | Matcher::Eq(needle) => {
+            Matcher::Regex(_) => re.is_some_and(|re| re.is_match(hay)),
+            Matcher::Contains(needle) | Matcher::Eq(needle) => {
                 let nd = needle.as_bytes();
                 find_subslice_ci(hay, nd)
             }
@@ -187,14 +257,53 @@ impl Engine {
     fn describe_match(req: &RequestView, r: &Rule) -> String {
         match r.field {
             Field::Path => format!("path matched {}", short(&r.matcher)),
+            Field::Query => format!("query matched {}", short(&r.matcher)),
             Field::UserAgent => format!("ua matched {}", short(&r.matcher)),
             Field::Header(ref h) => format!("header {} matched {}", h, short(&r.matcher)),
             Field::Body => "body matched".to_string(),
             Field::Ip => format!("ip matched {}", short(&r.matcher)),
+            Field::ClientCertCn => format!("client cert cn matched {}", short(&r.matcher)),
+            Field::Flag(ref f) => format!("flag {} matched {}", f, short(&r.matcher)),
         }
     }
 }
 
+// Hot-reloadable engine: `reload` swaps in a new rule set atomically. Each
+// decision runs against the snapshot it started with, so in-flight requests
+// finish on the old rules while new ones see the new revision.
+pub struct SharedEngine {
+    current: RwLock<Arc<Engine>>,
+    next_version: AtomicU64,
+}
+
+impl SharedEngine {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self { current: RwLock::new(Arc::new(Engine::new(rules).with_version(1))), next_version: AtomicU64::new(2) }
+    }
+
+    // Installs `rules` and returns their version; the flag store carries over.
+    pub fn reload(&self, rules: Vec<Rule>) -> u64 {
+        let mut cur = self.current.write().unwrap_or_else(|e| e.into_inner());
+        let version = self.next_version.fetch_add(1, Ordering::Relaxed);
+        let mut next = Engine::new(rules).with_version(version);
+        next.flags = cur.flags.clone();
+        *cur = Arc::new(next);
+        version
+    }
+
+    pub fn load(&self) -> Arc<Engine> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.load().version
+    }
+
+    pub fn decide(&self, req: &RequestView) -> Decision {
+        self.load().decide(req)
+    }
+}
+
 // Helpers (case-insensitive, ASCII-focused for speed)
 fn eq_ci(a: &str, b: &str) -> bool { a.eq_ignore_ascii_case(b) }
 fn contains_ci(hay: &str, needle: &str) -> bool {
@@ -220,7 +329,7 @@ fn short(m: &Matcher) -> String {
         Matcher::Contains(s) => format!("contains({})", s),
         Matcher::Prefix(s) => format!("prefix({})", s),
         Matcher::Suffix(s) => format!("suffix({})", s),
-        Matcher::Regex(s) => format!("regex-lite({})", s),
+        Matcher::Regex(s) => format!("regex({})", s),
         Matcher::Eq(s) => format!("eq({})", s),
     }
 }
@@ -236,7 +345,7 @@ pub fn default_rules() -> Vec<Rule> {
             field: Field::Path,
             matcher: Matcher::Contains("../".to_string()),
             action: Action::Deny(403),
-            tags: &["traversal"],
+            tags: vec!["traversal".into()],
             severity: 8,
         },
         Rule {
@@ -244,7 +353,7 @@ pub fn default_rules() -> Vec<Rule> {
             field: Field::UserAgent,
             matcher: Matcher::Contains("sqlmap".to_string()),
             action: Action::Deny(403),
-            tags: &["sql_injection_bot"],
+            tags: vec!["sql_injection_bot".into()],
             severity: 7,
         },
         Rule {
@@ -252,7 +361,7 @@ pub fn default_rules() -> Vec<Rule> {
             field: Field::Header("X-Forwarded-For".to_string()),
             matcher: Matcher::Regex("bad-proxy".to_string()),
             action: Action::Challenge(429),
-            tags: &["proxy_abuse"],
+            tags: vec!["proxy_abuse".into()],
             severity: 5,
         },
         Rule {
@@ -260,7 +369,7 @@ pub fn default_rules() -> Vec<Rule> {
             field: Field::Body,
             matcher: Matcher::Contains("UNION SELECT".to_string()),
             action: Action::Deny(403),
-            tags: &["sql_injection"],
+            tags: vec!["sql_injection".into()],
             severity: 9,
         },
         Rule {
@@ -268,7 +377,7 @@ pub fn default_rules() -> Vec<Rule> {
             field: Field::Path,
             matcher: Matcher::Prefix("/.well-known/".to_string()),
             action: Action::Allow,
-            tags: &["safe_allowlist"],
+            tags: vec!["safe_allowlist".into()],
             severity: 1,
         },
     ]
@@ -287,6 +396,7 @@ mod tests {
             headers: &[("X-Forwarded-For", "bad-proxy")],
             body: b"GET /?q=UNION SELECT id FROM users",
             ip: "203.0.113.10",
+            client_cert_cn: "",
         };
         let d = eng.decide(&req);
         match d.action {
@@ -294,4 +404,107 @@ mod tests {
             _ => panic!("expected deny"),
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_client_cert_cn() {
+        let rules = vec![Rule {
+            id: 10,
+            field: Field::ClientCertCn,
+            matcher: Matcher::Eq("revoked.internal".to_string()),
+            action: Action::Deny(403),
+            tags: vec!["mtls".into()],
+            severity: 6,
+        }];
+        let eng = Engine::new(rules);
+        let mut req = RequestView {
+            path: "/admin",
+            user_agent: "curl/7.79.1",
+            headers: &[],
+            body: b"",
+            ip: "10.0.0.5",
+            client_cert_cn: "revoked.internal",
+        };
+        assert!(matches!(eng.decide(&req).action, Action::Deny(403)));
+        req.client_cert_cn = "";
+        assert!(matches!(eng.decide(&req).action, Action::Allow));
+    }
+
+    #[test]
+    fn test_throttle_priority() {
+        let rule = |id, value: &str, action| Rule { id, field: Field::Path, matcher: Matcher::Prefix(value.into()), action, tags: vec![], severity: 5 };
+        let eng = Engine::new(vec![
+            rule(1, "/dl/", Action::LogOnly),
+            rule(2, "/dl/", Action::Throttle(65536)),
+            rule(3, "/dl/big", Action::Throttle(8192)),
+            rule(4, "/dl/bot", Action::Challenge(429)),
+        ]);
+        let req = |path| RequestView { path, user_agent: "x", headers: &[], body: b"", ip: "10.0.0.5", client_cert_cn: "" };
+        // throttle outranks log-only, the first throttle is kept, challenge outranks both
+        assert_eq!(eng.decide(&req("/dl/a")).action, Action::Throttle(65536));
+        assert_eq!(eng.decide(&req("/dl/big")).action, Action::Throttle(65536));
+        assert_eq!(eng.decide(&req("/dl/bot")).action, Action::Challenge(429));
+    }
+
+    #[test]
+    fn test_ip_canonical() {
+        let eng = Engine::new(vec![Rule {
+            id: 11,
+            field: Field::Ip,
+            matcher: Matcher::Eq("203.0.113.10".to_string()),
+            action: Action::Deny(403),
+            tags: vec!["denylist".into()],
+            severity: 5,
+        }]);
+        let req = RequestView {
+            path: "/",
+            user_agent: "curl/7.79.1",
+            headers: &[],
+            body: b"",
+            ip: "::ffff:203.0.113.10",
+            client_cert_cn: "",
+        };
+        assert!(matches!(eng.decide(&req).action, Action::Deny(403)));
+    }
+
+    #[test]
+    fn test_shared_engine_reload() {
+        let rule = |id: u32, action: Action| Rule {
+            id,
+            field: Field::Path,
+            matcher: Matcher::Prefix("/admin".to_string()),
+            action,
+            tags: vec![],
+            severity: 5,
+        };
+        let shared = SharedEngine::new(vec![rule(1, Action::LogOnly)]);
+        let req = RequestView { path: "/admin/users", user_agent: "", headers: &[], body: b"", ip: "10.0.0.1", client_cert_cn: "" };
+        let in_flight = shared.load();
+
+        assert_eq!(shared.reload(vec![rule(2, Action::Deny(403))]), 2);
+        let d = shared.decide(&req);
+        assert_eq!((d.applied_rule_id, d.rule_version), (Some(2), 2));
+        // a decision that started before the reload still sees revision 1
+        let d = in_flight.decide(&req);
+        assert_eq!((d.applied_rule_id, d.rule_version), (Some(1), 1));
+    }
+
+    #[test]
+    fn test_flag_field() {
+        let store = Arc::new(FlagStore::in_memory());
+        let eng = Engine::new(vec![Rule {
+            id: 12,
+            field: Field::Flag("waf.maintenance".to_string()),
+            matcher: Matcher::Eq("on".to_string()),
+            action: Action::Deny(503),
+            tags: vec!["maintenance".into()],
+            severity: 2,
+        }])
+        .with_flags(Arc::clone(&store));
+        let req = RequestView { path: "/", user_agent: "", headers: &[], body: b"", ip: "10.0.0.1", client_cert_cn: "" };
+        assert!(matches!(eng.decide(&req).action, Action::Allow));
+        store.set("waf.maintenance", "on").unwrap();
+        assert!(matches!(eng.decide(&req).action, Action::Deny(503)));
+        store.remove("waf.maintenance").unwrap();
+        assert!(matches!(eng.decide(&req).action, Action::Allow));
+    }
+}
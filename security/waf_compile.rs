@@ -0,0 +1,314 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/waf_compile.rs
+// Role: Ruleset compilation report (conflicts, shadowing, automaton sizes)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Hard errors (load fails): conflicting duplicate ids, conditions that can
+//   never match (including regexes that do not compile), out-of-range
+//   severities and statuses.
+// - Warnings (load proceeds): shadowed rules, rules that match every request.
+// - Per-field pattern automaton sizes (trie states over lowercased needles).
+// =============================================================================
+
+use crate::addr::ClientAddr;
+use crate::regex::Regex;
+use crate::waf::{Action, Engine, Field, Matcher, Rule};
+use std::collections::HashMap;
+use std::fmt;
+
+mod olwsx_plugins_flags {
+    pub use crate::flags::validate;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FindingKind {
+    // same id reused with a different action/tags/severity; identical
+    // definitions are accepted (the DSL emits one rule per `or` branch)
+    DuplicateId { first_index: usize },
+    Impossible,
+    InvalidSeverity,
+    InvalidStatus,
+    // an earlier rule matches a superset of requests and always wins
+    Shadowed { by_index: usize, by_id: u32 },
+    MatchesAll,
+}
+
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub level: Level,
+    pub rule_index: usize,
+    pub rule_id: u32,
+    pub kind: FindingKind,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldStats {
+    pub field: String,   // "path", "header:x-api-key", ...
+    pub rules: usize,
+    pub patterns: usize, // distinct lowercased needles
+    pub states: usize,   // trie states incl. root
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CompileReport {
+    pub rules: usize,
+    pub findings: Vec<Finding>,
+    pub fields: Vec<FieldStats>,
+}
+
+impl CompileReport {
+    pub fn errors(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(|f| f.level == Level::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(|f| f.level == Level::Warning)
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.errors().next().is_none()
+    }
+}
+
+impl fmt::Display for CompileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} rules, {} errors, {} warnings", self.rules, self.errors().count(), self.warnings().count())?;
+        for x in self.findings.iter() {
+            let lvl = if x.level == Level::Error { "error" } else { "warning" };
+            writeln!(f, "{}: rule #{} (id {}): {}", lvl, x.rule_index, x.rule_id, x.message)?;
+        }
+        for s in self.fields.iter() {
+            writeln!(f, "field {}: {} rules, {} patterns, {} states", s.field, s.rules, s.patterns, s.states)?;
+        }
+        Ok(())
+    }
+}
+
+// Checks the ruleset and builds the engine; any hard error fails the load.
+pub fn compile(rules: Vec<Rule>) -> Result<(Engine, CompileReport), CompileReport> {
+    let report = check(&rules);
+    if !report.is_ok() {
+        return Err(report);
+    }
+    Ok((Engine::new(rules), report))
+}
+
+pub fn check(rules: &[Rule]) -> CompileReport {
+    let mut report = CompileReport { rules: rules.len(), ..Default::default() };
+    let mut push = |level, i: usize, kind, message: String| {
+        report.findings.push(Finding { level, rule_index: i, rule_id: rules[i].id, kind, message });
+    };
+
+    let mut first_by_id: HashMap<u32, usize> = HashMap::new();
+    for (i, r) in rules.iter().enumerate() {
+        match first_by_id.get(&r.id) {
+            Some(&f) if !same_definition(&rules[f], r) => {
+                push(Level::Error, i, FindingKind::DuplicateId { first_index: f }, format!("duplicate id {} conflicts with rule #{}", r.id, f));
+            }
+            Some(_) => {}
+            None => {
+                first_by_id.insert(r.id, i);
+            }
+        }
+        if !(1..=10).contains(&r.severity) {
+            push(Level::Error, i, FindingKind::InvalidSeverity, format!("severity {} outside 1..10", r.severity));
+        }
+        match r.action {
+            Action::Deny(s) | Action::Challenge(s) if !(400..=599).contains(&s) => {
+                push(Level::Error, i, FindingKind::InvalidStatus, format!("status {} is not a 4xx/5xx", s));
+            }
+            _ => {}
+        }
+        if let Some(why) = impossible(r) {
+            push(Level::Error, i, FindingKind::Impossible, why);
+        } else if matches_all(r) {
+            push(Level::Warning, i, FindingKind::MatchesAll, "condition matches every request".to_string());
+        }
+    }
+
+    for (j, b) in rules.iter().enumerate() {
+        if let Some(i) = (0..j).find(|&i| wins_over(&rules[i].action, &b.action) && covers(&rules[i], b)) {
+            let a = &rules[i];
+            push(Level::Warning, j, FindingKind::Shadowed { by_index: i, by_id: a.id }, format!("unreachable: rule #{} (id {}) always matches first", i, a.id));
+        }
+    }
+
+    report.fields = field_stats(rules);
+    report
+}
+
+fn same_definition(a: &Rule, b: &Rule) -> bool {
+    a.action == b.action && a.tags == b.tags && a.severity == b.severity
+}
+
+// Mirrors Engine::decide: deny and allow short-circuit, a matched challenge
+// or throttle suppresses later log-only and throttle rules, and only the first
+// log-only rule is kept.
+fn wins_over(earlier: &Action, later: &Action) -> bool {
+    match earlier {
+        Action::Deny(_) | Action::Allow => true,
+        Action::Challenge(_) | Action::Throttle(_) => matches!(later, Action::LogOnly | Action::Throttle(_)),
+        Action::LogOnly => matches!(later, Action::LogOnly),
+    }
+}
+
+fn field_key(f: &Field) -> String {
+    match f {
+        Field::Path => "path".to_string(),
+        Field::Query => "query".to_string(),
+        Field::UserAgent => "ua".to_string(),
+        Field::Header(h) => format!("header:{}", h.to_ascii_lowercase()),
+        Field::Body => "body".to_string(),
+        Field::Ip => "ip".to_string(),
+        Field::ClientCertCn => "cn".to_string(),
+        Field::Flag(n) => format!("flag:{}", n),
+    }
+}
+
+// Effective matcher: body Eq is a substring test. Regexes are only compared
+// by source.
+fn normalized(r: &Rule) -> (u8, String) {
+    match (&r.field, &r.matcher) {
+        (_, Matcher::Regex(s)) => (4, s.clone()),
+        (Field::Body, Matcher::Eq(s)) | (_, Matcher::Contains(s)) => (0, s.to_ascii_lowercase()),
+        (_, Matcher::Prefix(s)) => (1, s.to_ascii_lowercase()),
+        (_, Matcher::Suffix(s)) => (2, s.to_ascii_lowercase()),
+        (_, Matcher::Eq(s)) => (3, s.to_ascii_lowercase()),
+    }
+}
+
+// True when every request matching `b` also matches `a`.
+fn covers(a: &Rule, b: &Rule) -> bool {
+    if field_key(&a.field) != field_key(&b.field) {
+        return false;
+    }
+    let ((ka, na), (kb, nb)) = (normalized(a), normalized(b));
+    match (ka, kb) {
+        (0, _) => nb.contains(&na),
+        (1, 1) | (1, 3) => nb.starts_with(&na),
+        (2, 2) | (2, 3) => nb.ends_with(&na),
+        (3, 3) | (4, 4) => na == nb,
+        _ => false,
+    }
+}
+
+fn needle(m: &Matcher) -> &str {
+    match m {
+        Matcher::Contains(s) | Matcher::Prefix(s) | Matcher::Suffix(s) | Matcher::Regex(s) | Matcher::Eq(s) => s,
+    }
+}
+
+fn impossible(r: &Rule) -> Option<String> {
+    let n = needle(&r.matcher);
+    let exact = matches!(r.matcher, Matcher::Eq(_) | Matcher::Prefix(_));
+    match &r.field {
+        Field::Header(h) if h.is_empty() => return Some("empty header name".to_string()),
+        Field::Flag(f) if olwsx_plugins_flags::validate(f, "").is_err() => return Some(format!("'{}' is not a valid flag name", f)),
+        _ if matches!(r.matcher, Matcher::Regex(_)) => return Regex::new(n).err().map(|e| e.to_string()),
+        Field::Body => return None,
+        _ => {}
+    }
+    if n.contains(['\r', '\n', '\0']) {
+        return Some("needle contains CR, LF or NUL, which never reach this field".to_string());
+    }
+    match &r.field {
+        Field::Path if exact && !n.is_empty() && !n.starts_with('/') && n != "*" => Some(format!("path never starts with '{}'", n)),
+        Field::Query if n.contains('?') && matches!(r.matcher, Matcher::Eq(_) | Matcher::Prefix(_)) => Some("query never starts with '?'".to_string()),
+        Field::Ip if matches!(r.matcher, Matcher::Eq(_)) => match ClientAddr::parse(n) {
+            Some(a) if a.to_string().eq_ignore_ascii_case(n) => None,
+            Some(a) => Some(format!("ip is compared in canonical form; use '{}'", a)),
+            None => Some(format!("'{}' is not an IP address", n)),
+        },
+        _ => None,
+    }
+}
+
+fn matches_all(r: &Rule) -> bool {
+    // Eq("") only matches an empty field, everything else with an empty needle matches always
+    needle(&r.matcher).is_empty() && (matches!(r.field, Field::Body) || !matches!(r.matcher, Matcher::Eq(_)))
+}
+
+fn field_stats(rules: &[Rule]) -> Vec<FieldStats> {
+    let mut by_field: Vec<(String, usize, Vec<String>)> = Vec::new();
+    for r in rules.iter() {
+        let key = field_key(&r.field);
+        let pos = match by_field.iter().position(|(k, _, _)| *k == key) {
+            Some(p) => p,
+            None => {
+                by_field.push((key, 0, Vec::new()));
+                by_field.len() - 1
+            }
+        };
+        by_field[pos].1 += 1;
+        // regexes run on their own, outside the literal automaton
+        if !matches!(r.matcher, Matcher::Regex(_)) {
+            by_field[pos].2.push(needle(&r.matcher).to_ascii_lowercase());
+        }
+    }
+    by_field
+        .into_iter()
+        .map(|(field, rules, mut needles)| {
+            needles.sort();
+            needles.dedup();
+            let mut trie: Vec<HashMap<u8, usize>> = vec![HashMap::new()];
+            for n in needles.iter() {
+                let mut s = 0;
+                for &c in n.as_bytes() {
+                    s = match trie[s].get(&c) {
+                        Some(&next) => next,
+                        None => {
+                            trie.push(HashMap::new());
+                            let next = trie.len() - 1;
+                            trie[s].insert(c, next);
+                            next
+                        }
+                    };
+                }
+            }
+            FieldStats { field, rules, patterns: needles.len(), states: trie.len() }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: u32, field: Field, matcher: Matcher, action: Action) -> Rule {
+        Rule { id, field, matcher, action, tags: vec![], severity: 5 }
+    }
+
+    #[test]
+    fn test_report_findings() {
+        let rules = vec![
+            rule(1, Field::Path, Matcher::Contains("../".into()), Action::Deny(403)),
+            rule(2, Field::Path, Matcher::Prefix("/static/../".into()), Action::Deny(403)),
+            rule(3, Field::UserAgent, Matcher::Contains("sqlmap".into()), Action::LogOnly),
+            rule(3, Field::UserAgent, Matcher::Contains("nikto".into()), Action::LogOnly),
+            rule(4, Field::Header("X-Debug".into()), Matcher::Eq("".into()), Action::Challenge(429)),
+            rule(5, Field::UserAgent, Matcher::Contains("SQLMAP/1.5".into()), Action::LogOnly),
+        ];
+        let (_, report) = compile(rules.clone()).unwrap();
+        let kinds: Vec<(usize, &FindingKind)> = report.findings.iter().map(|f| (f.rule_index, &f.kind)).collect();
+        assert_eq!(kinds, vec![(1, &FindingKind::Shadowed { by_index: 0, by_id: 1 }), (5, &FindingKind::Shadowed { by_index: 2, by_id: 3 })]);
+        let ua = report.fields.iter().find(|s| s.field == "ua").unwrap();
+        assert_eq!((ua.rules, ua.patterns, ua.states), (3, 3, 1 + 10 + 5));
+
+        let mut bad = rules;
+        bad.push(rule(3, Field::Path, Matcher::Eq("admin".into()), Action::Deny(403)));
+        bad.push(rule(6, Field::Ip, Matcher::Eq("::ffff:10.0.0.1".into()), Action::Deny(200)));
+        let err = compile(bad).err().unwrap();
+        let errs: Vec<&FindingKind> = err.errors().map(|f| &f.kind).collect();
+        assert_eq!(errs, vec![&FindingKind::DuplicateId { first_index: 2 }, &FindingKind::Impossible, &FindingKind::InvalidStatus, &FindingKind::Impossible]);
+        assert!(err.to_string().contains("use '10.0.0.1'"));
+    }
+}
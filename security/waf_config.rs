@@ -0,0 +1,351 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/waf_config.rs
+// Role: WAF rules from external config (JSON or TOML), line-accurate errors
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - One documented schema in two syntaxes; each rule is a table/object:
+//     id        integer, unique                         (required)
+//     field     path | query | ua | header | body | ip | cn   (required)
+//     header    header name, only with field = "header"
+//     match     contains | prefix | suffix | eq | regex (required)
+//     value     string                                  (required)
+//     action    deny | challenge | log | allow | throttle   (required)
+//     status    400..599; default 403 (deny) / 429 (challenge)
+//     rate      bytes/s, >= 1; required with (and only with) throttle
+//     tags      array of strings; default []
+//     severity  1..10; default 5
+// - JSON:  {"rules": [ {"id": 1, "field": "path", ...}, ... ]}
+// - TOML:  [[rule]] tables with `key = value` lines (strings, integers,
+//          single-line string arrays, `#` comments); right-hand sides are
+//          JSON-compatible in this schema and read with common/json.rs.
+// - Unknown keys are rejected so typos do not silently disable a condition.
+// - Every invalid rule is reported (ErrorReport), not just the first one;
+//   a syntax error stops parsing and is reported alone.
+// =============================================================================
+
+use crate::regex::Regex;
+use crate::waf::{Action, Engine, Field, Matcher, Rule};
+use crate::waf_dsl::{DslError, DEFAULT_SEVERITY};
+use olwsx_json::{JsonError, Reader, Value};
+use olwsx_report::{ErrorReport, Issue};
+use std::collections::HashSet;
+
+mod olwsx_json {
+    pub use crate::json::{JsonError, Reader, Value};
+}
+
+mod olwsx_report {
+    pub use crate::report::{ErrorReport, Issue};
+}
+
+const SOURCE: &str = "waf_rules";
+
+const KEYS: [&str; 10] = ["id", "field", "header", "match", "value", "action", "status", "rate", "tags", "severity"];
+
+// Parsed rule table: (line of the table, [(key, line, value)]).
+type Table = (usize, Vec<(String, usize, Value)>);
+
+#[derive(Clone, Debug, Default)]
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn from_json(src: &str) -> Result<Self, ErrorReport> {
+        build(json_tables(src).map_err(report)?)
+    }
+
+    pub fn from_toml(src: &str) -> Result<Self, ErrorReport> {
+        build(toml_tables(src).map_err(report)?)
+    }
+
+    pub fn into_engine(self) -> Engine {
+        Engine::new(self.rules)
+    }
+}
+
+impl From<JsonError> for DslError {
+    fn from(e: JsonError) -> Self {
+        DslError { line: e.line, msg: e.msg }
+    }
+}
+
+fn report(e: DslError) -> ErrorReport {
+    ErrorReport { issues: vec![Issue::error(SOURCE, e.msg).at_line(e.line)] }
+}
+
+fn json_tables(src: &str) -> Result<Vec<Table>, DslError> {
+    let mut p = Reader::new(src, 1);
+    let root_line = p.line();
+    let root = p.value()?;
+    if !p.at_end() {
+        return Err(p.err("trailing characters after document").into());
+    }
+    let Value::Obj(fields) = root else { return Err(DslError { line: root_line, msg: "expected an object with a \"rules\" array".to_string() }) };
+    let mut tables = Vec::new();
+    for (k, line, v) in fields {
+        match (k.as_str(), v) {
+            ("rules", Value::Arr(items)) => {
+                for (line, item) in items {
+                    match item {
+                        Value::Obj(kv) => tables.push((line, kv)),
+                        _ => return Err(DslError { line, msg: "each rule must be an object".to_string() }),
+                    }
+                }
+            }
+            ("rules", _) => return Err(DslError { line, msg: "\"rules\" must be an array".to_string() }),
+            (other, _) => return Err(DslError { line, msg: format!("unknown top-level key \"{}\"", other) }),
+        }
+    }
+    Ok(tables)
+}
+
+fn toml_tables(src: &str) -> Result<Vec<Table>, DslError> {
+    let mut tables: Vec<Table> = Vec::new();
+    for (n, raw) in src.lines().enumerate() {
+        let line = n + 1;
+        let err = |msg: String| DslError { line, msg };
+        let text = strip_comment(raw).trim();
+        if text.is_empty() {
+            continue;
+        }
+        if text.starts_with('[') {
+            if text != "[[rule]]" {
+                return Err(err(format!("unsupported table {}; expected [[rule]]", text)));
+            }
+            tables.push((line, Vec::new()));
+            continue;
+        }
+        let Some((k, v)) = text.split_once('=') else { return Err(err("expected key = value".to_string())) };
+        let Some(t) = tables.last_mut() else { return Err(err("key outside of a [[rule]] table".to_string())) };
+        let k = k.trim().trim_matches('"').to_string();
+        let mut p = Reader::new(v.trim(), line);
+        let val = p.value()?;
+        if !p.at_end() {
+            return Err(err("unexpected characters after value".to_string()));
+        }
+        t.1.push((k, line, val));
+    }
+    Ok(tables)
+}
+
+fn build(tables: Vec<Table>) -> Result<RuleSet, ErrorReport> {
+    let mut rules = Vec::with_capacity(tables.len());
+    let mut errors = ErrorReport::new();
+    let mut ids = HashSet::new();
+    for (line, kv) in tables {
+        let rule = match rule(line, &kv) {
+            Ok(r) => r,
+            Err(e) => {
+                errors.push(Issue::error(SOURCE, e.msg).at_line(e.line));
+                continue;
+            }
+        };
+        if !ids.insert(rule.id) {
+            let id_line = kv.iter().find(|(k, _, _)| k == "id").map(|(_, l, _)| *l).unwrap_or(line);
+            errors.push(Issue::error(SOURCE, format!("duplicate rule id {}", rule.id)).at_line(id_line).key("id"));
+            continue;
+        }
+        rules.push(rule);
+    }
+    errors.into_result(RuleSet { rules })
+}
+
+fn rule(line: usize, kv: &[(String, usize, Value)]) -> Result<Rule, DslError> {
+    let mut seen = HashSet::new();
+    for (k, l, _) in kv.iter() {
+        if !KEYS.contains(&k.as_str()) {
+            return Err(DslError { line: *l, msg: format!("unknown key \"{}\"", k) });
+        }
+        if !seen.insert(k.as_str()) {
+            return Err(DslError { line: *l, msg: format!("duplicate key \"{}\"", k) });
+        }
+    }
+    let get = |k: &str| kv.iter().find(|(key, _, _)| key == k).map(|(_, l, v)| (*l, v));
+    let missing = |k: &str| DslError { line, msg: format!("missing required key \"{}\"", k) };
+    let str_of = |k: &str| -> Result<Option<(usize, &str)>, DslError> {
+        match get(k) {
+            None => Ok(None),
+            Some((l, Value::Str(s))) => Ok(Some((l, s.as_str()))),
+            Some((l, _)) => Err(DslError { line: l, msg: format!("\"{}\" must be a string", k) }),
+        }
+    };
+    let int_of = |k: &str, lo: u64, hi: u64| -> Result<Option<u64>, DslError> {
+        match get(k) {
+            None => Ok(None),
+            Some((_, Value::Num(n))) if n.fract() == 0.0 && *n >= lo as f64 && *n <= hi as f64 => Ok(Some(*n as u64)),
+            Some((l, _)) => Err(DslError { line: l, msg: format!("\"{}\" must be an integer in {}..{}", k, lo, hi) }),
+        }
+    };
+
+    let id = int_of("id", 0, u32::MAX as u64)?.ok_or_else(|| missing("id"))? as u32;
+    let (fl, field) = str_of("field")?.ok_or_else(|| missing("field"))?;
+    let header = str_of("header")?;
+    let field = match (field, header) {
+        ("header", Some((_, h))) if !h.is_empty() => Field::Header(h.to_string()),
+        ("header", _) => return Err(DslError { line: fl, msg: "field \"header\" needs a non-empty \"header\" key".to_string() }),
+        (_, Some((hl, _))) => return Err(DslError { line: hl, msg: "\"header\" is only valid with field = \"header\"".to_string() }),
+        ("path", None) => Field::Path,
+        ("query", None) => Field::Query,
+        ("ua", None) => Field::UserAgent,
+        ("body", None) => Field::Body,
+        ("ip", None) => Field::Ip,
+        ("cn", None) => Field::ClientCertCn,
+        (other, None) => return Err(DslError { line: fl, msg: format!("unknown field \"{}\"", other) }),
+    };
+    let (ml, m) = str_of("match")?.ok_or_else(|| missing("match"))?;
+    let (_, value) = str_of("value")?.ok_or_else(|| missing("value"))?;
+    let value = value.to_string();
+    let matcher = match m {
+        "contains" => Matcher::Contains(value),
+        "prefix" => Matcher::Prefix(value),
+        "suffix" => Matcher::Suffix(value),
+        "eq" => Matcher::Eq(value),
+        "regex" => match Regex::new(&value) {
+            Ok(_) => Matcher::Regex(value),
+            Err(e) => return Err(DslError { line: ml, msg: e.to_string() }),
+        },
+        other => return Err(DslError { line: ml, msg: format!("unknown matcher \"{}\"", other) }),
+    };
+    let (al, a) = str_of("action")?.ok_or_else(|| missing("action"))?;
+    let status = int_of("status", 400, 599)?.map(|s| s as u16);
+    let rate = int_of("rate", 1, u32::MAX as u64)?.map(|r| r as u32);
+    if let (Some((rl, _)), false) = (get("rate"), a == "throttle") {
+        return Err(DslError { line: rl, msg: format!("\"rate\" is not valid with action \"{}\"", a) });
+    }
+    let action = match a {
+        "deny" => Action::Deny(status.unwrap_or(403)),
+        "challenge" => Action::Challenge(status.unwrap_or(429)),
+        "log" | "allow" | "throttle" if status.is_some() => {
+            let sl = get("status").map(|(l, _)| l).unwrap_or(line);
+            return Err(DslError { line: sl, msg: format!("\"status\" is not valid with action \"{}\"", a) });
+        }
+        "log" => Action::LogOnly,
+        "allow" => Action::Allow,
+        "throttle" => Action::Throttle(rate.ok_or_else(|| missing("rate"))?),
+        other => return Err(DslError { line: al, msg: format!("unknown action \"{}\"", other) }),
+    };
+    let tags = match get("tags") {
+        None => Vec::new(),
+        Some((l, Value::Arr(items))) => {
+            let mut tags = Vec::with_capacity(items.len());
+            for (_, t) in items.iter() {
+                match t {
+                    Value::Str(s) => tags.push(s.clone()),
+                    _ => return Err(DslError { line: l, msg: "\"tags\" must be an array of strings".to_string() }),
+                }
+            }
+            tags
+        }
+        Some((l, _)) => return Err(DslError { line: l, msg: "\"tags\" must be an array of strings".to_string() }),
+    };
+    let severity = int_of("severity", 1, 10)?.map(|s| s as u8).unwrap_or(DEFAULT_SEVERITY);
+    Ok(Rule { id, field, matcher, action, tags, severity })
+}
+
+// `#` outside of a string starts a comment.
+fn strip_comment(line: &str) -> &str {
+    let mut in_str = false;
+    let mut esc = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if esc => esc = false,
+            '\\' if in_str => esc = true,
+            '"' => in_str = !in_str,
+            '#' if !in_str => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::waf::RequestView;
+
+    #[test]
+    fn test_json_and_toml_agree() {
+        let json = r#"{
+  "rules": [
+    {"id": 101, "field": "path", "match": "contains", "value": "../",
+     "action": "deny", "tags": ["traversal"], "severity": 8},
+    {"id": 102, "field": "header", "header": "X-Api-Key", "match": "eq", "value": "say \"hi\"",
+     "action": "log"}
+  ]
+}"#;
+        let toml = r#"
+# traversal
+[[rule]]
+id = 101
+field = "path"
+match = "contains"
+value = "../"   # literal, not a pattern
+action = "deny"
+tags = ["traversal"]
+severity = 8
+
+[[rule]]
+id = 102
+field = "header"
+header = "X-Api-Key"
+match = "eq"
+value = "say \"hi\""
+action = "log"
+"#;
+        let a = RuleSet::from_json(json).unwrap();
+        let b = RuleSet::from_toml(toml).unwrap();
+        assert_eq!(crate::waf_dsl::to_text(&a.rules), crate::waf_dsl::to_text(&b.rules));
+        assert!(matches!(a.rules[0].action, Action::Deny(403)));
+        assert_eq!(a.rules[1].severity, DEFAULT_SEVERITY);
+
+        let eng = b.into_engine();
+        let req = RequestView { path: "/a/../b", user_agent: "x", headers: &[], body: b"", ip: "10.0.0.1", client_cert_cn: "" };
+        assert_eq!(eng.decide(&req).applied_rule_id, Some(101));
+    }
+
+    #[test]
+    fn test_errors_collected_with_lines() {
+        let e = RuleSet::from_json("{\"rules\": [\n {\"id\": 1, \"field\": \"path\", \"match\": \"eq\",\n  \"value\": \"/\", \"action\": \"deny\", \"severity\": 11}]}").unwrap_err();
+        assert_eq!(e.issues.len(), 1);
+        assert_eq!(e.issues[0].line, Some(3));
+        assert!(e.issues[0].message.contains("severity"));
+
+        let toml = "[[rule]]\nid = 1\nfield = \"path\"\nmatch = \"eq\"\nvalue = \"/\"\naction = \"block\"\n\
+                    [[rule]]\nid = 2\nfeild = \"path\"\n\
+                    [[rule]]\nid = 3\nfield = \"path\"\n\
+                    [[rule]]\nid = 4\nfield = \"ua\"\nmatch = \"eq\"\nvalue = \"x\"\naction = \"log\"\n\
+                    [[rule]]\nid = 4\nfield = \"ua\"\nmatch = \"eq\"\nvalue = \"y\"\naction = \"log\"";
+        let e = RuleSet::from_toml(toml).unwrap_err();
+        let got: Vec<(Option<usize>, &str)> = e.issues.iter().map(|i| (i.line, i.message.as_str())).collect();
+        assert_eq!(
+            got,
+            vec![
+                (Some(6), "unknown action \"block\""),
+                (Some(9), "unknown key \"feild\""),
+                (Some(10), "missing required key \"match\""),
+                (Some(20), "duplicate rule id 4"),
+            ]
+        );
+        assert!(e.to_string().starts_with("error: waf_rules:6: unknown action"));
+
+        // same status range as waf_compile::check: 4xx/5xx only
+        let e = RuleSet::from_toml("[[rule]]\nid = 1\nfield = \"path\"\nmatch = \"eq\"\nvalue = \"/\"\naction = \"deny\"\nstatus = 302").unwrap_err();
+        assert_eq!((e.issues.len(), e.issues[0].line), (1, Some(7)));
+        assert!(e.issues[0].message.contains("status"), "{}", e.issues[0].message);
+
+        let base = "[[rule]]\nid = 1\nfield = \"path\"\nmatch = \"prefix\"\nvalue = \"/dl/\"\n";
+        let set = RuleSet::from_toml(&format!("{}action = \"throttle\"\nrate = 65536", base)).unwrap();
+        assert_eq!(set.rules[0].action, Action::Throttle(65536));
+        let e = RuleSet::from_toml(&format!("{}action = \"throttle\"", base)).unwrap_err();
+        assert!(e.issues[0].message.contains("missing required key \"rate\""));
+        let e = RuleSet::from_toml(&format!("{}action = \"deny\"\nrate = 10", base)).unwrap_err();
+        assert_eq!(e.issues[0].line, Some(7));
+
+        // syntax errors stop parsing
+        let e = RuleSet::from_toml("[[rule]]\nid = \"x").unwrap_err();
+        assert_eq!((e.issues.len(), e.issues[0].line), (1, Some(2)));
+    }
+}
@@ -0,0 +1,380 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/waf_dsl.rs
+// Role: Textual rule DSL for the WAF (parse to fixed Rule schema, print back)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - One rule per line:
+//     rule 101 when path contains "../" or query any matches re"(?i)union\s+select"
+//          then deny 403 tags[traversal] sev 8
+//     rule 300 when flag "maintenance_mode" eq "on" then deny 503
+//     rule 400 when path prefix "/downloads/" then throttle 262144   (bytes/s)
+// - `or` branches become consecutive Rules sharing id/action/tags/severity;
+//   the schema has no conjunction, so there is no `and`.
+// - Round-trip: to_text(parse_rules(s)) is canonical and re-parses identically.
+// - `matches` patterns must compile (security/regex.rs syntax).
+// =============================================================================
+
+use crate::regex::Regex;
+use crate::waf::{Action, Field, Matcher, Rule};
+use std::collections::HashSet;
+use std::fmt;
+
+pub(crate) const DEFAULT_SEVERITY: u8 = 5;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DslError {
+    pub line: usize, // 1-based
+    pub msg: String,
+}
+
+impl fmt::Display for DslError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Word(String),
+    Str(String),
+    Re(String),
+    Open,
+    Close,
+    Comma,
+}
+
+// Parse a rule file; blank lines and `#` comments are ignored.
+pub fn parse_rules(src: &str) -> Result<Vec<Rule>, DslError> {
+    let mut out = Vec::new();
+    let mut ids = HashSet::new();
+    for (n, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |msg: String| DslError { line: n + 1, msg };
+        let toks = lex(line).map_err(err)?;
+        let rules = Parser { toks, pos: 0 }.rule().map_err(err)?;
+        if !ids.insert(rules[0].id) {
+            return Err(err(format!("duplicate rule id {}", rules[0].id)));
+        }
+        out.extend(rules);
+    }
+    Ok(out)
+}
+
+// Canonical text; consecutive rules with the same id are folded into `or`.
+pub fn to_text(rules: &[Rule]) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < rules.len() {
+        let head = &rules[i];
+        let mut j = i + 1;
+        while j < rules.len() && same_group(head, &rules[j]) {
+            j += 1;
+        }
+        let conds: Vec<String> = rules[i..j].iter().map(|r| format!("{} {}", field_text(&r.field), matcher_text(&r.matcher))).collect();
+        out.push_str(&format!("rule {} when {} then {}", head.id, conds.join(" or "), action_text(&head.action)));
+        if !head.tags.is_empty() {
+            out.push_str(&format!(" tags[{}]", head.tags.join(",")));
+        }
+        out.push_str(&format!(" sev {}\n", head.severity));
+        i = j;
+    }
+    out
+}
+
+fn same_group(a: &Rule, b: &Rule) -> bool {
+    a.id == b.id && action_text(&a.action) == action_text(&b.action) && a.tags == b.tags && a.severity == b.severity
+}
+
+fn field_text(f: &Field) -> String {
+    match f {
+        Field::Path => "path".to_string(),
+        Field::Query => "query".to_string(),
+        Field::UserAgent => "ua".to_string(),
+        Field::Header(h) => format!("header {}", quote(h)),
+        Field::Body => "body".to_string(),
+        Field::Ip => "ip".to_string(),
+        Field::ClientCertCn => "cn".to_string(),
+        Field::Flag(n) => format!("flag {}", quote(n)),
+    }
+}
+
+fn matcher_text(m: &Matcher) -> String {
+    match m {
+        Matcher::Contains(s) => format!("contains {}", quote(s)),
+        Matcher::Prefix(s) => format!("prefix {}", quote(s)),
+        Matcher::Suffix(s) => format!("suffix {}", quote(s)),
+        Matcher::Eq(s) => format!("eq {}", quote(s)),
+        Matcher::Regex(s) => format!("matches re\"{}\"", s.replace('"', "\\\"")),
+    }
+}
+
+fn action_text(a: &Action) -> String {
+    match a {
+        Action::Deny(code) => format!("deny {}", code),
+        Action::Challenge(code) => format!("challenge {}", code),
+        Action::LogOnly => "log".to_string(),
+        Action::Allow => "allow".to_string(),
+        Action::Throttle(rate) => format!("throttle {}", rate),
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn lex(line: &str) -> Result<Vec<Tok>, String> {
+    let cs: Vec<char> = line.chars().collect();
+    let mut toks = Vec::new();
+    let mut i = 0;
+    while i < cs.len() {
+        let c = cs[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '[' || c == ']' || c == ',' {
+            toks.push(match c {
+                '[' => Tok::Open,
+                ']' => Tok::Close,
+                _ => Tok::Comma,
+            });
+            i += 1;
+        } else if c == '"' || (c == 'r' && cs.get(i + 1) == Some(&'e') && cs.get(i + 2) == Some(&'"')) {
+            // regex literals keep backslashes verbatim; only \" is an escape
+            let raw = c == 'r';
+            i += if raw { 3 } else { 1 };
+            let mut s = String::new();
+            loop {
+                match cs.get(i) {
+                    None => return Err("unterminated string".to_string()),
+                    Some('"') => break,
+                    Some('\\') if cs.get(i + 1) == Some(&'"') => {
+                        s.push('"');
+                        i += 2;
+                    }
+                    Some('\\') if !raw && cs.get(i + 1) == Some(&'\\') => {
+                        s.push('\\');
+                        i += 2;
+                    }
+                    Some(ch) => {
+                        s.push(*ch);
+                        i += 1;
+                    }
+                }
+            }
+            i += 1;
+            toks.push(if raw { Tok::Re(s) } else { Tok::Str(s) });
+        } else if c.is_alphanumeric() || "_-.:".contains(c) {
+            let start = i;
+            while i < cs.len() && (cs[i].is_alphanumeric() || "_-.:".contains(cs[i])) {
+                i += 1;
+            }
+            toks.push(Tok::Word(cs[start..i].iter().collect()));
+        } else {
+            return Err(format!("unexpected character '{}'", c));
+        }
+    }
+    Ok(toks)
+}
+
+struct Parser {
+    toks: Vec<Tok>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Tok> {
+        let t = self.toks.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn peek_word(&self, w: &str) -> bool {
+        matches!(self.toks.get(self.pos), Some(Tok::Word(x)) if x == w)
+    }
+
+    fn word(&mut self, what: &str) -> Result<String, String> {
+        match self.next() {
+            Some(Tok::Word(w)) => Ok(w),
+            other => Err(format!("expected {}, found {}", what, describe(other))),
+        }
+    }
+
+    fn keyword(&mut self, kw: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Tok::Word(w)) if w == kw => Ok(()),
+            other => Err(format!("expected '{}', found {}", kw, describe(other))),
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self, what: &str) -> Result<T, String> {
+        let w = self.word(what)?;
+        w.parse().map_err(|_| format!("invalid {} '{}'", what, w))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Tok::Str(s)) => Ok(s),
+            other => Err(format!("expected quoted string, found {}", describe(other))),
+        }
+    }
+
+    fn rule(mut self) -> Result<Vec<Rule>, String> {
+        self.keyword("rule")?;
+        let id: u32 = self.number("rule id")?;
+        self.keyword("when")?;
+        let mut conds = vec![self.condition()?];
+        while self.peek_word("or") {
+            self.pos += 1;
+            conds.push(self.condition()?);
+        }
+        self.keyword("then")?;
+        let action = match self.word("action")?.as_str() {
+            "deny" => Action::Deny(self.number("status")?),
+            "challenge" => Action::Challenge(self.number("status")?),
+            "log" => Action::LogOnly,
+            "allow" => Action::Allow,
+            "throttle" => match self.number("rate")? {
+                0 => return Err("throttle rate must be positive".to_string()),
+                rate => Action::Throttle(rate),
+            },
+            other => return Err(format!("unknown action '{}'", other)),
+        };
+        let mut tags = Vec::new();
+        let mut severity = DEFAULT_SEVERITY;
+        while let Some(t) = self.next() {
+            match t {
+                Tok::Word(w) if w == "tags" => tags = self.tags()?,
+                Tok::Word(w) if w == "sev" => {
+                    severity = self.number("severity")?;
+                    if !(1..=10).contains(&severity) {
+                        return Err(format!("severity {} out of range 1..10", severity));
+                    }
+                }
+                other => return Err(format!("unexpected {}", describe(Some(other)))),
+            }
+        }
+        Ok(conds.into_iter().map(|(field, matcher)| Rule { id, field, matcher, action: action.clone(), tags: tags.clone(), severity }).collect())
+    }
+
+    fn condition(&mut self) -> Result<(Field, Matcher), String> {
+        let field = match self.word("field")?.as_str() {
+            "path" => Field::Path,
+            "query" => {
+                // `query any` reads better in rules; the whole query string is scanned either way
+                if self.peek_word("any") {
+                    self.pos += 1;
+                }
+                Field::Query
+            }
+            "ua" => Field::UserAgent,
+            "header" => Field::Header(self.string()?),
+            "body" => Field::Body,
+            "ip" => Field::Ip,
+            "cn" => Field::ClientCertCn,
+            "flag" => Field::Flag(self.string()?),
+            other => return Err(format!("unknown field '{}'", other)),
+        };
+        let matcher = match self.word("matcher")?.as_str() {
+            "contains" => Matcher::Contains(self.string()?),
+            "prefix" => Matcher::Prefix(self.string()?),
+            "suffix" => Matcher::Suffix(self.string()?),
+            "eq" => Matcher::Eq(self.string()?),
+            "matches" => match self.next() {
+                Some(Tok::Re(s)) | Some(Tok::Str(s)) => match Regex::new(&s) {
+                    Ok(_) => Matcher::Regex(s),
+                    Err(e) => return Err(e.to_string()),
+                },
+                other => return Err(format!("expected re\"...\", found {}", describe(other))),
+            },
+            other => return Err(format!("unknown matcher '{}'", other)),
+        };
+        Ok((field, matcher))
+    }
+
+    fn tags(&mut self) -> Result<Vec<String>, String> {
+        if self.next() != Some(Tok::Open) {
+            return Err("expected '[' after tags".to_string());
+        }
+        let mut tags = Vec::new();
+        loop {
+            match self.next() {
+                Some(Tok::Close) if tags.is_empty() => break,
+                Some(Tok::Word(w)) => tags.push(w),
+                other => return Err(format!("expected tag, found {}", describe(other))),
+            }
+            match self.next() {
+                Some(Tok::Comma) => {}
+                Some(Tok::Close) => break,
+                other => return Err(format!("expected ',' or ']', found {}", describe(other))),
+            }
+        }
+        Ok(tags)
+    }
+}
+
+fn describe(t: Option<Tok>) -> String {
+    match t {
+        None => "end of line".to_string(),
+        Some(Tok::Word(w)) => format!("'{}'", w),
+        Some(Tok::Str(s)) | Some(Tok::Re(s)) => format!("string \"{}\"", s),
+        Some(Tok::Open) => "'['".to_string(),
+        Some(Tok::Close) => "']'".to_string(),
+        Some(Tok::Comma) => "','".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::waf::{Engine, RequestView};
+
+    #[test]
+    fn test_parse_and_round_trip() {
+        let src = r#"
+            # traversal and injection
+            rule 101 when path contains "../" or query any matches re"(?i)union\s+select" then deny 403 tags[traversal, sqli] sev 8
+            rule 102 when header "X-Api-Key" eq "say \"hi\"" then log
+            rule 103 when ua prefix "curl/" then challenge 429 tags[] sev 2
+        "#;
+        let rules = parse_rules(src).unwrap();
+        assert_eq!(rules.len(), 4);
+        assert!(matches!(rules[1].field, Field::Query));
+        assert!(matches!(&rules[1].matcher, Matcher::Regex(r) if r == r"(?i)union\s+select"));
+        assert_eq!(rules[1].tags, ["traversal", "sqli"]);
+        assert_eq!(rules[0].tags, rules[1].tags);
+        assert!(matches!(&rules[2].matcher, Matcher::Eq(s) if s == "say \"hi\""));
+        assert_eq!(rules[2].severity, DEFAULT_SEVERITY);
+
+        let text = to_text(&rules);
+        assert!(text.starts_with("rule 101 when path contains \"../\" or query matches re\"(?i)union\\s+select\" then deny 403 tags[traversal,sqli] sev 8\n"));
+        assert_eq!(to_text(&parse_rules(&text).unwrap()), text);
+
+        let eng = Engine::new(rules);
+        let req = RequestView { path: "/a/../b", user_agent: "x", headers: &[], body: b"", ip: "10.0.0.1", client_cert_cn: "" };
+        assert_eq!(eng.decide(&req).applied_rule_id, Some(101));
+        // the pattern runs as a regex, not as a substring of its source
+        let sqli = RequestView { path: "/s?q=1' UNION  SELECT pw", ..req.clone() };
+        assert_eq!(eng.decide(&sqli).applied_rule_id, Some(101));
+        let benign = RequestView { path: "/s?q=union+select", ..req };
+        assert_eq!(eng.decide(&benign).applied_rule_id, None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let e = parse_rules("rule 1 when path contains \"x\" then deny 403\nrule 1 when ua eq \"y\" then allow").unwrap_err();
+        assert_eq!((e.line, e.msg.as_str()), (2, "duplicate rule id 1"));
+        assert!(parse_rules("rule 2 when path has \"x\" then allow").unwrap_err().msg.contains("unknown matcher"));
+        assert!(parse_rules("rule 3 when path contains \"x then allow").unwrap_err().msg.contains("unterminated"));
+        assert!(parse_rules("rule 4 when path contains \"x\" then deny 403 sev 11").is_err());
+        assert!(parse_rules("rule 5 when path contains \"x\"").unwrap_err().msg.contains("end of line"));
+        assert!(parse_rules("rule 6 when query matches re\"(union\" then deny 403").unwrap_err().msg.contains("regex"));
+        assert!(parse_rules("rule 7 when path prefix \"/dl/\" then throttle 0").is_err());
+        let rules = parse_rules("rule 8 when path prefix \"/dl/\" then throttle 65536").unwrap();
+        assert_eq!(rules[0].action, Action::Throttle(65536));
+        assert_eq!(to_text(&rules), "rule 8 when path prefix \"/dl/\" then throttle 65536 sev 5\n");
+    }
+}
@@ -0,0 +1,215 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/waf_routes.rs
+// Role: Per-route / per-vhost WAF ruleset attachment with base inheritance
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Named rulesets plus a global base set.
+// - Attachments select a ruleset (and optional tag groups) per route and vhost.
+// - Engines are compiled once per attachment.
+// - Routes match the canonical path (security/path.rs); wildcard routes match
+//   on segment boundaries, so "/api/*" and "/api*" cover "/api/x", not "/apix".
+//
+// Attachment precedence (first wins):
+//   1. host + exact route      2. host + longest wildcard route
+//   3. exact route             4. longest wildcard route
+//   5. no attachment: base set only
+//
+// Rule composition for the winning attachment:
+//   base (unless no_inherit) -> attached ruleset replaces base rules with the
+//   same id, other ruleset rules are appended -> `only_tags` keeps rules with
+//   any listed tag -> `disable` drops rule ids.
+// =============================================================================
+
+use crate::path::{canonical, route_matches};
+use crate::waf::{Decision, Engine, RequestView, Rule};
+use olwsx_report::{ErrorReport, Issue};
+use std::collections::HashMap;
+
+mod olwsx_report {
+    pub use crate::report::{ErrorReport, Issue};
+}
+
+#[derive(Clone, Debug)]
+pub struct RulesetAttachment {
+    pub route: String,        // exact path, or prefix ending in `*`
+    pub host: Option<String>, // vhost, case-insensitive, port ignored
+    pub ruleset: Option<String>,
+    pub inherit_base: bool,
+    pub only_tags: Vec<String>,
+    pub disable: Vec<u32>,
+}
+
+impl RulesetAttachment {
+    pub fn route(route: &str) -> Self {
+        Self { route: route.to_string(), host: None, ruleset: None, inherit_base: true, only_tags: Vec::new(), disable: Vec::new() }
+    }
+
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = Some(strip_port(host).to_ascii_lowercase());
+        self
+    }
+
+    pub fn ruleset(mut self, name: &str) -> Self {
+        self.ruleset = Some(name.to_string());
+        self
+    }
+
+    pub fn no_inherit(mut self) -> Self {
+        self.inherit_base = false;
+        self
+    }
+
+    pub fn only_tags<I: IntoIterator<Item = &'static str>>(mut self, tags: I) -> Self {
+        self.only_tags.extend(tags.into_iter().map(String::from));
+        self
+    }
+
+    pub fn disable<I: IntoIterator<Item = u32>>(mut self, ids: I) -> Self {
+        self.disable.extend(ids);
+        self
+    }
+
+    fn is_wildcard(&self) -> bool {
+        self.route.ends_with('*')
+    }
+}
+
+pub struct WafRouter {
+    base: Engine,
+    routes: Vec<(RulesetAttachment, Engine)>, // sorted by precedence
+}
+
+impl WafRouter {
+    // Reports every bad attachment, not just the first.
+    pub fn build(base: Vec<Rule>, rulesets: &HashMap<String, Vec<Rule>>, attachments: Vec<RulesetAttachment>) -> Result<Self, ErrorReport> {
+        let mut errors = ErrorReport::new();
+        let mut seen = std::collections::HashSet::new();
+        let mut routes = Vec::with_capacity(attachments.len());
+        for a in attachments {
+            let source = format!("waf_route {}", a.route);
+            if !seen.insert((a.host.clone(), a.route.clone())) {
+                errors.push(Issue::error(&source, format!("attached twice for host {:?}", a.host)));
+                continue;
+            }
+            match compose(&base, rulesets, &a) {
+                Ok(rules) => routes.push((a, Engine::new(rules))),
+                Err(e) => errors.push(Issue::error(&source, e).key("ruleset")),
+            }
+        }
+        if errors.has_errors() {
+            return Err(errors);
+        }
+        routes.sort_by(|(a, _), (b, _)| {
+            b.host
+                .is_some()
+                .cmp(&a.host.is_some())
+                .then_with(|| a.is_wildcard().cmp(&b.is_wildcard()))
+                .then_with(|| b.route.len().cmp(&a.route.len()))
+        });
+        Ok(Self { base: Engine::new(base), routes })
+    }
+
+    pub fn engine_for(&self, host: &str, path: &str) -> &Engine {
+        let host = strip_port(host);
+        let path = canonical(path);
+        self.routes
+            .iter()
+            .find(|(a, _)| a.host.as_deref().is_none_or(|h| h.eq_ignore_ascii_case(host)) && route_matches(&a.route, &path))
+            .map(|(_, e)| e)
+            .unwrap_or(&self.base)
+    }
+
+    pub fn decide(&self, host: &str, req: &RequestView) -> Decision {
+        self.engine_for(host, req.path).decide(req)
+    }
+}
+
+fn compose(base: &[Rule], rulesets: &HashMap<String, Vec<Rule>>, a: &RulesetAttachment) -> Result<Vec<Rule>, String> {
+    let attached: &[Rule] = match &a.ruleset {
+        Some(name) => rulesets.get(name).ok_or_else(|| format!("unknown ruleset '{}'", name))?,
+        None => &[],
+    };
+    let mut rules: Vec<Rule> = Vec::new();
+    if a.inherit_base {
+        // A base id overridden by the ruleset keeps its base position but takes
+        // the ruleset's branches (a DSL `or` rule may span several entries).
+        let mut emitted = std::collections::HashSet::new();
+        for r in base.iter() {
+            if attached.iter().any(|x| x.id == r.id) {
+                if emitted.insert(r.id) {
+                    rules.extend(attached.iter().filter(|x| x.id == r.id).cloned());
+                }
+            } else {
+                rules.push(r.clone());
+            }
+        }
+        rules.extend(attached.iter().filter(|x| !emitted.contains(&x.id)).cloned());
+    } else {
+        rules.extend(attached.iter().cloned());
+    }
+    if !a.only_tags.is_empty() {
+        rules.retain(|r| r.tags.iter().any(|t| a.only_tags.iter().any(|o| o == t)));
+    }
+    rules.retain(|r| !a.disable.contains(&r.id));
+    Ok(rules)
+}
+
+fn strip_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        return host.split_once(']').map(|(h, _)| &host[..h.len() + 1]).unwrap_or(host);
+    }
+    host.rsplit_once(':').map(|(h, _)| h).unwrap_or(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::waf::{default_rules, Action, Field, Matcher};
+
+    fn view<'a>(path: &'a str, body: &'a [u8]) -> RequestView<'a> {
+        RequestView { path, user_agent: "sqlmap/1.7", headers: &[], body, ip: "10.0.0.1", client_cert_cn: "" }
+    }
+
+    #[test]
+    fn test_route_rulesets() {
+        let strict = vec![
+            Rule { id: 2, field: Field::UserAgent, matcher: Matcher::Contains("sqlmap".into()), action: Action::LogOnly, tags: vec!["sql_injection_bot".into()], severity: 3 },
+            Rule { id: 200, field: Field::Header("Content-Type".into()), matcher: Matcher::Prefix("text/".into()), action: Action::Deny(415), tags: vec!["strict_json".into()], severity: 4 },
+        ];
+        let mut sets = HashMap::new();
+        sets.insert("strict_json".to_string(), strict);
+        let router = WafRouter::build(
+            default_rules(),
+            &sets,
+            vec![
+                RulesetAttachment::route("/api/*").ruleset("strict_json"),
+                RulesetAttachment::route("/static/*").only_tags(["traversal"]),
+                RulesetAttachment::route("/api/*").host("admin.example.com:8443").no_inherit(),
+            ],
+        )
+        .unwrap();
+
+        // base: sqlmap UA denied
+        assert!(matches!(router.decide("www.example.com", &view("/", b"")).action, Action::Deny(403)));
+        // api: rule 2 overridden to log-only, body rule still inherited
+        let d = router.decide("www.example.com", &view("/api/users?x=1", b""));
+        assert!(matches!(d.action, Action::LogOnly) && d.applied_rule_id == Some(2));
+        assert_eq!(router.decide("x", &view("/api/q", b"UNION SELECT 1")).applied_rule_id, Some(4));
+        // static: only the cheap path rules
+        assert!(matches!(router.decide("x", &view("/static/app.js", b"UNION SELECT")).action, Action::Allow));
+        assert_eq!(router.decide("x", &view("/static/../etc", b"")).applied_rule_id, Some(1));
+        // encoded or dotted spellings of /api select the api ruleset, /apix does not
+        for p in ["/static/%2e%2e/api/users", "//api/users", "/api", "/static/..%2fapi/q"] {
+            assert_eq!(router.decide("x", &view(p, b"")).applied_rule_id, Some(2), "{}", p);
+            assert!(matches!(router.decide("x", &view(p, b"")).action, Action::LogOnly), "{}", p);
+        }
+        assert!(matches!(router.decide("x", &view("/apix", b"")).action, Action::Deny(403)));
+        // vhost attachment wins over the plain route and inherits nothing
+        assert!(matches!(router.decide("ADMIN.example.com", &view("/api/x", b"UNION SELECT")).action, Action::Allow));
+
+        let bad = WafRouter::build(vec![], &sets, vec![RulesetAttachment::route("/x").ruleset("missing"), RulesetAttachment::route("/y").ruleset("gone")]);
+        assert_eq!(bad.err().map(|r| r.issues.len()), Some(2));
+    }
+}
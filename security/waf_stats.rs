@@ -0,0 +1,181 @@
+// =============================================================================
+// OLWSX - OverLab Web ServerX
+// File: security/waf_stats.rs
+// Role: Final & Stable WAF aggregation (per-tenant counters, top-k offenders)
+// Philosophy: One version, the most stable version, first and last.
+// -----------------------------------------------------------------------------
+// Responsibilities:
+// - Per-tenant deny/challenge counts by rule id.
+// - Fixed-memory top offending IPs and top triggered rules (Space-Saving).
+// - Rolling window via decay(): halves every counter, forgetting old bursts.
+// =============================================================================
+
+use crate::addr::ClientAddr;
+use crate::waf::{Action, Decision};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+// Space-Saving heavy hitters: at most `capacity` tracked keys; a new key
+// evicts the current minimum and inherits its count (as overestimate error).
+#[derive(Clone, Debug)]
+pub struct TopK<K: Eq + Hash + Clone> {
+    capacity: usize,
+    counts: HashMap<K, (u64, u64)>, // key -> (count, error)
+}
+
+impl<K: Eq + Hash + Clone> TopK<K> {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), counts: HashMap::new() }
+    }
+
+    pub fn add(&mut self, key: K, n: u64) {
+        if let Some(c) = self.counts.get_mut(&key) {
+            c.0 += n;
+            return;
+        }
+        if self.counts.len() < self.capacity {
+            self.counts.insert(key, (n, 0));
+            return;
+        }
+        let (min_key, min_count) = self
+            .counts
+            .iter()
+            .min_by_key(|(_, (c, _))| *c)
+            .map(|(k, (c, _))| (k.clone(), *c))
+            .expect("capacity >= 1");
+        self.counts.remove(&min_key);
+        self.counts.insert(key, (min_count + n, min_count));
+    }
+
+    // Highest counts first; ties broken by lower error (more certain first).
+    pub fn top(&self, n: usize) -> Vec<(K, u64)> {
+        let mut v: Vec<(&K, &(u64, u64))> = self.counts.iter().collect();
+        v.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.1 .1.cmp(&b.1 .1)));
+        v.into_iter().take(n).map(|(k, (c, _))| (k.clone(), *c)).collect()
+    }
+
+    pub fn decay(&mut self) {
+        self.counts.retain(|_, c| {
+            c.0 /= 2;
+            c.1 /= 2;
+            c.0 > 0
+        });
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TenantCounters {
+    pub denies: HashMap<u32, u64>,     // rule id -> count
+    pub challenges: HashMap<u32, u64>, // rule id -> count
+}
+
+#[derive(Clone, Debug)]
+pub struct TopReport {
+    pub top_ips: Vec<(ClientAddr, u64)>,
+    pub top_rules: Vec<(u32, u64)>,
+}
+
+pub struct WafStats {
+    inner: Mutex<State>,
+}
+
+struct State {
+    tenants: HashMap<String, TenantCounters>,
+    ips: TopK<ClientAddr>,
+    rules: TopK<u32>,
+}
+
+impl WafStats {
+    pub fn new(top_capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(State {
+                tenants: HashMap::new(),
+                ips: TopK::new(top_capacity),
+                rules: TopK::new(top_capacity),
+            }),
+        }
+    }
+
+    // Only deny/challenge outcomes are aggregated; allow/log are ignored.
+    pub fn record(&self, tenant: &str, ip: ClientAddr, d: &Decision) {
+        let rule = match (d.applied_rule_id, &d.action) {
+            (Some(id), Action::Deny(_)) | (Some(id), Action::Challenge(_)) => id,
+            _ => return,
+        };
+        let mut st = self.inner.lock().unwrap();
+        let t = st.tenants.entry(tenant.to_string()).or_default();
+        let bucket = if matches!(d.action, Action::Deny(_)) { &mut t.denies } else { &mut t.challenges };
+        *bucket.entry(rule).or_insert(0) += 1;
+        st.ips.add(ip, 1);
+        st.rules.add(rule, 1);
+    }
+
+    pub fn tenant(&self, tenant: &str) -> TenantCounters {
+        self.inner.lock().unwrap().tenants.get(tenant).cloned().unwrap_or_default()
+    }
+
+    // Flattened (tenant, rule_id, "deny"|"challenge", count) rows for label-based exporters.
+    pub fn rows(&self) -> Vec<(String, u32, &'static str, u64)> {
+        let st = self.inner.lock().unwrap();
+        let mut out = Vec::new();
+        for (tenant, c) in st.tenants.iter() {
+            for (rule, n) in c.denies.iter() {
+                out.push((tenant.clone(), *rule, "deny", *n));
+            }
+            for (rule, n) in c.challenges.iter() {
+                out.push((tenant.clone(), *rule, "challenge", *n));
+            }
+        }
+        out.sort();
+        out
+    }
+
+    pub fn report(&self, n: usize) -> TopReport {
+        let st = self.inner.lock().unwrap();
+        TopReport { top_ips: st.ips.top(n), top_rules: st.rules.top(n) }
+    }
+
+    // Call once per window to keep the top-k report rolling.
+    pub fn decay(&self) {
+        let mut st = self.inner.lock().unwrap();
+        st.ips.decay();
+        st.rules.decay();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decision(rule: u32, action: Action) -> Decision {
+        Decision { ts_ms: 0, applied_rule_id: Some(rule), action, reason: String::new(), tags: vec![], severity: 5, rule_version: 0 }
+    }
+
+    fn addr(s: &str) -> ClientAddr {
+        ClientAddr::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_stats_and_topk() {
+        let stats = WafStats::new(2);
+        for _ in 0..5 {
+            stats.record("acme", addr("203.0.113.7"), &decision(1, Action::Deny(403)));
+        }
+        stats.record("acme", addr("::ffff:203.0.113.8"), &decision(3, Action::Challenge(429)));
+        stats.record("other", addr("198.51.100.1"), &decision(1, Action::Deny(403)));
+        stats.record("other", addr("198.51.100.1"), &decision(5, Action::Allow));
+
+        let acme = stats.tenant("acme");
+        assert_eq!(acme.denies.get(&1), Some(&5));
+        assert_eq!(acme.challenges.get(&3), Some(&1));
+        assert_eq!(stats.rows().len(), 3);
+
+        let rep = stats.report(1);
+        assert_eq!(rep.top_ips, vec![(addr("203.0.113.7"), 5)]);
+        assert_eq!(rep.top_rules, vec![(1, 6)]);
+
+        stats.decay();
+        assert_eq!(stats.report(1).top_rules, vec![(1, 3)]);
+    }
+}